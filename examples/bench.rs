@@ -0,0 +1,109 @@
+//! Run the synthetic workloads of the bench module and print a table with
+//! their throughput and latency percentiles.
+//!
+//! Run with `bench MODE THREADS [ACTIVITIES] [DEPTH] [FAN_OUT] [WORK_US]
+//! [ROUND_TRIPS] [ELEMENTS] [SUSPENDED] [SENDERS] [TARGETS]`, where MODE is
+//! single, multi or distributed.
+//! Build with --release for meaningful numbers.
+
+extern crate constellation_rust;
+
+use std::env;
+use std::time::Duration;
+
+use constellation_rust::bench::{self, Workload};
+use constellation_rust::constellation_config::ConstellationConfiguration;
+use constellation_rust::constellation_factory::{new_constellation, Mode};
+use constellation_rust::context::Context;
+
+const CONTEXT_LABEL: &str = "bench";
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        println!(
+            "Usage: {} MODE THREADS [ACTIVITIES] [DEPTH] [FAN_OUT] [WORK_US] [ROUND_TRIPS] \
+             [ELEMENTS] [SUSPENDED] [SENDERS] [TARGETS]\n\
+             MODE is one of single, multi or distributed",
+            args[0]
+        );
+        return;
+    }
+
+    let mode = match args[1].as_str() {
+        "single" => Mode::SingleThreaded,
+        "multi" => Mode::MultiThreaded,
+        "distributed" => Mode::Distributed,
+        other => panic!("Unknown mode {}, use single, multi or distributed", other),
+    };
+    let arg = |i: usize, default: usize| {
+        args.get(i)
+            .map(|x| x.parse().expect("Arguments must be numbers"))
+            .unwrap_or(default)
+    };
+    let threads = arg(2, 1) as i32;
+    let work = Duration::from_micros(arg(6, 100) as u64);
+
+    let workloads = vec![
+        Workload::Independent {
+            activities: arg(3, 1000),
+            work,
+        },
+        Workload::Tree {
+            depth: arg(4, 4) as u32,
+            fan_out: arg(5, 4),
+            work,
+        },
+        Workload::PingPong {
+            round_trips: arg(7, 1000),
+        },
+        Workload::DivideAndConquer {
+            depth: arg(4, 4) as u32,
+            fan_out: arg(5, 4),
+            elements: arg(8, 1 << 22),
+            prefer_parent_thread: false,
+        },
+        Workload::DivideAndConquer {
+            depth: arg(4, 4) as u32,
+            fan_out: arg(5, 4),
+            elements: arg(8, 1 << 22),
+            prefer_parent_thread: true,
+        },
+        Workload::SuspendedPingPong {
+            round_trips: arg(7, 1000),
+            suspended: arg(9, 10000),
+        },
+        Workload::Scatter {
+            senders: arg(10, 64),
+            targets: arg(11, 100),
+        },
+    ];
+
+    let context = Context::new(CONTEXT_LABEL);
+
+    let mut config = ConstellationConfiguration::single_node(threads);
+    config.context_vec.append(&context);
+
+    let mut constellation = new_constellation(mode, config);
+    constellation.activate().unwrap();
+
+    if !constellation.is_master().unwrap() {
+        return;
+    }
+
+    let label = args[1].clone();
+    let mut results = Vec::new();
+    for workload in workloads.iter() {
+        let result = bench::run(constellation.as_mut(), workload, &context, TIMEOUT)
+            .expect("Workload did not complete");
+        println!("{}", result);
+        results.push((label.clone(), result));
+    }
+
+    println!("\n{}", bench::report(&results));
+
+    constellation
+        .done()
+        .expect("Failed to shutdown constellation");
+}
@@ -0,0 +1,132 @@
+//! Measure the round trip time of messages between two processes on the same
+//! node, once over TCP (TcpComm) and once over unix domain sockets
+//! (SameNodeComm wrapping the same TcpComm).
+//!
+//! Run with `comm_latency [ROUND_TRIPS] [MESSAGE_SIZE] [PORT]`, the process
+//! starts the second process itself. Build with --release for meaningful
+//! numbers.
+
+extern crate constellation_rust;
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use constellation_rust::{Communication, SameNodeComm, TcpComm};
+
+const PING_TAG: i32 = 1;
+const PONG_TAG: i32 = 2;
+const WARMUP_ROUND_TRIPS: usize = 100;
+
+/// Block until a message with the given tag arrives
+fn receive(comm: &dyn Communication, tag: i32) -> Vec<u8> {
+    loop {
+        if let Some((_, message)) = comm.try_receive(tag) {
+            return message;
+        }
+        thread::yield_now();
+    }
+}
+
+/// Send pings from rank 0 and answer them on rank 1
+///
+/// # Returns
+/// * `Option<Duration>` - The mean round trip time, on rank 0
+fn ping_pong(comm: &dyn Communication, round_trips: usize, size: usize) -> Option<Duration> {
+    let message = vec![0u8; size];
+    comm.barrier();
+
+    if comm.rank() == 1 {
+        for _ in 0..WARMUP_ROUND_TRIPS + round_trips {
+            let ping = receive(comm, PING_TAG);
+            comm.send(0, PONG_TAG, &ping);
+        }
+        comm.barrier();
+        return None;
+    }
+
+    for _ in 0..WARMUP_ROUND_TRIPS {
+        comm.send(1, PING_TAG, &message);
+        receive(comm, PONG_TAG);
+    }
+
+    let start = Instant::now();
+    for _ in 0..round_trips {
+        comm.send(1, PING_TAG, &message);
+        receive(comm, PONG_TAG);
+    }
+    let elapsed = start.elapsed();
+
+    comm.barrier();
+    Some(elapsed / round_trips as u32)
+}
+
+/// Run both measurements as the process with the given rank
+fn run(rank: usize, port: u16, directory: &Path, round_trips: usize, size: usize) {
+    let peers = |port: u16| {
+        vec![
+            format!("127.0.0.1:{}", port),
+            format!("127.0.0.1:{}", port + 1),
+        ]
+    };
+
+    let tcp = TcpComm::new(&peers(port), rank).expect("Could not set up TCP");
+    let over_tcp = ping_pong(&tcp, round_trips, size);
+    drop(tcp);
+
+    let tcp = TcpComm::new(&peers(port + 2), rank).expect("Could not set up TCP");
+    let same_node =
+        SameNodeComm::new(Arc::new(tcp), directory).expect("Could not set up unix domain sockets");
+    assert!(same_node.is_same_node(1 - rank as i32));
+    let over_unix = ping_pong(&same_node, round_trips, size);
+
+    if let (Some(over_tcp), Some(over_unix)) = (over_tcp, over_unix) {
+        println!(
+            "{} round trips of {} bytes between two processes:",
+            round_trips, size
+        );
+        println!("    TCP:                {:?}", over_tcp);
+        println!("    unix domain socket: {:?}", over_unix);
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    // The second process is started with "--peer DIRECTORY" prepended
+    if args.len() > 2 && args[1] == "--peer" {
+        let directory = PathBuf::from(&args[2]);
+        let (round_trips, size, port) = parse(&args[3..]);
+        run(1, port, &directory, round_trips, size);
+        return;
+    }
+
+    let (round_trips, size, port) = parse(&args[1..]);
+    let directory = env::temp_dir().join(format!("constellation-latency-{}", std::process::id()));
+
+    let mut peer = Command::new(env::current_exe().unwrap())
+        .arg("--peer")
+        .arg(&directory)
+        .args(&args[1..])
+        .spawn()
+        .expect("Could not start the second process");
+
+    run(0, port, &directory, round_trips, size);
+
+    peer.wait().unwrap();
+    std::fs::remove_dir_all(&directory).ok();
+}
+
+/// Parse the optional ROUND_TRIPS, MESSAGE_SIZE and PORT arguments
+fn parse(args: &[String]) -> (usize, usize, u16) {
+    let arg = |i: usize, default: usize| {
+        args.get(i)
+            .map(|x| x.parse().expect("Arguments must be numbers"))
+            .unwrap_or(default)
+    };
+
+    (arg(0, 10_000), arg(1, 64), arg(2, 7100) as u16)
+}
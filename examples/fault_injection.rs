@@ -0,0 +1,148 @@
+//! Run a map reduce under injected faults. The results sent between the
+//! workers and reducers are delayed with a probability of 10%, so they arrive
+//! out of order, and every activity migrates to another thread once before
+//! it runs. A well-written reducer only relies on its reduce function being
+//! associative and commutative, so every round must still produce the right
+//! sum.
+//!
+//! Run with `fault_injection [THREADS] [ROUNDS] [SEED]`, this example needs
+//! the `fault-injection` feature.
+
+#[macro_use]
+extern crate constellation_rust;
+
+use std::env;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use constellation_rust::constellation_config::ConstellationConfiguration;
+use constellation_rust::constellation_factory::{new_constellation, Mode};
+use constellation_rust::util::patterns::{map_reduce, MapFn, ReduceFn};
+use constellation_rust::{
+    Context, FaultInjector, FaultRule, FaultTarget, PayloadTrait, PayloadTraitClone, StealStrategy,
+};
+
+const CONTEXT_LABEL: &str = "faulty";
+const INPUTS: u64 = 200;
+const DELAY_PROBABILITY: f64 = 0.1;
+const DELAY: Duration = Duration::from_millis(20);
+
+/// Number mapped and summed by the activities
+#[derive(Debug, Clone)]
+struct Sum(u64);
+
+impl PayloadTrait for Sum {}
+
+impl PayloadTraitClone for Sum {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for Sum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Read the number carried by a payload
+fn value(payload: &dyn PayloadTrait) -> u64 {
+    payload
+        .as_any()
+        .downcast_ref::<Sum>()
+        .expect("Unexpected payload")
+        .0
+}
+
+/// Create the fault injector with the example rules: delay the results sent
+/// between activities, and migrate every activity once
+fn injector(seed: u64) -> FaultInjector {
+    let mut injector = FaultInjector::new(seed);
+    injector
+        .add_rule(FaultRule::delay_events(
+            FaultTarget::PayloadType("Sum".to_string()),
+            DELAY_PROBABILITY,
+            DELAY,
+        ))
+        .expect("Invalid delay rule");
+    injector
+        .add_rule(FaultRule::migrate(
+            FaultTarget::Context(CONTEXT_LABEL.to_string()),
+            1,
+        ))
+        .expect("Invalid migrate rule");
+
+    injector
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let threads: i32 = args
+        .get(1)
+        .map_or(4, |a| a.parse().expect("Invalid THREADS"));
+    let rounds: usize = args
+        .get(2)
+        .map_or(20, |a| a.parse().expect("Invalid ROUNDS"));
+    let seed: u64 = args.get(3).map_or(42, |a| a.parse().expect("Invalid SEED"));
+
+    let context = Context::new(CONTEXT_LABEL);
+    let context_vec = contexts![CONTEXT_LABEL];
+
+    let injector = Arc::new(injector(seed));
+    let mut config = ConstellationConfiguration::new(
+        StealStrategy::BIGGEST,
+        StealStrategy::BIGGEST,
+        1,
+        threads,
+        false,
+        context_vec,
+        Duration::from_micros(100),
+    );
+    config.fault_injector = Some(injector.clone());
+
+    let mut constellation = new_constellation(Mode::MultiThreaded, config);
+    constellation
+        .activate()
+        .expect("Could not activate constellation");
+
+    let map: MapFn = Arc::new(|input| Box::new(Sum(value(&*input) * value(&*input))));
+    let reduce: ReduceFn = Arc::new(|a, b| Box::new(Sum(value(&*a) + value(&*b))));
+    let expected: u64 = (0..INPUTS).map(|i| i * i).sum();
+
+    let mut wrong = 0;
+    for round in 1..=rounds {
+        let inputs: Vec<Box<dyn PayloadTrait>> = (0..INPUTS)
+            .map(|i| Box::new(Sum(i)) as Box<dyn PayloadTrait>)
+            .collect();
+        let result = map_reduce(
+            &mut *constellation,
+            inputs,
+            map.clone(),
+            reduce.clone(),
+            &context,
+        )
+        .expect("Could not submit the map reduce");
+
+        let result = value(&*result);
+        if result != expected {
+            println!("Round {}: got {}, expected {}", round, result, expected);
+            wrong += 1;
+        }
+    }
+
+    constellation
+        .done()
+        .expect("Failed to shutdown constellation");
+
+    println!(
+        "{} of {} rounds produced {} under {:?}",
+        rounds - wrong,
+        rounds,
+        expected,
+        injector.counts()
+    );
+    if wrong > 0 {
+        std::process::exit(1);
+    }
+}
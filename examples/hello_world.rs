@@ -1,23 +1,27 @@
-//! Create two activities and send a Hello World payload from one to the other.
+//! Create an activity which sends a Hello World payload to the application.
 
+#[macro_use]
 extern crate constellation_rust;
 
 use std::fmt;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use constellation_rust::activity_identifier::ActivityIdentifier;
 use constellation_rust::constellation::ConstellationTrait;
-use constellation_rust::constellation_config;
-use constellation_rust::constellation_factory::{new_constellation, Mode};
-use constellation_rust::context::{Context, ContextVec};
+use constellation_rust::constellation_config::{
+    self, DEFAULT_NUMBER_OF_NODES, DEFAULT_TIME_BETWEEN_STEALS,
+};
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::context::Context;
 use constellation_rust::event::Event;
 use constellation_rust::payload::{PayloadTrait, PayloadTraitClone};
-use constellation_rust::SingleEventCollector;
+use constellation_rust::ConstellationHandle;
 use constellation_rust::StealStrategy;
+use constellation_rust::SubmitOptions;
 use constellation_rust::{activity, activity::ActivityTrait};
 
 const CONTEXT_LABEL: &str = "Hello_World";
-const TIME_BETWEEN_STEALS: u64 = 100; // Microseconds
 
 /// Payload struct for passing data between activities
 /*---------------------------------------------------------------------------*/
@@ -48,13 +52,13 @@ struct HelloWorldActivity {
 }
 
 impl ActivityTrait for HelloWorldActivity {
-    fn cleanup(&mut self, _constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>) {
+    fn cleanup(&mut self, _constellation: &ConstellationHandle) {
         // no cleanup necessary
     }
 
     fn initialize(
         &mut self,
-        constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>,
+        constellation: &ConstellationHandle,
         id: &ActivityIdentifier,
     ) -> activity::State {
         // Create an event and send it to process with id self.target
@@ -65,17 +69,14 @@ impl ActivityTrait for HelloWorldActivity {
         let event = Event::new(Box::from(msg), id.clone(), self.target.clone());
 
         // Send the event containing the payload string
-        constellation
-            .lock()
-            .expect("Could not get lock on Constellation instance")
-            .send(event);
+        constellation.send(event).expect("Could not send event");
 
         return activity::State::FINISH;
     }
 
     fn process(
         &mut self,
-        _constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>,
+        _constellation: &ConstellationHandle,
         _event: Option<Box<Event>>,
         _id: &ActivityIdentifier,
     ) -> activity::State {
@@ -85,46 +86,36 @@ impl ActivityTrait for HelloWorldActivity {
 }
 /*---------------------------------------------------------------------------*/
 
-/// Create two activities, one which sends a "payload" type containing the
-/// string "Hello World" to anther activity, which only purpose is to wait for
-/// the payload and then return it to this program, in order to be displayed.
+/// Create an activity which sends a "payload" type containing the string
+/// "Hello World" to an identifier which does not belong to an activity. The
+/// application subscribes to that identifier, and receives the payload in
+/// order to display it.
 ///
 /// # Arguments
-/// * `constellation` - A boxed Constellation instance
-fn run(mut constellation: Box<dyn ConstellationTrait>) {
-    let context = Context {
-        label: CONTEXT_LABEL.to_string(),
-    };
-
-    let sec = SingleEventCollector::new();
-
-    // When submitting activity we need to cast the SingleEventCollector to
-    // be of the trait type ActivityTrait
-    let sec_aid = constellation.submit(
-        sec.clone() as Arc<Mutex<ActivityTrait>>,
-        &context,
-        false,
-        true,
-    );
+/// * `constellation` - The activated Constellation instance, shut down by
+/// `constellation_rust::run(..)` when this returns
+fn run(constellation: &mut dyn ConstellationTrait) {
+    let context = Context::new(CONTEXT_LABEL);
 
-    let hello_activity: Arc<Mutex<ActivityTrait>> =
-        Arc::new(Mutex::new(HelloWorldActivity { target: sec_aid }));
+    // The events sent to this identifier are received by the application
+    let target = constellation.allocate_external_id();
+    let events = constellation.subscribe(&target);
 
-    constellation.submit(hello_activity, &context, true, false);
+    let hello_activity: Arc<Mutex<dyn ActivityTrait>> =
+        Arc::new(Mutex::new(HelloWorldActivity { target }));
 
-    println!("Both events submitted to Constellation");
-
-    let time = std::time::Duration::from_secs(1);
+    constellation
+        .submit_with(hello_activity, &context, SubmitOptions::default())
+        .expect("Could not submit HelloWorldActivity");
 
-    println!("Waiting for payload in SingleEventCollector...");
-    let e = SingleEventCollector::get_event(sec, time);
+    println!("Activity submitted to Constellation");
 
-    println!("Got payload! Shutting down Constellation");
+    println!("Waiting for payload...");
+    let e = events
+        .recv_timeout(Duration::from_secs(1))
+        .expect("Did not receive the payload");
 
-    // Shut down constellation gracefully
-    constellation
-        .done()
-        .expect("Failed to shutdown constellation");
+    println!("Got payload!");
 
     println!(
         "\n-----------------------------------------------------------\
@@ -144,25 +135,18 @@ fn run(mut constellation: Box<dyn ConstellationTrait>) {
 /// NOTE, does not actually utilize distributed Constellation atm, but runs a
 /// single threaded instance
 fn main() {
-    let mut context_vec = ContextVec::new();
-    context_vec.append(&Context {
-        label: String::from(CONTEXT_LABEL),
-    });
+    let context_vec = contexts![CONTEXT_LABEL];
 
     let const_config = constellation_config::ConstellationConfiguration::new_single_threaded(
         StealStrategy::BIGGEST,
         StealStrategy::BIGGEST,
-        1,
+        DEFAULT_NUMBER_OF_NODES,
         true,
         context_vec,
-        TIME_BETWEEN_STEALS,
+        DEFAULT_TIME_BETWEEN_STEALS,
     );
 
-    let mut constellation = new_constellation(Mode::SingleThreaded, const_config);
-
-    constellation.activate().unwrap();
-
-    if constellation.is_master().unwrap() {
-        run(constellation);
-    }
+    // Activates the instance, calls run(..) on the master node and shuts the
+    // instance down afterwards, also if run(..) panics
+    constellation_rust::run(Mode::SingleThreaded, const_config, run);
 }
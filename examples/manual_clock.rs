@@ -0,0 +1,124 @@
+//! Test a timer without waiting for it. An event is sent with a delay of one
+//! hour to an activity, which forwards it to the application. The instance
+//! reads the time from a manual clock, so the event stays pending until the
+//! clock is advanced by an hour, after which it arrives right away.
+//!
+//! Run with `manual_clock [THREADS]`, a single thread runs the single threaded
+//! instance.
+
+#[macro_use]
+extern crate constellation_rust;
+
+use std::env;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use constellation_rust::constellation_config::ConstellationConfiguration;
+use constellation_rust::constellation_factory::{new_constellation, Mode};
+use constellation_rust::{
+    Clock, Context, Event, HandlerActivity, HandlerControl, ManualClock, PayloadTrait,
+    PayloadTraitClone, StealStrategy, SubmitOptions,
+};
+
+const CONTEXT_LABEL: &str = "timer";
+const DELAY: Duration = Duration::from_secs(3600);
+
+/// Payload of the delayed event
+#[derive(Debug, Clone)]
+struct Alarm(String);
+
+impl PayloadTrait for Alarm {}
+
+impl PayloadTraitClone for Alarm {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for Alarm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let threads: i32 = args
+        .get(1)
+        .map_or(1, |a| a.parse().expect("Invalid THREADS"));
+
+    let context = Context::new(CONTEXT_LABEL);
+    let context_vec = contexts![CONTEXT_LABEL];
+
+    let clock = Arc::new(ManualClock::new());
+    let mut config = ConstellationConfiguration::new(
+        StealStrategy::BIGGEST,
+        StealStrategy::BIGGEST,
+        1,
+        threads,
+        false,
+        context_vec,
+        Duration::from_micros(100),
+    );
+    config.clock = Clock::manual(&clock);
+
+    let mode = if threads > 1 {
+        Mode::MultiThreaded
+    } else {
+        Mode::SingleThreaded
+    };
+    let mut constellation = new_constellation(mode, config);
+    constellation
+        .activate()
+        .expect("Could not activate constellation");
+
+    // The activity forwards the alarm to the application
+    let target = constellation.allocate_external_id();
+    let alarms = constellation.subscribe(&target);
+    let forwarder = HandlerActivity::new((), move |_, event, _| {
+        HandlerControl::FinishWith(event.get_payload().clone(), target.clone())
+    });
+    let forwarder = constellation
+        .submit_with(
+            forwarder,
+            &context,
+            SubmitOptions {
+                expects_events: true,
+                ..Default::default()
+            },
+        )
+        .expect("Could not submit the forwarder");
+
+    let alarm = Event::new(
+        Box::new(Alarm("Wake up".to_string())),
+        forwarder.clone(),
+        forwarder,
+    );
+    constellation
+        .send_after(alarm, DELAY)
+        .expect("Could not send the alarm");
+
+    // Nothing arrives as long as the clock stands still
+    assert!(
+        alarms.recv_timeout(Duration::from_millis(100)).is_err(),
+        "The alarm arrived before it was due"
+    );
+
+    let advanced = Instant::now();
+    clock.advance(DELAY);
+    let alarm = alarms
+        .recv_timeout(Duration::from_secs(1))
+        .expect("The alarm did not arrive after advancing the clock");
+
+    println!(
+        "Received '{}' {:?} after advancing the clock by {:?}",
+        alarm.get_payload(),
+        advanced.elapsed(),
+        clock.elapsed()
+    );
+
+    constellation
+        .done()
+        .expect("Failed to shutdown constellation");
+}
@@ -0,0 +1,182 @@
+//! Count the allocations of a burst of tiny activities, with and without the
+//! object pool of the executor threads (`pool_allocations` in the
+//! configuration). A spawner activity submits ACTIVITIES activities which do
+//! nothing, BATCH at a time, yielding in between so the activities of a batch
+//! finish before the next batch is submitted. A counting global allocator
+//! counts the allocations made while the burst runs.
+//!
+//! Run with `pool_allocations [ACTIVITIES] [BATCH]`. The instance is single
+//! threaded: the pool of a thread is only refilled by the activities
+//! finishing on the thread which submitted them.
+
+#[macro_use]
+extern crate constellation_rust;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use constellation_rust::activity::{ActivityTrait, State};
+use constellation_rust::constellation_config::ConstellationConfiguration;
+use constellation_rust::constellation_factory::{new_constellation, Mode};
+use constellation_rust::{ActivityIdentifier, ConstellationHandle, Context, Event, StealStrategy};
+
+const CONTEXT_LABEL: &str = "burst";
+
+/// Global allocator counting the allocations
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Activity doing nothing
+struct Noop;
+
+impl ActivityTrait for Noop {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Activity submitting `left` Noop activities, `batch` per invocation
+struct Spawner {
+    left: usize,
+    batch: usize,
+    context: Context,
+}
+
+impl Spawner {
+    fn spawn_batch(&mut self, constellation: &ConstellationHandle) -> State {
+        let batch = self.batch.min(self.left);
+        for _ in 0..batch {
+            constellation
+                .submit(Arc::new(Mutex::new(Noop)), &self.context, true, false)
+                .expect("Could not submit activity");
+        }
+        self.left -= batch;
+
+        if self.left > 0 {
+            State::YIELD
+        } else {
+            State::FINISH
+        }
+    }
+}
+
+impl ActivityTrait for Spawner {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, constellation: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.spawn_batch(constellation)
+    }
+
+    fn process(
+        &mut self,
+        constellation: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        self.spawn_batch(constellation)
+    }
+}
+
+/// Run the burst and count the allocations made meanwhile
+///
+/// # Returns
+/// * `(usize, Duration)` - Number of allocations and time of the burst
+fn burst(pool_allocations: bool, activities: usize, batch: usize) -> (usize, Duration) {
+    let context = Context::new(CONTEXT_LABEL);
+    let context_vec = contexts![CONTEXT_LABEL];
+
+    let mut config = ConstellationConfiguration::new(
+        StealStrategy::BIGGEST,
+        StealStrategy::BIGGEST,
+        1,
+        1,
+        false,
+        context_vec,
+        Duration::from_micros(100),
+    );
+    config.pool_allocations = pool_allocations;
+
+    let mut constellation = new_constellation(Mode::SingleThreaded, config);
+    constellation
+        .activate()
+        .expect("Could not activate constellation");
+
+    let spawner = Spawner {
+        left: activities,
+        batch,
+        context: context.clone(),
+    };
+
+    let started = Instant::now();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    constellation
+        .submit(Arc::new(Mutex::new(spawner)), &context, false, false)
+        .expect("Could not submit the spawner");
+    constellation
+        .wait_until_idle(Duration::from_secs(600))
+        .expect("The burst did not finish");
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    let elapsed = started.elapsed();
+
+    constellation
+        .done()
+        .expect("Failed to shutdown constellation");
+
+    (allocations, elapsed)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let activities: usize = args
+        .get(1)
+        .map_or(100_000, |a| a.parse().expect("Invalid ACTIVITIES"));
+    let batch: usize = args
+        .get(2)
+        .map_or(100, |a| a.parse().expect("Invalid BATCH"));
+
+    let (unpooled, unpooled_time) = burst(false, activities, batch);
+    let (pooled, pooled_time) = burst(true, activities, batch);
+
+    println!("{} activities, batches of {}:", activities, batch);
+    println!(
+        "  without pool: {} allocations in {:?}",
+        unpooled, unpooled_time
+    );
+    println!(
+        "  with pool:    {} allocations in {:?}",
+        pooled, pooled_time
+    );
+    assert!(
+        pooled < unpooled,
+        "The object pool did not reduce the number of allocations"
+    );
+}
@@ -0,0 +1,191 @@
+//! Reproduce an ordering bug with a recorded schedule. Worker activities send
+//! their number to a collector activity, which wrongly assumes the numbers
+//! arrive in the order the workers were submitted. Runs are recorded until
+//! the numbers arrive out of order, after which the failing run is replayed:
+//! every replay must deliver the numbers in the same order.
+//!
+//! Run with `schedule_replay [THREADS] [REPLAYS]`.
+
+#[macro_use]
+extern crate constellation_rust;
+
+use std::env;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use constellation_rust::constellation_config::ConstellationConfiguration;
+use constellation_rust::constellation_factory::{new_constellation, Mode};
+use constellation_rust::{
+    ConstellationSpawn, Context, HandlerActivity, HandlerControl, PayloadTrait, PayloadTraitClone,
+    StealStrategy, SubmitOptions,
+};
+
+const CONTEXT_LABEL: &str = "replay";
+const WORKERS: u64 = 16;
+const MAX_RECORDED_RUNS: usize = 100;
+
+/// Numbers sent between the activities and to the application
+#[derive(Debug, Clone)]
+struct Numbers(Vec<u64>);
+
+impl PayloadTrait for Numbers {}
+
+impl PayloadTraitClone for Numbers {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for Numbers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+/// Some work which takes longer for the workers submitted first
+fn work(worker: u64) -> u64 {
+    (0..(WORKERS - worker) * 20_000).fold(worker, |acc, i| acc.wrapping_mul(31).wrapping_add(i))
+}
+
+/// Run the workers and the collector once
+///
+/// # Arguments
+/// * `config` - Configuration of the multithreaded instance, recording or
+/// replaying the schedule
+///
+/// # Returns
+/// * `Vec<u64>` - The numbers of the workers, in the order the collector
+/// received them
+fn run(config: Box<ConstellationConfiguration>) -> Vec<u64> {
+    let context = Context::new(CONTEXT_LABEL);
+
+    let mut constellation = new_constellation(Mode::MultiThreaded, config);
+    constellation
+        .activate()
+        .expect("Could not activate constellation");
+
+    // The collector sends the order to the application
+    let target = constellation.allocate_external_id();
+    let results = constellation.subscribe(&target);
+
+    let collector = HandlerActivity::new(Vec::new(), move |order: &mut Vec<u64>, event, _| {
+        let numbers = event.payload_as::<Numbers>().expect("Unexpected payload");
+        order.push(numbers.0[0]);
+
+        if order.len() as u64 == WORKERS {
+            HandlerControl::FinishWith(Box::new(Numbers(order.clone())), target.clone())
+        } else {
+            HandlerControl::Continue
+        }
+    });
+    let collector = constellation
+        .submit_with(
+            collector,
+            &context,
+            SubmitOptions {
+                expects_events: true,
+                ..Default::default()
+            },
+        )
+        .expect("Could not submit the collector");
+
+    for worker in 0..WORKERS {
+        constellation
+            .spawn_with_result(
+                &context,
+                move |_, _| Box::new(Numbers(vec![worker, work(worker)])),
+                collector.clone(),
+            )
+            .expect("Could not submit a worker");
+    }
+
+    let order = results
+        .recv_timeout(Duration::from_secs(30))
+        .expect("Did not receive the order")
+        .into_payload_as::<Numbers>()
+        .expect("Unexpected payload")
+        .0;
+
+    constellation
+        .done()
+        .expect("Failed to shutdown constellation");
+
+    order
+}
+
+/// Create the configuration, recording to or replaying from the given file
+fn config(threads: i32, trace: &Path, replay: bool) -> Box<ConstellationConfiguration> {
+    let context_vec = contexts![CONTEXT_LABEL];
+
+    let mut config = ConstellationConfiguration::new(
+        StealStrategy::BIGGEST,
+        StealStrategy::BIGGEST,
+        1,
+        threads,
+        false,
+        context_vec,
+        Duration::from_micros(100),
+    );
+    if replay {
+        config.replay_schedule = Some(trace.to_path_buf());
+    } else {
+        config.record_schedule = Some(trace.to_path_buf());
+    }
+
+    config
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let threads: i32 = args
+        .get(1)
+        .map_or(4, |a| a.parse().expect("Invalid THREADS"));
+    let replays: usize = args
+        .get(2)
+        .map_or(100, |a| a.parse().expect("Invalid REPLAYS"));
+    let trace = env::temp_dir().join("schedule_replay.trace");
+
+    // Record runs until the bug shows up
+    let failing = (1..=MAX_RECORDED_RUNS).find_map(|attempt| {
+        let order = run(config(threads, &trace, false));
+        let in_order = order.windows(2).all(|w| w[0] < w[1]);
+        println!("Recorded run {}: {:?}", attempt, order);
+
+        if in_order {
+            None
+        } else {
+            Some(order)
+        }
+    });
+    let failing = match failing {
+        Some(order) => order,
+        None => {
+            println!(
+                "All {} recorded runs delivered the numbers in order",
+                MAX_RECORDED_RUNS
+            );
+            return;
+        }
+    };
+
+    println!("Replaying {} {} times", trace.display(), replays);
+    let mut diverged = 0;
+    for _ in 0..replays {
+        let order = run(config(threads, &trace, true));
+        if order != failing {
+            println!("Replay diverged: {:?}", order);
+            diverged += 1;
+        }
+    }
+
+    println!(
+        "{} of {} replays reproduced {:?}",
+        replays - diverged,
+        replays,
+        failing
+    );
+    if diverged > 0 {
+        std::process::exit(1);
+    }
+}
@@ -10,9 +10,10 @@ use std::sync::{Arc, Mutex};
 
 use constellation_rust::activity;
 use constellation_rust::activity_identifier::ActivityIdentifier;
-use constellation_rust::constellation::ConstellationTrait;
 use constellation_rust::context::Context;
 use constellation_rust::event::Event;
+use constellation_rust::ConstellationHandle;
+use constellation_rust::SubmitOptions;
 
 use super::context::CONTEXT;
 use super::payload;
@@ -27,7 +28,7 @@ pub struct ComputeActivity {
 }
 
 impl activity::ActivityTrait for ComputeActivity {
-    fn cleanup(&mut self, _constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>) {
+    fn cleanup(&mut self, _constellation: &ConstellationHandle) {
         // no cleanup necessary
     }
 
@@ -38,14 +39,14 @@ impl activity::ActivityTrait for ComputeActivity {
     /// it to the parent. Otherwise split the problem over two new activities.
     ///
     /// # Arguments
-    /// * `constellation` - Arc reference to the Constellation instance
+    /// * `constellation` - Handle to the Constellation instance
     /// * `id` - Identifier for this activity
     ///
     /// # Returns
     /// * `State` - The state of which to put the activity in when returning
     fn initialize(
         &mut self,
-        constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>,
+        constellation: &ConstellationHandle,
         id: &ActivityIdentifier,
     ) -> activity::State {
         // Check if length is smaller than threshold
@@ -72,7 +73,7 @@ impl activity::ActivityTrait for ComputeActivity {
     /// result of solving a sub problem.
     ///
     /// # Arguments
-    /// * `constellation` - Àrc reference to the Constellation instance.
+    /// * `constellation` - Handle to the Constellation instance.
     /// * `event` - An event type wrapped in a option<..>, possibly containing
     /// the result of solving a sub problem.
     /// * `id` - Identifier for this activity
@@ -81,7 +82,7 @@ impl activity::ActivityTrait for ComputeActivity {
     /// * `State` - The state of which to put the activity in when returning
     fn process(
         &mut self,
-        constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>,
+        constellation: &ConstellationHandle,
         event: Option<Box<Event>>,
         id: &ActivityIdentifier,
     ) -> activity::State {
@@ -97,6 +98,12 @@ impl activity::ActivityTrait for ComputeActivity {
 
         return activity::State::FINISH;
     }
+
+    /// The length of the vectors, so that the biggest sub problems are
+    /// picked first when using StealStrategy::BIGGEST
+    fn size_hint(&self) -> usize {
+        self.vec1.len()
+    }
 }
 
 impl ComputeActivity {
@@ -115,12 +122,12 @@ impl ComputeActivity {
     /// Send the result to the parent activity
     ///
     /// # Arguments
-    /// * `constellation` - Arc reference to the Constellation instance
+    /// * `constellation` - Handle to the Constellation instance
     /// * `id` - Identifier for this activity
     /// * `vec` - The resulting vector after addition
     pub fn send_result_to_parent(
         &mut self,
-        constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>,
+        constellation: &ConstellationHandle,
         id: &ActivityIdentifier,
         vec: Vec<i32>,
     ) {
@@ -130,27 +137,24 @@ impl ComputeActivity {
         let event = Event::new(Box::from(msg), id.clone(), self.target.clone());
 
         // Send the event containing the payload string
-        constellation
-            .lock()
-            .expect("Could not get lock on Constellation instance")
-            .send(event);
+        constellation.send(event).expect("Could not send event");
     }
 
     /// Split the vectors over two new activities and wait for an event containing
     /// the result from each child.
     ///
     /// # Arguments
-    /// * `constellation` - Arc reference to the Constellation instance
+    /// * `constellation` - Handle to the Constellation instance
     /// * `id` - Identifier for this activity
     pub fn split_work_over_children(
         &mut self,
-        constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>,
+        constellation: &ConstellationHandle,
         id: &ActivityIdentifier,
     ) {
         // Create two new activities and split the work between them and one
         // activity waiting for events
         let half = (self.vec1.len() / 2) as i32;
-        let a: Arc<Mutex<activity::ActivityTrait>> = Arc::new(Mutex::new(ComputeActivity {
+        let a: Arc<Mutex<dyn activity::ActivityTrait>> = Arc::new(Mutex::new(ComputeActivity {
             vec1: Vec::from(&self.vec1[0..half as usize]),
             vec2: Vec::from(&self.vec1[0..half as usize]),
             threshold: self.threshold,
@@ -159,7 +163,7 @@ impl ComputeActivity {
             waiting_for_event: false,
         }));
 
-        let b: Arc<Mutex<activity::ActivityTrait>> = Arc::new(Mutex::new(ComputeActivity {
+        let b: Arc<Mutex<dyn activity::ActivityTrait>> = Arc::new(Mutex::new(ComputeActivity {
             vec1: Vec::from(&self.vec1[half as usize..(self.vec1.len() as i32) as usize]),
             vec2: Vec::from(&self.vec1[half as usize..(self.vec2.len() as i32) as usize]),
             threshold: self.threshold,
@@ -168,32 +172,13 @@ impl ComputeActivity {
             waiting_for_event: false,
         }));
 
-        // Acquire lock on constellation
-        let mut guard = constellation.lock().expect(&format!(
-            "Could not get lock on constellation from \
-             inside executing activity: {}",
-            id
-        ));
-
         // Submit compute activities to constellation
-        let aid_1 = guard.submit(
-            a,
-            &Context {
-                label: String::from(CONTEXT),
-            },
-            true,
-            false,
-        );
-        let aid_2 = guard.submit(
-            b,
-            &Context {
-                label: String::from(CONTEXT),
-            },
-            true,
-            false,
-        );
-
-        drop(guard);
+        let aid_1 = constellation
+            .submit_with(a, &Context::new(CONTEXT), SubmitOptions::default())
+            .expect("Could not submit child activity");
+        let aid_2 = constellation
+            .submit_with(b, &Context::new(CONTEXT), SubmitOptions::default())
+            .expect("Could not submit child activity");
 
         // Use vec1 for storing the result received from children
         self.vec1 = Vec::new();
@@ -209,7 +194,7 @@ impl ComputeActivity {
     /// payload type
     ///
     /// # Arguments
-    /// * `constellation` - Arc reference to the Constellation instance
+    /// * `constellation` - Handle to the Constellation instance
     /// * `event` - The received event
     /// * `id` - Identifier for this activity
     ///
@@ -217,16 +202,15 @@ impl ComputeActivity {
     /// * `State` - The state of which to put the activity in when returning
     fn process_event(
         &mut self,
-        constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>,
+        constellation: &ConstellationHandle,
         event: Box<Event>,
         id: &ActivityIdentifier,
     ) -> activity::State {
+        let src = event.get_src();
         let mut v: Vec<i32> = event
-            .get_payload()
-            .downcast_ref::<payload::Payload>()
-            .unwrap()
-            .vec
-            .clone();
+            .into_payload_as::<payload::Payload>()
+            .expect("Received unexpected payload")
+            .vec;
 
         // Check if this is the first event received
         if self.vec1.len() == 0 {
@@ -235,7 +219,7 @@ impl ComputeActivity {
             return activity::State::SUSPEND;
         }
 
-        if self.order.as_mut().unwrap().0 == event.get_src() {
+        if self.order.as_mut().unwrap().0 == src {
             v.append(&mut self.vec1);
             self.vec1 = v;
         } else {
@@ -243,7 +227,8 @@ impl ComputeActivity {
         }
 
         // Send result back to parent
-        self.send_result_to_parent(constellation, &id, self.vec1.clone());
+        let result = std::mem::replace(&mut self.vec1, Vec::new());
+        self.send_result_to_parent(constellation, &id, result);
 
         return activity::State::FINISH;
     }
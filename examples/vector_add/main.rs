@@ -2,7 +2,13 @@
 //!
 //! This program uses a divide and conquer approach for the solution,
 //! recursively creating new activities to solve the sub problems.
+//!
+//! With the optional <simulated_nodes> argument the program runs on a
+//! SimulatedCluster with that many nodes inside this process, instead of on
+//! the nodes started by mpirun, and checks the result and the liveness of
+//! all nodes before shutting down.
 
+#[macro_use]
 extern crate constellation_rust;
 
 use std::env;
@@ -11,11 +17,14 @@ use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 use constellation_rust::constellation::ConstellationTrait;
-use constellation_rust::constellation_config;
-use constellation_rust::constellation_factory::{new_constellation, Mode};
+use constellation_rust::constellation_config::{
+    self, DEFAULT_NUMBER_OF_NODES, DEFAULT_TIME_BETWEEN_STEALS,
+};
+use constellation_rust::constellation_factory::{new_constellation, run_worker, Mode};
 use constellation_rust::context::Context;
-use constellation_rust::context::ContextVec;
+use constellation_rust::SimulatedCluster;
 use constellation_rust::StealStrategy;
+use constellation_rust::SubmitOptions;
 use constellation_rust::{activity, SingleEventCollector};
 
 mod compute_activity;
@@ -23,7 +32,6 @@ mod context;
 mod payload;
 
 const THRESHOLD: i32 = 10;
-const TIME_BETWEEN_STEALS: u64 = 100; // Micro seconds
 
 /// Creates a SingleEventCollector and a ComputeActivity will will be the
 /// base of the vector add.
@@ -41,7 +49,7 @@ const TIME_BETWEEN_STEALS: u64 = 100; // Micro seconds
 /// # Returns
 /// * `Vec<i32>` - The resulting vector
 fn constellation_vector_add(
-    constellation: &mut Box<dyn ConstellationTrait>,
+    constellation: &mut dyn ConstellationTrait,
     vec1: Vec<i32>,
     vec2: Vec<i32>,
 ) -> Vec<i32> {
@@ -55,17 +63,20 @@ fn constellation_vector_add(
 
     // Create a single event collector to collect the final result
     let sec = SingleEventCollector::new();
-    let sec_aid = constellation.submit(
-        sec.clone() as Arc<Mutex<activity::ActivityTrait>>,
-        &Context {
-            label: String::from(context::CONTEXT),
-        },
-        false,
-        true,
-    );
+    let sec_aid = constellation
+        .submit_with(
+            sec.clone() as Arc<Mutex<dyn activity::ActivityTrait>>,
+            &Context::new(context::CONTEXT),
+            SubmitOptions {
+                may_be_stolen: false,
+                expects_events: true,
+                ..Default::default()
+            },
+        )
+        .expect("Could not submit SingleEventCollector");
 
     // This activity will be the base of all calculation
-    let start_compute_activity: Arc<Mutex<activity::ActivityTrait>> =
+    let start_compute_activity: Arc<Mutex<dyn activity::ActivityTrait>> =
         Arc::new(Mutex::new(compute_activity::ComputeActivity {
             vec1,
             vec2,
@@ -75,27 +86,21 @@ fn constellation_vector_add(
             waiting_for_event: false,
         }));
 
-    constellation.submit(
-        start_compute_activity,
-        &Context {
-            label: String::from(context::CONTEXT),
-        },
-        true,
-        false,
-    );
+    constellation
+        .submit_with(
+            start_compute_activity,
+            &Context::new(context::CONTEXT),
+            SubmitOptions::default(),
+        )
+        .expect("Could not submit ComputeActivity");
 
     // Wait for result
     let time = std::time::Duration::from_secs(1);
     let e = SingleEventCollector::get_event(sec, time);
 
-    let result = e
-        .get_payload()
-        .downcast_ref::<payload::Payload>()
-        .unwrap()
+    e.into_payload_as::<payload::Payload>()
+        .expect("Received unexpected payload")
         .vec
-        .clone();
-
-    result
 }
 
 /// Create vectors deepening on array_length and print result after execution
@@ -103,7 +108,10 @@ fn constellation_vector_add(
 /// # Arguments
 /// * `constellation` - Constellation instance
 /// * `array_length` - User defined length of the vectors used in the addition
-fn run(mut constellation: Box<dyn ConstellationTrait>, array_length: i32) {
+///
+/// # Returns
+/// * `Vec<i32>` - The resulting vector
+fn run(constellation: &mut dyn ConstellationTrait, array_length: i32) -> Vec<i32> {
     println!("Running Vector add with {} nodes", constellation.nodes());
 
     // Create two vectors and fill
@@ -116,11 +124,7 @@ fn run(mut constellation: Box<dyn ConstellationTrait>, array_length: i32) {
         vec2.push(x);
     }
 
-    let result = constellation_vector_add(&mut constellation, vec1, vec2);
-    // Shut down constellation gracefully
-    constellation
-        .done()
-        .expect("Failed to shutdown constellation");
+    let result = constellation_vector_add(constellation, vec1, vec2);
 
     let length = if array_length < 40 { array_length } else { 40 };
     println!(
@@ -130,6 +134,37 @@ fn run(mut constellation: Box<dyn ConstellationTrait>, array_length: i32) {
         length,
         &result[0..length as usize]
     );
+
+    result
+}
+
+/// Run the vector add on a SimulatedCluster, and check the result and that
+/// every node sent heartbeats during the run
+///
+/// # Arguments
+/// * `cluster` - The cluster, not yet activated
+/// * `array_length` - User defined length of the vectors used in the addition
+fn run_simulated(mut cluster: SimulatedCluster, array_length: i32) {
+    cluster
+        .activate()
+        .expect("Could not activate simulated cluster");
+
+    let result = run(cluster.as_master(), array_length);
+    let expected: Vec<i32> = (0..array_length).map(|x| 2 * x).collect();
+    assert_eq!(result, expected, "Wrong result on simulated cluster");
+
+    for rank in 0..cluster.size() {
+        let node = cluster.node(rank).unwrap();
+        for (peer, health) in node.node_health() {
+            println!("Node {} sees node {}: {}", rank, peer, health);
+            assert!(health.responsive, "Node {} lost node {}", rank, peer);
+        }
+    }
+
+    // Shut down all nodes gracefully, the master first
+    cluster
+        .done()
+        .expect("Failed to shutdown simulated cluster");
 }
 
 /// Gathers user input and creates a constellation instance.
@@ -139,7 +174,9 @@ fn main() {
     if args.len() < 3 {
         println!(
             "Please provide an number of nodes and array length\n\
-             mpirun ARGS vector_add <nmr_threads> <array_length>"
+             mpirun ARGS vector_add <nmr_threads> <array_length>\n\
+             or, without mpirun, on simulated nodes:\n\
+             vector_add <nmr_threads> <array_length> <simulated_nodes>"
         );
         exit(1);
     }
@@ -154,21 +191,33 @@ fn main() {
         args[2]
     ));
 
-    let mut context_vec = ContextVec::new();
-    context_vec.append(&Context {
-        label: String::from(context::CONTEXT),
-    });
+    let context_vec = contexts![context::CONTEXT];
 
     let const_config = constellation_config::ConstellationConfiguration::new(
         StealStrategy::BIGGEST,
         StealStrategy::BIGGEST,
-        1,
+        DEFAULT_NUMBER_OF_NODES,
         nmr_threads,
         true,
         context_vec,
-        TIME_BETWEEN_STEALS,
+        DEFAULT_TIME_BETWEEN_STEALS,
     );
 
+    if let Some(nodes) = args.get(3) {
+        let nodes = nodes.parse().expect(&format!(
+            "Cannot parse {} into an integer, please provide number of simulated nodes",
+            nodes
+        ));
+
+        let now = Instant::now();
+        run_simulated(
+            SimulatedCluster::new(nodes, nmr_threads, const_config),
+            array_length,
+        );
+        println!("\n\nExecution took: {}s", now.elapsed().as_secs());
+        return;
+    }
+
     let mut constellation = new_constellation(Mode::MultiThreaded, const_config);
 
     constellation.activate().unwrap();
@@ -176,7 +225,15 @@ fn main() {
     if constellation.is_master().unwrap() {
         // Execute vector add for the given length on the constellation instance
         let now = Instant::now();
-        run(constellation, array_length);
+        run(constellation.as_mut(), array_length);
+
+        // Shut down constellation gracefully
+        constellation
+            .done()
+            .expect("Failed to shutdown constellation");
         println!("\n\nExecution took: {}s", now.elapsed().as_secs());
+    } else {
+        // Steal activities from the other nodes until the master is done
+        run_worker(constellation.as_mut()).expect("Failed to shutdown constellation");
     }
 }
@@ -0,0 +1,149 @@
+///! Delivery acknowledgements for events, see
+///! `ConstellationTrait::send_with_ack(..)`. An event carrying an
+///! acknowledgement signals its AckHandle once the executor thread has handed
+///! it to the `process(..)` function of the destination activity. When the
+///! event is dropped without ever being handed to an activity (for example
+///! because the destination activity was cancelled), the handle is signalled
+///! that the event was dead-lettered instead. When the destination activity
+///! panics while processing the event, the handle is signalled that the
+///! event failed.
+extern crate crossbeam;
+
+use crossbeam::{bounded, Receiver, RecvTimeoutError, Sender};
+use std::cell::Cell;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Counter used to generate unique correlation ids
+static NEXT_ACK_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Outcome of an event sent with `ConstellationTrait::send_with_ack(..)`
+///
+/// * `Consumed` - The event was handed to the destination activity
+/// * `DeadLettered` - The event was dropped without reaching the destination
+/// activity
+/// * `Failed` - The event was handed to the destination activity, which
+/// panicked while processing it
+/// * `TimedOut` - The outcome was not known before the timeout expired
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AckStatus {
+    Consumed,
+    DeadLettered,
+    Failed,
+    TimedOut,
+}
+
+/// Handle used to wait for the outcome of an acknowledged event
+///
+/// # Members
+/// * `id` - Correlation id, shared with the event
+/// * `receiver` - Receiving end of the oneshot channel signalled by the event
+/// * `status` - The outcome, once it has been received
+pub struct AckHandle {
+    id: u64,
+    receiver: Receiver<AckStatus>,
+    status: Cell<Option<AckStatus>>,
+}
+
+impl AckHandle {
+    /// Block until the event has been consumed, dead-lettered or failed.
+    ///
+    /// # Arguments
+    /// * `timeout` - Maximum time to wait
+    ///
+    /// # Returns
+    /// * `AckStatus` - The outcome of the event, TimedOut if it was not known
+    /// before the timeout expired. Calling wait again after a timeout keeps
+    /// waiting for the outcome.
+    pub fn wait(&self, timeout: Duration) -> AckStatus {
+        if let Some(status) = self.status.get() {
+            return status;
+        }
+
+        let status = match self.receiver.recv_timeout(timeout) {
+            Ok(status) => status,
+            Err(RecvTimeoutError::Timeout) => return AckStatus::TimedOut,
+            Err(RecvTimeoutError::Disconnected) => AckStatus::DeadLettered,
+        };
+
+        self.status.set(Some(status));
+        status
+    }
+
+    /// The correlation id of the event this handle belongs to
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+/// Acknowledgement carried by an event, signals the AckHandle.
+///
+/// Dropping an EventAck which has not been signalled marks the event as
+/// dead-lettered.
+///
+/// # Members
+/// * `id` - Correlation id, shared with the AckHandle
+/// * `sender` - Sending end of the oneshot channel, taken when signalled
+pub(crate) struct EventAck {
+    id: u64,
+    sender: Option<Sender<AckStatus>>,
+}
+
+impl EventAck {
+    /// Create a new acknowledgement together with the handle it signals
+    ///
+    /// # Returns
+    /// * `(EventAck, AckHandle)` - The acknowledgement, which should be added
+    /// to the event, and the handle returned to the sender
+    pub(crate) fn new() -> (EventAck, AckHandle) {
+        let id = NEXT_ACK_ID.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = bounded(1);
+
+        (
+            EventAck {
+                id,
+                sender: Some(sender),
+            },
+            AckHandle {
+                id,
+                receiver,
+                status: Cell::new(None),
+            },
+        )
+    }
+
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Signal that the event has been handed to the destination activity
+    pub(crate) fn consumed(mut self) {
+        self.signal(AckStatus::Consumed);
+    }
+
+    /// Signal that the destination activity panicked while processing the
+    /// event
+    pub(crate) fn failed(mut self) {
+        self.signal(AckStatus::Failed);
+    }
+
+    fn signal(&mut self, status: AckStatus) {
+        if let Some(sender) = self.sender.take() {
+            // The handle may already have been dropped, which is fine
+            let _ = sender.try_send(status);
+        }
+    }
+}
+
+impl Drop for EventAck {
+    fn drop(&mut self) {
+        self.signal(AckStatus::DeadLettered);
+    }
+}
+
+impl fmt::Debug for EventAck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EventAck({})", self.id)
+    }
+}
@@ -16,14 +16,37 @@
 ///! See examples/ for some examples of what self-made activities may
 ///! look like.
 use super::activity_identifier::ActivityIdentifier;
-use super::constellation::ConstellationTrait;
+use super::error::ActivityError;
 use super::event::Event;
+use super::implementation::constellation_handle::ConstellationHandle;
+use super::ConstellationTrait;
 
+use std::any::Any;
 use std::sync::{Arc, Mutex};
 
 /// State used to specify whether a method from an activity is done or requires
 /// more data.
 ///
+/// * `FINISH` - The method is done, continue with the next method
+/// * `SUSPEND` - Wait for an event before calling `process(..)` again. An
+/// activity may return SUSPEND from `process(..)` as often as it likes, it is
+/// called again for every event sent to it, also when the activity was
+/// submitted without `expects_events`. This makes it possible to write
+/// long-lived consumers, see `util::activities::StreamConsumer`.
+/// * `YIELD` - Not done yet, but give other activities on this thread a
+/// turn. The activity is put back in the work queue and `process(..)` is
+/// called again later, without waiting for an event. Activities which
+/// yielded run after the queued activities which did not, in the order they
+/// yielded.
+/// * `FINISH_AFTER_DRAIN` - Finish once no more events are queued for the
+/// activity. Events queued on its thread are handed to `process(..)` first,
+/// when none are left `cleanup(..)` is called. While draining, SUSPEND is
+/// treated the same as FINISH_AFTER_DRAIN. Events which are still being
+/// routed to the thread when the queue is found empty are not waited for.
+/// When returned from `initialize(..)` it is treated as FINISH, except that
+/// an activity expecting events is cleaned up right away when none are
+/// queued for it.
+///
 /// The example below shows a possible initialization method of an activity.
 ///
 /// # Example
@@ -42,27 +65,56 @@ use std::sync::{Arc, Mutex};
 ///     return State::FINISH;
 /// }
 /// ```
+#[allow(non_camel_case_types)]
 pub enum State {
     FINISH,
     SUSPEND,
+    YIELD,
+    FINISH_AFTER_DRAIN,
+}
+
+/// Why an activity ended, passed to `ActivityTrait::on_complete(..)`
+///
+/// * `Finished` - The activity finished and `cleanup(..)` was called
+/// * `Cancelled` - The scope of the activity was cancelled, see
+/// `ConstellationTrait::cancel_scope(..)`. The activity is dropped without a
+/// call to `cleanup(..)`.
+/// * `Failed` - An invocation of the activity panicked, the value is the
+/// panic message. The executor thread catches the panic, calls
+/// `on_error(..)` and retires the activity without calling `cleanup(..)`.
+/// * `TimedOut` - The activity finished and `cleanup(..)` was called, but at
+/// least one of its invocations exceeded `SubmitOptions::max_execution_time`
+/// * `Shutdown` - The constellation instance was shut down forcefully before
+/// the activity finished, see `shutdown_timeout` in the configuration.
+/// `cancelled(..)` was called if the activity had started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompletionReason {
+    Finished,
+    Cancelled,
+    Failed(String),
+    TimedOut,
+    Shutdown,
 }
 
 /// All activities must implement this trait and each function must return
 /// a State (described above).
 ///
-/// An activity may use all constellation methods provided in the
-/// ConstellationTrait, by using the `constellation` argument.
-/// They include, but are not limited to, submitting new
-/// activities, processing data, sending data and notifying that the execution
-/// is done.
-pub trait ActivityTrait: Sync + Send + mopa::Any {
+/// An activity may submit new activities, send events and create or cancel
+/// scopes, by using the ConstellationHandle passed as the `constellation`
+/// argument. The handle never locks the constellation instance, so it can be
+/// used freely from within an executing activity.
+///
+/// Activities only have to be Send, not Sync: an activity is always accessed
+/// through its Mutex and runs on one executor thread at a time, although it
+/// may be moved to another thread when it is stolen.
+pub trait ActivityTrait: Send + ActivityAsAny + 'static {
     /// This method is called after the process method has returned FINISH,
     /// after this method returns the activity will be destroyed.
     ///
     /// # Arguments
-    /// * `constellation` - The constellation instance upon which to call
-    /// methods such as submit and send for newly created activities and events
-    fn cleanup(&mut self, constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>);
+    /// * `constellation` - The handle upon which to call methods such as
+    /// submit and send for newly created activities and events
+    fn cleanup(&mut self, constellation: &ConstellationHandle);
 
     /// Called immediately when a activity gets "activated" by an
     /// `ExecutorThread`.
@@ -71,21 +123,18 @@ pub trait ActivityTrait: Sync + Send + mopa::Any {
     /// starts with the process(..) method after being suspended
     ///
     /// # Arguments
-    /// * `constellation` - Reference to constellation instance, used to submit
+    /// * `constellation` - Handle to the constellation instance, used to submit
     /// new activities and events
     /// * `id` - ID for this activity, created internally
-    fn initialize(
-        &mut self,
-        constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>,
-        id: &ActivityIdentifier,
-    ) -> State;
+    fn initialize(&mut self, constellation: &ConstellationHandle, id: &ActivityIdentifier)
+        -> State;
 
     /// This method is called whenever a activity is reactivated after being
     /// suspended, or if it was just picked up by a `ExecutorThread` and
     /// returned State::FINISH from the `initialize(..)`
     ///
     /// # Arguments
-    /// * `constellation` - Reference to the constellation instance used to
+    /// * `constellation` - Handle to the constellation instance used to
     /// submit new activities and events
     /// * `event` - Event containing `Payload` which can be processed by the
     /// activity. The event is of type Option<..> and will have the value None
@@ -99,12 +148,231 @@ pub trait ActivityTrait: Sync + Send + mopa::Any {
     ///       wait for an Event to trigger activation.
     ///     - FINISH: In case the activity is done and should exit, the
     ///       `cleanup(..)` method will be called next
+    ///     - YIELD: Activity is not yet done, run it again later without
+    ///       waiting for an Event
+    ///     - FINISH_AFTER_DRAIN: Process the events still queued for this
+    ///       activity, then call `cleanup(..)`
+    fn process(
+        &mut self,
+        constellation: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        id: &ActivityIdentifier,
+    ) -> State;
+
+    /// Whether the events queued for this activity are handed over in one
+    /// call to `process_batch(..)`. It is read every time the activity is
+    /// reactivated.
+    ///
+    /// # Returns
+    /// * `bool` - Defaults to false: `process(..)` is called once per event,
+    /// and the events still queued when it returns FINISH or YIELD stay
+    /// queued
+    fn batch_events(&self) -> bool {
+        false
+    }
+
+    /// Called instead of `process(..)` when the activity opted in with
+    /// `batch_events()` and more than one event is queued for it at the
+    /// moment it is reactivated. All queued events are handed over in one
+    /// call, in the order they were sent, which avoids suspending and
+    /// reactivating the activity between every event (useful for reductions
+    /// with a high fan-in).
+    ///
+    /// The default implementation calls `process(..)` once per event, every
+    /// event is processed. If a call returned FINISH, so does this method,
+    /// otherwise it returns what the call for the last event returned. Once a
+    /// call returned FINISH_AFTER_DRAIN this method returns
+    /// FINISH_AFTER_DRAIN, unless a later call returns FINISH or YIELD.
+    ///
+    /// # Arguments
+    /// * `constellation` - Handle to the constellation instance used to
+    /// submit new activities and events
+    /// * `events` - All events queued for this activity, oldest first
+    /// * `id` - ID for this activity
+    ///
+    /// # Returns
+    /// * `State` - The state of which to put the activity after processing
+    /// the events, see `process(..)`
+    fn process_batch(
+        &mut self,
+        constellation: &ConstellationHandle,
+        events: Vec<Box<Event>>,
+        id: &ActivityIdentifier,
+    ) -> State {
+        let mut state = State::SUSPEND;
+        let mut draining = false;
+        let mut finished = false;
+
+        for event in events {
+            state = self.process(constellation, Some(event), id);
+            match state {
+                State::FINISH_AFTER_DRAIN => draining = true,
+                State::FINISH => finished = true,
+                _ => {}
+            }
+        }
+
+        match state {
+            _ if finished => State::FINISH,
+            State::SUSPEND if draining => State::FINISH_AFTER_DRAIN,
+            state => state,
+        }
+    }
+
+    /// Called instead of `cleanup(..)` when the constellation instance is shut
+    /// down forcefully (see `shutdown_timeout` in the configuration) while
+    /// this activity is suspended or has yielded, so it will never finish.
+    /// Release the resources held by the activity here.
+    ///
+    /// The default implementation calls `cleanup(..)`.
+    ///
+    /// # Arguments
+    /// * `constellation` - Handle to the constellation instance, which is
+    /// shutting down
+    fn cancelled(&mut self, constellation: &ConstellationHandle) {
+        self.cleanup(constellation);
+    }
+
+    /// Called when an invocation of the activity failed: it panicked, or it
+    /// exceeded its maximum execution time, see ActivityError. Use it to
+    /// compensate, e.g. tell the parent activity or release a lock held for
+    /// the activity. A panicking activity is retired after this call,
+    /// `on_complete(..)` is still called. A panic in this method is caught
+    /// and logged, it does not take down the executor thread.
+    ///
+    /// The default implementation does nothing.
+    ///
+    /// # Arguments
+    /// * `constellation` - Handle to the constellation instance, used to send
+    /// events or submit activities
+    /// * `error` - What went wrong
+    /// * `id` - ID for this activity
+    fn on_error(
+        &mut self,
+        _constellation: &ConstellationHandle,
+        _error: &ActivityError,
+        _id: &ActivityIdentifier,
+    ) {
+    }
+
+    /// Called once for every activity when it ended, as the last call on the
+    /// activity, whether it finished, was cancelled, failed or was dropped
+    /// when shutting down. Use it to react on how the activity ended, for
+    /// example to commit or roll back its work. A panic in this method is
+    /// caught and logged.
+    ///
+    /// The default implementation does nothing.
+    ///
+    /// # Arguments
+    /// * `reason` - Why the activity ended, see CompletionReason
+    fn on_complete(&mut self, _reason: CompletionReason) {}
+
+    /// Estimate of how much work this activity represents, compared to other
+    /// activities in the same application. It is read once when the activity
+    /// is submitted and used by the steal strategies (BIGGEST/SMALLEST) and
+    /// when load balancing between threads.
+    ///
+    /// # Returns
+    /// * `usize` - Relative size of this activity, defaults to 1
+    fn size_hint(&self) -> usize {
+        1
+    }
+
+    /// Name of the concrete activity type, used to find the decoder of an
+    /// activity stolen by another node. There is no need to implement this
+    /// yourself.
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Serialize the activity, so that another node may steal it before it
+    /// was initialized. The other node decodes it with the decoder registered
+    /// for the type, see `ConstellationConfiguration::register_activity(..)`.
+    ///
+    /// # Returns
+    /// * `Option<Vec<u8>>` - The serialized activity, defaults to None: the
+    /// activity is only executed on the node it was submitted to
+    fn encode(&self) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+/// Access to an activity as `Any`, used to downcast it to its concrete type.
+/// Implemented for every activity, there is no need to implement this
+/// yourself.
+pub trait ActivityAsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: ActivityTrait> ActivityAsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// The ActivityTrait as it was before activities received a
+/// ConstellationHandle. Activities implementing this trait can still be
+/// submitted, every method is called with `ConstellationHandle::
+/// to_constellation()`, a constellation instance wrapping a clone of the
+/// handle.
+///
+/// Port activities to the ActivityTrait by replacing the `constellation`
+/// argument with `&ConstellationHandle` and dropping the `lock()` calls.
+#[deprecated(note = "implement ActivityTrait, which receives a &ConstellationHandle")]
+pub trait LegacyActivityTrait: Send + 'static {
+    /// See `ActivityTrait::cleanup(..)`
+    fn cleanup(&mut self, constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>);
+
+    /// See `ActivityTrait::initialize(..)`
+    fn initialize(
+        &mut self,
+        constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>,
+        id: &ActivityIdentifier,
+    ) -> State;
+
+    /// See `ActivityTrait::process(..)`
     fn process(
         &mut self,
         constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>,
         event: Option<Box<Event>>,
         id: &ActivityIdentifier,
     ) -> State;
+
+    /// See `ActivityTrait::size_hint()`
+    fn size_hint(&self) -> usize {
+        1
+    }
 }
 
-mopafy!(ActivityTrait);
+#[allow(deprecated)]
+impl<T: LegacyActivityTrait> ActivityTrait for T {
+    fn cleanup(&mut self, constellation: &ConstellationHandle) {
+        LegacyActivityTrait::cleanup(self, constellation.to_constellation())
+    }
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        LegacyActivityTrait::initialize(self, constellation.to_constellation(), id)
+    }
+
+    fn process(
+        &mut self,
+        constellation: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        id: &ActivityIdentifier,
+    ) -> State {
+        LegacyActivityTrait::process(self, constellation.to_constellation(), event, id)
+    }
+
+    fn size_hint(&self) -> usize {
+        LegacyActivityTrait::size_hint(self)
+    }
+}
@@ -0,0 +1,564 @@
+///! The activities making up the benchmark workloads. Every activity records
+///! its latency in a shared list once it is done.
+use crate::activity::{ActivityTrait, State};
+use crate::payload::{PayloadTrait, PayloadTraitClone};
+use crate::{ActivityIdentifier, ConstellationHandle, Context, Event, SubmitOptions};
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Latencies recorded by the activities of a workload
+pub type Latencies = Arc<Mutex<Vec<Duration>>>;
+
+/// Keep the executor thread busy for the given time
+fn busy_work(work: Duration) {
+    let start = Instant::now();
+    while start.elapsed() < work {}
+}
+
+/// Messages sent between the benchmark activities
+#[derive(Debug, Clone)]
+pub enum BenchMessage {
+    Done,
+    Ping,
+    Stop,
+}
+
+impl PayloadTrait for BenchMessage {}
+
+impl PayloadTraitClone for BenchMessage {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for BenchMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Activity which only performs busy-work. Its latency is the time from
+/// submission until it finished.
+///
+/// # Members
+/// * `work` - Time spent on busy-work
+/// * `submitted` - When the activity was submitted
+/// * `latencies` - Where the latency is recorded
+pub struct BusyActivity {
+    pub work: Duration,
+    pub submitted: Instant,
+    pub latencies: Latencies,
+}
+
+impl ActivityTrait for BusyActivity {
+    fn cleanup(&mut self, _: &ConstellationHandle) {
+        self.latencies
+            .lock()
+            .unwrap()
+            .push(self.submitted.elapsed());
+    }
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        busy_work(self.work);
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Node in a fan-out/fan-in tree. It performs busy-work, submits `fan_out`
+/// children unless it is a leaf, waits for an event from every child and
+/// then sends an event to its parent. Its latency is the time from
+/// submission until its whole subtree finished.
+///
+/// # Members
+/// * `depth` - Number of levels below this node, 0 for a leaf
+/// * `fan_out` - Number of children of every node which is not a leaf
+/// * `work` - Time spent on busy-work by every node
+/// * `context` - Context the children are submitted with
+/// * `parent` - The node to notify when done, None for the root
+/// * `pending` - Number of children which are not done yet
+/// * `submitted` - When the activity was submitted
+/// * `latencies` - Where the latency is recorded
+pub struct TreeActivity {
+    pub depth: u32,
+    pub fan_out: usize,
+    pub work: Duration,
+    pub context: Context,
+    pub parent: Option<ActivityIdentifier>,
+    pub pending: usize,
+    pub submitted: Instant,
+    pub latencies: Latencies,
+}
+
+impl TreeActivity {
+    /// Notify the parent, if any, that this subtree is done
+    fn notify_parent(&self, constellation: &ConstellationHandle, id: &ActivityIdentifier) {
+        if let Some(parent) = &self.parent {
+            let e = Event::new(Box::new(BenchMessage::Done), id.clone(), parent.clone());
+            if let Err(e) = constellation.send(e) {
+                warn!("Could not notify parent in tree benchmark: {}", e);
+            }
+        }
+    }
+}
+
+impl ActivityTrait for TreeActivity {
+    fn cleanup(&mut self, _: &ConstellationHandle) {
+        self.latencies
+            .lock()
+            .unwrap()
+            .push(self.submitted.elapsed());
+    }
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        busy_work(self.work);
+
+        if self.depth == 0 {
+            self.notify_parent(constellation, id);
+            return State::FINISH;
+        }
+
+        for _ in 0..self.fan_out {
+            let child = TreeActivity {
+                depth: self.depth - 1,
+                fan_out: self.fan_out,
+                work: self.work,
+                context: self.context.clone(),
+                parent: Some(id.clone()),
+                pending: 0,
+                submitted: Instant::now(),
+                latencies: self.latencies.clone(),
+            };
+            let expects_events = child.depth > 0;
+
+            match constellation.submit(
+                Arc::new(Mutex::new(child)),
+                &self.context,
+                true,
+                expects_events,
+            ) {
+                Ok(_) => self.pending += 1,
+                Err(e) => warn!("Could not submit child in tree benchmark: {}", e),
+            }
+        }
+
+        if self.pending == 0 {
+            self.notify_parent(constellation, id);
+            return State::FINISH;
+        }
+
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        constellation: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        id: &ActivityIdentifier,
+    ) -> State {
+        // A leaf, or a node whose children could not be submitted, is done
+        // right after initialize(..)
+        if event.is_none() {
+            if self.pending == 0 {
+                return State::FINISH;
+            }
+            return State::SUSPEND;
+        }
+
+        self.pending -= 1;
+        if self.pending > 0 {
+            return State::SUSPEND;
+        }
+
+        self.notify_parent(constellation, id);
+        State::FINISH
+    }
+}
+
+/// Sends pings to a Ponger and waits for the answer, the latency of every
+/// round trip is recorded
+///
+/// # Members
+/// * `ponger` - The activity answering the pings
+/// * `remaining` - Number of round trips left
+/// * `sent` - When the last ping was sent
+/// * `latencies` - Where the round trip times are recorded
+pub struct Pinger {
+    pub ponger: ActivityIdentifier,
+    pub remaining: usize,
+    pub sent: Instant,
+    pub latencies: Latencies,
+}
+
+impl Pinger {
+    fn send(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+        m: BenchMessage,
+    ) {
+        self.sent = Instant::now();
+        let e = Event::new(Box::new(m), id.clone(), self.ponger.clone());
+        if let Err(e) = constellation.send(e) {
+            warn!("Could not send ping in ping-pong benchmark: {}", e);
+        }
+    }
+}
+
+impl ActivityTrait for Pinger {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        if self.remaining == 0 {
+            self.send(constellation, id, BenchMessage::Stop);
+            return State::FINISH;
+        }
+
+        self.send(constellation, id, BenchMessage::Ping);
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        constellation: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        id: &ActivityIdentifier,
+    ) -> State {
+        if event.is_none() {
+            if self.remaining == 0 {
+                return State::FINISH;
+            }
+            return State::SUSPEND;
+        }
+
+        self.latencies.lock().unwrap().push(self.sent.elapsed());
+        self.remaining -= 1;
+
+        if self.remaining == 0 {
+            self.send(constellation, id, BenchMessage::Stop);
+            return State::FINISH;
+        }
+
+        self.send(constellation, id, BenchMessage::Ping);
+        State::SUSPEND
+    }
+}
+
+/// Answers every ping with a ping to the sender, until it receives a stop
+///
+/// # Members
+/// * `stopped` - Set once the activity finished
+pub struct Ponger {
+    pub stopped: Arc<AtomicBool>,
+}
+
+impl ActivityTrait for Ponger {
+    fn cleanup(&mut self, _: &ConstellationHandle) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        constellation: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        id: &ActivityIdentifier,
+    ) -> State {
+        let event = match event {
+            Some(event) => event,
+            None => return State::SUSPEND,
+        };
+
+        if let Some(BenchMessage::Stop) = event.payload_as::<BenchMessage>() {
+            return State::FINISH;
+        }
+
+        let pong = Event::new(Box::new(BenchMessage::Ping), id.clone(), event.get_src());
+        if let Err(e) = constellation.send(pong) {
+            warn!("Could not answer ping in ping-pong benchmark: {}", e);
+        }
+
+        State::SUSPEND
+    }
+}
+
+/// Node in a divide-and-conquer tree over a block of data. It updates every
+/// element of its block, splits the block over `fan_out` children unless it
+/// is a leaf, waits for an event from every child and then sends an event to
+/// its parent. The children read the data their parent just wrote, so they
+/// run faster on the thread of their parent, where the data is still in the
+/// cache. Its latency is the time from submission until its whole subtree
+/// finished.
+///
+/// # Members
+/// * `depth` - Number of levels below this node, 0 for a leaf
+/// * `fan_out` - Number of children of every node which is not a leaf
+/// * `data` - The block of data of this node
+/// * `prefer_parent_thread` - Whether the children are submitted with
+/// `SubmitOptions::prefer_parent_thread`
+/// * `context` - Context the children are submitted with
+/// * `parent` - The node to notify when done, None for the root
+/// * `pending` - Number of children which are not done yet
+/// * `submitted` - When the activity was submitted
+/// * `latencies` - Where the latency is recorded
+pub struct SplitActivity {
+    pub depth: u32,
+    pub fan_out: usize,
+    pub data: Vec<u64>,
+    pub prefer_parent_thread: bool,
+    pub context: Context,
+    pub parent: Option<ActivityIdentifier>,
+    pub pending: usize,
+    pub submitted: Instant,
+    pub latencies: Latencies,
+}
+
+impl SplitActivity {
+    /// Notify the parent, if any, that this subtree is done
+    fn notify_parent(&self, constellation: &ConstellationHandle, id: &ActivityIdentifier) {
+        if let Some(parent) = &self.parent {
+            let e = Event::new(Box::new(BenchMessage::Done), id.clone(), parent.clone());
+            if let Err(e) = constellation.send(e) {
+                warn!(
+                    "Could not notify parent in divide-and-conquer benchmark: {}",
+                    e
+                );
+            }
+        }
+    }
+}
+
+impl ActivityTrait for SplitActivity {
+    fn cleanup(&mut self, _: &ConstellationHandle) {
+        self.latencies
+            .lock()
+            .unwrap()
+            .push(self.submitted.elapsed());
+    }
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        for x in self.data.iter_mut() {
+            *x = x.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+        }
+
+        if self.depth == 0 {
+            self.notify_parent(constellation, id);
+            return State::FINISH;
+        }
+
+        let chunk = (self.data.len() + self.fan_out - 1) / self.fan_out;
+        for _ in 0..self.fan_out {
+            let split = self.data.len().saturating_sub(chunk);
+            let child = SplitActivity {
+                depth: self.depth - 1,
+                fan_out: self.fan_out,
+                data: self.data.split_off(split),
+                prefer_parent_thread: self.prefer_parent_thread,
+                context: self.context.clone(),
+                parent: Some(id.clone()),
+                pending: 0,
+                submitted: Instant::now(),
+                latencies: self.latencies.clone(),
+            };
+            let options = SubmitOptions {
+                expects_events: child.depth > 0,
+                prefer_parent_thread: self.prefer_parent_thread,
+                ..Default::default()
+            };
+
+            match constellation.submit_with(Arc::new(Mutex::new(child)), &self.context, options) {
+                Ok(_) => self.pending += 1,
+                Err(e) => warn!(
+                    "Could not submit child in divide-and-conquer benchmark: {}",
+                    e
+                ),
+            }
+        }
+
+        if self.pending == 0 {
+            self.notify_parent(constellation, id);
+            return State::FINISH;
+        }
+
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        constellation: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        id: &ActivityIdentifier,
+    ) -> State {
+        if event.is_none() {
+            if self.pending == 0 {
+                return State::FINISH;
+            }
+            return State::SUSPEND;
+        }
+
+        self.pending -= 1;
+        if self.pending > 0 {
+            return State::SUSPEND;
+        }
+
+        self.notify_parent(constellation, id);
+        State::FINISH
+    }
+}
+
+/// Waits for a single event and finishes
+///
+/// # Members
+/// * `sleeping` - Number of Sleepers which started and did not finish yet
+pub struct Sleeper {
+    pub sleeping: Arc<AtomicUsize>,
+}
+
+impl ActivityTrait for Sleeper {
+    fn cleanup(&mut self, _: &ConstellationHandle) {
+        self.sleeping.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.sleeping.fetch_add(1, Ordering::SeqCst);
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        if event.is_none() {
+            return State::SUSPEND;
+        }
+
+        State::FINISH
+    }
+}
+
+/// Submits `targets` Catchers and sends each of them an event right away,
+/// usually before the load balancer placed them, so the events are kept
+/// until their destination is found
+///
+/// # Members
+/// * `targets` - Number of Catchers to submit
+/// * `context` - Context the Catchers are submitted with
+/// * `latencies` - Where the Catchers record their latency
+pub struct Scatterer {
+    pub targets: usize,
+    pub context: Context,
+    pub latencies: Latencies,
+}
+
+impl ActivityTrait for Scatterer {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        let options = SubmitOptions {
+            expects_events: true,
+            ..Default::default()
+        };
+
+        for _ in 0..self.targets {
+            let catcher = Catcher {
+                submitted: Instant::now(),
+                latencies: self.latencies.clone(),
+            };
+            let target = match constellation.submit_with(
+                Arc::new(Mutex::new(catcher)),
+                &self.context,
+                options.clone(),
+            ) {
+                Ok(target) => target,
+                Err(e) => {
+                    warn!("Could not submit catcher in scatter benchmark: {}", e);
+                    continue;
+                }
+            };
+
+            let e = Event::new(Box::new(BenchMessage::Ping), id.clone(), target);
+            if let Err(e) = constellation.send(e) {
+                warn!("Could not send event in scatter benchmark: {}", e);
+            }
+        }
+
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Waits for a single event. Its latency is the time from submission until
+/// the event arrived.
+///
+/// # Members
+/// * `submitted` - When the activity was submitted
+/// * `latencies` - Where the latency is recorded
+pub struct Catcher {
+    pub submitted: Instant,
+    pub latencies: Latencies,
+}
+
+impl ActivityTrait for Catcher {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        if event.is_none() {
+            return State::SUSPEND;
+        }
+
+        self.latencies
+            .lock()
+            .unwrap()
+            .push(self.submitted.elapsed());
+        State::FINISH
+    }
+}
@@ -0,0 +1,517 @@
+///! Synthetic workloads to tune a constellation configuration, for example
+///! `time_between_steals`, `steal_batch_size` or the number of threads. The
+///! workloads only use the public API, so the same code runs on every Mode
+///! and the results of different configurations can be compared:
+///!
+///! * `Workload::Independent` - Independent activities performing busy-work
+///! * `Workload::Tree` - A fan-out/fan-in tree of activities, every node waits
+///! for an event from each of its children
+///! * `Workload::PingPong` - Two activities sending events back and forth
+///! * `Workload::DivideAndConquer` - A tree splitting a block of data over
+///! its children, to compare placing children on the thread of their parent
+///! with always balancing them
+///! * `Workload::SuspendedPingPong` - `Workload::PingPong` next to many
+///! suspended activities, to check that delivering an event does not get
+///! slower with the number of suspended activities
+///! * `Workload::Scatter` - Many activities sending events to activities they
+///! just submitted, which are usually not placed yet, to measure contention
+///! on the events kept by the load balancer
+///!
+///! `run(..)` runs a workload on an activated instance and returns the
+///! throughput and latency percentiles in a BenchResult, `report(..)` turns a
+///! list of results into a table. See examples/bench.rs.
+///!
+///! The activities record their latency in memory shared with the caller, so
+///! a workload must run on the node calling `run(..)`. This holds as long as
+///! all activities execute on the master node.
+pub mod activities;
+
+use crate::{ActivityIdentifier, Event};
+use crate::{ConstellationError, ConstellationTrait, Context, MultiThreadedConstellation};
+use crate::{StealStats, SubmitOptions};
+use activities::{BenchMessage, BusyActivity, Latencies, Pinger, Ponger, Sleeper};
+use activities::{Scatterer, SplitActivity, TreeActivity};
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time between two checks whether a workload is done
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// A synthetic workload
+///
+/// * `Independent` - `activities` activities which each perform `work` of
+/// busy-work. The latency of an activity is the time from submission until
+/// it finished.
+/// * `Tree` - A tree of `depth` levels below the root where every node has
+/// `fan_out` children, every node performs `work` of busy-work. The latency
+/// of a node is the time from submission until its whole subtree finished.
+/// * `PingPong` - `round_trips` events sent back and forth between two
+/// activities. The latency is the time of a round trip.
+/// * `DivideAndConquer` - A tree like `Tree`, where the root holds `elements`
+/// numbers and every node updates its numbers and splits them over its
+/// children. With `prefer_parent_thread` the children are submitted with
+/// `SubmitOptions::prefer_parent_thread`.
+/// * `SuspendedPingPong` - Like `PingPong`, while `suspended` activities wait
+/// for an event. They are woken up and finish after the round trips.
+/// * `Scatter` - `senders` activities which each submit `targets` activities
+/// and send every one of them an event right away. The latency of a target is
+/// the time from submission until its event arrived.
+#[derive(Debug, Clone)]
+pub enum Workload {
+    Independent {
+        activities: usize,
+        work: Duration,
+    },
+    Tree {
+        depth: u32,
+        fan_out: usize,
+        work: Duration,
+    },
+    PingPong {
+        round_trips: usize,
+    },
+    DivideAndConquer {
+        depth: u32,
+        fan_out: usize,
+        elements: usize,
+        prefer_parent_thread: bool,
+    },
+    SuspendedPingPong {
+        round_trips: usize,
+        suspended: usize,
+    },
+    Scatter {
+        senders: usize,
+        targets: usize,
+    },
+}
+
+impl Workload {
+    /// Number of operations (activities or round trips) in the workload
+    pub fn operations(&self) -> usize {
+        match self {
+            Workload::Independent { activities, .. } => *activities,
+            Workload::Tree { depth, fan_out, .. }
+            | Workload::DivideAndConquer { depth, fan_out, .. } => {
+                (0..=*depth).map(|level| fan_out.pow(level)).sum()
+            }
+            Workload::PingPong { round_trips }
+            | Workload::SuspendedPingPong { round_trips, .. } => *round_trips,
+            Workload::Scatter { senders, targets } => senders * targets,
+        }
+    }
+}
+
+impl fmt::Display for Workload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Workload::Independent { activities, work } => {
+                write!(f, "independent({} x {:?})", activities, work)
+            }
+            Workload::Tree {
+                depth,
+                fan_out,
+                work,
+            } => write!(f, "tree(depth {}, fan-out {}, {:?})", depth, fan_out, work),
+            Workload::PingPong { round_trips } => write!(f, "ping-pong({})", round_trips),
+            Workload::DivideAndConquer {
+                depth,
+                fan_out,
+                elements,
+                prefer_parent_thread,
+            } => write!(
+                f,
+                "split(depth {}, fan-out {}, {} elements{})",
+                depth,
+                fan_out,
+                elements,
+                if *prefer_parent_thread { ", local" } else { "" }
+            ),
+            Workload::SuspendedPingPong {
+                round_trips,
+                suspended,
+            } => write!(f, "ping-pong({}, {} suspended)", round_trips, suspended),
+            Workload::Scatter { senders, targets } => {
+                write!(f, "scatter({} x {})", senders, targets)
+            }
+        }
+    }
+}
+
+/// Latency percentiles of the operations of a workload
+///
+/// # Members
+/// * `min` - Lowest latency
+/// * `mean` - Average latency
+/// * `p50` - Median latency
+/// * `p90` - 90th percentile
+/// * `p99` - 99th percentile
+/// * `max` - Highest latency
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LatencyStats {
+    pub min: Duration,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    /// Compute the percentiles of the given latencies, all zero if there are
+    /// none
+    pub fn from_latencies(mut latencies: Vec<Duration>) -> LatencyStats {
+        if latencies.is_empty() {
+            return LatencyStats::default();
+        }
+
+        latencies.sort();
+        let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+        let total: Duration = latencies.iter().sum();
+
+        LatencyStats {
+            min: latencies[0],
+            mean: total / latencies.len() as u32,
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: latencies[latencies.len() - 1],
+        }
+    }
+}
+
+impl fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "min {:?}, mean {:?}, p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+            self.min, self.mean, self.p50, self.p90, self.p99, self.max
+        )
+    }
+}
+
+/// Result of running a workload
+///
+/// # Members
+/// * `workload` - Description of the workload
+/// * `nodes` - Number of nodes of the instance
+/// * `threads` - Number of executor threads of the instance
+/// * `operations` - Number of operations which completed
+/// * `elapsed` - Time from submitting the workload until it completed
+/// * `latency` - Latency percentiles of the operations
+/// * `steal_stats` - Statistics on stealing during the run, None unless the
+/// instance is a MultiThreadedConstellation
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub workload: String,
+    pub nodes: i32,
+    pub threads: i32,
+    pub operations: usize,
+    pub elapsed: Duration,
+    pub latency: LatencyStats,
+    pub steal_stats: Option<StealStats>,
+}
+
+impl BenchResult {
+    /// Number of operations completed per second
+    pub fn throughput(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+
+        self.operations as f64 / seconds
+    }
+}
+
+impl fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} on {} node(s), {} thread(s): {} operations in {:?}, {:.1} ops/s, latency {}",
+            self.workload,
+            self.nodes,
+            self.threads,
+            self.operations,
+            self.elapsed,
+            self.throughput(),
+            self.latency
+        )?;
+        if let Some(stats) = &self.steal_stats {
+            write!(f, ", {}", stats)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Run a workload on an activated instance and wait until it completed
+///
+/// # Arguments
+/// * `constellation` - The instance, activated, on the master node
+/// * `workload` - The workload to run
+/// * `context` - Context the activities are submitted with, it must be
+/// served by the instance
+/// * `timeout` - Maximum time to wait for the workload to complete
+///
+/// # Returns
+/// * `Result<BenchResult, ConstellationError>` - The result, or
+/// ConstellationError if an activity could not be submitted or the workload
+/// did not complete within the timeout
+pub fn run(
+    constellation: &mut dyn ConstellationTrait,
+    workload: &Workload,
+    context: &Context,
+    timeout: Duration,
+) -> Result<BenchResult, ConstellationError> {
+    let latencies: Latencies = Arc::new(Mutex::new(Vec::new()));
+    // Set once the activities which do not record a latency finished
+    let stopped = Arc::new(AtomicBool::new(true));
+    let expected = workload.operations();
+    let steals_before = steal_stats(constellation);
+
+    // Activities waiting for an event until the workload completed, they are
+    // all suspended before the workload starts
+    let sleeping = Arc::new(AtomicUsize::new(0));
+    let sleepers = match workload {
+        Workload::SuspendedPingPong { suspended, .. } => {
+            suspend_sleepers(constellation, *suspended, &sleeping, context, timeout)?
+        }
+        _ => Vec::new(),
+    };
+
+    let start = Instant::now();
+
+    match workload {
+        Workload::Independent { activities, work } => {
+            for _ in 0..*activities {
+                let activity = BusyActivity {
+                    work: *work,
+                    submitted: Instant::now(),
+                    latencies: latencies.clone(),
+                };
+                constellation.submit(Arc::new(Mutex::new(activity)), context, true, false)?;
+            }
+        }
+        Workload::Tree {
+            depth,
+            fan_out,
+            work,
+        } => {
+            let root = TreeActivity {
+                depth: *depth,
+                fan_out: *fan_out,
+                work: *work,
+                context: context.clone(),
+                parent: None,
+                pending: 0,
+                submitted: Instant::now(),
+                latencies: latencies.clone(),
+            };
+            constellation.submit(Arc::new(Mutex::new(root)), context, true, *depth > 0)?;
+        }
+        Workload::DivideAndConquer {
+            depth,
+            fan_out,
+            elements,
+            prefer_parent_thread,
+        } => {
+            let root = SplitActivity {
+                depth: *depth,
+                fan_out: *fan_out,
+                data: (0..*elements as u64).collect(),
+                prefer_parent_thread: *prefer_parent_thread,
+                context: context.clone(),
+                parent: None,
+                pending: 0,
+                submitted: Instant::now(),
+                latencies: latencies.clone(),
+            };
+            constellation.submit(Arc::new(Mutex::new(root)), context, true, *depth > 0)?;
+        }
+        Workload::PingPong { round_trips } | Workload::SuspendedPingPong { round_trips, .. } => {
+            let options = SubmitOptions {
+                expects_events: true,
+                ..Default::default()
+            };
+            stopped.store(false, Ordering::SeqCst);
+            let ponger = constellation.submit_with(
+                Arc::new(Mutex::new(Ponger {
+                    stopped: stopped.clone(),
+                })),
+                context,
+                options.clone(),
+            )?;
+            let pinger = Pinger {
+                ponger,
+                remaining: *round_trips,
+                sent: Instant::now(),
+                latencies: latencies.clone(),
+            };
+            constellation.submit_with(Arc::new(Mutex::new(pinger)), context, options)?;
+        }
+        Workload::Scatter { senders, targets } => {
+            for _ in 0..*senders {
+                let scatterer = Scatterer {
+                    targets: *targets,
+                    context: context.clone(),
+                    latencies: latencies.clone(),
+                };
+                constellation.submit(Arc::new(Mutex::new(scatterer)), context, true, false)?;
+            }
+        }
+    }
+
+    while latencies.lock().unwrap().len() < expected || !stopped.load(Ordering::SeqCst) {
+        if start.elapsed() > timeout {
+            warn!(
+                "{} did not complete within {:?}, {} of {} operations done",
+                workload,
+                timeout,
+                latencies.lock().unwrap().len(),
+                expected
+            );
+            return Err(ConstellationError::Failed);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    let elapsed = start.elapsed();
+
+    for sleeper in sleepers {
+        let e = Event::new(Box::new(BenchMessage::Stop), sleeper.clone(), sleeper);
+        if let Err(e) = constellation.send(e) {
+            warn!("Could not wake up suspended activity: {}", e);
+        }
+    }
+    let deadline = Instant::now() + timeout;
+    while sleeping.load(Ordering::SeqCst) > 0 {
+        if Instant::now() > deadline {
+            warn!(
+                "{} suspended activities did not finish within {:?}",
+                sleeping.load(Ordering::SeqCst),
+                timeout
+            );
+            return Err(ConstellationError::Failed);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    let latencies = latencies.lock().unwrap().clone();
+    let steal_stats = match (steals_before, steal_stats(constellation)) {
+        (Some(before), Some(after)) => Some(StealStats {
+            steals: after.steals - before.steals,
+            items: after.items - before.items,
+            yields: after.yields - before.yields,
+            remote_requests: after.remote_requests - before.remote_requests,
+            remote_stolen: after.remote_stolen - before.remote_stolen,
+            remote_given: after.remote_given - before.remote_given,
+        }),
+        _ => None,
+    };
+
+    Ok(BenchResult {
+        workload: workload.to_string(),
+        nodes: constellation.nodes(),
+        threads: constellation.threads(),
+        operations: latencies.len(),
+        elapsed,
+        latency: LatencyStats::from_latencies(latencies),
+        steal_stats,
+    })
+}
+
+/// Submit Sleepers and wait until all of them are suspended
+///
+/// # Arguments
+/// * `constellation` - The instance, activated, on the master node
+/// * `count` - Number of Sleepers
+/// * `sleeping` - Number of Sleepers which started and did not finish
+/// * `context` - Context the Sleepers are submitted with
+/// * `timeout` - Maximum time to wait for the Sleepers to start
+///
+/// # Returns
+/// * `Result<Vec<ActivityIdentifier>, ConstellationError>` - The identifiers
+/// of the Sleepers, or ConstellationError if one could not be submitted or
+/// they did not all start within the timeout
+fn suspend_sleepers(
+    constellation: &mut dyn ConstellationTrait,
+    count: usize,
+    sleeping: &Arc<AtomicUsize>,
+    context: &Context,
+    timeout: Duration,
+) -> Result<Vec<ActivityIdentifier>, ConstellationError> {
+    let options = SubmitOptions {
+        expects_events: true,
+        ..Default::default()
+    };
+    let mut sleepers = Vec::with_capacity(count);
+    for _ in 0..count {
+        let sleeper = Sleeper {
+            sleeping: sleeping.clone(),
+        };
+        sleepers.push(constellation.submit_with(
+            Arc::new(Mutex::new(sleeper)),
+            context,
+            options.clone(),
+        )?);
+    }
+
+    let start = Instant::now();
+    while sleeping.load(Ordering::SeqCst) < count {
+        if start.elapsed() > timeout {
+            warn!(
+                "{} of {} suspended activities started within {:?}",
+                sleeping.load(Ordering::SeqCst),
+                count,
+                timeout
+            );
+            return Err(ConstellationError::Failed);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    Ok(sleepers)
+}
+
+/// The steal statistics of the instance, if it is multithreaded
+fn steal_stats(constellation: &dyn ConstellationTrait) -> Option<StealStats> {
+    constellation
+        .as_any()
+        .downcast_ref::<MultiThreadedConstellation>()
+        .map(|c| c.steal_stats())
+}
+
+/// Format results as a table, one row per result
+///
+/// # Arguments
+/// * `results` - The results, each labelled with the configuration which
+/// produced it
+///
+/// # Returns
+/// * `String` - The table, with a header
+pub fn report(results: &[(String, BenchResult)]) -> String {
+    let mut table = format!(
+        "{:<24} {:<36} {:>8} {:>12} {:>12} {:>12} {:>12} {:>12}\n",
+        "configuration", "workload", "threads", "ops/s", "p50", "p90", "p99", "max"
+    );
+
+    for (label, result) in results {
+        table.push_str(&format!(
+            "{:<24} {:<36} {:>8} {:>12.1} {:>12} {:>12} {:>12} {:>12}\n",
+            label,
+            result.workload,
+            result.threads,
+            result.throughput(),
+            format!("{:?}", result.latency.p50),
+            format!("{:?}", result.latency.p90),
+            format!("{:?}", result.latency.p99),
+            format!("{:?}", result.latency.max)
+        ));
+    }
+
+    table
+}
@@ -0,0 +1,149 @@
+///! Source of time for a constellation instance, see `clock` in the
+///! ConstellationConfiguration. Delayed events, the maximum execution time of
+///! activities, the idle executor threads and the heartbeats all read the time
+///! from the clock instead of the system.
+///!
+///! The default is the system clock. A ManualClock makes timers testable
+///! without real sleeps: its time stands still until the test advances it,
+///! after which everything which became due is fired.
+///!
+///! ```ignore
+///! let clock = Arc::new(ManualClock::new());
+///! config.clock = Clock::manual(&clock);
+///! ...
+///! constellation.send_after(event, Duration::from_secs(3600))?;
+///! clock.advance(Duration::from_secs(3600)); // the event is routed now
+///! ```
+///!
+///! Threads waiting for a time under a ManualClock do not sleep until the
+///! time in one go, but check again after at most MANUAL_POLL_INTERVAL of
+///! real time. Executor threads are woken up when the clock is advanced.
+use crate::implementation::parker::Parker;
+
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+/// Real time a thread waiting for a time under a ManualClock waits before
+/// checking the time again
+pub const MANUAL_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The clock a constellation instance reads the time from. Cloning it is
+/// cheap, clones read the same time.
+///
+/// # Members
+/// * `manual` - The manual clock, None for the system clock
+#[derive(Clone, Default)]
+pub struct Clock {
+    manual: Option<Arc<ManualClock>>,
+}
+
+impl Clock {
+    /// The system clock, `now()` is `Instant::now()`
+    pub fn system() -> Clock {
+        Clock { manual: None }
+    }
+
+    /// A clock reading the time of the given manual clock
+    pub fn manual(clock: &Arc<ManualClock>) -> Clock {
+        Clock {
+            manual: Some(clock.clone()),
+        }
+    }
+
+    /// Whether this clock is a manual clock
+    pub fn is_manual(&self) -> bool {
+        self.manual.is_some()
+    }
+
+    /// The current time
+    #[inline]
+    pub fn now(&self) -> Instant {
+        match &self.manual {
+            None => Instant::now(),
+            Some(clock) => clock.now(),
+        }
+    }
+
+    /// Real time to wait before the given time may have come, the caller
+    /// must check the time again afterwards
+    ///
+    /// # Arguments
+    /// * `deadline` - The time waited for
+    ///
+    /// # Returns
+    /// * `Duration` - Zero if the time has come, the time left for the
+    /// system clock, or MANUAL_POLL_INTERVAL for a manual clock
+    pub(crate) fn time_until(&self, deadline: Instant) -> Duration {
+        match &self.manual {
+            None => deadline.saturating_duration_since(Instant::now()),
+            Some(clock) if clock.now() >= deadline => Duration::from_secs(0),
+            Some(_) => MANUAL_POLL_INTERVAL,
+        }
+    }
+
+    /// Unpark the given parker whenever a manual clock is advanced, so the
+    /// thread parked on it notices the time which became due. Does nothing
+    /// for the system clock.
+    pub(crate) fn wake_on_advance(&self, parker: &Arc<Parker>) {
+        if let Some(clock) = &self.manual {
+            clock.parkers.lock().unwrap().push(Arc::downgrade(parker));
+        }
+    }
+}
+
+/// Clock whose time only moves when it is advanced, for tests
+///
+/// # Members
+/// * `start` - The time when the clock was created
+/// * `elapsed` - Time the clock was advanced since it was created
+/// * `parkers` - Parkers of the threads to wake up when the clock is advanced
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+    parkers: Mutex<Vec<Weak<Parker>>>,
+}
+
+impl ManualClock {
+    /// Create a clock standing still at the current time
+    pub fn new() -> ManualClock {
+        ManualClock {
+            start: Instant::now(),
+            elapsed: Mutex::new(Duration::from_secs(0)),
+            parkers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The current time of the clock
+    pub fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+
+    /// Time the clock was advanced since it was created
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+
+    /// Move the time forward, everything which becomes due is fired by the
+    /// threads of the constellation instances using the clock
+    ///
+    /// # Arguments
+    /// * `duration` - Time to move forward
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+
+        let mut parkers = self.parkers.lock().unwrap();
+        parkers.retain(|parker| match parker.upgrade() {
+            Some(parker) => {
+                parker.unpark();
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> ManualClock {
+        ManualClock::new()
+    }
+}
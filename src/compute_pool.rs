@@ -0,0 +1,179 @@
+///! Shared rayon thread pool for data-parallel sections inside activities,
+///! see `compute_pool` in the ConstellationConfiguration. Only available with
+///! the `compute-pool` feature.
+///!
+///! Rayon's global pool starts a thread for every core, which then compete
+///! with the executor threads for the CPUs. The compute pool is sized to use
+///! the cores left over by the executor threads instead, and it is reached
+///! from inside an activity through `ConstellationHandle::compute_pool()`:
+///!
+///! ```ignore
+///! let sum: u64 = match constellation.compute_pool() {
+///!     Some(pool) => pool.install(|| data.par_iter().sum()),
+///!     None => data.iter().sum(),
+///! };
+///! ```
+///!
+///! The executor thread of the activity blocks until the parallel section
+///! finishes. To keep activities running concurrently from all piling onto
+///! the pool, at most `max_concurrent` activities use it at the same time,
+///! others wait in `install(..)` until a permit is free. Parallel sections
+///! nested inside a section already running on the pool do not take another
+///! permit, so they can not deadlock on it.
+use crate::ConfigError;
+
+use std::sync::{Condvar, Mutex};
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+/// Rayon thread pool shared by all activities of a constellation instance
+///
+/// # Members
+/// * `pool` - The rayon thread pool
+/// * `max_concurrent` - Maximum number of activities using the pool at the
+/// same time
+/// * `active` - Number of activities using the pool
+/// * `released` - Notified whenever an activity stops using the pool
+pub struct ComputePool {
+    pool: ThreadPool,
+    max_concurrent: usize,
+    active: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Returns the permit of a ComputePool when dropped, also when the parallel
+/// section panics
+struct Permit<'a>(&'a ComputePool);
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.0.active.lock().unwrap() -= 1;
+        self.0.released.notify_one();
+    }
+}
+
+impl ComputePool {
+    /// Create a new pool
+    ///
+    /// # Arguments
+    /// * `threads` - Number of threads of the pool, at least 1
+    /// * `max_concurrent` - Maximum number of activities using the pool at
+    /// the same time, at least 1
+    ///
+    /// # Returns
+    /// * `Result<ComputePool, ConfigError>` - The pool, or
+    /// ConfigError::InvalidValue if an argument is 0 or the threads could not
+    /// be started
+    pub fn new(threads: usize, max_concurrent: usize) -> Result<ComputePool, ConfigError> {
+        if max_concurrent == 0 {
+            return Err(ConfigError::InvalidValue {
+                key: "compute_pool.max_concurrent".to_string(),
+                value: "0".to_string(),
+                reason: "at least one activity must be able to use the pool".to_string(),
+            });
+        }
+
+        if threads == 0 {
+            return Err(ConfigError::InvalidValue {
+                key: "compute_pool.threads".to_string(),
+                value: "0".to_string(),
+                reason: "the pool needs at least one thread".to_string(),
+            });
+        }
+
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("constellation-compute-{}", i))
+            .build()
+            .map_err(|e| ConfigError::InvalidValue {
+                key: "compute_pool.threads".to_string(),
+                value: threads.to_string(),
+                reason: e.to_string(),
+            })?;
+
+        Ok(ComputePool {
+            pool,
+            max_concurrent,
+            active: Mutex::new(0),
+            released: Condvar::new(),
+        })
+    }
+
+    /// Run a parallel section on the pool, waiting for a permit first when
+    /// `max_concurrent` activities are using the pool already. Blocks until
+    /// the section finishes.
+    ///
+    /// # Arguments
+    /// * `op` - The parallel section, rayon's parallel iterators and `join`
+    /// used inside it run on this pool
+    ///
+    /// # Returns
+    /// * `R` - The result of `op`
+    pub fn install<OP, R>(&self, op: OP) -> R
+    where
+        OP: FnOnce() -> R + Send,
+        R: Send,
+    {
+        if self.in_pool() {
+            return op();
+        }
+
+        let mut active = self.active.lock().unwrap();
+        while *active >= self.max_concurrent {
+            active = self.released.wait(active).unwrap();
+        }
+        *active += 1;
+        drop(active);
+
+        let _permit = Permit(self);
+        self.pool.install(op)
+    }
+
+    /// Same as `install(..)`, but returns None instead of waiting when no
+    /// permit is free, so the activity can fall back to running sequentially
+    ///
+    /// # Arguments
+    /// * `op` - The parallel section
+    ///
+    /// # Returns
+    /// * `Option<R>` - The result of `op`, None if it was not run
+    pub fn try_install<OP, R>(&self, op: OP) -> Option<R>
+    where
+        OP: FnOnce() -> R + Send,
+        R: Send,
+    {
+        if self.in_pool() {
+            return Some(op());
+        }
+
+        let mut active = self.active.lock().unwrap();
+        if *active >= self.max_concurrent {
+            return None;
+        }
+        *active += 1;
+        drop(active);
+
+        let _permit = Permit(self);
+        Some(self.pool.install(op))
+    }
+
+    /// Number of threads of the pool
+    pub fn threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    /// Maximum number of activities using the pool at the same time
+    pub fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    /// Number of activities using the pool right now
+    pub fn active(&self) -> usize {
+        *self.active.lock().unwrap()
+    }
+
+    /// Whether the calling thread is one of the threads of this pool
+    fn in_pool(&self) -> bool {
+        self.pool.current_thread_index().is_some()
+    }
+}
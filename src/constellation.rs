@@ -1,21 +1,40 @@
 ///! Main module for Constellation, use for setting up a Constellation instance,
 ///! specifying properties and configurations. See SingleThreadedConstellation
 ///! and MultiThreadedConstellation for examples.
-use crate::error::ConstellationError;
+use crate::ack::AckHandle;
+use crate::error::{ConstellationError, SendError};
+use crate::group::GroupHandle;
 use crate::implementation::constellation_identifier::ConstellationIdentifier;
-use crate::{ActivityIdentifier, ActivityTrait, Context, Event};
+use crate::{
+    ActivityIdentifier, ActivityTrait, ClosureActivity, ConstellationHandle, ConstellationSnapshot,
+    Context, DelayedEventToken, Event, PayloadTrait, ScopeId, SubmitOptions, SubscriptionMode,
+};
 
+use std::any::Any;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crossbeam::Receiver;
 
 /// Has to implement Sync and Send to be able to be shared in Arc<Mutex<..>>
-/// between threads. mopa::Any enables downcasting on the trait object.
-pub trait ConstellationTrait: Sync + Send + mopa::Any {
+/// between threads. ConstellationAsAny enables downcasting on the trait
+/// object.
+pub trait ConstellationTrait: Sync + Send + ConstellationAsAny + 'static {
     /// Activate Constellation instance.
     ///
     /// When created, the Constellation instance is inactive in order for the
     /// user to be able to change configuration and properties before
     /// activation.
     ///
+    /// Calling `activate()` again returns ConstellationError::AlreadyActivated,
+    /// unless `done()` shut the instance down. The instance is then restarted
+    /// with empty queues, activity identifiers and scopes keep counting so
+    /// identifiers handed out before are never reissued.
+    ///
+    /// The nodes which are not the master wait for the master to activate,
+    /// activating fails with ConstellationError::Failed if it does not
+    /// activate within a minute.
+    ///
     /// # Returns
     /// * `Result<bool, ConstellationError` - Result struct which contains a
     /// boolean to indicate whether activation was successful or not. Upon
@@ -31,37 +50,356 @@ pub trait ConstellationTrait: Sync + Send + mopa::Any {
     /// The activity must be inside an Arc<Mutex<..>>, in order to work with
     /// thread safety.
     /// * `context` - A reference to the context created for this activity
+    /// * `options` - SubmitOptions struct, specifying e.g. whether this
+    /// activity may be stolen and whether it expects events.
+    ///
+    /// # Returns
+    /// * `Result<ActivityIdentifier, ConstellationError>` - The generated
+    /// Activity Identifier for this Activity, ConstellationError::NotActivated
+    /// if the instance has not been activated or AlreadyShutDown if `done()`
+    /// has shut it down. UnknownContext if the context is not in the
+    /// configuration or no executor thread serves it, InvalidThreadAffinity
+    /// if the options ask for a thread which does not exist and
+    /// UnsatisfiablePlacement if the placement does not allow this node.
+    /// Nothing is submitted when an error is returned.
+    fn submit_with(
+        &mut self,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<ActivityIdentifier, ConstellationError>;
+
+    /// Submit an activity to Constellation, using the default SubmitOptions
+    /// apart from the two flags given. See `submit_with(..)`.
+    ///
+    /// # Arguments
+    /// * `activity` - A reference to an activity implementing the ActivityTrait.
+    /// The activity must be inside an Arc<Mutex<..>>, in order to work with
+    /// thread safety.
+    /// * `context` - A reference to the context created for this activity
     /// * `may_be_stolen` - A boolean indicating whether this activity can be
     /// stolen or not.
     /// * `expects_events` - A boolean indicating whether this activity expects
     /// events or not.
     ///
     /// # Returns
-    /// * `ActivityIdentifier` - The generated Activity Identifier for
-    /// this Activity.
+    /// * `Result<ActivityIdentifier, ConstellationError>` - The generated
+    /// Activity Identifier for this Activity, see `submit_with(..)`.
     fn submit(
         &mut self,
         activity: Arc<Mutex<dyn ActivityTrait>>,
         context: &Context,
         may_be_stolen: bool,
         expects_events: bool,
-    ) -> ActivityIdentifier;
+    ) -> Result<ActivityIdentifier, ConstellationError> {
+        self.submit_with(
+            activity,
+            context,
+            SubmitOptions {
+                may_be_stolen,
+                expects_events,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Submit an activity under a name, so that it can be found with
+    /// `lookup(..)` instead of passing its identifier around. The name is
+    /// registered on submission and released when the activity finishes.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the activity, unique amongst the live activities of
+    /// this constellation instance
+    /// * `activity` - A reference to an activity implementing the ActivityTrait
+    /// * `context` - A reference to the context created for this activity
+    /// * `options` - SubmitOptions struct, see `submit_with(..)`
+    ///
+    /// # Returns
+    /// * `Result<ActivityIdentifier, ConstellationError>` - The generated
+    /// Activity Identifier, ConstellationError::DuplicateName if another live
+    /// activity has the name, or any error of `submit_with(..)`. Nothing is
+    /// submitted when an error is returned.
+    fn submit_named(
+        &mut self,
+        name: &str,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<ActivityIdentifier, ConstellationError>;
+
+    /// Look up an activity submitted with `submit_named(..)`
+    ///
+    /// # Arguments
+    /// * `name` - Name of the activity
+    ///
+    /// # Returns
+    /// * `Option<ActivityIdentifier>` - Identifier of the activity, None if
+    /// there is no live activity with the name
+    fn lookup(&mut self, name: &str) -> Option<ActivityIdentifier>;
+
+    /// Create a new scope, used to cancel a group of activities together.
+    /// When called from within an activity running in a scope, the new scope
+    /// is a child of that scope.
+    ///
+    /// # Returns
+    /// * `ScopeId` - Identifier of the new scope
+    fn create_scope(&mut self) -> ScopeId;
+
+    /// Submit an activity in the given scope, see `submit_with(..)`.
+    ///
+    /// # Arguments
+    /// * `scope` - Scope created with `create_scope()`
+    /// * `activity` - A reference to an activity implementing the ActivityTrait.
+    /// * `context` - A reference to the context created for this activity
+    /// * `options` - SubmitOptions for this activity, the scope is overwritten
+    ///
+    /// # Returns
+    /// * `Result<ActivityIdentifier, ConstellationError>` - The generated
+    /// Activity Identifier for this Activity, see `submit_with(..)`.
+    fn submit_in_scope(
+        &mut self,
+        scope: ScopeId,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<ActivityIdentifier, ConstellationError> {
+        self.submit_with(
+            activity,
+            context,
+            SubmitOptions {
+                scope: Some(scope),
+                ..options
+            },
+        )
+    }
+
+    /// Cancel a scope, all pending and suspended activities in this scope and
+    /// its child scopes are removed without being cleaned up, and events
+    /// queued for or later sent to them are dropped. Activities which are
+    /// executing when the scope is cancelled run until they return.
+    ///
+    /// # Arguments
+    /// * `scope` - The scope to cancel
+    fn cancel_scope(&mut self, scope: ScopeId);
+
+    /// Submit a group of activities, which can be waited for or cancelled
+    /// together using the returned GroupHandle. The activities are submitted
+    /// in a new scope (a child scope when called from within a scoped
+    /// activity).
+    ///
+    /// # Arguments
+    /// * `activities` - The activities in the group
+    /// * `context` - A reference to the context used for all activities
+    /// * `options` - SubmitOptions used for all activities, the scope is
+    /// overwritten
+    ///
+    /// # Returns
+    /// * `Result<GroupHandle, ConstellationError>` - Handle used to wait for
+    /// or cancel the group, see `submit_with(..)` for the errors.
+    fn submit_group(
+        &mut self,
+        activities: Vec<Arc<Mutex<dyn ActivityTrait>>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<GroupHandle, ConstellationError>;
 
     /// Send an event
     ///
     /// # Arguments
     /// * `e` - The event to send, an event may contain a user-defined Payload
     /// struct, containing data.
-    fn send(&mut self, e: Box<Event>);
+    ///
+    /// # Returns
+    /// * `Result<(), SendError>` - SendError::Constellation holding
+    /// NotActivated if the instance has not been activated or AlreadyShutDown
+    /// if `done()` has shut it down. UnknownDestination if the destination
+    /// was not generated by this instance and DestinationFinished if the
+    /// destination activity has finished. An event for an activity which has
+    /// not been placed on a thread yet is kept until it is placed.
+    fn send(&mut self, e: Event) -> Result<(), SendError>;
+
+    /// Send an event and get notified once the destination activity has
+    /// consumed it, which is when its `process(..)` function returned after
+    /// being handed the event.
+    ///
+    /// # Arguments
+    /// * `e` - The event to send
+    ///
+    /// # Returns
+    /// * `Result<AckHandle, SendError>` - Handle to wait on, tells whether
+    /// the event was consumed, dead-lettered (dropped without reaching the
+    /// activity) or failed (the activity panicked while processing it), see
+    /// `send(..)` for the errors.
+    fn send_with_ack(&mut self, mut e: Event) -> Result<AckHandle, SendError> {
+        let handle = e.request_ack();
+        self.send(e)?;
+        Ok(handle)
+    }
+
+    /// Send an event after a delay, the event is routed as a normal event
+    /// once the delay has passed. Can e.g. be used by an activity to send
+    /// itself a timeout event. Delayed events which are still pending when
+    /// Constellation shuts down are dropped.
+    ///
+    /// # Arguments
+    /// * `e` - The event to send
+    /// * `delay` - Time to wait before sending the event
+    ///
+    /// # Returns
+    /// * `Result<DelayedEventToken, ConstellationError>` - Token which can be
+    /// used to cancel the event, see `send(..)` for the errors.
+    fn send_after(
+        &mut self,
+        e: Event,
+        delay: Duration,
+    ) -> Result<DelayedEventToken, ConstellationError>;
+
+    /// Cancel an event sent with `send_after(..)`
+    ///
+    /// # Arguments
+    /// * `token` - The token returned by `send_after(..)`
+    ///
+    /// # Returns
+    /// * `bool` - true if the event was cancelled, false if it was already
+    /// sent or cancelled
+    fn cancel_delayed(&mut self, token: DelayedEventToken) -> bool;
+
+    /// Send an event to every live activity with the given context, on all
+    /// threads. Each activity gets its own copy of the payload, use an
+    /// ArcPayload (see `Event::shared(..)`) to share large data instead.
+    /// Activities submitted after the call do not receive the event.
+    ///
+    /// # Arguments
+    /// * `payload` - The payload of the events
+    /// * `src` - Source activity identifier
+    /// * `context` - Context of the activities to send the event to
+    ///
+    /// # Returns
+    /// * `Result<usize, SendError>` - Number of events sent, see `send(..)`
+    /// for the errors. Activities which finish while the events are sent are
+    /// skipped.
+    fn send_to_context(
+        &mut self,
+        payload: Box<dyn PayloadTrait>,
+        src: ActivityIdentifier,
+        context: &Context,
+    ) -> Result<usize, SendError>;
+
+    /// Subscribe to the events sent to an activity, copies of the events are
+    /// sent to the returned channel as well, see `subscribe_with(..)`.
+    ///
+    /// # Arguments
+    /// * `aid` - Identifier of the activity
+    ///
+    /// # Returns
+    /// * `Receiver<Box<Event>>` - Channel receiving the events
+    fn subscribe(&mut self, aid: &ActivityIdentifier) -> Receiver<Box<Event>> {
+        self.subscribe_with(aid, SubscriptionMode::Duplicate)
+    }
+
+    /// Subscribe to the events sent to an activity, so that the application
+    /// can receive them on an ordinary thread. The subscription replaces an
+    /// earlier one to the same identifier, and is removed once the receiver
+    /// is dropped. See the `subscription` module.
+    ///
+    /// # Arguments
+    /// * `aid` - Identifier of the activity, or an identifier created with
+    /// `allocate_external_id()`
+    /// * `mode` - Whether the events are duplicated into the channel or
+    /// diverted to it, events for an external identifier are always diverted
+    ///
+    /// # Returns
+    /// * `Receiver<Box<Event>>` - Channel receiving the events
+    fn subscribe_with(
+        &mut self,
+        aid: &ActivityIdentifier,
+        mode: SubscriptionMode,
+    ) -> Receiver<Box<Event>>;
+
+    /// Create an identifier which does not belong to an activity, used as
+    /// destination for events consumed by the application through
+    /// `subscribe(..)`. Events sent to it while there is no subscription are
+    /// dropped.
+    ///
+    /// # Returns
+    /// * `ActivityIdentifier` - A new, unique identifier
+    fn allocate_external_id(&mut self) -> ActivityIdentifier;
+
+    /// Pause execution. Activities which are running finish their current
+    /// method call, after which the executor threads pick up no new work and
+    /// deliver no events until `resume()` is called. Activities can still be
+    /// submitted and events sent in the meantime, they are queued. Use
+    /// `wait_until_paused(..)` to wait for the running activities to return.
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - ConstellationError::NotActivated
+    /// if the instance has not been activated or AlreadyShutDown if `done()`
+    /// has shut it down
+    fn pause(&mut self) -> Result<(), ConstellationError>;
+
+    /// Resume execution after `pause()`, the work queued in the meantime is
+    /// picked up
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - See `pause()` for the errors
+    fn resume(&mut self) -> Result<(), ConstellationError>;
+
+    /// Wait until all executor threads are quiescent after `pause()`: no
+    /// activity is running and no work or events are being picked up
+    ///
+    /// # Arguments
+    /// * `timeout` - Maximum time to wait
+    ///
+    /// # Returns
+    /// * `Result<bool, ConstellationError>` - true once all executor threads
+    /// are quiescent, false if the instance is not paused or an activity was
+    /// still running when the timeout expired. See `pause()` for the errors.
+    fn wait_until_paused(&mut self, timeout: Duration) -> Result<bool, ConstellationError>;
+
+    /// Wait until the instance is idle: no activities are pending, suspended
+    /// or executing and no events are waiting to be delivered. Unlike
+    /// `done()` the instance keeps running, so more work can be submitted
+    /// afterwards. Events sent with a delay which is not due yet are not
+    /// waited for.
+    ///
+    /// # Arguments
+    /// * `timeout` - Maximum time to wait
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - ConstellationError::WorkLeft with
+    /// a report on the work left if the instance is not idle when the
+    /// timeout expires, see `pause()` for the other errors
+    fn wait_until_idle(&mut self, timeout: Duration) -> Result<(), ConstellationError>;
 
     /// Terminate Constellation instance.
     ///
+    /// Once this has returned true the instance is shut down: calling done
+    /// again returns true as well, while submitting activities or sending
+    /// events returns ConstellationError::AlreadyShutDown.
+    ///
+    /// While work is left, ConstellationError::WorkLeft is returned with a
+    /// report on the activities and events left, done can be called again
+    /// later.
+    ///
     /// # Returns
     /// * `Result<bool, ConstellationError` - Result which contains a boolean
     /// indicating whether Constellation successfully shutdown, upon error
-    /// a ConstellationError will be returned.
+    /// a ConstellationError will be returned (NotActivated if the instance
+    /// has not been activated).
     fn done(&mut self) -> Result<bool, ConstellationError>;
 
+    /// Take a snapshot of all queues: per thread the pending and suspended
+    /// activities with their context and age, and the events queued per
+    /// destination, plus the activities held back and the orphaned and
+    /// dead-lettered events. The queues are locked with a short try_lock, so
+    /// this can be called while the executor threads are busy, to find out
+    /// why a run hangs. `done()` embeds a snapshot in the WorkLeftReport.
+    ///
+    /// # Returns
+    /// * `ConstellationSnapshot` - The snapshot, empty if the instance has
+    /// not been activated
+    fn dump_state(&mut self) -> ConstellationSnapshot;
+
     /// Return the identifier for this Constellation instance
     ///
     /// # Returns
@@ -77,7 +415,129 @@ pub trait ConstellationTrait: Sync + Send + mopa::Any {
     fn is_master(&self) -> Result<bool, ConstellationError>;
 
     /// Return the number of nodes in this constellation instance.
+    ///
+    /// This is the number of processes of the communication layer, the same
+    /// on the instance and on the ConstellationHandle of its activities.
+    /// `number_of_nodes` of the configuration is only what is expected, it
+    /// is checked against this number when activating.
     fn nodes(&mut self) -> i32;
+
+    /// Wait until the master node is done. Nodes which are not the master
+    /// keep executing the activities they steal until then. Returns right
+    /// away on the master and on instances with a single node.
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - ConstellationError::Failed if the
+    /// master can not be reached anymore
+    fn wait_for_master(&mut self) -> Result<(), ConstellationError> {
+        Ok(())
+    }
+
+    /// Return the number of executor threads on this node. When the
+    /// configuration specified 0 threads, this is the resolved number of
+    /// available cores (after activation).
+    fn threads(&mut self) -> i32;
+}
+
+/// Submit closures as activities, see ClosureActivity. Implemented for every
+/// constellation instance, also the trait object returned by
+/// `new_constellation(..)`. The methods are generic, so they can not be part
+/// of ConstellationTrait itself. Inside an activity, use the methods of the
+/// same name on the ConstellationHandle.
+pub trait ConstellationSpawn {
+    /// Submit a closure as an activity, which runs it when initialized and
+    /// then finishes. Uses the default SubmitOptions.
+    ///
+    /// # Arguments
+    /// * `context` - A reference to the context created for this activity
+    /// * `task` - Closure called with the handle and identifier of the
+    /// activity
+    ///
+    /// # Returns
+    /// * `Result<ActivityIdentifier, ConstellationError>` - The generated
+    /// Activity Identifier for this Activity, see
+    /// `ConstellationTrait::submit_with(..)`.
+    fn spawn<F>(
+        &mut self,
+        context: &Context,
+        task: F,
+    ) -> Result<ActivityIdentifier, ConstellationError>
+    where
+        F: FnOnce(&ConstellationHandle, &ActivityIdentifier) + Send + 'static;
+
+    /// Same as `spawn(..)`, but the payload returned by the closure is sent
+    /// to the target in an event
+    ///
+    /// # Arguments
+    /// * `context` - A reference to the context created for this activity
+    /// * `task` - Closure called with the handle and identifier of the
+    /// activity
+    /// * `target` - Activity the returned payload is sent to
+    ///
+    /// # Returns
+    /// * `Result<ActivityIdentifier, ConstellationError>` - The generated
+    /// Activity Identifier for this Activity, see
+    /// `ConstellationTrait::submit_with(..)`.
+    fn spawn_with_result<F>(
+        &mut self,
+        context: &Context,
+        task: F,
+        target: ActivityIdentifier,
+    ) -> Result<ActivityIdentifier, ConstellationError>
+    where
+        F: FnOnce(&ConstellationHandle, &ActivityIdentifier) -> Box<dyn PayloadTrait>
+            + Send
+            + 'static;
+}
+
+impl<T: ConstellationTrait + ?Sized> ConstellationSpawn for T {
+    fn spawn<F>(
+        &mut self,
+        context: &Context,
+        task: F,
+    ) -> Result<ActivityIdentifier, ConstellationError>
+    where
+        F: FnOnce(&ConstellationHandle, &ActivityIdentifier) + Send + 'static,
+    {
+        self.submit_with(
+            ClosureActivity::new(task),
+            context,
+            SubmitOptions::default(),
+        )
+    }
+
+    fn spawn_with_result<F>(
+        &mut self,
+        context: &Context,
+        task: F,
+        target: ActivityIdentifier,
+    ) -> Result<ActivityIdentifier, ConstellationError>
+    where
+        F: FnOnce(&ConstellationHandle, &ActivityIdentifier) -> Box<dyn PayloadTrait>
+            + Send
+            + 'static,
+    {
+        self.submit_with(
+            ClosureActivity::with_result(task, target),
+            context,
+            SubmitOptions::default(),
+        )
+    }
 }
 
-mopafy!(ConstellationTrait);
+/// Access to a constellation instance as `Any`, used to downcast it to its
+/// concrete type. Implemented for every constellation instance.
+pub trait ConstellationAsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: ConstellationTrait> ConstellationAsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
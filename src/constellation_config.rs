@@ -1,30 +1,355 @@
 ///! Configurations for constellation, modify the parameters to maximize
 ///! performance.
+///!
+///! With the `config-file` feature enabled, a configuration can also be
+///! loaded from (and stored to) a TOML or JSON file, for example:
+///!
+///! ```toml
+///! local_steal_strategy = "BIGGEST"
+///! remote_steal_strategy = "SMALLEST"
+///! number_of_nodes = 1
+///! number_of_threads = 4
+///! debug = false
+///! context_vec = ["vector_add"]
+///! time_between_steals_us = 100
+///! max_activities_per_thread = 1000
+///! shed_high_watermark = 64
+///! steal_batch_size = 32
+///! thread_contexts = [["gpu"], ["cpu"], ["cpu"], ["cpu"]]
+///! deterministic_scheduling = false
+///! shutdown_timeout_ms = 5000
+///! load_report_interval_ms = 1000
+///! load_report_stale_intervals = 3
+///! master_rank = 0
+///! strict_node_count = false
+///! tcp_peers = ["10.0.0.1:7000", "10.0.0.2:7000"]
+///! tcp_rank = 0
+///! same_node_socket_dir = "/tmp/constellation-run-42"
+///! heartbeat_interval_ms = 1000
+///! heartbeat_miss_threshold = 3
+///! debug_json = "schedule.jsonl"
+///! record_schedule = "schedule.trace"
+///! queue_sample_interval_ms = 10
+///! parent_thread_load_factor = 2.0
+///! rebalance_threshold = 4.0
+///! pool_allocations = false
+///! allow_unserved_contexts = false
+///! no_progress_timeout_ms = 10000
+///!
+///! [steal_strategy_overrides]
+///! io = "SMALLEST"
+///! ```
+///!
+///! All fields are optional, missing fields get their default value.
+#[cfg(feature = "compute-pool")]
+use crate::compute_pool::ComputePool;
 use crate::context::ContextVec;
-use crate::StealStrategy;
+use crate::decoders::Decoders;
+#[cfg(feature = "fault-injection")]
+use crate::fault_injection::FaultInjector;
+use crate::intercept::{EventInterceptor, InterceptDecision};
+use crate::{
+    ActivityIdentifier, ActivityTrait, Clock, ConfigError, ConstellationSnapshot, Event,
+    MetricsSink, PayloadTrait, SchedulerEvent, StealStrategy,
+};
+
+#[cfg(feature = "config-file")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+#[cfg(feature = "config-file")]
+use std::fs;
+#[cfg(feature = "config-file")]
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{env, fmt, thread};
+
+use crossbeam::Sender;
+
+/// Environment variable overriding `number_of_threads`
+pub const ENV_THREADS: &str = "CONSTELLATION_THREADS";
+/// Environment variable overriding `debug` (true/false/1/0)
+pub const ENV_DEBUG: &str = "CONSTELLATION_DEBUG";
+/// Environment variable overriding `time_between_steals` (microseconds)
+pub const ENV_TIME_BETWEEN_STEALS: &str = "CONSTELLATION_TIME_BETWEEN_STEALS_US";
+/// Environment variable overriding `local_steal_strategy` (BIGGEST/SMALLEST)
+pub const ENV_LOCAL_STEAL_STRATEGY: &str = "CONSTELLATION_LOCAL_STEAL_STRATEGY";
+/// Environment variable overriding `tcp_rank`
+pub const ENV_TCP_RANK: &str = "CONSTELLATION_TCP_RANK";
+
+/// Default steal strategy, both between threads and between nodes
+pub const DEFAULT_STEAL_STRATEGY: StealStrategy = StealStrategy::BIGGEST;
+/// Default number of nodes
+pub const DEFAULT_NUMBER_OF_NODES: i32 = 1;
+/// Default number of threads, 0 means the number of available cores
+pub const DEFAULT_NUMBER_OF_THREADS: i32 = 0;
+/// Recommended `time_between_steals`
+pub const DEFAULT_TIME_BETWEEN_STEALS: Duration = Duration::from_micros(100);
+/// Default `load_report_interval`
+pub const DEFAULT_LOAD_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+/// Default `heartbeat_interval`
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// Default `parent_thread_load_factor`
+pub const DEFAULT_PARENT_THREAD_LOAD_FACTOR: f64 = 2.0;
+
+/// Called with the identifier of an activity which exceeded its
+/// `max_execution_time`, and the time it has been running
+pub type ExecutionTimeoutCallback = fn(&ActivityIdentifier, Duration);
+
+/// Called with the activity and the id of the executor thread, before the
+/// activity is invoked (initialize, process or cleanup)
+pub type ActivityStartHook = Arc<dyn Fn(&ActivityIdentifier, i32) + Send + Sync>;
+/// Called with the rank of a node which missed `heartbeat_miss_threshold`
+/// heartbeats in a row, and the number of heartbeats it missed
+pub type NodeUnresponsiveCallback = Arc<dyn Fn(i32, u32) + Send + Sync>;
+/// Called with a snapshot of the queues when a multithreaded instance made
+/// no progress for `no_progress_timeout` while activities are suspended
+pub type NoProgressCallback = Arc<dyn Fn(&ConstellationSnapshot) + Send + Sync>;
+
+/// Called with the activity, the id of the executor thread and the time the
+/// invocation took, after the activity returned
+pub type ActivityFinishHook = Arc<dyn Fn(&ActivityIdentifier, i32, Duration) + Send + Sync>;
+/// Called with the activity when it suspends waiting for events
+pub type ActivitySuspendHook = Arc<dyn Fn(&ActivityIdentifier) + Send + Sync>;
+/// Called with the id of an event and the activity it is handed to
+pub type EventDeliveredHook = Arc<dyn Fn(u64, &ActivityIdentifier) + Send + Sync>;
+
+/// Hooks called by the executor threads during the lifecycle of activities,
+/// used to attach profiling or tracing from outside the crate. Hooks which are
+/// not set cost a single branch.
+///
+/// The hooks are called on the executor threads, so they should be fast and
+/// must not call back into the constellation instance.
+///
+/// # Members
+/// * `on_activity_start` - Called before each invocation of an activity
+/// * `on_activity_finish` - Called after each invocation of an activity
+/// * `on_activity_suspend` - Called when an activity suspends
+/// * `on_event_delivered` - Called for each event handed to an activity
+#[derive(Clone, Default)]
+pub struct LifecycleHooks {
+    pub on_activity_start: Option<ActivityStartHook>,
+    pub on_activity_finish: Option<ActivityFinishHook>,
+    pub on_activity_suspend: Option<ActivitySuspendHook>,
+    pub on_event_delivered: Option<EventDeliveredHook>,
+}
 
 /// Configuration struct
 ///
 /// # Members
 /// * `local_steal_strategy` - StealStrategy between threads on a single node
-/// * `remote_steal_strategy` - StealStrategy between nodes in Constellation
-/// * `number_of_nodes` - Number of nodes used
-/// * `Number_of_threads` - Number of threads on each node
+/// * `steal_strategy_overrides` - StealStrategy of activities by the label of
+/// their context, replacing `local_steal_strategy` for these activities. The
+/// labels must be known contexts, checked by `validate()`. Empty by default.
+/// * `remote_steal_strategy` - StealStrategy between nodes in Constellation,
+/// deciding which activities a node hands to another node whose executor
+/// threads are all idle. Only activities which can be encoded, and whose type
+/// is registered with `register_activity(..)`, are handed over.
+/// * `number_of_nodes` - Number of nodes expected, compared with the number
+/// of MPI processes when activating, see `check_node_count(..)`. `nodes()`
+/// always returns the number of MPI processes.
+/// * `Number_of_threads` - Number of threads on each node, 0 means use the
+/// number of available cores, which is resolved on each node when activating
 /// * `debug` - Set to `true` to print debug messages
 /// * `context_vec` - Vector of Context struct, used to identify what contexts
-/// this node supports
-/// * `time_between_steals` - Time interval between stealing/distributing work
-/// amongst threads. Each time work is stolen/submitted a lock on the work
-/// queue is acquired, increasing this timer would make that less frequent.
+/// this node supports. When it is empty and there are no `thread_contexts`,
+/// activities of any context are accepted.
+/// * `time_between_steals` - Maximum time interval between stealing/distributing
+/// work amongst threads. Activities and events handed to the load balancer by
+/// the executor threads wake it up right away, the interval paces its
+/// periodic maintenance, such as routing delayed events and kept events. In
+/// configuration files it is given in microseconds, as
+/// `time_between_steals_us`.
+/// * `max_activities_per_thread` - Optional cap on the number of activities
+/// queued on a single executor thread. Activities exceeding the cap are placed
+/// on another thread, or held back until a thread has room. Defaults to None
+/// (unlimited), set the field directly to change it.
+/// * `shed_high_watermark` - Optional number of activities queued on an
+/// executor thread above which it hands stealable activities back to the load
+/// balancer, as long as other threads are idle. Defaults to None (never shed
+/// work), set the field directly to change it.
+/// * `steal_batch_size` - Maximum number of activities moved per lock
+/// acquisition, when the load balancer distributes activities submitted by
+/// threads and when an executor thread takes activities from its work queue,
+/// and the maximum number of activities handed to another node per steal.
+/// Defaults to 1, set the field directly to change it. See StealStats for
+/// tuning.
+/// * `use_env_overrides` - Whether the constellation factory applies the
+/// CONSTELLATION_* environment variables on top of this configuration, see
+/// `apply_env(..)`. Defaults to true.
+/// * `execution_timeout_callback` - Optional function called when an activity
+/// exceeds the `max_execution_time` it was submitted with, the activity is
+/// always logged as well. Defaults to None, set the field directly to change
+/// it.
+/// * `hooks` - Lifecycle hooks called by the executor threads, none are set
+/// by default, set the fields directly to change them.
+/// * `event_interceptors` - Interceptors every sent event passes before it is
+/// routed, see `add_event_interceptor(..)`
+/// * `thread_contexts` - Optional contexts served by each executor thread, by
+/// thread index. An activity is only placed on, and executed by, threads
+/// serving its context. Threads without an entry serve `context_vec`. Defaults
+/// to None, meaning every thread executes activities of any context. Checked
+/// by `validate_thread_contexts(..)` when activating.
+/// * `deterministic_scheduling` - When true, executor threads order the
+/// activities they pick from their queues by ActivityIdentifier, instead of
+/// the arbitrary order of the queues. Activities with equal priority and size
+/// then run in the order they were submitted. This is slower, use it to
+/// reproduce runs when debugging or testing. Defaults to false.
+/// * `shutdown_timeout` - Optional time `done()` waits for the remaining work
+/// to finish. When it expires, activities which are suspended or have yielded
+/// are cancelled (see `ActivityTrait::cancelled(..)`), activities which did
+/// not start are dropped together with all queued events, and `done()`
+/// returns `ConstellationError::ForcedShutdown`. Defaults to None, meaning
+/// `done()` returns false immediately while work is left. In configuration
+/// files it is given in milliseconds, as `shutdown_timeout_ms`.
+/// * `load_report_interval` - Time between two load reports of the load
+/// balancer, see NodeLoad. Defaults to 1 second, in configuration files it is
+/// given in milliseconds, as `load_report_interval_ms`.
+/// * `load_report_stale_intervals` - Number of intervals without a report
+/// after which the load of a node is unknown. Defaults to 3.
+/// * `master_rank` - MPI rank of the master node, which runs the coordinator
+/// and returns true from `is_master()`. Defaults to 0, checked against the
+/// number of MPI processes by `validate_master_rank(..)` when activating.
+/// * `strict_node_count` - When true, activating fails if `number_of_nodes`
+/// differs from the number of MPI processes, otherwise only a warning is
+/// logged. Defaults to false.
+/// * `tcp_peers` - Optional address ("host:port") of every process, by rank.
+/// When set, `Mode::Distributed` communicates over TCP (see TcpComm) instead
+/// of MPI. Defaults to None.
+/// * `tcp_rank` - Rank of this process when communicating over TCP, its index
+/// in `tcp_peers`. Defaults to 0, usually set per process with the
+/// CONSTELLATION_TCP_RANK environment variable.
+/// * `same_node_socket_dir` - Optional directory for the unix domain sockets
+/// of the processes. When set, `Mode::Distributed` sends the messages for
+/// processes on the same node over these sockets instead of MPI or TCP (see
+/// SameNodeComm). Use a separate directory for every run. Defaults to None.
+/// * `heartbeat_interval` - Time between two heartbeats sent by every node to
+/// all other nodes, see NodeHealth. Defaults to 1 second, in configuration
+/// files it is given in milliseconds, as `heartbeat_interval_ms`.
+/// * `heartbeat_miss_threshold` - Number of heartbeats a node may miss in a
+/// row before it is reported as unresponsive. Defaults to 3.
+/// * `on_node_unresponsive` - Optional callback, called on the heartbeat
+/// thread when a node becomes unresponsive. A warning is logged either way.
+/// * `debug_json` - Optional file to which multithreaded instances write
+/// their scheduling decisions, one JSON record per line, see ScheduleRecord.
+/// Independent of `debug`. Defaults to None.
+/// * `record_schedule` - Optional file to which multithreaded instances
+/// record their scheduling decisions, so the run can be replayed with
+/// `replay_schedule`, see the schedule_trace module. Executor threads do not
+/// shed work while recording. Defaults to None.
+/// * `replay_schedule` - Optional recording made with `record_schedule`,
+/// multithreaded instances replay its scheduling decisions to reproduce the
+/// recorded run. Can not be combined with `record_schedule`, defaults to
+/// None.
+/// * `queue_sample_interval` - Optional time between two samples of the queue
+/// depths of every executor thread, see QueueDepthStats. The histograms are
+/// logged at shutdown. Only used by multithreaded instances, defaults to
+/// None. In configuration files it is given in milliseconds, as
+/// `queue_sample_interval_ms`.
+/// * `parent_thread_load_factor` - An activity submitted with
+/// `SubmitOptions::prefer_parent_thread` stays on the thread of its parent as
+/// long as that thread has at most this factor times the average number of
+/// activities queued on the other threads. Defaults to 2.0.
+/// * `rebalance_threshold` - Optional ratio between the pending work of the
+/// most and the least loaded executor thread above which the load balancer
+/// moves queued activities from the one to the other, so threads do not go
+/// idle next to a thread holding a batch of long running activities. Only
+/// activities which may be stolen and have no thread affinity are moved, and
+/// only a few times each, so they do not bounce between threads. Only used by
+/// multithreaded instances, at least 1.0. Defaults to None, work is only
+/// balanced when it is submitted.
+/// * `pool_allocations` - When true, every executor thread keeps the
+/// allocations of the activity wrappers of finished activities, and of the
+/// events it drops itself, and reuses them for the activities submitted and
+/// events sent on that thread. Saves allocator calls for workloads with very
+/// many tiny activities. Defaults to false.
+/// * `allow_unserved_contexts` - When false, submitting an activity with a
+/// context no executor thread serves fails with `ConstellationError::
+/// UnknownContext`, instead of holding the activity back forever. Set it for
+/// dynamic setups, where threads serving the context are added later with
+/// `add_executor_threads(..)`. Defaults to false.
+/// * `no_progress_timeout` - Optional time after which a multithreaded
+/// instance which has suspended activities, but finished no activity and
+/// delivered no event while no activity was executing, is considered stuck,
+/// for example by a cycle of activities each waiting for an event from the
+/// other. A warning with a snapshot of the queues is logged, once per stall.
+/// Defaults to None, no check. In configuration files it is given in
+/// milliseconds, as `no_progress_timeout_ms`.
+/// * `on_no_progress` - Optional callback, called on the load balancer
+/// thread with the snapshot when a stall is detected. Not part of
+/// configuration files, defaults to None.
+/// * `metrics_sink` - Optional writer to which multithreaded instances write
+/// a ConstellationStats snapshot once every interval, and a final one when
+/// they shut down, see MetricsSink. Not part of configuration files, defaults
+/// to None.
+/// * `clock` - The clock delayed events, execution time limits, idle executor
+/// threads, the no-progress check and heartbeats read the time from, see
+/// Clock. Set it to a ManualClock to test timers without real sleeps. Not
+/// part of configuration files, defaults to the system clock.
+/// * `observer` - Optional channel receiving the scheduling decisions of the
+/// instance, for tests, see SchedulerEvent. Not part of configuration files,
+/// defaults to None, see `with_observer(..)`.
+/// * `compute_pool` - Optional rayon thread pool shared by all activities,
+/// reached through `ConstellationHandle::compute_pool()`, see ComputePool.
+/// Only available with the `compute-pool` feature, not part of configuration
+/// files. Defaults to None, see `enable_compute_pool(..)`.
+/// * `fault_injector` - Optional injector of faults in the events and
+/// activities, for testing, see FaultInjector. Only available with the
+/// `fault-injection` feature, not part of configuration files. Defaults to
+/// None.
+/// * `decoders` - Decoders of the activities and payloads which may be sent
+/// to other nodes, see `register_activity(..)` and `register_payload(..)`.
+/// Not part of configuration files, empty by default.
 #[derive(Clone)]
 pub struct ConstellationConfiguration {
     pub local_steal_strategy: StealStrategy,
+    pub steal_strategy_overrides: HashMap<String, StealStrategy>,
     pub remote_steal_strategy: StealStrategy,
     pub number_of_nodes: i32,
     pub number_of_threads: i32,
     pub debug: bool,
     pub context_vec: ContextVec,
-    pub time_between_steals: u64,
+    pub time_between_steals: Duration,
+    pub max_activities_per_thread: Option<usize>,
+    pub shed_high_watermark: Option<usize>,
+    pub steal_batch_size: usize,
+    pub use_env_overrides: bool,
+    pub execution_timeout_callback: Option<ExecutionTimeoutCallback>,
+    pub hooks: LifecycleHooks,
+    pub event_interceptors: Vec<EventInterceptor>,
+    pub thread_contexts: Option<Vec<ContextVec>>,
+    pub deterministic_scheduling: bool,
+    pub shutdown_timeout: Option<Duration>,
+    pub load_report_interval: Duration,
+    pub load_report_stale_intervals: u32,
+    pub master_rank: i32,
+    pub strict_node_count: bool,
+    pub tcp_peers: Option<Vec<String>>,
+    pub tcp_rank: usize,
+    pub same_node_socket_dir: Option<PathBuf>,
+    pub heartbeat_interval: Duration,
+    pub heartbeat_miss_threshold: u32,
+    pub on_node_unresponsive: Option<NodeUnresponsiveCallback>,
+    pub debug_json: Option<PathBuf>,
+    pub record_schedule: Option<PathBuf>,
+    pub replay_schedule: Option<PathBuf>,
+    pub queue_sample_interval: Option<Duration>,
+    pub parent_thread_load_factor: f64,
+    pub rebalance_threshold: Option<f64>,
+    pub pool_allocations: bool,
+    pub allow_unserved_contexts: bool,
+    pub no_progress_timeout: Option<Duration>,
+    pub on_no_progress: Option<NoProgressCallback>,
+    pub metrics_sink: Option<MetricsSink>,
+    pub clock: Clock,
+    pub observer: Option<Sender<SchedulerEvent>>,
+    #[cfg(feature = "compute-pool")]
+    pub compute_pool: Option<Arc<ComputePool>>,
+    #[cfg(feature = "fault-injection")]
+    pub fault_injector: Option<Arc<FaultInjector>>,
+    pub decoders: Decoders,
 }
 
 impl ConstellationConfiguration {
@@ -51,22 +376,78 @@ impl ConstellationConfiguration {
         threads: i32,
         debug: bool,
         context_vec: ContextVec,
-        time_between_steals: u64,
+        time_between_steals: Duration,
     ) -> Box<ConstellationConfiguration> {
         //---------------------SET LOGGING--------------------------
         if debug {
-            simple_logger::init().unwrap();
+            // A logger may be set up already, e.g. by an earlier configuration
+            simple_logger::init().ok();
         }
         //----------------------------------------------------------
 
         Box::from(ConstellationConfiguration {
             local_steal_strategy: lss,
+            steal_strategy_overrides: HashMap::new(),
             remote_steal_strategy: rss,
             number_of_nodes: nodes,
             number_of_threads: threads,
             debug,
             context_vec,
             time_between_steals,
+            max_activities_per_thread: None,
+            shed_high_watermark: None,
+            steal_batch_size: 1,
+            use_env_overrides: true,
+            execution_timeout_callback: None,
+            hooks: LifecycleHooks::default(),
+            event_interceptors: Vec::new(),
+            thread_contexts: None,
+            deterministic_scheduling: false,
+            shutdown_timeout: None,
+            load_report_interval: DEFAULT_LOAD_REPORT_INTERVAL,
+            load_report_stale_intervals: 3,
+            master_rank: 0,
+            strict_node_count: false,
+            tcp_peers: None,
+            tcp_rank: 0,
+            same_node_socket_dir: None,
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            heartbeat_miss_threshold: 3,
+            on_node_unresponsive: None,
+            debug_json: None,
+            record_schedule: None,
+            replay_schedule: None,
+            queue_sample_interval: None,
+            parent_thread_load_factor: DEFAULT_PARENT_THREAD_LOAD_FACTOR,
+            rebalance_threshold: None,
+            pool_allocations: false,
+            allow_unserved_contexts: false,
+            no_progress_timeout: None,
+            on_no_progress: None,
+            metrics_sink: None,
+            clock: Clock::system(),
+            observer: None,
+            #[cfg(feature = "compute-pool")]
+            compute_pool: None,
+            #[cfg(feature = "fault-injection")]
+            fault_injector: None,
+            decoders: Decoders::default(),
+        })
+    }
+
+    /// Create the default configuration for a single node with the given
+    /// number of threads, see `Default`
+    ///
+    /// # Arguments
+    /// * `threads` - Number of threads, 0 means the number of available cores
+    ///
+    /// # Returns
+    /// * `Box<ConstellationConfiguration>` - A boxed ConstellationConfiguration
+    /// struct
+    pub fn single_node(threads: i32) -> Box<ConstellationConfiguration> {
+        Box::from(ConstellationConfiguration {
+            number_of_threads: threads,
+            ..Default::default()
         })
     }
 
@@ -79,6 +460,8 @@ impl ConstellationConfiguration {
     /// * `debug` - boolean indicating whether to print debug messages or not
     /// * `context_vec` - A vector of Context struct, indicating what activities
     /// to execute on this node
+    /// * `time_between_steals` - Time the thread distributing activities
+    /// sleeps before checking for new work
     ///
     /// # Returns
     /// * `Box<ConstellationConfiguration>` - A boxed ConstellationConfiguration
@@ -89,8 +472,741 @@ impl ConstellationConfiguration {
         nodes: i32,
         debug: bool,
         context_vec: ContextVec,
-        time_between_steals: u64,
+        time_between_steals: Duration,
     ) -> Box<ConstellationConfiguration> {
         ConstellationConfiguration::new(lss, rss, nodes, 1, debug, context_vec, time_between_steals)
     }
+
+    /// Set `time_between_steals` from a number of microseconds, the unit it
+    /// used to be given in. Logs a warning with the assumed unit.
+    ///
+    /// # Arguments
+    /// * `time_between_steals` - Time between steals in microseconds
+    #[deprecated(note = "set time_between_steals to a Duration instead")]
+    pub fn set_time_between_steals(&mut self, time_between_steals: u64) {
+        self.time_between_steals = Duration::from_micros(time_between_steals);
+        warn!(
+            "time_between_steals given as {} without a unit, assuming microseconds: {:?}",
+            time_between_steals, self.time_between_steals
+        );
+    }
+
+    /// Register an interceptor which sees every event before it is routed,
+    /// interceptors are run in registration order. See the `intercept` module.
+    ///
+    /// # Arguments
+    /// * `interceptor` - Function which may modify the event, and returns
+    /// whether to continue, drop the event or redirect it to another activity
+    pub fn add_event_interceptor(
+        &mut self,
+        interceptor: Box<dyn Fn(&mut Event) -> InterceptDecision + Send + Sync>,
+    ) {
+        self.event_interceptors.push(Arc::from(interceptor));
+    }
+
+    /// Send the scheduling decisions of the instance to the given channel,
+    /// so tests can assert on them, see SchedulerEvent. The channel should be
+    /// unbounded.
+    ///
+    /// # Arguments
+    /// * `observer` - Sending half of the channel
+    pub fn with_observer(&mut self, observer: Sender<SchedulerEvent>) {
+        self.observer = Some(observer);
+    }
+
+    /// Register the decoder of an activity type, so that activities of the
+    /// type may be stolen by this node. Every node must register the same
+    /// types, see `ActivityTrait::encode()`.
+    ///
+    /// # Arguments
+    /// * `decode` - Decodes the bytes returned by `encode()` of the type
+    pub fn register_activity<T: ActivityTrait>(&mut self, decode: fn(&[u8]) -> Option<T>) {
+        self.decoders.register_activity(decode);
+    }
+
+    /// Register the decoder of a payload type, so that events with the
+    /// payload reach activities stolen by another node. Every node must
+    /// register the same types, see `PayloadTrait::encode()`.
+    ///
+    /// # Arguments
+    /// * `decode` - Decodes the bytes returned by `encode()` of the type
+    pub fn register_payload<T: PayloadTrait>(&mut self, decode: fn(&[u8]) -> Option<T>) {
+        self.decoders.register_payload(decode);
+    }
+
+    /// Create the compute pool, using the cores which are not used by the
+    /// executor threads, or a single thread when there are none left. Set
+    /// `compute_pool` directly to choose the number of threads.
+    ///
+    /// # Arguments
+    /// * `max_concurrent` - Maximum number of activities using the pool at
+    /// the same time
+    ///
+    /// # Returns
+    /// * `Result<(), ConfigError>` - ConfigError::InvalidValue if the pool
+    /// could not be created, the configuration is then unchanged
+    #[cfg(feature = "compute-pool")]
+    pub fn enable_compute_pool(&mut self, max_concurrent: usize) -> Result<(), ConfigError> {
+        let executors = self.resolved_number_of_threads() as usize;
+        let threads = available_cores().saturating_sub(executors).max(1);
+
+        self.compute_pool = Some(Arc::new(ComputePool::new(threads, max_concurrent)?));
+        if self.debug {
+            info!(
+                "Compute pool of {} threads, used by at most {} activities at once",
+                threads, max_concurrent
+            );
+        }
+        Ok(())
+    }
+
+    /// The contexts served by an executor thread, see `thread_contexts`
+    ///
+    /// # Arguments
+    /// * `thread_id` - Index of the executor thread
+    ///
+    /// # Returns
+    /// * `Option<ContextVec>` - The contexts of the thread, None if threads
+    /// are not assigned contexts, or this thread has no entry and
+    /// `context_vec` is empty. The thread executes activities of any context
+    /// then.
+    pub fn thread_context_vec(&self, thread_id: usize) -> Option<ContextVec> {
+        match self.thread_contexts.as_ref()?.get(thread_id) {
+            Some(contexts) => Some(contexts.clone()),
+            None if self.context_vec.context_vec.is_empty() => None,
+            None => Some(self.context_vec.clone()),
+        }
+    }
+
+    /// All contexts activities may be submitted with, these are the contexts
+    /// in `context_vec` and in `thread_contexts`. When `context_vec` is empty
+    /// and a thread has no entry in `thread_contexts`, that thread executes
+    /// activities of any context, so any context is known.
+    ///
+    /// # Returns
+    /// * `ContextVec` - The contexts, each listed once. Empty if activities of
+    /// any context may be submitted.
+    pub fn known_contexts(&self) -> ContextVec {
+        let mut known = self.context_vec.clone();
+        let wildcard_thread = self.thread_contexts.as_ref().map_or(true, |contexts| {
+            contexts.len() < self.resolved_number_of_threads() as usize
+        });
+        if known.context_vec.is_empty() && wildcard_thread {
+            return known;
+        }
+
+        for contexts in self.thread_contexts.iter().flatten() {
+            for context in contexts.context_vec.iter() {
+                if !known.contains(context) {
+                    known.append(context);
+                }
+            }
+        }
+
+        known
+    }
+
+    /// Check for values which can not work, called by the constellation
+    /// factory and when activating. `thread_contexts` is checked against the
+    /// resolved number of threads, see `validate_thread_contexts(..)`.
+    ///
+    /// # Returns
+    /// * `Result<(), ConfigError>` - ConfigError::InvalidValue naming the
+    /// first field found with an invalid value
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.number_of_threads < 0 {
+            return Err(ConfigError::InvalidValue {
+                key: "number_of_threads".to_string(),
+                value: self.number_of_threads.to_string(),
+                reason: "use a positive number, or 0 for the number of available cores".to_string(),
+            });
+        }
+
+        if self.number_of_nodes < 1 {
+            return Err(ConfigError::InvalidValue {
+                key: "number_of_nodes".to_string(),
+                value: self.number_of_nodes.to_string(),
+                reason: "at least one node is needed".to_string(),
+            });
+        }
+
+        if self.time_between_steals < Duration::from_micros(1) {
+            return Err(ConfigError::InvalidValue {
+                key: "time_between_steals".to_string(),
+                value: format!("{:?}", self.time_between_steals),
+                reason: "the load balancer would never sleep, use at least 1 microsecond"
+                    .to_string(),
+            });
+        }
+
+        if self.parent_thread_load_factor.is_nan() || self.parent_thread_load_factor < 0.0 {
+            return Err(ConfigError::InvalidValue {
+                key: "parent_thread_load_factor".to_string(),
+                value: self.parent_thread_load_factor.to_string(),
+                reason: "use a factor of 0 or more".to_string(),
+            });
+        }
+
+        if let Some(threshold) = self.rebalance_threshold {
+            if threshold.is_nan() || threshold < 1.0 {
+                return Err(ConfigError::InvalidValue {
+                    key: "rebalance_threshold".to_string(),
+                    value: threshold.to_string(),
+                    reason: "use a ratio of 1.0 or more".to_string(),
+                });
+            }
+        }
+
+        if self.no_progress_timeout == Some(Duration::from_secs(0)) {
+            return Err(ConfigError::InvalidValue {
+                key: "no_progress_timeout".to_string(),
+                value: "0".to_string(),
+                reason: "every pass of the load balancer would report a stall, use None to \
+                         disable the check"
+                    .to_string(),
+            });
+        }
+
+        if let (Some(_), Some(replay)) = (&self.record_schedule, &self.replay_schedule) {
+            return Err(ConfigError::InvalidValue {
+                key: "replay_schedule".to_string(),
+                value: replay.display().to_string(),
+                reason: "can not record and replay a schedule at the same time".to_string(),
+            });
+        }
+
+        // Any context is accepted when no context is known
+        let known = self.known_contexts();
+        if let Some(label) = self.steal_strategy_overrides.keys().find(|label| {
+            !known.context_vec.is_empty()
+                && !known
+                    .context_vec
+                    .iter()
+                    .any(|c| c.label() == label.as_str())
+        }) {
+            return Err(ConfigError::InvalidValue {
+                key: "steal_strategy_overrides".to_string(),
+                value: label.clone(),
+                reason: "not a context of this configuration".to_string(),
+            });
+        }
+
+        self.validate_thread_contexts(self.resolved_number_of_threads())
+    }
+
+    /// Check `thread_contexts` against the number of threads, there may not
+    /// be more entries than threads and no entry may be empty
+    ///
+    /// # Arguments
+    /// * `threads` - The resolved number of threads on this node
+    ///
+    /// # Returns
+    /// * `Result<(), ConfigError>` - ConfigError::InvalidValue describing the
+    /// first problem found
+    pub fn validate_thread_contexts(&self, threads: i32) -> Result<(), ConfigError> {
+        let contexts = match &self.thread_contexts {
+            Some(contexts) => contexts,
+            None => return Ok(()),
+        };
+
+        if contexts.len() > threads.max(0) as usize {
+            return Err(ConfigError::InvalidValue {
+                key: "thread_contexts".to_string(),
+                value: format!("{} entries", contexts.len()),
+                reason: format!("only {} executor threads are used", threads),
+            });
+        }
+
+        if let Some(i) = contexts.iter().position(|c| c.context_vec.is_empty()) {
+            return Err(ConfigError::InvalidValue {
+                key: format!("thread_contexts[{}]", i),
+                value: "[]".to_string(),
+                reason: "a thread must serve at least one context".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Check `master_rank` against the number of MPI processes
+    ///
+    /// # Arguments
+    /// * `world_size` - The number of MPI processes
+    ///
+    /// # Returns
+    /// * `Result<(), ConfigError>` - ConfigError::InvalidValue if there is no
+    /// process with the master rank
+    pub fn validate_master_rank(&self, world_size: i32) -> Result<(), ConfigError> {
+        if self.master_rank < 0 || self.master_rank >= world_size {
+            return Err(ConfigError::InvalidValue {
+                key: "master_rank".to_string(),
+                value: self.master_rank.to_string(),
+                reason: format!("there are {} MPI processes", world_size),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Compare `number_of_nodes` with the number of MPI processes, a mismatch
+    /// is logged as a warning
+    ///
+    /// # Arguments
+    /// * `world_size` - The number of MPI processes
+    ///
+    /// # Returns
+    /// * `Result<(), ConfigError>` - ConfigError::InvalidValue on a mismatch
+    /// if `strict_node_count` is set, Ok otherwise
+    pub fn check_node_count(&self, world_size: i32) -> Result<(), ConfigError> {
+        if self.number_of_nodes == world_size {
+            return Ok(());
+        }
+
+        let e = ConfigError::InvalidValue {
+            key: "number_of_nodes".to_string(),
+            value: self.number_of_nodes.to_string(),
+            reason: format!("there are {} MPI processes", world_size),
+        };
+        if self.strict_node_count {
+            return Err(e);
+        }
+
+        warn!("{}, using {} nodes", e, world_size);
+        Ok(())
+    }
+
+    /// Number of threads to use on this node. If `number_of_threads` is 0,
+    /// this is the number of available cores.
+    ///
+    /// # Returns
+    /// * `i32` - The resolved number of threads, always at least 1
+    pub fn resolved_number_of_threads(&self) -> i32 {
+        self.resolve_number_of_threads(available_cores)
+    }
+
+    /// Same as `resolved_number_of_threads(..)`, but with the function used to
+    /// detect the number of available cores given as argument.
+    ///
+    /// # Arguments
+    /// * `detect` - Function returning the number of available cores
+    ///
+    /// # Returns
+    /// * `i32` - The resolved number of threads, always at least 1
+    pub fn resolve_number_of_threads(&self, detect: fn() -> usize) -> i32 {
+        if self.number_of_threads == 0 {
+            return detect().max(1) as i32;
+        }
+
+        self.number_of_threads
+    }
+
+    /// Override fields with the values of the following environment
+    /// variables, when they are set:
+    /// * `CONSTELLATION_THREADS` - number_of_threads
+    /// * `CONSTELLATION_DEBUG` - debug
+    /// * `CONSTELLATION_TIME_BETWEEN_STEALS_US` - time_between_steals
+    /// * `CONSTELLATION_LOCAL_STEAL_STRATEGY` - local_steal_strategy
+    /// * `CONSTELLATION_TCP_RANK` - tcp_rank
+    ///
+    /// This is called by the constellation factory, unless
+    /// `use_env_overrides` is false. Each override is logged when debug is
+    /// on.
+    ///
+    /// # Returns
+    /// * `Result<(), ConfigError>` - ConfigError::InvalidValue if one of the
+    /// variables has a malformed value, the configuration is then unchanged
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        let debug = match env::var(ENV_DEBUG) {
+            Ok(value) => Some(parse_bool(ENV_DEBUG, &value)?),
+            Err(_) => None,
+        };
+        let threads = match env::var(ENV_THREADS) {
+            Ok(value) => Some(parse_threads(&value)?),
+            Err(_) => None,
+        };
+        let time_between_steals = match env::var(ENV_TIME_BETWEEN_STEALS) {
+            Ok(value) => Some(Duration::from_micros(parse_value::<u64>(
+                ENV_TIME_BETWEEN_STEALS,
+                &value,
+            )?)),
+            Err(_) => None,
+        };
+        let local_steal_strategy = match env::var(ENV_LOCAL_STEAL_STRATEGY) {
+            Ok(value) => Some(parse_value::<StealStrategy>(
+                ENV_LOCAL_STEAL_STRATEGY,
+                &value,
+            )?),
+            Err(_) => None,
+        };
+        let tcp_rank = match env::var(ENV_TCP_RANK) {
+            Ok(value) => Some(parse_value::<usize>(ENV_TCP_RANK, &value)?),
+            Err(_) => None,
+        };
+
+        if let Some(debug) = debug {
+            if debug && !self.debug {
+                // Logging is only set up in new(..) when debug is on
+                simple_logger::init().ok();
+            }
+            self.debug = debug;
+            self.log_override(ENV_DEBUG, debug);
+        }
+        if let Some(threads) = threads {
+            self.number_of_threads = threads;
+            self.log_override(ENV_THREADS, threads);
+        }
+        if let Some(time) = time_between_steals {
+            self.time_between_steals = time;
+            self.log_override(ENV_TIME_BETWEEN_STEALS, format!("{:?}", time));
+        }
+        if let Some(strategy) = local_steal_strategy {
+            self.log_override(ENV_LOCAL_STEAL_STRATEGY, &strategy);
+            self.local_steal_strategy = strategy;
+        }
+        if let Some(rank) = tcp_rank {
+            self.tcp_rank = rank;
+            self.log_override(ENV_TCP_RANK, rank);
+        }
+
+        Ok(())
+    }
+
+    fn log_override<T: fmt::Display>(&self, key: &str, value: T) {
+        if self.debug {
+            info!("Configuration overridden by environment: {}={}", key, value);
+        }
+    }
+
+    /// Load a configuration from a TOML or JSON file, the format is
+    /// determined by the file extension (.toml or .json). See the module
+    /// documentation for the layout of the file.
+    ///
+    /// # Arguments
+    /// * `path` - Path to the configuration file
+    ///
+    /// # Returns
+    /// * `Result<Box<ConstellationConfiguration>, ConfigError>` - The loaded
+    /// configuration, or a ConfigError if the file could not be read or
+    /// contains unknown fields or invalid values.
+    #[cfg(feature = "config-file")]
+    pub fn from_file<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<Box<ConstellationConfiguration>, ConfigError> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let format = FileFormat::from_path(path)?;
+
+        let content = fs::read_to_string(path).map_err(|e| ConfigError::Io(name.clone(), e))?;
+
+        let file: ConfigurationFile = match format {
+            FileFormat::Toml => toml::from_str(&content).map_err(|e| {
+                ConfigError::Parse(format!("{}: {}", name, toml_error(&content, e)))
+            })?,
+            FileFormat::Json => serde_json::from_str(&content)
+                .map_err(|e| ConfigError::Parse(format!("{}: {}", name, e)))?,
+        };
+
+        let mut config = ConstellationConfiguration::new(
+            file.local_steal_strategy,
+            file.remote_steal_strategy,
+            file.number_of_nodes,
+            file.number_of_threads,
+            file.debug,
+            file.context_vec,
+            Duration::from_micros(file.time_between_steals_us),
+        );
+        config.steal_strategy_overrides = file.steal_strategy_overrides;
+        config.max_activities_per_thread = file.max_activities_per_thread;
+        config.shed_high_watermark = file.shed_high_watermark;
+        config.steal_batch_size = file.steal_batch_size;
+        config.thread_contexts = file.thread_contexts;
+        config.deterministic_scheduling = file.deterministic_scheduling;
+        config.shutdown_timeout = file.shutdown_timeout_ms.map(Duration::from_millis);
+        config.load_report_interval = Duration::from_millis(file.load_report_interval_ms);
+        config.load_report_stale_intervals = file.load_report_stale_intervals;
+        config.master_rank = file.master_rank;
+        config.strict_node_count = file.strict_node_count;
+        config.tcp_peers = file.tcp_peers;
+        config.tcp_rank = file.tcp_rank;
+        config.same_node_socket_dir = file.same_node_socket_dir;
+        config.heartbeat_interval = Duration::from_millis(file.heartbeat_interval_ms);
+        config.heartbeat_miss_threshold = file.heartbeat_miss_threshold;
+        config.debug_json = file.debug_json;
+        config.record_schedule = file.record_schedule;
+        config.replay_schedule = file.replay_schedule;
+        config.queue_sample_interval = file.queue_sample_interval_ms.map(Duration::from_millis);
+        config.parent_thread_load_factor = file.parent_thread_load_factor;
+        config.rebalance_threshold = file.rebalance_threshold;
+        config.pool_allocations = file.pool_allocations;
+        config.allow_unserved_contexts = file.allow_unserved_contexts;
+        config.no_progress_timeout = file.no_progress_timeout_ms.map(Duration::from_millis);
+
+        Ok(config)
+    }
+
+    /// Store this configuration in a TOML or JSON file, the format is
+    /// determined by the file extension (.toml or .json). The file can be
+    /// loaded again with `from_file(..)`, e.g. to reproduce a run.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the file to write, overwritten if it exists
+    ///
+    /// # Returns
+    /// * `Result<(), ConfigError>` - ConfigError if the file could not be
+    /// written
+    #[cfg(feature = "config-file")]
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let format = FileFormat::from_path(path)?;
+
+        let file = ConfigurationFile {
+            local_steal_strategy: self.local_steal_strategy.clone(),
+            steal_strategy_overrides: self.steal_strategy_overrides.clone(),
+            remote_steal_strategy: self.remote_steal_strategy.clone(),
+            number_of_nodes: self.number_of_nodes,
+            number_of_threads: self.number_of_threads,
+            debug: self.debug,
+            context_vec: self.context_vec.clone(),
+            time_between_steals_us: self.time_between_steals.as_micros() as u64,
+            max_activities_per_thread: self.max_activities_per_thread,
+            shed_high_watermark: self.shed_high_watermark,
+            steal_batch_size: self.steal_batch_size,
+            thread_contexts: self.thread_contexts.clone(),
+            deterministic_scheduling: self.deterministic_scheduling,
+            shutdown_timeout_ms: self.shutdown_timeout.map(|t| t.as_millis() as u64),
+            load_report_interval_ms: self.load_report_interval.as_millis() as u64,
+            load_report_stale_intervals: self.load_report_stale_intervals,
+            master_rank: self.master_rank,
+            strict_node_count: self.strict_node_count,
+            tcp_peers: self.tcp_peers.clone(),
+            tcp_rank: self.tcp_rank,
+            same_node_socket_dir: self.same_node_socket_dir.clone(),
+            heartbeat_interval_ms: self.heartbeat_interval.as_millis() as u64,
+            heartbeat_miss_threshold: self.heartbeat_miss_threshold,
+            debug_json: self.debug_json.clone(),
+            record_schedule: self.record_schedule.clone(),
+            replay_schedule: self.replay_schedule.clone(),
+            queue_sample_interval_ms: self.queue_sample_interval.map(|t| t.as_millis() as u64),
+            parent_thread_load_factor: self.parent_thread_load_factor,
+            rebalance_threshold: self.rebalance_threshold,
+            pool_allocations: self.pool_allocations,
+            allow_unserved_contexts: self.allow_unserved_contexts,
+            no_progress_timeout_ms: self.no_progress_timeout.map(|t| t.as_millis() as u64),
+        };
+
+        let content = match format {
+            FileFormat::Toml => toml::to_string_pretty(&file)
+                .map_err(|e| ConfigError::Parse(format!("{}: {}", name, e)))?,
+            FileFormat::Json => serde_json::to_string_pretty(&file)
+                .map_err(|e| ConfigError::Parse(format!("{}: {}", name, e)))?,
+        };
+
+        fs::write(path, content).map_err(|e| ConfigError::Io(name, e))
+    }
+}
+
+/// Layout of a configuration file, mirrors ConstellationConfiguration but
+/// stores the contexts as a list of labels. The fields are written in
+/// order, and TOML requires all values to come before the tables, so the
+/// steal_strategy_overrides table is the last field.
+#[cfg(feature = "config-file")]
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct ConfigurationFile {
+    local_steal_strategy: StealStrategy,
+    remote_steal_strategy: StealStrategy,
+    number_of_nodes: i32,
+    number_of_threads: i32,
+    debug: bool,
+    context_vec: ContextVec,
+    #[serde(alias = "time_between_steals")]
+    time_between_steals_us: u64,
+    max_activities_per_thread: Option<usize>,
+    shed_high_watermark: Option<usize>,
+    steal_batch_size: usize,
+    thread_contexts: Option<Vec<ContextVec>>,
+    deterministic_scheduling: bool,
+    shutdown_timeout_ms: Option<u64>,
+    load_report_interval_ms: u64,
+    load_report_stale_intervals: u32,
+    master_rank: i32,
+    strict_node_count: bool,
+    tcp_peers: Option<Vec<String>>,
+    tcp_rank: usize,
+    same_node_socket_dir: Option<PathBuf>,
+    heartbeat_interval_ms: u64,
+    heartbeat_miss_threshold: u32,
+    debug_json: Option<PathBuf>,
+    record_schedule: Option<PathBuf>,
+    replay_schedule: Option<PathBuf>,
+    queue_sample_interval_ms: Option<u64>,
+    parent_thread_load_factor: f64,
+    rebalance_threshold: Option<f64>,
+    pool_allocations: bool,
+    allow_unserved_contexts: bool,
+    no_progress_timeout_ms: Option<u64>,
+    steal_strategy_overrides: HashMap<String, StealStrategy>,
+}
+
+impl Default for ConstellationConfiguration {
+    /// A single node using all available cores, BIGGEST steal strategies,
+    /// DEFAULT_TIME_BETWEEN_STEALS, debug messages off and no contexts, so
+    /// activities of any context are accepted
+    fn default() -> ConstellationConfiguration {
+        *ConstellationConfiguration::new(
+            DEFAULT_STEAL_STRATEGY,
+            DEFAULT_STEAL_STRATEGY,
+            DEFAULT_NUMBER_OF_NODES,
+            DEFAULT_NUMBER_OF_THREADS,
+            false,
+            ContextVec::new(),
+            DEFAULT_TIME_BETWEEN_STEALS,
+        )
+    }
+}
+
+#[cfg(feature = "config-file")]
+impl Default for ConfigurationFile {
+    fn default() -> ConfigurationFile {
+        ConfigurationFile {
+            local_steal_strategy: DEFAULT_STEAL_STRATEGY,
+            steal_strategy_overrides: HashMap::new(),
+            remote_steal_strategy: DEFAULT_STEAL_STRATEGY,
+            number_of_nodes: DEFAULT_NUMBER_OF_NODES,
+            number_of_threads: DEFAULT_NUMBER_OF_THREADS,
+            debug: false,
+            context_vec: ContextVec::new(),
+            time_between_steals_us: DEFAULT_TIME_BETWEEN_STEALS.as_micros() as u64,
+            max_activities_per_thread: None,
+            shed_high_watermark: None,
+            steal_batch_size: 1,
+            thread_contexts: None,
+            deterministic_scheduling: false,
+            shutdown_timeout_ms: None,
+            load_report_interval_ms: DEFAULT_LOAD_REPORT_INTERVAL.as_millis() as u64,
+            load_report_stale_intervals: 3,
+            master_rank: 0,
+            strict_node_count: false,
+            tcp_peers: None,
+            tcp_rank: 0,
+            same_node_socket_dir: None,
+            heartbeat_interval_ms: DEFAULT_HEARTBEAT_INTERVAL.as_millis() as u64,
+            heartbeat_miss_threshold: 3,
+            debug_json: None,
+            record_schedule: None,
+            replay_schedule: None,
+            queue_sample_interval_ms: None,
+            parent_thread_load_factor: DEFAULT_PARENT_THREAD_LOAD_FACTOR,
+            rebalance_threshold: None,
+            pool_allocations: false,
+            allow_unserved_contexts: false,
+            no_progress_timeout_ms: None,
+        }
+    }
+}
+
+/// Supported configuration file formats
+#[cfg(feature = "config-file")]
+enum FileFormat {
+    Toml,
+    Json,
+}
+
+#[cfg(feature = "config-file")]
+impl FileFormat {
+    fn from_path(path: &Path) -> Result<FileFormat, ConfigError> {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("toml") => Ok(FileFormat::Toml),
+            Some("json") => Ok(FileFormat::Json),
+            _ => Err(ConfigError::UnsupportedFormat(path.display().to_string())),
+        }
+    }
+}
+
+/// Describe an error in a TOML file. The toml crate reports an unknown field
+/// at the start of its table, the line of the field itself is looked up so
+/// the error points at the misspelled key.
+#[cfg(feature = "config-file")]
+fn toml_error(content: &str, error: toml::de::Error) -> String {
+    let message = error.to_string();
+    let (table_line, col) = match error.line_col() {
+        Some(position) => position,
+        None => return message,
+    };
+    let field = match message.split("unknown field `").nth(1) {
+        Some(rest) => rest.split('`').next().unwrap_or(""),
+        None => return message,
+    };
+
+    let found = content
+        .lines()
+        .enumerate()
+        .skip(table_line)
+        .find(|(_, line)| {
+            let line = line.trim_start();
+            line.starts_with(field) && line[field.len()..].trim_start().starts_with('=')
+        });
+
+    match found {
+        Some((number, line)) => {
+            let position = format!(" at line {} column {}", table_line + 1, col + 1);
+            format!(
+                "{} at line {} column {}",
+                message.trim_end_matches(position.as_str()),
+                number + 1,
+                line.len() - line.trim_start().len() + 1
+            )
+        }
+        None => message,
+    }
+}
+
+/// Parse an environment variable value
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, ConfigError>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .trim()
+        .parse::<T>()
+        .map_err(|e| ConfigError::InvalidValue {
+            key: key.to_string(),
+            value: value.to_string(),
+            reason: e.to_string(),
+        })
+}
+
+/// Parse a boolean, accepting true/false/1/0 in any case
+fn parse_bool(key: &str, value: &str) -> Result<bool, ConfigError> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(ConfigError::InvalidValue {
+            key: key.to_string(),
+            value: value.to_string(),
+            reason: "expected true, false, 1 or 0".to_string(),
+        }),
+    }
+}
+
+/// Parse a number of threads, which can not be negative
+fn parse_threads(value: &str) -> Result<i32, ConfigError> {
+    let threads = parse_value::<i32>(ENV_THREADS, value)?;
+    if threads < 0 {
+        return Err(ConfigError::InvalidValue {
+            key: ENV_THREADS.to_string(),
+            value: value.to_string(),
+            reason: "expected 0 (all available cores) or a positive number".to_string(),
+        });
+    }
+
+    Ok(threads)
+}
+
+/// Number of cores available to this process, 1 if it can not be determined
+fn available_cores() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
 }
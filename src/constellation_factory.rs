@@ -1,31 +1,172 @@
 ///! Use this struct to retrieve a ConstellationInstance, specify if you wish
 ///! to run single/multi-threaded or distributed using the Mode enum.
+///!
+///! `run(..)` wraps the steps every program takes: creating and activating
+///! the instance, running the application on the master node while the
+///! other nodes execute the activities they steal, and shutting the instance
+///! down, also when the application panics.
 use crate::{
-    ConstellationConfiguration, ConstellationTrait, MultiThreadedConstellation,
-    SingleThreadConstellation,
+    Communication, ConstellationConfiguration, ConstellationError, ConstellationTrait, MpiComm,
+    MultiThreadedConstellation, SameNodeComm, SingleThreadConstellation, TcpComm,
 };
 
-/// Use to specify which constellation instance to create
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Interval at which `run_worker(..)` retries shutting down a node which
+/// still executes stolen activities
+const WORKER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Use to specify which constellation instance to create. A distributed
+/// instance is multithreaded and communicates over TCP when `tcp_peers` is
+/// set in the configuration, over MPI otherwise. When `same_node_socket_dir`
+/// is set, processes on the same node communicate over unix domain sockets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
     SingleThreaded,
     MultiThreaded,
     Distributed,
 }
 
+/// Create a new constellation instance. Unless `use_env_overrides` is false
+/// in the configuration, the CONSTELLATION_* environment variables are
+/// applied first, see `ConstellationConfiguration::apply_env`.
+///
+/// Panics if one of the environment variables has a malformed value, if the
+/// configuration is invalid (see `ConstellationConfiguration::validate`), or
+/// if a distributed instance can not set up communication over TCP or unix
+/// domain sockets.
 pub fn new_constellation(
     mode: Mode,
-    config: Box<ConstellationConfiguration>,
+    mut config: Box<ConstellationConfiguration>,
 ) -> Box<dyn ConstellationTrait> {
+    if config.use_env_overrides {
+        if let Err(e) = config.apply_env() {
+            panic!("Could not apply environment overrides: {}", e);
+        }
+    }
+
+    if let Err(e) = config.validate() {
+        panic!("Invalid configuration: {}", e);
+    }
+
     match mode {
         Mode::SingleThreaded => Box::from(SingleThreadConstellation::new(config)),
         Mode::MultiThreaded => {
-            if config.number_of_threads == 1 && config.debug {
-                info!("Only one thread specified for multithreaded constellation, returning single threaded instead");
+            if config.resolved_number_of_threads() == 1 && config.debug {
+                info!(
+                    "Only one thread specified for multithreaded constellation, returning \
+                     single threaded instead"
+                );
                 return Box::from(SingleThreadConstellation::new(config));
             }
 
             Box::from(MultiThreadedConstellation::new(config))
         }
-        Mode::Distributed => unimplemented!(),
+        Mode::Distributed => {
+            let comm: Arc<dyn Communication> = match &config.tcp_peers {
+                Some(peers) => match TcpComm::new(peers, config.tcp_rank) {
+                    Ok(comm) => Arc::new(comm),
+                    Err(e) => panic!("Could not set up communication over TCP: {}", e),
+                },
+                None => Arc::new(MpiComm::new()),
+            };
+
+            let comm: Arc<dyn Communication> = match &config.same_node_socket_dir {
+                Some(directory) => match SameNodeComm::new(comm, directory) {
+                    Ok(comm) => Arc::new(comm),
+                    Err(e) => panic!("Could not set up communication on the same node: {}", e),
+                },
+                None => comm,
+            };
+
+            Box::from(MultiThreadedConstellation::with_communication(config, comm))
+        }
+    }
+}
+
+/// Create and activate a constellation instance, run `master_fn` on the
+/// master node and shut the instance down. The other nodes call
+/// `run_worker(..)` instead.
+///
+/// `done()` is called once `master_fn` returns, a warning is logged if the
+/// instance could not be shut down gracefully, in which case it is shut down
+/// forcefully when dropped. When `master_fn` panics, the instance is dropped,
+/// which shuts it down forcefully, and the panic is resumed afterwards.
+///
+/// Panics like `new_constellation(..)`, and if the instance can not be
+/// activated.
+///
+/// # Arguments
+/// * `mode` - Which constellation instance to create
+/// * `config` - Configuration of the instance
+/// * `master_fn` - The application, given the activated instance
+///
+/// # Returns
+/// * `Option<R>` - The result of `master_fn` on the master node, None on the
+/// other nodes
+pub fn run<R, F>(mode: Mode, config: Box<ConstellationConfiguration>, master_fn: F) -> Option<R>
+where
+    F: FnOnce(&mut dyn ConstellationTrait) -> R,
+{
+    let mut constellation = new_constellation(mode, config);
+
+    if let Err(e) = constellation.activate() {
+        panic!("Could not activate constellation: {}", e);
+    }
+
+    let master = match constellation.is_master() {
+        Ok(master) => master,
+        Err(e) => panic!("Could not determine the master node: {}", e),
+    };
+
+    if !master {
+        if let Err(e) = run_worker(constellation.as_mut()) {
+            warn!("Could not shut down worker node: {}", e);
+        }
+        return None;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| master_fn(constellation.as_mut())));
+
+    let result = match result {
+        Ok(result) => result,
+        Err(payload) => {
+            // Dropping shuts the instance down forcefully
+            drop(constellation);
+            panic::resume_unwind(payload);
+        }
+    };
+
+    match constellation.done() {
+        Ok(true) => (),
+        Ok(false) => warn!("Constellation could not shut down gracefully"),
+        Err(e) => warn!("Constellation could not shut down gracefully: {}", e),
+    }
+
+    Some(result)
+}
+
+/// Run a node which is not the master. The node executes the activities it
+/// steals from the other nodes until the master is done, then the instance
+/// is shut down once the stolen activities have finished.
+///
+/// # Arguments
+/// * `constellation` - The activated instance of a node which is not the
+/// master
+///
+/// # Returns
+/// * `Result<bool, ConstellationError>` - The result of `done()`, or the
+/// error of `wait_for_master()`
+pub fn run_worker(constellation: &mut dyn ConstellationTrait) -> Result<bool, ConstellationError> {
+    constellation.wait_for_master()?;
+
+    loop {
+        match constellation.done() {
+            Err(ConstellationError::WorkLeft(_)) => thread::sleep(WORKER_POLL_INTERVAL),
+            result => return result,
+        }
     }
 }
@@ -3,7 +3,83 @@
 ///! When setting up constellation all nodes/threads receive a vector of
 ///! contexts, they will then only execute activities that has one ore more
 ///! matching contexts.
+///!
+///! Labels are interned process-wide: every distinct label gets a ContextId
+///! the first time it is used, and a Context holds that id next to the
+///! interned label. Contexts are compared and hashed by their id, so checking
+///! whether a thread or node serves the context of an activity compares
+///! integers instead of strings, and cloning a Context does not allocate. The
+///! label is only kept for displaying the context and for exchanging it with
+///! other nodes and configuration files. The interned labels live until the
+///! process exits, there should be few of them.
+///!
+///! A ContextVec is most easily built with the `contexts!` macro. With the
+///! config-file feature, a Context is serialized as its label and a
+///! ContextVec as a list of labels.
+#[cfg(feature = "config-file")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::RwLock;
+
+use hashbrown::HashMap;
+
+lazy_static! {
+    /// The interned labels of all contexts created in this process
+    static ref INTERNER: RwLock<Interner> = RwLock::new(Interner {
+        ids: HashMap::new(),
+        labels: Vec::new(),
+    });
+}
+
+/// Interner of the context labels
+///
+/// # Members
+/// * `ids` - The id of every interned label
+/// * `labels` - The interned labels, by id
+struct Interner {
+    ids: HashMap<&'static str, ContextId>,
+    labels: Vec<&'static str>,
+}
+
+impl Interner {
+    /// The id and interned copy of a label, interning it if it is new. Only
+    /// takes the write lock for a new label.
+    fn intern(label: &str) -> (ContextId, &'static str) {
+        {
+            let interner = INTERNER.read().unwrap();
+            if let Some(&id) = interner.ids.get(label) {
+                return (id, interner.labels[id.0 as usize]);
+            }
+        }
+
+        let mut interner = INTERNER.write().unwrap();
+        // Another thread may have interned it meanwhile
+        if let Some(&id) = interner.ids.get(label) {
+            return (id, interner.labels[id.0 as usize]);
+        }
+
+        let id = ContextId(interner.labels.len() as u32);
+        let label: &'static str = Box::leak(label.to_string().into_boxed_str());
+        interner.ids.insert(label, id);
+        interner.labels.push(label);
+        (id, label)
+    }
+}
+
+/// Process-wide id of a context label, see `Context::intern(..)`. Ids are
+/// handed out in the order labels are first used, so they differ between
+/// processes, exchange labels with other nodes instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ContextId(u32);
+
+impl ContextId {
+    /// The label this id was interned for
+    pub fn label(self) -> &'static str {
+        INTERNER.read().unwrap().labels[self.0 as usize]
+    }
+}
 
 /// Holds any number of context, use to identify on which executor
 /// activities should be executed. Lives both in Constellation and on each
@@ -36,19 +112,127 @@ impl ContextVec {
     }
 }
 
+impl From<&str> for ContextVec {
+    fn from(label: &str) -> ContextVec {
+        ContextVec {
+            context_vec: vec![Context::new(label)],
+        }
+    }
+}
+
+impl From<Vec<&str>> for ContextVec {
+    fn from(labels: Vec<&str>) -> ContextVec {
+        ContextVec {
+            context_vec: labels.into_iter().map(Context::new).collect(),
+        }
+    }
+}
+
+#[cfg(feature = "config-file")]
+impl Serialize for ContextVec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.context_vec.iter().map(|x| x.label))
+    }
+}
+
+#[cfg(feature = "config-file")]
+impl<'de> Deserialize<'de> for ContextVec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ContextVec, D::Error> {
+        let labels: Vec<String> = Vec::deserialize(deserializer)?;
+        Ok(ContextVec {
+            context_vec: labels.iter().map(|x| Context::new(x)).collect(),
+        })
+    }
+}
+
+/// Create a ContextVec holding a Context for each of the given labels
+///
+/// # Example
+///
+/// ```
+/// # #[macro_use] extern crate constellation_rust;
+/// # fn main() {
+/// let contexts = contexts!["io", "compute/gpu"];
+/// assert_eq!(contexts.context_vec.len(), 2);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! contexts {
+    ($($label:expr),* $(,)?) => {{
+        let mut context_vec = $crate::ContextVec::new();
+        $(context_vec.append(&$crate::Context::new($label));)*
+        context_vec
+    }};
+}
+
 impl fmt::Display for ContextVec {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let tmp: &Vec<Context> = self.context_vec.as_ref();
-        let labels: Vec<String> = tmp.into_iter().map(|x| x.label.clone()).collect();
+        let labels: Vec<&str> = tmp.into_iter().map(|x| x.label).collect();
 
         write!(f, "context:{:?}", labels)
     }
 }
 
 /// Context used to identify where an activity should be executed.
+///
+/// # Members
+/// * `id` - Interned id of the label, compared and hashed
+/// * `label` - The interned label, for displaying the context
 #[derive(Debug, Clone)]
 pub struct Context {
-    pub label: String,
+    id: ContextId,
+    label: &'static str,
+}
+
+impl Context {
+    /// Create the context with the given label, the label is interned the
+    /// first time it is used
+    pub fn new(label: &str) -> Context {
+        let (id, label) = Interner::intern(label);
+        Context { id, label }
+    }
+
+    /// The id of a label, interning it if it is new. Thread safe, the same
+    /// label always gets the same id within a process.
+    ///
+    /// # Arguments
+    /// * `label` - Label of a context
+    ///
+    /// # Returns
+    /// * `ContextId` - The id of the label
+    pub fn intern(label: &str) -> ContextId {
+        Interner::intern(label).0
+    }
+
+    pub fn id(&self) -> ContextId {
+        self.id
+    }
+
+    pub fn label(&self) -> &'static str {
+        self.label
+    }
+}
+
+impl From<&str> for Context {
+    fn from(label: &str) -> Context {
+        Context::new(label)
+    }
+}
+
+#[cfg(feature = "config-file")]
+impl Serialize for Context {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.label)
+    }
+}
+
+#[cfg(feature = "config-file")]
+impl<'de> Deserialize<'de> for Context {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Context, D::Error> {
+        let label = String::deserialize(deserializer)?;
+        Ok(Context::new(&label))
+    }
 }
 
 impl fmt::Display for Context {
@@ -59,8 +243,15 @@ impl fmt::Display for Context {
 
 impl PartialEq for Context {
     fn eq(&self, other: &Context) -> bool {
-        self.label == other.label
+        self.id == other.id
     }
 }
 
 impl Eq for Context {}
+
+/// Must hash exactly the field compared by `eq`
+impl Hash for Context {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
@@ -0,0 +1,92 @@
+///! Decoders of the activities and payloads sent to other nodes. An activity
+///! stolen by another node, and the events sent to it, cross the nodes in the
+///! form returned by `ActivityTrait::encode()` and `PayloadTrait::encode()`,
+///! together with the name of their type. The receiving node looks up the
+///! decoder registered for that name, so every node must register the same
+///! types, see `ConstellationConfiguration::register_activity(..)` and
+///! `ConstellationConfiguration::register_payload(..)`.
+use crate::{ActivityTrait, PayloadTrait};
+
+use std::sync::{Arc, Mutex};
+
+use hashbrown::HashMap;
+
+/// Turns an encoded activity back into an activity, None if the bytes are
+/// malformed
+pub type ActivityDecoder =
+    Arc<dyn Fn(&[u8]) -> Option<Arc<Mutex<dyn ActivityTrait>>> + Send + Sync>;
+/// Turns an encoded payload back into a payload, None if the bytes are
+/// malformed
+pub type PayloadDecoder = Arc<dyn Fn(&[u8]) -> Option<Box<dyn PayloadTrait>> + Send + Sync>;
+
+/// The decoders of every activity and payload type which may be sent to
+/// another node
+///
+/// # Members
+/// * `activities` - Decoder of every activity type, by type name
+/// * `payloads` - Decoder of every payload type, by type name
+#[derive(Clone, Default)]
+pub struct Decoders {
+    activities: HashMap<String, ActivityDecoder>,
+    payloads: HashMap<String, PayloadDecoder>,
+}
+
+impl Decoders {
+    /// Register the decoder of an activity type, replacing an earlier one
+    ///
+    /// # Arguments
+    /// * `decode` - Decodes the bytes returned by `encode()` of the type
+    pub fn register_activity<T: ActivityTrait>(&mut self, decode: fn(&[u8]) -> Option<T>) {
+        self.activities.insert(
+            std::any::type_name::<T>().to_string(),
+            Arc::new(move |bytes: &[u8]| {
+                decode(bytes)
+                    .map(|activity| Arc::new(Mutex::new(activity)) as Arc<Mutex<dyn ActivityTrait>>)
+            }),
+        );
+    }
+
+    /// Register the decoder of a payload type, replacing an earlier one
+    ///
+    /// # Arguments
+    /// * `decode` - Decodes the bytes returned by `encode()` of the type
+    pub fn register_payload<T: PayloadTrait>(&mut self, decode: fn(&[u8]) -> Option<T>) {
+        self.payloads.insert(
+            std::any::type_name::<T>().to_string(),
+            Arc::new(move |bytes: &[u8]| {
+                decode(bytes).map(|payload| Box::new(payload) as Box<dyn PayloadTrait>)
+            }),
+        );
+    }
+
+    /// Whether a decoder is registered for the activity type
+    pub fn decodes_activity(&self, type_name: &str) -> bool {
+        self.activities.contains_key(type_name)
+    }
+
+    /// Decode an activity of the given type
+    ///
+    /// # Returns
+    /// * `Option<Arc<Mutex<dyn ActivityTrait>>>` - The activity, None if no
+    /// decoder is registered for the type or the bytes are malformed
+    pub fn decode_activity(
+        &self,
+        type_name: &str,
+        bytes: &[u8],
+    ) -> Option<Arc<Mutex<dyn ActivityTrait>>> {
+        self.activities
+            .get(type_name)
+            .and_then(|decode| decode(bytes))
+    }
+
+    /// Decode a payload of the given type
+    ///
+    /// # Returns
+    /// * `Option<Box<dyn PayloadTrait>>` - The payload, None if no decoder is
+    /// registered for the type or the bytes are malformed
+    pub fn decode_payload(&self, type_name: &str, bytes: &[u8]) -> Option<Box<dyn PayloadTrait>> {
+        self.payloads
+            .get(type_name)
+            .and_then(|decode| decode(bytes))
+    }
+}
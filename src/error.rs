@@ -1,23 +1,233 @@
 //! Module for handling Errors and Results
-use std::{error, fmt, result};
+use crate::submit_options::Placement;
+use crate::work_left::WorkLeftReport;
+use crate::ActivityIdentifier;
 
-#[derive(Debug)]
-pub struct ConstellationError;
+use std::time::Duration;
+use std::{error, fmt, io, result};
+
+/// Error returned by Constellation operations
+///
+/// * `Failed` - Generic error, the cause is logged where it occurs
+/// * `NotActivated` - The constellation instance has not been activated yet,
+/// call `activate()` first
+/// * `AlreadyActivated` - `activate()` was called before on this instance,
+/// and it has not been shut down with `done()` since
+/// * `AlreadyShutDown` - The constellation instance has been shut down with
+/// `done()`, it can not be used anymore
+/// * `InvalidConfiguration` - The configuration is inconsistent, the cause is
+/// logged when activating
+/// * `ForcedShutdown` - `done()` shut the instance down after the
+/// `shutdown_timeout` expired with work left, the value is the number of
+/// activities which were cancelled (see `ActivityTrait::cancelled(..)`). The
+/// instance is shut down, calling `done()` again returns true.
+/// * `WorkLeft` - `done()` could not shut the instance down because work is
+/// left, the report describes what is left and embeds a snapshot of all
+/// queues. Calling `done()` again later may succeed.
+/// * `UnknownContext` - An activity was submitted with a context which is not
+/// in the configuration, or which no executor thread serves so it would never
+/// run, the value is its label. See `allow_unserved_contexts` in the
+/// configuration.
+/// * `InvalidThreadAffinity` - An activity was submitted with an affinity for
+/// a thread which does not exist, the value is the thread index
+/// * `QueueFull` - An activity could not be submitted because the work queue
+/// is full. Work queues are not bounded yet, so this is currently never
+/// returned.
+/// * `DuplicateName` - An activity was submitted with a name which belongs to
+/// an activity that has not finished yet, the value is the name
+/// * `UnsatisfiablePlacement` - An activity was submitted with a placement
+/// which can not be satisfied by the nodes of the instance, the value is the
+/// placement, see `SubmitOptions::placement`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConstellationError {
+    Failed,
+    NotActivated,
+    AlreadyActivated,
+    AlreadyShutDown,
+    InvalidConfiguration,
+    ForcedShutdown(usize),
+    WorkLeft(Box<WorkLeftReport>),
+    UnknownContext(String),
+    InvalidThreadAffinity(usize),
+    QueueFull,
+    DuplicateName(String),
+    UnsatisfiablePlacement(Placement),
+}
 
 // Result type which can often have Constellation errors
 pub type Result<T> = result::Result<T, ConstellationError>;
 
 impl fmt::Display for ConstellationError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "THIS IS AN ERROR")
+        match self {
+            ConstellationError::Failed => write!(f, "Constellation operation failed"),
+            ConstellationError::NotActivated => write!(
+                f,
+                "Constellation instance is not activated, call activate() first"
+            ),
+            ConstellationError::AlreadyActivated => {
+                write!(f, "Constellation instance has already been activated")
+            }
+            ConstellationError::AlreadyShutDown => {
+                write!(f, "Constellation instance has already been shut down")
+            }
+            ConstellationError::InvalidConfiguration => {
+                write!(f, "Invalid constellation configuration")
+            }
+            ConstellationError::ForcedShutdown(cancelled) => write!(
+                f,
+                "Constellation instance was shut down forcefully, {} activities were cancelled",
+                cancelled
+            ),
+            ConstellationError::WorkLeft(report) => {
+                write!(f, "Constellation instance can not shut down. {}", report)
+            }
+            ConstellationError::UnknownContext(label) => {
+                write!(f, "Unknown context: {}", label)
+            }
+            ConstellationError::InvalidThreadAffinity(index) => {
+                write!(f, "Thread affinity {} is out of range", index)
+            }
+            ConstellationError::QueueFull => write!(f, "Work queue is full"),
+            ConstellationError::DuplicateName(name) => {
+                write!(f, "An activity named {} is already running", name)
+            }
+            ConstellationError::UnsatisfiablePlacement(placement) => {
+                write!(f, "Placement {} can not be satisfied", placement)
+            }
+        }
     }
 }
 
 impl error::Error for ConstellationError {
-    // TODO Add methods/functions to identify error
-
-    fn cause(&self) -> Option<&error::Error> {
+    fn cause(&self) -> Option<&dyn error::Error> {
         // Generic error, underlying cause isn't tracked.
         None
     }
 }
+
+/// Error returned when an event can not be sent. Events for an activity which
+/// has not been placed on a thread yet are not an error, they are kept until
+/// the activity is placed.
+///
+/// * `Constellation` - The instance can not send events, e.g. because it is
+/// not activated (NotActivated) or has been shut down (AlreadyShutDown)
+/// * `UnknownDestination` - The destination identifier was not generated by
+/// this constellation instance, e.g. it belongs to another instance
+/// * `DestinationFinished` - The destination activity has finished
+/// * `QueueFull` - The event queue of the destination is full. Event queues
+/// are not bounded yet, so this is currently never returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendError {
+    Constellation(ConstellationError),
+    UnknownDestination(ActivityIdentifier),
+    DestinationFinished(ActivityIdentifier),
+    QueueFull(ActivityIdentifier),
+}
+
+impl From<ConstellationError> for SendError {
+    fn from(e: ConstellationError) -> SendError {
+        SendError::Constellation(e)
+    }
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SendError::Constellation(e) => write!(f, "Could not send event: {}", e),
+            SendError::UnknownDestination(aid) => {
+                write!(f, "Unknown destination activity {}", aid)
+            }
+            SendError::DestinationFinished(aid) => {
+                write!(f, "Destination activity {} has finished", aid)
+            }
+            SendError::QueueFull(aid) => {
+                write!(f, "Event queue of destination activity {} is full", aid)
+            }
+        }
+    }
+}
+
+impl error::Error for SendError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SendError::Constellation(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Error of an activity, passed to `ActivityTrait::on_error(..)`
+///
+/// * `Panicked` - An invocation of the activity panicked, the value is the
+/// panic message. The activity is retired afterwards, see
+/// `CompletionReason::Failed`.
+/// * `TimedOut` - An invocation of the activity exceeded its
+/// `max_execution_time`, see SubmitOptions. Activities are not preempted, so
+/// this is reported when the invocation returned, and the activity keeps
+/// running. Only reported once per activity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActivityError {
+    Panicked(String),
+    TimedOut { limit: Duration, elapsed: Duration },
+}
+
+impl fmt::Display for ActivityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ActivityError::Panicked(message) => write!(f, "Activity panicked: {}", message),
+            ActivityError::TimedOut { limit, elapsed } => write!(
+                f,
+                "Activity ran for {:?}, exceeding its maximum execution time of {:?}",
+                elapsed, limit
+            ),
+        }
+    }
+}
+
+impl error::Error for ActivityError {}
+
+/// Error returned when a ConstellationConfiguration can not be created,
+/// loaded or stored.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// Reading or writing the file at the given path failed
+    Io(String, io::Error),
+    /// The file could not be parsed or serialized, the message contains the
+    /// location of the problem in the file
+    Parse(String),
+    /// The file extension does not match a supported format
+    UnsupportedFormat(String),
+    /// A value given for a configuration key is malformed
+    InvalidValue {
+        key: String,
+        value: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "Could not access {}: {}", path, e),
+            ConfigError::Parse(msg) => write!(f, "Invalid configuration: {}", msg),
+            ConfigError::UnsupportedFormat(path) => write!(
+                f,
+                "Unsupported configuration format for {}, use .toml or .json",
+                path
+            ),
+            ConfigError::InvalidValue { key, value, reason } => {
+                write!(f, "Invalid value '{}' for {}: {}", value, key, reason)
+            }
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ConfigError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
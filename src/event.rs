@@ -2,21 +2,33 @@
 ///! Payload to carry the data, which can be user implemented as long as it
 ///! extends the `PayloadTrait`. Events also carry information about the sending
 ///! and receiving activities.
-use super::payload::PayloadTrait;
+use super::payload::{ArcPayload, PayloadTrait, TakenPayload};
+use crate::ack::{AckHandle, EventAck};
 use crate::activity_identifier::ActivityIdentifier;
+use crate::implementation::object_pool;
 use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Counter used to give every event a unique id
+static NEXT_EVENT_ID: AtomicU64 = AtomicU64::new(0);
 
 /// Event type, used for passing information between activities
 ///
 /// # Members
+/// * `id` - Unique id of the event, copies of an event share the same id
 /// * `src` - Source activity identifier
 /// * `dst` - Destination activity identifier
 /// * `payload` - Data which should be communicated
-#[derive(Clone, Debug)]
+/// * `ack` - Acknowledgement signalled when the event is handed to the
+/// destination activity, only set for events sent with `send_with_ack(..)`
+#[derive(Debug)]
 pub struct Event {
+    id: u64,
     src: ActivityIdentifier,
     dst: ActivityIdentifier,
     payload: Box<dyn PayloadTrait>,
+    ack: Option<EventAck>,
 }
 
 impl Event {
@@ -24,14 +36,125 @@ impl Event {
         payload: Box<dyn PayloadTrait>,
         src: ActivityIdentifier,
         dst: ActivityIdentifier,
-    ) -> Box<Event> {
-        Box::new(Event { src, dst, payload })
+    ) -> Event {
+        Event {
+            id: NEXT_EVENT_ID.fetch_add(1, Ordering::Relaxed),
+            src,
+            dst,
+            payload,
+            ack: None,
+        }
+    }
+
+    /// Create an event carrying a shared payload, copies of the event and
+    /// other events created from the same Arc share a single copy of the
+    /// data. Use this when sending the same (large) payload to many
+    /// activities.
+    ///
+    /// # Arguments
+    /// * `payload` - The shared payload
+    /// * `src` - Source activity identifier
+    /// * `dst` - Destination activity identifier
+    ///
+    /// # Returns
+    /// * `Event` - Event carrying an ArcPayload
+    pub fn shared<T: PayloadTrait>(
+        payload: Arc<T>,
+        src: ActivityIdentifier,
+        dst: ActivityIdentifier,
+    ) -> Event {
+        Event::new(Box::new(ArcPayload::new(payload)), src, dst)
+    }
+
+    /// Box the event, the queues of the constellation instance hold boxed
+    /// events. The box is taken from the object pool of the thread, if there
+    /// is one.
+    pub(crate) fn boxed(self) -> Box<Event> {
+        object_pool::boxed_event(self)
+    }
+
+    /// Event held by a box in the object pool, it carries nothing of the
+    /// event which used the box before. Does not allocate.
+    pub(crate) fn released() -> Event {
+        Event {
+            id: 0,
+            src: ActivityIdentifier::released(),
+            dst: ActivityIdentifier::released(),
+            payload: Box::new(TakenPayload),
+            ack: None,
+        }
+    }
+
+    pub fn get_id(&self) -> u64 {
+        self.id
     }
 
     pub fn get_payload(&self) -> &Box<dyn PayloadTrait> {
         &self.payload
     }
 
+    pub fn get_payload_mut(&mut self) -> &mut Box<dyn PayloadTrait> {
+        &mut self.payload
+    }
+
+    /// Consume the event and return its payload, so that the data can be
+    /// kept without cloning it
+    ///
+    /// # Returns
+    /// * `Box<dyn PayloadTrait>` - The payload
+    pub fn into_payload(self) -> Box<dyn PayloadTrait> {
+        self.payload
+    }
+
+    /// Take the payload out of the event, leaving a placeholder behind
+    ///
+    /// # Returns
+    /// * `Option<Box<dyn PayloadTrait>>` - The payload, None if it has been
+    /// taken already
+    pub fn take_payload(&mut self) -> Option<Box<dyn PayloadTrait>> {
+        if self.payload.as_any().is::<TakenPayload>() {
+            return None;
+        }
+
+        Some(std::mem::replace(&mut self.payload, Box::new(TakenPayload)))
+    }
+
+    /// Consume the event and return its payload as its concrete type, see
+    /// `into_payload()`. A shared payload (see `Event::shared(..)`) is only
+    /// returned as `ArcPayload<T>`.
+    ///
+    /// # Returns
+    /// * `Result<T, Event>` - The payload, or the unchanged event if the
+    /// payload is of another type
+    pub fn into_payload_as<T: PayloadTrait>(self) -> Result<T, Event> {
+        if !self.payload.as_any().is::<T>() {
+            return Err(self);
+        }
+
+        match self.payload.into_any().downcast::<T>() {
+            Ok(payload) => Ok(*payload),
+            Err(_) => unreachable!("Payload type checked before downcast"),
+        }
+    }
+
+    /// The payload as its concrete type, a shared payload (see
+    /// `Event::shared(..)`) is returned as the type it wraps
+    ///
+    /// # Returns
+    /// * `Option<&T>` - The payload, None if it is of another type
+    pub fn payload_as<T: PayloadTrait>(&self) -> Option<&T> {
+        let payload = self.payload.as_any();
+        payload
+            .downcast_ref::<T>()
+            .or_else(|| payload.downcast_ref::<ArcPayload<T>>().map(|p| &**p))
+    }
+
+    /// The payload as its concrete type, see `payload_as(..)`. A shared
+    /// payload can not be borrowed mutably, only as `ArcPayload<T>`
+    pub fn payload_as_mut<T: PayloadTrait>(&mut self) -> Option<&mut T> {
+        self.payload.as_any_mut().downcast_mut::<T>()
+    }
+
     pub fn get_src(&self) -> ActivityIdentifier {
         self.src.clone()
     }
@@ -39,14 +162,96 @@ impl Event {
     pub fn get_dst(&self) -> ActivityIdentifier {
         self.dst.clone()
     }
+
+    /// Change the destination, used when an interceptor redirects the event
+    pub(crate) fn set_dst(&mut self, dst: ActivityIdentifier) {
+        self.dst = dst;
+    }
+
+    /// Compact single line description of the event, used for logging
+    ///
+    /// # Returns
+    /// * `String` - Event id, source, destination and payload type
+    pub fn summary(&self) -> String {
+        format!(
+            "EID:{} {} -> {} ({})",
+            self.id,
+            self.src,
+            self.dst,
+            self.payload.type_name()
+        )
+    }
+
+    /// The correlation id of the acknowledgement, if this event was sent with
+    /// `send_with_ack(..)`
+    pub fn ack_id(&self) -> Option<u64> {
+        self.ack.as_ref().map(|ack| ack.id())
+    }
+
+    /// Attach a new acknowledgement to this event
+    ///
+    /// # Returns
+    /// * `AckHandle` - Handle signalled when the event is consumed or
+    /// dead-lettered
+    pub(crate) fn request_ack(&mut self) -> AckHandle {
+        let (ack, handle) = EventAck::new();
+        self.ack = Some(ack);
+        handle
+    }
+
+    /// Move the payload into a copy of this event, leaving a placeholder
+    /// behind. The acknowledgement stays with this event.
+    pub(crate) fn take(&mut self) -> Event {
+        Event {
+            id: self.id,
+            src: self.src.clone(),
+            dst: self.dst.clone(),
+            payload: std::mem::replace(&mut self.payload, Box::new(TakenPayload)),
+            ack: None,
+        }
+    }
+
+    /// Put the payload of an event created with `take()` back
+    pub(crate) fn restore(&mut self, taken: Event) {
+        self.payload = taken.payload;
+    }
+
+    /// Take the acknowledgement out of this event, so that it can be
+    /// signalled by the executor thread
+    pub(crate) fn take_ack(&mut self) -> Option<EventAck> {
+        self.ack.take()
+    }
+}
+
+/// Copies of an event do not carry the acknowledgement, only the original
+/// event can be consumed or dead-lettered.
+impl Clone for Event {
+    fn clone(&self) -> Event {
+        Event {
+            id: self.id,
+            src: self.src.clone(),
+            dst: self.dst.clone(),
+            payload: self.payload.clone(),
+            ack: None,
+        }
+    }
 }
 
 impl fmt::Display for Event {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "Src: {}\nDst: {}\nData: {:?}",
-            self.src, self.dst, self.payload
+            "Event: {}\nSrc: {}\nDst: {}\nType: {}\nData: {}",
+            self.id,
+            self.src,
+            self.dst,
+            self.payload.type_name(),
+            self.payload
         )
     }
 }
+
+/// Token identifying an event sent with `ConstellationTrait::send_after(..)`,
+/// used to cancel the event before it is sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DelayedEventToken(pub(crate) u64);
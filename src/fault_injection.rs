@@ -0,0 +1,356 @@
+///! Fault injection for testing, see `fault_injector` in the
+///! ConstellationConfiguration. Only available with the `fault-injection`
+///! feature.
+///!
+///! A FaultInjector holds rules, each pairing a target with a fault. Event
+///! faults are injected in the send path, after the event interceptors, and
+///! target events by the context label of their destination or by the type
+///! name of their payload:
+///! - `DropEvent` discards the event, as if it was lost
+///! - `DelayEvent` holds the event back for a while before it is routed, so
+///! it may arrive after events sent later
+///!
+///! Activity faults are injected when an executor thread picks an activity
+///! from its work queue, and target activities by their context label:
+///! - `Migrate` hands the activity back to the load balancer instead of
+///! running it, until it migrated the given number of times. Only multi
+///! threaded instances migrate activities.
+///! - `PanicOnce` panics the first activity picked up, it is retired like
+///! any activity which panics and its executor thread keeps running
+///!
+///! The first rule matching an event decides what happens to it, rules with a
+///! probability which do not fire let the next rules decide. The injector
+///! draws from a seeded pseudo random generator, but the order in which
+///! threads draw from it still differs between runs.
+///!
+///! ```ignore
+///! let mut injector = FaultInjector::new(42);
+///! injector.add_rule(FaultRule::delay_events(
+///!     FaultTarget::Context("reduce".to_string()),
+///!     0.1,
+///!     Duration::from_millis(5),
+///! ))?;
+///! injector.add_rule(FaultRule::migrate(FaultTarget::Any, 1))?;
+///! config.fault_injector = Some(Arc::new(injector));
+///! ```
+use crate::{ActivityIdentifier, ConfigError, Event};
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use hashbrown::HashSet;
+
+/// Seed used instead of 0, which the generator can not leave
+const ZERO_SEED_REPLACEMENT: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// What a rule applies to
+///
+/// * `Any` - Every event and every activity
+/// * `Context` - Events for activities with the given context label, and
+/// activities with that label
+/// * `PayloadType` - Events whose payload has the given type name, either the
+/// full path as given by `PayloadTrait::type_name()` or the name without the
+/// path. Never matches activities.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FaultTarget {
+    Any,
+    Context(String),
+    PayloadType(String),
+}
+
+/// The fault a rule injects
+///
+/// * `DropEvent` - Discard matching events with the given probability
+/// * `DelayEvent` - Hold matching events back for `delay` with the given
+/// probability, an event is delayed at most once
+/// * `Migrate` - Hand matching activities back to the load balancer when they
+/// are picked up, until they migrated `times` times
+/// * `PanicOnce` - Panic the first matching activity picked up
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    DropEvent { probability: f64 },
+    DelayEvent { probability: f64, delay: Duration },
+    Migrate { times: u32 },
+    PanicOnce,
+}
+
+/// A fault and what it applies to, see `FaultInjector::add_rule(..)`
+///
+/// # Members
+/// * `target` - The events or activities the fault is injected in
+/// * `fault` - The fault
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultRule {
+    pub target: FaultTarget,
+    pub fault: Fault,
+}
+
+impl FaultRule {
+    /// Drop the targeted events with the given probability
+    pub fn drop_events(target: FaultTarget, probability: f64) -> FaultRule {
+        FaultRule {
+            target,
+            fault: Fault::DropEvent { probability },
+        }
+    }
+
+    /// Delay the targeted events by `delay` with the given probability
+    pub fn delay_events(target: FaultTarget, probability: f64, delay: Duration) -> FaultRule {
+        FaultRule {
+            target,
+            fault: Fault::DelayEvent { probability, delay },
+        }
+    }
+
+    /// Migrate the targeted activities `times` times before they run
+    pub fn migrate(target: FaultTarget, times: u32) -> FaultRule {
+        FaultRule {
+            target,
+            fault: Fault::Migrate { times },
+        }
+    }
+
+    /// Panic the first targeted activity picked up
+    pub fn panic_once(target: FaultTarget) -> FaultRule {
+        FaultRule {
+            target,
+            fault: Fault::PanicOnce,
+        }
+    }
+
+    /// Whether the rule applies to an event with the given destination label
+    /// and payload type name
+    fn matches_event(&self, label: Option<&str>, type_name: &str) -> bool {
+        match &self.target {
+            FaultTarget::Any => true,
+            FaultTarget::Context(target) => label == Some(target.as_str()),
+            FaultTarget::PayloadType(target) => {
+                type_name == target
+                    || (type_name.ends_with(target.as_str())
+                        && type_name[..type_name.len() - target.len()].ends_with("::"))
+            }
+        }
+    }
+
+    /// Whether the rule applies to an activity with the given context label
+    fn matches_activity(&self, label: &str) -> bool {
+        match &self.target {
+            FaultTarget::Any => true,
+            FaultTarget::Context(target) => label == target,
+            FaultTarget::PayloadType(_) => false,
+        }
+    }
+}
+
+/// What to do with an event, decided by `FaultInjector::inject_event(..)`
+///
+/// * `Deliver` - Route the event as usual
+/// * `Drop` - Discard the event
+/// * `Delay` - Send the event after the given delay
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum EventFault {
+    Deliver,
+    Drop,
+    Delay(Duration),
+}
+
+/// What to do with an activity picked up by an executor thread, decided by
+/// `FaultInjector::inject_pickup(..)`
+///
+/// * `Run` - Run the activity as usual
+/// * `Migrate` - Hand the activity back to the load balancer
+/// * `Panic` - Panic the executor thread
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PickupFault {
+    Run,
+    Migrate,
+    Panic,
+}
+
+/// Number of faults injected so far, see `FaultInjector::counts()`
+///
+/// # Members
+/// * `dropped_events` - Events dropped
+/// * `delayed_events` - Events delayed
+/// * `migrations` - Activities handed back to the load balancer
+/// * `panics` - Activities panicked
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FaultCounts {
+    pub dropped_events: usize,
+    pub delayed_events: usize,
+    pub migrations: usize,
+    pub panics: usize,
+}
+
+/// Injects faults in the events and activities of a constellation instance,
+/// shared by all its threads
+///
+/// # Members
+/// * `rules` - The rules, in the order they were added, each with whether it
+/// fired already, only used by PanicOnce
+/// * `rng` - State of the xorshift generator deciding whether faults with a
+/// probability fire
+/// * `delayed` - Events delayed and not routed yet, by id and destination,
+/// these are delivered without injecting faults again
+/// * `dropped_events`, `delayed_events`, `migrations`, `panics` - Number of
+/// faults injected, see FaultCounts
+pub struct FaultInjector {
+    rules: Vec<(FaultRule, AtomicBool)>,
+    rng: Mutex<u64>,
+    delayed: Mutex<HashSet<(u64, ActivityIdentifier)>>,
+    dropped_events: AtomicUsize,
+    delayed_events: AtomicUsize,
+    migrations: AtomicUsize,
+    panics: AtomicUsize,
+}
+
+impl FaultInjector {
+    /// Create an injector without rules
+    ///
+    /// # Arguments
+    /// * `seed` - Seed of the generator deciding whether faults with a
+    /// probability fire
+    pub fn new(seed: u64) -> FaultInjector {
+        FaultInjector {
+            rules: Vec::new(),
+            rng: Mutex::new(if seed == 0 {
+                ZERO_SEED_REPLACEMENT
+            } else {
+                seed
+            }),
+            delayed: Mutex::new(HashSet::new()),
+            dropped_events: AtomicUsize::new(0),
+            delayed_events: AtomicUsize::new(0),
+            migrations: AtomicUsize::new(0),
+            panics: AtomicUsize::new(0),
+        }
+    }
+
+    /// Add a rule, after the rules added before
+    ///
+    /// # Arguments
+    /// * `rule` - The rule
+    ///
+    /// # Returns
+    /// * `Result<(), ConfigError>` - ConfigError::InvalidValue if the
+    /// probability of the fault is not between 0 and 1
+    pub fn add_rule(&mut self, rule: FaultRule) -> Result<(), ConfigError> {
+        let probability = match &rule.fault {
+            Fault::DropEvent { probability } | Fault::DelayEvent { probability, .. } => {
+                *probability
+            }
+            Fault::Migrate { .. } | Fault::PanicOnce => 0.0,
+        };
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(ConfigError::InvalidValue {
+                key: "fault_injector.probability".to_string(),
+                value: probability.to_string(),
+                reason: "must be between 0 and 1".to_string(),
+            });
+        }
+
+        self.rules.push((rule, AtomicBool::new(false)));
+        Ok(())
+    }
+
+    /// The rules, in the order they were added
+    pub fn rules(&self) -> Vec<FaultRule> {
+        self.rules.iter().map(|(rule, _)| rule.clone()).collect()
+    }
+
+    /// Number of faults injected so far
+    pub fn counts(&self) -> FaultCounts {
+        FaultCounts {
+            dropped_events: self.dropped_events.load(Ordering::Relaxed),
+            delayed_events: self.delayed_events.load(Ordering::Relaxed),
+            migrations: self.migrations.load(Ordering::Relaxed),
+            panics: self.panics.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Decide what to do with an event which is about to be routed. An event
+    /// delayed before is delivered.
+    ///
+    /// # Arguments
+    /// * `e` - The event
+    /// * `label` - Context label of the destination, None if it is not a live
+    /// activity of this instance
+    ///
+    /// # Returns
+    /// * `EventFault` - Whether to deliver, drop or delay the event
+    pub(crate) fn inject_event(&self, e: &Event, label: Option<&str>) -> EventFault {
+        let key = (e.get_id(), e.get_dst());
+        if self.delayed.lock().unwrap().remove(&key) {
+            return EventFault::Deliver;
+        }
+
+        let type_name = e.get_payload().type_name();
+        for (rule, _) in self.rules.iter() {
+            if !rule.matches_event(label, type_name) {
+                continue;
+            }
+
+            match &rule.fault {
+                Fault::DropEvent { probability } if self.fires(*probability) => {
+                    self.dropped_events.fetch_add(1, Ordering::Relaxed);
+                    return EventFault::Drop;
+                }
+                Fault::DelayEvent { probability, delay } if self.fires(*probability) => {
+                    self.delayed.lock().unwrap().insert(key);
+                    self.delayed_events.fetch_add(1, Ordering::Relaxed);
+                    return EventFault::Delay(*delay);
+                }
+                _ => {}
+            }
+        }
+
+        EventFault::Deliver
+    }
+
+    /// Decide what to do with an activity an executor thread picked up
+    ///
+    /// # Arguments
+    /// * `label` - Context label of the activity
+    /// * `migrations` - Number of times the activity migrated before, None
+    /// if it can not migrate because the instance is single threaded
+    ///
+    /// # Returns
+    /// * `PickupFault` - Whether to run, migrate or panic the activity
+    pub(crate) fn inject_pickup(&self, label: &str, migrations: Option<u32>) -> PickupFault {
+        for (rule, fired) in self.rules.iter() {
+            if !rule.matches_activity(label) {
+                continue;
+            }
+
+            match &rule.fault {
+                Fault::Migrate { times } if migrations.map_or(false, |m| m < *times) => {
+                    self.migrations.fetch_add(1, Ordering::Relaxed);
+                    return PickupFault::Migrate;
+                }
+                Fault::PanicOnce if !fired.swap(true, Ordering::SeqCst) => {
+                    self.panics.fetch_add(1, Ordering::Relaxed);
+                    return PickupFault::Panic;
+                }
+                _ => {}
+            }
+        }
+
+        PickupFault::Run
+    }
+
+    /// Draw whether a fault with the given probability fires
+    fn fires(&self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+
+        let mut state = self.rng.lock().unwrap();
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+
+        // The upper 53 bits give a uniform value in [0, 1)
+        ((*state >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
@@ -0,0 +1,297 @@
+///! Groups of activities, submitted together with
+///! `ConstellationTrait::submit_group(..)`. The returned GroupHandle can be
+///! used to wait until all activities in the group have finished, or to
+///! cancel all of them.
+///!
+///! A group is backed by a scope, so activities submitted from within the
+///! activities of a group are cancelled together with the group as well (but
+///! they are not waited for).
+use crate::activity::{CompletionReason, State};
+use crate::implementation::scope_registry::ScopeRegistry;
+use crate::{
+    ActivityError, ActivityIdentifier, ActivityTrait, ConstellationError, ConstellationHandle,
+    Context, Event, ScopeId, SubmitOptions,
+};
+
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Handle to a group of submitted activities
+///
+/// # Members
+/// * `scope` - The scope all activities in the group were submitted in
+/// * `state` - Completion state, shared with the activities in the group
+/// * `scopes` - Registry of scopes of the constellation instance, used to
+/// cancel the group
+#[derive(Clone)]
+pub struct GroupHandle {
+    scope: ScopeId,
+    state: Arc<GroupState>,
+    scopes: Arc<Mutex<ScopeRegistry>>,
+}
+
+impl GroupHandle {
+    pub(crate) fn new(
+        scope: ScopeId,
+        state: Arc<GroupState>,
+        scopes: Arc<Mutex<ScopeRegistry>>,
+    ) -> GroupHandle {
+        GroupHandle {
+            scope,
+            state,
+            scopes,
+        }
+    }
+
+    /// Block until all activities in the group have finished.
+    ///
+    /// # Arguments
+    /// * `timeout` - Maximum time to wait
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - Ok when all activities finished,
+    /// ConstellationError when the timeout expired, the group was cancelled
+    /// or one of its activities failed
+    pub fn wait(&self, timeout: Duration) -> Result<(), ConstellationError> {
+        let deadline = Instant::now() + timeout;
+        let mut guard = self.state.progress.lock().unwrap();
+
+        loop {
+            if guard.cancelled {
+                warn!("Waiting for group in cancelled scope {}", self.scope);
+                return Err(ConstellationError::Failed);
+            }
+            if guard.failed {
+                warn!("An activity of the group in scope {} failed", self.scope);
+                return Err(ConstellationError::Failed);
+            }
+            if guard.remaining == 0 {
+                return Ok(());
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                warn!(
+                    "Timeout waiting for group in scope {}, {} activities remaining",
+                    self.scope, guard.remaining
+                );
+                return Err(ConstellationError::Failed);
+            }
+
+            guard = self
+                .state
+                .finished
+                .wait_timeout(guard, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Cancel all activities in the group which have not finished yet, see
+    /// `ConstellationTrait::cancel_scope(..)`. Threads waiting on the group
+    /// are woken up and return an error.
+    pub fn cancel(&self) {
+        self.state.progress.lock().unwrap().cancelled = true;
+        self.state.finished.notify_all();
+
+        self.scopes.lock().unwrap().cancel(self.scope);
+    }
+
+    /// Number of activities in the group which have not finished yet
+    pub fn remaining(&self) -> usize {
+        self.state.progress.lock().unwrap().remaining
+    }
+
+    /// The scope the activities in this group were submitted in
+    pub fn scope(&self) -> ScopeId {
+        self.scope
+    }
+}
+
+/// Completion state of a group, shared between the GroupHandle and all
+/// activities in the group.
+pub(crate) struct GroupState {
+    progress: Mutex<GroupProgress>,
+    finished: Condvar,
+}
+
+struct GroupProgress {
+    remaining: usize,
+    cancelled: bool,
+    failed: bool,
+}
+
+impl GroupState {
+    pub(crate) fn new(size: usize) -> Arc<GroupState> {
+        Arc::new(GroupState {
+            progress: Mutex::new(GroupProgress {
+                remaining: size,
+                cancelled: false,
+                failed: false,
+            }),
+            finished: Condvar::new(),
+        })
+    }
+
+    fn member_finished(&self) {
+        let mut guard = self.progress.lock().unwrap();
+        guard.remaining -= 1;
+
+        if guard.remaining == 0 {
+            self.finished.notify_all();
+        }
+    }
+
+    fn member_failed(&self) {
+        self.progress.lock().unwrap().failed = true;
+        self.finished.notify_all();
+    }
+
+    fn member_cancelled(&self) {
+        self.progress.lock().unwrap().cancelled = true;
+        self.finished.notify_all();
+    }
+}
+
+/// Wraps an activity submitted in a group, forwarding all calls and marking
+/// the activity as finished in the group after its cleanup. A cancelled
+/// activity marks the group as cancelled, a failed activity as failed.
+pub(crate) struct GroupMember {
+    activity: Arc<Mutex<dyn ActivityTrait>>,
+    state: Arc<GroupState>,
+}
+
+impl GroupMember {
+    pub(crate) fn new(
+        activity: Arc<Mutex<dyn ActivityTrait>>,
+        state: Arc<GroupState>,
+    ) -> Arc<Mutex<GroupMember>> {
+        Arc::new(Mutex::new(GroupMember { activity, state }))
+    }
+}
+
+impl ActivityTrait for GroupMember {
+    fn cleanup(&mut self, constellation: &ConstellationHandle) {
+        self.activity.lock().unwrap().cleanup(constellation);
+        self.state.member_finished();
+    }
+
+    fn cancelled(&mut self, constellation: &ConstellationHandle) {
+        self.activity.lock().unwrap().cancelled(constellation);
+        self.state.member_cancelled();
+    }
+
+    fn on_error(
+        &mut self,
+        constellation: &ConstellationHandle,
+        error: &ActivityError,
+        id: &ActivityIdentifier,
+    ) {
+        // The activity is poisoned when it panicked, it is still told
+        self.activity
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .on_error(constellation, error, id);
+    }
+
+    fn on_complete(&mut self, reason: CompletionReason) {
+        let failed = match reason {
+            CompletionReason::Failed(_) => true,
+            _ => false,
+        };
+
+        // The activity is poisoned when it failed, it is still told
+        self.activity
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .on_complete(reason);
+
+        if failed {
+            self.state.member_failed();
+        }
+    }
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        self.activity.lock().unwrap().initialize(constellation, id)
+    }
+
+    fn process(
+        &mut self,
+        constellation: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        id: &ActivityIdentifier,
+    ) -> State {
+        self.activity
+            .lock()
+            .unwrap()
+            .process(constellation, event, id)
+    }
+
+    fn batch_events(&self) -> bool {
+        self.activity.lock().unwrap().batch_events()
+    }
+
+    fn process_batch(
+        &mut self,
+        constellation: &ConstellationHandle,
+        events: Vec<Box<Event>>,
+        id: &ActivityIdentifier,
+    ) -> State {
+        self.activity
+            .lock()
+            .unwrap()
+            .process_batch(constellation, events, id)
+    }
+
+    fn size_hint(&self) -> usize {
+        self.activity.lock().unwrap().size_hint()
+    }
+}
+
+/// Submit all activities in the given new scope, wrapped so that their
+/// completion is tracked. Used by the constellation implementations of
+/// `submit_group(..)`. If one of the activities can not be submitted, the
+/// activities submitted before it are cancelled.
+///
+/// # Arguments
+/// * `scope` - The scope created for the group
+/// * `scopes` - Registry of scopes the scope was created in
+/// * `activities` - The activities in the group
+/// * `context` - The context of the activities
+/// * `options` - SubmitOptions for the activities, the scope is overwritten
+/// * `submit` - Function submitting a single activity
+pub(crate) fn submit_group<F>(
+    scope: ScopeId,
+    scopes: Arc<Mutex<ScopeRegistry>>,
+    activities: Vec<Arc<Mutex<dyn ActivityTrait>>>,
+    context: &Context,
+    options: SubmitOptions,
+    mut submit: F,
+) -> Result<GroupHandle, ConstellationError>
+where
+    F: FnMut(
+        Arc<Mutex<dyn ActivityTrait>>,
+        &Context,
+        SubmitOptions,
+    ) -> Result<ActivityIdentifier, ConstellationError>,
+{
+    let state = GroupState::new(activities.len());
+
+    for activity in activities {
+        let options = SubmitOptions {
+            scope: Some(scope),
+            ..options.clone()
+        };
+
+        if let Err(e) = submit(GroupMember::new(activity, state.clone()), context, options) {
+            scopes.lock().unwrap().cancel(scope);
+            return Err(e);
+        }
+    }
+
+    Ok(GroupHandle::new(scope, state, scopes))
+}
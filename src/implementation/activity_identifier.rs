@@ -1,7 +1,9 @@
 use crate::implementation::communication::node_handler::NodeHandler;
 use crate::implementation::constellation_identifier::ConstellationIdentifier;
 
+use std::cmp::Ordering;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -13,14 +15,15 @@ use std::sync::Mutex;
 /// identifier and cause faulty executions.
 ///
 /// Two ActivityIdentifiers can be compared to each other and displayed on
-/// the screen.
+/// the screen. Equality, ordering and hashing only consider the constellation
+/// id, node id and activity id, the node name is not part of the identity.
 ///
 /// # Members
 /// * `constellation_id` - Constellation identifier
 /// * `node_info` - Information about the node, such as node id, node name,
 /// and number of threads.
 /// * `activity_id` - A u64 number, unique for this ActivityIdentifier
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone)]
 pub struct ActivityIdentifier {
     pub constellation_id: i32,
     pub node_info: NodeHandler,
@@ -60,6 +63,44 @@ impl ActivityIdentifier {
             activity_id: const_id.generate_activity_id(),
         }
     }
+
+    /// Generate a new identifier in place, for a wrapper taken from the
+    /// object pool. The buffer of the node name is reused.
+    ///
+    /// # Arguments
+    /// * `const_id` - Arc reference to the constellation identifier.
+    pub(crate) fn renew(&mut self, const_id: &Arc<Mutex<ConstellationIdentifier>>) {
+        let mut const_id = const_id.lock().unwrap();
+
+        self.constellation_id = const_id.constellation_id;
+        self.node_info
+            .node_name
+            .clone_from(&const_id.node_info.node_name);
+        self.node_info.node_id = const_id.node_info.node_id;
+        self.activity_id = const_id.generate_activity_id();
+    }
+
+    /// Forget the identifier, for a wrapper returned to the object pool. Only
+    /// the buffer of the node name is kept, it is cleared.
+    pub(crate) fn release(&mut self) {
+        self.constellation_id = -1;
+        self.node_info.node_name.clear();
+        self.node_info.node_id = 0;
+        self.activity_id = u64::MAX;
+    }
+
+    /// Identifier of no activity, held by events in the object pool. Does
+    /// not allocate.
+    pub(crate) fn released() -> ActivityIdentifier {
+        ActivityIdentifier {
+            constellation_id: -1,
+            node_info: NodeHandler {
+                node_name: String::new(),
+                node_id: 0,
+            },
+            activity_id: u64::MAX,
+        }
+    }
 }
 
 impl PartialEq for ActivityIdentifier {
@@ -71,3 +112,38 @@ impl PartialEq for ActivityIdentifier {
 }
 
 impl Eq for ActivityIdentifier {}
+
+/// Orders by constellation id, then node id, then activity id. Identifiers
+/// generated on the same node are ordered by creation.
+impl Ord for ActivityIdentifier {
+    fn cmp(&self, other: &ActivityIdentifier) -> Ordering {
+        (
+            self.constellation_id,
+            self.node_info.node_id,
+            self.activity_id,
+        )
+            .cmp(&(
+                other.constellation_id,
+                other.node_info.node_id,
+                other.activity_id,
+            ))
+    }
+}
+
+impl PartialOrd for ActivityIdentifier {
+    fn partial_cmp(&self, other: &ActivityIdentifier) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Must hash exactly the fields compared by `eq`, otherwise equal identifiers
+/// could end up in different buckets of the work and event queues. Skipping
+/// the node name also avoids hashing a String on every queue lookup, which
+/// happens for every activity and event that is routed.
+impl Hash for ActivityIdentifier {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.constellation_id.hash(state);
+        self.node_info.node_id.hash(state);
+        self.activity_id.hash(state);
+    }
+}
@@ -0,0 +1,61 @@
+///! Registry of the names of activities submitted with
+///! `ConstellationTrait::submit_named(..)`, shared between the executor
+///! threads and the load balancer of a constellation instance so that
+///! `lookup(..)` works from the application and from inside activities.
+///!
+///! A name is registered when the activity is submitted, so it can be looked
+///! up before the activity is placed on a thread. It is released when the
+///! activity finishes or is removed because its scope was cancelled, after
+///! which it can be used again.
+use crate::ActivityIdentifier;
+
+use hashbrown::HashMap;
+
+/// ActivityNames struct
+///
+/// # Members
+/// * `names` - The identifier of every registered name
+/// * `ids` - The name of every named activity, used to release it
+pub struct ActivityNames {
+    names: HashMap<String, ActivityIdentifier>,
+    ids: HashMap<ActivityIdentifier, String>,
+}
+
+impl ActivityNames {
+    pub fn new() -> ActivityNames {
+        ActivityNames {
+            names: HashMap::new(),
+            ids: HashMap::new(),
+        }
+    }
+
+    /// Whether the name belongs to a live activity
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains_key(name)
+    }
+
+    /// Register the name of a submitted activity, the caller checks that the
+    /// name is free with `contains(..)` first
+    pub fn register(&mut self, name: &str, aid: ActivityIdentifier) {
+        self.names.insert(name.to_string(), aid.clone());
+        self.ids.insert(aid, name.to_string());
+    }
+
+    /// The identifier of the activity with the given name
+    pub fn lookup(&self, name: &str) -> Option<ActivityIdentifier> {
+        self.names.get(name).cloned()
+    }
+
+    /// Whether the activity was submitted with a name
+    pub fn is_named(&self, aid: &ActivityIdentifier) -> bool {
+        self.ids.contains_key(aid)
+    }
+
+    /// Release the name of an activity which finished or was cancelled, does
+    /// nothing for activities without a name
+    pub fn release(&mut self, aid: &ActivityIdentifier) {
+        if let Some(name) = self.ids.remove(aid) {
+            self.names.remove(&name);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+///! Queue of activities of an executor thread, used for both the activities
+///! waiting to run and the suspended activities. Activities are looked up by
+///! their identifier like in a HashMap, but iterated in the order they were
+///! inserted, so the executor can pick the oldest activity amongst equals and
+///! an activity can not be starved by a stream of newer submissions.
+///!
+///! Removing an activity leaves its entry in the insertion order behind, it is
+///! skipped when iterating and cleaned up once stale entries outnumber the
+///! activities, which keeps both insertion and removal O(1) amortized.
+use crate::implementation::activity_wrapper::ActivityWrapperTrait;
+use crate::ActivityIdentifier;
+
+use std::collections::VecDeque;
+
+use hashbrown::HashMap;
+
+/// Insertion ordered map of activities
+///
+/// # Members
+/// * `activities` - The activities, with the sequence number of their entry
+/// in `order`
+/// * `order` - Sequence numbers and identifiers in insertion order, an entry
+/// is stale when its sequence number does not match the activity
+/// * `next` - Sequence number of the next insertion
+pub struct ActivityQueue {
+    activities: HashMap<ActivityIdentifier, (u64, Box<dyn ActivityWrapperTrait>)>,
+    order: VecDeque<(u64, ActivityIdentifier)>,
+    next: u64,
+}
+
+impl ActivityQueue {
+    pub fn new() -> ActivityQueue {
+        ActivityQueue {
+            activities: HashMap::new(),
+            order: VecDeque::new(),
+            next: 0,
+        }
+    }
+
+    /// Insert an activity at the back of the queue. An activity with the
+    /// same identifier is replaced and returned, the new one goes to the back.
+    pub fn insert(
+        &mut self,
+        key: ActivityIdentifier,
+        activity: Box<dyn ActivityWrapperTrait>,
+    ) -> Option<Box<dyn ActivityWrapperTrait>> {
+        let seq = self.next;
+        self.next += 1;
+        self.order.push_back((seq, key.clone()));
+
+        let old = self.activities.insert(key, (seq, activity)).map(|(_, a)| a);
+        if old.is_some() {
+            self.compact();
+        }
+        old
+    }
+
+    pub fn remove(&mut self, key: &ActivityIdentifier) -> Option<Box<dyn ActivityWrapperTrait>> {
+        let activity = self.activities.remove(key).map(|(_, a)| a);
+        if activity.is_some() {
+            self.compact();
+        }
+        activity
+    }
+
+    pub fn contains_key(&self, key: &ActivityIdentifier) -> bool {
+        self.activities.contains_key(key)
+    }
+
+    /// Iterate over the activities in insertion order
+    pub fn iter(
+        &self,
+    ) -> impl Iterator<Item = (&ActivityIdentifier, &Box<dyn ActivityWrapperTrait>)> {
+        self.order
+            .iter()
+            .filter_map(move |(seq, key)| match self.activities.get(key) {
+                Some((s, activity)) if s == seq => Some((key, activity)),
+                _ => None,
+            })
+    }
+
+    /// Iterate over the identifiers in insertion order
+    pub fn keys(&self) -> impl Iterator<Item = &ActivityIdentifier> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// Iterate over the activities in insertion order
+    pub fn values(&self) -> impl Iterator<Item = &Box<dyn ActivityWrapperTrait>> {
+        self.iter().map(|(_, a)| a)
+    }
+
+    /// Remove all activities and return them in insertion order
+    pub fn drain(
+        &mut self,
+    ) -> impl Iterator<Item = (ActivityIdentifier, Box<dyn ActivityWrapperTrait>)> {
+        let mut activities = std::mem::replace(&mut self.activities, HashMap::new());
+        let order = std::mem::replace(&mut self.order, VecDeque::new());
+
+        order
+            .into_iter()
+            .filter_map(move |(seq, key)| match activities.remove(&key) {
+                Some((s, activity)) if s == seq => Some((key, activity)),
+                Some(entry) => {
+                    activities.insert(key, entry);
+                    None
+                }
+                None => None,
+            })
+    }
+
+    pub fn clear(&mut self) {
+        self.activities.clear();
+        self.order.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.activities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.activities.is_empty()
+    }
+
+    /// Drop the stale entries of `order` once they outnumber the activities
+    fn compact(&mut self) {
+        if self.order.len() <= 2 * self.activities.len() + 16 {
+            return;
+        }
+
+        let activities = &self.activities;
+        self.order
+            .retain(|(seq, key)| activities.get(key).map_or(false, |(s, _)| s == seq));
+    }
+}
@@ -0,0 +1,431 @@
+///! Registry of activity names across the nodes of a constellation instance,
+///! so that a node can find an activity submitted with `submit_named(..)` on
+///! another node. The master holds the authoritative table, which is the
+///! ActivityNames registry of its own activities, and every node runs a
+///! registry thread which exchanges small control messages with it:
+///!
+///! * Other nodes send lookups, registrations of their own identifiers and
+///! releases to the master with the REGISTRY_TAG.
+///! * The master answers on the REGISTRY_REPLY_TAG, and sends invalidations
+///! for names it handed out once they are released or taken by another
+///! activity.
+///!
+///! Other nodes cache the names they resolved until the master invalidates
+///! them, so repeated lookups do not leave the node. A lookup which is not
+///! cached waits for the answer of the master for at most the given timeout.
+///! Activities look up names on the node they run on: on the master a
+///! lookup is a local table read, an activity stolen by another node may
+///! block its executor thread until the master answers.
+use crate::implementation::activity_names::ActivityNames;
+use crate::implementation::communication::comm::{Communication, REGISTRY_REPLY_TAG, REGISTRY_TAG};
+use crate::implementation::communication::wire::{
+    put_aid, put_str, put_u64, take_aid, take_str, take_u64,
+};
+use crate::implementation::panic_hook;
+use crate::{ActivityIdentifier, ConstellationError};
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crossbeam::{bounded, unbounded, Receiver, RecvTimeoutError, Sender};
+
+/// Time between two checks for registry messages
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Control message exchanged between the registry threads
+///
+/// * `Lookup` - Ask the master for the identifier with the given name
+/// * `Register` - Ask the master to register a name for an identifier of the
+/// sending node
+/// * `Release` - Release the name of an identifier of the sending node
+/// * `Resolved` - Answer to a lookup, None if the name is not registered
+/// * `Registered` - Answer to a registration, false if the name was taken
+/// * `Invalidate` - A name handed out earlier was released or taken by
+/// another activity
+#[derive(Debug, Clone, PartialEq)]
+enum Message {
+    Lookup(u64, String),
+    Register(u64, String, ActivityIdentifier),
+    Release(ActivityIdentifier),
+    Resolved(u64, Option<ActivityIdentifier>),
+    Registered(u64, bool),
+    Invalidate(String),
+}
+
+/// Answer of the master to a request, handed to the waiting thread
+enum Reply {
+    Resolved(Option<ActivityIdentifier>),
+    Registered(bool),
+}
+
+/// Name registry of one node
+///
+/// # Members
+/// * `comm` - Communication with the other nodes
+/// * `master_rank` - Rank of the master, which holds the table
+/// * `names` - The authoritative table, only used on the master
+/// * `cache` - Names resolved by the master, only used on the other nodes
+/// * `pending` - Requests sent to the master which were not answered yet,
+/// with the name they are for
+/// * `next_request` - Number of the next request
+/// * `handed_out` - Names resolved for the other nodes, with the identifier
+/// and the nodes which cached it, only used on the master
+pub(crate) struct ActivityRegistry {
+    comm: Arc<dyn Communication>,
+    master_rank: i32,
+    names: Arc<Mutex<ActivityNames>>,
+    cache: Mutex<HashMap<String, ActivityIdentifier>>,
+    pending: Mutex<HashMap<u64, (String, Sender<Reply>)>>,
+    next_request: AtomicU64,
+    handed_out: Mutex<HashMap<String, (ActivityIdentifier, HashSet<i32>)>>,
+}
+
+impl ActivityRegistry {
+    pub fn new(
+        comm: Arc<dyn Communication>,
+        master_rank: i32,
+        names: Arc<Mutex<ActivityNames>>,
+    ) -> ActivityRegistry {
+        ActivityRegistry {
+            comm,
+            master_rank,
+            names,
+            cache: Mutex::new(HashMap::new()),
+            pending: Mutex::new(HashMap::new()),
+            next_request: AtomicU64::new(0),
+            handed_out: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Look up the activity with the given name, on any node
+    ///
+    /// # Arguments
+    /// * `name` - Name of the activity
+    /// * `timeout` - Maximum time to wait for the master, not used on the
+    /// master or for cached names
+    ///
+    /// # Returns
+    /// * `Option<ActivityIdentifier>` - Identifier of the activity, the rank
+    /// of its node is `node_info.node_id`. None if the name is not registered
+    /// or the master did not answer in time.
+    pub fn lookup(&self, name: &str, timeout: Duration) -> Option<ActivityIdentifier> {
+        if self.is_master() {
+            return self.names.lock().unwrap().lookup(name);
+        }
+
+        if let Some(aid) = self.cache.lock().unwrap().get(name) {
+            return Some(aid.clone());
+        }
+
+        match self.request(name, timeout, |request| {
+            Message::Lookup(request, name.to_string())
+        }) {
+            Some(Reply::Resolved(aid)) => aid,
+            _ => None,
+        }
+    }
+
+    /// Register a name for an identifier of this node, e.g. one created with
+    /// `allocate_external_id()`
+    ///
+    /// # Arguments
+    /// * `name` - The name
+    /// * `aid` - The identifier
+    /// * `timeout` - Maximum time to wait for the master
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - ConstellationError::DuplicateName
+    /// if the name is taken, Failed if the master did not answer in time
+    pub fn register(
+        &self,
+        name: &str,
+        aid: &ActivityIdentifier,
+        timeout: Duration,
+    ) -> Result<(), ConstellationError> {
+        if self.is_master() {
+            let mut names = self.names.lock().unwrap();
+            if names.contains(name) {
+                return Err(ConstellationError::DuplicateName(name.to_string()));
+            }
+            names.register(name, aid.clone());
+            return Ok(());
+        }
+
+        match self.request(name, timeout, |request| {
+            Message::Register(request, name.to_string(), aid.clone())
+        }) {
+            Some(Reply::Registered(true)) => Ok(()),
+            Some(Reply::Registered(false)) => {
+                Err(ConstellationError::DuplicateName(name.to_string()))
+            }
+            _ => {
+                warn!("Master did not answer the registration of {}", name);
+                Err(ConstellationError::Failed)
+            }
+        }
+    }
+
+    /// Release the name of an identifier of this node, does nothing if it
+    /// has no name
+    pub fn release(&self, aid: &ActivityIdentifier) {
+        if self.is_master() {
+            self.names.lock().unwrap().release(aid);
+        } else {
+            self.cache.lock().unwrap().retain(|_, cached| cached != aid);
+            self.comm.send(
+                self.master_rank,
+                REGISTRY_TAG,
+                &Message::Release(aid.clone()).encode(),
+            );
+        }
+    }
+
+    fn is_master(&self) -> bool {
+        self.comm.is_master(self.master_rank)
+    }
+
+    /// Send a request to the master and wait for the answer
+    fn request<F>(&self, name: &str, timeout: Duration, message: F) -> Option<Reply>
+    where
+        F: FnOnce(u64) -> Message,
+    {
+        let request = self.next_request.fetch_add(1, Ordering::SeqCst);
+        let (sender, receiver) = bounded(1);
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(request, (name.to_string(), sender));
+
+        self.comm
+            .send(self.master_rank, REGISTRY_TAG, &message(request).encode());
+
+        match receiver.recv_timeout(timeout) {
+            Ok(reply) => Some(reply),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&request);
+                None
+            }
+        }
+    }
+
+    /// Handle the messages which arrived, called by the registry thread
+    fn poll(&self) {
+        if self.is_master() {
+            while let Some((source, bytes)) = self.comm.try_receive(REGISTRY_TAG) {
+                match Message::decode(&bytes) {
+                    Some(message) => self.handle_request(source, message),
+                    None => warn!("Dropping malformed registry message from {}", source),
+                }
+            }
+            self.invalidate_changed();
+        } else {
+            while let Some((source, bytes)) = self.comm.try_receive(REGISTRY_REPLY_TAG) {
+                match Message::decode(&bytes) {
+                    Some(message) => self.handle_reply(message),
+                    None => warn!("Dropping malformed registry message from {}", source),
+                }
+            }
+        }
+    }
+
+    /// Handle a request of another node, on the master
+    fn handle_request(&self, source: i32, message: Message) {
+        let reply = match message {
+            Message::Lookup(request, name) => {
+                let aid = self.names.lock().unwrap().lookup(&name);
+                if let Some(aid) = &aid {
+                    self.handed_out
+                        .lock()
+                        .unwrap()
+                        .entry(name)
+                        .or_insert_with(|| (aid.clone(), HashSet::new()))
+                        .1
+                        .insert(source);
+                }
+                Message::Resolved(request, aid)
+            }
+            Message::Register(request, name, aid) => {
+                let mut names = self.names.lock().unwrap();
+                let free = !names.contains(&name);
+                if free {
+                    names.register(&name, aid);
+                }
+                Message::Registered(request, free)
+            }
+            Message::Release(aid) => {
+                self.names.lock().unwrap().release(&aid);
+                return;
+            }
+            other => {
+                warn!("Unexpected registry message from {}: {:?}", source, other);
+                return;
+            }
+        };
+
+        self.comm.send(source, REGISTRY_REPLY_TAG, &reply.encode());
+    }
+
+    /// Handle an answer or invalidation of the master, on the other nodes.
+    /// Resolved names are cached here instead of by the waiting thread, so an
+    /// invalidation sent after the answer always removes them.
+    fn handle_reply(&self, message: Message) {
+        let (request, reply) = match message {
+            Message::Resolved(request, aid) => (request, Reply::Resolved(aid)),
+            Message::Registered(request, ok) => (request, Reply::Registered(ok)),
+            Message::Invalidate(name) => {
+                self.cache.lock().unwrap().remove(&name);
+                return;
+            }
+            other => {
+                warn!("Unexpected registry message from the master: {:?}", other);
+                return;
+            }
+        };
+
+        let (name, sender) = match self.pending.lock().unwrap().remove(&request) {
+            Some(pending) => pending,
+            // The request timed out
+            None => return,
+        };
+
+        if let Reply::Resolved(Some(aid)) = &reply {
+            self.cache.lock().unwrap().insert(name, aid.clone());
+        }
+        let _ = sender.send(reply);
+    }
+
+    /// Send invalidations for the names handed out which no longer belong to
+    /// the same identifier, on the master
+    fn invalidate_changed(&self) {
+        let mut handed_out = self.handed_out.lock().unwrap();
+        if handed_out.is_empty() {
+            return;
+        }
+
+        let names = self.names.lock().unwrap();
+        let changed: Vec<String> = handed_out
+            .iter()
+            .filter(|(name, (aid, _))| names.lookup(name).as_ref() != Some(aid))
+            .map(|(name, _)| name.clone())
+            .collect();
+        drop(names);
+
+        for name in changed {
+            let (_, ranks) = handed_out.remove(&name).unwrap();
+            let message = Message::Invalidate(name).encode();
+            for rank in ranks {
+                self.comm.send(rank, REGISTRY_REPLY_TAG, &message);
+            }
+        }
+    }
+}
+
+/// Start the registry thread of a node
+///
+/// # Arguments
+/// * `registry` - The registry of this node
+///
+/// # Returns
+/// * `io::Result<Sender<()>>` - Dropping the sender stops the thread, error
+/// if the thread could not be spawned
+pub(crate) fn start(registry: Arc<ActivityRegistry>) -> io::Result<Sender<()>> {
+    let (stop, stopped): (Sender<()>, Receiver<()>) = unbounded();
+
+    thread::Builder::new()
+        .name(panic_hook::registry_thread_name())
+        .spawn(move || loop {
+            registry.poll();
+
+            match stopped.recv_timeout(POLL_INTERVAL) {
+                Err(RecvTimeoutError::Timeout) => continue,
+                _ => return,
+            }
+        })?;
+
+    Ok(stop)
+}
+
+impl Message {
+    /// Encode the message, integers are big endian and strings are prefixed
+    /// with their length
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        match self {
+            Message::Lookup(request, name) => {
+                bytes.push(0);
+                put_u64(&mut bytes, *request);
+                put_str(&mut bytes, name);
+            }
+            Message::Register(request, name, aid) => {
+                bytes.push(1);
+                put_u64(&mut bytes, *request);
+                put_str(&mut bytes, name);
+                put_aid(&mut bytes, aid);
+            }
+            Message::Release(aid) => {
+                bytes.push(2);
+                put_aid(&mut bytes, aid);
+            }
+            Message::Resolved(request, aid) => {
+                bytes.push(3);
+                put_u64(&mut bytes, *request);
+                if let Some(aid) = aid {
+                    put_aid(&mut bytes, aid);
+                }
+            }
+            Message::Registered(request, ok) => {
+                bytes.push(4);
+                put_u64(&mut bytes, *request);
+                bytes.push(*ok as u8);
+            }
+            Message::Invalidate(name) => {
+                bytes.push(5);
+                put_str(&mut bytes, name);
+            }
+        }
+
+        bytes
+    }
+
+    /// Decode a message, None if it is malformed
+    fn decode(bytes: &[u8]) -> Option<Message> {
+        let (kind, mut rest) = bytes.split_first()?;
+
+        let message = match kind {
+            0 => Message::Lookup(take_u64(&mut rest)?, take_str(&mut rest)?),
+            1 => Message::Register(
+                take_u64(&mut rest)?,
+                take_str(&mut rest)?,
+                take_aid(&mut rest)?,
+            ),
+            2 => Message::Release(take_aid(&mut rest)?),
+            3 => {
+                let request = take_u64(&mut rest)?;
+                if rest.is_empty() {
+                    Message::Resolved(request, None)
+                } else {
+                    Message::Resolved(request, Some(take_aid(&mut rest)?))
+                }
+            }
+            4 => {
+                let request = take_u64(&mut rest)?;
+                let (ok, remaining) = rest.split_first()?;
+                rest = remaining;
+                Message::Registered(request, *ok != 0)
+            }
+            5 => Message::Invalidate(take_str(&mut rest)?),
+            _ => return None,
+        };
+
+        if rest.is_empty() {
+            Some(message)
+        } else {
+            None
+        }
+    }
+}
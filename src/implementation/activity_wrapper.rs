@@ -1,13 +1,40 @@
-use crate::activity::State;
+use crate::activity::{CompletionReason, State};
 use crate::implementation::constellation_identifier::ConstellationIdentifier;
-use crate::{ActivityIdentifier, ActivityTrait, ConstellationTrait, Context, Event};
+use crate::implementation::object_pool;
+use crate::implementation::scope_registry;
+use crate::{
+    ActivityError, ActivityIdentifier, ActivityTrait, ConstellationHandle, Context, Event,
+    Placement, ScopeId, SubmitOptions,
+};
 use std::fmt;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
 
-pub trait ActivityWrapperTrait: Sync + Send + ActivityTrait + fmt::Display + mopa::Any {
+pub trait ActivityWrapperTrait: Send + ActivityTrait + fmt::Display {
     fn activity_identifier(&self) -> &ActivityIdentifier;
     fn expects_event(&self) -> bool;
     fn may_be_stolen(&self) -> bool;
+    fn size(&self) -> usize;
+    fn priority(&self) -> i32;
+    fn thread_affinity(&self) -> Option<usize>;
+    fn scope(&self) -> Option<ScopeId>;
+    fn max_execution_time(&self) -> Option<Duration>;
+    fn placement(&self) -> Placement;
+    fn context(&self) -> &Context;
+    fn yield_round(&self) -> u64;
+    fn set_yield_round(&mut self, round: u64);
+    fn submitted_at(&self) -> Instant;
+    fn suspended_at(&self) -> Option<Instant>;
+    fn set_suspended_at(&mut self, at: Instant);
+    fn migrations(&self) -> u32;
+    fn migrated_from(&self) -> Option<i32>;
+    fn record_migration(&mut self, from: i32);
+    fn timed_out(&self) -> bool;
+    fn set_timed_out(&mut self);
+    fn retries(&self) -> u32;
+    fn failures(&self) -> u32;
+    fn record_failure(&mut self);
+    fn recycle(self: Box<Self>);
 }
 
 /// Structure for internal use inside Constellation only. As soon as an
@@ -19,17 +46,37 @@ pub trait ActivityWrapperTrait: Sync + Send + ActivityTrait + fmt::Display + mop
 ///
 /// # Members
 /// * `id` - A generated activity identifier, unique for this activity
-/// * `may_be_stolen` - Indicates whether this activity may be stolen by other
-/// threads/nodes
 /// * `context` - The context specifying where an activity may be executed
-/// * `expects_events` - Indicates whether this activity expects events to
-/// complete
+/// * `options` - The SubmitOptions given when submitting the activity, such
+/// as whether it may be stolen and whether it expects events
+/// * `size` - The size hint of the activity, cached at submit time
+/// * `yield_round` - When the activity last returned State::YIELD, counted by
+/// the executor thread it ran on. 0 if it never yielded, in which case it has
+/// not been initialized yet when it is taken from the work queue.
+/// * `submitted_at` - When the activity was submitted
+/// * `suspended_at` - When the activity was last suspended, None if it never
+/// was
+/// * `migrations` - Number of times the activity was moved from the queues of
+/// one executor thread to another, by shedding or retiring threads
+/// * `migrated_from` - Id of the executor thread the activity was last moved
+/// away from, None if it never migrated
+/// * `timed_out` - Whether an invocation of the activity exceeded its
+/// maximum execution time
+/// * `failures` - Number of times the activity panicked and was run again,
+/// see `SubmitOptions::retries`
 /// * `activity` - A user defined activity to be executed in Constellation
 pub struct ActivityWrapper {
     id: ActivityIdentifier,
-    may_be_stolen: bool,
     context: Context,
-    expects_events: bool,
+    options: SubmitOptions,
+    size: usize,
+    yield_round: u64,
+    submitted_at: Instant,
+    suspended_at: Option<Instant>,
+    migrations: u32,
+    migrated_from: Option<i32>,
+    timed_out: bool,
+    failures: u32,
     activity: Arc<Mutex<dyn ActivityTrait>>,
 }
 
@@ -39,28 +86,140 @@ impl ActivityWrapperTrait for ActivityWrapper {
     }
 
     fn expects_event(&self) -> bool {
-        return self.expects_events;
+        return self.options.expects_events;
     }
 
     fn may_be_stolen(&self) -> bool {
-        return self.may_be_stolen;
+        return self.options.may_be_stolen;
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn priority(&self) -> i32 {
+        self.options.priority
+    }
+
+    fn thread_affinity(&self) -> Option<usize> {
+        self.options.thread_affinity
+    }
+
+    fn scope(&self) -> Option<ScopeId> {
+        self.options.scope
+    }
+
+    fn max_execution_time(&self) -> Option<Duration> {
+        self.options.max_execution_time
+    }
+
+    fn placement(&self) -> Placement {
+        self.options.placement
+    }
+
+    fn context(&self) -> &Context {
+        &self.context
+    }
+
+    fn yield_round(&self) -> u64 {
+        self.yield_round
+    }
+
+    fn set_yield_round(&mut self, round: u64) {
+        self.yield_round = round;
+    }
+
+    fn submitted_at(&self) -> Instant {
+        self.submitted_at
+    }
+
+    fn suspended_at(&self) -> Option<Instant> {
+        self.suspended_at
+    }
+
+    fn set_suspended_at(&mut self, at: Instant) {
+        self.suspended_at = Some(at);
+    }
+
+    fn migrations(&self) -> u32 {
+        self.migrations
+    }
+
+    fn migrated_from(&self) -> Option<i32> {
+        self.migrated_from
+    }
+
+    fn record_migration(&mut self, from: i32) {
+        self.migrations += 1;
+        self.migrated_from = Some(from);
+    }
+
+    fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+
+    fn set_timed_out(&mut self) {
+        self.timed_out = true;
+    }
+
+    fn retries(&self) -> u32 {
+        self.options.retries
+    }
+
+    fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    fn record_failure(&mut self) {
+        self.failures += 1;
+    }
+
+    /// Drop the wrapper of a finished activity, it is kept in the object pool
+    /// of the thread if there is one
+    fn recycle(self: Box<Self>) {
+        object_pool::release_wrapper(self);
     }
 }
 
 impl ActivityTrait for ActivityWrapper {
-    fn cleanup(&mut self, constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>) {
+    fn cleanup(&mut self, constellation: &ConstellationHandle) {
+        self.lock_activity().cleanup(constellation);
+    }
+
+    fn cancelled(&mut self, constellation: &ConstellationHandle) {
         self.activity
             .lock()
             .expect(&format!(
                 "Could not acquire lock on activity with id {}",
                 self.activity_identifier()
             ))
-            .cleanup(constellation);
+            .cancelled(constellation);
+    }
+
+    fn on_error(
+        &mut self,
+        constellation: &ConstellationHandle,
+        error: &ActivityError,
+        id: &ActivityIdentifier,
+    ) {
+        // The activity is poisoned when it panicked, it is still told
+        self.activity
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .on_error(constellation, error, id);
+    }
+
+    fn on_complete(&mut self, reason: CompletionReason) {
+        // The activity is poisoned when it failed, it is still told
+        self.activity
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .on_complete(reason);
     }
 
     fn initialize(
         &mut self,
-        constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>,
+        constellation: &ConstellationHandle,
         id: &ActivityIdentifier,
     ) -> State {
         assert_eq!(
@@ -72,18 +231,12 @@ impl ActivityTrait for ActivityWrapper {
             id
         );
 
-        self.activity
-            .lock()
-            .expect(&format!(
-                "Could not acquire lock on activity with id {}",
-                id
-            ))
-            .initialize(constellation, id)
+        self.lock_activity().initialize(constellation, id)
     }
 
     fn process(
         &mut self,
-        constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>,
+        constellation: &ConstellationHandle,
         event: Option<Box<Event>>,
         id: &ActivityIdentifier,
     ) -> State {
@@ -96,42 +249,190 @@ impl ActivityTrait for ActivityWrapper {
             id
         );
 
+        self.lock_activity().process(constellation, event, id)
+    }
+
+    fn batch_events(&self) -> bool {
+        self.lock_activity().batch_events()
+    }
+
+    fn process_batch(
+        &mut self,
+        constellation: &ConstellationHandle,
+        events: Vec<Box<Event>>,
+        id: &ActivityIdentifier,
+    ) -> State {
+        assert_eq!(
+            self.activity_identifier(),
+            id,
+            "Found different activity identifiers in wrapper \
+             and argument: {} - {}",
+            self.activity_identifier(),
+            id
+        );
+
+        self.lock_activity()
+            .process_batch(constellation, events, id)
+    }
+
+    fn type_name(&self) -> &'static str {
         self.activity
             .lock()
             .expect(&format!(
                 "Could not acquire lock on activity with id {}",
-                id
+                self.activity_identifier()
             ))
-            .process(constellation, event, id)
+            .type_name()
+    }
+
+    fn encode(&self) -> Option<Vec<u8>> {
+        self.activity
+            .lock()
+            .expect(&format!(
+                "Could not acquire lock on activity with id {}",
+                self.activity_identifier()
+            ))
+            .encode()
     }
 }
 
 impl ActivityWrapper {
+    /// Wrap a newly submitted activity. If no scope is given in the options,
+    /// the activity inherits the scope of the activity executing on the
+    /// calling thread (if any). The wrapper is taken from the object pool of
+    /// the thread, if there is one.
     pub fn new(
         const_id: Arc<Mutex<ConstellationIdentifier>>,
         activity: Arc<Mutex<dyn ActivityTrait>>,
         context: &Context,
-        may_be_stolen: bool,
-        expects_events: bool,
+        options: SubmitOptions,
     ) -> Box<ActivityWrapper> {
+        let mut options = options;
+        if options.scope.is_none() {
+            options.scope = scope_registry::current_scope();
+        }
+
+        let size = activity
+            .lock()
+            .expect("Could not acquire lock on activity to read its size hint")
+            .size_hint();
+
+        if let Some(mut wrapper) = object_pool::take_wrapper() {
+            // Overwrite every field, reusing the buffer of the node name
+            wrapper.id.renew(&const_id);
+            wrapper.context = context.clone();
+            wrapper.options = options;
+            wrapper.size = size;
+            wrapper.yield_round = 0;
+            wrapper.submitted_at = Instant::now();
+            wrapper.suspended_at = None;
+            wrapper.migrations = 0;
+            wrapper.migrated_from = None;
+            wrapper.timed_out = false;
+            wrapper.failures = 0;
+            wrapper.activity = activity;
+            return wrapper;
+        }
+
         Box::from(ActivityWrapper {
             id: ActivityIdentifier::new(const_id),
             context: (*context).clone(),
-            may_be_stolen,
-            expects_events,
+            options,
+            size,
+            yield_round: 0,
+            submitted_at: Instant::now(),
+            suspended_at: None,
+            migrations: 0,
+            migrated_from: None,
+            timed_out: false,
+            failures: 0,
             activity: activity.clone(), // Clone the reference
         })
     }
+
+    /// Wrap an activity stolen from another node, it keeps the identifier it
+    /// was given on the node it was submitted to
+    ///
+    /// # Arguments
+    /// * `id` - The identifier of the activity
+    /// * `activity` - The decoded activity
+    /// * `context` - The context of the activity
+    /// * `options` - The options the activity was submitted with, as far as
+    /// they apply on another node
+    pub(crate) fn stolen(
+        id: ActivityIdentifier,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Box<ActivityWrapper> {
+        let size = activity
+            .lock()
+            .expect("Could not acquire lock on activity to read its size hint")
+            .size_hint();
+
+        Box::from(ActivityWrapper {
+            id,
+            context: context.clone(),
+            options,
+            size,
+            yield_round: 0,
+            submitted_at: Instant::now(),
+            suspended_at: None,
+            migrations: 0,
+            migrated_from: None,
+            timed_out: false,
+            failures: 0,
+            activity,
+        })
+    }
+
+    /// Lock the activity to run it. The lock is poisoned when the activity
+    /// panicked, which is only recovered from when it is run again, see
+    /// `SubmitOptions::retries`.
+    fn lock_activity(&self) -> MutexGuard<'_, dyn ActivityTrait> {
+        self.activity.lock().unwrap_or_else(|e| {
+            if self.failures == 0 {
+                panic!(
+                    "Could not acquire lock on activity with id {}: {:?}",
+                    self.id, e
+                );
+            }
+            e.into_inner()
+        })
+    }
+
+    /// Clear everything of the activity this wrapper was used for, before it
+    /// is kept in the object pool. The activity is dropped, only the
+    /// allocations of the wrapper and its node name are kept. The context is
+    /// an interned label, it holds nothing of the activity.
+    ///
+    /// # Arguments
+    /// * `released` - Activity to hold instead, which is never run
+    pub(crate) fn reset(&mut self, released: Arc<Mutex<dyn ActivityTrait>>) {
+        self.id.release();
+        self.options = SubmitOptions::default();
+        self.size = 0;
+        self.yield_round = 0;
+        self.suspended_at = None;
+        self.migrations = 0;
+        self.migrated_from = None;
+        self.timed_out = false;
+        self.failures = 0;
+        self.activity = released;
+    }
 }
 
 impl fmt::Display for ActivityWrapper {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "{}:stealable:{}:{}:exp_event:{}",
-            self.id, self.may_be_stolen, self.context, self.expects_events
+            "{}:stealable:{}:{}:exp_event:{}:size:{}:priority:{}",
+            self.id,
+            self.options.may_be_stolen,
+            self.context,
+            self.options.expects_events,
+            self.size,
+            self.options.priority
         )
     }
 }
-
-mopafy!(ActivityWrapperTrait);
@@ -0,0 +1,91 @@
+///! Communication between the processes of a constellation instance. The
+///! identifiers and constellations only talk to the other processes through
+///! the Communication trait, so that the transport can be swapped: MpiComm
+///! uses MPI, TcpComm plain TCP and LocalComm runs a group of processes
+///! inside a single process, which is used for testing distributed code
+///! without mpirun. SameNodeComm wraps one of the others and takes a faster
+///! path for processes on the same node.
+use super::node_handler::NodeHandler;
+
+use std::collections::HashMap;
+
+/// Tag of the heartbeats sent between nodes, see NodeHealth. Messages sent
+/// by applications through a Communication should use lower tags.
+pub const HEARTBEAT_TAG: i32 = 32_000;
+
+/// Tag of the requests sent to the activity registry of the master
+pub const REGISTRY_TAG: i32 = 32_001;
+
+/// Tag of the answers and invalidations sent by the activity registry of the
+/// master
+pub const REGISTRY_REPLY_TAG: i32 = 32_002;
+
+/// Tag of the constellation_id the master sends to the other processes when
+/// activating
+pub const CONSTELLATION_ID_TAG: i32 = 32_003;
+
+/// Tag of the load reports every node sends to the other nodes, see NodeLoad
+pub const LOAD_TAG: i32 = 32_004;
+
+/// Tag of the requests for activities a node sends when all its executor
+/// threads are idle
+pub const STEAL_TAG: i32 = 32_005;
+
+/// Tag of the activities handed to a node in answer to a steal request
+pub const STEAL_REPLY_TAG: i32 = 32_006;
+
+/// Tag of the events forwarded to the node executing their destination
+pub const EVENT_TAG: i32 = 32_007;
+
+/// Tag of the message the master sends to the other nodes once it is done
+pub const SHUTDOWN_TAG: i32 = 32_008;
+
+/// Transport used to communicate with the other processes
+pub trait Communication: Send + Sync {
+    /// Rank of the calling process, from 0 to `size()`
+    fn rank(&self) -> i32;
+
+    /// Number of processes
+    fn size(&self) -> i32;
+
+    /// Name of the node the calling process runs on
+    fn processor_name(&self) -> String;
+
+    /// Node information of every process, by rank. This is a collective
+    /// call, it MUST be called from each process.
+    ///
+    /// # Returns
+    /// * `HashMap<i32, NodeHandler>` - The node information of every process
+    fn groups(&self) -> HashMap<i32, NodeHandler>;
+
+    /// Send a message to another process, without waiting for it to be
+    /// received
+    ///
+    /// # Arguments
+    /// * `destination` - Rank of the receiving process
+    /// * `tag` - Tag identifying the kind of message
+    /// * `message` - The message
+    fn send(&self, destination: i32, tag: i32, message: &[u8]);
+
+    /// Receive a message with the given tag, if one has arrived
+    ///
+    /// # Arguments
+    /// * `tag` - Tag identifying the kind of message
+    ///
+    /// # Returns
+    /// * `Option<(i32, Vec<u8>)>` - The rank of the sender and the message,
+    /// None if no message with the tag has arrived
+    fn try_receive(&self, tag: i32) -> Option<(i32, Vec<u8>)>;
+
+    /// Block until all processes have called `barrier()`
+    fn barrier(&self);
+
+    /// Check whether the calling process is the master
+    ///
+    /// # Arguments
+    /// * `master_rank` - Rank of the master process, see `master_rank` in the
+    /// ConstellationConfiguration
+    fn is_master(&self, master_rank: i32) -> bool {
+        self.rank() == master_rank
+    }
+}
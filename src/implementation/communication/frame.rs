@@ -0,0 +1,115 @@
+///! Framing of messages sent over a byte stream, shared by the transports
+///! which use sockets (TcpComm and SameNodeComm). A connection starts with
+///! the rank of the sender, followed by frames holding the tag, the length of
+///! the message and the message itself, all integers are big endian.
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+
+/// Messages received from all peers, shared with the listener and the
+/// threads reading from the connections
+///
+/// # Members
+/// * `messages` - The messages in the order they were received: sender rank,
+/// tag and message
+/// * `arrived` - Signalled when a message arrives
+/// * `closers` - Close the connections accepted from peers, called when the
+/// transport is dropped
+/// * `stopped` - Set when the transport is dropped, stops the listener
+pub(crate) struct Inbox {
+    messages: Mutex<VecDeque<(i32, i32, Vec<u8>)>>,
+    arrived: Condvar,
+    closers: Mutex<Vec<Box<dyn Fn() + Send>>>,
+    stopped: AtomicBool,
+}
+
+impl Inbox {
+    pub(crate) fn new() -> Inbox {
+        Inbox {
+            messages: Mutex::new(VecDeque::new()),
+            arrived: Condvar::new(),
+            closers: Mutex::new(Vec::new()),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    /// Remove the oldest message with the given tag, if one has arrived
+    pub(crate) fn try_receive(&self, tag: i32) -> Option<(i32, Vec<u8>)> {
+        let mut messages = self.messages.lock().unwrap();
+        let index = messages.iter().position(|m| m.1 == tag)?;
+        messages
+            .remove(index)
+            .map(|(source, _, message)| (source, message))
+    }
+
+    /// Block until a message with the given tag arrives from the given peer
+    pub(crate) fn receive_from(&self, source: i32, tag: i32) -> Vec<u8> {
+        let mut messages = self.messages.lock().unwrap();
+
+        loop {
+            if let Some(index) = messages.iter().position(|m| m.0 == source && m.1 == tag) {
+                return messages.remove(index).unwrap().2;
+            }
+
+            messages = self.arrived.wait(messages).unwrap();
+        }
+    }
+
+    /// Register a function closing an accepted connection, so that the
+    /// thread reading from it stops when the transport is dropped
+    pub(crate) fn on_close(&self, close: Box<dyn Fn() + Send>) {
+        self.closers.lock().unwrap().push(close);
+    }
+
+    /// Stop the listener and close all accepted connections
+    pub(crate) fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+
+        for close in self.closers.lock().unwrap().drain(..) {
+            close();
+        }
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Read the frames sent over a connection into the inbox, until the peer
+    /// closes the connection
+    pub(crate) fn read_frames<R: Read>(&self, mut stream: R) -> io::Result<()> {
+        let mut word = [0u8; 4];
+        stream.read_exact(&mut word)?;
+        let source = i32::from_be_bytes(word);
+
+        loop {
+            if let Err(e) = stream.read_exact(&mut word) {
+                if e.kind() == io::ErrorKind::UnexpectedEof {
+                    return Ok(());
+                }
+                return Err(e);
+            }
+            let tag = i32::from_be_bytes(word);
+
+            stream.read_exact(&mut word)?;
+            let mut message = vec![0u8; u32::from_be_bytes(word) as usize];
+            stream.read_exact(&mut message)?;
+
+            self.messages
+                .lock()
+                .unwrap()
+                .push_back((source, tag, message));
+            self.arrived.notify_all();
+        }
+    }
+}
+
+/// Encode a message as a frame
+pub(crate) fn encode(tag: i32, message: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(8 + message.len());
+    frame.extend_from_slice(&tag.to_be_bytes());
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(message);
+
+    frame
+}
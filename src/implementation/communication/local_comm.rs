@@ -0,0 +1,125 @@
+///! Communication between a group of processes which all run inside the
+///! calling process, each process is represented by a LocalComm with its own
+///! rank. Used to run and test distributed code without mpirun.
+use super::comm::Communication;
+use super::node_handler::NodeHandler;
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Barrier, Mutex};
+
+/// A message waiting to be received: sender rank, tag and message
+type LocalMessage = (i32, i32, Vec<u8>);
+
+/// State shared by all processes in a group
+///
+/// # Members
+/// * `node_names` - Name of the node of every process, by rank
+/// * `mailboxes` - Messages waiting to be received by every process, by rank
+/// * `barrier` - Barrier for all processes in the group
+struct LocalGroup {
+    node_names: Vec<String>,
+    mailboxes: Vec<Mutex<VecDeque<LocalMessage>>>,
+    barrier: Barrier,
+}
+
+/// One process in a group of processes running inside this process
+///
+/// # Members
+/// * `rank` - Rank of this process
+/// * `group` - State shared by all processes in the group
+#[derive(Clone)]
+pub struct LocalComm {
+    rank: i32,
+    group: Arc<LocalGroup>,
+}
+
+impl LocalComm {
+    /// Create a group holding only a single process
+    pub fn new() -> LocalComm {
+        LocalComm::group(1).remove(0)
+    }
+
+    /// Create a group of processes which all run on the same node
+    ///
+    /// # Arguments
+    /// * `size` - Number of processes, at least 1
+    ///
+    /// # Returns
+    /// * `Vec<LocalComm>` - The processes, by rank
+    pub fn group(size: usize) -> Vec<LocalComm> {
+        LocalComm::group_on_nodes(vec!["localhost".to_string(); size.max(1)])
+    }
+
+    /// Create a group of processes with the given node names
+    ///
+    /// # Arguments
+    /// * `node_names` - Name of the node of every process, by rank
+    ///
+    /// # Returns
+    /// * `Vec<LocalComm>` - The processes, by rank
+    pub fn group_on_nodes(node_names: Vec<String>) -> Vec<LocalComm> {
+        let size = node_names.len();
+        let group = Arc::new(LocalGroup {
+            node_names,
+            mailboxes: (0..size).map(|_| Mutex::new(VecDeque::new())).collect(),
+            barrier: Barrier::new(size),
+        });
+
+        (0..size)
+            .map(|rank| LocalComm {
+                rank: rank as i32,
+                group: group.clone(),
+            })
+            .collect()
+    }
+}
+
+impl Communication for LocalComm {
+    fn rank(&self) -> i32 {
+        self.rank
+    }
+
+    fn size(&self) -> i32 {
+        self.group.node_names.len() as i32
+    }
+
+    fn processor_name(&self) -> String {
+        self.group.node_names[self.rank as usize].clone()
+    }
+
+    fn groups(&self) -> HashMap<i32, NodeHandler> {
+        self.group
+            .node_names
+            .iter()
+            .enumerate()
+            .map(|(rank, name)| {
+                (
+                    rank as i32,
+                    NodeHandler {
+                        node_name: name.clone(),
+                        node_id: rank,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn send(&self, destination: i32, tag: i32, message: &[u8]) {
+        self.group.mailboxes[destination as usize]
+            .lock()
+            .unwrap()
+            .push_back((self.rank, tag, message.to_vec()));
+    }
+
+    fn try_receive(&self, tag: i32) -> Option<(i32, Vec<u8>)> {
+        let mut mailbox = self.group.mailboxes[self.rank as usize].lock().unwrap();
+        let index = mailbox.iter().position(|m| m.1 == tag)?;
+        mailbox
+            .remove(index)
+            .map(|(source, _, message)| (source, message))
+    }
+
+    fn barrier(&self) {
+        self.group.barrier.wait();
+    }
+}
@@ -1,2 +1,9 @@
+pub mod comm;
+mod frame;
+pub mod local_comm;
+pub mod mpi_comm;
 pub mod mpi_info;
 pub mod node_handler;
+pub mod same_node_comm;
+pub mod tcp_comm;
+pub(crate) mod wire;
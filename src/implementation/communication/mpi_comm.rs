@@ -0,0 +1,69 @@
+///! Communication using MPI, the transport used by default
+extern crate mpi;
+
+use super::comm::Communication;
+use super::mpi_info;
+use super::node_handler::{self, NodeHandler};
+
+use mpi::environment::Universe;
+use mpi::point_to_point::{Destination, Source};
+use mpi::topology::Communicator;
+use std::collections::HashMap;
+
+/// Communication between MPI processes, using the Universe shared by all
+/// constellation instances in this process
+///
+/// # Members
+/// * `universe` - MPI Universe struct
+pub struct MpiComm {
+    universe: &'static Universe,
+}
+
+impl MpiComm {
+    /// Create a new instance, MPI is initialized on the first call
+    pub fn new() -> MpiComm {
+        MpiComm {
+            universe: mpi_info::universe(),
+        }
+    }
+}
+
+impl Communication for MpiComm {
+    fn rank(&self) -> i32 {
+        self.universe.world().rank()
+    }
+
+    fn size(&self) -> i32 {
+        self.universe.world().size()
+    }
+
+    fn processor_name(&self) -> String {
+        mpi::environment::processor_name().expect("Could not retrieve processor_name")
+    }
+
+    fn groups(&self) -> HashMap<i32, NodeHandler> {
+        let mut groups = HashMap::new();
+        node_handler::create_groups(&mut groups, self.universe);
+
+        groups
+    }
+
+    fn send(&self, destination: i32, tag: i32, message: &[u8]) {
+        self.universe
+            .world()
+            .process_at_rank(destination)
+            .send_with_tag(message, tag);
+    }
+
+    fn try_receive(&self, tag: i32) -> Option<(i32, Vec<u8>)> {
+        let world = self.universe.world();
+        let (message, status) = world.any_process().immediate_matched_probe_with_tag(tag)?;
+        let (data, _) = message.matched_receive_vec::<u8>();
+
+        Some((status.source_rank(), data))
+    }
+
+    fn barrier(&self) {
+        self.universe.world().barrier();
+    }
+}
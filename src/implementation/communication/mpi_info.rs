@@ -1,24 +1,29 @@
-//! Contains functions for all MPI information used in Constellation,
-//! could be replaced with an alternative communication scheme
+//! Holds the MPI Universe used by MpiComm, see the Communication trait for
+//! the information used in Constellation
 
 extern crate mpi;
 
 use mpi::environment::Universe;
-use mpi::topology::{Communicator, SystemCommunicator};
+use mpi::Threading;
 
-/// Get the MPI rank of the calling process
-pub fn rank(universe: &Universe) -> i32 {
-    universe.world().rank()
-}
-
-pub fn world(universe: &Universe) -> SystemCommunicator {
-    universe.world()
-}
+lazy_static! {
+    /// MPI can only be initialized once per process (and not again after it
+    /// has been finalized), so all constellation instances share this
+    /// Universe, which lives until the process exits. MPI is used from
+    /// several threads (e.g. the heartbeat thread), so full thread support is
+    /// requested.
+    static ref UNIVERSE: Universe = {
+        let (universe, threading) = mpi::initialize_with_threading(Threading::Multiple)
+            .expect("Could not initialize MPI");
+        if threading != Threading::Multiple {
+            warn!("MPI does not support calls from multiple threads at the same time");
+        }
 
-pub fn size(universe: &Universe) -> i32 {
-    universe.world().size()
+        universe
+    };
 }
 
-pub fn master(universe: &Universe) -> bool {
-    universe.world().rank() == 0
+/// Get the MPI Universe, MPI is initialized on the first call
+pub fn universe() -> &'static Universe {
+    &UNIVERSE
 }
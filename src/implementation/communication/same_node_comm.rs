@@ -0,0 +1,229 @@
+///! Fast path for processes which run on the same node. SameNodeComm wraps
+///! another transport (MPI or TCP) and sends the messages for processes on
+///! the same node over unix domain sockets instead, which skips the network
+///! stack. Which processes share a node is taken from `groups()` of the
+///! wrapped transport. Messages to other nodes, the barrier and the node
+///! information are left to the wrapped transport, so callers of `send` do
+///! not notice which path a message takes.
+///!
+///! Every process listens on a socket named after its rank in a directory
+///! shared by the processes, see `same_node_socket_dir` in the
+///! ConstellationConfiguration. Use a separate directory for every run.
+use super::comm::Communication;
+use super::frame::{self, Inbox};
+use super::node_handler::NodeHandler;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Time the listener sleeps when there is no connection to accept
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A transport which sends messages to processes on the same node over unix
+/// domain sockets, and all other messages over the wrapped transport
+///
+/// # Members
+/// * `inner` - The wrapped transport
+/// * `socket` - Path of the socket this process listens on
+/// * `directory` - Directory holding the sockets of all processes
+/// * `same_node` - Whether a process runs on the same node, by rank
+/// * `connections` - Connection to every process on the same node, by rank,
+/// None when it still has to be connected
+/// * `inbox` - Messages received over unix domain sockets
+pub struct SameNodeComm {
+    inner: Arc<dyn Communication>,
+    socket: PathBuf,
+    directory: PathBuf,
+    same_node: Vec<bool>,
+    connections: Vec<Mutex<Option<UnixStream>>>,
+    inbox: Arc<Inbox>,
+}
+
+impl SameNodeComm {
+    /// Start listening on the socket of this process and find the processes
+    /// on the same node. This is a collective call, it MUST be called from
+    /// each process, as it waits until all processes are listening.
+    ///
+    /// # Arguments
+    /// * `inner` - The transport used for processes on other nodes
+    /// * `directory` - Directory in which the sockets are created, created if
+    /// it does not exist
+    ///
+    /// # Returns
+    /// * `io::Result<SameNodeComm>` - The new instance, or an error if the
+    /// socket could not be created
+    pub fn new(inner: Arc<dyn Communication>, directory: &Path) -> io::Result<SameNodeComm> {
+        fs::create_dir_all(directory)?;
+
+        let rank = inner.rank();
+        let socket = socket_path(directory, rank);
+        if socket.exists() {
+            fs::remove_file(&socket)?;
+        }
+
+        let listener = UnixListener::bind(&socket)?;
+        listener.set_nonblocking(true)?;
+
+        let inbox = Arc::new(Inbox::new());
+        let listener_inbox = inbox.clone();
+        thread::Builder::new()
+            .name(format!("constellation-same-node-listener-{}", rank))
+            .spawn(move || listen(listener, listener_inbox))?;
+
+        let groups = inner.groups();
+        let node_name = groups
+            .get(&rank)
+            .map(|node| node.node_name.clone())
+            .unwrap_or_else(|| inner.processor_name());
+        let same_node = (0..inner.size())
+            .map(|peer| {
+                peer != rank
+                    && groups
+                        .get(&peer)
+                        .map_or(false, |node| node.node_name == node_name)
+            })
+            .collect::<Vec<bool>>();
+
+        // Make sure every process is listening before the first message is
+        // sent
+        inner.barrier();
+
+        Ok(SameNodeComm {
+            connections: same_node.iter().map(|_| Mutex::new(None)).collect(),
+            same_node,
+            socket,
+            directory: directory.to_path_buf(),
+            inner,
+            inbox,
+        })
+    }
+
+    /// Check whether a process runs on the same node as this process, the
+    /// messages for it are sent over a unix domain socket
+    ///
+    /// # Arguments
+    /// * `rank` - Rank of the process
+    pub fn is_same_node(&self, rank: i32) -> bool {
+        self.same_node.get(rank as usize).cloned().unwrap_or(false)
+    }
+
+    /// Write a frame to a process on the same node, connecting first if there
+    /// is no connection yet
+    fn write_frame(&self, destination: i32, tag: i32, message: &[u8]) -> io::Result<()> {
+        let mut connection = self.connections[destination as usize].lock().unwrap();
+
+        if connection.is_none() {
+            let mut stream = UnixStream::connect(socket_path(&self.directory, destination))?;
+            stream.write_all(&self.inner.rank().to_be_bytes())?;
+            *connection = Some(stream);
+        }
+
+        let result = connection
+            .as_mut()
+            .unwrap()
+            .write_all(&frame::encode(tag, message));
+        if result.is_err() {
+            *connection = None;
+        }
+
+        result
+    }
+}
+
+impl Communication for SameNodeComm {
+    fn rank(&self) -> i32 {
+        self.inner.rank()
+    }
+
+    fn size(&self) -> i32 {
+        self.inner.size()
+    }
+
+    fn processor_name(&self) -> String {
+        self.inner.processor_name()
+    }
+
+    fn groups(&self) -> HashMap<i32, NodeHandler> {
+        self.inner.groups()
+    }
+
+    fn send(&self, destination: i32, tag: i32, message: &[u8]) {
+        if self.is_same_node(destination) {
+            match self.write_frame(destination, tag, message) {
+                Ok(()) => return,
+                Err(e) => warn!(
+                    "Could not send to rank {} on the same node, using the wrapped transport: {}",
+                    destination, e
+                ),
+            }
+        }
+
+        self.inner.send(destination, tag, message);
+    }
+
+    fn try_receive(&self, tag: i32) -> Option<(i32, Vec<u8>)> {
+        self.inbox
+            .try_receive(tag)
+            .or_else(|| self.inner.try_receive(tag))
+    }
+
+    fn barrier(&self) {
+        self.inner.barrier();
+    }
+}
+
+/// Stop listening, close all connections and remove the socket
+impl Drop for SameNodeComm {
+    fn drop(&mut self) {
+        self.inbox.stop();
+        fs::remove_file(&self.socket).ok();
+    }
+}
+
+/// Path of the socket the process with the given rank listens on
+fn socket_path(directory: &Path, rank: i32) -> PathBuf {
+    directory.join(format!("rank-{}.sock", rank))
+}
+
+/// Accept connections from processes on the same node until stopped, every
+/// connection is read by its own thread
+fn listen(listener: UnixListener, inbox: Arc<Inbox>) {
+    while !inbox.is_stopped() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                match stream.try_clone() {
+                    Ok(clone) => inbox.on_close(Box::new(move || {
+                        clone.shutdown(Shutdown::Both).ok();
+                    })),
+                    Err(e) => warn!("Could not register connection from peer: {}", e),
+                }
+
+                let inbox = inbox.clone();
+                let spawned = thread::Builder::new()
+                    .name("constellation-same-node-reader".to_string())
+                    .spawn(move || {
+                        let read = stream
+                            .set_nonblocking(false)
+                            .and_then(|_| inbox.read_frames(stream));
+                        if let Err(e) = read {
+                            if !inbox.is_stopped() {
+                                warn!("Connection from peer closed: {}", e);
+                            }
+                        }
+                    });
+                if let Err(e) = spawned {
+                    warn!("Could not spawn thread reading from peer: {}", e);
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_INTERVAL),
+            Err(e) => warn!("Could not accept connection: {}", e),
+        }
+    }
+}
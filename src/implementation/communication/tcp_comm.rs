@@ -0,0 +1,252 @@
+///! Communication over TCP, for deployments without MPI. Every process is
+///! given the addresses of all processes, its rank is its index in that list.
+///! Processes whose addresses resolve to the same IP address are on the same
+///! node.
+///!
+///! Messages are sent as frames (see frame.rs) over one persistent
+///! connection per peer, which is (re)connected when needed.
+use super::comm::Communication;
+use super::frame::{self, Inbox};
+use super::node_handler::NodeHandler;
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Tag reserved for the messages used by `barrier()`
+const BARRIER_TAG: i32 = i32::min_value();
+
+/// Number of attempts to connect to a peer, peers which are still starting
+/// up get some time to start listening
+const CONNECT_ATTEMPTS: u32 = 50;
+
+/// Time between two attempts to connect to a peer
+const CONNECT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time the listener sleeps when there is no connection to accept
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// One process in a group of processes communicating over TCP
+///
+/// # Members
+/// * `rank` - Rank of this process, its index in `peers`
+/// * `peers` - Resolved address of every process, by rank
+/// * `connections` - Connection to every process, by rank, None when it
+/// still has to be (re)connected
+/// * `inbox` - Messages received from all peers
+pub struct TcpComm {
+    rank: i32,
+    peers: Vec<SocketAddr>,
+    connections: Vec<Mutex<Option<TcpStream>>>,
+    inbox: Arc<Inbox>,
+}
+
+impl TcpComm {
+    /// Resolve the addresses of all processes and start listening on the
+    /// port of this process, on all interfaces.
+    ///
+    /// # Arguments
+    /// * `peers` - Address ("host:port") of every process, by rank
+    /// * `rank` - Rank of this process, its index in `peers`
+    ///
+    /// # Returns
+    /// * `io::Result<TcpComm>` - The new instance, or an error if an address
+    /// could not be resolved, the rank is out of range or the port could not
+    /// be bound
+    pub fn new(peers: &[String], rank: usize) -> io::Result<TcpComm> {
+        if rank >= peers.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("rank {} is not one of the {} peers", rank, peers.len()),
+            ));
+        }
+
+        let peers = peers
+            .iter()
+            .map(|peer| {
+                peer.to_socket_addrs()?.next().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("Could not resolve {}", peer),
+                    )
+                })
+            })
+            .collect::<io::Result<Vec<SocketAddr>>>()?;
+
+        let listener = TcpListener::bind(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            peers[rank].port(),
+        ))?;
+        listener.set_nonblocking(true)?;
+
+        let inbox = Arc::new(Inbox::new());
+
+        let listener_inbox = inbox.clone();
+        thread::Builder::new()
+            .name(format!("constellation-tcp-listener-{}", rank))
+            .spawn(move || listen(listener, listener_inbox))?;
+
+        Ok(TcpComm {
+            rank: rank as i32,
+            connections: peers.iter().map(|_| Mutex::new(None)).collect(),
+            peers,
+            inbox,
+        })
+    }
+
+    /// Write a frame to the given peer, connecting first if there is no
+    /// connection yet. A broken connection is reconnected once.
+    fn write_frame(&self, destination: usize, tag: i32, message: &[u8]) -> io::Result<()> {
+        let mut connection = self.connections[destination].lock().unwrap();
+
+        for _ in 0..2 {
+            if connection.is_none() {
+                *connection = Some(self.connect(destination)?);
+            }
+
+            let stream = connection.as_mut().unwrap();
+            match stream.write_all(&frame::encode(tag, message)) {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    warn!(
+                        "Connection to rank {} broken, reconnecting: {}",
+                        destination, e
+                    );
+                    *connection = None;
+                }
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::BrokenPipe,
+            format!("Could not send to rank {}", destination),
+        ))
+    }
+
+    /// Connect to the given peer and send the rank of this process
+    fn connect(&self, destination: usize) -> io::Result<TcpStream> {
+        let mut last_error = None;
+
+        for _ in 0..CONNECT_ATTEMPTS {
+            match TcpStream::connect(self.peers[destination]) {
+                Ok(mut stream) => {
+                    stream.set_nodelay(true)?;
+                    stream.write_all(&self.rank.to_be_bytes())?;
+                    return Ok(stream);
+                }
+                Err(e) => {
+                    last_error = Some(e);
+                    thread::sleep(CONNECT_INTERVAL);
+                }
+            }
+        }
+
+        Err(last_error.unwrap())
+    }
+}
+
+impl Communication for TcpComm {
+    fn rank(&self) -> i32 {
+        self.rank
+    }
+
+    fn size(&self) -> i32 {
+        self.peers.len() as i32
+    }
+
+    fn processor_name(&self) -> String {
+        self.peers[self.rank as usize].ip().to_string()
+    }
+
+    fn groups(&self) -> HashMap<i32, NodeHandler> {
+        self.peers
+            .iter()
+            .enumerate()
+            .map(|(rank, address)| {
+                (
+                    rank as i32,
+                    NodeHandler {
+                        node_name: address.ip().to_string(),
+                        node_id: rank,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn send(&self, destination: i32, tag: i32, message: &[u8]) {
+        if let Err(e) = self.write_frame(destination as usize, tag, message) {
+            warn!(
+                "Dropped message with tag {} to rank {}: {}",
+                tag, destination, e
+            );
+        }
+    }
+
+    fn try_receive(&self, tag: i32) -> Option<(i32, Vec<u8>)> {
+        self.inbox.try_receive(tag)
+    }
+
+    fn barrier(&self) {
+        for peer in 0..self.size() {
+            if peer != self.rank {
+                self.send(peer, BARRIER_TAG, &[]);
+            }
+        }
+
+        // Messages from a peer arrive in order, so the first barrier message
+        // of every peer belongs to this barrier
+        for peer in 0..self.size() {
+            if peer != self.rank {
+                self.inbox.receive_from(peer, BARRIER_TAG);
+            }
+        }
+    }
+}
+
+/// Stop listening and close all connections, so that peers notice and
+/// reconnect when a new instance is started
+impl Drop for TcpComm {
+    fn drop(&mut self) {
+        self.inbox.stop();
+    }
+}
+
+/// Accept connections from peers until stopped, every connection is read by
+/// its own thread
+fn listen(listener: TcpListener, inbox: Arc<Inbox>) {
+    while !inbox.is_stopped() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                match stream.try_clone() {
+                    Ok(clone) => inbox.on_close(Box::new(move || {
+                        clone.shutdown(Shutdown::Both).ok();
+                    })),
+                    Err(e) => warn!("Could not register connection from peer: {}", e),
+                }
+
+                let inbox = inbox.clone();
+                let spawned = thread::Builder::new()
+                    .name("constellation-tcp-reader".to_string())
+                    .spawn(move || {
+                        let read = stream
+                            .set_nonblocking(false)
+                            .and_then(|_| inbox.read_frames(stream));
+                        if let Err(e) = read {
+                            if !inbox.is_stopped() {
+                                warn!("Connection from peer closed: {}", e);
+                            }
+                        }
+                    });
+                if let Err(e) = spawned {
+                    warn!("Could not spawn thread reading from peer: {}", e);
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_INTERVAL),
+            Err(e) => warn!("Could not accept connection: {}", e),
+        }
+    }
+}
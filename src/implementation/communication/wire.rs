@@ -0,0 +1,88 @@
+///! Encoding of the control messages exchanged between the nodes, e.g. by
+///! the activity registry, the load reports and the stealing between nodes.
+///! Integers are big endian, strings and byte arrays are prefixed with their
+///! length.
+use crate::implementation::communication::node_handler::NodeHandler;
+use crate::ActivityIdentifier;
+
+pub(crate) fn put_u64(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend_from_slice(&value.to_be_bytes());
+}
+
+pub(crate) fn put_str(bytes: &mut Vec<u8>, value: &str) {
+    put_u64(bytes, value.len() as u64);
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+pub(crate) fn put_bytes(bytes: &mut Vec<u8>, value: &[u8]) {
+    put_u64(bytes, value.len() as u64);
+    bytes.extend_from_slice(value);
+}
+
+pub(crate) fn put_aid(bytes: &mut Vec<u8>, aid: &ActivityIdentifier) {
+    bytes.extend_from_slice(&aid.constellation_id.to_be_bytes());
+    put_u64(bytes, aid.node_info.node_id as u64);
+    put_str(bytes, &aid.node_info.node_name);
+    put_u64(bytes, aid.activity_id);
+}
+
+pub(crate) fn take_u64(bytes: &mut &[u8]) -> Option<u64> {
+    if bytes.len() < 8 {
+        return None;
+    }
+    let (value, rest) = bytes.split_at(8);
+    *bytes = rest;
+
+    let mut word = [0u8; 8];
+    word.copy_from_slice(value);
+    Some(u64::from_be_bytes(word))
+}
+
+pub(crate) fn take_u8(bytes: &mut &[u8]) -> Option<u8> {
+    let (value, rest) = bytes.split_first()?;
+    *bytes = rest;
+
+    Some(*value)
+}
+
+pub(crate) fn take_bytes(bytes: &mut &[u8]) -> Option<Vec<u8>> {
+    let len = take_u64(bytes)? as usize;
+    if bytes.len() < len {
+        return None;
+    }
+    let (value, rest) = bytes.split_at(len);
+    *bytes = rest;
+
+    Some(value.to_vec())
+}
+
+pub(crate) fn take_str(bytes: &mut &[u8]) -> Option<String> {
+    let len = take_u64(bytes)? as usize;
+    if bytes.len() < len {
+        return None;
+    }
+    let (value, rest) = bytes.split_at(len);
+    *bytes = rest;
+
+    String::from_utf8(value.to_vec()).ok()
+}
+
+pub(crate) fn take_aid(bytes: &mut &[u8]) -> Option<ActivityIdentifier> {
+    if bytes.len() < 4 {
+        return None;
+    }
+    let (value, rest) = bytes.split_at(4);
+    *bytes = rest;
+
+    let mut word = [0u8; 4];
+    word.copy_from_slice(value);
+
+    Some(ActivityIdentifier {
+        constellation_id: i32::from_be_bytes(word),
+        node_info: NodeHandler {
+            node_id: take_u64(bytes)? as usize,
+            node_name: take_str(bytes)?,
+        },
+        activity_id: take_u64(bytes)?,
+    })
+}
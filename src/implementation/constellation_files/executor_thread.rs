@@ -1,14 +1,73 @@
 extern crate crossbeam;
 
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use super::super::activity_wrapper::ActivityWrapperTrait;
+use crate::ack::EventAck;
 use crate::activity_identifier::ActivityIdentifier;
+use crate::clock::Clock;
+use crate::constellation_config::LifecycleHooks;
+#[cfg(feature = "fault-injection")]
+use crate::fault_injection::PickupFault;
+use crate::implementation::activity_queue::ActivityQueue;
+use crate::implementation::constellation_files::thread_helper::{ExecutorQueues, ThreadHelper};
+use crate::implementation::constellation_handle::SharedState;
+use crate::implementation::delayed_events::DelayedEvents;
 use crate::implementation::event_queue::EventQueue;
-use crate::{activity, ConstellationTrait, Event};
+use crate::implementation::execution_monitor::ExecutionMonitor;
+use crate::implementation::finished_activities::FinishedActivities;
+use crate::implementation::idle_monitor::IdleMonitor;
+use crate::implementation::object_pool;
+use crate::implementation::panic_hook;
+use crate::implementation::parker::Parker;
+use crate::implementation::pause_gate::PauseGate;
+use crate::implementation::scope_registry::{self, ScopeRegistry};
+use crate::schedule_trace::{ReplayDrain, ReplayStep, ScheduleTrace, REPLAY_STALL_TIMEOUT};
+use crate::scheduler_event::{self, SchedulerEvent};
+use crate::steal_strategy::ContextStealStrategies;
+use crate::{
+    activity, ActivityError, CompletionReason, ConstellationConfiguration, ConstellationHandle,
+    Event, SendError,
+};
 
 use crossbeam::{Receiver, Sender};
-use hashbrown::HashMap;
+
+/// Number of consecutive iterations without work after which the executor
+/// thread parks itself
+const IDLE_ITERATIONS_BEFORE_PARKING: u32 = 64;
+
+/// Maximum time an idle executor thread stays parked, it wakes up earlier
+/// when work is inserted in its queues or a delayed event is due
+const MAX_PARK_TIME: Duration = Duration::from_millis(10);
+
+/// Number of times an activity may be handed back to the load balancer, or
+/// moved by its rebalancing, so activities do not bounce between threads
+/// which take turns being idle
+pub(crate) const MAX_SHED_MIGRATIONS: u32 = 2;
+
+/// Time between two checks of all suspended activities for events. In
+/// between, only the suspended activities which received events are checked,
+/// the full check catches events inserted without being recorded as arrival.
+const SUSPENDED_SCAN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time to wait before checking again whether the next recorded step can be
+/// replayed, or the recorded events have arrived
+const REPLAY_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Signal sent by the InnerConstellation to its executor thread
+///
+/// * `Shutdown` - Shut down if all queues are empty
+/// * `ForceShutdown` - Cancel the activities left in the queues, drop the
+/// queued events and shut down
+pub enum ExecutorSignal {
+    Shutdown,
+    ForceShutdown,
+}
 
 /// The executor thread runs in asynchronously and is in charge of executing
 /// activities. It will periodically check for work/events in the Constellation
@@ -24,83 +83,341 @@ use hashbrown::HashMap;
 /// was returned). This activity is triggered by sending receiving an event.
 /// * `event_queue` - Shared queue for events containing data, executor will
 /// check this queue whenever if is expecting events
-/// * `constellation` - A reference to the InnerConstellation instance, required
-/// by the functions in the activities executed
+/// * `handle` - Handle passed to the activities executed, used by them to
+/// submit activities and send events
 /// * `receiver` - Receiving channel used to get signals from parent
-/// * `sender` - Sending channel used to signal parent
+/// * `sender` - Sending channel used to signal parent, sends the number of
+/// cancelled activities when shutting down, or None if there was work left
 /// * `thread_id` - Sending channel used to signal parent
+/// * `steal_strategy` - Decides per context whether the biggest or smallest
+/// activity in the work queue is picked first, based on the activity size
+/// hints
+/// * `scopes` - Registry of scopes, used to find cancelled activities
+/// * `scope_generation` - Generation of the scope registry when the queues
+/// were last checked for cancelled activities
+/// * `finished` - Activities which finished recently, shared with everything
+/// sending events to this thread. Events for them are dropped.
+/// * `delayed_events` - Events sent with a delay, which this thread sends when
+/// they are due. Only set when running single threaded.
+/// * `execution` - Records the activity currently running on this thread, used
+/// to detect activities exceeding their maximum execution time
+/// * `parker` - Used to park the thread when it is idle, unparked by
+/// everything inserting work in the queues of this thread
+/// * `idle_iterations` - Number of consecutive iterations without work
+/// * `idle` - Whether the thread has been without work long enough to park,
+/// and is counted as idle by the parent
+/// * `parent` - Used to hand excess activities back to the load balancer and
+/// to find out whether other threads are idle. Only set when running multi
+/// threaded.
+/// * `shed_high_watermark` - Number of activities in the work queue above
+/// which stealable activities are handed back to the load balancer, while
+/// other threads are idle
+/// * `steal_batch_size` - Maximum number of activities taken from the work
+/// queue per lock acquisition
+/// * `stolen` - Activities taken from the work queue which have not been run
+/// yet, in the order they are run
+/// * `deterministic_scheduling` - Whether ties between activities are broken
+/// by their identifiers, instead of the order of the queues
+/// * `yield_round` - Number of times an activity yielded on this thread, used
+/// to run yielded activities in the order they yielded
+/// * `hooks` - Lifecycle hooks from the configuration
+/// * `invocation_started` - When the running activity was invoked, only
+/// recorded when the on_activity_finish hook is set
+/// * `pause` - Gate closed while the constellation instance is paused, no
+/// work is picked up and no events are delivered while it is closed
+/// * `idle_monitor` - Keeps track of the threads which are working, this
+/// thread counts as working while it is not idle
+/// * `ready` - Activities which had events queued when they were suspended,
+/// checked for events with the arrivals of the event queue
+/// * `last_suspended_scan` - When all suspended activities were last checked
+/// for events
+/// * `trace` - Recording or replay of the scheduling decisions, taken from
+/// the parent
+/// * `clock` - The clock deciding when delayed events are due and when
+/// activities exceed their maximum execution time
+/// * `injected_panic` - Message of the panic injected in the activity picked
+/// up last, raised from its next invocation
+/// * `iterations` - Number of iterations of the loop, shared with the queues
+/// of the thread
 pub struct ExecutorThread {
-    work_queue: Arc<Mutex<HashMap<ActivityIdentifier, Box<dyn ActivityWrapperTrait>>>>,
-    work_suspended: Arc<Mutex<HashMap<ActivityIdentifier, Box<dyn ActivityWrapperTrait>>>>,
+    work_queue: Arc<Mutex<ActivityQueue>>,
+    work_suspended: Arc<Mutex<ActivityQueue>>,
     event_queue: Arc<Mutex<EventQueue>>,
-    constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>,
-    receiver: Receiver<bool>,
-    sender: Sender<bool>,
+    handle: ConstellationHandle,
+    receiver: Receiver<ExecutorSignal>,
+    sender: Sender<Option<usize>>,
     thread_id: i32,
+    steal_strategy: ContextStealStrategies,
+    scopes: Arc<Mutex<ScopeRegistry>>,
+    scope_generation: u64,
+    finished: Arc<Mutex<FinishedActivities>>,
+    delayed_events: Option<Arc<Mutex<DelayedEvents>>>,
+    execution: Arc<Mutex<ExecutionMonitor>>,
+    parker: Arc<Parker>,
+    idle_iterations: u32,
+    idle: bool,
+    parent: Option<ThreadHelper>,
+    shed_high_watermark: Option<usize>,
+    steal_batch_size: usize,
+    stolen: VecDeque<Box<dyn ActivityWrapperTrait>>,
+    deterministic_scheduling: bool,
+    yield_round: u64,
+    hooks: LifecycleHooks,
+    invocation_started: Option<(ActivityIdentifier, Instant)>,
+    pause: Arc<PauseGate>,
+    idle_monitor: Arc<IdleMonitor>,
+    ready: Vec<ActivityIdentifier>,
+    last_suspended_scan: Instant,
+    trace: Option<Arc<ScheduleTrace>>,
+    clock: Clock,
+    #[cfg(feature = "fault-injection")]
+    injected_panic: Option<String>,
+    iterations: Arc<AtomicU64>,
+}
+
+/// Settings of an executor thread, taken from the configuration
+///
+/// # Members
+/// * `steal_strategies` - Steal strategy of every context, used to select
+/// which activity to execute next
+/// * `shed_high_watermark` - Optional number of queued activities above
+/// which work is handed back to the load balancer, only set when running
+/// multi threaded
+/// * `steal_batch_size` - Maximum number of activities taken from the work
+/// queue at once
+/// * `deterministic_scheduling` - Whether to break ties between activities,
+/// and order suspended activities, by their identifiers
+/// * `hooks` - Lifecycle hooks to call
+/// * `clock` - The clock of the constellation instance, advancing a manual
+/// clock wakes up the thread
+#[derive(Clone)]
+pub struct ExecutorSettings {
+    pub steal_strategies: ContextStealStrategies,
+    pub shed_high_watermark: Option<usize>,
+    pub steal_batch_size: usize,
+    pub deterministic_scheduling: bool,
+    pub hooks: LifecycleHooks,
+    pub clock: Clock,
+}
+
+impl ExecutorSettings {
+    /// Take the settings of an executor thread from the configuration
+    ///
+    /// # Arguments
+    /// * `config` - The configuration
+    /// * `multi_threaded` - Whether the thread is part of a multi threaded
+    /// instance, only those hand work back to the load balancer
+    pub fn new(config: &ConstellationConfiguration, multi_threaded: bool) -> ExecutorSettings {
+        ExecutorSettings {
+            steal_strategies: ContextStealStrategies::new(
+                config.local_steal_strategy.clone(),
+                config.steal_strategy_overrides.clone(),
+            ),
+            shed_high_watermark: if multi_threaded {
+                config.shed_high_watermark
+            } else {
+                None
+            },
+            steal_batch_size: config.steal_batch_size,
+            deterministic_scheduling: config.deterministic_scheduling,
+            hooks: config.hooks.clone(),
+            clock: config.clock.clone(),
+        }
+    }
 }
 
 impl ExecutorThread {
     /// Create a new ExecutorThread
     ///
     /// # Arguments
-    /// * `multi_threaded` - Boolean indicating whether this executor is part
-    /// of multi threaded constellation or not. This is used to indicate
-    /// whether to push suspended events/activities to the parent or locally.
-    /// * `work_queue` - Injector queue of ActivityWrapperTraits which
-    /// is shared with constellation instance
-    /// * `event_queue` - Same as work_queue but for events
-    /// * `constellation` - Shared constellation which can be used when
-    /// processing activities
+    /// * `queues` - The queues of this thread, shared with the constellation
+    /// instance and with everything inserting work in them
+    /// * `shared` - The tables shared by all threads of the constellation
+    /// instance. Delayed events are only sent by this thread when running
+    /// single threaded.
+    /// * `settings` - Settings taken from the configuration
+    /// * `handle` - Handle passed to the activities when processing them
+    /// * `parent` - Link to the load balancer, None when running single
+    /// threaded
+    /// * `receiver` - Receiving channel used to get signals from the
+    /// constellation instance
+    /// * `sender` - Sending channel used to signal the constellation instance
     ///
     /// # Returns
     /// * `ExecutorThread` - New executor thread which asynchronously processes
     /// events
     pub fn new(
-        work_queue: Arc<Mutex<HashMap<ActivityIdentifier, Box<dyn ActivityWrapperTrait>>>>,
-        work_suspended: Arc<Mutex<HashMap<ActivityIdentifier, Box<dyn ActivityWrapperTrait>>>>,
-        event_queue: Arc<Mutex<EventQueue>>,
-        constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>,
-        receiver: Receiver<bool>,
-        sender: Sender<bool>,
-        thread_id: i32,
+        queues: &ExecutorQueues,
+        shared: &SharedState,
+        settings: ExecutorSettings,
+        handle: ConstellationHandle,
+        parent: Option<ThreadHelper>,
+        receiver: Receiver<ExecutorSignal>,
+        sender: Sender<Option<usize>>,
     ) -> ExecutorThread {
+        // Delayed events are routed by the parent when multi threaded
+        let delayed_events = match parent {
+            Some(_) => None,
+            None => Some(shared.delayed_events.clone()),
+        };
+        let idle_monitor = shared.idle_monitor.clone();
+        let parker = queues.parker.clone();
+        let clock = settings.clock;
+
+        // The thread starts out working, until it found no work
+        idle_monitor.set_busy();
+        let trace = parent.as_ref().and_then(|p| p.schedule_trace());
+        clock.wake_on_advance(&parker);
+
         ExecutorThread {
-            work_queue,
-            work_suspended,
-            event_queue,
-            constellation,
+            work_queue: queues.activities.clone(),
+            work_suspended: queues.activities_suspended.clone(),
+            event_queue: queues.event_queue.clone(),
+            thread_id: handle.thread_id(),
+            handle,
             receiver,
             sender,
-            thread_id,
+            steal_strategy: settings.steal_strategies,
+            scopes: shared.scopes.clone(),
+            scope_generation: 0,
+            finished: shared.finished.clone(),
+            delayed_events,
+            execution: queues.execution.clone(),
+            parker,
+            idle_iterations: 0,
+            idle: false,
+            parent,
+            shed_high_watermark: settings.shed_high_watermark,
+            steal_batch_size: settings.steal_batch_size.max(1),
+            stolen: VecDeque::new(),
+            deterministic_scheduling: settings.deterministic_scheduling,
+            yield_round: 0,
+            hooks: settings.hooks,
+            invocation_started: None,
+            pause: shared.pause.clone(),
+            idle_monitor,
+            ready: Vec::new(),
+            last_suspended_scan: Instant::now(),
+            trace,
+            clock,
+            #[cfg(feature = "fault-injection")]
+            injected_panic: None,
+            iterations: queues.iterations.clone(),
         }
     }
 
     /// Tries to steal a batch of work from the shared work_queue. If there is
     /// work, it will return one of the stolen jobs, which is to be
-    /// executed immediately.
+    /// executed immediately. The job with the highest priority is picked,
+    /// ties are broken by the steal strategy of their contexts comparing the
+    /// size hints of the activities. Remaining ties are broken by picking the
+    /// activity submitted first, or the smallest identifier with
+    /// deterministic scheduling. Activities submitted at the same instant are
+    /// picked in the order they were queued, see ActivityQueue. Activities
+    /// which yielded are only picked when no activity which did not yield is
+    /// left, the one which yielded first is picked first.
+    ///
+    /// Up to `steal_batch_size` activities are taken at once, the others are
+    /// returned by the next calls, before the work queue is checked again.
     ///
     /// # Returns
     /// * `Option<Box<dyn ActivityWrapperTrait>>` - If there is work, it will
     /// pop one job from the local queue and return that wrapped in Some(..)
     fn check_for_work(&mut self) -> Option<Box<dyn ActivityWrapperTrait>> {
+        if let Some(activity) = self.stolen.pop_front() {
+            return Some(activity);
+        }
+
+        if self.work_queue.lock().unwrap().is_empty() {
+            return None;
+        }
+        self.set_idle(false);
+
         let mut guard = self.work_queue.lock().unwrap();
         if guard.is_empty() {
             drop(guard);
             return None;
         }
 
-        let mut key = None;
-        let mut activity: Option<Box<dyn ActivityWrapperTrait>> = None;
+        let strategies = &self.steal_strategy;
+        let size_rank =
+            |a: &Box<dyn ActivityWrapperTrait>| strategies.size_rank(a.context(), a.size());
+
+        if self.deterministic_scheduling {
+            let mut keys: Vec<(ActivityIdentifier, u64, i32, i64)> = guard
+                .iter()
+                .map(|(k, a)| (k.clone(), a.yield_round(), a.priority(), size_rank(a)))
+                .collect();
 
-        let mut it = guard.keys().take(1).map(|x| key = Some(x.clone()));
-        it.next();
+            keys.sort_by(|a, b| (a.1, b.2, a.3).cmp(&(b.1, a.2, b.3)).then(a.0.cmp(&b.0)));
 
-        if key.is_some() {
-            activity = guard.remove(&key.unwrap());
+            for (key, _, _, _) in keys.into_iter().take(self.steal_batch_size) {
+                if let Some(activity) = guard.remove(&key) {
+                    self.stolen.push_back(activity);
+                }
+            }
+            drop(guard);
+
+            self.record_steal(self.stolen.len());
+            return self.stolen.pop_front();
+        }
+
+        if self.steal_batch_size == 1 {
+            let mut activity: Option<Box<dyn ActivityWrapperTrait>> = None;
+
+            let key = guard
+                .iter()
+                .min_by_key(|(_, a)| {
+                    (
+                        a.yield_round(),
+                        Reverse(a.priority()),
+                        size_rank(a),
+                        a.submitted_at(),
+                    )
+                })
+                .map(|(k, _)| k.clone());
+
+            if key.is_some() {
+                activity = guard.remove(&key.unwrap());
+            }
+            drop(guard);
+
+            self.record_steal(activity.is_some() as usize);
+            return activity;
+        }
+
+        let mut keys: Vec<(ActivityIdentifier, u64, i32, i64, Instant)> = guard
+            .iter()
+            .map(|(k, a)| {
+                let rank = size_rank(a);
+                (
+                    k.clone(),
+                    a.yield_round(),
+                    a.priority(),
+                    rank,
+                    a.submitted_at(),
+                )
+            })
+            .collect();
+
+        keys.sort_by_key(|&(_, y, p, s, t)| (y, Reverse(p), s, t));
+
+        for (key, _, _, _, _) in keys.into_iter().take(self.steal_batch_size) {
+            if let Some(activity) = guard.remove(&key) {
+                self.stolen.push_back(activity);
+            }
         }
         drop(guard);
 
-        activity
+        self.record_steal(self.stolen.len());
+        self.stolen.pop_front()
+    }
+
+    /// Record a steal from the work queue in the statistics of the parent
+    fn record_steal(&self, items: usize) {
+        if let Some(parent) = &self.parent {
+            parent.record_steal(self.thread_id, items);
+        }
     }
 
     /// Executes a stolen activity. It starts with the initialize(..) function,
@@ -111,56 +428,617 @@ impl ExecutorThread {
     /// can then be re-activated by receiving an event.
     ///
     /// When an activity is re-activated with an event, it will start with
-    /// the *process* function
+    /// the *process* function. The same holds for an activity which yielded,
+    /// which is called with the events queued for it, if any.
     ///
     /// # Arguments
     /// * `activity` - A boxed activity to perform work on.
     fn run_activity(&mut self, mut activity: Box<dyn ActivityWrapperTrait>) {
         let aid = activity.activity_identifier().clone();
 
-        // Initialize
-        match activity.initialize(self.constellation.clone(), &aid) {
-            activity::State::SUSPEND => {
-                // Activity must suspend, add to suspended queue and
-                // stop processing
+        if self.is_cancelled(&activity) {
+            self.complete(&mut activity, CompletionReason::Cancelled);
+            self.drop_cancelled(aid);
+            return;
+        }
+        #[cfg(feature = "fault-injection")]
+        {
+            activity = match self.inject_fault(activity) {
+                Some(activity) => activity,
+                None => return,
+            };
+        }
+        if let Some(trace) = &self.trace {
+            trace.step(self.thread_id, &aid);
+        }
 
-                self.work_suspended.lock().unwrap().insert(aid, activity);
-                return;
+        scope_registry::set_current_scope(activity.scope());
+
+        // An activity which yielded has been initialized already
+        let yielded = activity.yield_round() > 0;
+        let mut drain_only = false;
+
+        if !yielded {
+            // Initialize
+            let injected = self.take_injected_panic();
+            self.start_execution(&activity);
+            let state = Self::invoke(&mut activity, |a| {
+                Self::raise_injected_panic(injected);
+                a.initialize(&self.handle, &aid)
+            });
+            self.finish_execution(&mut activity);
+            let state = match state {
+                Ok(state) => state,
+                Err(message) => {
+                    self.retry_or_fail(aid, activity, message, false);
+                    return;
+                }
+            };
+
+            match state {
+                activity::State::SUSPEND => {
+                    // Activity must suspend, add to suspended queue and
+                    // stop processing
+
+                    self.suspend(aid, activity);
+                    return;
+                }
+                activity::State::YIELD => {
+                    self.yield_activity(aid, activity);
+                    return;
+                }
+                activity::State::FINISH => {}
+                activity::State::FINISH_AFTER_DRAIN => drain_only = true,
             }
-            activity::State::FINISH => {}
         }
 
-        let mut event: Option<Box<Event>> = None;
+        let mut events: Vec<Box<Event>> = Vec::new();
 
         if activity.expects_event() {
-            event = self.event_queue.lock().unwrap().remove(aid.clone());
-            if event.is_none() {
-                self.work_suspended.lock().unwrap().insert(aid, activity);
+            events = self.drain_events(&aid);
+            if events.is_empty() && drain_only {
+                // Nothing to drain, it would never be woken up again
+                self.finish(aid, activity);
                 return;
             }
+            if events.is_empty() && !yielded {
+                self.suspend(aid, activity);
+                return;
+            }
+        }
+
+        self.process(activity, events);
+    }
+
+    /// Run the activity picked up through the fault injector, if there is
+    /// one. The activity may be handed back to the load balancer, or its next
+    /// invocation panics.
+    ///
+    /// # Returns
+    /// * `Option<Box<dyn ActivityWrapperTrait>>` - The activity, None if it
+    /// migrated
+    #[cfg(feature = "fault-injection")]
+    fn inject_fault(
+        &mut self,
+        activity: Box<dyn ActivityWrapperTrait>,
+    ) -> Option<Box<dyn ActivityWrapperTrait>> {
+        let injector = match self.handle.fault_injector() {
+            Some(injector) => injector.clone(),
+            None => return Some(activity),
+        };
+
+        let migrations = self.parent.as_ref().map(|_| activity.migrations());
+        match injector.inject_pickup(activity.context().label(), migrations) {
+            PickupFault::Run => Some(activity),
+            PickupFault::Migrate => {
+                // Only activities of a multithreaded instance migrate
+                if let Some(parent) = &self.parent {
+                    parent.shed(self.thread_id, vec![activity], &self.event_queue);
+                }
+                None
+            }
+            PickupFault::Panic => {
+                // Raised inside the invocation, so the activity is retired
+                // like any activity which panicked
+                let aid = activity.activity_identifier();
+                self.injected_panic = Some(format!("Activity {} panicked by fault injector", aid));
+                Some(activity)
+            }
         }
+    }
+
+    /// Take the panic injected in the activity picked up last, if any
+    #[cfg(feature = "fault-injection")]
+    fn take_injected_panic(&mut self) -> Option<String> {
+        self.injected_panic.take()
+    }
 
-        self.process(activity, event);
+    #[cfg(not(feature = "fault-injection"))]
+    fn take_injected_panic(&mut self) -> Option<String> {
+        None
+    }
+
+    /// Raise the panic taken by `take_injected_panic()`, called from inside
+    /// the invocation of the activity
+    fn raise_injected_panic(injected: Option<String>) {
+        if let Some(message) = injected {
+            panic!("{}", message);
+        }
     }
 
     /// Start the process function on an activity and handle return value
     /// appropriately (can be suspend or finish). Upon finish, the cleanup
     /// function will be called on the activity.
-    fn process(&mut self, mut activity: Box<dyn ActivityWrapperTrait>, e: Option<Box<Event>>) {
+    ///
+    /// If more than one event is passed to an activity which opted in with
+    /// `batch_events()`, they are all delivered in a single call to
+    /// `process_batch(..)`. Otherwise they are delivered one per call to
+    /// `process(..)`, as if the activity was reactivated between them, and
+    /// the events left when it finishes or yields are queued again.
+    /// Acknowledgements of the events are signalled once the activity
+    /// returns, as failed if it panicked.
+    ///
+    /// When the activity returns FINISH_AFTER_DRAIN, it is processed again
+    /// with the events queued for it until none are left, after which it is
+    /// cleaned up.
+    fn process(&mut self, mut activity: Box<dyn ActivityWrapperTrait>, events: Vec<Box<Event>>) {
         let aid = activity.activity_identifier().clone();
+        let batch = activity.batch_events();
+        let mut pending = VecDeque::from(events);
+        let mut draining = false;
 
-        match activity.process(self.constellation.clone(), e, &aid) {
-            activity::State::SUSPEND => {
-                // Activity must suspend, add to suspended queue and
-                // stop processing
-                self.work_suspended.lock().unwrap().insert(aid, activity);
-                return;
+        scope_registry::set_current_scope(activity.scope());
+
+        loop {
+            let mut events: Vec<Box<Event>> = if batch {
+                pending.drain(..).collect()
+            } else {
+                pending.pop_front().into_iter().collect()
+            };
+            let acks: Vec<EventAck> = events.iter_mut().filter_map(|e| e.take_ack()).collect();
+
+            if let Some(hook) = &self.hooks.on_event_delivered {
+                for e in events.iter() {
+                    hook(e.get_id(), &aid);
+                }
+            }
+            if let (Some(parent), false) = (&self.parent, events.is_empty()) {
+                parent.record_delivered(events.len());
             }
-            activity::State::FINISH => {
-                // Cleanup activity
-                activity.cleanup(self.constellation.clone());
+
+            let injected = self.take_injected_panic();
+            self.start_execution(&activity);
+            let state = Self::invoke(&mut activity, |a| {
+                Self::raise_injected_panic(injected);
+                if events.len() > 1 {
+                    a.process_batch(&self.handle, events, &aid)
+                } else {
+                    a.process(&self.handle, events.pop(), &aid)
+                }
+            });
+            self.finish_execution(&mut activity);
+
+            let state = match state {
+                Ok(state) => {
+                    for ack in acks {
+                        ack.consumed();
+                    }
+                    state
+                }
+                Err(message) => {
+                    for ack in acks {
+                        ack.failed();
+                    }
+                    self.requeue_events(&aid, pending);
+                    self.retry_or_fail(aid, activity, message, true);
+                    return;
+                }
+            };
+
+            match state {
+                // Deliver the next event right away
+                activity::State::SUSPEND if !pending.is_empty() => {}
+                activity::State::FINISH_AFTER_DRAIN if !pending.is_empty() => draining = true,
+                activity::State::SUSPEND if !draining => {
+                    // Activity must suspend, add to suspended queue and
+                    // stop processing
+                    self.suspend(aid, activity);
+                    return;
+                }
+                activity::State::YIELD => {
+                    self.requeue_events(&aid, pending);
+                    self.yield_activity(aid, activity);
+                    return;
+                }
+                activity::State::FINISH => {
+                    if !pending.is_empty() {
+                        warn!(
+                            "Activity {} finished with {} unprocessed events, dropping them",
+                            aid,
+                            pending.len()
+                        );
+                    }
+                    self.requeue_events(&aid, pending);
+                    break;
+                }
+                activity::State::SUSPEND | activity::State::FINISH_AFTER_DRAIN => {
+                    // Keep processing until no events are queued
+                    pending.extend(self.drain_events(&aid));
+                    if pending.is_empty() {
+                        break;
+                    }
+                    draining = true;
+                }
             }
         }
+
+        self.finish(aid, activity);
+    }
+
+    /// Queue events taken for an activity again, in front of the events
+    /// which arrived since
+    fn requeue_events(&self, aid: &ActivityIdentifier, events: VecDeque<Box<Event>>) {
+        self.event_queue
+            .lock()
+            .unwrap()
+            .requeue(aid.clone(), events);
+    }
+
+    /// Call the cleanup function on an activity which finished and record
+    /// that it completed.
+    fn finish(&mut self, aid: ActivityIdentifier, mut activity: Box<dyn ActivityWrapperTrait>) {
+        let injected = self.take_injected_panic();
+        self.start_execution(&activity);
+        let cleaned = Self::invoke(&mut activity, |a| {
+            Self::raise_injected_panic(injected);
+            a.cleanup(&self.handle)
+        });
+        self.finish_execution(&mut activity);
+        if let Err(message) = cleaned {
+            self.fail(aid, activity, message);
+            return;
+        }
+
+        let reason = if activity.timed_out() {
+            CompletionReason::TimedOut
+        } else {
+            CompletionReason::Finished
+        };
+        self.complete(&mut activity, reason);
+
+        self.record_finished(aid);
+        activity.recycle();
+    }
+
+    /// Record that an activity finished and drop the events which arrived for
+    /// it after it was last processed. The lock on the finished activities is
+    /// held meanwhile, so events sent to the activity either arrived before
+    /// and are dropped here, or are dropped by the sender.
+    fn record_finished(&mut self, aid: ActivityIdentifier) {
+        let mut finished = self.finished.lock().unwrap();
+        let events = self.event_queue.lock().unwrap().drain_for(&aid);
+        finished.record_dropped(events.len());
+        for e in events {
+            object_pool::release_event(e);
+        }
+        self.handle.forget_activity(&aid);
+        scheduler_event::observe(self.handle.observer(), || SchedulerEvent::Finished {
+            aid: aid.clone(),
+        });
+        finished.record(aid);
+    }
+
+    /// Add an activity to the suspended queue, where it waits for events. If
+    /// events are queued for it already, it is checked again immediately.
+    fn suspend(&mut self, aid: ActivityIdentifier, mut activity: Box<dyn ActivityWrapperTrait>) {
+        activity.set_suspended_at(Instant::now());
+        if let Some(hook) = &self.hooks.on_activity_suspend {
+            hook(&aid);
+        }
+
+        if self.event_queue.lock().unwrap().count_for(&aid) > 0 {
+            self.ready.push(aid.clone());
+        }
+        self.work_suspended.lock().unwrap().insert(aid, activity);
+    }
+
+    /// Put an activity which yielded back in the work queue, behind the
+    /// activities which did not yield
+    fn yield_activity(
+        &mut self,
+        aid: ActivityIdentifier,
+        mut activity: Box<dyn ActivityWrapperTrait>,
+    ) {
+        self.yield_round += 1;
+        activity.set_yield_round(self.yield_round);
+
+        if let Some(parent) = &self.parent {
+            parent.record_yield();
+        }
+
+        self.work_queue.lock().unwrap().insert(aid, activity);
+    }
+
+    /// Record that the activity is about to be invoked, so that the watchdog
+    /// can detect it exceeding its maximum execution time
+    fn start_execution(&mut self, activity: &Box<dyn ActivityWrapperTrait>) {
+        let aid = activity.activity_identifier();
+
+        panic_hook::set_current_activity(Some(aid.clone()));
+        self.execution.lock().unwrap().start(
+            aid.clone(),
+            activity.max_execution_time(),
+            self.clock.now(),
+        );
+
+        if let Some(hook) = &self.hooks.on_activity_start {
+            hook(aid, self.thread_id);
+        }
+        if self.hooks.on_activity_finish.is_some() {
+            self.invocation_started = Some((aid.clone(), Instant::now()));
+        }
+    }
+
+    /// Record that the activity invoked last has returned. When it exceeded
+    /// its maximum execution time for the first time, it is marked as timed
+    /// out and told, see `ActivityTrait::on_error(..)`.
+    fn finish_execution(&mut self, activity: &mut Box<dyn ActivityWrapperTrait>) {
+        panic_hook::set_current_activity(None);
+        let finished = self.execution.lock().unwrap().finish(self.clock.now());
+        if let Some((aid, elapsed)) = finished {
+            warn!(
+                "Activity {} returned on thread {} after {:?}, exceeding its maximum \
+                 execution time",
+                aid, self.thread_id, elapsed
+            );
+            if !activity.timed_out() {
+                activity.set_timed_out();
+                let error = ActivityError::TimedOut {
+                    limit: activity.max_execution_time().unwrap_or_default(),
+                    elapsed,
+                };
+                self.report_error(activity, &error);
+            }
+        }
+
+        if let Some(hook) = &self.hooks.on_activity_finish {
+            if let Some((aid, started)) = self.invocation_started.take() {
+                hook(&aid, self.thread_id, started.elapsed());
+            }
+        }
+    }
+
+    /// Invoke a method of an activity, catching a panic of the activity so
+    /// it does not take down the executor thread
+    ///
+    /// # Returns
+    /// * `Result<T, String>` - The result of the method, the panic message if
+    /// it panicked
+    fn invoke<T, F>(activity: &mut Box<dyn ActivityWrapperTrait>, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&mut Box<dyn ActivityWrapperTrait>) -> T,
+    {
+        panic::catch_unwind(AssertUnwindSafe(|| f(activity)))
+            .map_err(|payload| panic_hook::panic_message(payload.as_ref()))
+    }
+
+    /// Run an activity which panicked in `initialize(..)` or `process(..)`
+    /// again if it has retries left, see `SubmitOptions::retries`, otherwise
+    /// retire it. It is told what went wrong either way.
+    ///
+    /// # Arguments
+    /// * `aid` - Identifier of the activity
+    /// * `activity` - The activity
+    /// * `message` - The panic message
+    /// * `initialized` - Whether it panicked after it was initialized, it
+    /// then runs again like an activity which yielded
+    fn retry_or_fail(
+        &mut self,
+        aid: ActivityIdentifier,
+        mut activity: Box<dyn ActivityWrapperTrait>,
+        message: String,
+        initialized: bool,
+    ) {
+        if activity.failures() >= activity.retries() {
+            self.fail(aid, activity, message);
+            return;
+        }
+
+        activity.record_failure();
+        warn!(
+            "Activity {} panicked on thread {}, running it again (retry {} of {}): {}",
+            aid,
+            self.thread_id,
+            activity.failures(),
+            activity.retries(),
+            message
+        );
+        self.report_error(&mut activity, &ActivityError::Panicked(message));
+
+        if initialized {
+            self.yield_activity(aid, activity);
+        } else {
+            self.work_queue.lock().unwrap().insert(aid, activity);
+        }
+    }
+
+    /// Retire an activity which panicked: it is told what went wrong and that
+    /// it failed, and is recorded as finished without calling its cleanup
+    fn fail(
+        &mut self,
+        aid: ActivityIdentifier,
+        mut activity: Box<dyn ActivityWrapperTrait>,
+        message: String,
+    ) {
+        warn!(
+            "Activity {} panicked on thread {}, retiring it: {}",
+            aid, self.thread_id, message
+        );
+
+        self.report_error(&mut activity, &ActivityError::Panicked(message.clone()));
+        self.complete(&mut activity, CompletionReason::Failed(message));
+
+        self.record_finished(aid);
+        activity.recycle();
+    }
+
+    /// Tell an activity about an error, see `ActivityTrait::on_error(..)`. A
+    /// panic of the hook is logged.
+    fn report_error(&self, activity: &mut Box<dyn ActivityWrapperTrait>, error: &ActivityError) {
+        let aid = activity.activity_identifier().clone();
+
+        panic_hook::set_current_activity(Some(aid.clone()));
+        let reported = Self::invoke(activity, |a| a.on_error(&self.handle, error, &aid));
+        panic_hook::set_current_activity(None);
+
+        if let Err(message) = reported {
+            warn!("Error hook of activity {} panicked: {}", aid, message);
+        }
+    }
+
+    /// Tell an activity why it ended, this is the last call on the activity.
+    /// A panic of the hook is logged.
+    fn complete(&self, activity: &mut Box<dyn ActivityWrapperTrait>, reason: CompletionReason) {
+        let aid = activity.activity_identifier().clone();
+
+        panic_hook::set_current_activity(Some(aid.clone()));
+        let completed = Self::invoke(activity, |a| a.on_complete(reason));
+        panic_hook::set_current_activity(None);
+
+        if let Err(message) = completed {
+            warn!("Completion hook of activity {} panicked: {}", aid, message);
+        }
+    }
+
+    /// Check whether the scope of the activity has been cancelled
+    fn is_cancelled(&self, activity: &Box<dyn ActivityWrapperTrait>) -> bool {
+        match activity.scope() {
+            Some(scope) => self.scopes.lock().unwrap().is_cancelled(scope),
+            None => false,
+        }
+    }
+
+    /// Drop the events of an activity whose scope was cancelled, and make sure
+    /// events sent to it later are dropped as well
+    fn drop_cancelled(&mut self, aid: ActivityIdentifier) {
+        let events = self.event_queue.lock().unwrap().drain_for(&aid);
+        for e in events {
+            object_pool::release_event(e);
+        }
+        self.handle.forget_activity(&aid);
+        self.scopes.lock().unwrap().record_cancelled_activity(aid);
+    }
+
+    /// Remove all activities in cancelled scopes from the work queue and the
+    /// suspended queue, this is only done when a scope has been cancelled
+    /// since the last check.
+    fn remove_cancelled_work(&mut self) {
+        let generation = self.scopes.lock().unwrap().generation();
+        if generation == self.scope_generation {
+            return;
+        }
+        self.scope_generation = generation;
+
+        for queue in [self.work_queue.clone(), self.work_suspended.clone()].iter() {
+            let mut guard = queue.lock().unwrap();
+            let cancelled: Vec<ActivityIdentifier> = guard
+                .iter()
+                .filter(|(_, a)| self.is_cancelled(a))
+                .map(|(k, _)| k.clone())
+                .collect();
+
+            let removed: Vec<_> = cancelled
+                .into_iter()
+                .filter_map(|aid| guard.remove(&aid).map(|activity| (aid, activity)))
+                .collect();
+            // Released first, submit_named(..) holds the activity names while
+            // it places an activity
+            drop(guard);
+
+            for (aid, mut activity) in removed {
+                self.complete(&mut activity, CompletionReason::Cancelled);
+                self.drop_cancelled(aid);
+            }
+        }
+    }
+
+    /// Send all delayed events which are due, through the handle so they are
+    /// routed as normal events
+    fn send_delayed_events(&mut self) {
+        let events = match &self.delayed_events {
+            Some(delayed) => delayed.lock().unwrap().pop_due(),
+            None => return,
+        };
+        if !events.is_empty() {
+            self.set_idle(false);
+        }
+
+        for e in events {
+            match self.handle.send_boxed(e) {
+                // The activity may finish before its delayed event is due,
+                // e.g. a timeout it no longer needs
+                Ok(()) | Err(SendError::DestinationFinished(_)) => {}
+                Err(err) => warn!("Could not send delayed event: {}", err),
+            }
+        }
+    }
+
+    /// Cancel the activities left in the queues when shutting down forcefully.
+    /// Activities which are suspended or have yielded are cancelled, see
+    /// `ActivityTrait::cancelled(..)`. Activities which did not start are
+    /// dropped, as are all queued events. Every activity is told it ended
+    /// because of the shutdown.
+    ///
+    /// # Returns
+    /// * `usize` - Number of cancelled activities
+    fn cancel_remaining_work(&mut self) -> usize {
+        let mut queued: Vec<Box<dyn ActivityWrapperTrait>> = self.stolen.drain(..).collect();
+        queued.extend(self.work_queue.lock().unwrap().drain().map(|(_, a)| a));
+
+        let (mut started, not_started): (Vec<_>, Vec<_>) =
+            queued.into_iter().partition(|a| a.yield_round() > 0);
+        started.extend(self.work_suspended.lock().unwrap().drain().map(|(_, a)| a));
+        if self.deterministic_scheduling {
+            started.sort_by(|a, b| a.activity_identifier().cmp(b.activity_identifier()));
+        }
+
+        let events = self.event_queue.lock().unwrap().clear();
+
+        warn!(
+            "Shutting down thread {} forcefully, cancelling {} activities, dropping {} \
+             activities which did not start and {} events",
+            self.thread_id,
+            started.len(),
+            not_started.len(),
+            events
+        );
+
+        let cancelled = started.len();
+        for mut activity in started {
+            scope_registry::set_current_scope(activity.scope());
+            self.start_execution(&activity);
+            let result = Self::invoke(&mut activity, |a| a.cancelled(&self.handle));
+            self.finish_execution(&mut activity);
+            match result {
+                Ok(()) => self.complete(&mut activity, CompletionReason::Shutdown),
+                Err(message) => {
+                    let error = ActivityError::Panicked(message.clone());
+                    self.report_error(&mut activity, &error);
+                    self.complete(&mut activity, CompletionReason::Failed(message));
+                }
+            }
+        }
+        for mut activity in not_started {
+            scope_registry::set_current_scope(activity.scope());
+            self.complete(&mut activity, CompletionReason::Shutdown);
+        }
+        scope_registry::set_current_scope(None);
+
+        // Drop what the cancelled activities submitted or sent locally
+        self.work_queue.lock().unwrap().clear();
+        self.event_queue.lock().unwrap().clear();
+
+        cancelled
     }
 
     /// Returns whether there is something left in the queues
@@ -170,7 +1048,8 @@ impl ExecutorThread {
     ///     - true: There are remaining items
     ///     - false: THere are no remaining items
     pub fn queues_empty(&self) -> bool {
-        if self.work_queue.lock().unwrap().is_empty()
+        if self.stolen.is_empty()
+            && self.work_queue.lock().unwrap().is_empty()
             && self.work_suspended.lock().unwrap().is_empty()
             && self.event_queue.lock().unwrap().is_empty()
         {
@@ -180,66 +1059,332 @@ impl ExecutorThread {
         false
     }
 
-    fn check_suspended_work(&mut self) {
-        let keys: Vec<ActivityIdentifier> = self
-            .work_suspended
-            .lock()
-            .unwrap()
-            .keys()
-            .map(|x| x.clone())
-            .collect();
+    /// Process suspended activities for which events have arrived. Only the
+    /// destinations of the events which arrived since the last check are
+    /// looked up, so the cost does not depend on the number of suspended
+    /// activities. Once every SUSPENDED_SCAN_INTERVAL all suspended
+    /// activities are checked instead.
+    ///
+    /// # Returns
+    /// * `bool` - true if at least one activity was processed
+    fn check_suspended_work(&mut self) -> bool {
+        let mut processed = false;
+        let mut keys = self.event_queue.lock().unwrap().take_arrivals();
+        keys.append(&mut self.ready);
+
+        {
+            let suspended = self.work_suspended.lock().unwrap();
+            if suspended.is_empty() {
+                return false;
+            }
+
+            if self.last_suspended_scan.elapsed() >= SUSPENDED_SCAN_INTERVAL {
+                self.last_suspended_scan = Instant::now();
+                keys = suspended.keys().cloned().collect();
+            } else {
+                keys.retain(|key| suspended.contains_key(key));
+            }
+        }
+
+        if self.deterministic_scheduling {
+            keys.sort();
+            keys.dedup();
+        }
         for key in keys {
-            let event = self.event_queue.lock().unwrap().remove(key.clone());
+            if self.event_queue.lock().unwrap().contains_key(&key) {
+                self.set_idle(false);
+            }
+            let events = self.event_queue.lock().unwrap().drain_for(&key);
 
-            if event.is_some() {
-                // We have received the event!
+            if !events.is_empty() {
+                // We have received the event(s)!
                 let activity = self.work_suspended.lock().unwrap().remove(&key);
                 if activity.is_some() {
-                    self.process(activity.unwrap(), event);
+                    if let Some(trace) = &self.trace {
+                        trace.step(self.thread_id, &key);
+                        trace.drained(&key, &events);
+                    }
+                    self.process(activity.unwrap(), events);
+                    processed = true;
                 } else {
                     // For thread safety
-                    self.event_queue.lock().unwrap().insert(key, event.unwrap());
+                    let mut guard = self.event_queue.lock().unwrap();
+                    for event in events {
+                        guard.insert(key.clone(), event);
+                    }
+                }
+            }
+        }
+
+        processed
+    }
+
+    /// Take the events queued for an activity. When a schedule is replayed,
+    /// this waits until the events the activity received before are queued,
+    /// and takes only those. When a schedule is recorded, the events taken
+    /// are recorded.
+    fn drain_events(&mut self, aid: &ActivityIdentifier) -> Vec<Box<Event>> {
+        let trace = match &self.trace {
+            Some(trace) => trace.clone(),
+            None => return self.event_queue.lock().unwrap().drain_for(aid),
+        };
+
+        let waiting_since = Instant::now();
+        loop {
+            match trace.expected_events(aid) {
+                ReplayDrain::Any => {
+                    let events = self.event_queue.lock().unwrap().drain_for(aid);
+                    trace.drained(aid, &events);
+                    return events;
                 }
+                ReplayDrain::Recorded(ids) => {
+                    // The load balancer keeps the events routed to the
+                    // activity while it is outside of the queues
+                    let kept = match &self.parent {
+                        Some(parent) => parent.take_kept_events(aid),
+                        None => Vec::new(),
+                    };
+
+                    let mut event_queue = self.event_queue.lock().unwrap();
+                    for e in kept {
+                        event_queue.insert(aid.clone(), e);
+                    }
+                    if let Some(events) = event_queue.take_events(aid, &ids) {
+                        drop(event_queue);
+                        trace.events_received(aid);
+                        return events;
+                    }
+                }
+                ReplayDrain::NotSent => {}
+            }
+
+            if waiting_since.elapsed() >= REPLAY_STALL_TIMEOUT {
+                trace.stop(&format!(
+                    "{} did not receive the events it received before within {:?}",
+                    aid, REPLAY_STALL_TIMEOUT
+                ));
+            } else {
+                trace.wait(REPLAY_POLL_INTERVAL);
             }
         }
     }
 
+    /// Start the next recorded step of the schedule being replayed, if it is
+    /// the turn of this thread and the activity is in its queues. Otherwise
+    /// wait a little for the other threads.
+    fn replay_step(&mut self, trace: &Arc<ScheduleTrace>) {
+        let aid = match trace.next_step(self.thread_id) {
+            ReplayStep::Run(aid) => aid,
+            ReplayStep::Wait => return trace.wait(REPLAY_POLL_INTERVAL),
+            ReplayStep::Finished => return,
+        };
+
+        let activity = self.work_queue.lock().unwrap().remove(&aid);
+        if let Some(activity) = activity {
+            trace.step_started();
+            return self.run_activity(activity);
+        }
+
+        let activity = self.work_suspended.lock().unwrap().remove(&aid);
+        match activity {
+            Some(activity) => {
+                trace.step_started();
+                let events = self.drain_events(&aid);
+                self.process(activity, events);
+            }
+            // Not placed on this thread yet, or still being processed
+            None => trace.wait(REPLAY_POLL_INTERVAL),
+        }
+    }
+
+    /// Record whether this thread is idle, the parent keeps count of the
+    /// idle threads. A thread must stop being idle before it takes work from
+    /// its queues, see IdleMonitor.
+    fn set_idle(&mut self, idle: bool) {
+        if self.idle == idle {
+            return;
+        }
+        self.idle = idle;
+
+        if idle {
+            self.idle_monitor.set_idle();
+        } else {
+            self.idle_monitor.set_busy();
+        }
+
+        if let Some(parent) = &self.parent {
+            parent.set_idle(idle);
+        }
+    }
+
+    /// Stop being counted before shutting down, neither as idle by the
+    /// parent nor as working by the idle monitor
+    fn leave(&mut self) {
+        self.set_idle(false);
+        self.idle_monitor.set_idle();
+    }
+
+    /// Hand stealable activities back to the load balancer when more
+    /// activities are queued than the high-watermark while other threads are
+    /// idle. Activities with a thread affinity are kept, and so are
+    /// activities which already migrated MAX_SHED_MIGRATIONS times. No work is
+    /// handed back while a schedule is recorded or replayed.
+    fn shed_excess_work(&mut self) {
+        if self.trace.is_some() {
+            return;
+        }
+        let watermark = match self.shed_high_watermark {
+            Some(watermark) => watermark,
+            None => return,
+        };
+        let parent = match self.parent.as_mut() {
+            Some(parent) => parent,
+            None => return,
+        };
+
+        if parent.idle_threads() == 0 {
+            return;
+        }
+
+        let mut guard = self.work_queue.lock().unwrap();
+        if guard.len() <= watermark {
+            return;
+        }
+
+        let excess = guard.len() - watermark;
+        let keys: Vec<ActivityIdentifier> = guard
+            .iter()
+            .filter(|(_, a)| {
+                a.may_be_stolen()
+                    && a.thread_affinity().is_none()
+                    && a.migrations() < MAX_SHED_MIGRATIONS
+            })
+            .map(|(k, _)| k.clone())
+            .take(excess)
+            .collect();
+        let activities: Vec<Box<dyn ActivityWrapperTrait>> =
+            keys.iter().filter_map(|k| guard.remove(k)).collect();
+        drop(guard);
+
+        if !activities.is_empty() {
+            parent.shed(self.thread_id, activities, &self.event_queue);
+        }
+    }
+
+    /// Park the thread after it found no work for a number of consecutive
+    /// iterations, until work is inserted, a delayed event is due or
+    /// MAX_PARK_TIME has passed.
+    ///
+    /// # Arguments
+    /// * `found_work` - Whether work was found in the current iteration
+    fn park_if_idle(&mut self, found_work: bool) {
+        if found_work {
+            self.idle_iterations = 0;
+            self.set_idle(false);
+            return;
+        }
+
+        self.idle_iterations += 1;
+        if self.idle_iterations < IDLE_ITERATIONS_BEFORE_PARKING {
+            return;
+        }
+        self.set_idle(true);
+
+        let timeout = match &self.delayed_events {
+            Some(delayed) => match delayed.lock().unwrap().next_due() {
+                Some(due) => MAX_PARK_TIME.min(self.clock.time_until(due)),
+                None => MAX_PARK_TIME,
+            },
+            None => MAX_PARK_TIME,
+        };
+
+        self.parker.park_timeout(timeout);
+    }
+
     /// This will startup the thread, periodically check for work forever or
-    /// if shut down from parent Constellation.
+    /// if shut down from parent Constellation. While the constellation
+    /// instance is paused, only cancelled work is removed and signals are
+    /// handled.
     pub fn run(&mut self) {
         loop {
-            // Check if we have received event for work
-            if !self.work_suspended.lock().unwrap().is_empty() {
-                // Process event
-                self.check_suspended_work();
-            }
+            self.iterations.fetch_add(1, Ordering::Relaxed);
+
+            // Remove activities of which the scope has been cancelled
+            self.remove_cancelled_work();
+
+            let mut found_work = false;
+
+            // Pick up no work and deliver no events while paused
+            if let Some(_guard) = PauseGate::enter(&self.pause) {
+                // Send delayed events which are due
+                self.send_delayed_events();
+
+                match self.trace.clone().filter(|trace| trace.is_replaying()) {
+                    // Take turns with the other threads as recorded, waiting
+                    // for its turn counts as working
+                    Some(trace) => {
+                        self.replay_step(&trace);
+                        found_work = true;
+                    }
+                    None => {
+                        // Check if we have received event for work
+                        found_work = self.check_suspended_work();
 
-            // Check for fresh work
-            match self.check_for_work() {
-                Some(x) => self.run_activity(x),
-                None => (),
+                        // Hand back work if this thread has too much while
+                        // others idle
+                        self.shed_excess_work();
+
+                        // Check for fresh work
+                        match self.check_for_work() {
+                            Some(x) => {
+                                self.run_activity(x);
+                                found_work = true;
+                            }
+                            None => (),
+                        }
+                    }
+                }
+
+                // No activity is executing on this thread anymore
+                scope_registry::set_current_scope(None);
             }
 
             // Check for signal to shut down
-            if let Ok(_) = self.receiver.try_recv().map(|val| {
-                if val {
+            match self.receiver.try_recv() {
+                Ok(ExecutorSignal::Shutdown) => {
                     info!("Got signal to shutdown");
 
                     if self.queues_empty() {
                         // Signal that we are shutting down
-                        self.sender.send(true).expect(
+                        self.leave();
+                        self.sender.send(Some(0)).expect(
                             "Failed to send signal to \
                              InnerConstellation from executor thread",
                         );
                         return; // Shutdown thread
                     } else {
-                        self.sender.send(false).expect(
+                        self.sender.send(None).expect(
                             "Failed to send signal to \
                              InnerConstellation from executor thread",
                         );
                     }
                 }
-            }){};
+                Ok(ExecutorSignal::ForceShutdown) => {
+                    info!("Got signal to shutdown forcefully");
+
+                    let cancelled = self.cancel_remaining_work();
+                    self.leave();
+                    self.sender.send(Some(cancelled)).expect(
+                        "Failed to send signal to \
+                         InnerConstellation from executor thread",
+                    );
+                    return; // Shutdown thread
+                }
+                Err(_) => (),
+            }
+
+            // Avoid spinning when there is nothing to do
+            self.park_if_idle(found_work);
         }
     }
 }
@@ -1,24 +1,40 @@
 extern crate crossbeam;
 extern crate mpi;
 
-use crate::implementation::activity_wrapper::ActivityWrapper;
-use crate::implementation::activity_wrapper::ActivityWrapperTrait;
-use crate::implementation::constellation_files::executor_thread::ExecutorThread;
-use crate::implementation::constellation_files::thread_helper::ThreadHelper;
+use crate::constellation_config::ExecutionTimeoutCallback;
+use crate::group::GroupHandle;
+use crate::implementation::activity_names::ActivityNames;
+use crate::implementation::communication::comm::Communication;
+use crate::implementation::constellation_files::executor_thread::{
+    ExecutorSettings, ExecutorSignal, ExecutorThread,
+};
+use crate::implementation::constellation_files::thread_helper::{ExecutorQueues, ThreadHelper};
+use crate::implementation::constellation_files::SHUTDOWN_WAIT;
+use crate::implementation::constellation_handle::{ConstellationHandle, SharedState};
 use crate::implementation::constellation_identifier::ConstellationIdentifier;
-use crate::implementation::event_queue::EventQueue;
+use crate::implementation::context_members::ContextMembers;
+use crate::implementation::delayed_events::DelayedEvents;
+use crate::implementation::finished_activities::{
+    FinishedActivities, FINISHED_ACTIVITIES_CAPACITY,
+};
+use crate::implementation::idle_monitor::IdleMonitor;
+use crate::implementation::object_pool;
+use crate::implementation::panic_hook;
+use crate::implementation::pause_gate::PauseGate;
+use crate::implementation::scope_registry::ScopeRegistry;
+use crate::snapshot::{self, ConstellationSnapshot};
+use crate::subscription::Subscriptions;
 use crate::{
     ActivityIdentifier, ActivityTrait, ConstellationConfiguration, ConstellationError,
-    ConstellationTrait, Context, ContextVec, Event,
+    ConstellationTrait, Context, DelayedEventToken, Event, PayloadTrait, ScopeId, SendError,
+    SubmitOptions, SubscriptionMode, WorkLeftReport,
 };
-use mpi::environment::Universe;
 
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time;
+use std::time::{Duration, Instant};
 
 use crossbeam::{unbounded, Receiver, Sender};
-use hashbrown::HashMap;
 
 /// This data structure is used in order to share a constellation instance
 /// between both the Executor and SingleThreadedConstellation (initiated by
@@ -28,12 +44,7 @@ use hashbrown::HashMap;
 /// events and submitting activities.
 ///
 /// # Members
-/// * `identifier` - Identifier for this constellation instance, must be
-/// protected with mutex since it contains dynamic methods for ID generation
 /// * `debug` - Bool indicating whether to print debug messages
-/// * `nodes` - Number of nodes in running constellation instance
-/// * `context_vec` - Vector of contexts, indicating which activities to execute
-/// on this thread
 /// * `executor` - The thread actually processing submitted activities
 /// * `multi_threaded` - used to indicate whether this instance is running on
 /// multiple threads or not. If yes, the suspended queues will be linked with
@@ -42,22 +53,37 @@ use hashbrown::HashMap;
 /// submit and send on this struct push the activity/event to the thread_handler
 /// * `thread_id` - The ID of the thread running this instance of `
 /// `InnerConstellation`
-/// * `work_queue` - WorkQueue used to share activities with the executor thread
-/// * `work_suspended` - Work queue containing data which gets suspended
-/// by thread
-/// * `event_queue` - Queue used to share events with the executor thread
+/// * `queues` - Queues used to share activities and events with the executor
+/// thread, with its identifier, parker and execution monitor. The identifier
+/// contains the counter used to generate activity identifiers.
+/// * `shared` - Tables shared with all threads of this constellation
+/// instance. Delayed events are only used when running single threaded,
+/// otherwise they are passed on to the parent.
+/// * `settings` - Settings of the executor thread, taken from the
+/// configuration
+/// * `execution_timeout_callback` - Called for activities exceeding their
+/// maximum execution time, only used when running single threaded
+/// * `pool_allocations` - Whether the executor thread enables the object pool
+/// of its thread, see `object_pool`
+/// * `handle` - Handle submitting activities and sending events for this
+/// instance, also handed to the activities run by the executor thread
+/// * `shutdown_timeout` - Time `done()` waits for the remaining work before
+/// shutting down forcefully, only used when running single threaded
+/// * `shut_down` - Set once `done()` has shut down the executor thread
 pub struct InnerConstellation {
-    identifier: Arc<Mutex<ConstellationIdentifier>>,
     debug: bool,
-    nodes: i32,
-    context_vec: ContextVec,
     executor: Option<ThreadHandler>,
     multi_threaded: bool,
     parent: Option<ThreadHelper>,
     thread_id: i32,
-    pub work_queue: Arc<Mutex<HashMap<ActivityIdentifier, Box<dyn ActivityWrapperTrait>>>>,
-    pub work_suspended: Arc<Mutex<HashMap<ActivityIdentifier, Box<dyn ActivityWrapperTrait>>>>,
-    pub event_queue: Arc<Mutex<EventQueue>>,
+    queues: ExecutorQueues,
+    shared: SharedState,
+    settings: ExecutorSettings,
+    execution_timeout_callback: Option<ExecutionTimeoutCallback>,
+    pool_allocations: bool,
+    handle: ConstellationHandle,
+    shutdown_timeout: Option<Duration>,
+    shut_down: bool,
 }
 
 impl ConstellationTrait for InnerConstellation {
@@ -65,143 +91,203 @@ impl ConstellationTrait for InnerConstellation {
         panic!("This function should never be called from inside inner class");
     }
 
-    fn submit(
+    fn submit_with(
         &mut self,
-        activity: Arc<Mutex<ActivityTrait>>,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
         context: &Context,
-        may_be_stolen: bool,
-        expects_events: bool,
-    ) -> ActivityIdentifier {
-        let activity_wrapper = ActivityWrapper::new(
-            self.identifier.clone(),
-            activity,
-            context,
-            may_be_stolen,
-            expects_events,
-        );
-        let activity_id = activity_wrapper.activity_identifier().clone();
+        options: SubmitOptions,
+    ) -> Result<ActivityIdentifier, ConstellationError> {
+        self.check_running()?;
 
-        if self.debug {
-            info!("Submitting activity with id: {}", &activity_id);
-        }
+        self.handle.submit_with(activity, context, options)
+    }
 
-        if !self.multi_threaded {
-            self.work_queue
-                .lock()
-                .unwrap()
-                .insert(activity_id.clone(), activity_wrapper);
-            return activity_id;
-        }
+    fn submit_named(
+        &mut self,
+        name: &str,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<ActivityIdentifier, ConstellationError> {
+        self.check_running()?;
+
+        self.handle.submit_named(name, activity, context, options)
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<ActivityIdentifier> {
+        self.handle.lookup(name)
+    }
 
-        self.parent
-            .as_mut()
-            .expect("Found no parent, make sure to set a ThreadHandler")
-            .submit(activity_wrapper);
+    fn create_scope(&mut self) -> ScopeId {
+        self.handle.create_scope()
+    }
 
-        activity_id
+    fn cancel_scope(&mut self, scope: ScopeId) {
+        self.handle.cancel_scope(scope);
+    }
+
+    fn submit_group(
+        &mut self,
+        activities: Vec<Arc<Mutex<dyn ActivityTrait>>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<GroupHandle, ConstellationError> {
+        self.check_running()?;
+
+        self.handle.submit_group(activities, context, options)
     }
 
     /// Perform a send operation with the event specified as argument
     ///
     /// # Arguments
     /// * `e` - Event to send, contains src and destination IDs
-    fn send(&mut self, e: Box<Event>) {
-        if self.debug {
-            info!("Send Event: {} -> {}", e.get_src(), e.get_dst());
-        }
+    fn send(&mut self, e: Event) -> Result<(), SendError> {
+        self.check_running()?;
 
-        let aid = e.get_dst();
+        self.handle.send(e)
+    }
 
-        // Running single threaded instance
-        if !self.multi_threaded {
-            self.event_queue.lock().unwrap().insert(aid, e);
-            return;
-        }
+    fn send_after(
+        &mut self,
+        e: Event,
+        delay: Duration,
+    ) -> Result<DelayedEventToken, ConstellationError> {
+        self.check_running()?;
 
-        // Check if we already have the corresponding activity
-        let mut exists = self.work_queue.lock().unwrap().contains_key(&aid);
-        if exists {
-            self.event_queue.lock().unwrap().insert(aid, e);
-            return;
-        }
+        self.handle.send_after(e, delay)
+    }
+
+    fn cancel_delayed(&mut self, token: DelayedEventToken) -> bool {
+        self.handle.cancel_delayed(token)
+    }
+
+    fn send_to_context(
+        &mut self,
+        payload: Box<dyn PayloadTrait>,
+        src: ActivityIdentifier,
+        context: &Context,
+    ) -> Result<usize, SendError> {
+        self.check_running()?;
+
+        self.handle.send_to_context(payload, src, context)
+    }
+
+    fn subscribe_with(
+        &mut self,
+        aid: &ActivityIdentifier,
+        mode: SubscriptionMode,
+    ) -> Receiver<Box<Event>> {
+        self.handle.subscribe_with(aid, mode)
+    }
+
+    fn allocate_external_id(&mut self) -> ActivityIdentifier {
+        self.handle.allocate_external_id()
+    }
+
+    fn pause(&mut self) -> Result<(), ConstellationError> {
+        self.check_running()?;
+
+        self.shared.pause.pause();
+        Ok(())
+    }
 
-        // Check if we have it in the suspended queue
-        exists = self.work_suspended.lock().unwrap().contains_key(&aid);
-        if exists {
-            self.event_queue.lock().unwrap().insert(aid, e);
-            return;
+    fn resume(&mut self) -> Result<(), ConstellationError> {
+        self.check_running()?;
+
+        self.shared.pause.resume();
+        self.queues.parker.unpark();
+        Ok(())
+    }
+
+    fn wait_until_paused(&mut self, timeout: Duration) -> Result<bool, ConstellationError> {
+        self.check_running()?;
+
+        Ok(self.shared.pause.wait_until_quiescent(timeout))
+    }
+
+    fn wait_until_idle(&mut self, timeout: Duration) -> Result<(), ConstellationError> {
+        self.check_running()?;
+
+        let idle_monitor = self.shared.idle_monitor.clone();
+        if idle_monitor.wait_until_idle(timeout, || self.work_left()) {
+            return Ok(());
         }
 
-        // Let parent deal with event, perhaps some other thread has the
-        // activity
-        self.parent
-            .as_mut()
-            .expect("No existing parent, make sure to set a ThreadHandler")
-            .send(e);
+        let report = self.work_left_report();
+        warn!(
+            "Thread {} is not idle after waiting {:?}. {}",
+            self.thread_id, timeout, report
+        );
+        Err(ConstellationError::WorkLeft(Box::new(report)))
     }
 
     /// Returns whether the work_queue and event_queue are BOTH empty
     ///
+    /// When running single threaded with a `shutdown_timeout`, waits for the
+    /// work to finish until it expires, after which the executor thread is
+    /// shut down forcefully, see `force_done()`.
+    ///
     /// # Returns
     /// * `Result<bool, ConstellationError>` - The result will always contain
     /// True if both queues are empty, otherwise a ConstellationError will be
-    /// returned.
+    /// returned: WorkLeft with a report on the queues, or ForcedShutdown when
+    /// the executor thread was shut down forcefully. Returns true without
+    /// doing anything if the executor thread has already been shut down.
+    /// False is returned while an activity exceeds its maximum execution
+    /// time.
     fn done(&mut self) -> Result<bool, ConstellationError> {
-        // Check if we still have activities running
-        match self.work_left() {
-            true => {
-                let (w, w_s) = (
-                    self.work_queue.lock().unwrap().len(),
-                    self.work_suspended.lock().unwrap().len(),
-                );
-                warn!(
-                    "Found work left in thread: {}, work_queue len: {}, work_suspended len: {}",
-                    self.thread_id, w, w_s
-                );
-                return Ok(false);
+        if self.shut_down {
+            return Ok(true);
+        }
+        self.check_running()?;
+
+        if let Some(timeout) = self.shutdown_timeout {
+            if !self.wait_for_work(timeout) {
+                let cancelled = self.force_done()?;
+                return Err(ConstellationError::ForcedShutdown(cancelled));
             }
-            _ => (),
         }
 
-        // Shut down thread
-        let handler = self.executor.as_ref().unwrap();
-        handler
-            .sender
-            .send(true)
-            .expect("Failed to send signal to executor");
+        if !self.multi_threaded {
+            self.check_execution_time();
+        }
 
-        let time = time::Duration::from_secs(100);
-        if self.debug {
-            info!(
-                "Waiting for {}s for executor thread with id: {} to shut down",
-                100,
-                self.identifier.lock().unwrap()
+        if self.queues.execution.lock().unwrap().is_hung() {
+            warn!(
+                "Thread {} is running an activity which exceeded its maximum execution time",
+                self.thread_id
             );
+            return Ok(false);
         }
 
-        if let Ok(r) = handler.receiver.recv_timeout(time) {
-            if !r {
-                warn!("Executor thread signals that there is work left");
-                let (w, w_s) = (
-                    self.work_queue.lock().unwrap().len(),
-                    self.work_suspended.lock().unwrap().len(),
-                );
+        // Check if we still have activities running
+        if self.work_left() {
+            let report = self.work_left_report();
+            warn!("Found work left in thread: {}. {}", self.thread_id, report);
+            return Err(ConstellationError::WorkLeft(Box::new(report)));
+        }
+
+        // Shut down thread
+        match self.shut_down_executor(ExecutorSignal::Shutdown, SHUTDOWN_WAIT)? {
+            Some(_) => Ok(true),
+            None => {
+                let report = self.work_left_report();
                 warn!(
-                    "Work in thread: {}, work_queue len: {}, work_suspended len: {}",
-                    self.thread_id, w, w_s
+                    "Executor thread {} signals that there is work left. {}",
+                    self.thread_id, report
                 );
-                return Ok(false);
+                Err(ConstellationError::WorkLeft(Box::new(report)))
             }
-        } else {
-            warn!("Timeout waiting for the executor thread to shutdown, something is wrong");
-            return Err(ConstellationError);
         }
+    }
 
-        Ok(true)
+    fn dump_state(&mut self) -> ConstellationSnapshot {
+        self.snapshot()
     }
 
     fn identifier(&mut self) -> ConstellationIdentifier {
-        self.identifier
+        self.queues
+            .const_id
             .lock()
             .expect("Could not get lock on ConstellationIdentifier")
             .clone()
@@ -212,68 +298,133 @@ impl ConstellationTrait for InnerConstellation {
     }
 
     fn nodes(&mut self) -> i32 {
-        self.nodes
+        self.handle.nodes()
+    }
+
+    fn threads(&mut self) -> i32 {
+        self.handle.threads()
     }
 }
 
 impl InnerConstellation {
+    /// Create the executor of a single threaded instance, running as thread
+    /// 0 of the given parent identifier
     pub fn new(
         config: &Box<ConstellationConfiguration>,
-        universe: &Universe,
-        activity_counter: Arc<Mutex<u64>>,
-        thread_id: i32,
+        comm: &dyn Communication,
+        parent: &ConstellationIdentifier,
+        scopes: Arc<Mutex<ScopeRegistry>>,
+        subscriptions: Arc<Subscriptions>,
     ) -> InnerConstellation {
-        InnerConstellation {
-            identifier: Arc::new(Mutex::new(ConstellationIdentifier::new(
-                universe,
-                activity_counter,
-                thread_id,
+        let thread_id = 0;
+        let queues = ExecutorQueues::new(
+            Arc::new(Mutex::new(parent.generate_child_identifier(thread_id))),
+            None,
+        );
+        let shared = SharedState {
+            scopes,
+            subscriptions,
+            finished: Arc::new(Mutex::new(FinishedActivities::new(
+                FINISHED_ACTIVITIES_CAPACITY,
             ))),
+            context_members: Arc::new(Mutex::new(ContextMembers::new())),
+            names: Arc::new(Mutex::new(ActivityNames::new())),
+            delayed_events: Arc::new(Mutex::new(DelayedEvents::new(config.clock.clone()))),
+            pause: Arc::new(PauseGate::new()),
+            idle_monitor: Arc::new(IdleMonitor::new()),
+        };
+        let handle = ConstellationHandle::new(config, comm, &queues, &shared, 1, thread_id, None);
+
+        InnerConstellation {
             debug: config.debug,
-            nodes: config.number_of_nodes,
-            context_vec: config.context_vec.clone(),
             executor: None,
             multi_threaded: false,
             parent: None,
             thread_id,
-            work_queue: Arc::new(Mutex::new(HashMap::new())),
-            work_suspended: Arc::new(Mutex::new(HashMap::new())),
-            event_queue: Arc::from(Mutex::from(EventQueue::new())),
+            queues,
+            shared,
+            settings: ExecutorSettings::new(config, false),
+            execution_timeout_callback: config.execution_timeout_callback,
+            pool_allocations: config.pool_allocations,
+            handle,
+            shutdown_timeout: config.shutdown_timeout,
+            shut_down: false,
         }
     }
 
+    /// Create the executor of a thread of a multi threaded instance
+    ///
+    /// # Arguments
+    /// * `config` - The configuration of the instance
+    /// * `comm` - Communication with the other processes
+    /// * `queues` - The queues of the executor thread, registered with the
+    /// parent
+    /// * `parent` - Link to the load balancer, the tables it shares with all
+    /// threads are used
+    /// * `scopes` - Registry of scopes of the instance
+    /// * `subscriptions` - Subscriptions of the application to events
+    /// * `thread_id` - Identifier of the executor thread
     pub fn new_multithreaded(
         config: &Box<ConstellationConfiguration>,
-        identifier: Arc<Mutex<ConstellationIdentifier>>,
+        comm: &dyn Communication,
+        queues: ExecutorQueues,
         parent: ThreadHelper,
-        work_queue: Arc<Mutex<HashMap<ActivityIdentifier, Box<dyn ActivityWrapperTrait>>>>,
-        work_suspended: Arc<Mutex<HashMap<ActivityIdentifier, Box<dyn ActivityWrapperTrait>>>>,
-        event_queue: Arc<Mutex<EventQueue>>,
+        scopes: Arc<Mutex<ScopeRegistry>>,
+        subscriptions: Arc<Subscriptions>,
         thread_id: i32,
     ) -> InnerConstellation {
+        let shared = SharedState {
+            scopes,
+            subscriptions,
+            finished: parent.finished_activities(),
+            context_members: parent.context_members(),
+            names: parent.activity_names(),
+            // Delayed events are routed by the parent, this queue stays empty
+            delayed_events: Arc::new(Mutex::new(DelayedEvents::new(config.clock.clone()))),
+            pause: parent.pause_gate(),
+            idle_monitor: parent.idle_monitor(),
+        };
+        let handle = ConstellationHandle::new(
+            config,
+            comm,
+            &queues,
+            &shared,
+            config.resolved_number_of_threads(),
+            thread_id,
+            Some(parent.clone()),
+        );
+
         InnerConstellation {
-            identifier,
             debug: config.debug,
-            nodes: config.number_of_nodes,
-            context_vec: config.context_vec.clone(),
             executor: None,
             multi_threaded: true,
             parent: Some(parent),
             thread_id,
-            work_queue,
-            work_suspended,
-            event_queue,
+            queues,
+            shared,
+            settings: ExecutorSettings::new(config, true),
+            execution_timeout_callback: config.execution_timeout_callback,
+            pool_allocations: config.pool_allocations,
+            handle,
+            shutdown_timeout: None,
+            shut_down: false,
         }
     }
 
+    /// Number of events dropped because their destination activity had
+    /// finished
+    pub fn dropped_events(&self) -> usize {
+        self.shared.finished.lock().unwrap().dropped_events()
+    }
+
     /// Check if there is work left in the queues
     ///
     /// # Returns
     /// * `bool` - True if there is work in at least one queue, false otherwise
     pub fn work_left(&mut self) -> bool {
-        if self.work_queue.lock().unwrap().is_empty()
-            && self.work_suspended.lock().unwrap().is_empty()
-            && self.event_queue.lock().unwrap().is_empty()
+        if self.queues.activities.lock().unwrap().is_empty()
+            && self.queues.activities_suspended.lock().unwrap().is_empty()
+            && self.queues.event_queue.lock().unwrap().is_empty()
         {
             return false;
         }
@@ -281,37 +432,256 @@ impl InnerConstellation {
         return true;
     }
 
-    /// Method that creates the executor thread and activates InnerConstellation
+    /// Check whether the executor thread is running
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - ConstellationError::NotActivated
+    /// if the executor thread has not been started, AlreadyShutDown if it has
+    /// been shut down
+    fn check_running(&self) -> Result<(), ConstellationError> {
+        if self.executor.is_none() {
+            warn!("Constellation instance is used before it was activated");
+            return Err(ConstellationError::NotActivated);
+        }
+
+        if self.shut_down {
+            warn!("Constellation instance is used after it was shut down");
+            return Err(ConstellationError::AlreadyShutDown);
+        }
+
+        Ok(())
+    }
+
+    /// Check whether the executor thread was started and not shut down yet
+    pub fn is_running(&self) -> bool {
+        self.executor.is_some() && !self.shut_down
+    }
+
+    /// Check whether the activity running on the executor thread exceeded its
+    /// maximum execution time, and report it. When running multi threaded
+    /// this is done by the parent instead.
+    fn check_execution_time(&mut self) {
+        let timed_out = self
+            .queues
+            .execution
+            .lock()
+            .unwrap()
+            .check(self.settings.clock.now());
+
+        if let Some((aid, elapsed)) = timed_out {
+            warn!(
+                "Activity {} exceeded its maximum execution time, running for {:?}",
+                aid, elapsed
+            );
+
+            if let Some(callback) = self.execution_timeout_callback {
+                callback(&aid, elapsed);
+            }
+        }
+    }
+
+    /// Gather a report on the work left in the queues of this thread
+    pub fn work_left_report(&self) -> WorkLeftReport {
+        let mut report = WorkLeftReport::new();
+        report.add_thread(
+            self.thread_id,
+            &self.queues.activities.lock().unwrap(),
+            &self.queues.activities_suspended.lock().unwrap(),
+            &self.queues.event_queue.lock().unwrap(),
+        );
+        report.snapshot = Some(Box::new(self.snapshot()));
+
+        report
+    }
+
+    /// Take a snapshot of the queues of this thread, see
+    /// ConstellationSnapshot
+    pub fn snapshot(&self) -> ConstellationSnapshot {
+        let node = self.queues.const_id.lock().unwrap().node_info.node_id;
+        let mut snapshot = ConstellationSnapshot::now(node);
+        snapshot.add_thread(
+            self.thread_id,
+            &self.queues.activities,
+            &self.queues.activities_suspended,
+            &self.queues.event_queue,
+        );
+        match snapshot::try_lock(&self.shared.finished) {
+            Some(finished) => snapshot.dead_lettered = finished.dropped_events(),
+            None => snapshot.skipped.push("finished activities".to_string()),
+        }
+
+        snapshot
+    }
+
+    /// Wait until no work is left, or the timeout expires
     ///
     /// # Arguments
-    /// * `inner_constellation` - An Arc<Mutex<..>> reference to the
-    /// constellation instance on which THIS method was called.
-    pub fn activate_inner(&mut self, inner_constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>) {
-        let (s, r): (Sender<bool>, Receiver<bool>) = unbounded();
-        let (s2, r2): (Sender<bool>, Receiver<bool>) = unbounded();
-
-        let inner_work_queue = self.work_queue.clone();
-        let inner_work_suspended = self.work_suspended.clone();
-        let inner_event_queue = self.event_queue.clone();
+    /// * `timeout` - Maximum time to wait
+    ///
+    /// # Returns
+    /// * `bool` - true if no work is left
+    fn wait_for_work(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+
+        while self.work_left() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            if !self.multi_threaded {
+                self.check_execution_time();
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        true
+    }
+
+    /// Shut the executor thread down forcefully. Activities which are
+    /// suspended or have yielded are cancelled, activities which did not start
+    /// are dropped, as are the queued and delayed events. The activity
+    /// running on the executor thread, if any, is not interrupted, the
+    /// executor thread shuts down once it returns.
+    ///
+    /// # Returns
+    /// * `Result<usize, ConstellationError>` - Number of cancelled activities,
+    /// ConstellationError if the executor thread did not respond
+    pub fn force_done(&mut self) -> Result<usize, ConstellationError> {
+        self.force_done_within(SHUTDOWN_WAIT)
+    }
+
+    /// Shut the executor thread down forcefully, see `force_done()`, waiting
+    /// at most the given time for it to respond
+    ///
+    /// # Arguments
+    /// * `timeout` - Maximum time to wait for the executor thread
+    ///
+    /// # Returns
+    /// * `Result<usize, ConstellationError>` - Number of cancelled activities,
+    /// ConstellationError if the executor thread did not respond in time
+    pub fn force_done_within(&mut self, timeout: Duration) -> Result<usize, ConstellationError> {
+        if self.shut_down {
+            return Ok(0);
+        }
+        self.check_running()?;
+
+        match self.shut_down_executor(ExecutorSignal::ForceShutdown, timeout)? {
+            Some(cancelled) => Ok(cancelled),
+            None => {
+                warn!("Executor thread {} did not shut down", self.thread_id);
+                Err(ConstellationError::Failed)
+            }
+        }
+    }
+
+    /// Signal the executor thread to shut down and wait for its answer
+    ///
+    /// # Arguments
+    /// * `signal` - The signal to send
+    /// * `timeout` - Maximum time to wait for the answer
+    ///
+    /// # Returns
+    /// * `Result<Option<usize>, ConstellationError>` - The number of cancelled
+    /// activities if the executor thread shut down, None if it had work left.
+    /// ConstellationError if it did not answer in time.
+    fn shut_down_executor(
+        &mut self,
+        signal: ExecutorSignal,
+        timeout: Duration,
+    ) -> Result<Option<usize>, ConstellationError> {
+        let handler = self.executor.as_ref().unwrap();
+        if handler.sender.send(signal).is_err() {
+            warn!(
+                "Failed to send signal to executor thread {}, it is not running",
+                self.thread_id
+            );
+            return Err(ConstellationError::Failed);
+        }
+        self.queues.parker.unpark();
+
+        if self.debug {
+            info!(
+                "Waiting for {:?} for executor thread with id: {} to shut down",
+                timeout,
+                self.queues.const_id.lock().unwrap()
+            );
+        }
+
+        let answer = match handler.receiver.recv_timeout(timeout) {
+            Ok(answer) => answer,
+            Err(_) => {
+                warn!("Timeout waiting for the executor thread to shutdown, something is wrong");
+                return Err(ConstellationError::Failed);
+            }
+        };
+
+        if answer.is_some() {
+            let delayed = self.shared.delayed_events.lock().unwrap().len();
+            if delayed > 0 {
+                warn!("Dropping {} delayed events which are not due yet", delayed);
+            }
+
+            self.shut_down = true;
+        }
+
+        Ok(answer)
+    }
+
+    /// Replace the constellation_id, before activating, by the one agreed on
+    /// with the other nodes
+    pub fn set_constellation_id(&mut self, constellation_id: i32) {
+        self.queues.const_id.lock().unwrap().constellation_id = constellation_id;
+    }
+
+    /// Method that creates the executor thread and activates InnerConstellation
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - ConstellationError if the executor
+    /// thread could not be spawned, AlreadyActivated if it was started before
+    pub fn activate_inner(&mut self) -> Result<(), ConstellationError> {
+        if self.executor.is_some() {
+            warn!(
+                "Executor thread {} is started more than once",
+                self.thread_id
+            );
+            return Err(ConstellationError::AlreadyActivated);
+        }
+
+        let (s, r): (Sender<ExecutorSignal>, Receiver<ExecutorSignal>) = unbounded();
+        let (s2, r2): (Sender<Option<usize>>, Receiver<Option<usize>>) = unbounded();
+
+        let queues = self.queues.clone();
+        let shared = self.shared.clone();
+        let settings = self.settings.clone();
+        let handle = self.handle.clone();
+        let parent = self.parent.clone();
         let id = self.thread_id;
+        let pool_allocations = self.pool_allocations;
+
+        panic_hook::install();
 
         // Start executor thread, it will keep running until shut down by
         // Constellation
-        thread::spawn(move || {
-            let mut executor = ExecutorThread::new(
-                inner_work_queue,
-                inner_work_suspended,
-                inner_event_queue,
-                inner_constellation,
-                r,
-                s2,
-                id,
-            );
-
-            executor.run();
-        });
+        let spawned = thread::Builder::new()
+            .name(panic_hook::executor_thread_name(id))
+            .spawn(move || {
+                if pool_allocations {
+                    object_pool::enable();
+                }
+
+                let mut executor =
+                    ExecutorThread::new(&queues, &shared, settings, handle, parent, r, s2);
+
+                executor.run();
+            });
+
+        if let Err(e) = spawned {
+            warn!("Could not spawn executor thread {}: {}", id, e);
+            return Err(ConstellationError::Failed);
+        }
 
         self.executor = Some(ThreadHandler::new(r2, s));
+
+        Ok(())
     }
 }
 
@@ -322,12 +692,12 @@ impl InnerConstellation {
 /// * `sender` - Used to send signal executor thread when ready
 /// to shut down gracefully
 struct ThreadHandler {
-    receiver: Receiver<bool>,
-    sender: Sender<bool>,
+    receiver: Receiver<Option<usize>>,
+    sender: Sender<ExecutorSignal>,
 }
 
 impl ThreadHandler {
-    fn new(receiver: Receiver<bool>, sender: Sender<bool>) -> ThreadHandler {
+    fn new(receiver: Receiver<Option<usize>>, sender: Sender<ExecutorSignal>) -> ThreadHandler {
         ThreadHandler { receiver, sender }
     }
 }
@@ -1,6 +1,27 @@
 mod executor_thread;
 mod inner_constellation;
-mod thread_helper;
+pub(crate) mod thread_helper;
 
 pub mod multi_threaded_constellation;
 pub mod single_threaded_constellation;
+
+use std::time::Duration;
+
+/// Maximum time `done()` waits for a thread to shut down
+const SHUTDOWN_WAIT: Duration = Duration::from_secs(100);
+
+/// Maximum time to wait for a thread to shut down when an instance is dropped
+/// without calling `done()`
+const DROP_SHUTDOWN_WAIT: Duration = Duration::from_secs(2);
+
+/// Maximum time a node waits for the activity registry of the master to
+/// answer a lookup or registration
+const REGISTRY_WAIT: Duration = Duration::from_secs(5);
+
+/// Maximum time a node which is not the master waits for the master to hand
+/// it the constellation_id when activating
+const AGREE_WAIT: Duration = Duration::from_secs(60);
+
+/// Time between two checks whether the master is done, on the nodes which
+/// are not the master
+const MASTER_POLL_INTERVAL: Duration = Duration::from_millis(10);
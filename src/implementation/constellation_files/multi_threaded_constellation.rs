@@ -6,23 +6,52 @@
 ///! The actual thread logic and work distribution is taken care of with the
 ///! thread_handler struct, this class only initializes everything and redirects
 ///! user called functions to the correct place in the handler
-use super::super::mpi::environment::Universe;
-use crate::implementation::communication::mpi_info;
+///!
+///! Executor threads can be added and removed after activation with
+///! `add_executor_threads(..)` and `remove_executor_threads(..)`.
+///!
+///! Every node, also the nodes which are not the master, starts its executor
+///! threads and a heartbeat thread when activated, see `node_health()`. With
+///! more than one node, every node also starts a registry thread, so that
+///! `lookup(..)` finds the named activities of the master from any node, see
+///! `register_name(..)`. A node whose threads are all idle steals activities
+///! from the other nodes, see the remote_steal module. The other nodes keep
+///! running until the master is done, see `wait_for_master()`.
+///!
+///! An instance which is dropped without calling `done()` shuts its threads
+///! down forcefully, waiting only briefly for them to respond. After `done()`
+///! the instance can be activated again, which starts new threads.
+use crate::group::{self, GroupHandle};
+use crate::implementation::activity_names::ActivityNames;
+use crate::implementation::activity_registry::{self, ActivityRegistry};
+use crate::implementation::communication::comm::{Communication, SHUTDOWN_TAG};
+use crate::implementation::communication::mpi_comm::MpiComm;
 use crate::implementation::constellation_files::inner_constellation::InnerConstellation;
 use crate::implementation::constellation_files::thread_helper::{
-    ExecutorQueues, MultiThreadHelper, ThreadHelper,
+    ExecutorQueues, MultiThreadHelper,
+};
+use crate::implementation::constellation_files::{
+    AGREE_WAIT, DROP_SHUTDOWN_WAIT, MASTER_POLL_INTERVAL, REGISTRY_WAIT, SHUTDOWN_WAIT,
 };
 use crate::implementation::constellation_identifier::ConstellationIdentifier;
+use crate::implementation::heartbeat;
+use crate::implementation::panic_hook;
+use crate::implementation::scope_registry::ScopeRegistry;
+use crate::schedule_log::ScheduleLog;
+use crate::schedule_trace::ScheduleTrace;
+use crate::subscription::{self, Subscriptions};
 use crate::{
     ActivityIdentifier, ActivityTrait, ConstellationConfiguration, ConstellationError,
-    ConstellationTrait, Context, Event,
+    ConstellationSnapshot, ConstellationStats, ConstellationTrait, Context, ContextVec,
+    DelayedEventToken, Event, HealthTable, LoadEntry, NodeHealth, PayloadTrait, QueueDepthStats,
+    ScopeId, SendError, StealStats, SubmitOptions, SubscriptionMode,
 };
 
 use std::sync::{Arc, Mutex};
 use std::thread;
 
-use crossbeam::{deque, unbounded, Receiver, Sender};
-use std::time;
+use crossbeam::{unbounded, Receiver, Sender};
+use std::time::Duration;
 
 /// Contains all the wrapper information necessary for the user to communicate
 /// with the thread_handler and the InnerConstellation/Executor threads.
@@ -34,19 +63,56 @@ use std::time;
 /// activities/events and inter-node communication
 /// * `signal_thread_handler` - Tuple holding communicators to signal the
 /// thread_handler, used for shutting down Constellation.
-/// * `universe` - MPI universe struct
+/// * `comm` - Communication with the other processes
+/// * `nodes` - Number of nodes, the number of processes `comm` connects.
+/// Cached upon activation, `number_of_nodes` of the configuration is only
+/// checked against it
 /// * `debug` - From configuration, used to determine whether to print debug
 /// messages or not
-/// * `thread_count` - Number of threads specified by user
+/// * `thread_count` - Number of threads specified by user, resolved to the
+/// number of available cores upon activation if it was 0, updated when
+/// threads are added or removed
 /// * `config` - ConstellationConfiguration struct
+/// * `known_contexts` - Contexts activities may be submitted with, see
+/// `ConstellationConfiguration::known_contexts()`
+/// * `scopes` - Registry of scopes, shared with all threads
+/// * `subscriptions` - Subscriptions of the application to events, shared
+/// with all threads
+/// * `names` - Names of the live activities, shared with all threads. On the
+/// master this is the table of the activity registry.
+/// * `registry` - The activity registry of this node, None until activated
+/// and when there is only one node
+/// * `registry_thread` - Dropping it stops the registry thread
+/// * `activated` - Set once `activate()` started the threads of this node
+/// * `shut_down` - Set once `done()` has shut down all threads and the
+/// thread_handler
+/// * `health` - The last heartbeat of every other node, None until activated
+/// * `heartbeat` - Dropping it stops the heartbeat thread
+/// * `schedule_log` - Structured log of scheduling decisions, if
+/// `debug_json` is set in the configuration
+/// * `schedule_trace` - Recording or replay of scheduling decisions, if
+/// `record_schedule` or `replay_schedule` is set in the configuration
 pub struct MultiThreadedConstellation {
     const_id: ConstellationIdentifier,
     thread_handler: Option<MultiThreadHelper>,
     signal_thread_handler: Option<(Sender<bool>, Receiver<bool>)>,
-    universe: Universe,
+    comm: Arc<dyn Communication>,
+    nodes: i32,
     debug: bool,
     thread_count: i32,
     config: Box<ConstellationConfiguration>,
+    known_contexts: ContextVec,
+    scopes: Arc<Mutex<ScopeRegistry>>,
+    subscriptions: Arc<Subscriptions>,
+    names: Arc<Mutex<ActivityNames>>,
+    registry: Option<Arc<ActivityRegistry>>,
+    registry_thread: Option<Sender<()>>,
+    activated: bool,
+    shut_down: bool,
+    health: Option<Arc<Mutex<HealthTable>>>,
+    heartbeat: Option<Sender<()>>,
+    schedule_log: Option<Arc<ScheduleLog>>,
+    schedule_trace: Option<Arc<ScheduleTrace>>,
 }
 
 impl ConstellationTrait for MultiThreadedConstellation {
@@ -62,77 +128,116 @@ impl ConstellationTrait for MultiThreadedConstellation {
     /// boolean which will have the value true if this is the master thread and
     /// false otherwise.
     ///
-    /// Upon failure a ConstellationError will be returned
+    /// Upon failure a ConstellationError will be returned, AlreadyActivated
+    /// if the instance was activated before and is not shut down. An
+    /// instance which was shut down is restarted.
     fn activate(&mut self) -> Result<bool, ConstellationError> {
-        if self.is_master().unwrap() {
-            if self.debug {
-                info!("Activating Multithreaded Constellation");
+        if self.activated {
+            if !self.shut_down {
+                warn!("Constellation instance is activated more than once");
+                return Err(ConstellationError::AlreadyActivated);
             }
+            self.restart();
+        }
 
-            // Queues used for threads to share events/activities with thread handler
-            let activities_from_threads = Arc::new(Mutex::new(deque::Injector::new()));
-            let events_from_threads = Arc::new(Mutex::new(deque::Injector::new()));
+        let world_size = self.comm.size();
+        self.nodes = world_size;
+        if let Err(e) = self
+            .config
+            .validate()
+            .and_then(|_| self.config.validate_master_rank(world_size))
+            .and_then(|_| self.config.check_node_count(world_size))
+        {
+            warn!("Can not activate: {}", e);
+            return Err(ConstellationError::InvalidConfiguration);
+        }
 
-            let mut thread_handler = MultiThreadHelper::new(
-                self.debug,
-                activities_from_threads.clone(),
-                events_from_threads.clone(),
-                self.config.time_between_steals,
-            );
+        // All nodes use the constellation_id of the master
+        self.const_id.constellation_id = ConstellationIdentifier::agree_constellation_id(
+            &*self.comm,
+            self.config.master_rank,
+            self.const_id.constellation_id,
+            AGREE_WAIT,
+        )?;
 
-            for i in 0..self.thread_count {
-                let executor_queues =
-                    ExecutorQueues::new(Arc::new(Mutex::new(ConstellationIdentifier::new(
-                        &self.universe,
-                        self.const_id.activity_counter.clone(),
-                        i,
-                    ))));
-
-                // This struct links the activities and events passed through the functions "submit" and "send" to the thread_handler
-                let helper =
-                    ThreadHelper::new(activities_from_threads.clone(), events_from_threads.clone());
-
-                let inner_constellation: Arc<Mutex<Box<dyn ConstellationTrait>>> =
-                    Arc::new(Mutex::new(Box::new(InnerConstellation::new_multithreaded(
-                        &self.config,
-                        executor_queues.const_id.clone(),
-                        helper,
-                        executor_queues.activities.clone(),
-                        executor_queues.activities_suspended.clone(),
-                        executor_queues.event_queue.clone(),
-                        i,
-                    ))));
-
-                if let Some(inner) = inner_constellation
-                    .lock()
-                    .unwrap()
-                    .downcast_mut::<InnerConstellation>()
-                {
-                    inner.activate_inner(inner_constellation.clone());
-                }
+        self.start_heartbeat()?;
+        self.start_registry()?;
+
+        let master = self.is_master()?;
+        if self.debug {
+            info!("Activating Multithreaded Constellation");
+        }
+
+        // Resolve the number of threads on this node, 0 means the number of
+        // available cores
+        self.thread_count = self.config.resolved_number_of_threads();
+        self.config.number_of_threads = self.thread_count;
+        if self.debug {
+            info!("Using {} executor threads", self.thread_count);
+        }
 
-                thread_handler.push(executor_queues, inner_constellation.clone());
+        // Threads are started from here on, a failure below leaves the
+        // instance activated so they are not started twice
+        self.activated = true;
+
+        // Only the master writes the schedule log and records or replays the
+        // schedule, the other nodes would write to the same files
+        if master {
+            // A restarted instance keeps appending to the same log
+            self.schedule_log = match &self.config.debug_json {
+                Some(_) if self.schedule_log.is_some() => self.schedule_log.take(),
+                Some(path) => match ScheduleLog::create(path) {
+                    Ok(log) => Some(log),
+                    Err(e) => {
+                        warn!("Could not create schedule log {}: {}", path.display(), e);
+                        None
+                    }
+                },
+                None => None,
+            };
+
+            // A restarted instance keeps recording, or replaying, the same
+            // trace
+            if self.schedule_trace.is_none() {
+                self.schedule_trace = self.open_schedule_trace();
             }
+        }
 
-            let (s, r): (Sender<bool>, Receiver<bool>) = unbounded();
-            let (s2, r2): (Sender<bool>, Receiver<bool>) = unbounded();
+        let mut thread_handler = MultiThreadHelper::new(
+            &self.config,
+            self.comm.clone(),
+            self.names.clone(),
+            self.schedule_log.clone(),
+            self.schedule_trace.clone(),
+        );
 
-            let mut inner_handler = thread_handler.clone();
+        for i in 0..self.thread_count {
+            self.start_executor_thread(&mut thread_handler, i)?;
+        }
 
-            // Start multi-thread handler, this function will periodically
-            // check for new activities/events, try to steal events from other nodes
-            // and perform load-balancing.
-            thread::spawn(move || {
+        let (s, r): (Sender<bool>, Receiver<bool>) = unbounded();
+        let (s2, r2): (Sender<bool>, Receiver<bool>) = unbounded();
+
+        let mut inner_handler = thread_handler.clone();
+
+        // Start multi-thread handler, this function will periodically
+        // check for new activities/events, steal activities from other
+        // nodes and perform load-balancing.
+        let spawned = thread::Builder::new()
+            .name(panic_hook::balancer_thread_name())
+            .spawn(move || {
                 inner_handler.run(r, s2);
             });
 
-            self.thread_handler = Some(thread_handler);
-            self.signal_thread_handler = Some((s, r2));
-
-            return Ok(true);
+        if let Err(e) = spawned {
+            warn!("Could not spawn load balancer thread: {}", e);
+            return Err(ConstellationError::Failed);
         }
 
-        Ok(false)
+        self.thread_handler = Some(thread_handler);
+        self.signal_thread_handler = Some((s, r2));
+
+        Ok(master)
     }
 
     /// Submit a new activity from user application, redirects to the thread
@@ -143,27 +248,98 @@ impl ConstellationTrait for MultiThreadedConstellation {
     /// The activity must be inside an Arc<Mutex<..>>, in order to work with
     /// thread safety.
     /// * `context` - A reference to the context created for this activity
-    /// * `may_be_stolen` - A boolean indicating whether this activity can be
-    /// stolen or not.
-    /// * `expects_events` - A boolean indicating whether this activity expects
-    /// events or not. Setting this flag to true, when applicable,
-    /// might increase performance.
+    /// * `options` - SubmitOptions for this activity, setting
+    /// `expects_events` to true, when applicable, might increase performance.
     ///
     /// # Returns
-    /// * `ActivityIdentifier` - The generated Activity Identifier for
-    /// this Activity
-    fn submit(
+    /// * `Result<ActivityIdentifier, ConstellationError>` - The generated
+    /// Activity Identifier for this Activity, ConstellationError::NotActivated
+    /// if the instance has not been activated
+    fn submit_with(
+        &mut self,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<ActivityIdentifier, ConstellationError> {
+        self.validate_options(context, &options)?;
+
+        Ok(self.activated_handler()?.submit(activity, context, options))
+    }
+
+    /// Submit an activity under a name, the name is registered in the
+    /// thread handler, see `ConstellationTrait::submit_named(..)`
+    fn submit_named(
         &mut self,
-        activity: Arc<Mutex<ActivityTrait>>,
+        name: &str,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
         context: &Context,
-        may_be_stolen: bool,
-        expects_events: bool,
-    ) -> ActivityIdentifier {
-        self.thread_handler.as_mut().unwrap().submit(
-            activity,
+        options: SubmitOptions,
+    ) -> Result<ActivityIdentifier, ConstellationError> {
+        self.validate_options(context, &options)?;
+
+        self.activated_handler()?
+            .submit_named(name, activity, context, options)
+    }
+
+    /// Look up an activity submitted with `submit_named(..)`, on a node
+    /// which is not the master the name is resolved by the master, waiting
+    /// at most REGISTRY_WAIT for its answer
+    fn lookup(&mut self, name: &str) -> Option<ActivityIdentifier> {
+        match &self.registry {
+            Some(registry) => registry.lookup(name, REGISTRY_WAIT),
+            None => self.names.lock().unwrap().lookup(name),
+        }
+    }
+
+    /// Create a new scope, see `cancel_scope(..)`
+    ///
+    /// # Returns
+    /// * `ScopeId` - Identifier of the new scope
+    fn create_scope(&mut self) -> ScopeId {
+        let scope = self.scopes.lock().unwrap().create(None);
+
+        if self.debug {
+            info!("Created scope: {}", scope);
+        }
+
+        scope
+    }
+
+    /// Cancel all activities in the given scope and its child scopes, each
+    /// executor thread removes them from its queues
+    ///
+    /// # Arguments
+    /// * `scope` - The scope to cancel
+    fn cancel_scope(&mut self, scope: ScopeId) {
+        if self.debug {
+            info!("Cancelling scope: {}", scope);
+        }
+
+        self.scopes.lock().unwrap().cancel(scope);
+    }
+
+    /// Submit a group of activities, see `ConstellationTrait::submit_group`
+    ///
+    /// # Returns
+    /// * `Result<GroupHandle, ConstellationError>` - Handle used to wait for
+    /// or cancel the group
+    fn submit_group(
+        &mut self,
+        activities: Vec<Arc<Mutex<dyn ActivityTrait>>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<GroupHandle, ConstellationError> {
+        self.activated_handler()?;
+
+        let scope = self.create_scope();
+        let scopes = self.scopes.clone();
+        group::submit_group(
+            scope,
+            scopes,
+            activities,
             context,
-            may_be_stolen,
-            expects_events,
+            options,
+            |activity, context, options| self.submit_with(activity, context, options),
         )
     }
 
@@ -171,64 +347,190 @@ impl ConstellationTrait for MultiThreadedConstellation {
     ///
     /// # Arguments
     /// * `e` - Event to send
-    fn send(&mut self, e: Box<Event>) {
-        self.thread_handler.as_mut().unwrap().send(e);
+    ///
+    /// # Returns
+    /// * `Result<(), SendError>` - See `ConstellationTrait::send(..)`
+    fn send(&mut self, e: Event) -> Result<(), SendError> {
+        self.activated_handler()?;
+
+        let aid = e.get_dst();
+        if !self.const_id.generated(&aid) {
+            return Err(SendError::UnknownDestination(aid));
+        }
+        if self.const_id.from_earlier_run(&aid) {
+            return Err(SendError::DestinationFinished(aid));
+        }
+
+        if self.scopes.lock().unwrap().is_activity_cancelled(&aid) {
+            if self.debug {
+                info!("Dropping event for cancelled activity: {}", e.summary());
+            }
+            return Ok(());
+        }
+
+        let handler = self.activated_handler()?;
+        if handler.has_finished(&aid) {
+            return Err(SendError::DestinationFinished(aid));
+        }
+        handler.send(e.boxed());
+        Ok(())
+    }
+
+    /// Send an event after the given delay, routed by the thread handler
+    ///
+    /// # Arguments
+    /// * `e` - Event to send
+    /// * `delay` - Time to wait before sending
+    ///
+    /// # Returns
+    /// * `Result<DelayedEventToken, ConstellationError>` - Token used to
+    /// cancel the event
+    fn send_after(
+        &mut self,
+        e: Event,
+        delay: Duration,
+    ) -> Result<DelayedEventToken, ConstellationError> {
+        Ok(self.activated_handler()?.send_after(e.boxed(), delay))
+    }
+
+    /// Cancel an event sent with `send_after(..)`
+    ///
+    /// # Returns
+    /// * `bool` - true if the event was still pending
+    fn cancel_delayed(&mut self, token: DelayedEventToken) -> bool {
+        match self.thread_handler.as_mut() {
+            Some(handler) => handler.cancel_delayed(token),
+            None => false,
+        }
+    }
+
+    /// Send an event to every live activity with the given context, the
+    /// members are looked up in the thread handler and then sent one by one
+    ///
+    /// # Returns
+    /// * `Result<usize, SendError>` - Number of events sent, see
+    /// `ConstellationTrait::send_to_context(..)`
+    fn send_to_context(
+        &mut self,
+        payload: Box<dyn PayloadTrait>,
+        src: ActivityIdentifier,
+        context: &Context,
+    ) -> Result<usize, SendError> {
+        let members = self.activated_handler()?.context_members(context);
+
+        let mut sent = 0;
+        for aid in members {
+            match self.send(Event::new(payload.clone(), src.clone(), aid)) {
+                Ok(()) => sent += 1,
+                Err(SendError::DestinationFinished(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(sent)
+    }
+
+    fn subscribe_with(
+        &mut self,
+        aid: &ActivityIdentifier,
+        mode: SubscriptionMode,
+    ) -> Receiver<Box<Event>> {
+        self.subscriptions.subscribe(aid, mode)
+    }
+
+    fn allocate_external_id(&mut self) -> ActivityIdentifier {
+        let aid = ActivityIdentifier::new(Arc::new(Mutex::new(self.const_id.clone())));
+        self.subscriptions.add_external(aid.clone());
+        aid
+    }
+
+    /// Pause all executor threads and the thread_handler, which stops
+    /// routing activities and events
+    fn pause(&mut self) -> Result<(), ConstellationError> {
+        self.activated_handler()?.pause();
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<(), ConstellationError> {
+        self.activated_handler()?.resume();
+        Ok(())
+    }
+
+    fn wait_until_paused(&mut self, timeout: Duration) -> Result<bool, ConstellationError> {
+        Ok(self.activated_handler()?.wait_until_paused(timeout))
+    }
+
+    /// Wait until no executor thread is working and no activities or events
+    /// are left in their queues or in the thread_handler
+    fn wait_until_idle(&mut self, timeout: Duration) -> Result<(), ConstellationError> {
+        let handler = self.activated_handler()?;
+        if handler.wait_until_idle(timeout) {
+            return Ok(());
+        }
+
+        let report = handler.work_left_report();
+        warn!("Not idle after waiting {:?}. {}", timeout, report);
+        Err(ConstellationError::WorkLeft(Box::new(report)))
     }
 
     /// Signal Constellation that it is done, perform a graceful shutdown of
-    /// all threads and the thread_handler
+    /// all threads and the thread_handler. With a `shutdown_timeout`, waits
+    /// for the remaining work until it expires and then shuts down forcefully.
+    /// The node no longer asks the other nodes for activities from the first
+    /// call on, see `wait_for_master()`. Once the master is shut down it tells
+    /// the other nodes.
     ///
     /// # Returns
     /// * `Result<bool, ConstellationError>` - Result type containing true if
     /// it could successfully shutdown, false otherwise.
     ///
-    /// Upon error a ConstellationError is returned
+    /// Upon error a ConstellationError is returned, ForcedShutdown if the
+    /// instance was shut down forcefully
     fn done(&mut self) -> Result<bool, ConstellationError> {
+        if self.shut_down {
+            return Ok(true);
+        }
+
         if self.debug {
             info!("Attempting to shut down Constellation gracefully");
         }
 
-        let inner = self.thread_handler.as_mut().unwrap().done();
+        self.activated_handler()?.stop_stealing();
+
+        if let Some(timeout) = self.config.shutdown_timeout {
+            let handler = self.activated_handler()?;
+            if !handler.wait_for_work(timeout) {
+                warn!(
+                    "Work left after waiting {:?}, shutting down forcefully",
+                    timeout
+                );
+                let cancelled = handler.force_done(SHUTDOWN_WAIT)?;
+                self.shut_down_balancer(SHUTDOWN_WAIT)?;
+                return Err(ConstellationError::ForcedShutdown(cancelled));
+            }
+        }
+
+        let inner = self.activated_handler()?.done();
 
         if inner.is_ok() {
             info!("All threads were shutdown successfully");
 
             // All threads were shutdown ok
             if *inner.as_ref().unwrap() {
-                // Shut down thread_handler
-                self.signal_thread_handler
-                    .as_ref()
-                    .unwrap()
-                    .0
-                    .send(true)
-                    .expect("Failed to send signal to load balancer");
-
-                let time = time::Duration::from_secs(100);
-                if self.debug {
-                    info!("Waiting for {}s for load balancer to shut down", 100);
-                }
-                if let Ok(r) = self
-                    .signal_thread_handler
-                    .as_ref()
-                    .unwrap()
-                    .1
-                    .recv_timeout(time)
-                {
-                    if !r {
-                        warn!("Something went wrong shutting down the load balancer");
-                        return Err(ConstellationError);
-                    }
-                } else {
-                    warn!("Timeout waiting for the load balancer to shutdown");
-                    return Err(ConstellationError);
-                }
-                info!("Load balancer successfully shutdown");
+                self.shut_down_balancer(SHUTDOWN_WAIT)?;
             }
         }
 
         inner
     }
 
+    fn dump_state(&mut self) -> ConstellationSnapshot {
+        match self.thread_handler.as_mut() {
+            Some(handler) => handler.snapshot(),
+            None => ConstellationSnapshot::now(self.comm.rank() as usize),
+        }
+    }
+
     /// Retrieve an identifier for this Constellation instance
     ///
     /// # Returns
@@ -238,26 +540,628 @@ impl ConstellationTrait for MultiThreadedConstellation {
     }
 
     fn is_master(&self) -> Result<bool, ConstellationError> {
-        Ok(mpi_info::master(&self.universe))
+        Ok(self.comm.is_master(self.config.master_rank))
     }
 
     fn nodes(&mut self) -> i32 {
-        mpi_info::size(&self.universe)
+        self.nodes
+    }
+
+    /// Wait for the message the master sends once it is shut down, polling
+    /// every MASTER_POLL_INTERVAL. Returns right away on the master and once
+    /// this node is shut down.
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - ConstellationError::Failed if the
+    /// master became unresponsive, see `node_health()`
+    fn wait_for_master(&mut self) -> Result<(), ConstellationError> {
+        if self.shut_down || self.is_master()? {
+            return Ok(());
+        }
+        self.activated_handler()?;
+
+        let master = self.config.master_rank;
+        loop {
+            if let Some((source, _)) = self.comm.try_receive(SHUTDOWN_TAG) {
+                if source == master {
+                    return Ok(());
+                }
+                warn!("Ignoring shutdown message from node {}", source);
+            }
+
+            let health = self.node_health();
+            if let Some((_, health)) = health.iter().find(|(rank, _)| *rank == master) {
+                if !health.responsive {
+                    warn!("Stopped waiting for the master, it is {}", health);
+                    return Err(ConstellationError::Failed);
+                }
+            }
+
+            thread::sleep(MASTER_POLL_INTERVAL);
+        }
+    }
+
+    fn threads(&mut self) -> i32 {
+        self.thread_count
     }
 }
 
 impl MultiThreadedConstellation {
     pub fn new(config: Box<ConstellationConfiguration>) -> MultiThreadedConstellation {
-        let universe = mpi::initialize().unwrap();
+        MultiThreadedConstellation::with_communication(config, Arc::new(MpiComm::new()))
+    }
+
+    /// Create a new instance which communicates with the other processes
+    /// through the given transport instead of MPI, e.g. a LocalComm to run
+    /// several processes inside this process
+    ///
+    /// # Arguments
+    /// * `config` - The configuration
+    /// * `comm` - Communication with the other processes
+    pub fn with_communication(
+        mut config: Box<ConstellationConfiguration>,
+        comm: Arc<dyn Communication>,
+    ) -> MultiThreadedConstellation {
+        let subscriptions = Arc::new(Subscriptions::new());
+        config
+            .event_interceptors
+            .push(subscription::interceptor(subscriptions.clone()));
 
         MultiThreadedConstellation {
-            const_id: ConstellationIdentifier::new(&universe, Arc::new(Mutex::new(0)), -1),
+            const_id: ConstellationIdentifier::new(
+                &*comm,
+                ConstellationIdentifier::next_constellation_id(),
+                Arc::new(Mutex::new(0)),
+                -1,
+            ),
             thread_handler: None,
             signal_thread_handler: None,
-            universe,
+            nodes: comm.size(),
+            comm,
             debug: config.debug,
             thread_count: config.number_of_threads,
+            known_contexts: config.known_contexts(),
             config,
+            scopes: Arc::new(Mutex::new(ScopeRegistry::new())),
+            subscriptions,
+            names: Arc::new(Mutex::new(ActivityNames::new())),
+            registry: None,
+            registry_thread: None,
+            activated: false,
+            shut_down: false,
+            health: None,
+            heartbeat: None,
+            schedule_log: None,
+            schedule_trace: None,
+        }
+    }
+
+    /// Forget the threads of an instance which was shut down, so that
+    /// `activate()` starts new ones with empty queues. The identifier,
+    /// activity counter and scopes are kept, so no identifier is handed out
+    /// twice.
+    fn restart(&mut self) {
+        if self.debug {
+            info!("Restarting Multithreaded Constellation");
+        }
+
+        self.scopes.lock().unwrap().clear_cancelled_activities();
+        self.const_id.first_activity_id = *self.const_id.activity_counter.lock().unwrap();
+        self.thread_handler = None;
+        self.signal_thread_handler = None;
+        self.health = None;
+        self.heartbeat = None;
+        self.registry = None;
+        self.registry_thread = None;
+        self.activated = false;
+        self.shut_down = false;
+    }
+
+    /// Start `n` additional executor threads, the load balancer starts
+    /// placing work on them right away.
+    ///
+    /// # Arguments
+    /// * `n` - Number of threads to add
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - ConstellationError::NotActivated
+    /// if the instance has not been activated, Failed if a thread could not
+    /// be started (threads started before it keep running)
+    pub fn add_executor_threads(&mut self, n: usize) -> Result<(), ConstellationError> {
+        self.activated_handler()?;
+
+        let mut thread_handler = self.thread_handler.take().unwrap();
+        let mut result = Ok(());
+
+        for _ in 0..n {
+            result = self.start_executor_thread(&mut thread_handler, self.thread_count);
+            if result.is_err() {
+                break;
+            }
+            self.thread_count += 1;
+        }
+
+        self.thread_handler = Some(thread_handler);
+
+        if self.debug {
+            info!("Running with {} executor threads", self.thread_count);
+        }
+
+        result
+    }
+
+    /// Retire the last `n` executor threads, their pending activities and
+    /// events are migrated to the remaining threads before the threads are
+    /// shut down.
+    ///
+    /// Threads holding activities which may not be stolen (see
+    /// SubmitOptions) are not retired.
+    ///
+    /// # Arguments
+    /// * `n` - Number of threads to remove, at least one thread must remain
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - ConstellationError::NotActivated
+    /// if the instance has not been activated, Failed if the threads could
+    /// not be retired
+    pub fn remove_executor_threads(&mut self, n: usize) -> Result<(), ConstellationError> {
+        let handler = self.activated_handler()?;
+        let result = handler.retire_threads(n);
+        self.thread_count = handler.thread_count() as i32;
+
+        if self.debug {
+            info!("Running with {} executor threads", self.thread_count);
+        }
+
+        result
+    }
+
+    /// Statistics on stealing activities in batches, use these to tune
+    /// `steal_batch_size` in the configuration
+    ///
+    /// # Returns
+    /// * `StealStats` - The statistics, all zero if the instance has not been
+    /// activated
+    pub fn steal_stats(&self) -> StealStats {
+        self.thread_handler
+            .as_ref()
+            .map_or(StealStats::default(), |handler| handler.steal_stats())
+    }
+
+    /// Snapshot of the statistics of this node, the same snapshot is written
+    /// periodically to `metrics_sink` if it is configured
+    ///
+    /// # Returns
+    /// * `ConstellationStats` - The statistics, all zero if the instance has
+    /// not been activated
+    pub fn stats(&mut self) -> ConstellationStats {
+        self.thread_handler
+            .as_mut()
+            .map_or(ConstellationStats::default(), |handler| handler.stats())
+    }
+
+    /// Number of iterations of the loop of every executor thread so far. An
+    /// executor thread which finds no work parks itself, so the counts of
+    /// an idle instance grow slowly.
+    ///
+    /// # Returns
+    /// * `Vec<u64>` - The counts by thread index, empty if the instance has
+    /// not been activated
+    pub fn loop_iterations(&self) -> Vec<u64> {
+        self.thread_handler
+            .as_ref()
+            .map_or(Vec::new(), |handler| handler.loop_iterations())
+    }
+
+    /// Histograms of the number of pending activities, suspended activities
+    /// and queued events of every executor thread, sampled once every
+    /// `queue_sample_interval` in the configuration
+    ///
+    /// # Returns
+    /// * `Vec<QueueDepthStats>` - The histograms by thread index, empty if
+    /// sampling is disabled or the instance has not been activated
+    pub fn queue_depth_stats(&self) -> Vec<QueueDepthStats> {
+        self.thread_handler
+            .as_ref()
+            .map_or(Vec::new(), |handler| handler.queue_depth_stats())
+    }
+
+    /// Number of events dropped because their destination activity had
+    /// already finished
+    ///
+    /// # Returns
+    /// * `usize` - The number of events, zero if the instance has not been
+    /// activated
+    pub fn dropped_events(&self) -> usize {
+        self.thread_handler
+            .as_ref()
+            .map_or(0, |handler| handler.dropped_events())
+    }
+
+    /// The last load reported by every node, see NodeLoad. Use it to see how
+    /// evenly the work is spread over the nodes.
+    ///
+    /// # Returns
+    /// * `Vec<LoadEntry>` - The reports ordered by node id, empty if the
+    /// instance has not been activated
+    pub fn cluster_load(&self) -> Vec<LoadEntry> {
+        self.thread_handler
+            .as_ref()
+            .map_or(Vec::new(), |handler| handler.cluster_load())
+    }
+
+    /// Register a name for an identifier of this node, e.g. one created with
+    /// `allocate_external_id()` on a node which is not the master, so that
+    /// `lookup(..)` finds it on every node. The name is kept until
+    /// `release_name(..)` is called.
+    ///
+    /// # Arguments
+    /// * `name` - The name, unique amongst the named activities of the
+    /// instance
+    /// * `aid` - The identifier
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - ConstellationError::DuplicateName
+    /// if the name is taken, Failed if the master did not answer within
+    /// REGISTRY_WAIT
+    pub fn register_name(
+        &mut self,
+        name: &str,
+        aid: &ActivityIdentifier,
+    ) -> Result<(), ConstellationError> {
+        if let Some(registry) = &self.registry {
+            return registry.register(name, aid, REGISTRY_WAIT);
+        }
+
+        let mut names = self.names.lock().unwrap();
+        if names.contains(name) {
+            return Err(ConstellationError::DuplicateName(name.to_string()));
+        }
+        names.register(name, aid.clone());
+
+        Ok(())
+    }
+
+    /// Release the name registered with `register_name(..)` for an
+    /// identifier, does nothing if it has no name
+    pub fn release_name(&mut self, aid: &ActivityIdentifier) {
+        match &self.registry {
+            Some(registry) => registry.release(aid),
+            None => self.names.lock().unwrap().release(aid),
+        }
+    }
+
+    /// The liveness of every other node, based on the heartbeats it sent, see
+    /// `heartbeat_interval` in the configuration. Heartbeats stop once the
+    /// instance is shut down.
+    ///
+    /// # Returns
+    /// * `Vec<(i32, NodeHealth)>` - The rank and liveness of every other
+    /// node, ordered by rank. Empty if the instance has not been activated
+    pub fn node_health(&self) -> Vec<(i32, NodeHealth)> {
+        self.health
+            .as_ref()
+            .map_or(Vec::new(), |health| health.lock().unwrap().snapshot())
+    }
+
+    /// The contexts served by each executor thread, see `thread_contexts` in
+    /// the configuration
+    ///
+    /// # Returns
+    /// * `Vec<Option<ContextVec>>` - The contexts of each thread by thread
+    /// index, None for threads executing activities of any context. Empty if
+    /// the instance has not been activated
+    pub fn topology(&mut self) -> Vec<Option<ContextVec>> {
+        self.thread_handler
+            .as_mut()
+            .map_or(Vec::new(), |handler| handler.thread_contexts())
+    }
+
+    /// The executor threads which execute activities with the given context
+    ///
+    /// # Arguments
+    /// * `context` - The context to look up
+    ///
+    /// # Returns
+    /// * `Vec<usize>` - Indices of the threads serving the context
+    pub fn threads_serving(&mut self, context: &Context) -> Vec<usize> {
+        self.topology()
+            .iter()
+            .enumerate()
+            .filter(|(_, contexts)| contexts.as_ref().map_or(true, |c| c.contains(context)))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Create an executor thread with its InnerConstellation and queues,
+    /// activate it and register it with the thread handler
+    ///
+    /// # Arguments
+    /// * `thread_handler` - The thread handler to register the thread with
+    /// * `thread_id` - Identifier of the new thread
+    fn start_executor_thread(
+        &self,
+        thread_handler: &mut MultiThreadHelper,
+        thread_id: i32,
+    ) -> Result<(), ConstellationError> {
+        let executor_queues = ExecutorQueues::new(
+            Arc::new(Mutex::new(
+                self.const_id.generate_child_identifier(thread_id),
+            )),
+            self.config.thread_context_vec(thread_id as usize),
+        );
+
+        // This struct links the activities and events passed through the
+        // functions "submit" and "send" to the thread_handler
+        let helper = thread_handler.thread_helper();
+
+        let inner_constellation: Arc<Mutex<Box<dyn ConstellationTrait>>> =
+            Arc::new(Mutex::new(Box::new(InnerConstellation::new_multithreaded(
+                &self.config,
+                &*self.comm,
+                executor_queues.clone(),
+                helper,
+                self.scopes.clone(),
+                self.subscriptions.clone(),
+                thread_id,
+            ))));
+
+        if let Some(inner) = inner_constellation
+            .lock()
+            .unwrap()
+            .as_any_mut()
+            .downcast_mut::<InnerConstellation>()
+        {
+            inner.activate_inner()?;
+        }
+
+        thread_handler.push(executor_queues, inner_constellation);
+
+        Ok(())
+    }
+
+    /// Start sending heartbeats to, and checking the heartbeats of, the other
+    /// nodes. Nothing is started when there are no other nodes.
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - ConstellationError if the
+    /// heartbeat thread could not be spawned
+    fn start_heartbeat(&mut self) -> Result<(), ConstellationError> {
+        let rank = self.comm.rank();
+        let peers: Vec<i32> = (0..self.comm.size()).filter(|peer| *peer != rank).collect();
+        if peers.is_empty() || self.heartbeat.is_some() {
+            return Ok(());
+        }
+
+        let health = Arc::new(Mutex::new(HealthTable::new(
+            peers,
+            self.config.heartbeat_interval,
+            self.config.heartbeat_miss_threshold,
+            self.config.clock.now(),
+        )));
+
+        match heartbeat::start(
+            self.comm.clone(),
+            health.clone(),
+            self.config.heartbeat_interval,
+            self.config.on_node_unresponsive.clone(),
+            self.debug,
+            self.config.clock.clone(),
+        ) {
+            Ok(stop) => {
+                self.health = Some(health);
+                self.heartbeat = Some(stop);
+                Ok(())
+            }
+            Err(e) => {
+                warn!("Could not spawn heartbeat thread: {}", e);
+                Err(ConstellationError::Failed)
+            }
+        }
+    }
+
+    /// Start the registry thread, which exchanges activity names with the
+    /// other nodes. Nothing is started when there are no other nodes.
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - ConstellationError if the
+    /// registry thread could not be spawned
+    fn start_registry(&mut self) -> Result<(), ConstellationError> {
+        if self.comm.size() < 2 || self.registry_thread.is_some() {
+            return Ok(());
+        }
+
+        let registry = Arc::new(ActivityRegistry::new(
+            self.comm.clone(),
+            self.config.master_rank,
+            self.names.clone(),
+        ));
+
+        match activity_registry::start(registry.clone()) {
+            Ok(stop) => {
+                self.registry = Some(registry);
+                self.registry_thread = Some(stop);
+                Ok(())
+            }
+            Err(e) => {
+                warn!("Could not spawn registry thread: {}", e);
+                Err(ConstellationError::Failed)
+            }
+        }
+    }
+
+    /// Shut down the thread_handler, after all threads have been shut down
+    ///
+    /// # Arguments
+    /// * `timeout` - Maximum time to wait for the load balancer
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - ConstellationError if the load
+    /// balancer did not shut down
+    fn shut_down_balancer(&mut self, timeout: Duration) -> Result<(), ConstellationError> {
+        if self
+            .signal_thread_handler
+            .as_ref()
+            .unwrap()
+            .0
+            .send(true)
+            .is_err()
+        {
+            warn!("Failed to send signal to load balancer, it is not running");
+            return Err(ConstellationError::Failed);
+        }
+        // Do not wait for the load balancer to wake up by itself
+        if let Some(thread_handler) = &self.thread_handler {
+            thread_handler.wake();
+        }
+
+        if self.debug {
+            info!("Waiting for {:?} for load balancer to shut down", timeout);
+        }
+        if let Ok(r) = self
+            .signal_thread_handler
+            .as_ref()
+            .unwrap()
+            .1
+            .recv_timeout(timeout)
+        {
+            if !r {
+                warn!("Something went wrong shutting down the load balancer");
+                return Err(ConstellationError::Failed);
+            }
+        } else {
+            warn!("Timeout waiting for the load balancer to shutdown");
+            return Err(ConstellationError::Failed);
+        }
+        info!("Load balancer successfully shutdown");
+        for stats in self.queue_depth_stats() {
+            info!("Queue depths of {}", stats);
+        }
+        if let Some(sink) = self.config.metrics_sink.clone() {
+            let stats = self.stats();
+            sink.write(&stats);
+        }
+        self.shut_down = true;
+        self.heartbeat = None;
+        self.registry_thread = None;
+        if self.comm.is_master(self.config.master_rank) {
+            for node in (0..self.comm.size()).filter(|node| *node != self.comm.rank()) {
+                self.comm.send(node, SHUTDOWN_TAG, &[]);
+            }
+        }
+        if let Some(log) = &self.schedule_log {
+            log.flush();
+        }
+        if let Some(trace) = &self.schedule_trace {
+            trace.flush();
+        }
+
+        Ok(())
+    }
+
+    /// Start the recording, or read the recording to replay, set in the
+    /// configuration
+    ///
+    /// # Returns
+    /// * `Option<Arc<ScheduleTrace>>` - The trace, None if neither is set or
+    /// the file could not be opened, which is logged
+    fn open_schedule_trace(&self) -> Option<Arc<ScheduleTrace>> {
+        let (path, trace) = match (&self.config.record_schedule, &self.config.replay_schedule) {
+            (Some(path), _) => (path, ScheduleTrace::record(path)),
+            (None, Some(path)) => (path, ScheduleTrace::replay(path)),
+            (None, None) => return None,
+        };
+
+        match trace {
+            Ok(trace) => Some(trace),
+            Err(e) => {
+                warn!("Could not open schedule trace {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Check the context and options of an activity submitted to this node,
+    /// see `SubmitOptions::validate(..)`
+    fn validate_options(
+        &mut self,
+        context: &Context,
+        options: &SubmitOptions,
+    ) -> Result<(), ConstellationError> {
+        let known =
+            self.known_contexts.context_vec.is_empty() || self.known_contexts.contains(context);
+        let allow_unserved = self.config.allow_unserved_contexts;
+        let handler = self.activated_handler()?;
+        let served = known && (allow_unserved || handler.serves(context));
+        let threads = handler.thread_count();
+        options.validate(
+            context,
+            served,
+            threads,
+            self.comm.rank() as usize,
+            self.comm.is_master(self.config.master_rank),
+            self.comm.size() as usize,
+        )
+    }
+
+    /// Get the thread handler, which only exists after activation
+    ///
+    /// # Returns
+    /// * `Result<&mut MultiThreadHelper, ConstellationError>` - The thread
+    /// handler, ConstellationError::NotActivated if the instance has not been
+    /// activated, AlreadyShutDown if it has been shut down
+    fn activated_handler(&mut self) -> Result<&mut MultiThreadHelper, ConstellationError> {
+        if self.shut_down {
+            warn!("Constellation instance is used after it was shut down");
+            return Err(ConstellationError::AlreadyShutDown);
+        }
+
+        match self.thread_handler.as_mut() {
+            Some(handler) => Ok(handler),
+            None => {
+                warn!("Constellation instance is used before it was activated");
+                Err(ConstellationError::NotActivated)
+            }
+        }
+    }
+}
+
+/// Best-effort shutdown of an instance on which `done()` was never called,
+/// e.g. because the application panicked. The threads are shut down
+/// forcefully and the work left is logged. Nothing is done when dropped on a
+/// thread started by Constellation, as waiting for the threads from there
+/// could deadlock.
+impl Drop for MultiThreadedConstellation {
+    fn drop(&mut self) {
+        if self.shut_down || self.thread_handler.is_none() {
+            return;
+        }
+
+        if panic_hook::on_constellation_thread() {
+            warn!("Constellation instance dropped on one of its own threads, not shutting down");
+            return;
+        }
+
+        let handler = self.thread_handler.as_mut().unwrap();
+        if handler.work_left() {
+            warn!(
+                "Constellation instance dropped without calling done(), shutting down. {}",
+                handler.work_left_report()
+            );
+        } else {
+            warn!("Constellation instance dropped without calling done(), shutting down");
+        }
+
+        match handler.force_done(DROP_SHUTDOWN_WAIT) {
+            Ok(cancelled) if cancelled > 0 => {
+                warn!("Cancelled {} activities", cancelled);
+            }
+            Ok(_) => (),
+            Err(e) => warn!("Could not shut down all executor threads: {}", e),
+        }
+
+        if let Err(e) = self.shut_down_balancer(DROP_SHUTDOWN_WAIT) {
+            warn!("Could not shut down the load balancer: {}", e);
         }
     }
 }
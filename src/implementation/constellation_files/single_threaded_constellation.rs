@@ -1,17 +1,30 @@
 //! Single threaded implementation of Constellation.
+//!
+//! An instance which is dropped without calling `done()` shuts its executor
+//! thread down forcefully, waiting only briefly for it to respond. After
+//! `done()` the instance can be activated again, which starts a new executor
+//! thread.
 extern crate crossbeam;
-extern crate mpi;
 
 use super::inner_constellation::InnerConstellation;
-use crate::implementation::communication::mpi_info;
+use super::{AGREE_WAIT, DROP_SHUTDOWN_WAIT};
+use crate::group::GroupHandle;
+use crate::implementation::communication::comm::Communication;
+use crate::implementation::communication::mpi_comm::MpiComm;
 use crate::implementation::constellation_identifier::ConstellationIdentifier;
+use crate::implementation::panic_hook;
+use crate::implementation::scope_registry::ScopeRegistry;
+use crate::subscription::{self, Subscriptions};
 use crate::{
     ActivityIdentifier, ActivityTrait, ConstellationConfiguration, ConstellationError,
-    ConstellationTrait, Context, Event,
+    ConstellationSnapshot, ConstellationTrait, Context, DelayedEventToken, Event, PayloadTrait,
+    ScopeId, SendError, SubmitOptions, SubscriptionMode,
 };
-use mpi::environment::Universe;
 
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crossbeam::Receiver;
 
 /// A single threaded Constellation initializer, it creates an executor thread
 /// and a InnerConstellation object. The inner_constellation contains all
@@ -24,12 +37,25 @@ use std::sync::{Arc, Mutex};
 /// InnerConstellation struct. This struct is shared with the executor thread
 /// in order for the executor to be able to submit/send events using the
 /// Constellation trait
-/// * `universe` - MPI Universe struct
+/// * `comm` - Communication with the other processes
 /// * `debug` - boolean indicating whether to display debug messages or not
+/// * `config` - ConstellationConfiguration struct
+/// * `activated` - Set once `activate()` succeeded
+/// * `const_id` - Identifier of this instance, with thread_id set to -1. The
+/// identifier of the executor thread is derived from it, its
+/// constellation_id and activity counter are kept when restarted
+/// * `scopes` - Registry of scopes, kept when restarted
+/// * `subscriptions` - Subscriptions of the application to events, kept when
+/// restarted
 pub struct SingleThreadConstellation {
     inner_constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>,
-    universe: Universe,
+    comm: Arc<dyn Communication>,
     debug: bool,
+    config: Box<ConstellationConfiguration>,
+    activated: bool,
+    const_id: ConstellationIdentifier,
+    scopes: Arc<Mutex<ScopeRegistry>>,
+    subscriptions: Arc<Subscriptions>,
 }
 
 impl ConstellationTrait for SingleThreadConstellation {
@@ -43,21 +69,55 @@ impl ConstellationTrait for SingleThreadConstellation {
     /// boolean which will have the value true if this is the master thread and
     /// false otherwise.
     ///
-    /// Upon failure a ConstellationError will be returned
+    /// Upon failure a ConstellationError will be returned, AlreadyActivated
+    /// if the instance was activated before and is not shut down. An
+    /// instance which was shut down is restarted.
     fn activate(&mut self) -> Result<bool, ConstellationError> {
+        if self.activated {
+            if self.is_running() {
+                warn!("Constellation instance is activated more than once");
+                return Err(ConstellationError::AlreadyActivated);
+            }
+            self.restart();
+        }
+
+        let world_size = self.comm.size();
+        if let Err(e) = self
+            .config
+            .validate()
+            .and_then(|_| self.config.validate_master_rank(world_size))
+            .and_then(|_| self.config.check_node_count(world_size))
+        {
+            warn!("Can not activate: {}", e);
+            return Err(ConstellationError::InvalidConfiguration);
+        }
+
+        // All nodes use the constellation_id of the master
+        self.const_id.constellation_id = ConstellationIdentifier::agree_constellation_id(
+            &*self.comm,
+            self.config.master_rank,
+            self.const_id.constellation_id,
+            AGREE_WAIT,
+        )?;
+
+        let mut guard = self.inner_constellation.lock().unwrap();
+        let inner = guard
+            .as_any_mut()
+            .downcast_mut::<InnerConstellation>()
+            .unwrap();
+        inner.set_constellation_id(self.const_id.constellation_id);
+
         if self.is_master().unwrap() {
             if self.debug {
                 info!("Activating Single Threaded Constellation");
             }
-            self.inner_constellation
-                .lock()
-                .unwrap()
-                .downcast_mut::<InnerConstellation>()
-                .unwrap()
-                .activate_inner(self.inner_constellation.clone());
+            inner.activate_inner()?;
 
+            self.activated = true;
             return Ok(true);
         }
+
+        self.activated = true;
         return Ok(false);
     }
 
@@ -73,35 +133,175 @@ impl ConstellationTrait for SingleThreadConstellation {
     /// The activity must be inside an Arc<Mutex<..>>, in order to work with
     /// thread safety.
     /// * `context` - A reference to the context created for this activity
-    /// * `may_be_stolen` - A boolean indicating whether this activity can be
-    /// stolen or not.
-    /// * `expects_events` - A boolean indicating whether this activity expects
-    /// events or not.
+    /// * `options` - SubmitOptions for this activity
     ///
     /// # Returns
-    /// * `ActivityIdentifier` - The generated Activity Identifier for
-    /// this Activity
-    fn submit(
+    /// * `Result<ActivityIdentifier, ConstellationError>` - The generated
+    /// Activity Identifier for this Activity, ConstellationError::NotActivated
+    /// if the executor has not been started yet
+    fn submit_with(
         &mut self,
         activity: Arc<Mutex<dyn ActivityTrait>>,
         context: &Context,
-        may_be_stolen: bool,
-        expects_events: bool,
-    ) -> ActivityIdentifier {
-        self.inner_constellation.lock().unwrap().submit(
-            activity,
-            context,
-            may_be_stolen,
-            expects_events,
-        )
+        options: SubmitOptions,
+    ) -> Result<ActivityIdentifier, ConstellationError> {
+        self.inner_constellation
+            .lock()
+            .unwrap()
+            .submit_with(activity, context, options)
+    }
+
+    fn submit_named(
+        &mut self,
+        name: &str,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<ActivityIdentifier, ConstellationError> {
+        self.inner_constellation
+            .lock()
+            .unwrap()
+            .submit_named(name, activity, context, options)
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<ActivityIdentifier> {
+        self.inner_constellation.lock().unwrap().lookup(name)
+    }
+
+    /// Create a new scope, see `cancel_scope(..)`
+    ///
+    /// # Returns
+    /// * `ScopeId` - Identifier of the new scope
+    fn create_scope(&mut self) -> ScopeId {
+        self.inner_constellation.lock().unwrap().create_scope()
+    }
+
+    /// Cancel all activities in the given scope and its child scopes
+    ///
+    /// # Arguments
+    /// * `scope` - The scope to cancel
+    fn cancel_scope(&mut self, scope: ScopeId) {
+        self.inner_constellation.lock().unwrap().cancel_scope(scope);
+    }
+
+    /// Submit a group of activities, see `ConstellationTrait::submit_group`
+    ///
+    /// # Returns
+    /// * `Result<GroupHandle, ConstellationError>` - Handle used to wait for
+    /// or cancel the group
+    fn submit_group(
+        &mut self,
+        activities: Vec<Arc<Mutex<dyn ActivityTrait>>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<GroupHandle, ConstellationError> {
+        self.inner_constellation
+            .lock()
+            .unwrap()
+            .submit_group(activities, context, options)
     }
 
     /// Perform a send operation with the event specified as argument
     ///
     /// # Arguments
     /// * `e` - Event to send
-    fn send(&mut self, e: Box<Event>) {
-        self.inner_constellation.lock().unwrap().send(e);
+    ///
+    /// # Returns
+    /// * `Result<(), SendError>` - See `ConstellationTrait::send(..)`
+    fn send(&mut self, e: Event) -> Result<(), SendError> {
+        self.inner_constellation.lock().unwrap().send(e)
+    }
+
+    /// Send an event after the given delay
+    ///
+    /// # Arguments
+    /// * `e` - Event to send
+    /// * `delay` - Time to wait before sending
+    ///
+    /// # Returns
+    /// * `Result<DelayedEventToken, ConstellationError>` - Token used to
+    /// cancel the event
+    fn send_after(
+        &mut self,
+        e: Event,
+        delay: Duration,
+    ) -> Result<DelayedEventToken, ConstellationError> {
+        self.inner_constellation
+            .lock()
+            .unwrap()
+            .send_after(e, delay)
+    }
+
+    /// Cancel an event sent with `send_after(..)`
+    ///
+    /// # Returns
+    /// * `bool` - true if the event was still pending
+    fn cancel_delayed(&mut self, token: DelayedEventToken) -> bool {
+        self.inner_constellation
+            .lock()
+            .unwrap()
+            .cancel_delayed(token)
+    }
+
+    fn send_to_context(
+        &mut self,
+        payload: Box<dyn PayloadTrait>,
+        src: ActivityIdentifier,
+        context: &Context,
+    ) -> Result<usize, SendError> {
+        self.inner_constellation
+            .lock()
+            .unwrap()
+            .send_to_context(payload, src, context)
+    }
+
+    fn subscribe_with(
+        &mut self,
+        aid: &ActivityIdentifier,
+        mode: SubscriptionMode,
+    ) -> Receiver<Box<Event>> {
+        self.subscriptions.subscribe(aid, mode)
+    }
+
+    fn allocate_external_id(&mut self) -> ActivityIdentifier {
+        self.inner_constellation
+            .lock()
+            .unwrap()
+            .allocate_external_id()
+    }
+
+    /// Pause the executor thread, see `ConstellationTrait::pause()`
+    fn pause(&mut self) -> Result<(), ConstellationError> {
+        self.inner_constellation.lock().unwrap().pause()
+    }
+
+    /// Resume the executor thread after `pause()`
+    fn resume(&mut self) -> Result<(), ConstellationError> {
+        self.inner_constellation.lock().unwrap().resume()
+    }
+
+    /// Wait until the executor thread is quiescent after `pause()`
+    ///
+    /// # Arguments
+    /// * `timeout` - Maximum time to wait
+    ///
+    /// # Returns
+    /// * `Result<bool, ConstellationError>` - true once the executor thread
+    /// is quiescent, false if the timeout expired
+    fn wait_until_paused(&mut self, timeout: Duration) -> Result<bool, ConstellationError> {
+        self.inner_constellation
+            .lock()
+            .unwrap()
+            .wait_until_paused(timeout)
+    }
+
+    /// Wait until the executor thread is idle, see
+    /// `ConstellationTrait::wait_until_idle(..)`
+    fn wait_until_idle(&mut self, timeout: Duration) -> Result<(), ConstellationError> {
+        self.inner_constellation
+            .lock()
+            .unwrap()
+            .wait_until_idle(timeout)
     }
 
     /// Signal Constellation that it is done, perform a graceful shutdown
@@ -123,6 +323,13 @@ impl ConstellationTrait for SingleThreadConstellation {
     ///
     /// # Returns
     /// * `ConstellationIdentifier` - Identifier for this Constellation instance
+    fn dump_state(&mut self) -> ConstellationSnapshot {
+        self.inner_constellation
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .dump_state()
+    }
+
     fn identifier(&mut self) -> ConstellationIdentifier {
         self.inner_constellation.lock().unwrap().identifier()
     }
@@ -135,11 +342,7 @@ impl ConstellationTrait for SingleThreadConstellation {
     /// this process is the leader, false otherwise.
     /// Will return ConstellationError if something went wrong.
     fn is_master(&self) -> Result<bool, ConstellationError> {
-        if mpi_info::rank(&self.universe) == 0 {
-            Ok(true)
-        } else {
-            Ok(false)
-        }
+        Ok(self.comm.is_master(self.config.master_rank))
     }
 
     /// Return the total number of nodes in the Constellation instance
@@ -149,6 +352,14 @@ impl ConstellationTrait for SingleThreadConstellation {
     fn nodes(&mut self) -> i32 {
         self.inner_constellation.lock().unwrap().nodes()
     }
+
+    /// Return the number of executor threads, always 1
+    ///
+    /// # Returns
+    /// * `i32` - Number of threads
+    fn threads(&mut self) -> i32 {
+        1
+    }
 }
 
 impl SingleThreadConstellation {
@@ -162,17 +373,130 @@ impl SingleThreadConstellation {
     /// * `SingleThreadedConstellation` - New single threaded Constellation
     /// instance
     pub fn new(config: Box<ConstellationConfiguration>) -> SingleThreadConstellation {
-        let universe = mpi::initialize().unwrap();
+        SingleThreadConstellation::with_communication(config, Arc::new(MpiComm::new()))
+    }
+
+    /// Create a new instance which communicates with the other processes
+    /// through the given transport instead of MPI, e.g. a LocalComm to run
+    /// several processes inside this process
+    ///
+    /// # Arguments
+    /// * `config` - A boxed ConstellationConfiguration
+    /// * `comm` - Communication with the other processes
+    pub fn with_communication(
+        mut config: Box<ConstellationConfiguration>,
+        comm: Arc<dyn Communication>,
+    ) -> SingleThreadConstellation {
+        let const_id = ConstellationIdentifier::new(
+            &*comm,
+            ConstellationIdentifier::next_constellation_id(),
+            Arc::new(Mutex::new(0)),
+            -1,
+        );
+        let scopes = Arc::new(Mutex::new(ScopeRegistry::new()));
+        let subscriptions = Arc::new(Subscriptions::new());
+        config
+            .event_interceptors
+            .push(subscription::interceptor(subscriptions.clone()));
 
         SingleThreadConstellation {
             inner_constellation: Arc::new(Mutex::new(Box::new(InnerConstellation::new(
                 &config,
-                &universe,
-                Arc::new(Mutex::new(0)),
-                0,
+                &*comm,
+                &const_id,
+                scopes.clone(),
+                subscriptions.clone(),
             )))),
-            universe,
+            comm,
             debug: config.debug,
+            config,
+            activated: false,
+            const_id,
+            scopes,
+            subscriptions,
+        }
+    }
+
+    /// Check whether the executor thread was started and not shut down yet
+    fn is_running(&self) -> bool {
+        self.inner_constellation
+            .lock()
+            .unwrap()
+            .as_any()
+            .downcast_ref::<InnerConstellation>()
+            .map_or(false, |inner| inner.is_running())
+    }
+
+    /// Replace the InnerConstellation of an instance which was shut down by
+    /// a new one, with empty queues. The identifier, activity counter and
+    /// scopes are kept, so no identifier is handed out twice.
+    fn restart(&mut self) {
+        if self.debug {
+            info!("Restarting Single Threaded Constellation");
+        }
+
+        self.scopes.lock().unwrap().clear_cancelled_activities();
+        self.const_id.first_activity_id = *self.const_id.activity_counter.lock().unwrap();
+        self.inner_constellation = Arc::new(Mutex::new(Box::new(InnerConstellation::new(
+            &self.config,
+            &*self.comm,
+            &self.const_id,
+            self.scopes.clone(),
+            self.subscriptions.clone(),
+        ))));
+        self.activated = false;
+    }
+
+    /// Number of events dropped because their destination activity had
+    /// already finished
+    pub fn dropped_events(&self) -> usize {
+        self.inner_constellation
+            .lock()
+            .unwrap()
+            .as_any()
+            .downcast_ref::<InnerConstellation>()
+            .unwrap()
+            .dropped_events()
+    }
+}
+
+/// Best-effort shutdown of an instance on which `done()` was never called,
+/// e.g. because the application panicked. The executor thread is shut down
+/// forcefully and the work left is logged. Nothing is done when dropped on a
+/// thread started by Constellation, as waiting for the executor thread from
+/// there could deadlock.
+impl Drop for SingleThreadConstellation {
+    fn drop(&mut self) {
+        if panic_hook::on_constellation_thread() {
+            warn!("Constellation instance dropped on one of its own threads, not shutting down");
+            return;
+        }
+
+        // The lock is poisoned if the application panicked while holding it
+        let mut guard = self
+            .inner_constellation
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let inner = match guard.as_any_mut().downcast_mut::<InnerConstellation>() {
+            Some(inner) if inner.is_running() => inner,
+            _ => return,
+        };
+
+        if inner.work_left() {
+            warn!(
+                "Constellation instance dropped without calling done(), shutting down. {}",
+                inner.work_left_report()
+            );
+        } else {
+            warn!("Constellation instance dropped without calling done(), shutting down");
+        }
+
+        match inner.force_done_within(DROP_SHUTDOWN_WAIT) {
+            Ok(cancelled) if cancelled > 0 => {
+                warn!("Cancelled {} activities", cancelled);
+            }
+            Ok(_) => (),
+            Err(e) => warn!("Could not shut down the executor thread: {}", e),
         }
     }
 }
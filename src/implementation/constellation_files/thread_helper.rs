@@ -13,21 +13,79 @@
 ///! The `run` method should be started with a new thread, ìt will periodically
 ///! check threads for suspended activities and events to distribute evenly
 ///! across all threads.
-
+///!
+///! Threads can be added and retired while running, the threads are kept in
+///! a ThreadRegistry shared between all clones of the MultiThreadHelper, each
+///! clone keeps a snapshot which is refreshed when the registry changes.
+///!
+///! With a `rebalance_threshold`, the `run` method also moves queued
+///! activities from the thread with the most pending work to the thread with
+///! the least, so work placed on a thread earlier does not stay there while
+///! other threads are idle.
+///!
+///! With more than one node, the `run` method also steals activities from
+///! the other nodes once all threads of this node are idle, hands activities
+///! to the nodes asking for them and forwards events to the node executing
+///! their destination, see the remote_steal module.
+use super::executor_thread::MAX_SHED_MIGRATIONS;
+use crate::clock::Clock;
+use crate::constellation_config::{ExecutionTimeoutCallback, NoProgressCallback};
+use crate::decoders::Decoders;
+#[cfg(feature = "fault-injection")]
+use crate::fault_injection::{EventFault, FaultInjector};
+use crate::implementation::activity_names::ActivityNames;
+use crate::implementation::activity_queue::ActivityQueue;
 use crate::implementation::activity_wrapper::{ActivityWrapper, ActivityWrapperTrait};
+use crate::implementation::communication::comm::{
+    Communication, EVENT_TAG, LOAD_TAG, STEAL_REPLY_TAG, STEAL_TAG,
+};
+use crate::implementation::constellation_files::inner_constellation::InnerConstellation;
 use crate::implementation::constellation_identifier::ConstellationIdentifier;
+use crate::implementation::context_members::ContextMembers;
+use crate::implementation::delayed_events::DelayedEvents;
 use crate::implementation::event_queue::EventQueue;
+use crate::implementation::execution_monitor::ExecutionMonitor;
+use crate::implementation::finished_activities::{
+    FinishedActivities, FINISHED_ACTIVITIES_CAPACITY,
+};
+use crate::implementation::idle_monitor::IdleMonitor;
+use crate::implementation::parker::Parker;
+use crate::implementation::pause_gate::PauseGate;
+use crate::implementation::progress_monitor::ProgressMonitor;
+use crate::implementation::remote_steal::{
+    RemoteEvent, RemoteSteal, StealReply, StealRequest, StolenActivity, HOSTED_PURGE_INTERVAL,
+    STEAL_BACKOFF,
+};
+use crate::implementation::sharded_event_queue::{self, ShardedEventQueue};
+use crate::intercept::{self, EventInterceptor};
+use crate::metrics::{ConstellationStats, MetricsSink};
+use crate::node_load::{LoadEntry, LoadTable, NodeLoad};
+use crate::queue_depth::QueueDepthStats;
+use crate::schedule_log::{self, ScheduleLog, ScheduleLogger, ScheduleRecord};
+use crate::schedule_trace::ScheduleTrace;
+use crate::scheduler_event::{self, SchedulerEvent};
+use crate::snapshot::{self, ConstellationSnapshot};
+use crate::steal_stats::{StealCounters, StealStats};
+use crate::work_left::{EventInfo, WorkLeftReport};
 use crate::{
-    ActivityIdentifier, ActivityTrait, ConstellationError, ConstellationTrait, Context, Event,
+    ActivityIdentifier, ActivityTrait, CompletionReason, ConstellationConfiguration,
+    ConstellationError, ConstellationTrait, Context, ContextVec, DelayedEventToken, Event,
+    StealStrategy, SubmitOptions,
 };
 
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time;
+use std::time::{self, Duration, Instant};
 
 use crossbeam::{deque, deque::Steal, Receiver, Sender};
 use hashbrown::HashMap;
 
+/// Minimum time between two rebalancing passes of the `run` method
+const REBALANCE_INTERVAL: Duration = Duration::from_millis(10);
+
 /// Struct holding all queues related to one single thread.
 ///
 /// # Members
@@ -36,24 +94,47 @@ use hashbrown::HashMap;
 /// struct
 /// * `activities_suspended` - Suspended activities
 /// * `event_queue` - Event queue
+/// * `execution` - Monitor of the activity running on the thread
+/// * `parker` - Parker of the executor thread, must be unparked after
+/// inserting activities or events in its queues
+/// * `contexts` - The contexts of the activities this thread may execute,
+/// None if it executes activities of any context
+/// * `iterations` - Number of iterations of the loop of the executor thread
 #[derive(Clone)]
 pub struct ExecutorQueues {
     pub const_id: Arc<Mutex<ConstellationIdentifier>>,
-    pub activities: Arc<Mutex<HashMap<ActivityIdentifier, Box<dyn ActivityWrapperTrait>>>>,
-    pub activities_suspended:
-    Arc<Mutex<HashMap<ActivityIdentifier, Box<dyn ActivityWrapperTrait>>>>,
+    pub activities: Arc<Mutex<ActivityQueue>>,
+    pub activities_suspended: Arc<Mutex<ActivityQueue>>,
     pub event_queue: Arc<Mutex<EventQueue>>,
+    pub execution: Arc<Mutex<ExecutionMonitor>>,
+    pub parker: Arc<Parker>,
+    pub contexts: Option<ContextVec>,
+    pub iterations: Arc<AtomicU64>,
 }
 
 impl ExecutorQueues {
-    pub fn new(constellation_identifier: Arc<Mutex<ConstellationIdentifier>>) -> ExecutorQueues {
+    pub fn new(
+        constellation_identifier: Arc<Mutex<ConstellationIdentifier>>,
+        contexts: Option<ContextVec>,
+    ) -> ExecutorQueues {
         ExecutorQueues {
             const_id: constellation_identifier,
-            activities: Arc::new(Mutex::new(HashMap::new())),
-            activities_suspended: Arc::new(Mutex::new(HashMap::new())),
-            event_queue: Arc::new(Mutex::new(EventQueue::new())),
+            activities: Arc::new(Mutex::new(ActivityQueue::new())),
+            activities_suspended: Arc::new(Mutex::new(ActivityQueue::new())),
+            event_queue: Arc::new(Mutex::new(EventQueue::with_arrivals())),
+            execution: Arc::new(Mutex::new(ExecutionMonitor::new())),
+            parker: Arc::new(Parker::new()),
+            contexts,
+            iterations: Arc::new(AtomicU64::new(0)),
         }
     }
+
+    /// Whether this thread may execute activities with the given context
+    pub fn serves(&self, context: &Context) -> bool {
+        self.contexts
+            .as_ref()
+            .map_or(true, |contexts| contexts.contains(context))
+    }
 }
 
 /// Structure holding a shared activity and event queue, which is used to pass
@@ -62,31 +143,391 @@ impl ExecutorQueues {
 /// # Members
 /// * `activities` - Reference to an Injector queue containing activities
 /// * `events` - Reference to an Injector queue containing events
+/// * `kept_events` - Reference to the events kept by the MultiThreadHelper
+/// because their destination was not found yet
+/// * `finished` - Reference to the activities which finished recently
+/// * `context_members` - Reference to the live activities of every context
+/// * `names` - Reference to the names of the live activities
+/// * `delayed_events` - Reference to the queue of events sent with a delay
+/// * `idle_threads` - Number of executor threads which are currently idle
+/// * `steal_counters` - Statistics on stealing activities in batches
+/// * `delivered_events` - Number of events delivered to activities
+/// * `registry` - Threads registered with the MultiThreadHelper
+/// * `schedule_log` - Optional structured log of scheduling decisions
+/// * `schedule_trace` - Optional recording or replay of scheduling decisions
+/// * `observer` - Optional observer of scheduling decisions
+/// * `pause` - Gate closed while the instance is paused
+/// * `idle_monitor` - Keeps track of the threads which are working
+/// * `max_activities_per_thread` - Optional cap on the number of activities
+/// queued on a thread, see `submit_near(..)`
+/// * `parent_thread_load_factor` - Load above which `submit_near(..)` leaves
+/// the placement to the MultiThreadHelper
+/// * `balancer` - Parker of the `run` method of the MultiThreadHelper,
+/// unparked after handing it activities or events
 #[derive(Clone)]
 pub struct ThreadHelper {
     activities: Arc<Mutex<deque::Injector<Box<dyn ActivityWrapperTrait>>>>,
     events: Arc<Mutex<deque::Injector<Box<Event>>>>,
+    kept_events: Arc<ShardedEventQueue>,
+    finished: Arc<Mutex<FinishedActivities>>,
+    context_members: Arc<Mutex<ContextMembers>>,
+    names: Arc<Mutex<ActivityNames>>,
+    delayed_events: Arc<Mutex<DelayedEvents>>,
+    idle_threads: Arc<AtomicUsize>,
+    steal_counters: Arc<StealCounters>,
+    delivered_events: Arc<AtomicU64>,
+    registry: Arc<Mutex<ThreadRegistry>>,
+    schedule_log: Option<ScheduleLogger>,
+    schedule_trace: Option<Arc<ScheduleTrace>>,
+    observer: Option<Sender<SchedulerEvent>>,
+    pause: Arc<PauseGate>,
+    idle_monitor: Arc<IdleMonitor>,
+    max_activities_per_thread: Option<usize>,
+    parent_thread_load_factor: f64,
+    balancer: Arc<Parker>,
 }
 
 impl ThreadHelper {
-    pub fn new(
-        activities: Arc<Mutex<deque::Injector<Box<dyn ActivityWrapperTrait>>>>,
-        events: Arc<Mutex<deque::Injector<Box<Event>>>>,
-    ) -> ThreadHelper {
-        ThreadHelper { activities, events }
+    /// Number of threads registered with the MultiThreadHelper
+    pub fn thread_count(&self) -> usize {
+        self.registry.lock().unwrap().threads.len()
+    }
+
+    /// Take a snapshot of the queues of all registered threads and the kept
+    /// events, see ConstellationSnapshot. The activities held back by the
+    /// load balancer are only known to the MultiThreadHelper.
+    ///
+    /// # Arguments
+    /// * `node` - Identifier of this node
+    pub fn snapshot(&self, node: usize) -> ConstellationSnapshot {
+        let mut snapshot = ConstellationSnapshot::now(node);
+        let threads = match snapshot::try_lock(&self.registry) {
+            Some(registry) => registry.threads.clone(),
+            None => {
+                snapshot.skipped.push("thread registry".to_string());
+                Vec::new()
+            }
+        };
+
+        add_threads(&mut snapshot, &threads);
+        snapshot.add_orphaned_events(&self.kept_events);
+        add_dead_lettered(&mut snapshot, &self.finished);
+
+        snapshot
+    }
+
+    /// Whether a registered executor thread serves the given context
+    pub fn serves(&self, context: &Context) -> bool {
+        self.registry
+            .lock()
+            .unwrap()
+            .threads
+            .iter()
+            .any(|t| t.1.serves(context))
     }
 
     /// Can be called from inside the InnerConstellation to share with
     /// MultiThreadHelper
-    pub fn submit(&mut self, activity_wrapper: Box<ActivityWrapper>) {
+    pub fn submit(&self, activity_wrapper: Box<ActivityWrapper>) {
         self.activities.lock().unwrap().push(activity_wrapper);
+        self.balancer.unpark();
+    }
+
+    /// Insert an activity directly in the work queue of the executor thread
+    /// it was submitted from, see `SubmitOptions::prefer_parent_thread`. The
+    /// activity is handed to the MultiThreadHelper instead when the thread
+    /// does not serve its context, is at `max_activities_per_thread`, or has
+    /// more than `parent_thread_load_factor` times the average number of
+    /// activities queued on the other threads. When a schedule is replayed,
+    /// the activity is always handed to the MultiThreadHelper, which places
+    /// it on the thread it was placed on before.
+    ///
+    /// # Arguments
+    /// * `work_queue` - Work queue of the executor thread
+    /// * `activity_wrapper` - The activity to submit
+    pub fn submit_near(
+        &self,
+        work_queue: &Arc<Mutex<ActivityQueue>>,
+        activity_wrapper: Box<ActivityWrapper>,
+    ) {
+        if self
+            .schedule_trace
+            .as_ref()
+            .map_or(false, |t| t.is_replaying())
+        {
+            return self.submit(activity_wrapper);
+        }
+
+        let queues: Vec<ExecutorQueues> = self
+            .registry
+            .lock()
+            .unwrap()
+            .threads
+            .iter()
+            .map(|t| t.1.clone())
+            .collect();
+
+        let index = match queues
+            .iter()
+            .position(|q| Arc::ptr_eq(&q.activities, work_queue))
+        {
+            Some(index) if queues[index].serves(activity_wrapper.context()) => index,
+            _ => return self.submit(activity_wrapper),
+        };
+
+        let queued: Vec<usize> = queues
+            .iter()
+            .map(|q| q.activities.lock().unwrap().len())
+            .collect();
+        let own = queued[index];
+        if self
+            .max_activities_per_thread
+            .map_or(false, |cap| own >= cap)
+        {
+            return self.submit(activity_wrapper);
+        }
+        if queued.len() > 1 {
+            let others = (queued.iter().sum::<usize>() - own) as f64 / (queued.len() - 1) as f64;
+            if own as f64 > self.parent_thread_load_factor * others.max(1.0) {
+                return self.submit(activity_wrapper);
+            }
+        }
+
+        if let Some(logger) = &self.schedule_log {
+            logger.record(submit_record(&*activity_wrapper, Some(index)));
+        }
+        scheduler_event::observe(&self.observer, || {
+            placement_event(&*activity_wrapper, Some(index))
+        });
+
+        let aid = activity_wrapper.activity_identifier().clone();
+        if let Some(trace) = &self.schedule_trace {
+            trace.placed(&aid, index);
+        }
+        work_queue.lock().unwrap().insert(aid, activity_wrapper);
+        queues[index].parker.unpark();
     }
 
     /// Can be called from inside the InnerConstellation to share with
     /// MultiThreadHelper
-    pub fn send(&mut self, e: Box<Event>) {
+    pub fn send(&self, e: Box<Event>) {
         self.events.lock().unwrap().push(e);
+        self.balancer.unpark();
+    }
+
+    /// Check whether events for the activity may still be routed by the
+    /// MultiThreadHelper. As long as this is the case, an event for the
+    /// activity must be routed as well, instead of being delivered on the
+    /// thread directly, to keep the events in the order they were sent.
+    ///
+    /// The MultiThreadHelper holds the lock on the events while routing them,
+    /// so an event taken from the queue has reached its thread, or is kept,
+    /// when this method gets the lock.
+    ///
+    /// # Arguments
+    /// * `aid` - Identifier of the destination activity
+    ///
+    /// # Returns
+    /// * `bool` - true if events for the activity may be in flight
+    pub fn events_in_flight(&self, aid: &ActivityIdentifier) -> bool {
+        let guard = self.events.lock().unwrap();
+        !guard.is_empty() || self.kept_events.shard(aid).contains_key(aid)
+    }
+
+    /// Check whether the activity is known to have finished
+    pub fn has_finished(&self, aid: &ActivityIdentifier) -> bool {
+        self.finished.lock().unwrap().contains(aid)
+    }
+
+    /// Take the events kept by the MultiThreadHelper for an activity which
+    /// was not found in the queues of any thread, in the order they were
+    /// routed. Used by an executor thread replaying a schedule, while the
+    /// activity waits outside of its queues for the events it received
+    /// before.
+    pub(crate) fn take_kept_events(&self, aid: &ActivityIdentifier) -> Vec<Box<Event>> {
+        self.kept_events.shard(aid).drain_for(aid)
+    }
+
+    /// The activities which finished recently, shared with the
+    /// MultiThreadHelper. Executor threads record the activities they finish
+    /// here, so that events for them are dropped.
+    pub fn finished_activities(&self) -> Arc<Mutex<FinishedActivities>> {
+        self.finished.clone()
+    }
+
+    /// The live activities of every context, shared with the
+    /// MultiThreadHelper and all executor threads
+    pub fn context_members(&self) -> Arc<Mutex<ContextMembers>> {
+        self.context_members.clone()
+    }
+
+    /// The names of the live activities, shared with the MultiThreadHelper
+    /// and all executor threads
+    pub fn activity_names(&self) -> Arc<Mutex<ActivityNames>> {
+        self.names.clone()
+    }
+
+    /// The gate closed while the instance is paused, shared with the
+    /// MultiThreadHelper and all executor threads
+    pub fn pause_gate(&self) -> Arc<PauseGate> {
+        self.pause.clone()
+    }
+
+    /// The monitor of the threads which are working, shared with the
+    /// MultiThreadHelper and all executor threads
+    pub fn idle_monitor(&self) -> Arc<IdleMonitor> {
+        self.idle_monitor.clone()
     }
+
+    /// The recording or replay of scheduling decisions, shared with the
+    /// MultiThreadHelper and all executor threads
+    pub(crate) fn schedule_trace(&self) -> Option<Arc<ScheduleTrace>> {
+        self.schedule_trace.clone()
+    }
+
+    /// Record the submission of an activity in the schedule trace, called on
+    /// the thread submitting it
+    pub(crate) fn trace_submitted(&self, aid: &ActivityIdentifier) {
+        if let Some(trace) = &self.schedule_trace {
+            trace.submitted(aid);
+        }
+    }
+
+    /// Record the sending of an event in the schedule trace, called on the
+    /// thread sending it before anything else is done with it
+    pub(crate) fn trace_sent(&self, e: &Event) {
+        if let Some(trace) = &self.schedule_trace {
+            trace.sent(e);
+        }
+    }
+
+    /// Can be called from inside the InnerConstellation to share with
+    /// MultiThreadHelper
+    pub fn send_after(&self, e: Box<Event>, delay: Duration) -> DelayedEventToken {
+        self.trace_sent(&e);
+        self.delayed_events.lock().unwrap().push(e, delay)
+    }
+
+    /// Hold back an event delayed by the fault injector, it is routed by the
+    /// MultiThreadHelper once it is due. Unlike `send_after(..)` the event is
+    /// not traced again.
+    #[cfg(feature = "fault-injection")]
+    pub(crate) fn delay_injected(&self, e: Box<Event>, delay: Duration) {
+        self.delayed_events.lock().unwrap().push(e, delay);
+    }
+
+    /// Can be called from inside the InnerConstellation to share with
+    /// MultiThreadHelper
+    pub fn cancel_delayed(&self, token: DelayedEventToken) -> bool {
+        self.delayed_events.lock().unwrap().cancel(token)
+    }
+
+    /// Can be called from the executor thread to hand activities back to the
+    /// MultiThreadHelper, which redistributes them. Every activity counts one
+    /// migration. The events queued for the activities on the thread are
+    /// kept by the MultiThreadHelper, which delivers them once the activities
+    /// are placed again.
+    ///
+    /// The lock on the finished activities is held while the events are
+    /// taken, the MultiThreadHelper holds it while routing an event, so no
+    /// event reaches the thread after the activities were taken from its
+    /// queues.
+    ///
+    /// # Arguments
+    /// * `thread_id` - Id of the executor thread handing the activities back
+    /// * `activities` - The activities, no longer in the queues of the thread
+    /// * `event_queue` - The event queue of the thread
+    pub fn shed(
+        &self,
+        thread_id: i32,
+        activities: Vec<Box<dyn ActivityWrapperTrait>>,
+        event_queue: &Mutex<EventQueue>,
+    ) {
+        if let Some(logger) = &self.schedule_log {
+            logger.record(ScheduleRecord::Rebalance {
+                thread: thread_id,
+                activities: activities.len(),
+            });
+        }
+
+        let finished = self.finished.lock().unwrap();
+        {
+            let mut event_queue = event_queue.lock().unwrap();
+            for activity in &activities {
+                let aid = activity.activity_identifier();
+                let events = event_queue.drain_for(aid);
+                if !events.is_empty() {
+                    let mut kept = self.kept_events.shard(aid);
+                    for event in events {
+                        kept.insert(aid.clone(), event);
+                    }
+                }
+            }
+        }
+
+        let guard = self.activities.lock().unwrap();
+        for mut activity in activities {
+            activity.record_migration(thread_id);
+            guard.push(activity);
+        }
+        drop(guard);
+        drop(finished);
+        self.balancer.unpark();
+    }
+
+    /// Record that an executor thread became idle, or is no longer idle
+    pub fn set_idle(&self, idle: bool) {
+        if idle {
+            self.idle_threads.fetch_add(1, Ordering::SeqCst);
+        } else {
+            self.idle_threads.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Number of executor threads which are currently idle
+    pub fn idle_threads(&self) -> usize {
+        self.idle_threads.load(Ordering::SeqCst)
+    }
+
+    /// Record that the executor thread with the given id took the given
+    /// number of activities from its work queue in one steal
+    pub fn record_steal(&self, thread_id: i32, items: usize) {
+        self.steal_counters.record(items);
+
+        if let (Some(logger), true) = (&self.schedule_log, items > 0) {
+            logger.record(ScheduleRecord::Steal {
+                thread: thread_id,
+                activities: items,
+            });
+        }
+    }
+
+    /// Record that an activity on the executor thread yielded
+    pub fn record_yield(&self) {
+        self.steal_counters.record_yield();
+    }
+
+    /// Record that events were delivered to an activity, the load balancer
+    /// counts them as progress, see ProgressMonitor
+    pub fn record_delivered(&self, events: usize) {
+        self.delivered_events
+            .fetch_add(events as u64, Ordering::Relaxed);
+    }
+}
+
+/// Type of the entries in the thread list, the InnerConstellation of a thread
+/// together with its queues
+type ThreadEntry = (Arc<Mutex<Box<dyn ConstellationTrait>>>, ExecutorQueues);
+
+/// Threads registered with the MultiThreadHelper, shared between the user
+/// facing instance and the load balancer
+///
+/// # Members
+/// * `threads` - The registered threads
+/// * `generation` - Incremented whenever threads are added or removed
+struct ThreadRegistry {
+    threads: Vec<ThreadEntry>,
+    generation: u64,
 }
 
 /// Structure holding all thread information, references to queues inside
@@ -98,7 +539,13 @@ impl ThreadHelper {
 ///
 /// # Members
 /// * `threads` - Vector containing each threads specific InnerConstellation
-/// instance as well as references to all their queues
+/// instance as well as references to all their queues, a snapshot of the
+/// registry
+/// * `registry` - The threads currently registered, shared with all clones
+/// * `threads_generation` - Generation of the registry the snapshot in
+/// `threads` was taken from
+/// * `balancer_generation` - Generation of the registry last seen by the
+/// `run` method
 /// * `time_between_checks` - The time to wait between checking threads for
 /// activities and events that have been submitted. OPTIMIZATION: This can be
 /// fine-tuned for performance depending on application, for example: if an
@@ -111,40 +558,323 @@ impl ThreadHelper {
 /// * `events_from_threads` - Events passed on from threads, should be shared
 /// with the ThreadHelper
 /// * `local_events` - Stores events which have no matching activity on this
-/// node
+/// node, sharded by destination
+/// * `finished` - Activities which finished recently, events for them are
+/// dropped instead of being distributed or kept, should be shared with the
+/// ThreadHelper
+/// * `context_members` - Live activities of every context, should be shared
+/// with the ThreadHelper
+/// * `names` - Names of the live activities, owned by the
+/// MultiThreadedConstellation and should be shared with the ThreadHelper
+/// * `max_activities_per_thread` - Optional cap on the number of activities in
+/// the work queue of each thread
+/// * `overflow` - Activities which could not be placed because all threads
+/// were at the cap, drained by the `run` method
+/// * `overflow_count` - Number of times an activity could not be placed on
+/// the thread it was meant for because of the cap
+/// * `delayed_events` - Events sent with a delay, routed by the `run` method
+/// when they are due, should be shared with the ThreadHelper
+/// * `execution_timeout_callback` - Called for activities exceeding their
+/// maximum execution time
+/// * `idle_threads` - Number of idle executor threads, should be shared with
+/// the ThreadHelper
+/// * `steal_batch_size` - Maximum number of activities taken from
+/// `activities_from_threads` per lock acquisition
+/// * `steal_counters` - Statistics on stealing activities in batches, should
+/// be shared with the ThreadHelper
+/// * `event_interceptors` - Interceptors run on events sent by the user and
+/// on delayed events once they are due
+/// * `comm` - Communication with the other nodes, used to exchange load
+/// reports, steal activities and forward events
+/// * `node_id` - Identifier of this node, its rank in `comm`
+/// * `master_rank` - Rank of the master node, see `Placement::MasterOnly`
+/// * `remote` - State of the stealing between nodes, see RemoteSteal
+/// * `remote_steal_strategy` - Decides which activities are handed to
+/// another node first: the biggest or the smallest
+/// * `decoders` - Decoders of the activities and payloads received from
+/// other nodes
+/// * `load_report_interval` - Time between two load reports
+/// * `last_load_report` - When the load of this node was last recorded
+/// * `load_table` - The last load reported by every node, including this one
+/// * `schedule_log` - Optional structured log of scheduling decisions
+/// * `last_log_flush` - When the schedule log was last flushed
+/// * `schedule_trace` - Optional recording or replay of scheduling
+/// decisions, shared with the ThreadHelper
+/// * `pause` - Gate closed while the instance is paused, no activities or
+/// events are routed while it is closed. Shared with the ThreadHelper.
+/// * `idle_monitor` - Keeps track of the threads which are working, the `run`
+/// method counts as working while it routes activities and events. Shared
+/// with the ThreadHelper.
+/// * `queue_sample_interval` - Optional time between two samples of the
+/// queue depths of the threads
+/// * `last_queue_sample` - When the queue depths were last sampled
+/// * `queue_depths` - Histograms of the sampled queue depths, by thread index
+/// * `metrics_sink` - Optional writer of periodic ConstellationStats snapshots
+/// * `last_metrics_export` - When the last snapshot was written
+/// * `parent_thread_load_factor` - Handed to the ThreadHelper, see
+/// `ThreadHelper::submit_near(..)`
+/// * `clock` - The clock deciding when activities exceed their maximum
+/// execution time, and when the instance made no progress for too long
+/// * `observer` - Optional observer of scheduling decisions, shared with the
+/// ThreadHelper
+/// * `rebalance_threshold` - Optional ratio between the pending work of the
+/// most and the least loaded thread above which queued activities are moved,
+/// see `rebalance()`
+/// * `last_rebalance` - When the `run` method last checked the balance
+/// * `balancer_parker` - Parker of the `run` method, which waits on it for
+/// at most `time_between_steals` between two passes. Shared with the
+/// ThreadHelper, which unparks it when it hands over activities or events.
+/// * `delivered_events` - Number of events delivered to activities, counted
+/// by the executor threads. Shared with the ThreadHelper.
+/// * `progress_monitor` - Detects stalls of the instance, None unless a
+/// `no_progress_timeout` is configured
+/// * `no_progress_callback` - Optional function called with a snapshot of
+/// the queues when a stall is detected
+/// * `fault_injector` - Injects faults in the events routed after the
+/// interceptors, see FaultInjector
 #[derive(Clone)]
 pub struct MultiThreadHelper {
-    pub threads: Vec<(Arc<Mutex<Box<dyn ConstellationTrait>>>, ExecutorQueues)>,
+    pub threads: Vec<ThreadEntry>,
+    registry: Arc<Mutex<ThreadRegistry>>,
+    threads_generation: u64,
+    balancer_generation: Arc<AtomicU64>,
     time_between_steals: time::Duration,
     debug: bool,
     activities_from_threads: Arc<Mutex<deque::Injector<Box<dyn ActivityWrapperTrait>>>>,
     events_from_threads: Arc<Mutex<deque::Injector<Box<Event>>>>,
-    local_events: Arc<Mutex<EventQueue>>,
+    local_events: Arc<ShardedEventQueue>,
+    finished: Arc<Mutex<FinishedActivities>>,
+    context_members: Arc<Mutex<ContextMembers>>,
+    names: Arc<Mutex<ActivityNames>>,
+    max_activities_per_thread: Option<usize>,
+    overflow: Arc<Mutex<VecDeque<Box<dyn ActivityWrapperTrait>>>>,
+    overflow_count: Arc<AtomicUsize>,
+    delayed_events: Arc<Mutex<DelayedEvents>>,
+    execution_timeout_callback: Option<ExecutionTimeoutCallback>,
+    idle_threads: Arc<AtomicUsize>,
+    steal_batch_size: usize,
+    steal_counters: Arc<StealCounters>,
+    event_interceptors: Vec<EventInterceptor>,
+    comm: Arc<dyn Communication>,
+    node_id: usize,
+    master_rank: i32,
+    remote: Arc<Mutex<RemoteSteal>>,
+    remote_steal_strategy: StealStrategy,
+    decoders: Decoders,
+    load_report_interval: Duration,
+    last_load_report: Option<Instant>,
+    load_table: Arc<Mutex<LoadTable>>,
+    schedule_log: Option<ScheduleLogger>,
+    last_log_flush: Instant,
+    schedule_trace: Option<Arc<ScheduleTrace>>,
+    pause: Arc<PauseGate>,
+    idle_monitor: Arc<IdleMonitor>,
+    queue_sample_interval: Option<Duration>,
+    last_queue_sample: Option<Instant>,
+    queue_depths: Arc<Mutex<Vec<QueueDepthStats>>>,
+    metrics_sink: Option<MetricsSink>,
+    last_metrics_export: Option<Instant>,
+    parent_thread_load_factor: f64,
+    clock: Clock,
+    observer: Option<Sender<SchedulerEvent>>,
+    rebalance_threshold: Option<f64>,
+    last_rebalance: Option<Instant>,
+    balancer_parker: Arc<Parker>,
+    delivered_events: Arc<AtomicU64>,
+    progress_monitor: Option<ProgressMonitor>,
+    no_progress_callback: Option<NoProgressCallback>,
+    #[cfg(feature = "fault-injection")]
+    fault_injector: Option<Arc<FaultInjector>>,
 }
 
 impl MultiThreadHelper {
     /// Create new, clean instance
     ///
     /// # Arguments
-    /// * `debug` - Boolean indicating whether to print debug messages or not
-    /// * `activities_from_threads` - Activities passed on from threads,
-    /// should be shared with the ThreadHelper
-    /// * `events_from_threads` - Events passed on from threads, should be shared
-    /// with the ThreadHelper
+    /// * `config` - The configuration of the instance, e.g. the time between
+    /// steals, the per thread cap and the load reports
+    /// * `comm` - Communication with the other nodes, the rank of this node
+    /// is its identifier
+    /// * `names` - Names of the live activities, also used by the activity
+    /// registry of the node
+    /// * `schedule_log` - Optional structured log of scheduling decisions
+    /// * `schedule_trace` - Optional recording or replay of scheduling
+    /// decisions
     pub fn new(
-        debug: bool,
-        activities_from_threads: Arc<Mutex<deque::Injector<Box<dyn ActivityWrapperTrait>>>>,
-        events_from_threads: Arc<Mutex<deque::Injector<Box<Event>>>>,
-        time_between_steals: u64,
+        config: &ConstellationConfiguration,
+        comm: Arc<dyn Communication>,
+        names: Arc<Mutex<ActivityNames>>,
+        schedule_log: Option<Arc<ScheduleLog>>,
+        schedule_trace: Option<Arc<ScheduleTrace>>,
     ) -> MultiThreadHelper {
+        let clock = config.clock.clone();
+
+        // A manual clock wakes the `run` method up when delayed events may
+        // have become due
+        let balancer_parker = Arc::new(Parker::new());
+        clock.wake_on_advance(&balancer_parker);
+
+        let progress_monitor = config
+            .no_progress_timeout
+            .map(|timeout| ProgressMonitor::new(timeout, clock.now()));
+
         MultiThreadHelper {
             threads: Vec::new(),
-            time_between_steals: time::Duration::from_micros(time_between_steals),
-            debug,
-            activities_from_threads,
-            events_from_threads,
-            local_events: Arc::new(Mutex::new(EventQueue::new())),
+            registry: Arc::new(Mutex::new(ThreadRegistry {
+                threads: Vec::new(),
+                generation: 0,
+            })),
+            threads_generation: 0,
+            balancer_generation: Arc::new(AtomicU64::new(0)),
+            time_between_steals: config.time_between_steals,
+            debug: config.debug,
+            activities_from_threads: Arc::new(Mutex::new(deque::Injector::new())),
+            events_from_threads: Arc::new(Mutex::new(deque::Injector::new())),
+            local_events: Arc::new(ShardedEventQueue::new(sharded_event_queue::SHARDS)),
+            finished: Arc::new(Mutex::new(FinishedActivities::new(
+                FINISHED_ACTIVITIES_CAPACITY,
+            ))),
+            context_members: Arc::new(Mutex::new(ContextMembers::new())),
+            names,
+            max_activities_per_thread: config.max_activities_per_thread,
+            overflow: Arc::new(Mutex::new(VecDeque::new())),
+            overflow_count: Arc::new(AtomicUsize::new(0)),
+            delayed_events: Arc::new(Mutex::new(DelayedEvents::new(clock.clone()))),
+            execution_timeout_callback: config.execution_timeout_callback,
+            idle_threads: Arc::new(AtomicUsize::new(0)),
+            steal_batch_size: config.steal_batch_size.max(1),
+            steal_counters: Arc::new(StealCounters::new()),
+            event_interceptors: config.event_interceptors.clone(),
+            node_id: comm.rank() as usize,
+            comm,
+            master_rank: config.master_rank,
+            remote: Arc::new(Mutex::new(RemoteSteal::new())),
+            remote_steal_strategy: config.remote_steal_strategy.clone(),
+            decoders: config.decoders.clone(),
+            load_report_interval: config.load_report_interval,
+            last_load_report: None,
+            load_table: Arc::new(Mutex::new(LoadTable::new(
+                config.load_report_interval,
+                config.load_report_stale_intervals,
+            ))),
+            schedule_log: schedule_log.as_ref().map(ScheduleLog::logger),
+            last_log_flush: Instant::now(),
+            schedule_trace,
+            pause: Arc::new(PauseGate::new()),
+            idle_monitor: Arc::new(IdleMonitor::new()),
+            queue_sample_interval: config.queue_sample_interval,
+            last_queue_sample: None,
+            queue_depths: Arc::new(Mutex::new(Vec::new())),
+            metrics_sink: config.metrics_sink.clone(),
+            last_metrics_export: None,
+            parent_thread_load_factor: config.parent_thread_load_factor,
+            clock,
+            observer: config.observer.clone(),
+            rebalance_threshold: config.rebalance_threshold,
+            last_rebalance: None,
+            balancer_parker,
+            delivered_events: Arc::new(AtomicU64::new(0)),
+            progress_monitor,
+            no_progress_callback: config.on_no_progress.clone(),
+            #[cfg(feature = "fault-injection")]
+            fault_injector: config.fault_injector.clone(),
+        }
+    }
+
+    /// Number of times an activity exceeded the per thread cap on the thread
+    /// it was meant for, and had to be placed elsewhere or held back.
+    pub fn overflow_count(&self) -> usize {
+        self.overflow_count.load(Ordering::Relaxed)
+    }
+
+    /// Statistics on stealing activities in batches, by the load balancer and
+    /// the executor threads
+    pub fn steal_stats(&self) -> StealStats {
+        self.steal_counters.snapshot()
+    }
+
+    /// Check whether the activity is known to have finished
+    pub fn has_finished(&self, aid: &ActivityIdentifier) -> bool {
+        self.finished.lock().unwrap().contains(aid)
+    }
+
+    /// The live activities with the given context, see
+    /// `ConstellationTrait::send_to_context(..)`
+    pub fn context_members(&self, context: &Context) -> Vec<ActivityIdentifier> {
+        self.context_members.lock().unwrap().members(context)
+    }
+
+    /// Submit an activity under a name, see `submit(..)` and
+    /// `ConstellationTrait::submit_named(..)`
+    pub fn submit_named(
+        &mut self,
+        name: &str,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<ActivityIdentifier, ConstellationError> {
+        // Keep the registry locked until the name is registered, so the name
+        // can not be taken in between and the activity can not finish before
+        let names = self.names.clone();
+        let mut names = names.lock().unwrap();
+        if names.contains(name) {
+            return Err(ConstellationError::DuplicateName(name.to_string()));
+        }
+
+        let aid = self.submit(activity, context, options);
+        names.register(name, aid.clone());
+
+        Ok(aid)
+    }
+
+    /// Number of events dropped because their destination activity had
+    /// finished
+    pub fn dropped_events(&self) -> usize {
+        self.finished.lock().unwrap().dropped_events()
+    }
+
+    /// Number of iterations of the loop of every executor thread so far, by
+    /// thread index. Idle threads park, so their count barely grows.
+    pub fn loop_iterations(&self) -> Vec<u64> {
+        self.threads
+            .iter()
+            .map(|(_, queues)| queues.iterations.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// Histograms of the queue depths sampled on every thread, empty unless
+    /// `queue_sample_interval` is set
+    pub fn queue_depth_stats(&self) -> Vec<QueueDepthStats> {
+        self.queue_depths.lock().unwrap().clone()
+    }
+
+    /// Take a snapshot of the statistics of this node
+    pub fn stats(&mut self) -> ConstellationStats {
+        self.sync_threads();
+
+        let mut stats = ConstellationStats::now(self.node_id);
+        for (_, queues) in self.threads.iter() {
+            stats.pending += queues.activities.lock().unwrap().len();
+            stats.suspended += queues.activities_suspended.lock().unwrap().len();
+            stats.events += queues.event_queue.lock().unwrap().len();
         }
+        stats.events += self.local_events.len();
+        stats.held_back = self.overflow.lock().unwrap().len();
+        stats.overflows = self.overflow_count() as u64;
+
+        let finished = self.finished.lock().unwrap();
+        stats.activities_finished = finished.total();
+        stats.dropped_events = finished.dropped_events();
+        drop(finished);
+
+        stats.steal_stats = self.steal_counters.snapshot();
+
+        stats
+    }
+
+    /// The last load reported by every node
+    pub fn cluster_load(&self) -> Vec<LoadEntry> {
+        self.load_table.lock().unwrap().snapshot()
     }
 
     /// Push new thread
@@ -159,7 +889,164 @@ impl MultiThreadHelper {
         executor_queues: ExecutorQueues,
         constellation: Arc<Mutex<Box<dyn ConstellationTrait>>>,
     ) {
-        self.threads.push((constellation, executor_queues));
+        self.register_threads(vec![(constellation, executor_queues)]);
+    }
+
+    /// Create a ThreadHelper sharing the queues of this MultiThreadHelper,
+    /// used to pass activities and events from a new thread to the `run`
+    /// method
+    pub fn thread_helper(&self) -> ThreadHelper {
+        ThreadHelper {
+            activities: self.activities_from_threads.clone(),
+            events: self.events_from_threads.clone(),
+            kept_events: self.local_events.clone(),
+            finished: self.finished.clone(),
+            context_members: self.context_members.clone(),
+            names: self.names.clone(),
+            delayed_events: self.delayed_events.clone(),
+            idle_threads: self.idle_threads.clone(),
+            steal_counters: self.steal_counters.clone(),
+            delivered_events: self.delivered_events.clone(),
+            registry: self.registry.clone(),
+            schedule_log: self.schedule_log.clone(),
+            schedule_trace: self.schedule_trace.clone(),
+            observer: self.observer.clone(),
+            pause: self.pause.clone(),
+            idle_monitor: self.idle_monitor.clone(),
+            max_activities_per_thread: self.max_activities_per_thread,
+            parent_thread_load_factor: self.parent_thread_load_factor,
+            balancer: self.balancer_parker.clone(),
+        }
+    }
+
+    /// Wake the `run` method up, for example after signalling it to shut
+    /// down
+    pub fn wake(&self) {
+        self.balancer_parker.unpark();
+    }
+
+    /// Stop the executor threads and the `run` method from picking up work,
+    /// see `ConstellationTrait::pause()`
+    pub fn pause(&self) {
+        self.pause.pause();
+    }
+
+    /// Let the executor threads and the `run` method pick up work again
+    pub fn resume(&mut self) {
+        self.pause.resume();
+        self.wake();
+
+        self.sync_threads();
+        for (_, queues) in self.threads.iter() {
+            queues.parker.unpark();
+        }
+    }
+
+    /// Wait until the executor threads and the `run` method stopped picking
+    /// up work after `pause()`
+    ///
+    /// # Arguments
+    /// * `timeout` - Maximum time to wait
+    ///
+    /// # Returns
+    /// * `bool` - true if all of them are quiescent, false if the instance is
+    /// not paused or the timeout expired
+    pub fn wait_until_paused(&self, timeout: Duration) -> bool {
+        self.pause.wait_until_quiescent(timeout)
+    }
+
+    /// Wait until no thread is working and no activities or events are left,
+    /// see `ConstellationTrait::wait_until_idle(..)`
+    ///
+    /// # Arguments
+    /// * `timeout` - Maximum time to wait
+    ///
+    /// # Returns
+    /// * `bool` - true if the instance is idle, false if the timeout expired
+    pub fn wait_until_idle(&mut self, timeout: Duration) -> bool {
+        let idle_monitor = self.idle_monitor.clone();
+
+        idle_monitor.wait_until_idle(timeout, || {
+            self.work_left() || !self.local_events.is_empty()
+        })
+    }
+
+    /// Number of threads currently registered
+    pub fn thread_count(&mut self) -> usize {
+        self.sync_threads();
+        self.threads.len()
+    }
+
+    /// Whether a registered thread serves the given context
+    pub fn serves(&mut self, context: &Context) -> bool {
+        self.sync_threads();
+        self.is_served(context)
+    }
+
+    /// The contexts served by each registered thread, by thread index
+    ///
+    /// # Returns
+    /// * `Vec<Option<ContextVec>>` - The contexts of each thread, None for
+    /// threads executing activities of any context
+    pub fn thread_contexts(&mut self) -> Vec<Option<ContextVec>> {
+        self.sync_threads();
+        self.threads.iter().map(|t| t.1.contexts.clone()).collect()
+    }
+
+    /// Retire the last `n` threads. The load balancer stops placing work on
+    /// them, after which their activities and events are migrated to the
+    /// remaining threads and the threads are shut down.
+    ///
+    /// Threads holding activities which may not be stolen, or activities of a
+    /// context no remaining thread serves, are not retired.
+    ///
+    /// # Arguments
+    /// * `n` - Number of threads to retire, at least one thread must remain
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - ConstellationError::Failed if the
+    /// threads could not be retired, threads which were not retired keep
+    /// running
+    pub fn retire_threads(&mut self, n: usize) -> Result<(), ConstellationError> {
+        let count = self.thread_count();
+        if n >= count {
+            warn!(
+                "Can not retire {} of {} threads, at least one thread must remain",
+                n, count
+            );
+            return Err(ConstellationError::Failed);
+        }
+
+        // Make sure the load balancer no longer places work on the threads
+        let mut retiring = {
+            let mut registry = self.registry.lock().unwrap();
+            let retiring = registry.threads.split_off(count - n);
+            registry.generation += 1;
+            retiring
+        };
+        self.sync_threads();
+        self.wait_for_balancer();
+
+        if let Some(i) = retiring.iter().position(|t| self.holds_unmovable(&t.1)) {
+            warn!(
+                "Thread {} holds activities which can not be moved, not retiring threads",
+                count - n + i
+            );
+            self.register_threads(retiring);
+            return Err(ConstellationError::Failed);
+        }
+
+        while let Some(thread) = retiring.pop() {
+            let index = count - n + retiring.len();
+
+            if let Err(e) = self.retire_thread(index, &thread) {
+                retiring.push(thread);
+                self.register_threads(retiring);
+                return Err(e);
+            }
+        }
+
+        Ok(())
     }
 
     /// Periodically checks for events from the queues which should be shared
@@ -174,33 +1061,91 @@ impl MultiThreadHelper {
     /// * `sender` - The sending channel for this thread
     pub fn run(&mut self, receiver: Receiver<bool>, sender: Sender<bool>) {
         loop {
-            // Check for events from threads
-            if !self.events_from_threads.lock().unwrap().is_empty() {
-                self.handle_thread_events();
-            }
+            // Pick up threads which were added or retired
+            self.sync_threads();
+            self.balancer_generation
+                .store(self.threads_generation, Ordering::SeqCst);
+
+            // Route no activities or events while paused
+            let mut handed_over = false;
+            if let Some(_guard) = PauseGate::enter(&self.pause) {
+                // Count as working before taking anything from the queues
+                let routing = self.has_routing_work();
+                if routing {
+                    self.idle_monitor.set_busy();
+                }
+
+                // Check for events from threads
+                if !self.events_from_threads.lock().unwrap().is_empty() {
+                    self.handle_thread_events();
+                }
+
+                // Check for activities from threads
+                if !self.activities_from_threads.lock().unwrap().is_empty() {
+                    self.handle_thread_activity();
+                }
+
+                // Check local events
+                self.handle_local_events();
+
+                // Place activities held back by the per thread cap, or because
+                // no thread served their context
+                self.handle_overflow();
+
+                // Route delayed events which are due
+                self.handle_delayed_events();
+
+                // Move queued activities away from overloaded threads
+                self.rebalance();
+
+                // Steal activities from, and hand activities to, the other
+                // nodes
+                self.handle_remote();
 
-            // Check for activities from threads
-            if !self.activities_from_threads.lock().unwrap().is_empty() {
-                self.handle_thread_activity();
+                if routing {
+                    self.idle_monitor.set_idle();
+                }
+
+                // Activities and events beyond the batch of this pass are
+                // handled in the next pass right away
+                handed_over = self.has_handed_over_work();
             }
 
-            // Check local events
-            self.handle_local_events();
+            // Report activities exceeding their maximum execution time
+            self.check_execution_times();
+
+            // Report a stall, e.g. activities suspended waiting for each other
+            self.check_progress();
+
+            // Record the load of this node and the loads reported by the
+            // other nodes
+            self.report_load();
+            self.receive_load_reports();
+
+            // Sample the queue depths of the threads
+            self.sample_queue_depths();
+
+            // Write the statistics to the metrics sink
+            self.export_metrics();
+
+            // Write the records buffered in the schedule log
+            self.flush_schedule_log();
 
             // Check for signal to shut down
-            if let Ok(_) = receiver.try_recv().map(|val| {
-                if val {
-                    // Signal that we are shutting down
-                    sender.send(true).expect(
-                        "Failed to send signal to \
-                         InnerConstellation from executor thread",
-                    );
-                    return; // Shutdown thread
-                }
-            }) {};
+            if let Ok(true) = receiver.try_recv() {
+                // Signal that we are shutting down
+                sender.send(true).expect(
+                    "Failed to send signal to \
+                     InnerConstellation from executor thread",
+                );
+                return; // Shutdown thread
+            }
 
-            // Sleep for the given time
-            thread::sleep(self.time_between_steals);
+            // Wait until activities or events are handed over, the timeout
+            // paces the periodic work above
+            if !handed_over {
+                self.balancer_parker.park_timeout(self.time_between_steals);
+            }
         }
     }
 
@@ -213,41 +1158,44 @@ impl MultiThreadHelper {
     /// The activity must be inside an Arc<Mutex<..>>, in order to work with
     /// thread safety.
     /// * `context` - A reference to the context created for this activity.
-    /// * `may_be_stolen` - A boolean indicating whether this activity can be
-    /// stolen or not.
-    /// * `expects_events` - A boolean indicating whether this activity expects
-    /// events or not. Can be used for optimization.
+    /// * `options` - SubmitOptions for this activity, if it has a thread
+    /// affinity it is placed on that thread instead of the least loaded one.
     ///
     /// # Returns
     /// * `ActivityIdentifier` - The generated Activity Identifier for
     /// this Activity
     pub fn submit(
         &mut self,
-        activity: Arc<Mutex<ActivityTrait>>,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
         context: &Context,
-        may_be_stolen: bool,
-        expects_events: bool,
+        options: SubmitOptions,
     ) -> ActivityIdentifier {
-        let index = self.get_thread_with_least_work();
+        self.sync_threads();
+        let index = self.select_thread(options.thread_affinity, context);
 
-        let thread = &self.threads[index].1;
+        // The identifier is generated from the thread the activity is placed
+        // on, any thread will do if no thread serves the context
+        let const_id = self.threads[index.unwrap_or(0)].1.const_id.clone();
 
-        let const_id = thread.const_id.clone();
-
-        let activity_wrapper =
-            ActivityWrapper::new(const_id, activity, context, may_be_stolen, expects_events);
+        let activity_wrapper = ActivityWrapper::new(const_id, activity, context, options);
         let aid = activity_wrapper.activity_identifier().clone();
-
-        if self.debug {
-            info!("Submitting activity with ID: {} to thread: {}", &aid, index);
+        if let Some(trace) = &self.schedule_trace {
+            trace.submitted(&aid);
         }
-
-        self.threads[index]
-            .1
-            .activities
+        self.context_members
             .lock()
             .unwrap()
-            .insert(aid.clone(), activity_wrapper);
+            .add(aid.clone(), context);
+
+        match index {
+            Some(index) => {
+                if self.debug {
+                    info!("Submitting activity with ID: {} to thread: {}", &aid, index);
+                }
+                self.place_activity(index, activity_wrapper);
+            }
+            None => self.hold_back_unserved(activity_wrapper),
+        }
 
         aid
     }
@@ -258,9 +1206,40 @@ impl MultiThreadHelper {
     /// * `e` - Event to send
     pub fn send(&mut self, e: Box<Event>) {
         if self.debug {
-            info!("Send Event: {} -> {}", e.get_src(), e.get_dst());
+            info!("Send Event: {}", e.summary());
         }
-        self.distribute_event(e);
+        if let Some(trace) = &self.schedule_trace {
+            trace.sent(&e);
+        }
+        self.sync_threads();
+        self.intercept_and_distribute(e);
+    }
+
+    /// Send an event after the given delay, it is routed by the `run` method
+    /// once it is due
+    ///
+    /// # Arguments
+    /// * `e` - Event to send
+    /// * `delay` - Time to wait before sending
+    ///
+    /// # Returns
+    /// * `DelayedEventToken` - Token used to cancel the event
+    pub fn send_after(&mut self, e: Box<Event>, delay: Duration) -> DelayedEventToken {
+        if self.debug {
+            info!("Send Event after {:?}: {}", delay, e.summary());
+        }
+        if let Some(trace) = &self.schedule_trace {
+            trace.sent(&e);
+        }
+        self.delayed_events.lock().unwrap().push(e, delay)
+    }
+
+    /// Cancel an event sent with `send_after(..)`
+    ///
+    /// # Returns
+    /// * `bool` - true if the event was still pending
+    pub fn cancel_delayed(&mut self, token: DelayedEventToken) -> bool {
+        self.delayed_events.lock().unwrap().cancel(token)
     }
 
     /// (Try) to perform a graceful shutdown of all threads
@@ -269,41 +1248,423 @@ impl MultiThreadHelper {
     /// * `Result<bool, ConstellationError>` - Result type containing true if
     /// it could successfully shutdown all threads, false otherwise.
     ///
-    /// Upon error a ConstellationError is returned
+    /// Upon error a ConstellationError is returned, WorkLeft with a report on
+    /// all threads if work is left
     pub fn done(&mut self) -> Result<bool, ConstellationError> {
+        self.sync_threads();
+
+        let overflow = self.overflow.lock().unwrap().len();
+        if overflow > 0 {
+            let report = self.work_left_report();
+            warn!(
+                "Found {} activities waiting for a thread below the cap. {}",
+                overflow, report
+            );
+            return Err(ConstellationError::WorkLeft(Box::new(report)));
+        }
+
+        if self.debug && self.max_activities_per_thread.is_some() {
+            info!(
+                "Activities exceeded the per thread cap {} times",
+                self.overflow_count()
+            );
+        }
+
+        // Threads exit when shut down, only start shutting them down once
+        // none of them has work left
+        if self.work_left() {
+            let report = self.work_left_report();
+            warn!("Found work left in threads, not shutting down. {}", report);
+            return Err(ConstellationError::WorkLeft(Box::new(report)));
+        }
+
         for x in 0..self.threads.len() {
-            if let Ok(res) = self.threads[x]
+            let res = self.threads[x]
                 .0
                 .lock()
                 .expect("Could not get lock on constellation instance")
-                .done()
-            {
-                if !res {
-                    return Ok(false);
+                .done();
+
+            match res {
+                Ok(true) => (),
+                Ok(false) => return Ok(false),
+                Err(ConstellationError::WorkLeft(report)) => {
+                    return Err(ConstellationError::WorkLeft(report));
+                }
+                Err(_) => {
+                    warn!("Got Error when shutting down thread: {}", x);
+                    return Err(ConstellationError::Failed);
                 }
-            } else {
-                warn!("Got Error when shutting down thread: {}", x);
-                return Err(ConstellationError);
             }
         }
 
+        let delayed = self.delayed_events.lock().unwrap().len();
+        if delayed > 0 {
+            warn!("Dropping {} delayed events which are not due yet", delayed);
+        }
+
         Ok(true)
     }
 
+    /// Check whether activities or events are left in the queues of the
+    /// threads or are still being routed
+    ///
+    /// # Returns
+    /// * `bool` - true if there is work left
+    pub fn work_left(&mut self) -> bool {
+        self.sync_threads();
+
+        !self.overflow.lock().unwrap().is_empty()
+            || !self.events_from_threads.lock().unwrap().is_empty()
+            || !self.activities_from_threads.lock().unwrap().is_empty()
+            || self.threads.iter().any(|t| has_work(&t.1))
+            || self.remote.lock().unwrap().awaiting_reply(Instant::now())
+    }
+
+    /// Stop asking the other nodes for activities, once the instance is
+    /// shutting down. Activities are still handed to the nodes asking for
+    /// them. Waits for the answer to an outstanding steal request, at most
+    /// STEAL_REPLY_WAIT, so the activities it hands over are not missed.
+    pub fn stop_stealing(&self) {
+        self.remote.lock().unwrap().stopped = true;
+        while self.remote.lock().unwrap().awaiting_reply(Instant::now()) {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Gather a report on the work left in the queues of all threads, the
+    /// activities held back and the events kept because their destination
+    /// was not found
+    ///
+    /// # Returns
+    /// * `WorkLeftReport` - The report, threads are identified by their index
+    pub fn work_left_report(&mut self) -> WorkLeftReport {
+        self.sync_threads();
+
+        let mut report = WorkLeftReport::new();
+        for activity in self.overflow.lock().unwrap().iter() {
+            report.unplaced.push(activity.activity_identifier().clone());
+        }
+        for (index, (_, queues)) in self.threads.iter().enumerate() {
+            report.add_thread(
+                index as i32,
+                &queues.activities.lock().unwrap(),
+                &queues.activities_suspended.lock().unwrap(),
+                &queues.event_queue.lock().unwrap(),
+            );
+        }
+        for shard in self.local_events.shards() {
+            for event in shard.lock().unwrap().events() {
+                report.orphaned_events.push(EventInfo::new(event));
+            }
+        }
+
+        report.snapshot = Some(Box::new(self.snapshot()));
+
+        report
+    }
+
+    /// Take a snapshot of the queues of all threads, the activities held back
+    /// and the events kept because their destination was not found, see
+    /// ConstellationSnapshot. Queues which stay locked are skipped.
+    pub fn snapshot(&mut self) -> ConstellationSnapshot {
+        self.sync_threads();
+
+        let mut snapshot = ConstellationSnapshot::now(self.node_id);
+        add_threads(&mut snapshot, &self.threads);
+        snapshot.add_unplaced(&self.overflow);
+        snapshot.add_orphaned_events(&self.local_events);
+        add_dead_lettered(&mut snapshot, &self.finished);
+
+        snapshot
+    }
+
+    /// Wait until no work is left, or the timeout expires
+    ///
+    /// # Arguments
+    /// * `timeout` - Maximum time to wait
+    ///
+    /// # Returns
+    /// * `bool` - true if no work is left
+    pub fn wait_for_work(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+
+        while self.work_left() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        true
+    }
+
+    /// Shut all threads down forcefully, see `InnerConstellation::
+    /// force_done()`. Activities which are still waiting to be placed on a
+    /// thread did not start, they are told they ended because of the shutdown
+    /// and are dropped together with the events which are still being
+    /// routed.
+    ///
+    /// # Arguments
+    /// * `timeout` - Maximum time to wait for every thread to shut down
+    ///
+    /// # Returns
+    /// * `Result<usize, ConstellationError>` - Number of cancelled activities,
+    /// ConstellationError if a thread could not be shut down
+    pub fn force_done(&mut self, timeout: Duration) -> Result<usize, ConstellationError> {
+        self.sync_threads();
+
+        let mut dropped: Vec<Box<dyn ActivityWrapperTrait>> =
+            self.overflow.lock().unwrap().drain(..).collect();
+        {
+            let guard = self.activities_from_threads.lock().unwrap();
+            loop {
+                match guard.steal() {
+                    Steal::Success(activity) => dropped.push(activity),
+                    Steal::Retry => continue,
+                    Steal::Empty => break,
+                }
+            }
+        }
+
+        let mut events = self.local_events.clear();
+        {
+            let guard = self.events_from_threads.lock().unwrap();
+            loop {
+                match guard.steal() {
+                    Steal::Success(_) => events += 1,
+                    Steal::Retry => continue,
+                    Steal::Empty => break,
+                }
+            }
+        }
+
+        if !dropped.is_empty() || events > 0 {
+            warn!(
+                "Dropping {} activities which were not placed and {} events which were not routed",
+                dropped.len(),
+                events
+            );
+        }
+        for mut activity in dropped {
+            activity.on_complete(CompletionReason::Shutdown);
+        }
+
+        let mut cancelled = 0;
+        for x in 0..self.threads.len() {
+            let mut guard = self.threads[x]
+                .0
+                .lock()
+                .expect("Could not get lock on constellation instance");
+
+            let result = match guard.as_any_mut().downcast_mut::<InnerConstellation>() {
+                Some(inner) => inner.force_done_within(timeout),
+                None => guard.done().map(|_| 0),
+            };
+
+            match result {
+                Ok(n) => cancelled += n,
+                Err(e) => {
+                    warn!("Got Error when shutting down thread: {}", x);
+                    return Err(e);
+                }
+            }
+        }
+
+        let delayed = self.delayed_events.lock().unwrap().len();
+        if delayed > 0 {
+            warn!("Dropping {} delayed events which are not due yet", delayed);
+        }
+
+        Ok(cancelled)
+    }
+
+    /// Refresh the snapshot in `self.threads` if threads were added or
+    /// retired since it was taken
+    fn sync_threads(&mut self) {
+        let registry = self.registry.lock().unwrap();
+
+        if registry.generation != self.threads_generation {
+            self.threads = registry.threads.clone();
+            self.threads_generation = registry.generation;
+        }
+    }
+
+    /// Append threads to the registry, behind the threads already registered
+    fn register_threads(&mut self, threads: Vec<ThreadEntry>) {
+        {
+            let mut registry = self.registry.lock().unwrap();
+            registry.threads.extend(threads);
+            registry.generation += 1;
+        }
+
+        self.sync_threads();
+    }
+
+    /// Wait until the `run` method has picked up the current registry
+    fn wait_for_balancer(&self) {
+        self.wake();
+        while self.balancer_generation.load(Ordering::SeqCst) < self.threads_generation {
+            thread::sleep(self.time_between_steals);
+        }
+    }
+
+    /// Migrate all work of a thread which is no longer registered to the
+    /// remaining threads and shut it down, repeated until the thread has no
+    /// work left.
+    ///
+    /// # Arguments
+    /// * `index` - Index the thread had in the registry
+    /// * `thread` - The thread to retire
+    fn retire_thread(
+        &mut self,
+        index: usize,
+        thread: &ThreadEntry,
+    ) -> Result<(), ConstellationError> {
+        loop {
+            if self.holds_unmovable(&thread.1) {
+                warn!(
+                    "Thread {} holds activities which can not be moved, not retiring it",
+                    index
+                );
+                return Err(ConstellationError::Failed);
+            }
+
+            self.migrate_work(&thread.1);
+
+            match thread.0.lock().unwrap().done() {
+                Ok(true) => {
+                    if self.debug {
+                        info!("Retired thread {}", index);
+                    }
+                    return Ok(());
+                }
+                Ok(false) | Err(ConstellationError::WorkLeft(_)) => {
+                    thread::sleep(self.time_between_steals)
+                }
+                Err(e) => {
+                    warn!("Got Error when retiring thread: {}", index);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Move all activities and events from the queues of a retiring thread
+    /// to the registered threads, every activity moved counts one migration
+    fn migrate_work(&mut self, queues: &ExecutorQueues) {
+        let thread_id = queues.const_id.lock().unwrap().thread_id;
+        let activities: Vec<Box<dyn ActivityWrapperTrait>> = queues
+            .activities
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(_, mut activity)| {
+                activity.record_migration(thread_id);
+                activity
+            })
+            .collect();
+
+        self.distribute_activities(activities);
+
+        let suspended: Vec<(ActivityIdentifier, Box<dyn ActivityWrapperTrait>)> = queues
+            .activities_suspended
+            .lock()
+            .unwrap()
+            .drain()
+            .collect();
+
+        for (aid, mut activity) in suspended {
+            let index = match self.get_thread_with_least_work(activity.context()) {
+                Some(index) => index,
+                None => {
+                    // Keep it, retiring the thread fails
+                    queues
+                        .activities_suspended
+                        .lock()
+                        .unwrap()
+                        .insert(aid, activity);
+                    continue;
+                }
+            };
+
+            activity.record_migration(thread_id);
+            self.observe(|| placement_event(&*activity, Some(index)));
+            self.threads[index]
+                .1
+                .activities_suspended
+                .lock()
+                .unwrap()
+                .insert(aid, activity);
+            self.threads[index].1.parker.unpark();
+        }
+
+        let events: Vec<Box<Event>> = {
+            let mut guard = queues.event_queue.lock().unwrap();
+            let keys: Vec<ActivityIdentifier> = guard.keys().cloned().collect();
+            keys.iter().flat_map(|key| guard.drain_for(key)).collect()
+        };
+
+        for e in events {
+            self.distribute_event(e);
+        }
+    }
+
+    /// Select the thread to place an activity on, this is the thread given by
+    /// the affinity if it exists and serves the context of the activity,
+    /// otherwise the thread with the least work amongst the threads serving
+    /// the context.
+    ///
+    /// # Arguments
+    /// * `thread_affinity` - Optional thread index requested for the activity
+    /// * `context` - The context of the activity
+    ///
+    /// # Returns
+    /// * `Option<usize>` - the index of the selected thread, None if no thread
+    /// serves the context
+    fn select_thread(
+        &mut self,
+        thread_affinity: Option<usize>,
+        context: &Context,
+    ) -> Option<usize> {
+        match thread_affinity {
+            Some(index) if index < self.threads.len() && self.threads[index].1.serves(context) => {
+                Some(index)
+            }
+            _ => self.get_thread_with_least_work(context),
+        }
+    }
+
     /// Find the thread with the least combined work in it's work queue and
-    /// suspended queue.
+    /// suspended queue, amongst the threads serving the given context. The
+    /// work of a thread is the sum of the size hints of all its activities.
+    /// Hung threads are skipped, unless all these threads are hung.
+    ///
+    /// # Arguments
+    /// * `context` - The context the thread must serve
     ///
     /// # Returns
-    /// * `usize` - the index of the thread which has the least work currently.
-    fn get_thread_with_least_work(&mut self) -> usize {
+    /// * `Option<usize>` - the index of the thread which has the least work
+    /// currently, None if no thread serves the context
+    fn get_thread_with_least_work(&mut self, context: &Context) -> Option<usize> {
         let mut shortest = u64::max_value();
-        let mut index = 0;
+        let mut index = None;
 
         for i in 0..self.threads.len() {
-            let length = self.threads[i].1.activities.lock().unwrap().len()
-                + self.threads[i].1.activities_suspended.lock().unwrap().len();
+            if !self.threads[i].1.serves(context) {
+                continue;
+            }
+            if index.is_none() {
+                index = Some(i);
+            }
+            if self.is_hung(i) {
+                continue;
+            }
+
+            let length = queue_size(&self.threads[i].1.activities)
+                + queue_size(&self.threads[i].1.activities_suspended);
             if length < shortest as usize {
-                index = i;
+                index = Some(i);
                 shortest = length as u64;
             }
         }
@@ -311,52 +1672,162 @@ impl MultiThreadHelper {
         index
     }
 
+    /// Whether any thread serves the given context
+    fn is_served(&self, context: &Context) -> bool {
+        self.threads.iter().any(|t| t.1.serves(context))
+    }
+
+    /// Whether the activities queued on a thread can not be moved to the
+    /// registered threads, because they may not be stolen or no registered
+    /// thread serves their context
+    fn holds_unmovable(&self, queues: &ExecutorQueues) -> bool {
+        queues
+            .activities
+            .lock()
+            .unwrap()
+            .values()
+            .chain(queues.activities_suspended.lock().unwrap().values())
+            .any(|a| !a.may_be_stolen() || !self.is_served(a.context()))
+    }
+
+    /// Hold back an activity of a context no thread serves, it is placed by
+    /// the `run` method once a thread serving the context is added
+    fn hold_back_unserved(&mut self, activity: Box<dyn ActivityWrapperTrait>) {
+        warn!(
+            "No thread serves {}, holding back activity: {}",
+            activity.context(),
+            activity.activity_identifier()
+        );
+        self.log_placement(&*activity, None);
+        self.overflow.lock().unwrap().push_back(activity);
+    }
+
     /// Send an event to the thread containing the target activity. If no such
     /// thread exists, store event locally. Use the `run` method to periodically
-    /// search for the activity
+    /// search for the activity.
+    ///
+    /// Events for an activity which finished are dropped. The lock on the
+    /// finished activities is held until the event is delivered or kept, the
+    /// executor threads hold it while dropping the events of an activity
+    /// which finished, so no event is left behind for it.
     fn distribute_event(&mut self, event: Box<Event>) {
         let key = event.get_dst();
 
-        for i in 0..self.threads.len() {
-            let c1 = self.threads[i]
-                .1
-                .activities
-                .lock()
-                .unwrap()
-                .contains_key(&key);
-            let c2 = self.threads[i]
-                .1
-                .activities_suspended
-                .lock()
-                .unwrap()
-                .contains_key(&key);
-            if c1 || c2 {
-                self.threads[i]
-                    .1
-                    .event_queue
-                    .lock()
-                    .unwrap()
-                    .insert(key, event);
-                return;
+        let finished = self.finished.clone();
+        let mut finished = finished.lock().unwrap();
+        if let Some(finished_at) = finished.finished_at(&key) {
+            if self.debug {
+                info!(
+                    "Destination finished {:?} ago, dropping Event: {}",
+                    finished_at.elapsed(),
+                    event.summary()
+                );
             }
+            finished.record_dropped(1);
+            self.log_schedule(|| ScheduleRecord::Orphan {
+                destination: key.to_string(),
+                events: 1,
+            });
+            self.observe(|| SchedulerEvent::Orphaned { id: event.get_id() });
+            return;
         }
 
-        // Event does not exist in any activity yet, let it sit in our local
-        // queue until we find a matching activity. This should in essence only
-        // be possible when an event has an invalid destination, or is retrieved
-        // from another node, without the matching activity
-        self.local_events
-            .lock()
-            .unwrap()
-            .insert(event.get_dst(), event);
+        match self.thread_holding(&key) {
+            Some(index) => {
+                if self.debug {
+                    info!("Deliver Event to thread {}: {}", index, event.summary());
+                }
+                // Events kept for the activity earlier go first, so events
+                // are delivered in the order they were routed
+                let mut events = self.local_events.shard(&key).drain_for(&key);
+                events.push(event);
+                self.deliver_events(index, key, events);
+            }
+            None => {
+                // The activity was handed to another node, or belongs to one
+                if let Some(node) = self.remote_location(&key) {
+                    if !self.forward_event(node, &event) {
+                        finished.record_dropped(1);
+                    }
+                    return;
+                }
+
+                // Event does not exist in any activity yet, let it sit in our
+                // local queue until we find a matching activity. This happens
+                // when the activity is executing, when an event has an invalid
+                // destination, or is retrieved from another node, without the
+                // matching activity
+                if self.debug {
+                    info!("No destination found, keeping Event: {}", event.summary());
+                }
+                self.log_schedule(|| ScheduleRecord::EventRoute {
+                    event: event.get_id(),
+                    destination: key.to_string(),
+                    thread: None,
+                });
+                self.observe(|| SchedulerEvent::EventRouted {
+                    id: event.get_id(),
+                    dst: key.clone(),
+                    thread: None,
+                });
+                self.local_events.shard(&key).insert(key, event);
+            }
+        }
+    }
+
+    /// Find the thread which holds the activity in its work queue or its
+    /// suspended queue
+    ///
+    /// # Arguments
+    /// * `key` - Identifier of the activity
+    ///
+    /// # Returns
+    /// * `Option<usize>` - Index of the thread, None if no thread holds the
+    /// activity
+    fn thread_holding(&self, key: &ActivityIdentifier) -> Option<usize> {
+        self.threads.iter().position(|(_, queues)| {
+            queues.activities.lock().unwrap().contains_key(key)
+                || queues
+                    .activities_suspended
+                    .lock()
+                    .unwrap()
+                    .contains_key(key)
+        })
+    }
+
+    /// Insert events for one activity in the event queue of a thread, in the
+    /// given order, and wake the thread up
+    fn deliver_events(&mut self, index: usize, key: ActivityIdentifier, events: Vec<Box<Event>>) {
+        let queues = &self.threads[index].1;
+        let mut guard = queues.event_queue.lock().unwrap();
+        for event in events {
+            self.log_schedule(|| ScheduleRecord::EventRoute {
+                event: event.get_id(),
+                destination: key.to_string(),
+                thread: Some(index),
+            });
+            self.observe(|| SchedulerEvent::EventRouted {
+                id: event.get_id(),
+                dst: key.clone(),
+                thread: Some(index),
+            });
+            guard.insert(key.clone(), event);
+        }
+        drop(guard);
+        queues.parker.unpark();
     }
 
     /// Handles all events from threads by looping through the
     /// `self.events_from_threads` queue, stealing all events and distributing
     /// them to the thread which has the corresponding activity.
+    ///
+    /// The lock on the events is held until all of them are routed, see
+    /// `ThreadHelper::events_in_flight(..)`.
     fn handle_thread_events(&mut self) {
+        let events_from_threads = self.events_from_threads.clone();
+        let guard = events_from_threads.lock().unwrap();
         loop {
-            let event = self.events_from_threads.lock().unwrap().steal();
+            let event = guard.steal();
             match event {
                 Steal::Success(e) => {
                     self.distribute_event(e);
@@ -368,69 +1839,1338 @@ impl MultiThreadHelper {
         }
     }
 
-    /// Insert an activity to the thread which has the least work
+    /// Insert an activity to the thread which has the least work, or the
+    /// thread it has an affinity for
     ///
     /// # Arguments
     /// * `activity_trait` - The activity to submit
     fn distribute_activity(&mut self, activity_trait: Box<dyn ActivityWrapperTrait>) {
-        let index = self.get_thread_with_least_work();
+        match self.select_thread(activity_trait.thread_affinity(), activity_trait.context()) {
+            Some(index) => self.place_activity(index, activity_trait),
+            None => self.hold_back_unserved(activity_trait),
+        }
+    }
+
+    /// Insert a batch of activities, each in the work queue of the thread it
+    /// has an affinity for or the thread with the least work, taking the
+    /// activities placed earlier in the batch into account. The work queue of
+    /// each thread is locked once for the whole batch. The per thread cap is
+    /// respected like in `place_activity(..)`.
+    ///
+    /// # Arguments
+    /// * `activities` - The activities to insert
+    fn distribute_activities(&mut self, activities: Vec<Box<dyn ActivityWrapperTrait>>) {
+        if activities.len() <= 1 {
+            for activity in activities {
+                self.distribute_activity(activity);
+            }
+            return;
+        }
+
+        let count = self.threads.len();
+        let hung: Vec<bool> = (0..count).map(|i| self.is_hung(i)).collect();
+        let mut work: Vec<usize> = self
+            .threads
+            .iter()
+            .map(|t| queue_size(&t.1.activities) + queue_size(&t.1.activities_suspended))
+            .collect();
+        let mut queued: Vec<usize> = self
+            .threads
+            .iter()
+            .map(|t| t.1.activities.lock().unwrap().len())
+            .collect();
+        let mut batches: Vec<Vec<Box<dyn ActivityWrapperTrait>>> =
+            (0..count).map(|_| Vec::new()).collect();
+
+        for activity in activities {
+            if let Some(i) = self.replayed_thread(&*activity) {
+                work[i] += activity.size();
+                queued[i] += 1;
+                batches[i].push(activity);
+                continue;
+            }
+
+            let serving: Vec<bool> = self
+                .threads
+                .iter()
+                .map(|t| t.1.serves(activity.context()))
+                .collect();
+
+            let preferred = match activity.thread_affinity() {
+                Some(index) if index < count && serving[index] => index,
+                _ => match least_loaded(&work, &hung, |i| serving[i]) {
+                    Some(index) => index,
+                    None => {
+                        self.hold_back_unserved(activity);
+                        continue;
+                    }
+                },
+            };
+
+            let index = match self.max_activities_per_thread {
+                Some(cap) if queued[preferred] >= cap => {
+                    self.overflow_count.fetch_add(1, Ordering::Relaxed);
+                    least_loaded(&work, &hung, |i| queued[i] < cap && !hung[i] && serving[i])
+                }
+                _ => Some(preferred),
+            };
+
+            match index {
+                Some(i) => {
+                    work[i] += activity.size();
+                    queued[i] += 1;
+                    batches[i].push(activity);
+                }
+                None => {
+                    if self.debug {
+                        info!(
+                            "All threads at cap, holding back activity: {}",
+                            activity.activity_identifier()
+                        );
+                    }
+                    self.log_placement(&*activity, None);
+                    self.overflow.lock().unwrap().push_back(activity);
+                }
+            }
+        }
+
+        for (i, batch) in batches.into_iter().enumerate() {
+            if batch.is_empty() {
+                continue;
+            }
+
+            let mut guard = self.threads[i].1.activities.lock().unwrap();
+            for activity in batch {
+                self.log_placement(&*activity, Some(i));
+                if let Some(trace) = &self.schedule_trace {
+                    trace.placed(activity.activity_identifier(), i);
+                }
+                guard.insert(activity.activity_identifier().clone(), activity);
+            }
+            drop(guard);
+
+            self.threads[i].1.parker.unpark();
+        }
+    }
+
+    /// Insert an activity in the work queue of the given thread. If that
+    /// thread is at the per thread cap, the least loaded thread below the cap
+    /// serving the context of the activity is used instead. If all these
+    /// threads are at the cap, the activity is held back in the overflow
+    /// queue. When a schedule is replayed, the activity is inserted on the
+    /// thread it was placed on before instead.
+    ///
+    /// # Arguments
+    /// * `index` - Index of the preferred thread
+    /// * `activity` - The activity to insert
+    fn place_activity(&mut self, index: usize, activity: Box<dyn ActivityWrapperTrait>) {
+        if let Some(index) = self.replayed_thread(&*activity) {
+            self.log_placement(&*activity, Some(index));
+            return self.insert_activity(index, activity);
+        }
+
+        let mut index = index;
+
+        if let Some(cap) = self.max_activities_per_thread {
+            if self.threads[index].1.activities.lock().unwrap().len() >= cap {
+                self.overflow_count.fetch_add(1, Ordering::Relaxed);
 
-        let aid = activity_trait.activity_identifier();
+                match self.get_thread_below_cap(cap, activity.context()) {
+                    Some(i) => index = i,
+                    None => {
+                        if self.debug {
+                            info!(
+                                "All threads at cap, holding back activity: {}",
+                                activity.activity_identifier()
+                            );
+                        }
+                        self.log_placement(&*activity, None);
+                        self.overflow.lock().unwrap().push_back(activity);
+                        return;
+                    }
+                }
+            }
+        }
+
+        self.log_placement(&*activity, Some(index));
+        self.insert_activity(index, activity);
+    }
+
+    /// Insert an activity in the work queue of the given thread, without
+    /// checking the per thread cap
+    ///
+    /// # Arguments
+    /// * `index` - Index of the thread
+    /// * `activity` - The activity to insert
+    fn insert_activity(&mut self, index: usize, activity: Box<dyn ActivityWrapperTrait>) {
+        let aid = activity.activity_identifier().clone();
+        if let Some(trace) = &self.schedule_trace {
+            trace.placed(&aid, index);
+        }
 
         self.threads[index]
             .1
             .activities
             .lock()
             .unwrap()
-            .insert(aid.clone(), activity_trait);
+            .insert(aid, activity);
+        self.threads[index].1.parker.unpark();
     }
 
-    /// Goes through all local events and checks if any thread has the target
-    /// activity.
-    fn handle_local_events(&mut self) {
-        let mut guard = self.local_events.lock().unwrap();
-        if guard.is_empty() {
-            drop(guard);
-            return;
-        }
+    /// The thread an activity was placed on in the schedule being replayed
+    ///
+    /// # Returns
+    /// * `Option<usize>` - The index of the thread, None if no schedule is
+    /// replayed, or the activity was not placed on a registered thread
+    fn replayed_thread(&self, activity: &dyn ActivityWrapperTrait) -> Option<usize> {
+        self.schedule_trace
+            .as_ref()
+            .and_then(|trace| trace.replayed_placement(activity.activity_identifier()))
+            .filter(|&index| index < self.threads.len())
+    }
 
-        let mut key = None;
+    /// Find the thread with the least work, amongst the threads which have
+    /// less activities queued than the cap, serve the given context and are
+    /// not hung.
+    ///
+    /// # Arguments
+    /// * `cap` - Maximum number of activities queued on a thread
+    /// * `context` - The context the thread must serve
+    ///
+    /// # Returns
+    /// * `Option<usize>` - The index of the thread, None if all these threads
+    /// are at the cap
+    fn get_thread_below_cap(&mut self, cap: usize, context: &Context) -> Option<usize> {
+        let mut shortest = usize::max_value();
+        let mut index = None;
 
-        let mut it = guard.keys().take(1).map(|x| key = Some(x.clone()));
-        it.next();
+        for i in 0..self.threads.len() {
+            if !self.threads[i].1.serves(context)
+                || self.threads[i].1.activities.lock().unwrap().len() >= cap
+                || self.is_hung(i)
+            {
+                continue;
+            }
 
-        if !key.is_some() {
-            drop(guard);
-            return;
+            let length = queue_size(&self.threads[i].1.activities)
+                + queue_size(&self.threads[i].1.activities_suspended);
+            if length < shortest {
+                index = Some(i);
+                shortest = length;
+            }
         }
 
-        let event = guard.remove(key.unwrap()).unwrap();
+        index
+    }
 
-        drop(guard);
+    /// Route all delayed events which are due to the thread holding the
+    /// target activity
+    fn handle_delayed_events(&mut self) {
+        let events = self.delayed_events.lock().unwrap().pop_due();
 
-        self.distribute_event(event);
+        for e in events {
+            self.intercept_and_distribute(e);
+        }
     }
 
-    /// Handle activities from threads, checks the
-    /// `self.activities_from_threads` to find these activities, this struct
-    /// should be shared with ALL threads through the ThreadHelper struct.
-    fn handle_thread_activity(&mut self) {
-        // Load balance activities
-        let activity = self.activities_from_threads.lock().unwrap().steal();
-        match activity {
-            Steal::Success(activity) => {
-                self.distribute_activity(activity);
+    /// Run the event through the interceptors and send it to the thread
+    /// holding the target activity, unless an interceptor dropped it. With a
+    /// fault injector the event may be dropped or delayed afterwards.
+    fn intercept_and_distribute(&mut self, mut e: Box<Event>) {
+        if !intercept::intercept(&self.event_interceptors, &mut e) {
+            if self.debug {
+                info!("Event dropped by interceptor: {}", e.summary());
             }
-            _ => {}
+            return;
         }
 
-        // Make sure event goes to correct thread
-        let event = self.events_from_threads.lock().unwrap().steal();
-        match event {
-            Steal::Success(e) => {
-                self.distribute_event(e);
-            }
-            _ => {}
-        }
+        #[cfg(feature = "fault-injection")]
+        let e = match self.inject_fault(e) {
+            Some(e) => e,
+            None => return,
+        };
+
+        self.distribute_event(e);
+    }
+
+    /// Run the event through the fault injector, if there is one. A delayed
+    /// event is routed by the `run` method once it is due.
+    ///
+    /// # Returns
+    /// * `Option<Box<Event>>` - The event, None if it was dropped or delayed
+    #[cfg(feature = "fault-injection")]
+    fn inject_fault(&self, e: Box<Event>) -> Option<Box<Event>> {
+        let injector = match &self.fault_injector {
+            Some(injector) => injector,
+            None => return Some(e),
+        };
+
+        let label = self
+            .context_members
+            .lock()
+            .unwrap()
+            .label(&e.get_dst())
+            .map(String::from);
+        match injector.inject_event(&e, label.as_deref()) {
+            EventFault::Deliver => Some(e),
+            EventFault::Drop => {
+                if self.debug {
+                    info!("Event dropped by fault injector: {}", e.summary());
+                }
+                None
+            }
+            EventFault::Delay(delay) => {
+                if self.debug {
+                    info!(
+                        "Event delayed {:?} by fault injector: {}",
+                        delay,
+                        e.summary()
+                    );
+                }
+                self.delayed_events.lock().unwrap().push(e, delay);
+                None
+            }
+        }
+    }
+
+    /// Check whether the executor threads handed over activities or events
+    /// which were not taken by the `run` method yet
+    fn has_handed_over_work(&self) -> bool {
+        !self.events_from_threads.lock().unwrap().is_empty()
+            || !self.activities_from_threads.lock().unwrap().is_empty()
+    }
+
+    /// Check whether the `run` method has activities or events to route
+    fn has_routing_work(&self) -> bool {
+        !self.events_from_threads.lock().unwrap().is_empty()
+            || !self.activities_from_threads.lock().unwrap().is_empty()
+            || !self.local_events.is_empty()
+            || !self.overflow.lock().unwrap().is_empty()
+            || self.delayed_events.lock().unwrap().has_due()
+    }
+
+    /// Check whether any thread is running an activity which exceeded its
+    /// maximum execution time. Such activities are logged and passed to the
+    /// execution timeout callback, once.
+    fn check_execution_times(&mut self) {
+        let now = self.clock.now();
+
+        for i in 0..self.threads.len() {
+            let timed_out = self.threads[i].1.execution.lock().unwrap().check(now);
+
+            if let Some((aid, elapsed)) = timed_out {
+                warn!(
+                    "Activity {} on thread {} exceeded its maximum execution time, running \
+                     for {:?}",
+                    aid, i, elapsed
+                );
+
+                if let Some(callback) = self.execution_timeout_callback {
+                    callback(&aid, elapsed);
+                }
+            }
+        }
+    }
+
+    /// Check whether the instance made progress, see ProgressMonitor. While
+    /// paused it is not expected to. A detected stall is logged with a
+    /// snapshot of the queues and passed to the `no_progress_callback`.
+    fn check_progress(&mut self) {
+        if self.progress_monitor.is_none() {
+            return;
+        }
+
+        let suspended: usize = self
+            .threads
+            .iter()
+            .map(|(_, queues)| queues.activities_suspended.lock().unwrap().len())
+            .sum();
+        let executing = self.idle_threads.load(Ordering::SeqCst) < self.threads.len();
+        let may_be_stuck = suspended > 0 && !executing && !self.pause.is_paused();
+
+        let finished = self.finished.lock().unwrap().total();
+        let delivered = self.delivered_events.load(Ordering::Relaxed);
+        let now = self.clock.now();
+        let stalled = match self.progress_monitor.as_mut() {
+            Some(monitor) => monitor.check(finished, delivered, may_be_stuck, now),
+            None => None,
+        };
+
+        if let Some(stalled) = stalled {
+            let snapshot = self.snapshot();
+            warn!(
+                "No progress for {:?}: {} activities are suspended, while none finished or \
+                 received an event and no activity is executing. They may be waiting for \
+                 events from each other. {}",
+                stalled, suspended, snapshot
+            );
+
+            if let Some(callback) = &self.no_progress_callback {
+                callback(&snapshot);
+            }
+        }
+    }
+
+    /// Whether the thread is running an activity which exceeded its maximum
+    /// execution time, such threads are not given new work
+    fn is_hung(&self, index: usize) -> bool {
+        self.threads[index].1.execution.lock().unwrap().is_hung()
+    }
+
+    /// Record a scheduling decision in the schedule log, if there is one. The
+    /// record is only created when it is written.
+    fn log_schedule<F: FnOnce() -> ScheduleRecord>(&self, record: F) {
+        if let Some(logger) = &self.schedule_log {
+            logger.record(record());
+        }
+    }
+
+    /// Record placing an activity on a thread, or holding it back, in the
+    /// schedule log and tell the observer, if there are any
+    fn log_placement(&self, activity: &dyn ActivityWrapperTrait, thread: Option<usize>) {
+        self.log_schedule(|| submit_record(activity, thread));
+        self.observe(|| placement_event(activity, thread));
+    }
+
+    /// Send a scheduling decision to the observer, if there is one
+    fn observe<F: FnOnce() -> SchedulerEvent>(&self, event: F) {
+        scheduler_event::observe(&self.observer, event);
+    }
+
+    /// Write the records buffered by all threads in the schedule log, once
+    /// every `schedule_log::FLUSH_INTERVAL`
+    fn flush_schedule_log(&mut self) {
+        if let Some(logger) = &self.schedule_log {
+            if self.last_log_flush.elapsed() >= schedule_log::FLUSH_INTERVAL {
+                logger.log().flush();
+                self.last_log_flush = Instant::now();
+            }
+        }
+    }
+
+    /// Record the load of this node in the load table and send it to the
+    /// other nodes, once every `load_report_interval`. The pending activities
+    /// are those queued on the executor threads which did not start yet, and
+    /// those held back.
+    fn report_load(&mut self) {
+        if let Some(last) = self.last_load_report {
+            if last.elapsed() < self.load_report_interval {
+                return;
+            }
+        }
+
+        let mut load = NodeLoad::new(self.node_id);
+        for thread in self.threads.iter() {
+            for activity in thread.1.activities.lock().unwrap().values() {
+                load.add(activity.context().label());
+            }
+        }
+        for activity in self.overflow.lock().unwrap().iter() {
+            load.add(activity.context().label());
+        }
+
+        if self.debug {
+            info!("Load report: {}", load);
+        }
+
+        let message = load.encode();
+        for peer in (0..self.comm.size()).filter(|peer| *peer as usize != self.node_id) {
+            self.comm.send(peer, LOAD_TAG, &message);
+        }
+
+        self.load_table.lock().unwrap().record(load);
+        self.last_load_report = Some(Instant::now());
+    }
+
+    /// Record the load reports which arrived from the other nodes in the load
+    /// table, malformed reports and reports claiming to be from another node
+    /// are dropped
+    fn receive_load_reports(&mut self) {
+        while let Some((source, bytes)) = self.comm.try_receive(LOAD_TAG) {
+            match NodeLoad::decode(&bytes) {
+                Some(load) if load.node_id == source as usize => {
+                    if self.debug {
+                        info!("Load report received: {}", load);
+                    }
+                    self.load_table
+                        .lock()
+                        .unwrap()
+                        .record_at(load, Instant::now());
+                }
+                _ => warn!("Dropping malformed load report from node {}", source),
+            }
+        }
+    }
+
+    /// Steal activities between nodes, see the remote_steal module: answer
+    /// the steal requests of the other nodes, place the activities handed to
+    /// this node, route the events forwarded to it and ask another node for
+    /// activities when all threads are idle. Nothing is stolen while a
+    /// schedule is recorded or replayed, the schedule would depend on the
+    /// other nodes.
+    fn handle_remote(&mut self) {
+        if self.comm.size() < 2 || self.schedule_trace.is_some() {
+            return;
+        }
+
+        self.answer_steal_requests();
+        self.receive_stolen_activities();
+        self.receive_remote_events();
+        self.purge_hosted();
+
+        if self.is_idle() {
+            self.request_work();
+        }
+    }
+
+    /// Whether all threads are idle, and no activities are queued on them or
+    /// waiting to be routed
+    fn is_idle(&self) -> bool {
+        !self.threads.is_empty()
+            && self.idle_threads.load(Ordering::SeqCst) >= self.threads.len()
+            && !self.has_routing_work()
+            && self
+                .threads
+                .iter()
+                .all(|(_, queues)| queues.activities.lock().unwrap().is_empty())
+    }
+
+    /// Ask another node for activities, unless a request is outstanding, the
+    /// last request was answered without activities less than STEAL_BACKOFF
+    /// ago, or the instance is shutting down. A node which did not answer in
+    /// time is treated like a node which had nothing to give.
+    fn request_work(&mut self) {
+        let now = Instant::now();
+        let victim = {
+            let mut remote = self.remote.lock().unwrap();
+            if remote.stopped || remote.awaiting_reply(now) || now < remote.next_request {
+                return;
+            }
+            if let Some((node, _)) = remote.awaiting.take() {
+                warn!("Node {} did not answer the steal request in time", node);
+                remote.last_empty = Some(node);
+            }
+
+            let loaded = self.load_table.lock().unwrap().victim(self.node_id, None);
+            let victim = remote.pick_victim(loaded, self.node_id, self.comm.size());
+            remote.awaiting = Some((victim, now));
+            victim
+        };
+
+        if self.debug {
+            info!(
+                "All threads idle, requesting activities from node {}",
+                victim
+            );
+        }
+        let request = StealRequest {
+            contexts: self.served_labels(),
+        };
+        self.comm.send(victim, STEAL_TAG, &request.encode());
+        self.steal_counters.record_remote_request();
+    }
+
+    /// Labels of the contexts served by the threads
+    ///
+    /// # Returns
+    /// * `Option<Vec<String>>` - The labels, None if a thread serves every
+    /// context
+    fn served_labels(&self) -> Option<Vec<String>> {
+        let mut labels: Vec<String> = Vec::new();
+        for (_, queues) in self.threads.iter() {
+            for context in queues.contexts.as_ref()?.context_vec.iter() {
+                if !labels.iter().any(|label| label == context.label()) {
+                    labels.push(context.label().to_string());
+                }
+            }
+        }
+
+        Some(labels)
+    }
+
+    /// Answer the steal requests of the other nodes with the activities
+    /// taken by `take_for_node(..)`. Every request is answered, possibly
+    /// without activities, so the other node does not wait for an answer.
+    fn answer_steal_requests(&mut self) {
+        while let Some((thief, bytes)) = self.comm.try_receive(STEAL_TAG) {
+            let activities = match StealRequest::decode(&bytes) {
+                Some(request) => self.take_for_node(thief, &request),
+                None => {
+                    warn!(
+                        "Malformed steal request from node {}, answering without activities",
+                        thief
+                    );
+                    Vec::new()
+                }
+            };
+
+            if self.debug {
+                info!("Handing {} activities to node {}", activities.len(), thief);
+            }
+            let reply = StealReply {
+                returned: false,
+                activities,
+            };
+            self.comm.send(thief, STEAL_REPLY_TAG, &reply.encode());
+        }
+    }
+
+    /// Take queued activities which did not start yet to hand to another
+    /// node, up to `steal_batch_size` and at most half of the activities
+    /// which may be handed out, rounded up. The `remote_steal_strategy`
+    /// decides whether the biggest or the smallest go first. Activities with
+    /// a thread affinity, a scope or a name stay on this node, as well as
+    /// activities of a type without a registered decoder.
+    ///
+    /// # Arguments
+    /// * `thief` - Rank of the node asking for activities
+    /// * `request` - The request of that node
+    ///
+    /// # Returns
+    /// * `Vec<StolenActivity>` - The encoded activities, the events sent to
+    /// them from now on are forwarded to the thief
+    fn take_for_node(&mut self, thief: i32, request: &StealRequest) -> Vec<StolenActivity> {
+        let master = thief == self.master_rank;
+
+        let mut candidates: Vec<(usize, ActivityIdentifier, usize)> = Vec::new();
+        for (index, (_, queues)) in self.threads.iter().enumerate() {
+            for (key, a) in queues.activities.lock().unwrap().iter() {
+                if a.may_be_stolen()
+                    && a.yield_round() == 0
+                    && a.thread_affinity().is_none()
+                    && a.scope().is_none()
+                    && a.placement().allows(thief as usize, master)
+                    && request.serves(a.context().label())
+                    && self.decoders.decodes_activity(a.type_name())
+                {
+                    candidates.push((index, key.clone(), a.size()));
+                }
+            }
+        }
+        {
+            let names = self.names.lock().unwrap();
+            candidates.retain(|(_, key, _)| !names.is_named(key));
+        }
+
+        match self.remote_steal_strategy {
+            StealStrategy::BIGGEST => candidates.sort_by_key(|&(_, _, size)| Reverse(size)),
+            StealStrategy::SMALLEST => candidates.sort_by_key(|&(_, _, size)| size),
+        }
+        let budget = self.steal_batch_size.min((candidates.len() + 1) / 2);
+
+        let taken: Vec<StolenActivity> = candidates
+            .into_iter()
+            .take(budget)
+            .filter_map(|(index, key, _)| self.take_encoded(index, &key))
+            .collect();
+        if taken.is_empty() {
+            return taken;
+        }
+
+        {
+            let mut members = self.context_members.lock().unwrap();
+            let mut remote = self.remote.lock().unwrap();
+            for stolen in taken.iter() {
+                members.remove(&stolen.aid);
+                remote.hosted.remove(&stolen.aid);
+                remote.migrated.insert(stolen.aid.clone(), thief);
+            }
+        }
+        self.steal_counters.record_remote_given(taken.len());
+
+        taken
+    }
+
+    /// Remove a queued activity from the work queue of a thread, together
+    /// with the events queued for it, and encode them. Both are put back when
+    /// one of them can not be encoded.
+    ///
+    /// # Arguments
+    /// * `index` - Index of the thread
+    /// * `key` - Identifier of the activity
+    ///
+    /// # Returns
+    /// * `Option<StolenActivity>` - The encoded activity, None if it was
+    /// picked up by the thread meanwhile or can not be encoded
+    fn take_encoded(&self, index: usize, key: &ActivityIdentifier) -> Option<StolenActivity> {
+        let queues = &self.threads[index].1;
+
+        // Keep the lock on the work queue until the events are taken, the
+        // executor thread holds it while delivering an event locally
+        let mut guard = queues.activities.lock().unwrap();
+        let activity = guard.remove(key)?;
+        let mut events = queues.event_queue.lock().unwrap().drain_for(key);
+        events.extend(self.local_events.shard(key).drain_for(key));
+
+        let encoded = activity.encode().and_then(|bytes| {
+            Some(StolenActivity {
+                aid: key.clone(),
+                context: activity.context().label().to_string(),
+                expects_events: activity.expects_event(),
+                priority: activity.priority(),
+                max_execution_time: activity.max_execution_time(),
+                placement: activity.placement(),
+                retries: activity.retries(),
+                type_name: activity.type_name().to_string(),
+                activity: bytes,
+                events: events
+                    .iter()
+                    .map(|e| RemoteEvent::from_event(e))
+                    .collect::<Option<Vec<RemoteEvent>>>()?,
+            })
+        });
+
+        if encoded.is_none() {
+            let mut event_queue = queues.event_queue.lock().unwrap();
+            for event in events {
+                event_queue.insert(key.clone(), event);
+            }
+            drop(event_queue);
+            guard.insert(key.clone(), activity);
+        }
+
+        encoded
+    }
+
+    /// Place the activities the other nodes handed to this node, or returned
+    /// because they could not decode them
+    fn receive_stolen_activities(&mut self) {
+        while let Some((source, bytes)) = self.comm.try_receive(STEAL_REPLY_TAG) {
+            let reply = match StealReply::decode(&bytes) {
+                Some(reply) => reply,
+                None => {
+                    warn!("Dropping malformed steal reply from node {}", source);
+                    continue;
+                }
+            };
+
+            if !reply.returned {
+                let mut remote = self.remote.lock().unwrap();
+                if remote.awaiting.map_or(false, |(node, _)| node == source) {
+                    remote.awaiting = None;
+                }
+                if reply.activities.is_empty() {
+                    remote.last_empty = Some(source);
+                    remote.next_request = Instant::now() + STEAL_BACKOFF;
+                } else {
+                    remote.last_empty = None;
+                }
+            }
+
+            self.place_received(source, reply);
+        }
+    }
+
+    /// Decode the activities received from another node and place them on
+    /// the threads, after which the events queued for them are routed.
+    /// Activities which can not be decoded are returned to that node.
+    ///
+    /// # Arguments
+    /// * `source` - Rank of the node which sent the activities
+    /// * `reply` - The activities
+    fn place_received(&mut self, source: i32, reply: StealReply) {
+        let returned = reply.returned;
+        let mut activities: Vec<Box<dyn ActivityWrapperTrait>> = Vec::new();
+        let mut events = Vec::new();
+        let mut undecodable = Vec::new();
+
+        for stolen in reply.activities {
+            match self.decode_stolen(&stolen) {
+                Some((activity, decoded)) => {
+                    activities.push(activity);
+                    events.extend(decoded);
+                }
+                None => undecodable.push(stolen),
+            }
+        }
+
+        if !undecodable.is_empty() && returned {
+            warn!(
+                "Dropping {} activities returned by node {}, they can not be decoded on this \
+                 node either",
+                undecodable.len(),
+                source
+            );
+        } else if !undecodable.is_empty() {
+            warn!(
+                "Returning {} activities to node {}, they can not be decoded",
+                undecodable.len(),
+                source
+            );
+            {
+                let mut remote = self.remote.lock().unwrap();
+                for stolen in undecodable.iter() {
+                    remote.hosted.remove(&stolen.aid);
+                    remote.migrated.insert(stolen.aid.clone(), source);
+                }
+            }
+            let reply = StealReply {
+                returned: true,
+                activities: undecodable,
+            };
+            self.comm.send(source, STEAL_REPLY_TAG, &reply.encode());
+        }
+        if activities.is_empty() {
+            return;
+        }
+
+        if self.debug {
+            info!(
+                "Received {} activities from node {}",
+                activities.len(),
+                source
+            );
+        }
+        {
+            let mut members = self.context_members.lock().unwrap();
+            let mut remote = self.remote.lock().unwrap();
+            for activity in activities.iter() {
+                let aid = activity.activity_identifier();
+                members.add(aid.clone(), activity.context());
+                remote.migrated.remove(aid);
+                if aid.node_info.node_id != self.node_id {
+                    remote.hosted.insert(aid.clone());
+                }
+            }
+        }
+        if !returned {
+            self.steal_counters.record_remote_stolen(activities.len());
+        }
+
+        self.distribute_activities(activities);
+        for e in events {
+            self.distribute_event(e);
+        }
+    }
+
+    /// Decode an activity received from another node, and the events queued
+    /// for it
+    ///
+    /// # Returns
+    /// * `Option<(Box<dyn ActivityWrapperTrait>, Vec<Box<Event>>)>` - The
+    /// wrapped activity and its events, None if the activity or one of the
+    /// events can not be decoded
+    fn decode_stolen(
+        &self,
+        stolen: &StolenActivity,
+    ) -> Option<(Box<dyn ActivityWrapperTrait>, Vec<Box<Event>>)> {
+        let events = stolen
+            .events
+            .iter()
+            .cloned()
+            .map(|e| e.into_event(&self.decoders))
+            .collect::<Option<Vec<Box<Event>>>>()?;
+        let activity = self
+            .decoders
+            .decode_activity(&stolen.type_name, &stolen.activity)?;
+
+        let options = SubmitOptions {
+            expects_events: stolen.expects_events,
+            priority: stolen.priority,
+            max_execution_time: stolen.max_execution_time,
+            placement: stolen.placement,
+            retries: stolen.retries,
+            ..SubmitOptions::default()
+        };
+        let context = Context::new(&stolen.context);
+
+        Some((
+            ActivityWrapper::stolen(stolen.aid.clone(), activity, &context, options),
+            events,
+        ))
+    }
+
+    /// Route the events the other nodes forwarded to this node. An event for
+    /// an activity submitted to another node, which did not leave this node,
+    /// is kept until the activity arrives. Events which can not be decoded
+    /// are dropped.
+    fn receive_remote_events(&mut self) {
+        while let Some((source, bytes)) = self.comm.try_receive(EVENT_TAG) {
+            let event = match RemoteEvent::decode(&bytes).and_then(|e| e.into_event(&self.decoders))
+            {
+                Some(event) => event,
+                None => {
+                    warn!("Dropping malformed event from node {}", source);
+                    self.finished.lock().unwrap().record_dropped(1);
+                    continue;
+                }
+            };
+
+            let dst = event.get_dst();
+            if dst.node_info.node_id != self.node_id {
+                let mut remote = self.remote.lock().unwrap();
+                if !remote.migrated.contains_key(&dst) {
+                    remote.hosted.insert(dst);
+                }
+            }
+
+            if self.debug {
+                info!("Event forwarded by node {}: {}", source, event.summary());
+            }
+            self.distribute_event(event);
+        }
+    }
+
+    /// The node to forward the events for an activity which is not on this
+    /// node to, see `RemoteSteal::location(..)`
+    fn remote_location(&self, key: &ActivityIdentifier) -> Option<i32> {
+        let nodes = self.comm.size();
+        if nodes < 2 {
+            return None;
+        }
+
+        self.remote
+            .lock()
+            .unwrap()
+            .location(key, self.node_id, nodes)
+    }
+
+    /// Send an event to the node executing its destination
+    ///
+    /// # Returns
+    /// * `bool` - false if the payload of the event can not be encoded, the
+    /// event is dropped
+    fn forward_event(&self, node: i32, e: &Event) -> bool {
+        match RemoteEvent::from_event(e) {
+            Some(remote) => {
+                if self.debug {
+                    info!("Forward Event to node {}: {}", node, e.summary());
+                }
+                self.comm.send(node, EVENT_TAG, &remote.encode());
+                true
+            }
+            None => {
+                warn!(
+                    "Dropping Event for an activity on node {}, its payload can not be encoded: {}",
+                    node,
+                    e.summary()
+                );
+                false
+            }
+        }
+    }
+
+    /// Forget the hosted activities which finished, once every
+    /// HOSTED_PURGE_INTERVAL
+    fn purge_hosted(&mut self) {
+        let finished = self.finished.lock().unwrap();
+        let mut remote = self.remote.lock().unwrap();
+        if remote.last_purge.elapsed() < HOSTED_PURGE_INTERVAL {
+            return;
+        }
+
+        remote.hosted.retain(|aid| !finished.contains(aid));
+        remote.last_purge = Instant::now();
+    }
+
+    /// Record the number of pending activities, suspended activities and
+    /// queued events of every thread in its histograms, once every
+    /// `queue_sample_interval`
+    fn sample_queue_depths(&mut self) {
+        let interval = match self.queue_sample_interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        if let Some(last) = self.last_queue_sample {
+            if last.elapsed() < interval {
+                return;
+            }
+        }
+
+        let depths: Vec<(usize, usize, usize)> = self
+            .threads
+            .iter()
+            .map(|thread| {
+                (
+                    thread.1.activities.lock().unwrap().len(),
+                    thread.1.activities_suspended.lock().unwrap().len(),
+                    thread.1.event_queue.lock().unwrap().len(),
+                )
+            })
+            .collect();
+
+        let mut stats = self.queue_depths.lock().unwrap();
+        for (index, (pending, suspended, events)) in depths.into_iter().enumerate() {
+            if index == stats.len() {
+                stats.push(QueueDepthStats::new(index));
+            }
+
+            stats[index].pending.record(pending);
+            stats[index].suspended.record(suspended);
+            stats[index].events.record(events);
+        }
+
+        self.last_queue_sample = Some(Instant::now());
+    }
+
+    /// Move queued activities from the thread with the most pending work to
+    /// the thread with the least, once every REBALANCE_INTERVAL, when the
+    /// ratio between the two exceeds `rebalance_threshold`. The pending work
+    /// is the sum of the size hints of the activities in the work queue, half
+    /// the difference is moved, taken from the back of the queue together
+    /// with the events already delivered for them. Activities which may not
+    /// be stolen, have a thread affinity, have a context the other thread
+    /// does not serve, or migrated MAX_SHED_MIGRATIONS times already stay
+    /// where they are. Nothing is moved while a schedule is recorded or
+    /// replayed.
+    fn rebalance(&mut self) {
+        let threshold = match self.rebalance_threshold {
+            Some(threshold) => threshold,
+            None => return,
+        };
+        if self.schedule_trace.is_some() || self.threads.len() < 2 {
+            return;
+        }
+        if let Some(last) = self.last_rebalance {
+            if last.elapsed() < REBALANCE_INTERVAL {
+                return;
+            }
+        }
+        self.last_rebalance = Some(Instant::now());
+
+        let loads: Vec<usize> = self
+            .threads
+            .iter()
+            .map(|(_, queues)| queue_size(&queues.activities))
+            .collect();
+        let heaviest = match (0..loads.len()).max_by_key(|&i| loads[i]) {
+            Some(index) => index,
+            None => return,
+        };
+        let lightest = match (0..loads.len())
+            .filter(|&i| !self.is_hung(i))
+            .min_by_key(|&i| loads[i])
+        {
+            Some(index) => index,
+            None => return,
+        };
+        let (most, least) = (loads[heaviest], loads[lightest]);
+        if most <= least + 1 || most as f64 <= threshold * least.max(1) as f64 {
+            return;
+        }
+
+        let from = self.threads[heaviest].1.clone();
+        let to = self.threads[lightest].1.clone();
+        let thread_id = from.const_id.lock().unwrap().thread_id;
+
+        // Keep the lock on the work queue until the events are taken as well,
+        // the executor thread holds it while delivering an event locally
+        let (activities, events) = {
+            let mut guard = from.activities.lock().unwrap();
+            let candidates: Vec<(ActivityIdentifier, usize)> = guard
+                .iter()
+                .filter(|(_, a)| {
+                    a.may_be_stolen()
+                        && a.thread_affinity().is_none()
+                        && a.migrations() < MAX_SHED_MIGRATIONS
+                        && to.serves(a.context())
+                })
+                .map(|(key, a)| (key.clone(), a.size()))
+                .collect();
+
+            let budget = (most - least) / 2;
+            let mut moved = 0;
+            let mut keys = Vec::new();
+            for (key, size) in candidates.into_iter().rev() {
+                if moved >= budget {
+                    break;
+                }
+                moved += size;
+                keys.push(key);
+            }
+            let activities: Vec<Box<dyn ActivityWrapperTrait>> =
+                keys.iter().filter_map(|key| guard.remove(key)).collect();
+
+            let mut event_queue = from.event_queue.lock().unwrap();
+            let events: Vec<(ActivityIdentifier, Vec<Box<Event>>)> = keys
+                .into_iter()
+                .map(|key| {
+                    let events = event_queue.drain_for(&key);
+                    (key, events)
+                })
+                .collect();
+            (activities, events)
+        };
+        if activities.is_empty() {
+            return;
+        }
+
+        if self.debug {
+            info!(
+                "Rebalancing {} activities from thread {} to thread {}",
+                activities.len(),
+                heaviest,
+                lightest
+            );
+        }
+        self.log_schedule(|| ScheduleRecord::Rebalance {
+            thread: thread_id,
+            activities: activities.len(),
+        });
+
+        {
+            let mut guard = to.activities.lock().unwrap();
+            for mut activity in activities {
+                activity.record_migration(thread_id);
+                self.log_placement(&*activity, Some(lightest));
+                guard.insert(activity.activity_identifier().clone(), activity);
+            }
+        }
+        {
+            let mut guard = to.event_queue.lock().unwrap();
+            for (key, events) in events {
+                for event in events {
+                    guard.insert(key.clone(), event);
+                }
+            }
+        }
+        to.parker.unpark();
+    }
+
+    /// Write a snapshot of the statistics to the metrics sink, once every
+    /// interval of the sink
+    fn export_metrics(&mut self) {
+        let sink = match &self.metrics_sink {
+            Some(sink) => sink.clone(),
+            None => return,
+        };
+        if let Some(last) = self.last_metrics_export {
+            if last.elapsed() < sink.interval {
+                return;
+            }
+        }
+
+        sink.write(&self.stats());
+        self.last_metrics_export = Some(Instant::now());
+    }
+
+    /// Move activities from the overflow queue to threads which have dropped
+    /// below the cap, or which serve their context, in the order they were
+    /// held back. Activities for which there is still no thread stay in the
+    /// overflow queue.
+    fn handle_overflow(&mut self) {
+        let held_back: Vec<Box<dyn ActivityWrapperTrait>> =
+            self.overflow.lock().unwrap().drain(..).collect();
+        let mut remaining = VecDeque::new();
+
+        for activity in held_back {
+            let index = match self.max_activities_per_thread {
+                Some(cap) => self.get_thread_below_cap(cap, activity.context()),
+                None => self.get_thread_with_least_work(activity.context()),
+            };
+
+            match index {
+                Some(index) => self.insert_activity(index, activity),
+                None => remaining.push_back(activity),
+            }
+        }
+
+        if !remaining.is_empty() {
+            let mut guard = self.overflow.lock().unwrap();
+            // Keep the order, activities held back meanwhile go last
+            remaining.extend(guard.drain(..));
+            *guard = remaining;
+        }
+    }
+
+    /// Goes through all local events and checks if any thread has the target
+    /// activity. All events kept for an activity are delivered at once, in the
+    /// order they were routed. Events kept for an activity which finished
+    /// meanwhile are dropped. The shards of the local events are handled one
+    /// at a time, so the other shards stay available for routing events.
+    fn handle_local_events(&mut self) {
+        let local_events = self.local_events.clone();
+        for shard in local_events.shards() {
+            self.handle_local_shard(shard);
+        }
+    }
+
+    /// Drop the events of one shard of the local events whose destination
+    /// finished, and deliver the events whose destination was found.
+    ///
+    /// The lock on the finished activities is held until the events are
+    /// delivered, like in `distribute_event(..)`, so an activity can not
+    /// finish between being found on a thread and getting its events.
+    fn handle_local_shard(&mut self, shard: &Mutex<EventQueue>) {
+        if shard.lock().unwrap().is_empty() {
+            return;
+        }
+
+        let finished = self.finished.clone();
+        let mut finished = finished.lock().unwrap();
+        let mut orphans: HashMap<ActivityIdentifier, usize> = HashMap::new();
+        {
+            let observer = &self.observer;
+            let dropped = shard.lock().unwrap().retain(|key, event| {
+                if finished.contains(key) {
+                    *orphans.entry(key.clone()).or_insert(0) += 1;
+                    scheduler_event::observe(observer, || SchedulerEvent::Orphaned {
+                        id: event.get_id(),
+                    });
+                    return false;
+                }
+                true
+            });
+            finished.record_dropped(dropped);
+        }
+
+        for (key, events) in orphans {
+            if self.debug {
+                info!("Destination finished, dropping {} kept Events", events);
+            }
+            self.log_schedule(|| ScheduleRecord::Orphan {
+                destination: key.to_string(),
+                events,
+            });
+        }
+
+        let keys: Vec<ActivityIdentifier> = shard.lock().unwrap().keys().cloned().collect();
+
+        for key in keys {
+            if let Some(index) = self.thread_holding(&key) {
+                // Keep the lock until the events are delivered, see
+                // `ThreadHelper::events_in_flight(..)`
+                let mut guard = shard.lock().unwrap();
+                let events = guard.drain_for(&key);
+                if self.debug {
+                    info!("Deliver {} kept Events to thread {}", events.len(), index);
+                }
+                self.deliver_events(index, key, events);
+                drop(guard);
+            } else if let Some(node) = self.remote_location(&key) {
+                // The activity left this node after the events were kept
+                let events = shard.lock().unwrap().drain_for(&key);
+                let dropped = events
+                    .iter()
+                    .filter(|e| !self.forward_event(node, e))
+                    .count();
+                finished.record_dropped(dropped);
+            }
+        }
+    }
+
+    /// Handle activities from threads, checks the
+    /// `self.activities_from_threads` to find these activities, this struct
+    /// should be shared with ALL threads through the ThreadHelper struct.
+    ///
+    /// All queued activities are placed as one batch, so the events kept for
+    /// them are delivered in the same pass instead of one batch per pass.
+    fn handle_thread_activity(&mut self) {
+        // Take the activities, up to steal_batch_size per lock acquisition
+        let mut activities = Vec::new();
+        loop {
+            let taken = activities.len();
+            {
+                let guard = self.activities_from_threads.lock().unwrap();
+                while activities.len() - taken < self.steal_batch_size {
+                    match guard.steal() {
+                        Steal::Success(activity) => activities.push(activity),
+                        Steal::Retry => continue,
+                        Steal::Empty => break,
+                    }
+                }
+            }
+
+            if activities.len() == taken {
+                break;
+            }
+            self.steal_counters.record(activities.len() - taken);
+        }
+
+        self.distribute_activities(activities);
+
+        // Make sure event goes to correct thread, the lock is held until it
+        // is routed
+        let events_from_threads = self.events_from_threads.clone();
+        let guard = events_from_threads.lock().unwrap();
+        match guard.steal() {
+            Steal::Success(e) => {
+                self.distribute_event(e);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Index of the thread with the least work amongst the eligible threads which
+/// are not hung. If all eligible threads are hung, the first eligible thread
+/// is returned.
+///
+/// # Arguments
+/// * `work` - The work of each thread
+/// * `hung` - Whether each thread is hung
+/// * `eligible` - Filter on the thread indices
+///
+/// # Returns
+/// * `Option<usize>` - The index of the thread, None if no thread is eligible
+fn least_loaded<F: Fn(usize) -> bool>(work: &[usize], hung: &[bool], eligible: F) -> Option<usize> {
+    let mut index = None;
+
+    for i in (0..work.len()).filter(|&i| eligible(i)) {
+        match index {
+            None => index = Some(i),
+            Some(j) if hung[j] && !hung[i] => index = Some(i),
+            Some(j) if !hung[i] && work[i] < work[j] => index = Some(i),
+            _ => {}
+        }
+    }
+
+    index
+}
+
+/// Whether the thread has activities or events queued
+fn has_work(queues: &ExecutorQueues) -> bool {
+    !queues.activities.lock().unwrap().is_empty()
+        || !queues.activities_suspended.lock().unwrap().is_empty()
+        || !queues.event_queue.lock().unwrap().is_empty()
+}
+
+/// Add the queues of the threads to a snapshot, threads are identified by
+/// their index
+fn add_threads(snapshot: &mut ConstellationSnapshot, threads: &[ThreadEntry]) {
+    for (index, (_, queues)) in threads.iter().enumerate() {
+        snapshot.add_thread(
+            index as i32,
+            &queues.activities,
+            &queues.activities_suspended,
+            &queues.event_queue,
+        );
+    }
+}
+
+/// Add the number of dead-lettered events to a snapshot
+fn add_dead_lettered(snapshot: &mut ConstellationSnapshot, finished: &Mutex<FinishedActivities>) {
+    match snapshot::try_lock(finished) {
+        Some(finished) => snapshot.dead_lettered = finished.dropped_events(),
+        None => snapshot.skipped.push("finished activities".to_string()),
+    }
+}
+
+/// Sum of the size hints of all activities in the given queue
+fn queue_size(queue: &Arc<Mutex<ActivityQueue>>) -> usize {
+    queue.lock().unwrap().values().map(|a| a.size()).sum()
+}
+
+/// Record of placing an activity on a thread, or holding it back
+fn submit_record(activity: &dyn ActivityWrapperTrait, thread: Option<usize>) -> ScheduleRecord {
+    ScheduleRecord::Submit {
+        activity: activity.activity_identifier().to_string(),
+        context: activity.context().label().to_string(),
+        thread,
+    }
+}
+
+/// Event telling the observer an activity was placed on a thread, or held
+/// back. Activities which were moved away from a thread before are reported
+/// as migrated.
+fn placement_event(activity: &dyn ActivityWrapperTrait, thread: Option<usize>) -> SchedulerEvent {
+    let aid = activity.activity_identifier().clone();
+    match activity.migrated_from() {
+        Some(from) => SchedulerEvent::Migrated {
+            aid,
+            from,
+            to: thread,
+        },
+        None => SchedulerEvent::Submitted { aid, thread },
     }
 }
@@ -0,0 +1,893 @@
+///! Handle passed to activities by the executor threads, used to submit
+///! activities and send events from inside an activity.
+///!
+///! The handle never locks the constellation instance. Submitted activities
+///! and sent events are pushed onto the queues of the executor thread, or
+///! onto the injector queues of the load balancer when running multi
+///! threaded, so activities running on different threads do not serialize on
+///! a single mutex.
+///!
+///! The handle is cheap to clone and can be sent to other threads, so
+///! adapters running work outside of the executor thread can capture it. It
+///! also implements the ConstellationTrait, see `to_constellation()`, which
+///! keeps activities written against the old `Arc<Mutex<..>>` argument
+///! working through `LegacyActivityTrait`.
+#[cfg(feature = "compute-pool")]
+use crate::compute_pool::ComputePool;
+#[cfg(feature = "fault-injection")]
+use crate::fault_injection::{EventFault, FaultInjector};
+use crate::group::{self, GroupHandle};
+use crate::implementation::activity_names::ActivityNames;
+use crate::implementation::activity_queue::ActivityQueue;
+use crate::implementation::activity_wrapper::{ActivityWrapper, ActivityWrapperTrait};
+use crate::implementation::communication::comm::Communication;
+use crate::implementation::constellation_files::thread_helper::{ExecutorQueues, ThreadHelper};
+use crate::implementation::constellation_identifier::ConstellationIdentifier;
+use crate::implementation::context_members::ContextMembers;
+use crate::implementation::delayed_events::DelayedEvents;
+use crate::implementation::event_queue::EventQueue;
+use crate::implementation::finished_activities::FinishedActivities;
+use crate::implementation::idle_monitor::IdleMonitor;
+use crate::implementation::parker::Parker;
+use crate::implementation::pause_gate::PauseGate;
+use crate::implementation::scope_registry::{self, ScopeRegistry};
+use crate::intercept::{self, EventInterceptor};
+use crate::scheduler_event::SchedulerEvent;
+use crate::subscription::Subscriptions;
+use crate::{
+    AckHandle, ActivityIdentifier, ActivityTrait, ClosureActivity, ConstellationConfiguration,
+    ConstellationError, ConstellationSnapshot, ConstellationTrait, Context, ContextVec,
+    DelayedEventToken, Event, PayloadTrait, ScopeId, SendError, SubmitOptions, SubscriptionMode,
+};
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crossbeam::{Receiver, Sender};
+
+/// Handle used by activities to interact with the constellation instance
+/// they run in, see the module documentation.
+///
+/// # Members
+/// * `identifier` - Identifier of the executor thread, used to generate
+/// activity identifiers
+/// * `debug` - Whether to print debug messages
+/// * `master` - Whether this node is the master node
+/// * `nodes` - Number of nodes in the constellation instance
+/// * `threads` - Number of executor threads on this node
+/// * `thread_id` - Identifier of the executor thread
+/// * `contexts` - Contexts of the activities executed by the executor thread
+/// * `known_contexts` - Contexts activities may be submitted with, any
+/// context when empty
+/// * `allow_unserved_contexts` - Whether activities may be submitted with a
+/// context no executor thread serves
+/// * `parent` - Link to the load balancer, None when running single threaded
+/// * `work_queue` - Work queue of the executor thread
+/// * `work_suspended` - Suspended activities of the executor thread
+/// * `event_queue` - Event queue of the executor thread
+/// * `parker` - Parker of the executor thread, unparked after inserting in
+/// its queues
+/// * `scopes` - Registry of scopes, used to drop events for cancelled
+/// activities and to create and cancel scopes
+/// * `subscriptions` - Subscriptions of the application to events, see
+/// `subscribe_with(..)`
+/// * `finished` - Activities which finished recently, used to reject events
+/// for them
+/// * `context_members` - Live activities of every context, used to multicast
+/// events, see `send_to_context(..)`
+/// * `names` - Names of the live activities submitted with
+/// `submit_named(..)`
+/// * `delayed_events` - Queue of events sent with a delay, only used when
+/// running single threaded
+/// * `event_interceptors` - Interceptors run on every event sent
+/// * `observer` - Observer of the scheduling decisions, told by the executor
+/// thread when an activity finishes
+/// * `compute_pool` - Rayon thread pool shared by all activities, see
+/// `compute_pool()`
+/// * `fault_injector` - Injects faults in the events sent, see FaultInjector
+#[derive(Clone)]
+pub struct ConstellationHandle {
+    identifier: Arc<Mutex<ConstellationIdentifier>>,
+    debug: bool,
+    master: bool,
+    nodes: i32,
+    threads: i32,
+    thread_id: i32,
+    contexts: ContextVec,
+    known_contexts: ContextVec,
+    allow_unserved_contexts: bool,
+    parent: Option<ThreadHelper>,
+    work_queue: Arc<Mutex<ActivityQueue>>,
+    work_suspended: Arc<Mutex<ActivityQueue>>,
+    event_queue: Arc<Mutex<EventQueue>>,
+    parker: Arc<Parker>,
+    scopes: Arc<Mutex<ScopeRegistry>>,
+    subscriptions: Arc<Subscriptions>,
+    finished: Arc<Mutex<FinishedActivities>>,
+    context_members: Arc<Mutex<ContextMembers>>,
+    names: Arc<Mutex<ActivityNames>>,
+    delayed_events: Arc<Mutex<DelayedEvents>>,
+    event_interceptors: Vec<EventInterceptor>,
+    observer: Option<Sender<SchedulerEvent>>,
+    #[cfg(feature = "compute-pool")]
+    compute_pool: Option<Arc<ComputePool>>,
+    #[cfg(feature = "fault-injection")]
+    fault_injector: Option<Arc<FaultInjector>>,
+}
+
+/// The tables shared by all executor threads of a constellation instance,
+/// used by their handles and by the executor threads themselves
+///
+/// # Members
+/// * `scopes` - Registry of scopes
+/// * `subscriptions` - Subscriptions of the application to events
+/// * `finished` - Activities which finished recently
+/// * `context_members` - Live activities of every context
+/// * `names` - Names of the live activities
+/// * `delayed_events` - Queue of events sent with a delay, only used when
+/// running single threaded
+/// * `pause` - Gate closed while the instance is paused
+/// * `idle_monitor` - Keeps track of the threads which are working
+#[derive(Clone)]
+pub(crate) struct SharedState {
+    pub scopes: Arc<Mutex<ScopeRegistry>>,
+    pub subscriptions: Arc<Subscriptions>,
+    pub finished: Arc<Mutex<FinishedActivities>>,
+    pub context_members: Arc<Mutex<ContextMembers>>,
+    pub names: Arc<Mutex<ActivityNames>>,
+    pub delayed_events: Arc<Mutex<DelayedEvents>>,
+    pub pause: Arc<PauseGate>,
+    pub idle_monitor: Arc<IdleMonitor>,
+}
+
+impl ConstellationHandle {
+    /// Create the handle of an executor thread
+    ///
+    /// # Arguments
+    /// * `config` - The configuration of the instance
+    /// * `comm` - Communication with the other processes
+    /// * `queues` - The queues of the executor thread, the thread serves the
+    /// contexts of the configuration unless the queues list its contexts
+    /// * `shared` - The tables shared by all threads of the instance
+    /// * `threads` - Number of executor threads on this node
+    /// * `thread_id` - Identifier of the executor thread
+    /// * `parent` - Link to the load balancer, None when running single
+    /// threaded
+    pub(crate) fn new(
+        config: &ConstellationConfiguration,
+        comm: &dyn Communication,
+        queues: &ExecutorQueues,
+        shared: &SharedState,
+        threads: i32,
+        thread_id: i32,
+        parent: Option<ThreadHelper>,
+    ) -> ConstellationHandle {
+        ConstellationHandle {
+            identifier: queues.const_id.clone(),
+            debug: config.debug,
+            master: comm.is_master(config.master_rank),
+            nodes: comm.size(),
+            threads,
+            thread_id,
+            contexts: queues
+                .contexts
+                .clone()
+                .unwrap_or_else(|| config.context_vec.clone()),
+            known_contexts: config.known_contexts(),
+            allow_unserved_contexts: config.allow_unserved_contexts,
+            parent,
+            work_queue: queues.activities.clone(),
+            work_suspended: queues.activities_suspended.clone(),
+            event_queue: queues.event_queue.clone(),
+            parker: queues.parker.clone(),
+            scopes: shared.scopes.clone(),
+            subscriptions: shared.subscriptions.clone(),
+            finished: shared.finished.clone(),
+            context_members: shared.context_members.clone(),
+            names: shared.names.clone(),
+            delayed_events: shared.delayed_events.clone(),
+            event_interceptors: config.event_interceptors.clone(),
+            observer: config.observer.clone(),
+            #[cfg(feature = "compute-pool")]
+            compute_pool: config.compute_pool.clone(),
+            #[cfg(feature = "fault-injection")]
+            fault_injector: config.fault_injector.clone(),
+        }
+    }
+
+    /// The observer of the configuration, used by the executor thread when
+    /// an activity finishes
+    pub(crate) fn observer(&self) -> &Option<Sender<SchedulerEvent>> {
+        &self.observer
+    }
+
+    /// The fault injector of the configuration, used by the executor thread
+    /// when it picks up activities
+    #[cfg(feature = "fault-injection")]
+    pub(crate) fn fault_injector(&self) -> Option<&Arc<FaultInjector>> {
+        self.fault_injector.as_ref()
+    }
+
+    /// The rayon thread pool for data-parallel sections, shared by all
+    /// activities of this constellation instance, see ComputePool
+    ///
+    /// # Returns
+    /// * `Option<&ComputePool>` - The pool, None if `compute_pool` is not set
+    /// in the configuration
+    #[cfg(feature = "compute-pool")]
+    pub fn compute_pool(&self) -> Option<&ComputePool> {
+        self.compute_pool.as_deref()
+    }
+
+    /// Whether activities with the given context may be submitted: the
+    /// context is known, and an executor thread serves it or unserved
+    /// contexts are allowed
+    fn serves(&self, context: &Context) -> bool {
+        if !self.known_contexts.context_vec.is_empty() && !self.known_contexts.contains(context) {
+            return false;
+        }
+
+        match &self.parent {
+            Some(parent) => self.allow_unserved_contexts || parent.serves(context),
+            None => true,
+        }
+    }
+
+    /// Submit an activity, see `ConstellationTrait::submit_with(..)`. When
+    /// called from within an activity running in a scope, the new activity
+    /// is part of that scope unless the options specify one.
+    ///
+    /// # Arguments
+    /// * `activity` - The activity to submit
+    /// * `context` - The context of the activity
+    /// * `options` - SubmitOptions for this activity
+    ///
+    /// # Returns
+    /// * `Result<ActivityIdentifier, ConstellationError>` - The generated
+    /// identifier of the activity, see `SubmitOptions::validate(..)` for the
+    /// errors
+    pub fn submit_with(
+        &self,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<ActivityIdentifier, ConstellationError> {
+        let threads = match &self.parent {
+            Some(parent) => parent.thread_count(),
+            None => 1,
+        };
+        let node_id = self.identifier.lock().unwrap().node_info.node_id;
+        options.validate(
+            context,
+            self.serves(context),
+            threads,
+            node_id,
+            self.master,
+            self.nodes as usize,
+        )?;
+        let prefer_parent_thread =
+            options.prefer_parent_thread && options.thread_affinity.is_none();
+
+        let activity_wrapper =
+            ActivityWrapper::new(self.identifier.clone(), activity, context, options);
+        let activity_id = activity_wrapper.activity_identifier().clone();
+
+        if self.debug {
+            info!("Submitting activity with id: {}", &activity_id);
+        }
+        if let Some(parent) = &self.parent {
+            parent.trace_submitted(&activity_id);
+        }
+
+        self.context_members
+            .lock()
+            .unwrap()
+            .add(activity_id.clone(), context);
+
+        match &self.parent {
+            Some(parent) if prefer_parent_thread => {
+                parent.submit_near(&self.work_queue, activity_wrapper)
+            }
+            Some(parent) => parent.submit(activity_wrapper),
+            None => {
+                self.work_queue
+                    .lock()
+                    .unwrap()
+                    .insert(activity_id.clone(), activity_wrapper);
+                self.parker.unpark();
+            }
+        }
+
+        Ok(activity_id)
+    }
+
+    /// Submit an activity under a name, see
+    /// `ConstellationTrait::submit_named(..)`
+    pub fn submit_named(
+        &self,
+        name: &str,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<ActivityIdentifier, ConstellationError> {
+        // Keep the registry locked until the name is registered, so the name
+        // can not be taken in between and the activity can not finish before
+        let mut names = self.names.lock().unwrap();
+        if names.contains(name) {
+            return Err(ConstellationError::DuplicateName(name.to_string()));
+        }
+
+        let activity_id = self.submit_with(activity, context, options)?;
+        names.register(name, activity_id.clone());
+
+        Ok(activity_id)
+    }
+
+    /// Look up an activity submitted with `submit_named(..)`
+    ///
+    /// # Returns
+    /// * `Option<ActivityIdentifier>` - Identifier of the activity, None if
+    /// there is no live activity with the name
+    pub fn lookup(&self, name: &str) -> Option<ActivityIdentifier> {
+        self.names.lock().unwrap().lookup(name)
+    }
+
+    /// Submit a closure as an activity, see `ConstellationSpawn::spawn(..)`
+    pub fn spawn<F>(
+        &self,
+        context: &Context,
+        task: F,
+    ) -> Result<ActivityIdentifier, ConstellationError>
+    where
+        F: FnOnce(&ConstellationHandle, &ActivityIdentifier) + Send + 'static,
+    {
+        self.submit_with(
+            ClosureActivity::new(task),
+            context,
+            SubmitOptions::default(),
+        )
+    }
+
+    /// Submit a closure as an activity and send its result to the target,
+    /// see `ConstellationSpawn::spawn_with_result(..)`
+    pub fn spawn_with_result<F>(
+        &self,
+        context: &Context,
+        task: F,
+        target: ActivityIdentifier,
+    ) -> Result<ActivityIdentifier, ConstellationError>
+    where
+        F: FnOnce(&ConstellationHandle, &ActivityIdentifier) -> Box<dyn PayloadTrait>
+            + Send
+            + 'static,
+    {
+        self.submit_with(
+            ClosureActivity::with_result(task, target),
+            context,
+            SubmitOptions::default(),
+        )
+    }
+
+    /// Submit an activity using the default SubmitOptions apart from the two
+    /// flags given, see `submit_with(..)`
+    pub fn submit(
+        &self,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
+        context: &Context,
+        may_be_stolen: bool,
+        expects_events: bool,
+    ) -> Result<ActivityIdentifier, ConstellationError> {
+        self.submit_with(
+            activity,
+            context,
+            SubmitOptions {
+                may_be_stolen,
+                expects_events,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Submit an activity in the given scope, see `submit_with(..)`
+    pub fn submit_in_scope(
+        &self,
+        scope: ScopeId,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<ActivityIdentifier, ConstellationError> {
+        self.submit_with(
+            activity,
+            context,
+            SubmitOptions {
+                scope: Some(scope),
+                ..options
+            },
+        )
+    }
+
+    /// Create a new scope, a child of the scope of the calling activity if it
+    /// runs in one
+    ///
+    /// # Returns
+    /// * `ScopeId` - Identifier of the new scope
+    pub fn create_scope(&self) -> ScopeId {
+        let scope = self
+            .scopes
+            .lock()
+            .unwrap()
+            .create(scope_registry::current_scope());
+
+        if self.debug {
+            info!("Created scope: {}", scope);
+        }
+
+        scope
+    }
+
+    /// Cancel a scope, see `ConstellationTrait::cancel_scope(..)`
+    pub fn cancel_scope(&self, scope: ScopeId) {
+        if self.debug {
+            info!("Cancelling scope: {}", scope);
+        }
+
+        self.scopes.lock().unwrap().cancel(scope);
+    }
+
+    /// Submit a group of activities, see `ConstellationTrait::submit_group(..)`
+    pub fn submit_group(
+        &self,
+        activities: Vec<Arc<Mutex<dyn ActivityTrait>>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<GroupHandle, ConstellationError> {
+        group::submit_group(
+            self.create_scope(),
+            self.scopes.clone(),
+            activities,
+            context,
+            options,
+            |activity, context, options| self.submit_with(activity, context, options),
+        )
+    }
+
+    /// Send an event, it is delivered locally if the destination activity is
+    /// queued on this thread, otherwise it is routed by the load balancer.
+    /// Events for cancelled activities and events dropped by an interceptor
+    /// are discarded.
+    ///
+    /// # Arguments
+    /// * `e` - Event to send, contains src and destination IDs
+    ///
+    /// # Returns
+    /// * `Result<(), SendError>` - UnknownDestination if the destination was
+    /// not generated by this constellation instance, DestinationFinished if
+    /// it is known to have finished. An activity which finishes while the
+    /// event is routed is not reported, the event is dropped.
+    pub fn send(&self, e: Event) -> Result<(), SendError> {
+        self.send_boxed(e.boxed())
+    }
+
+    /// Send an event which is boxed already, see `send(..)`
+    pub(crate) fn send_boxed(&self, mut e: Box<Event>) -> Result<(), SendError> {
+        if self.debug {
+            info!("Send Event: {}", e.summary());
+        }
+        if let Some(parent) = &self.parent {
+            parent.trace_sent(&e);
+        }
+
+        if !intercept::intercept(&self.event_interceptors, &mut e) {
+            if self.debug {
+                info!("Event dropped by interceptor: {}", e.summary());
+            }
+            return Ok(());
+        }
+
+        #[cfg(feature = "fault-injection")]
+        let e = match self.inject_fault(e) {
+            Some(e) => e,
+            None => return Ok(()),
+        };
+
+        let aid = e.get_dst();
+
+        {
+            let identifier = self.identifier.lock().unwrap();
+            if !identifier.generated(&aid) {
+                return Err(SendError::UnknownDestination(aid));
+            }
+            if identifier.from_earlier_run(&aid) {
+                return Err(SendError::DestinationFinished(aid));
+            }
+        }
+
+        if self.scopes.lock().unwrap().is_activity_cancelled(&aid) {
+            if self.debug {
+                info!("Dropping event for cancelled activity: {}", e.summary());
+            }
+            return Ok(());
+        }
+
+        let parent = match &self.parent {
+            Some(parent) => parent,
+            None => {
+                // Running single threaded instance. Keep the lock on the
+                // finished activities until the event is queued, the executor
+                // thread holds it while dropping the events of an activity
+                // which finished.
+                let finished = self.finished.lock().unwrap();
+                if finished.contains(&aid) {
+                    return Err(SendError::DestinationFinished(aid));
+                }
+                self.event_queue.lock().unwrap().insert(aid, e);
+                self.parker.unpark();
+                return Ok(());
+            }
+        };
+
+        if parent.has_finished(&aid) {
+            return Err(SendError::DestinationFinished(aid));
+        }
+
+        // Check if we already have the corresponding activity, in the work
+        // queue or in the suspended queue. Events sent to it earlier which
+        // are still being routed must arrive first, so in that case this
+        // event is routed as well.
+        if !parent.events_in_flight(&aid) {
+            // Keep the lock on the work queue until the event is queued, the
+            // load balancer holds it while moving queued activities to
+            // another thread
+            let work_queue = self.work_queue.lock().unwrap();
+            if work_queue.contains_key(&aid)
+                || self.work_suspended.lock().unwrap().contains_key(&aid)
+            {
+                self.event_queue.lock().unwrap().insert(aid, e);
+                drop(work_queue);
+                self.parker.unpark();
+                return Ok(());
+            }
+        }
+
+        // Let parent deal with event, perhaps some other thread has the
+        // activity
+        parent.send(e);
+
+        Ok(())
+    }
+
+    /// Run the event through the fault injector, if there is one. A delayed
+    /// event passes the interceptors again once it is due.
+    ///
+    /// # Returns
+    /// * `Option<Box<Event>>` - The event, None if it was dropped or delayed
+    #[cfg(feature = "fault-injection")]
+    fn inject_fault(&self, e: Box<Event>) -> Option<Box<Event>> {
+        let injector = match &self.fault_injector {
+            Some(injector) => injector,
+            None => return Some(e),
+        };
+
+        let label = self
+            .context_members
+            .lock()
+            .unwrap()
+            .label(&e.get_dst())
+            .map(String::from);
+        match injector.inject_event(&e, label.as_deref()) {
+            EventFault::Deliver => Some(e),
+            EventFault::Drop => {
+                if self.debug {
+                    info!("Event dropped by fault injector: {}", e.summary());
+                }
+                None
+            }
+            EventFault::Delay(delay) => {
+                if self.debug {
+                    info!(
+                        "Event delayed {:?} by fault injector: {}",
+                        delay,
+                        e.summary()
+                    );
+                }
+                match &self.parent {
+                    Some(parent) => parent.delay_injected(e, delay),
+                    None => {
+                        self.delayed_events.lock().unwrap().push(e, delay);
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// Send an event to every live activity with the given context, see
+    /// `ConstellationTrait::send_to_context(..)`
+    pub fn send_to_context(
+        &self,
+        payload: Box<dyn PayloadTrait>,
+        src: ActivityIdentifier,
+        context: &Context,
+    ) -> Result<usize, SendError> {
+        let members = self.context_members.lock().unwrap().members(context);
+        if self.debug {
+            info!(
+                "Send Event to {} activities with context {}",
+                members.len(),
+                context.label()
+            );
+        }
+
+        let mut sent = 0;
+        for aid in members {
+            match self.send(Event::new(payload.clone(), src.clone(), aid)) {
+                Ok(()) => sent += 1,
+                Err(SendError::DestinationFinished(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(sent)
+    }
+
+    /// Forget an activity which finished or was removed because its scope
+    /// was cancelled, so that it does not receive multicast events and its
+    /// name is released
+    pub(crate) fn forget_activity(&self, aid: &ActivityIdentifier) {
+        self.context_members.lock().unwrap().remove(aid);
+        self.names.lock().unwrap().release(aid);
+    }
+
+    /// Send an event and request an acknowledgement, see
+    /// `ConstellationTrait::send_with_ack(..)`
+    pub fn send_with_ack(&self, mut e: Event) -> Result<AckHandle, SendError> {
+        let handle = e.request_ack();
+        self.send(e)?;
+        Ok(handle)
+    }
+
+    /// Send an event after the given delay, see
+    /// `ConstellationTrait::send_after(..)`
+    pub fn send_after(
+        &self,
+        e: Event,
+        delay: Duration,
+    ) -> Result<DelayedEventToken, ConstellationError> {
+        if self.debug {
+            info!("Send Event after {:?}: {}", delay, e.summary());
+        }
+
+        match &self.parent {
+            Some(parent) => Ok(parent.send_after(e.boxed(), delay)),
+            None => Ok(self.delayed_events.lock().unwrap().push(e.boxed(), delay)),
+        }
+    }
+
+    /// Cancel an event sent with `send_after(..)`
+    ///
+    /// # Returns
+    /// * `bool` - true if the event was cancelled before it was sent
+    pub fn cancel_delayed(&self, token: DelayedEventToken) -> bool {
+        match &self.parent {
+            Some(parent) => parent.cancel_delayed(token),
+            None => self.delayed_events.lock().unwrap().cancel(token),
+        }
+    }
+
+    /// Subscribe to the events sent to an activity, see
+    /// `ConstellationTrait::subscribe_with(..)`
+    pub fn subscribe_with(
+        &self,
+        aid: &ActivityIdentifier,
+        mode: SubscriptionMode,
+    ) -> Receiver<Box<Event>> {
+        self.subscriptions.subscribe(aid, mode)
+    }
+
+    /// Create an identifier which does not belong to an activity, see
+    /// `ConstellationTrait::allocate_external_id()`
+    pub fn allocate_external_id(&self) -> ActivityIdentifier {
+        let aid = ActivityIdentifier::new(self.identifier.clone());
+        self.subscriptions.add_external(aid.clone());
+        aid
+    }
+
+    /// Identifier of the executor thread this handle belongs to
+    pub fn identifier(&self) -> ConstellationIdentifier {
+        self.identifier
+            .lock()
+            .expect("Could not get lock on ConstellationIdentifier")
+            .clone()
+    }
+
+    /// Take a snapshot of the queues of all executor threads, see
+    /// `ConstellationTrait::dump_state()`. The activities held back by the
+    /// load balancer are not included, they are only known to the instance
+    /// itself.
+    pub fn dump_state(&self) -> ConstellationSnapshot {
+        let node = self.identifier().node_info.node_id;
+        match &self.parent {
+            Some(parent) => parent.snapshot(node),
+            None => {
+                let mut snapshot = ConstellationSnapshot::now(node);
+                snapshot.add_thread(
+                    self.thread_id,
+                    &self.work_queue,
+                    &self.work_suspended,
+                    &self.event_queue,
+                );
+                snapshot
+            }
+        }
+    }
+
+    /// Whether this node is the master node
+    pub fn is_master(&self) -> bool {
+        self.master
+    }
+
+    /// Number of nodes in the constellation instance
+    pub fn nodes(&self) -> i32 {
+        self.nodes
+    }
+
+    /// Number of executor threads on this node
+    pub fn threads(&self) -> i32 {
+        self.threads
+    }
+
+    /// Identifier of the executor thread this handle belongs to
+    pub fn thread_id(&self) -> i32 {
+        self.thread_id
+    }
+
+    /// Contexts of the activities executed by the executor thread this
+    /// handle belongs to
+    pub fn contexts(&self) -> &ContextVec {
+        &self.contexts
+    }
+
+    /// Wrap a clone of this handle in the type activities received before
+    /// the handle was introduced. The returned instance does not share a
+    /// lock with anything, locking it never blocks other activities.
+    ///
+    /// # Returns
+    /// * `Arc<Mutex<Box<dyn ConstellationTrait>>>` - The handle as a
+    /// constellation instance
+    pub fn to_constellation(&self) -> Arc<Mutex<Box<dyn ConstellationTrait>>> {
+        Arc::new(Mutex::new(Box::new(self.clone())))
+    }
+}
+
+/// Lets activities use the handle wherever a constellation instance is
+/// expected. The handle can not activate, pause or shut down the instance it
+/// belongs to, these methods fail.
+impl ConstellationTrait for ConstellationHandle {
+    fn activate(&mut self) -> Result<bool, ConstellationError> {
+        warn!("A constellation instance can not be activated through a handle");
+        Err(ConstellationError::Failed)
+    }
+
+    fn submit_with(
+        &mut self,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<ActivityIdentifier, ConstellationError> {
+        ConstellationHandle::submit_with(self, activity, context, options)
+    }
+
+    fn submit_named(
+        &mut self,
+        name: &str,
+        activity: Arc<Mutex<dyn ActivityTrait>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<ActivityIdentifier, ConstellationError> {
+        ConstellationHandle::submit_named(self, name, activity, context, options)
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<ActivityIdentifier> {
+        ConstellationHandle::lookup(self, name)
+    }
+
+    fn create_scope(&mut self) -> ScopeId {
+        ConstellationHandle::create_scope(self)
+    }
+
+    fn cancel_scope(&mut self, scope: ScopeId) {
+        ConstellationHandle::cancel_scope(self, scope)
+    }
+
+    fn submit_group(
+        &mut self,
+        activities: Vec<Arc<Mutex<dyn ActivityTrait>>>,
+        context: &Context,
+        options: SubmitOptions,
+    ) -> Result<GroupHandle, ConstellationError> {
+        ConstellationHandle::submit_group(self, activities, context, options)
+    }
+
+    fn send(&mut self, e: Event) -> Result<(), SendError> {
+        ConstellationHandle::send(self, e)
+    }
+
+    fn send_after(
+        &mut self,
+        e: Event,
+        delay: Duration,
+    ) -> Result<DelayedEventToken, ConstellationError> {
+        ConstellationHandle::send_after(self, e, delay)
+    }
+
+    fn cancel_delayed(&mut self, token: DelayedEventToken) -> bool {
+        ConstellationHandle::cancel_delayed(self, token)
+    }
+
+    fn send_to_context(
+        &mut self,
+        payload: Box<dyn PayloadTrait>,
+        src: ActivityIdentifier,
+        context: &Context,
+    ) -> Result<usize, SendError> {
+        ConstellationHandle::send_to_context(self, payload, src, context)
+    }
+
+    fn subscribe_with(
+        &mut self,
+        aid: &ActivityIdentifier,
+        mode: SubscriptionMode,
+    ) -> Receiver<Box<Event>> {
+        ConstellationHandle::subscribe_with(self, aid, mode)
+    }
+
+    fn allocate_external_id(&mut self) -> ActivityIdentifier {
+        ConstellationHandle::allocate_external_id(self)
+    }
+
+    fn pause(&mut self) -> Result<(), ConstellationError> {
+        warn!("A constellation instance can not be paused through a handle");
+        Err(ConstellationError::Failed)
+    }
+
+    fn resume(&mut self) -> Result<(), ConstellationError> {
+        warn!("A constellation instance can not be resumed through a handle");
+        Err(ConstellationError::Failed)
+    }
+
+    fn wait_until_paused(&mut self, _timeout: Duration) -> Result<bool, ConstellationError> {
+        warn!("A constellation instance can not be paused through a handle");
+        Err(ConstellationError::Failed)
+    }
+
+    fn wait_until_idle(&mut self, _timeout: Duration) -> Result<(), ConstellationError> {
+        // The activity waiting is executing, the instance is never idle
+        warn!("Can not wait for a constellation instance to be idle through a handle");
+        Err(ConstellationError::Failed)
+    }
+
+    fn done(&mut self) -> Result<bool, ConstellationError> {
+        warn!("A constellation instance can not be shut down through a handle");
+        Err(ConstellationError::Failed)
+    }
+
+    fn dump_state(&mut self) -> ConstellationSnapshot {
+        ConstellationHandle::dump_state(self)
+    }
+
+    fn identifier(&mut self) -> ConstellationIdentifier {
+        ConstellationHandle::identifier(self)
+    }
+
+    fn is_master(&self) -> Result<bool, ConstellationError> {
+        Ok(ConstellationHandle::is_master(self))
+    }
+
+    fn nodes(&mut self) -> i32 {
+        ConstellationHandle::nodes(self)
+    }
+
+    fn threads(&mut self) -> i32 {
+        ConstellationHandle::threads(self)
+    }
+}
@@ -1,14 +1,46 @@
 ///! An identifier for each thread running in constellation. It holds
 ///! information about all nodes and threads, as well as helps with generating
 ///! unique IDs for all newly submitted activities.
-use mpi::environment::Universe;
-use mpi::topology::{Communicator, Rank};
-
+///!
+///! The constellation_id identifies a run: it is nonzero, differs between
+///! runs and between the instances in one process, and is shared by all
+///! threads and nodes of an instance, the master hands it to the other nodes
+///! when activating.
+use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
 use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::process;
+use std::sync::atomic::{AtomicI32, Ordering};
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use crate::implementation::communication::comm::{Communication, CONSTELLATION_ID_TAG};
 use crate::implementation::communication::node_handler;
+use crate::{ActivityIdentifier, ConstellationError};
+
+/// Counter used to give every constellation instance in this process its own
+/// constellation_id
+static NEXT_CONSTELLATION_ID: AtomicI32 = AtomicI32::new(0);
+
+/// Time between two checks for the constellation_id sent by the master
+const AGREE_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+lazy_static! {
+    /// Random offset of the constellation_ids handed out in this process, so
+    /// they differ between runs. Derived from the startup time, the process
+    /// id and the random keys of the standard hasher.
+    static ref CONSTELLATION_ID_SEED: i32 = {
+        let mut hasher = RandomState::new().build_hasher();
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.as_nanos())
+            .hash(&mut hasher);
+        process::id().hash(&mut hasher);
+        (hasher.finish() % i32::MAX as u64) as i32
+    };
+}
 
 /// This struct is used to identify a certain thread and node in the running
 /// Constellation instance. Each struct shares an Arc to a counter, which
@@ -16,9 +48,9 @@ use crate::implementation::communication::node_handler;
 /// across all threads/nodes.
 ///
 /// # Members
-/// * `constellation_id` - An i32 number identifying this entire constellation
-/// instance. Can be used for e.g. distinguishing multiple executions of the
-/// same program, by each time incrementing this number and saving the logs.
+/// * `constellation_id` - A positive i32 number identifying this entire
+/// constellation instance, see the module documentation. Distinguishes the
+/// logs and traces of multiple executions of the same program.
 /// * `node_info` - NodeHandler struct, containing information about the node
 /// which created this ConstellationIdentifier instance.
 /// * `group` - A HashMap linking each MPI Rank to a certain NodeHandler struct,
@@ -26,13 +58,16 @@ use crate::implementation::communication::node_handler;
 /// * `thread_id` - A number identifying the thread who created this instance
 /// * `activity_counter` A shared Arc counter for all ConstellationIdentifier
 /// instances, used to create unique IDs for all generated activities.
+/// * `first_activity_id` - The first activity ID handed out since the
+/// instance was (re)started, lower IDs belong to an earlier run
 #[derive(Debug)]
 pub struct ConstellationIdentifier {
     pub constellation_id: i32,
     pub node_info: node_handler::NodeHandler,
-    pub group: HashMap<Rank, node_handler::NodeHandler>, // All processes and their node information
+    pub group: HashMap<i32, node_handler::NodeHandler>, // All processes and their node information
     pub thread_id: i32,
     pub activity_counter: Arc<Mutex<u64>>, // Shared between all threads
+    pub first_activity_id: u64,
 }
 
 impl ConstellationIdentifier {
@@ -42,7 +77,9 @@ impl ConstellationIdentifier {
     /// "currently running with this ID".
     ///
     /// # Arguments
-    /// * `universe` - MPI Universe construct
+    /// * `comm` - Communication with the other processes
+    /// * `constellation_id` - Identifier of the constellation instance, see
+    /// `next_constellation_id()`
     /// * `activity_counter` - An Arc<Mutex<u64>> counter, which is used to
     /// keep all ActivityIdentifiers unique across the entire constellation
     /// instance. Always increment this counter when creating a new activity ID
@@ -52,27 +89,27 @@ impl ConstellationIdentifier {
     /// * `ConstellationIdentifier` - Unique ConstellationIdentifier
     /// for each thread on each node
     pub fn new(
-        universe: &Universe,
+        comm: &dyn Communication,
+        constellation_id: i32,
         activity_counter: Arc<Mutex<u64>>,
         thread_id: i32,
     ) -> ConstellationIdentifier {
-        let world = universe.world();
-        let rank = world.rank();
+        let rank = comm.rank();
 
         let mut const_id = ConstellationIdentifier {
-            constellation_id: 0,
+            constellation_id,
             node_info: node_handler::NodeHandler {
-                node_name: mpi::environment::processor_name()
-                    .expect("Could not retrieve processor_name"),
+                node_name: comm.processor_name(),
                 node_id: 0,
             },
             group: HashMap::new(),
             thread_id,
             activity_counter,
+            first_activity_id: 0,
         };
 
-        // Create mpi groups to track processes on each node
-        node_handler::create_groups(&mut const_id.group, &universe);
+        // Create groups to track processes on each node
+        const_id.group = comm.groups();
 
         const_id.node_info.node_id = const_id.group.get(&rank).unwrap().node_id;
 
@@ -95,7 +132,139 @@ impl ConstellationIdentifier {
             group: HashMap::new(),
             thread_id: 0,
             activity_counter: Arc::new(Mutex::new(0)),
+            first_activity_id: 0,
+        }
+    }
+
+    /// Generate the identifier of a child, e.g. an executor thread, of the
+    /// instance this identifier belongs to. The child shares the
+    /// constellation_id, the node information and the activity counter of
+    /// this identifier, so the activity IDs it hands out stay unique across
+    /// the instance, and differs only in thread_id.
+    ///
+    /// # Arguments
+    /// * `thread_id` - A unique number identifying the child thread
+    ///
+    /// # Returns
+    /// * `ConstellationIdentifier` - The identifier of the child
+    pub fn generate_child_identifier(&self, thread_id: i32) -> ConstellationIdentifier {
+        ConstellationIdentifier {
+            constellation_id: self.constellation_id,
+            node_info: self.node_info.clone(),
+            group: self.group.clone(),
+            thread_id,
+            activity_counter: self.activity_counter.clone(),
+            first_activity_id: self.first_activity_id,
+        }
+    }
+
+    /// Generate the identifier for a new constellation instance, so that
+    /// activities of multiple instances in one process do not share IDs.
+    ///
+    /// The identifiers are handed out in order from a random offset, so they
+    /// differ between runs. The processes on the other nodes take over the
+    /// identifier of the master when activating, see
+    /// `agree_constellation_id(..)`.
+    ///
+    /// # Returns
+    /// * `i32` - A positive constellation_id not used by any other instance
+    /// in this process
+    pub fn next_constellation_id() -> i32 {
+        let counter = NEXT_CONSTELLATION_ID.fetch_add(1, Ordering::Relaxed);
+
+        // Wrap within 1..=i32::MAX
+        let offset = (*CONSTELLATION_ID_SEED as i64 + counter as i64) % i32::MAX as i64;
+        offset as i32 + 1
+    }
+
+    /// Agree on the constellation_id of an instance: the master sends its
+    /// identifier to all other processes, which wait for it. This is a
+    /// collective call, it MUST be called from each process when activating.
+    /// When the processes share a thread, the master must call it first.
+    ///
+    /// # Arguments
+    /// * `comm` - Communication with the other processes
+    /// * `master_rank` - Rank of the master process
+    /// * `constellation_id` - The identifier of the calling process
+    /// * `timeout` - Maximum time the other processes wait for the master
+    ///
+    /// # Returns
+    /// * `Result<i32, ConstellationError>` - The constellation_id of the
+    /// master, ConstellationError::Failed if it did not arrive in time
+    pub fn agree_constellation_id(
+        comm: &dyn Communication,
+        master_rank: i32,
+        constellation_id: i32,
+        timeout: Duration,
+    ) -> Result<i32, ConstellationError> {
+        if comm.is_master(master_rank) {
+            let message = constellation_id.to_be_bytes();
+            for rank in (0..comm.size()).filter(|&rank| rank != master_rank) {
+                comm.send(rank, CONSTELLATION_ID_TAG, &message);
+            }
+
+            return Ok(constellation_id);
+        }
+
+        let start = Instant::now();
+        loop {
+            match comm.try_receive(CONSTELLATION_ID_TAG) {
+                Some((_, message)) if message.len() == 4 => {
+                    let mut bytes = [0; 4];
+                    bytes.copy_from_slice(&message);
+                    return Ok(i32::from_be_bytes(bytes));
+                }
+                Some((rank, message)) => warn!(
+                    "Dropping malformed constellation_id of {} bytes from rank {}",
+                    message.len(),
+                    rank
+                ),
+                None if start.elapsed() >= timeout => {
+                    warn!(
+                        "Master {} did not send the constellation_id within {:?}",
+                        master_rank, timeout
+                    );
+                    return Err(ConstellationError::Failed);
+                }
+                None => thread::sleep(AGREE_POLL_INTERVAL),
+            }
+        }
+    }
+
+    /// Check whether an activity identifier was generated by this
+    /// constellation instance. Identifiers generated on other nodes can not be
+    /// checked here, they are assumed to be valid.
+    ///
+    /// # Arguments
+    /// * `aid` - The activity identifier to check
+    ///
+    /// # Returns
+    /// * `bool` - false if the identifier belongs to another constellation
+    /// instance, or has not been handed out yet
+    pub fn generated(&self, aid: &ActivityIdentifier) -> bool {
+        if aid.constellation_id != self.constellation_id {
+            return false;
+        }
+        if aid.node_info.node_id != self.node_info.node_id {
+            return true;
         }
+
+        aid.activity_id < *self.activity_counter.lock().unwrap()
+    }
+
+    /// Check whether an activity identifier was handed out by this instance
+    /// before it was last restarted, see `first_activity_id`
+    ///
+    /// # Arguments
+    /// * `aid` - The activity identifier to check
+    ///
+    /// # Returns
+    /// * `bool` - true if the activity belongs to an earlier run, it can not
+    /// receive events anymore
+    pub fn from_earlier_run(&self, aid: &ActivityIdentifier) -> bool {
+        aid.constellation_id == self.constellation_id
+            && aid.node_info.node_id == self.node_info.node_id
+            && aid.activity_id < self.first_activity_id
     }
 
     /// Increment the counter when creating a unique number for an activity
@@ -131,6 +300,7 @@ impl Clone for ConstellationIdentifier {
             group: HashMap::new(),
             thread_id: self.thread_id,
             activity_counter: self.activity_counter.clone(),
+            first_activity_id: self.first_activity_id,
         }
     }
 }
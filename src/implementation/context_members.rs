@@ -0,0 +1,76 @@
+///! Registry of the live activities of every context, shared between the
+///! executor threads and the load balancer of a constellation instance, used
+///! to multicast events to all activities with a context, see
+///! `ConstellationTrait::send_to_context(..)`.
+///!
+///! An activity is added when it is submitted, and removed when it finishes or
+///! is removed because its scope was cancelled. Activities which are running,
+///! queued on a thread or still being placed are all members.
+use crate::context::ContextId;
+use crate::{ActivityIdentifier, Context};
+
+use hashbrown::{HashMap, HashSet};
+
+/// ContextMembers struct
+///
+/// # Members
+/// * `members` - The live activities of every context, by context id
+/// * `contexts` - The context of every live activity, used to remove it
+pub struct ContextMembers {
+    members: HashMap<ContextId, HashSet<ActivityIdentifier>>,
+    contexts: HashMap<ActivityIdentifier, Context>,
+}
+
+impl ContextMembers {
+    pub fn new() -> ContextMembers {
+        ContextMembers {
+            members: HashMap::new(),
+            contexts: HashMap::new(),
+        }
+    }
+
+    /// Record a submitted activity
+    pub fn add(&mut self, aid: ActivityIdentifier, context: &Context) {
+        self.members
+            .entry(context.id())
+            .or_insert_with(HashSet::new)
+            .insert(aid.clone());
+        self.contexts.insert(aid, context.clone());
+    }
+
+    /// Forget an activity which finished or was cancelled
+    pub fn remove(&mut self, aid: &ActivityIdentifier) {
+        let id = match self.contexts.remove(aid) {
+            Some(context) => context.id(),
+            None => return,
+        };
+
+        let empty = match self.members.get_mut(&id) {
+            Some(members) => {
+                members.remove(aid);
+                members.is_empty()
+            }
+            None => false,
+        };
+        if empty {
+            self.members.remove(&id);
+        }
+    }
+
+    /// The context label of a live activity
+    #[cfg(feature = "fault-injection")]
+    pub fn label(&self, aid: &ActivityIdentifier) -> Option<&str> {
+        self.contexts.get(aid).map(Context::label)
+    }
+
+    /// The live activities with the given context, in the order they were
+    /// generated
+    pub fn members(&self, context: &Context) -> Vec<ActivityIdentifier> {
+        let mut members: Vec<ActivityIdentifier> = match self.members.get(&context.id()) {
+            Some(members) => members.iter().cloned().collect(),
+            None => Vec::new(),
+        };
+        members.sort();
+        members
+    }
+}
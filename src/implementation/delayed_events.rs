@@ -0,0 +1,95 @@
+///! Queue of events which should be sent after a delay, see
+///! `ConstellationTrait::send_after(..)`. The queue is ordered by the time at
+///! which events are due, and is periodically polled by the thread routing
+///! events (the MultiThreadHelper, or the executor thread when running single
+///! threaded). The time is read from the clock of the constellation instance.
+use crate::clock::Clock;
+use crate::event::DelayedEventToken;
+use crate::Event;
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use hashbrown::HashMap;
+
+/// DelayedEvents struct
+///
+/// # Members
+/// * `next_token` - Counter used to generate unique tokens
+/// * `queue` - Events ordered by the time they are due, the token is part of
+/// the key to keep events which are due at the same time apart
+/// * `due` - The time each pending token is due, used to cancel events
+/// * `clock` - The clock deciding when events are due
+pub struct DelayedEvents {
+    next_token: u64,
+    queue: BTreeMap<(Instant, u64), Box<Event>>,
+    due: HashMap<u64, Instant>,
+    clock: Clock,
+}
+
+impl DelayedEvents {
+    pub fn new(clock: Clock) -> DelayedEvents {
+        DelayedEvents {
+            next_token: 0,
+            queue: BTreeMap::new(),
+            due: HashMap::new(),
+            clock,
+        }
+    }
+
+    /// Add an event which should be sent after the given delay
+    pub fn push(&mut self, event: Box<Event>, delay: Duration) -> DelayedEventToken {
+        let token = self.next_token;
+        self.next_token += 1;
+
+        let due = self.clock.now() + delay;
+        self.queue.insert((due, token), event);
+        self.due.insert(token, due);
+
+        DelayedEventToken(token)
+    }
+
+    /// Remove a pending event
+    ///
+    /// # Returns
+    /// * `bool` - true if the event was still pending, false if it was
+    /// already sent or cancelled
+    pub fn cancel(&mut self, token: DelayedEventToken) -> bool {
+        match self.due.remove(&token.0) {
+            Some(due) => self.queue.remove(&(due, token.0)).is_some(),
+            None => false,
+        }
+    }
+
+    /// Remove and return all events that are due, in the order they became
+    /// due
+    pub fn pop_due(&mut self) -> Vec<Box<Event>> {
+        let now = self.clock.now();
+        let mut events = Vec::new();
+
+        while let Some(&(due, token)) = self.queue.keys().next() {
+            if due > now {
+                break;
+            }
+
+            self.due.remove(&token);
+            events.push(self.queue.remove(&(due, token)).unwrap());
+        }
+
+        events
+    }
+
+    /// The time at which the next pending event is due
+    pub fn next_due(&self) -> Option<Instant> {
+        self.queue.keys().next().map(|&(due, _)| due)
+    }
+
+    /// Whether a pending event is due
+    pub fn has_due(&self) -> bool {
+        self.next_due().map_or(false, |due| due <= self.clock.now())
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+}
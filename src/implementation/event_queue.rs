@@ -1,9 +1,16 @@
 ///! Wrapper module for the Event HashMap, unique for each thread. This module
 ///! makes sure there can be multiple events sent to the same destination,
-///! by extending the ordinary HashMap (from hashbrown) to hold a vector of
-///! Events as value.
+///! by extending the ordinary HashMap (from hashbrown) to hold a queue of
+///! Events as value. Events for the same destination are kept in the order
+///! they were inserted, and a destination without events has no entry.
+///!
+///! The event queue of an executor thread also records the destination of
+///! every inserted event, so the executor only has to check the suspended
+///! activities which received events instead of all of them.
 use crate::{ActivityIdentifier, Event};
 
+use std::collections::VecDeque;
+
 use hashbrown::hash_map::Keys;
 use hashbrown::HashMap;
 
@@ -11,41 +18,173 @@ use hashbrown::HashMap;
 ///
 /// # Members
 /// * `data` - The HashMap containing as key the ActivityIdentifiers
-/// representing the destination activity as well as a vector of Events which
-/// should go there.
+/// representing the destination activity as well as a queue of Events which
+/// should go there, oldest first.
+/// * `arrivals` - Destinations of the events inserted since the arrivals were
+/// last taken, None if they are not recorded
 pub struct EventQueue {
-    data: HashMap<ActivityIdentifier, Vec<Box<Event>>>,
+    data: HashMap<ActivityIdentifier, VecDeque<Box<Event>>>,
+    arrivals: Option<Vec<ActivityIdentifier>>,
 }
 
 impl EventQueue {
     pub fn new() -> EventQueue {
         EventQueue {
             data: HashMap::new(),
+            arrivals: None,
+        }
+    }
+
+    /// Create an event queue recording the destination of every inserted
+    /// event, see take_arrivals(). The arrivals must be taken regularly.
+    pub fn with_arrivals() -> EventQueue {
+        EventQueue {
+            data: HashMap::new(),
+            arrivals: Some(Vec::new()),
         }
     }
 
     pub fn insert(&mut self, key: ActivityIdentifier, event: Box<Event>) {
-        self.data.entry(key).or_insert_with(Vec::new).push(event);
+        if let Some(arrivals) = self.arrivals.as_mut() {
+            arrivals.push(key.clone());
+        }
+        self.data
+            .entry(key)
+            .or_insert_with(VecDeque::new)
+            .push_back(event);
     }
 
-    /// If there are multiple events, only one is returned. When the last one
-    /// is returned, the entry is removed.
+    /// If there are multiple events, only the oldest one is returned. When
+    /// the last one is returned, the entry is removed.
     pub fn remove(&mut self, key: ActivityIdentifier) -> Option<Box<Event>> {
-        let mut event: Option<Box<Event>> = None;
-        self.data.entry(key.clone()).and_modify(|e| event = e.pop());
+        let events = self.data.get_mut(&key)?;
+        let event = events.pop_front();
 
-        let empty = self.data.get(&key);
-
-        if empty.is_some() && empty.unwrap().is_empty() {
+        if events.is_empty() {
             self.data.remove(&key);
         }
         event
     }
 
+    /// Put events taken from the queue back in front of the events queued
+    /// for the given key since, keeping their order
+    pub fn requeue(&mut self, key: ActivityIdentifier, events: VecDeque<Box<Event>>) {
+        if events.is_empty() {
+            return;
+        }
+        let queued = self.data.entry(key).or_insert_with(VecDeque::new);
+        for event in events.into_iter().rev() {
+            queued.push_front(event);
+        }
+    }
+
+    /// Remove and return all events for the given key, in the order they
+    /// were inserted. The vector is empty if there were no events.
+    pub fn drain_for(&mut self, key: &ActivityIdentifier) -> Vec<Box<Event>> {
+        self.data
+            .remove(key)
+            .map_or_else(Vec::new, |events| events.into_iter().collect())
+    }
+
+    /// Remove and return the events with the given ids for the given key, in
+    /// the order of the ids. Nothing is removed unless all of them are
+    /// queued.
+    ///
+    /// # Arguments
+    /// * `key` - Destination of the events
+    /// * `ids` - Ids of the events, see `Event::get_id()`
+    ///
+    /// # Returns
+    /// * `Option<Vec<Box<Event>>>` - The events, None if not all of them are
+    /// queued
+    pub fn take_events(
+        &mut self,
+        key: &ActivityIdentifier,
+        ids: &[u64],
+    ) -> Option<Vec<Box<Event>>> {
+        if ids.is_empty() {
+            return Some(Vec::new());
+        }
+
+        let events = self.data.get_mut(key)?;
+        if !ids
+            .iter()
+            .all(|id| events.iter().any(|e| e.get_id() == *id))
+        {
+            return None;
+        }
+
+        let mut taken = Vec::with_capacity(ids.len());
+        for id in ids {
+            let position = events.iter().position(|e| e.get_id() == *id)?;
+            taken.extend(events.remove(position));
+        }
+
+        if events.is_empty() {
+            self.data.remove(key);
+        }
+        Some(taken)
+    }
+
+    /// Take the destinations of the events inserted since the last call, a
+    /// destination is listed once per event. Empty if arrivals are not
+    /// recorded.
+    pub fn take_arrivals(&mut self) -> Vec<ActivityIdentifier> {
+        self.arrivals
+            .as_mut()
+            .map_or_else(Vec::new, |arrivals| std::mem::replace(arrivals, Vec::new()))
+    }
+
+    /// Number of events queued for the given key
+    pub fn count_for(&self, key: &ActivityIdentifier) -> usize {
+        self.data.get(key).map_or(0, |events| events.len())
+    }
+
+    /// Keep only the events for which the predicate returns true, the order
+    /// of the kept events does not change. Entries left without events are
+    /// removed.
+    ///
+    /// # Arguments
+    /// * `f` - Predicate called with the destination and the event
+    ///
+    /// # Returns
+    /// * `usize` - Number of events removed
+    pub fn retain<F>(&mut self, mut f: F) -> usize
+    where
+        F: FnMut(&ActivityIdentifier, &Event) -> bool,
+    {
+        let mut removed = 0;
+        self.data.retain(|key, events| {
+            let before = events.len();
+            events.retain(|event| f(key, event));
+            removed += before - events.len();
+            !events.is_empty()
+        });
+        removed
+    }
+
     pub fn contains_key(&mut self, key: &ActivityIdentifier) -> bool {
         self.data.contains_key(key)
     }
 
+    /// Iterate over all queued events
+    pub fn events(&self) -> impl Iterator<Item = &Box<Event>> {
+        self.data.values().flat_map(|events| events.iter())
+    }
+
+    /// Remove all events
+    ///
+    /// # Returns
+    /// * `usize` - Number of events removed
+    pub fn clear(&mut self) -> usize {
+        let events = self.data.values().map(|events| events.len()).sum();
+        self.data.clear();
+        if let Some(arrivals) = self.arrivals.as_mut() {
+            arrivals.clear();
+        }
+        events
+    }
+
     pub fn is_empty(&self) -> bool {
         self.data.is_empty()
     }
@@ -54,7 +193,7 @@ impl EventQueue {
         self.data.len()
     }
 
-    pub fn keys(&self) -> Keys<ActivityIdentifier, Vec<Box<Event>>> {
+    pub fn keys(&self) -> Keys<ActivityIdentifier, VecDeque<Box<Event>>> {
         self.data.keys()
     }
 }
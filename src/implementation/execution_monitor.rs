@@ -0,0 +1,110 @@
+///! Keeps track of the activity an executor thread is currently running, in
+///! order to detect activities exceeding their `max_execution_time` (see
+///! SubmitOptions). There is one monitor per executor thread, it is updated by
+///! the executor thread and checked by a watchdog: the MultiThreadHelper when
+///! running multi threaded, or `done()` when running single threaded.
+///!
+///! Activities are not preempted, a thread running an activity which exceeded
+///! its execution time is reported and considered hung until the activity
+///! returns.
+use crate::ActivityIdentifier;
+
+use std::time::{Duration, Instant};
+
+/// ExecutionMonitor struct
+///
+/// # Members
+/// * `running` - The activity currently executing with an execution time
+/// limit, None if no such activity is running
+pub struct ExecutionMonitor {
+    running: Option<RunningActivity>,
+}
+
+/// Activity currently executing on an executor thread
+///
+/// # Members
+/// * `aid` - Identifier of the activity
+/// * `started` - When the executor thread invoked the activity
+/// * `limit` - Maximum execution time of the activity
+/// * `timed_out` - Whether the activity has been reported for exceeding its
+/// execution time
+struct RunningActivity {
+    aid: ActivityIdentifier,
+    started: Instant,
+    limit: Duration,
+    timed_out: bool,
+}
+
+impl ExecutionMonitor {
+    pub fn new() -> ExecutionMonitor {
+        ExecutionMonitor { running: None }
+    }
+
+    /// Record that an activity is invoked, only activities with an execution
+    /// time limit are tracked.
+    ///
+    /// # Arguments
+    /// * `aid` - Identifier of the activity
+    /// * `limit` - The maximum execution time of the activity
+    /// * `now` - The current time
+    pub fn start(&mut self, aid: ActivityIdentifier, limit: Option<Duration>, now: Instant) {
+        self.running = limit.map(|limit| RunningActivity {
+            aid,
+            started: now,
+            limit,
+            timed_out: false,
+        });
+    }
+
+    /// Record that the running activity returned
+    ///
+    /// # Arguments
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    /// * `Option<(ActivityIdentifier, Duration)>` - The activity and the time
+    /// it ran, if it exceeded its execution time limit, also when the
+    /// watchdog did not check it meanwhile
+    pub fn finish(&mut self, now: Instant) -> Option<(ActivityIdentifier, Duration)> {
+        let running = self.running.take()?;
+        let elapsed = now.duration_since(running.started);
+
+        if running.timed_out || elapsed > running.limit {
+            Some((running.aid, elapsed))
+        } else {
+            None
+        }
+    }
+
+    /// Check whether the running activity exceeded its execution time limit,
+    /// every activity is only reported once.
+    ///
+    /// # Arguments
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    /// * `Option<(ActivityIdentifier, Duration)>` - The activity and the time
+    /// it has been running, if it has just been found to exceed its limit
+    pub fn check(&mut self, now: Instant) -> Option<(ActivityIdentifier, Duration)> {
+        let running = self.running.as_mut()?;
+        if running.timed_out {
+            return None;
+        }
+
+        let elapsed = now.duration_since(running.started);
+        if elapsed <= running.limit {
+            return None;
+        }
+
+        running.timed_out = true;
+        Some((running.aid.clone(), elapsed))
+    }
+
+    /// Whether the thread is running an activity which exceeded its execution
+    /// time limit
+    pub fn is_hung(&self) -> bool {
+        self.running
+            .as_ref()
+            .map_or(false, |running| running.timed_out)
+    }
+}
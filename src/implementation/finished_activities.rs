@@ -0,0 +1,93 @@
+///! Bounded record of the activities which finished recently, shared between
+///! the executor threads and the load balancer of a constellation instance.
+///! An event whose destination has finished can never be delivered, it is
+///! dropped instead of being kept forever, which would prevent `done()` from
+///! shutting down. Dropping an event signals DeadLettered to its
+///! acknowledgement, if one was requested.
+///!
+///! Only the last FINISHED_ACTIVITIES_CAPACITY activities are remembered, so
+///! memory stays constant. Events for an activity which finished longer ago
+///! are kept as before, and reported as orphaned by `done()`.
+use crate::ActivityIdentifier;
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use hashbrown::HashMap;
+
+/// Number of finished activities remembered per constellation instance
+pub const FINISHED_ACTIVITIES_CAPACITY: usize = 4096;
+
+/// FinishedActivities struct
+///
+/// # Members
+/// * `finished` - When each remembered activity finished
+/// * `order` - The remembered activities in the order they finished, used to
+/// forget the oldest one when the capacity is reached
+/// * `capacity` - Maximum number of activities remembered
+/// * `dropped_events` - Number of events dropped because their destination
+/// had finished
+/// * `total` - Number of activities which finished, including those which
+/// have been forgotten
+pub struct FinishedActivities {
+    finished: HashMap<ActivityIdentifier, Instant>,
+    order: VecDeque<ActivityIdentifier>,
+    capacity: usize,
+    dropped_events: usize,
+    total: u64,
+}
+
+impl FinishedActivities {
+    pub fn new(capacity: usize) -> FinishedActivities {
+        FinishedActivities {
+            finished: HashMap::new(),
+            order: VecDeque::new(),
+            capacity: capacity.max(1),
+            dropped_events: 0,
+            total: 0,
+        }
+    }
+
+    /// Record that an activity finished, the activity which finished first
+    /// is forgotten when the capacity is reached
+    pub fn record(&mut self, aid: ActivityIdentifier) {
+        if self.finished.insert(aid.clone(), Instant::now()).is_some() {
+            return;
+        }
+
+        self.total += 1;
+        self.order.push_back(aid);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.finished.remove(&oldest);
+            }
+        }
+    }
+
+    /// Check whether the activity is known to have finished
+    pub fn contains(&self, aid: &ActivityIdentifier) -> bool {
+        self.finished.contains_key(aid)
+    }
+
+    /// When the activity finished, None if it did not finish or has been
+    /// forgotten
+    pub fn finished_at(&self, aid: &ActivityIdentifier) -> Option<Instant> {
+        self.finished.get(aid).cloned()
+    }
+
+    /// Count events which were dropped because their destination finished
+    pub fn record_dropped(&mut self, events: usize) {
+        self.dropped_events += events;
+    }
+
+    /// Number of events dropped because their destination had finished
+    pub fn dropped_events(&self) -> usize {
+        self.dropped_events
+    }
+
+    /// Number of activities which finished, including those which have been
+    /// forgotten
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+}
@@ -0,0 +1,110 @@
+///! Heartbeats between the nodes of a constellation instance. Every node runs
+///! a heartbeat thread, also the nodes which are not the master and do not
+///! run a load balancer. Once every `heartbeat_interval` it sends a heartbeat
+///! to all other nodes, records the heartbeats which arrived in the
+///! HealthTable and reports the nodes which became unresponsive. The interval
+///! is measured on the clock of the constellation instance.
+use crate::clock::Clock;
+use crate::constellation_config::NodeUnresponsiveCallback;
+use crate::implementation::communication::comm::{Communication, HEARTBEAT_TAG};
+use crate::implementation::panic_hook;
+use crate::node_health::HealthTable;
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crossbeam::{unbounded, Receiver, RecvTimeoutError, Sender};
+
+/// Start the heartbeat thread
+///
+/// # Arguments
+/// * `comm` - Communication with the other nodes
+/// * `table` - The table in which the heartbeats are recorded
+/// * `interval` - Time between two heartbeats
+/// * `on_unresponsive` - Optional callback, called on the heartbeat thread
+/// with the rank of every node which became unresponsive and the number of
+/// heartbeats it missed
+/// * `debug` - Whether to log nodes which respond again
+/// * `clock` - The clock the heartbeats are recorded with
+///
+/// # Returns
+/// * `io::Result<Sender<()>>` - Dropping the sender stops the thread, error
+/// if the thread could not be spawned
+pub fn start(
+    comm: Arc<dyn Communication>,
+    table: Arc<Mutex<HealthTable>>,
+    interval: Duration,
+    on_unresponsive: Option<NodeUnresponsiveCallback>,
+    debug: bool,
+    clock: Clock,
+) -> io::Result<Sender<()>> {
+    let (stop, stopped) = unbounded();
+
+    thread::Builder::new()
+        .name(panic_hook::heartbeat_thread_name())
+        .spawn(move || {
+            run(
+                &*comm,
+                &table,
+                interval,
+                on_unresponsive,
+                debug,
+                &clock,
+                stopped,
+            )
+        })?;
+
+    Ok(stop)
+}
+
+fn run(
+    comm: &dyn Communication,
+    table: &Mutex<HealthTable>,
+    interval: Duration,
+    on_unresponsive: Option<NodeUnresponsiveCallback>,
+    debug: bool,
+    clock: &Clock,
+    stopped: Receiver<()>,
+) {
+    let rank = comm.rank();
+
+    loop {
+        let next = clock.now() + interval;
+
+        for peer in (0..comm.size()).filter(|peer| *peer != rank) {
+            comm.send(peer, HEARTBEAT_TAG, &[]);
+        }
+
+        let unresponsive = {
+            let mut table = table.lock().unwrap();
+            while let Some((source, _)) = comm.try_receive(HEARTBEAT_TAG) {
+                if table.record_at(source, clock.now()) && debug {
+                    info!("Node {} responds again", source);
+                }
+            }
+
+            table.check_at(clock.now())
+        };
+
+        for (peer, missed) in unresponsive {
+            warn!(
+                "Node {} is unresponsive, it missed {} heartbeats in a row",
+                peer, missed
+            );
+            if let Some(callback) = &on_unresponsive {
+                callback(peer, missed);
+            }
+        }
+
+        // Wait until the next heartbeat is due, or the thread is stopped
+        loop {
+            match stopped.recv_timeout(clock.time_until(next)) {
+                Err(RecvTimeoutError::Timeout) if clock.now() >= next => break,
+                Err(RecvTimeoutError::Timeout) => continue,
+                _ => return,
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+///! Keeps track of the threads of a constellation instance which are working,
+///! used to wait until the instance is idle without shutting it down. An
+///! executor thread counts as active from the moment it takes work from its
+///! queues until it has been without work long enough to park, so activities
+///! which are executing are accounted for. The load balancer counts as active
+///! while it routes activities and events.
+///!
+///! Waiting threads are notified when the last active thread becomes idle,
+///! and check the queues of the instance in between as well, so a missed
+///! notification only delays them by IDLE_POLL_INTERVAL.
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Maximum time between two checks whether the instance is idle
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// IdleMonitor struct
+///
+/// # Members
+/// * `active` - Number of threads which are currently working
+/// * `epoch` - Incremented whenever a thread starts working, used to detect
+/// threads which started working while the queues were checked
+/// * `lock` - Lock used together with `condvar`
+/// * `condvar` - Notified when the last active thread becomes idle
+pub struct IdleMonitor {
+    active: AtomicUsize,
+    epoch: AtomicU64,
+    lock: Mutex<()>,
+    condvar: Condvar,
+}
+
+impl IdleMonitor {
+    pub fn new() -> IdleMonitor {
+        IdleMonitor {
+            active: AtomicUsize::new(0),
+            epoch: AtomicU64::new(0),
+            lock: Mutex::new(()),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Record that a thread starts working, must be called before it takes
+    /// work from a queue
+    pub fn set_busy(&self) {
+        // Count the thread before changing the epoch, see `wait_until_idle`
+        self.active.fetch_add(1, Ordering::SeqCst);
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Record that a thread which was working became idle, or exited
+    pub fn set_idle(&self) {
+        if self.active.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let _guard = self.lock.lock().unwrap();
+            self.condvar.notify_all();
+        }
+    }
+
+    /// Wait until no thread is working and no work is left in the queues
+    ///
+    /// # Arguments
+    /// * `timeout` - Maximum time to wait
+    /// * `work_left` - Checks whether work is left in the queues of the
+    /// instance
+    ///
+    /// # Returns
+    /// * `bool` - true if the instance is idle, false if the timeout expired
+    pub fn wait_until_idle<F: FnMut() -> bool>(&self, timeout: Duration, mut work_left: F) -> bool {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            // A thread which takes work from the queues after they were
+            // checked is either counted as active, or it changed the epoch
+            let epoch = self.epoch.load(Ordering::SeqCst);
+            if self.active.load(Ordering::SeqCst) == 0
+                && !work_left()
+                && self.epoch.load(Ordering::SeqCst) == epoch
+            {
+                return true;
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+
+            let guard = self.lock.lock().unwrap();
+            let _ = self
+                .condvar
+                .wait_timeout(guard, IDLE_POLL_INTERVAL.min(deadline - now))
+                .unwrap();
+        }
+    }
+}
@@ -1,8 +1,26 @@
 extern crate mpi;
 
 pub mod activity_identifier;
-mod activity_wrapper;
-mod communication;
+pub(crate) mod activity_names;
+pub(crate) mod activity_queue;
+pub(crate) mod activity_registry;
+pub(crate) mod activity_wrapper;
+pub mod communication;
 pub mod constellation_files;
+pub mod constellation_handle;
 pub mod constellation_identifier;
-mod event_queue;
+pub(crate) mod context_members;
+mod delayed_events;
+pub(crate) mod event_queue;
+mod execution_monitor;
+pub(crate) mod finished_activities;
+mod heartbeat;
+mod idle_monitor;
+pub(crate) mod object_pool;
+pub(crate) mod panic_hook;
+pub(crate) mod parker;
+mod pause_gate;
+mod progress_monitor;
+pub(crate) mod remote_steal;
+pub(crate) mod scope_registry;
+pub(crate) mod sharded_event_queue;
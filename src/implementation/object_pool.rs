@@ -0,0 +1,154 @@
+///! Per thread pools of the allocations of activity wrappers and events, see
+///! `pool_allocations` in the ConstellationConfiguration.
+///!
+///! An executor thread enables the pool of its own thread when it starts. The
+///! wrappers of the activities finishing on the thread, and the events the
+///! thread drops itself, are reset and kept, up to POOL_CAPACITY of each. They
+///! are reused for the activities submitted and the events sent on the same
+///! thread. Only the allocations are kept: the boxes, and for a wrapper the
+///! buffer of its node name. A reset wrapper holds no identifier, options or
+///! activity of its previous use and a reset event no identifiers, payload or
+///! acknowledgement, so nothing leaks into the next use.
+///!
+///! The pools are thread local, so taking and releasing needs no
+///! synchronization. Objects released on a thread without a pool, such as the
+///! thread of the application or the load balancer, are dropped as usual, as
+///! are the events handed to an activity, which owns them from then on.
+use crate::activity::State;
+use crate::implementation::activity_wrapper::ActivityWrapper;
+use crate::{ActivityIdentifier, ActivityTrait, ConstellationHandle, Event};
+
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
+
+/// Maximum number of wrappers, and of events, kept per thread
+pub const POOL_CAPACITY: usize = 1024;
+
+thread_local! {
+    static POOL: RefCell<Option<ObjectPool>> = RefCell::new(None);
+}
+
+/// The pool of a thread
+///
+/// # Members
+/// * `wrappers` - Reset wrappers, ready to be reused
+/// * `events` - Reset events, ready to be reused
+/// * `released` - Activity held by the reset wrappers in place of the
+/// activity of their previous use
+struct ObjectPool {
+    wrappers: Vec<Box<ActivityWrapper>>,
+    events: Vec<Box<Event>>,
+    released: Arc<Mutex<dyn ActivityTrait>>,
+}
+
+/// Activity held by a reset wrapper, it is never run
+struct Released;
+
+impl ActivityTrait for Released {
+    fn cleanup(&mut self, _: &ConstellationHandle) {
+        unreachable!("Activity wrapper used after it was returned to the pool");
+    }
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        unreachable!("Activity wrapper used after it was returned to the pool");
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        unreachable!("Activity wrapper used after it was returned to the pool");
+    }
+}
+
+/// Enable the pool of the calling thread, should only be called by an
+/// executor thread before it starts running activities
+pub(crate) fn enable() {
+    POOL.with(|pool| {
+        let mut pool = pool.borrow_mut();
+        if pool.is_none() {
+            *pool = Some(ObjectPool {
+                wrappers: Vec::new(),
+                events: Vec::new(),
+                released: Arc::new(Mutex::new(Released)),
+            });
+        }
+    });
+}
+
+/// Take a reset wrapper from the pool of the calling thread
+///
+/// # Returns
+/// * `Option<Box<ActivityWrapper>>` - A wrapper to overwrite completely, None
+/// if the pool is empty or the thread has no pool
+pub(crate) fn take_wrapper() -> Option<Box<ActivityWrapper>> {
+    POOL.try_with(|pool| pool.borrow_mut().as_mut()?.wrappers.pop())
+        .ok()
+        .flatten()
+}
+
+/// Reset the wrapper of a finished activity and keep it in the pool of the
+/// calling thread. It is dropped if the thread has no pool or the pool is
+/// full.
+pub(crate) fn release_wrapper(mut wrapper: Box<ActivityWrapper>) {
+    let released = POOL
+        .try_with(|pool| {
+            pool.borrow()
+                .as_ref()
+                .filter(|pool| pool.wrappers.len() < POOL_CAPACITY)
+                .map(|pool| pool.released.clone())
+        })
+        .ok()
+        .flatten();
+
+    if let Some(released) = released {
+        // Not borrowing the pool, dropping the activity runs user code
+        wrapper.reset(released);
+        let _ = POOL.try_with(|pool| {
+            if let Some(pool) = pool.borrow_mut().as_mut() {
+                pool.wrappers.push(wrapper);
+            }
+        });
+    }
+}
+
+/// Box an event, reusing a box from the pool of the calling thread if there
+/// is one
+pub(crate) fn boxed_event(event: Event) -> Box<Event> {
+    let pooled = POOL
+        .try_with(|pool| pool.borrow_mut().as_mut()?.events.pop())
+        .ok()
+        .flatten();
+
+    match pooled {
+        Some(mut boxed) => {
+            *boxed = event;
+            boxed
+        }
+        None => Box::new(event),
+    }
+}
+
+/// Reset an event the runtime drops and keep it in the pool of the calling
+/// thread. It is dropped if the thread has no pool or the pool is full.
+pub(crate) fn release_event(mut event: Box<Event>) {
+    let enabled = POOL
+        .try_with(|pool| {
+            pool.borrow()
+                .as_ref()
+                .map_or(false, |pool| pool.events.len() < POOL_CAPACITY)
+        })
+        .unwrap_or(false);
+
+    if enabled {
+        // Not borrowing the pool, dropping the payload runs user code
+        *event = Event::released();
+        let _ = POOL.try_with(|pool| {
+            if let Some(pool) = pool.borrow_mut().as_mut() {
+                pool.events.push(event);
+            }
+        });
+    }
+}
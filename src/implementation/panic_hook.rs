@@ -0,0 +1,106 @@
+///! Panic hook for the threads started by Constellation. Executor threads are
+///! named "constellation-exec-<thread id>" and the load balancer thread
+///! "constellation-balancer". When one of them panics, the hook logs the
+///! thread name, the activity it was running and the panic message, before
+///! handing over to the previously installed (usually the default) hook.
+use crate::ActivityIdentifier;
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::panic;
+use std::sync::Once;
+use std::thread;
+
+/// Prefix of the names of all threads started by Constellation
+pub const THREAD_NAME_PREFIX: &str = "constellation-";
+
+static INSTALL: Once = Once::new();
+
+thread_local! {
+    /// The activity currently being invoked on this thread
+    static CURRENT_ACTIVITY: RefCell<Option<ActivityIdentifier>> = RefCell::new(None);
+}
+
+/// Name of the executor thread with the given id
+pub fn executor_thread_name(thread_id: i32) -> String {
+    format!("{}exec-{}", THREAD_NAME_PREFIX, thread_id)
+}
+
+/// Name of the load balancer thread
+pub fn balancer_thread_name() -> String {
+    format!("{}balancer", THREAD_NAME_PREFIX)
+}
+
+/// Name of the thread sending and checking heartbeats
+pub fn heartbeat_thread_name() -> String {
+    format!("{}heartbeat", THREAD_NAME_PREFIX)
+}
+
+/// Name of the thread exchanging activity names with the other nodes
+pub fn registry_thread_name() -> String {
+    format!("{}registry", THREAD_NAME_PREFIX)
+}
+
+/// Check whether the calling thread was started by Constellation
+pub fn on_constellation_thread() -> bool {
+    thread::current()
+        .name()
+        .map_or(false, |name| name.starts_with(THREAD_NAME_PREFIX))
+}
+
+/// Set the activity currently being invoked on this thread, reported when the
+/// thread panics
+pub fn set_current_activity(aid: Option<ActivityIdentifier>) {
+    CURRENT_ACTIVITY.with(|current| *current.borrow_mut() = aid);
+}
+
+/// The activity currently being invoked on this thread, None outside of
+/// activities, e.g. on the thread of the application
+pub fn current_activity() -> Option<ActivityIdentifier> {
+    CURRENT_ACTIVITY
+        .try_with(|current| current.borrow().clone())
+        .ok()
+        .and_then(|aid| aid)
+}
+
+/// The message of a panic, from the payload it was raised with
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown panic")
+    }
+}
+
+/// Install the panic hook, this is only done once, further calls do nothing.
+pub fn install() {
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            let thread = thread::current();
+
+            if let Some(name) = thread
+                .name()
+                .filter(|name| name.starts_with(THREAD_NAME_PREFIX))
+            {
+                let activity = CURRENT_ACTIVITY
+                    .try_with(|current| current.borrow().as_ref().map(|aid| aid.to_string()))
+                    .ok()
+                    .and_then(|aid| aid)
+                    .unwrap_or_else(|| String::from("none"));
+
+                let message = panic_message(info.payload());
+
+                error!(
+                    "Thread {} panicked while running activity {}: {}",
+                    name, activity, message
+                );
+            }
+
+            previous(info);
+        }));
+    });
+}
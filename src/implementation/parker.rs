@@ -0,0 +1,61 @@
+///! Parker used by idle executor threads. Instead of spinning its loop, an
+///! executor thread which found no work for a number of consecutive
+///! iterations parks itself until work is inserted in its queues, or until a
+///! timeout expires.
+///!
+///! Everything inserting activities or events into the queues of a thread
+///! (the constellation instance, the load balancer) must unpark it, as must
+///! signalling the thread to shut down. An unpark which arrives before the
+///! thread parks is not lost, the next park returns immediately.
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Parker struct
+///
+/// # Members
+/// * `unparked` - Set by `unpark()`, cleared when the parked thread wakes up
+/// * `condvar` - Used to wake up the parked thread
+pub struct Parker {
+    unparked: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl Parker {
+    pub fn new() -> Parker {
+        Parker {
+            unparked: Mutex::new(false),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Block the calling thread until `unpark()` is called, or the timeout
+    /// expires
+    ///
+    /// # Arguments
+    /// * `timeout` - Maximum time to stay parked
+    pub fn park_timeout(&self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        let mut unparked = self.unparked.lock().unwrap();
+
+        while !*unparked {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            unparked = self
+                .condvar
+                .wait_timeout(unparked, deadline - now)
+                .unwrap()
+                .0;
+        }
+
+        *unparked = false;
+    }
+
+    /// Wake up the parked thread, or make its next park return immediately
+    pub fn unpark(&self) {
+        *self.unparked.lock().unwrap() = true;
+        self.condvar.notify_one();
+    }
+}
@@ -0,0 +1,104 @@
+///! Gate used to pause a constellation instance. While paused, the executor
+///! threads and the load balancer pick up no new work and deliver no events,
+///! activities which are running when the gate is closed finish their current
+///! method call. Submitted activities and sent events keep queueing up, they
+///! are picked up once the gate is opened again.
+///!
+///! Every thread passes the gate at the start of each iteration of its loop,
+///! and leaves it once it is done picking up and running work. The gate
+///! counts the threads which passed it, so `wait_until_quiescent(..)` can
+///! confirm no thread is still running work after the gate was closed.
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time between two checks whether all threads left the gate
+const QUIESCENT_POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// PauseGate struct
+///
+/// # Members
+/// * `paused` - Set while the instance is paused
+/// * `busy` - Number of threads which passed the gate and did not leave it yet
+pub struct PauseGate {
+    paused: AtomicBool,
+    busy: AtomicUsize,
+}
+
+/// Returned when passing the gate, the thread leaves the gate when it is
+/// dropped, also when an activity panics
+pub struct PauseGuard {
+    gate: Arc<PauseGate>,
+}
+
+impl PauseGate {
+    pub fn new() -> PauseGate {
+        PauseGate {
+            paused: AtomicBool::new(false),
+            busy: AtomicUsize::new(0),
+        }
+    }
+
+    /// Close the gate, threads which already passed it keep running until
+    /// they leave it
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Open the gate again
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Pass the gate before picking up work
+    ///
+    /// # Arguments
+    /// * `gate` - The gate to pass
+    ///
+    /// # Returns
+    /// * `Option<PauseGuard>` - Guard to hold while picking up and running
+    /// work, None if the gate is closed and no work may be picked up
+    pub fn enter(gate: &Arc<PauseGate>) -> Option<PauseGuard> {
+        // Count the thread before checking the flag, so that a thread which
+        // misses `pause()` is always seen by `wait_until_quiescent(..)`
+        gate.busy.fetch_add(1, Ordering::SeqCst);
+        if gate.is_paused() {
+            gate.busy.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+
+        Some(PauseGuard { gate: gate.clone() })
+    }
+
+    /// Wait until no thread is still running work after the gate was closed
+    ///
+    /// # Arguments
+    /// * `timeout` - Maximum time to wait
+    ///
+    /// # Returns
+    /// * `bool` - true if the gate is closed and all threads left it, false
+    /// if the gate is open or a thread did not leave it within the timeout
+    pub fn wait_until_quiescent(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+
+        while self.busy.load(Ordering::SeqCst) > 0 {
+            if !self.is_paused() || Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(QUIESCENT_POLL_INTERVAL);
+        }
+
+        self.is_paused()
+    }
+}
+
+impl Drop for PauseGuard {
+    fn drop(&mut self) {
+        self.gate.busy.fetch_sub(1, Ordering::SeqCst);
+    }
+}
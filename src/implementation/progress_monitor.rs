@@ -0,0 +1,80 @@
+///! Detects a multithreaded instance which makes no progress, the most common
+///! cause being a cycle of activities each suspended waiting for an event the
+///! other will never send. The load balancer feeds it the number of finished
+///! activities and delivered events on every pass, together with whether the
+///! instance could be stuck: activities are suspended while no executor
+///! thread is executing an activity. When the counters did not change for
+///! `no_progress_timeout` while the instance could be stuck, the stall is
+///! reported, once until progress is made again.
+///!
+///! An activity which executes for a long time does not count as a stall, the
+///! instance can not be stuck while a thread is busy.
+use std::time::{Duration, Instant};
+
+/// ProgressMonitor struct
+///
+/// # Members
+/// * `timeout` - Time without progress after which a stall is reported
+/// * `progress` - The number of finished activities and delivered events
+/// last seen
+/// * `since` - When the progress counters last changed, or the instance last
+/// could not be stuck
+/// * `reported` - Whether the current stall has been reported
+#[derive(Clone)]
+pub struct ProgressMonitor {
+    timeout: Duration,
+    progress: (u64, u64),
+    since: Instant,
+    reported: bool,
+}
+
+impl ProgressMonitor {
+    /// Create a monitor which reports stalls longer than the timeout
+    ///
+    /// # Arguments
+    /// * `timeout` - Time without progress after which a stall is reported
+    /// * `now` - The current time
+    pub fn new(timeout: Duration, now: Instant) -> ProgressMonitor {
+        ProgressMonitor {
+            timeout,
+            progress: (0, 0),
+            since: now,
+            reported: false,
+        }
+    }
+
+    /// Record the progress counters and check for a stall
+    ///
+    /// # Arguments
+    /// * `finished` - Number of activities which finished so far
+    /// * `delivered` - Number of events delivered to activities so far
+    /// * `may_be_stuck` - Whether activities are suspended while no activity
+    /// is executing
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    /// * `Option<Duration>` - The time without progress, if a stall has just
+    /// been detected
+    pub fn check(
+        &mut self,
+        finished: u64,
+        delivered: u64,
+        may_be_stuck: bool,
+        now: Instant,
+    ) -> Option<Duration> {
+        if !may_be_stuck || (finished, delivered) != self.progress {
+            self.progress = (finished, delivered);
+            self.since = now;
+            self.reported = false;
+            return None;
+        }
+
+        let stalled = now.saturating_duration_since(self.since);
+        if self.reported || stalled < self.timeout {
+            return None;
+        }
+
+        self.reported = true;
+        Some(stalled)
+    }
+}
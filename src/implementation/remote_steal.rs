@@ -0,0 +1,438 @@
+///! Stealing activities between nodes. A node whose executor threads are all
+///! idle, and which has nothing left to route, asks another node for work
+///! with a StealRequest on the STEAL_TAG. The node asked is the node which
+///! reported the most pending activities, see LoadTable, or the next node in
+///! turn when no node reported any or that node had nothing to give the last
+///! time. Only one request is outstanding at a time, after an empty answer
+///! the node waits STEAL_BACKOFF before asking again.
+///!
+///! The victim answers on the STEAL_REPLY_TAG with up to `steal_batch_size`
+///! activities which did not start yet, picked by the
+///! `remote_steal_strategy`, together with the events queued for them, but
+///! at most half of the activities it may hand out, rounded up. Only activities which may
+///! be stolen, whose placement allows the thief, which have no thread
+///! affinity, scope or name, and which can be encoded together with their
+///! queued events are handed out, see `ActivityTrait::encode()`. The thief
+///! decodes them with the decoders of its configuration, an activity it can
+///! not decode is returned to the victim.
+///!
+///! Activities keep their identifier, the node in it is the node they were
+///! submitted to: their home. Events for an activity which is not on a node
+///! are forwarded on the EVENT_TAG, to the node it was handed to if it left
+///! the node, otherwise to its home. Every node remembers where the
+///! activities it handed out went, so events follow an activity across
+///! several steals.
+use crate::decoders::Decoders;
+use crate::implementation::communication::wire::{
+    put_aid, put_bytes, put_str, put_u64, take_aid, take_bytes, take_str, take_u64, take_u8,
+};
+use crate::{ActivityIdentifier, Event, Placement};
+
+use std::time::{Duration, Instant};
+
+use hashbrown::{HashMap, HashSet};
+
+/// Maximum time a node waits for the answer to a steal request, after which
+/// it may ask another node
+pub(crate) const STEAL_REPLY_WAIT: Duration = Duration::from_secs(1);
+
+/// Time a node waits before asking for work again after an empty answer
+pub(crate) const STEAL_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Time between two purges of the finished activities from the hosted
+/// activities
+pub(crate) const HOSTED_PURGE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Request for activities, sent by a node whose executor threads are idle
+///
+/// # Members
+/// * `contexts` - Labels of the contexts the executor threads of the thief
+/// serve, None if a thread serves every context
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct StealRequest {
+    pub contexts: Option<Vec<String>>,
+}
+
+impl StealRequest {
+    /// Whether the thief executes activities with the given context label
+    pub fn serves(&self, label: &str) -> bool {
+        self.contexts
+            .as_ref()
+            .map_or(true, |labels| labels.iter().any(|l| l == label))
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        match &self.contexts {
+            None => bytes.push(0),
+            Some(labels) => {
+                bytes.push(1);
+                put_u64(&mut bytes, labels.len() as u64);
+                for label in labels {
+                    put_str(&mut bytes, label);
+                }
+            }
+        }
+
+        bytes
+    }
+
+    /// Decode a request, None if it is malformed
+    pub fn decode(bytes: &[u8]) -> Option<StealRequest> {
+        let mut rest = bytes;
+
+        let contexts = match take_u8(&mut rest)? {
+            0 => None,
+            1 => {
+                let count = take_u64(&mut rest)?;
+                let mut labels = Vec::new();
+                for _ in 0..count {
+                    labels.push(take_str(&mut rest)?);
+                }
+                Some(labels)
+            }
+            _ => return None,
+        };
+
+        if rest.is_empty() {
+            Some(StealRequest { contexts })
+        } else {
+            None
+        }
+    }
+}
+
+/// An event on its way to another node
+///
+/// # Members
+/// * `src` - Source activity identifier
+/// * `dst` - Destination activity identifier
+/// * `type_name` - Type of the payload, used to find its decoder
+/// * `payload` - The encoded payload, see `PayloadTrait::encode()`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RemoteEvent {
+    pub src: ActivityIdentifier,
+    pub dst: ActivityIdentifier,
+    pub type_name: String,
+    pub payload: Vec<u8>,
+}
+
+impl RemoteEvent {
+    /// Prepare an event to be sent to another node
+    ///
+    /// # Returns
+    /// * `Option<RemoteEvent>` - The event, None if its payload can not be
+    /// encoded
+    pub fn from_event(e: &Event) -> Option<RemoteEvent> {
+        let payload = e.get_payload();
+
+        Some(RemoteEvent {
+            src: e.get_src(),
+            dst: e.get_dst(),
+            type_name: payload.type_name().to_string(),
+            payload: payload.encode()?,
+        })
+    }
+
+    /// Turn the event back into an event, with the decoder registered for
+    /// the type of its payload
+    ///
+    /// # Returns
+    /// * `Option<Box<Event>>` - The event, None if no decoder is registered
+    /// for the payload or it is malformed
+    pub fn into_event(self, decoders: &Decoders) -> Option<Box<Event>> {
+        let payload = decoders.decode_payload(&self.type_name, &self.payload)?;
+
+        Some(Event::new(payload, self.src, self.dst).boxed())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.put(&mut bytes);
+
+        bytes
+    }
+
+    /// Decode an event, None if it is malformed
+    pub fn decode(bytes: &[u8]) -> Option<RemoteEvent> {
+        let mut rest = bytes;
+        let event = RemoteEvent::take(&mut rest)?;
+
+        if rest.is_empty() {
+            Some(event)
+        } else {
+            None
+        }
+    }
+
+    fn put(&self, bytes: &mut Vec<u8>) {
+        put_aid(bytes, &self.src);
+        put_aid(bytes, &self.dst);
+        put_str(bytes, &self.type_name);
+        put_bytes(bytes, &self.payload);
+    }
+
+    fn take(bytes: &mut &[u8]) -> Option<RemoteEvent> {
+        Some(RemoteEvent {
+            src: take_aid(bytes)?,
+            dst: take_aid(bytes)?,
+            type_name: take_str(bytes)?,
+            payload: take_bytes(bytes)?,
+        })
+    }
+}
+
+/// An activity handed to another node, with the options which still apply
+/// there
+///
+/// # Members
+/// * `aid` - Identifier of the activity, kept on the other node
+/// * `context` - Label of the context of the activity
+/// * `expects_events` - See SubmitOptions
+/// * `priority` - See SubmitOptions
+/// * `max_execution_time` - See SubmitOptions
+/// * `placement` - See SubmitOptions
+/// * `retries` - See SubmitOptions
+/// * `type_name` - Type of the activity, used to find its decoder
+/// * `activity` - The encoded activity, see `ActivityTrait::encode()`
+/// * `events` - The events queued for the activity, in the order they were
+/// routed
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct StolenActivity {
+    pub aid: ActivityIdentifier,
+    pub context: String,
+    pub expects_events: bool,
+    pub priority: i32,
+    pub max_execution_time: Option<Duration>,
+    pub placement: Placement,
+    pub retries: u32,
+    pub type_name: String,
+    pub activity: Vec<u8>,
+    pub events: Vec<RemoteEvent>,
+}
+
+impl StolenActivity {
+    fn put(&self, bytes: &mut Vec<u8>) {
+        put_aid(bytes, &self.aid);
+        put_str(bytes, &self.context);
+        bytes.push(self.expects_events as u8);
+        put_u64(bytes, self.priority as i64 as u64);
+        match self.max_execution_time {
+            None => bytes.push(0),
+            Some(time) => {
+                bytes.push(1);
+                put_u64(bytes, time.as_nanos() as u64);
+            }
+        }
+        match self.placement {
+            Placement::Anywhere => bytes.push(0),
+            Placement::MasterOnly => bytes.push(1),
+            Placement::Node(node) => {
+                bytes.push(2);
+                put_u64(bytes, node as u64);
+            }
+            Placement::NotNode(node) => {
+                bytes.push(3);
+                put_u64(bytes, node as u64);
+            }
+        }
+        put_u64(bytes, self.retries as u64);
+        put_str(bytes, &self.type_name);
+        put_bytes(bytes, &self.activity);
+        put_u64(bytes, self.events.len() as u64);
+        for event in self.events.iter() {
+            event.put(bytes);
+        }
+    }
+
+    fn take(bytes: &mut &[u8]) -> Option<StolenActivity> {
+        let aid = take_aid(bytes)?;
+        let context = take_str(bytes)?;
+        let expects_events = take_u8(bytes)? != 0;
+        let priority = take_u64(bytes)? as i64 as i32;
+        let max_execution_time = match take_u8(bytes)? {
+            0 => None,
+            1 => Some(Duration::from_nanos(take_u64(bytes)?)),
+            _ => return None,
+        };
+        let placement = match take_u8(bytes)? {
+            0 => Placement::Anywhere,
+            1 => Placement::MasterOnly,
+            2 => Placement::Node(take_u64(bytes)? as usize),
+            3 => Placement::NotNode(take_u64(bytes)? as usize),
+            _ => return None,
+        };
+        let retries = take_u64(bytes)? as u32;
+        let type_name = take_str(bytes)?;
+        let activity = take_bytes(bytes)?;
+        let count = take_u64(bytes)?;
+        let mut events = Vec::new();
+        for _ in 0..count {
+            events.push(RemoteEvent::take(bytes)?);
+        }
+
+        Some(StolenActivity {
+            aid,
+            context,
+            expects_events,
+            priority,
+            max_execution_time,
+            placement,
+            retries,
+            type_name,
+            activity,
+            events,
+        })
+    }
+}
+
+/// Answer to a steal request
+///
+/// # Members
+/// * `returned` - Whether the activities are returned by a thief which could
+/// not decode them, instead of handed out by a victim
+/// * `activities` - The activities, empty if the victim had nothing to give
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct StealReply {
+    pub returned: bool,
+    pub activities: Vec<StolenActivity>,
+}
+
+impl StealReply {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        bytes.push(self.returned as u8);
+        put_u64(&mut bytes, self.activities.len() as u64);
+        for activity in self.activities.iter() {
+            activity.put(&mut bytes);
+        }
+
+        bytes
+    }
+
+    /// Decode a reply, None if it is malformed
+    pub fn decode(bytes: &[u8]) -> Option<StealReply> {
+        let mut rest = bytes;
+
+        let returned = take_u8(&mut rest)? != 0;
+        let count = take_u64(&mut rest)?;
+        let mut activities = Vec::new();
+        for _ in 0..count {
+            activities.push(StolenActivity::take(&mut rest)?);
+        }
+
+        if rest.is_empty() {
+            Some(StealReply {
+                returned,
+                activities,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// State of the stealing between nodes of one node, shared by all clones of
+/// the MultiThreadHelper
+///
+/// # Members
+/// * `stopped` - Set once the instance is shutting down, no more requests are
+/// sent. Activities are still handed to other nodes.
+/// * `awaiting` - The node a steal request was sent to which did not answer
+/// yet, and when it was sent
+/// * `next_request` - Earliest moment to send the next steal request
+/// * `next_victim` - Node asked next when no node reported pending activities
+/// * `last_empty` - Node which had nothing to give the last time, it is only
+/// asked again in turn
+/// * `migrated` - The node every activity handed out by this node went to
+/// * `hosted` - Activities submitted to other nodes which were handed to this
+/// node, or which events were forwarded to
+/// * `last_purge` - When the finished activities were last removed from
+/// `hosted`
+pub(crate) struct RemoteSteal {
+    pub stopped: bool,
+    pub awaiting: Option<(i32, Instant)>,
+    pub next_request: Instant,
+    next_victim: i32,
+    pub last_empty: Option<i32>,
+    pub migrated: HashMap<ActivityIdentifier, i32>,
+    pub hosted: HashSet<ActivityIdentifier>,
+    pub last_purge: Instant,
+}
+
+impl RemoteSteal {
+    pub fn new() -> RemoteSteal {
+        let now = Instant::now();
+
+        RemoteSteal {
+            stopped: false,
+            awaiting: None,
+            next_request: now,
+            next_victim: 0,
+            last_empty: None,
+            migrated: HashMap::new(),
+            hosted: HashSet::new(),
+            last_purge: now,
+        }
+    }
+
+    /// Whether a steal request was sent which may still be answered
+    pub fn awaiting_reply(&self, now: Instant) -> bool {
+        self.awaiting.map_or(false, |(_, sent)| {
+            now.duration_since(sent) < STEAL_REPLY_WAIT
+        })
+    }
+
+    /// Pick the node to send a steal request to
+    ///
+    /// # Arguments
+    /// * `loaded` - The node which reported the most pending activities, if
+    /// any, see `LoadTable::victim(..)`
+    /// * `node_id` - Rank of this node, it is never picked
+    /// * `nodes` - Number of nodes, at least two
+    ///
+    /// # Returns
+    /// * `i32` - Rank of the node to ask
+    pub fn pick_victim(&mut self, loaded: Option<usize>, node_id: usize, nodes: i32) -> i32 {
+        if let Some(victim) = loaded {
+            if Some(victim as i32) != self.last_empty {
+                return victim as i32;
+            }
+        }
+
+        loop {
+            let victim = self.next_victim % nodes;
+            self.next_victim = (victim + 1) % nodes;
+            if victim as usize != node_id {
+                return victim;
+            }
+        }
+    }
+
+    /// The node events for an activity which is not on this node are
+    /// forwarded to
+    ///
+    /// # Arguments
+    /// * `aid` - Identifier of the activity
+    /// * `node_id` - Rank of this node
+    /// * `nodes` - Number of nodes
+    ///
+    /// # Returns
+    /// * `Option<i32>` - The node the activity was handed to, or its home if
+    /// it was submitted to another node and not handed to this one. None if
+    /// the activity belongs on this node.
+    pub fn location(&self, aid: &ActivityIdentifier, node_id: usize, nodes: i32) -> Option<i32> {
+        if let Some(node) = self.migrated.get(aid) {
+            return Some(*node);
+        }
+
+        let home = aid.node_info.node_id;
+        if home != node_id && home < nodes as usize && !self.hosted.contains(aid) {
+            Some(home as i32)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,113 @@
+///! Keeps track of all scopes in a constellation instance, which scope is the
+///! parent of which and which scopes have been cancelled. Shared between all
+///! executor threads of a constellation instance.
+///!
+///! The scope of the activity currently executing on a thread is kept in a
+///! thread local, so that activities submitted from within that activity end
+///! up in the same scope.
+use crate::{ActivityIdentifier, ScopeId};
+
+use std::cell::Cell;
+
+use hashbrown::{HashMap, HashSet};
+
+thread_local! {
+    static CURRENT_SCOPE: Cell<Option<ScopeId>> = Cell::new(None);
+}
+
+/// Scope of the activity currently executing on this thread, if any
+pub fn current_scope() -> Option<ScopeId> {
+    CURRENT_SCOPE.with(|x| x.get())
+}
+
+/// Set the scope of the activity executing on this thread, should only be
+/// called by the executor thread around calls into an activity
+pub fn set_current_scope(scope: Option<ScopeId>) {
+    CURRENT_SCOPE.with(|x| x.set(scope));
+}
+
+/// Registry struct
+///
+/// # Members
+/// * `next_id` - Counter used to generate unique ScopeIds
+/// * `parents` - The parent of each scope, None for top-level scopes
+/// * `cancelled` - Scopes which have been cancelled
+/// * `cancelled_activities` - Activities removed because their scope was
+/// cancelled, events sent to them are dropped
+/// * `generation` - Incremented on every cancellation, so that executor
+/// threads know when to look for cancelled activities in their queues
+pub struct ScopeRegistry {
+    next_id: u64,
+    parents: HashMap<ScopeId, Option<ScopeId>>,
+    cancelled: HashSet<ScopeId>,
+    cancelled_activities: HashSet<ActivityIdentifier>,
+    generation: u64,
+}
+
+impl ScopeRegistry {
+    pub fn new() -> ScopeRegistry {
+        ScopeRegistry {
+            next_id: 0,
+            parents: HashMap::new(),
+            cancelled: HashSet::new(),
+            cancelled_activities: HashSet::new(),
+            generation: 0,
+        }
+    }
+
+    /// Create a new scope
+    ///
+    /// # Arguments
+    /// * `parent` - The scope the new scope is nested in, if any
+    ///
+    /// # Returns
+    /// * `ScopeId` - Identifier of the new scope
+    pub fn create(&mut self, parent: Option<ScopeId>) -> ScopeId {
+        let id = ScopeId(self.next_id);
+        self.next_id += 1;
+        self.parents.insert(id, parent);
+
+        id
+    }
+
+    /// Mark a scope, and thereby all its child scopes, as cancelled
+    pub fn cancel(&mut self, scope: ScopeId) {
+        if self.cancelled.insert(scope) {
+            self.generation += 1;
+        }
+    }
+
+    /// Check whether the scope or one of its ancestors has been cancelled
+    pub fn is_cancelled(&self, scope: ScopeId) -> bool {
+        let mut current = Some(scope);
+
+        while let Some(s) = current {
+            if self.cancelled.contains(&s) {
+                return true;
+            }
+            current = self.parents.get(&s).cloned().unwrap_or(None);
+        }
+
+        false
+    }
+
+    /// Remember that an activity was removed because its scope was cancelled
+    pub fn record_cancelled_activity(&mut self, aid: ActivityIdentifier) {
+        self.cancelled_activities.insert(aid);
+    }
+
+    /// Check whether an activity was removed because its scope was cancelled
+    pub fn is_activity_cancelled(&self, aid: &ActivityIdentifier) -> bool {
+        self.cancelled_activities.contains(aid)
+    }
+
+    /// Forget the activities removed because their scope was cancelled, used
+    /// when an instance is restarted. Their identifiers are never reissued.
+    pub fn clear_cancelled_activities(&mut self) {
+        self.cancelled_activities.clear();
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
@@ -0,0 +1,79 @@
+///! Event queue split into shards, each with its own lock, for the events the
+///! MultiThreadHelper keeps because their destination was not found yet.
+///!
+///! The shard of an event is selected by hashing its destination, so all
+///! events for one activity are in the same shard and keep their order.
+///! Routing an event, checking for events in flight on an executor thread and
+///! the resolution pass of the load balancer only lock the shards they need,
+///! instead of serializing on a single lock.
+use crate::implementation::event_queue::EventQueue;
+use crate::ActivityIdentifier;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, MutexGuard};
+
+/// Number of shards the kept events are split into
+pub const SHARDS: usize = 16;
+
+/// EventQueue split into shards by destination
+///
+/// # Members
+/// * `shards` - The shards, at least one
+pub struct ShardedEventQueue {
+    shards: Vec<Mutex<EventQueue>>,
+}
+
+impl ShardedEventQueue {
+    /// Create an empty queue with the given number of shards
+    ///
+    /// # Arguments
+    /// * `shards` - Number of shards, at least 1
+    pub fn new(shards: usize) -> ShardedEventQueue {
+        ShardedEventQueue {
+            shards: (0..shards.max(1))
+                .map(|_| Mutex::new(EventQueue::new()))
+                .collect(),
+        }
+    }
+
+    /// Lock the shard holding the events for the given destination
+    pub fn shard(&self, key: &ActivityIdentifier) -> MutexGuard<'_, EventQueue> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let index = (hasher.finish() % self.shards.len() as u64) as usize;
+
+        self.shards[index].lock().unwrap()
+    }
+
+    /// Iterate over the shards, for passes over all kept events. Every shard
+    /// is locked on its own, the queue as a whole is never locked.
+    pub fn shards(&self) -> impl Iterator<Item = &Mutex<EventQueue>> {
+        self.shards.iter()
+    }
+
+    /// Remove all events
+    ///
+    /// # Returns
+    /// * `usize` - Number of events removed
+    pub fn clear(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().clear())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards
+            .iter()
+            .all(|shard| shard.lock().unwrap().is_empty())
+    }
+
+    /// Number of destinations with kept events
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().len())
+            .sum()
+    }
+}
@@ -0,0 +1,45 @@
+///! Event interceptors, registered on the configuration with
+///! `ConstellationConfiguration::add_event_interceptor(..)`. Every event sent
+///! through `ConstellationTrait::send(..)` passes the interceptors in
+///! registration order before it is routed, delayed events pass them once they
+///! are due. Each interceptor may modify the event and decides what happens to
+///! it next, for example to stamp trace ids, enforce payload size limits or
+///! redirect traffic to another activity.
+use crate::{ActivityIdentifier, Event};
+
+use std::sync::Arc;
+
+/// Decision of an interceptor on an event
+///
+/// * `Continue` - Pass the event on to the next interceptor, or route it
+/// * `Drop` - Discard the event, later interceptors do not see it
+/// * `Redirect` - Change the destination of the event and continue
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterceptDecision {
+    Continue,
+    Drop,
+    Redirect(ActivityIdentifier),
+}
+
+/// Interceptor called with every event before it is routed
+pub type EventInterceptor = Arc<dyn Fn(&mut Event) -> InterceptDecision + Send + Sync>;
+
+/// Run the event through the interceptors, in order
+///
+/// # Arguments
+/// * `interceptors` - The registered interceptors
+/// * `e` - The event, modified by the interceptors
+///
+/// # Returns
+/// * `bool` - false if an interceptor dropped the event
+pub(crate) fn intercept(interceptors: &[EventInterceptor], e: &mut Event) -> bool {
+    for interceptor in interceptors {
+        match interceptor(e) {
+            InterceptDecision::Continue => {}
+            InterceptDecision::Drop => return false,
+            InterceptDecision::Redirect(dst) => e.set_dst(dst),
+        }
+    }
+
+    true
+}
@@ -1,34 +1,95 @@
-#[macro_use]
-extern crate mopa;
 extern crate hashbrown;
+#[macro_use]
+extern crate lazy_static;
 extern crate objekt;
 #[macro_use]
 extern crate log;
 extern crate simple_logger;
 
+pub mod ack;
 pub mod activity;
+pub mod bench;
+pub mod clock;
+#[cfg(feature = "compute-pool")]
+pub mod compute_pool;
 pub mod constellation;
 pub mod constellation_config;
 pub mod constellation_factory;
 pub mod context;
+pub mod decoders;
 pub mod error;
 pub mod event;
+#[cfg(feature = "fault-injection")]
+pub mod fault_injection;
+pub mod group;
 pub mod implementation;
+pub mod intercept;
+pub mod metrics;
+pub mod node_health;
+pub mod node_load;
 pub mod payload;
+pub mod queue_depth;
+pub mod schedule_log;
+pub mod schedule_trace;
+pub mod scheduler_event;
+pub mod scope;
+pub mod snapshot;
+pub mod steal_stats;
 pub mod steal_strategy;
+pub mod submit_options;
+pub mod subscription;
 pub mod util;
+pub mod work_left;
 
-pub use activity::ActivityTrait;
+pub use ack::{AckHandle, AckStatus};
+#[allow(deprecated)]
+pub use activity::LegacyActivityTrait;
+pub use activity::{ActivityTrait, CompletionReason};
 pub use activity_identifier::ActivityIdentifier;
-pub use constellation::ConstellationTrait;
-pub use constellation_config::ConstellationConfiguration;
-pub use constellation_factory::new_constellation;
-pub use context::{Context, ContextVec};
-pub use error::ConstellationError;
-pub use event::Event;
+pub use clock::{Clock, ManualClock};
+#[cfg(feature = "compute-pool")]
+pub use compute_pool::ComputePool;
+pub use constellation::{ConstellationSpawn, ConstellationTrait};
+pub use constellation_config::{ConstellationConfiguration, LifecycleHooks};
+pub use constellation_factory::{new_constellation, run, run_worker};
+pub use context::{Context, ContextId, ContextVec};
+pub use decoders::{ActivityDecoder, Decoders, PayloadDecoder};
+pub use error::{ActivityError, ConfigError, ConstellationError, SendError};
+pub use event::{DelayedEventToken, Event};
+#[cfg(feature = "fault-injection")]
+pub use fault_injection::{Fault, FaultCounts, FaultInjector, FaultRule, FaultTarget};
+pub use group::GroupHandle;
 pub use implementation::activity_identifier;
-pub use implementation::constellation_files::multi_threaded_constellation::MultiThreadedConstellation;
-pub use implementation::constellation_files::single_threaded_constellation::SingleThreadConstellation;
-pub use payload::{PayloadTrait, PayloadTraitClone};
-pub use steal_strategy::StealStrategy;
+pub use implementation::communication::comm::Communication;
+pub use implementation::communication::local_comm::LocalComm;
+pub use implementation::communication::mpi_comm::MpiComm;
+pub use implementation::communication::same_node_comm::SameNodeComm;
+pub use implementation::communication::tcp_comm::TcpComm;
+pub use implementation::constellation_files::{
+    multi_threaded_constellation::MultiThreadedConstellation,
+    single_threaded_constellation::SingleThreadConstellation,
+};
+pub use implementation::constellation_handle::ConstellationHandle;
+pub use intercept::{EventInterceptor, InterceptDecision};
+pub use metrics::{ConstellationStats, MetricsSink};
+pub use node_health::{HealthTable, NodeHealth};
+pub use node_load::{LoadEntry, LoadTable, NodeLoad};
+pub use payload::{ArcPayload, PayloadTrait, PayloadTraitClone};
+pub use queue_depth::{DepthHistogram, QueueDepthStats};
+pub use schedule_log::{ScheduleEntry, ScheduleRecord};
+pub use scheduler_event::SchedulerEvent;
+pub use scope::ScopeId;
+pub use snapshot::{ActivitySnapshot, ConstellationSnapshot, QueuedEvents, ThreadSnapshot};
+pub use steal_stats::StealStats;
+pub use steal_strategy::{ParseStealStrategyError, StealStrategy};
+pub use submit_options::{Placement, SubmitOptions};
+pub use subscription::SubscriptionMode;
+#[cfg(feature = "futures")]
+pub use util::activities::async_activity::{AsyncActivity, Spawner};
+pub use util::activities::closure_activity::ClosureActivity;
+pub use util::activities::handler_activity::{HandlerActivity, HandlerControl};
+#[cfg(feature = "futures")]
+pub use util::activities::single_event_collector::EventFuture;
 pub use util::activities::single_event_collector::SingleEventCollector;
+pub use util::simulated_cluster::SimulatedCluster;
+pub use work_left::WorkLeftReport;
@@ -0,0 +1,141 @@
+///! Periodic export of the statistics of a multithreaded constellation
+///! instance, see `metrics_sink` in the ConstellationConfiguration. The load
+///! balancer writes a ConstellationStats snapshot to the sink once every
+///! interval, as one JSON object per line, for example (wrapped here):
+///!
+///! ```json
+///! {"time_us":1571234567890123,"node":0,"activities_finished":812,"pending":40,
+///!  "suspended":3,"events":1,"held_back":0,"overflows":0,"dropped_events":0,
+///!  "steals":97,"stolen_items":852,"yields":0}
+///! ```
+///!
+///! The writer is flushed after every line, and a final snapshot is written
+///! when the load balancer shuts down, so the last line always holds the
+///! totals of the run.
+use crate::StealStats;
+
+use std::fmt;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Snapshot of the statistics of a constellation instance
+///
+/// # Members
+/// * `time_us` - Time of the snapshot, in microseconds since the UNIX epoch
+/// * `node` - Identifier of the node
+/// * `activities_finished` - Number of activities which finished
+/// * `pending` - Number of activities queued on the executor threads which
+/// did not start yet
+/// * `suspended` - Number of activities waiting for an event
+/// * `events` - Number of events queued, including those whose destination
+/// was not found yet
+/// * `held_back` - Number of activities held back because no thread serves
+/// their context or all threads are at `max_activities_per_thread`
+/// * `overflows` - Number of times an activity could not be placed on its
+/// thread because it was at `max_activities_per_thread`
+/// * `dropped_events` - Number of events dropped because their destination
+/// had finished
+/// * `steal_stats` - Statistics on stealing activities
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ConstellationStats {
+    pub time_us: u64,
+    pub node: usize,
+    pub activities_finished: u64,
+    pub pending: usize,
+    pub suspended: usize,
+    pub events: usize,
+    pub held_back: usize,
+    pub overflows: u64,
+    pub dropped_events: usize,
+    pub steal_stats: StealStats,
+}
+
+impl ConstellationStats {
+    /// Create an empty snapshot of the given node, taken now
+    pub fn now(node: usize) -> ConstellationStats {
+        let time_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.as_micros() as u64);
+
+        ConstellationStats {
+            time_us,
+            node,
+            ..Default::default()
+        }
+    }
+
+    /// Encode the snapshot as a single line of JSON, without the newline
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"time_us\":{},\"node\":{},\"activities_finished\":{},\"pending\":{},\
+             \"suspended\":{},\"events\":{},\"held_back\":{},\"overflows\":{},\
+             \"dropped_events\":{},\"steals\":{},\"stolen_items\":{},\"yields\":{}}}",
+            self.time_us,
+            self.node,
+            self.activities_finished,
+            self.pending,
+            self.suspended,
+            self.events,
+            self.held_back,
+            self.overflows,
+            self.dropped_events,
+            self.steal_stats.steals,
+            self.steal_stats.items,
+            self.steal_stats.yields
+        )
+    }
+}
+
+impl fmt::Display for ConstellationStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "node {}: {} finished, {} pending, {} suspended, {} events, {} held back, \
+             {} overflows, {} dropped events, {}",
+            self.node,
+            self.activities_finished,
+            self.pending,
+            self.suspended,
+            self.events,
+            self.held_back,
+            self.overflows,
+            self.dropped_events,
+            self.steal_stats
+        )
+    }
+}
+
+/// Destination of the periodic statistics, e.g. a file, a pipe or stderr.
+/// Clones share the writer.
+///
+/// # Members
+/// * `interval` - Time between two snapshots
+/// * `writer` - Where the snapshots are written
+#[derive(Clone)]
+pub struct MetricsSink {
+    pub interval: Duration,
+    pub writer: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl MetricsSink {
+    /// Create a sink writing a snapshot once every interval
+    ///
+    /// # Arguments
+    /// * `interval` - Time between two snapshots
+    /// * `writer` - Where the snapshots are written
+    pub fn new(interval: Duration, writer: Box<dyn Write + Send>) -> MetricsSink {
+        MetricsSink {
+            interval,
+            writer: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    /// Write a snapshot as one line and flush the writer
+    pub fn write(&self, stats: &ConstellationStats) {
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(e) = writeln!(writer, "{}", stats.to_json()).and_then(|_| writer.flush()) {
+            warn!("Could not write the metrics: {}", e);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+///! Liveness of the other nodes in a constellation instance. Every node
+///! periodically sends a heartbeat to all other nodes, see
+///! `heartbeat_interval` in the ConstellationConfiguration. The moment the
+///! last heartbeat of every node arrived is kept in a HealthTable.
+///!
+///! A node misses a heartbeat for every interval which passed since its last
+///! heartbeat (or since the table was created, if it never sent one). Once it
+///! missed `heartbeat_miss_threshold` heartbeats in a row it is unresponsive,
+///! until it sends a heartbeat again.
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use hashbrown::HashMap;
+
+/// Liveness of a single node
+///
+/// # Members
+/// * `last_seen` - Time since the last heartbeat of the node, None if it
+/// never sent one
+/// * `missed` - Number of heartbeats missed in a row
+/// * `responsive` - False once the node missed too many heartbeats in a row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeHealth {
+    pub last_seen: Option<Duration>,
+    pub missed: u32,
+    pub responsive: bool,
+}
+
+impl fmt::Display for NodeHealth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.responsive {
+            write!(f, "responsive")?;
+        } else {
+            write!(f, "unresponsive")?;
+        }
+
+        match self.last_seen {
+            Some(age) => write!(f, ", last seen {:?} ago", age)?,
+            None => write!(f, ", never seen")?,
+        }
+        write!(f, ", {} missed", self.missed)
+    }
+}
+
+/// The last heartbeat of every other node
+///
+/// # Members
+/// * `interval` - Time between two heartbeats of a node
+/// * `miss_threshold` - Number of heartbeats missed in a row after which a
+/// node is unresponsive
+/// * `started` - When the table was created, used for nodes which never sent
+/// a heartbeat
+/// * `peers` - For every other node, by rank: when its last heartbeat
+/// arrived, and whether it was reported as unresponsive
+pub struct HealthTable {
+    interval: Duration,
+    miss_threshold: u32,
+    started: Instant,
+    peers: HashMap<i32, (Option<Instant>, bool)>,
+}
+
+impl HealthTable {
+    /// Create a table for the given nodes, none of which sent a heartbeat yet
+    ///
+    /// # Arguments
+    /// * `peers` - Ranks of the other nodes
+    /// * `interval` - Time between two heartbeats of a node
+    /// * `miss_threshold` - Number of heartbeats missed in a row after which
+    /// a node is unresponsive, at least 1
+    /// * `started` - The moment from which heartbeats are expected
+    pub fn new(
+        peers: Vec<i32>,
+        interval: Duration,
+        miss_threshold: u32,
+        started: Instant,
+    ) -> HealthTable {
+        HealthTable {
+            interval: interval.max(Duration::from_millis(1)),
+            miss_threshold: miss_threshold.max(1),
+            started,
+            peers: peers
+                .into_iter()
+                .map(|rank| (rank, (None, false)))
+                .collect(),
+        }
+    }
+
+    /// Record a heartbeat which arrived at the given moment. Heartbeats of
+    /// unknown nodes are ignored.
+    ///
+    /// # Returns
+    /// * `bool` - Whether the node was unresponsive, and responds again
+    pub fn record_at(&mut self, rank: i32, received: Instant) -> bool {
+        match self.peers.get_mut(&rank) {
+            Some((last_seen, reported)) => {
+                if last_seen.map_or(true, |last| last < received) {
+                    *last_seen = Some(received);
+                }
+
+                let recovered = *reported;
+                *reported = false;
+                recovered
+            }
+            None => false,
+        }
+    }
+
+    /// Find the nodes which became unresponsive at the given moment, every
+    /// node is returned once until it sends a heartbeat again
+    ///
+    /// # Returns
+    /// * `Vec<(i32, u32)>` - The rank of every node which became unresponsive
+    /// and the number of heartbeats it missed, ordered by rank
+    pub fn check_at(&mut self, now: Instant) -> Vec<(i32, u32)> {
+        let (started, interval) = (self.started, self.interval);
+        let mut unresponsive = Vec::new();
+
+        for (rank, (last_seen, reported)) in self.peers.iter_mut() {
+            let missed = missed(*last_seen, started, interval, now);
+            if missed >= self.miss_threshold && !*reported {
+                *reported = true;
+                unresponsive.push((*rank, missed));
+            }
+        }
+        unresponsive.sort();
+
+        unresponsive
+    }
+
+    /// The liveness of every node at the given moment, ordered by rank
+    pub fn snapshot_at(&self, now: Instant) -> Vec<(i32, NodeHealth)> {
+        let mut health: Vec<(i32, NodeHealth)> = self
+            .peers
+            .iter()
+            .map(|(rank, (last_seen, _))| {
+                let missed = missed(*last_seen, self.started, self.interval, now);
+                (
+                    *rank,
+                    NodeHealth {
+                        last_seen: last_seen.map(|last| now.saturating_duration_since(last)),
+                        missed,
+                        responsive: missed < self.miss_threshold,
+                    },
+                )
+            })
+            .collect();
+        health.sort_by_key(|(rank, _)| *rank);
+
+        health
+    }
+
+    /// The liveness of every node, see `snapshot_at(..)`
+    pub fn snapshot(&self) -> Vec<(i32, NodeHealth)> {
+        self.snapshot_at(Instant::now())
+    }
+}
+
+/// Number of heartbeats a node missed in a row at the given moment
+fn missed(last_seen: Option<Instant>, started: Instant, interval: Duration, now: Instant) -> u32 {
+    let since = now.saturating_duration_since(last_seen.unwrap_or(started));
+    (since.as_nanos() / interval.as_nanos()) as u32
+}
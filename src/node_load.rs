@@ -0,0 +1,240 @@
+///! Load of the nodes in a constellation instance. The load balancer of every
+///! node periodically records how many activities are pending on it, per
+///! context, see `load_report_interval` in the ConstellationConfiguration.
+///! Every node sends its reports to the other nodes with the LOAD_TAG, and
+///! keeps its own and the received reports in a LoadTable, together with the
+///! moment they were received, so that the most loaded node can be picked as
+///! victim when stealing between nodes.
+///!
+///! A node which did not report for `load_report_stale_intervals` intervals
+///! is unknown rather than idle: its last report is still listed, marked as
+///! stale, but it is never picked as victim.
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use hashbrown::HashMap;
+
+use crate::implementation::communication::wire::{put_str, put_u64, take_str, take_u64};
+
+/// Load reported by a single node
+///
+/// # Members
+/// * `node_id` - The node which reported the load
+/// * `pending` - Number of activities waiting to be executed on the node
+/// * `per_context` - Number of pending activities per context label
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeLoad {
+    pub node_id: usize,
+    pub pending: usize,
+    pub per_context: HashMap<String, usize>,
+}
+
+impl NodeLoad {
+    pub fn new(node_id: usize) -> NodeLoad {
+        NodeLoad {
+            node_id,
+            pending: 0,
+            per_context: HashMap::new(),
+        }
+    }
+
+    /// Count a pending activity with the given context label
+    pub fn add(&mut self, label: &str) {
+        self.pending += 1;
+        *self.per_context.entry(label.to_string()).or_insert(0) += 1;
+    }
+
+    /// Number of pending activities with the given context label
+    pub fn pending_for(&self, label: &str) -> usize {
+        self.per_context.get(label).cloned().unwrap_or(0)
+    }
+
+    /// Encode the report to be sent to the other nodes, see `decode(..)`
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        put_u64(&mut bytes, self.node_id as u64);
+        put_u64(&mut bytes, self.pending as u64);
+        put_u64(&mut bytes, self.per_context.len() as u64);
+        for (label, count) in self.per_context.iter() {
+            put_str(&mut bytes, label);
+            put_u64(&mut bytes, *count as u64);
+        }
+
+        bytes
+    }
+
+    /// Decode a report received from another node
+    ///
+    /// # Returns
+    /// * `Option<NodeLoad>` - The report, None if it is malformed
+    pub fn decode(bytes: &[u8]) -> Option<NodeLoad> {
+        let mut rest = bytes;
+        let mut load = NodeLoad::new(take_u64(&mut rest)? as usize);
+        load.pending = take_u64(&mut rest)? as usize;
+        for _ in 0..take_u64(&mut rest)? {
+            let label = take_str(&mut rest)?;
+            load.per_context
+                .insert(label, take_u64(&mut rest)? as usize);
+        }
+
+        if rest.is_empty() {
+            Some(load)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for NodeLoad {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut contexts: Vec<_> = self.per_context.iter().collect();
+        contexts.sort();
+
+        write!(f, "Node {}: {} pending", self.node_id, self.pending)?;
+        for (label, count) in contexts {
+            write!(f, ", {}: {}", label, count)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Entry of a LoadTable snapshot
+///
+/// # Members
+/// * `load` - The last load reported by the node
+/// * `age` - Time since the report was received
+/// * `stale` - Whether the report is too old to be used, the load of the node
+/// is unknown
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadEntry {
+    pub load: NodeLoad,
+    pub age: Duration,
+    pub stale: bool,
+}
+
+impl fmt::Display for LoadEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({:?} ago", self.load, self.age)?;
+        if self.stale {
+            write!(f, ", stale")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// The last load reported by every node
+///
+/// # Members
+/// * `interval` - Time between two reports of a node
+/// * `stale_intervals` - Number of intervals after which a report is stale
+/// * `entries` - The last report of every node and when it was received
+pub struct LoadTable {
+    interval: Duration,
+    stale_intervals: u32,
+    entries: HashMap<usize, (NodeLoad, Instant)>,
+}
+
+impl LoadTable {
+    pub fn new(interval: Duration, stale_intervals: u32) -> LoadTable {
+        LoadTable {
+            interval,
+            stale_intervals: stale_intervals.max(1),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Record a report received now, see `record_at(..)`
+    pub fn record(&mut self, load: NodeLoad) {
+        self.record_at(load, Instant::now());
+    }
+
+    /// Record a report received at the given moment, it replaces the report
+    /// of the same node unless that one was received later
+    ///
+    /// # Arguments
+    /// * `load` - The reported load
+    /// * `received` - When the report was received
+    pub fn record_at(&mut self, load: NodeLoad, received: Instant) {
+        if let Some((_, last)) = self.entries.get(&load.node_id) {
+            if *last > received {
+                return;
+            }
+        }
+
+        self.entries.insert(load.node_id, (load, received));
+    }
+
+    /// Whether a report received at `received` is stale at `now`
+    fn is_stale(&self, received: Instant, now: Instant) -> bool {
+        now.saturating_duration_since(received) > self.interval * self.stale_intervals
+    }
+
+    /// The load of a node, see `load_at(..)`
+    pub fn load(&self, node_id: usize) -> Option<&NodeLoad> {
+        self.load_at(node_id, Instant::now())
+    }
+
+    /// The load of a node at the given moment
+    ///
+    /// # Returns
+    /// * `Option<&NodeLoad>` - The last report of the node, None if the node
+    /// never reported or the report is stale
+    pub fn load_at(&self, node_id: usize, now: Instant) -> Option<&NodeLoad> {
+        match self.entries.get(&node_id) {
+            Some((load, received)) if !self.is_stale(*received, now) => Some(load),
+            _ => None,
+        }
+    }
+
+    /// Pick the node to steal from, see `victim_at(..)`
+    pub fn victim(&self, thief: usize, label: Option<&str>) -> Option<usize> {
+        self.victim_at(thief, label, Instant::now())
+    }
+
+    /// Pick the node to steal from at the given moment: the node with the
+    /// most pending activities, of the given context if a label is given.
+    /// Nodes with stale reports are never picked.
+    ///
+    /// # Arguments
+    /// * `thief` - The node which steals, it is never picked
+    /// * `label` - Only count activities with this context label
+    /// * `now` - The moment used to decide which reports are stale
+    ///
+    /// # Returns
+    /// * `Option<usize>` - The node to steal from, None if no node with a
+    /// fresh report has pending activities
+    pub fn victim_at(&self, thief: usize, label: Option<&str>, now: Instant) -> Option<usize> {
+        self.entries
+            .iter()
+            .filter(|(node, (_, received))| **node != thief && !self.is_stale(*received, now))
+            .map(|(node, (load, _))| match label {
+                Some(label) => (load.pending_for(label), *node),
+                None => (load.pending, *node),
+            })
+            .filter(|(pending, _)| *pending > 0)
+            .max_by_key(|(pending, node)| (*pending, std::cmp::Reverse(*node)))
+            .map(|(_, node)| node)
+    }
+
+    /// All reports, see `snapshot_at(..)`
+    pub fn snapshot(&self) -> Vec<LoadEntry> {
+        self.snapshot_at(Instant::now())
+    }
+
+    /// All reports at the given moment, ordered by node id
+    pub fn snapshot_at(&self, now: Instant) -> Vec<LoadEntry> {
+        let mut entries: Vec<LoadEntry> = self
+            .entries
+            .values()
+            .map(|(load, received)| LoadEntry {
+                load: load.clone(),
+                age: now.saturating_duration_since(*received),
+                stale: self.is_stale(*received, now),
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.load.node_id);
+
+        entries
+    }
+}
@@ -2,9 +2,33 @@
 ///! between activities using the `Event` struct.
 ///!
 ///! See examples/.. for some examples of what a payload struct could look like
-use std::fmt::{Debug, Display};
+use std::any::Any;
+use std::fmt::{self, Debug, Display};
+use std::ops::Deref;
+use std::sync::Arc;
 
-pub trait PayloadTrait: Sync + Send + Debug + PayloadTraitClone + Display + mopa::Any {}
+pub trait PayloadTrait:
+    Sync + Send + Debug + PayloadTraitClone + Display + PayloadAsAny + 'static
+{
+    /// Name of the concrete payload type, used when printing events. There
+    /// is no need to implement this yourself.
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Serialize the payload, so that the event can be sent to an activity
+    /// stolen by another node. The other node decodes it with the decoder
+    /// registered for the type, see
+    /// `ConstellationConfiguration::register_payload(..)`.
+    ///
+    /// # Returns
+    /// * `Option<Vec<u8>>` - The serialized payload, defaults to None: events
+    /// with this payload are dropped when their destination runs on another
+    /// node
+    fn encode(&self) -> Option<Vec<u8>> {
+        None
+    }
+}
 
 pub trait PayloadTraitClone {
     fn clone_box(&self) -> Box<dyn PayloadTrait>;
@@ -16,4 +40,95 @@ impl Clone for Box<dyn PayloadTrait> {
     }
 }
 
-mopafy!(PayloadTrait);
+/// Access to a payload as `Any`, used to downcast it to its concrete type,
+/// see `Event::payload_as(..)` and `Event::into_payload_as(..)`. Implemented
+/// for every payload, there is no need to implement this yourself.
+pub trait PayloadAsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: PayloadTrait> PayloadAsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+/// Left in an event after its payload has been taken with
+/// `Event::take_payload()`
+#[derive(Debug, Clone)]
+pub(crate) struct TakenPayload;
+
+impl PayloadTrait for TakenPayload {}
+
+impl PayloadTraitClone for TakenPayload {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(TakenPayload)
+    }
+}
+
+impl Display for TakenPayload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<payload taken>")
+    }
+}
+
+/// Payload shared between events instead of copied, cloning it (e.g. when
+/// the same data is sent to many activities) only clones the Arc. Create
+/// events carrying it with `Event::shared(..)`, the data can be read with
+/// `Event::payload_as::<T>()` like an unshared payload.
+///
+/// # Members
+/// * `0` - The shared payload
+#[derive(Debug)]
+pub struct ArcPayload<T: PayloadTrait>(pub Arc<T>);
+
+impl<T: PayloadTrait> ArcPayload<T> {
+    pub fn new(payload: Arc<T>) -> ArcPayload<T> {
+        ArcPayload(payload)
+    }
+
+    /// The shared payload
+    pub fn arc(&self) -> &Arc<T> {
+        &self.0
+    }
+}
+
+impl<T: PayloadTrait> Deref for ArcPayload<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: PayloadTrait> PayloadTrait for ArcPayload<T> {
+    fn type_name(&self) -> &'static str {
+        self.0.type_name()
+    }
+
+    fn encode(&self) -> Option<Vec<u8>> {
+        self.0.encode()
+    }
+}
+
+impl<T: PayloadTrait> PayloadTraitClone for ArcPayload<T> {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(ArcPayload(self.0.clone()))
+    }
+}
+
+impl<T: PayloadTrait> Display for ArcPayload<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(&*self.0, f)
+    }
+}
@@ -0,0 +1,110 @@
+///! Histograms of the queue depths of the executor threads, see
+///! `queue_sample_interval` in the ConstellationConfiguration. Averages hide
+///! bursts, so the load balancer samples the number of pending activities,
+///! suspended activities and queued events of every thread at a fixed
+///! interval, and counts each sample in a histogram with a fixed number of
+///! buckets.
+///!
+///! Bucket 0 counts empty queues, bucket i > 0 counts depths from 2^(i-1) up
+///! to 2^i - 1, the last bucket counts everything above. The highest depth
+///! sampled is kept as well, so a peak is never lost in a bucket.
+use std::fmt;
+
+/// Number of buckets in a DepthHistogram
+pub const HISTOGRAM_BUCKETS: usize = 16;
+
+/// Histogram of sampled queue depths
+///
+/// # Members
+/// * `buckets` - Number of samples per bucket, see the module documentation
+/// * `samples` - Total number of samples
+/// * `max` - Highest depth sampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DepthHistogram {
+    pub buckets: [u64; HISTOGRAM_BUCKETS],
+    pub samples: u64,
+    pub max: usize,
+}
+
+impl DepthHistogram {
+    pub fn new() -> DepthHistogram {
+        DepthHistogram::default()
+    }
+
+    /// Count a sampled depth
+    pub fn record(&mut self, depth: usize) {
+        self.buckets[DepthHistogram::bucket(depth)] += 1;
+        self.samples += 1;
+        self.max = self.max.max(depth);
+    }
+
+    /// Index of the bucket counting the given depth
+    pub fn bucket(depth: usize) -> usize {
+        let bits = (usize::max_value().count_ones() - depth.leading_zeros()) as usize;
+        bits.min(HISTOGRAM_BUCKETS - 1)
+    }
+
+    /// Lowest and highest depth counted by a bucket, None as highest depth
+    /// for the last bucket
+    pub fn bucket_range(index: usize) -> (usize, Option<usize>) {
+        match index {
+            0 => (0, Some(0)),
+            i if i == HISTOGRAM_BUCKETS - 1 => (1 << (i - 1), None),
+            i => (1 << (i - 1), Some((1 << i) - 1)),
+        }
+    }
+}
+
+impl fmt::Display for DepthHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} samples, max {}", self.samples, self.max)?;
+
+        for (i, count) in self.buckets.iter().enumerate() {
+            if *count == 0 {
+                continue;
+            }
+
+            match DepthHistogram::bucket_range(i) {
+                (low, Some(high)) if low == high => write!(f, ", {}: {}", low, count)?,
+                (low, Some(high)) => write!(f, ", {}-{}: {}", low, high, count)?,
+                (low, None) => write!(f, ", {}+: {}", low, count)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Queue depth histograms of one executor thread
+///
+/// # Members
+/// * `thread` - Index of the thread
+/// * `pending` - Activities queued on the thread which did not start yet
+/// * `suspended` - Activities on the thread waiting for an event
+/// * `events` - Events queued on the thread
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct QueueDepthStats {
+    pub thread: usize,
+    pub pending: DepthHistogram,
+    pub suspended: DepthHistogram,
+    pub events: DepthHistogram,
+}
+
+impl QueueDepthStats {
+    pub fn new(thread: usize) -> QueueDepthStats {
+        QueueDepthStats {
+            thread,
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for QueueDepthStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "thread {}: pending ({}), suspended ({}), events ({})",
+            self.thread, self.pending, self.suspended, self.events
+        )
+    }
+}
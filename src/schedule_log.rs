@@ -0,0 +1,298 @@
+///! Structured log of the scheduling decisions of a multithreaded
+///! constellation instance, see `debug_json` in the
+///! ConstellationConfiguration. Every decision is written as one JSON object
+///! per line, holding the time in microseconds since the UNIX epoch, the kind
+///! of decision and its details, for example (the longer lines are wrapped
+///! here, in the log every object is on a single line):
+///!
+///! ```json
+///! {"time_us":1571234567890123,"kind":"submit","activity":"CID:1185349127:NID:0:AID:3",
+///!  "context":"x","thread":1}
+///! {"time_us":1571234567890456,"kind":"steal","thread":1,"activities":1}
+///! {"time_us":1571234567890789,"kind":"event_route","event":7,
+///!  "destination":"CID:1185349127:NID:0:AID:3","thread":null}
+///! ```
+///!
+///! With the `config-file` feature enabled, every line can be read back as a
+///! ScheduleEntry with serde_json.
+///!
+///! Records are buffered per thread, and written when the buffer is full,
+///! periodically by the load balancer and when the instance shuts down. The
+///! human readable debug log is not affected.
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "config-file")]
+use serde::{Deserialize, Serialize};
+
+/// Number of records a thread buffers before writing them
+const BUFFERED_RECORDS: usize = 256;
+
+/// Maximum time a thread buffers records before writing them
+pub(crate) const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A scheduling decision
+///
+/// * `Submit` - An activity was placed in the work queue of an executor
+/// thread, `thread` is None if it was held back because no thread serves its
+/// context or all threads are at `max_activities_per_thread`
+/// * `Steal` - An executor thread took activities from its work queue
+/// * `EventRoute` - An event was handed to the thread holding its
+/// destination, `thread` is None if the destination was not found and the
+/// event is kept until it is
+/// * `Orphan` - Events were dropped because their destination already
+/// finished
+/// * `Rebalance` - An executor thread handed activities back to the load
+/// balancer, see `shed_high_watermark`, or the load balancer moved queued
+/// activities away from it, see `rebalance_threshold`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "config-file", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "config-file",
+    serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)
+)]
+pub enum ScheduleRecord {
+    Submit {
+        activity: String,
+        context: String,
+        thread: Option<usize>,
+    },
+    Steal {
+        thread: i32,
+        activities: usize,
+    },
+    EventRoute {
+        event: u64,
+        destination: String,
+        thread: Option<usize>,
+    },
+    Orphan {
+        destination: String,
+        events: usize,
+    },
+    Rebalance {
+        thread: i32,
+        activities: usize,
+    },
+}
+
+/// A line of the log: a scheduling decision and when it was made
+///
+/// # Members
+/// * `time_us` - Time of the decision, in microseconds since the UNIX epoch
+/// * `record` - The decision
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "config-file", derive(Serialize, Deserialize))]
+pub struct ScheduleEntry {
+    pub time_us: u64,
+    #[cfg_attr(feature = "config-file", serde(flatten))]
+    pub record: ScheduleRecord,
+}
+
+impl ScheduleEntry {
+    /// Create an entry for a decision made now
+    pub fn now(record: ScheduleRecord) -> ScheduleEntry {
+        let time_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.as_micros() as u64);
+
+        ScheduleEntry { time_us, record }
+    }
+
+    /// Encode the entry as a single line of JSON, without the newline
+    pub fn to_json(&self) -> String {
+        let fields = match &self.record {
+            ScheduleRecord::Submit {
+                activity,
+                context,
+                thread,
+            } => format!(
+                "\"kind\":\"submit\",\"activity\":{},\"context\":{},\"thread\":{}",
+                json_string(activity),
+                json_string(context),
+                json_option(thread)
+            ),
+            ScheduleRecord::Steal { thread, activities } => format!(
+                "\"kind\":\"steal\",\"thread\":{},\"activities\":{}",
+                thread, activities
+            ),
+            ScheduleRecord::EventRoute {
+                event,
+                destination,
+                thread,
+            } => format!(
+                "\"kind\":\"event_route\",\"event\":{},\"destination\":{},\"thread\":{}",
+                event,
+                json_string(destination),
+                json_option(thread)
+            ),
+            ScheduleRecord::Orphan {
+                destination,
+                events,
+            } => format!(
+                "\"kind\":\"orphan\",\"destination\":{},\"events\":{}",
+                json_string(destination),
+                events
+            ),
+            ScheduleRecord::Rebalance { thread, activities } => format!(
+                "\"kind\":\"rebalance\",\"thread\":{},\"activities\":{}",
+                thread, activities
+            ),
+        };
+
+        format!("{{\"time_us\":{},{}}}", self.time_us, fields)
+    }
+}
+
+/// Encode a string as a JSON string
+fn json_string(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len() + 2);
+    encoded.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => encoded.push_str("\\\""),
+            '\\' => encoded.push_str("\\\\"),
+            '\n' => encoded.push_str("\\n"),
+            '\r' => encoded.push_str("\\r"),
+            '\t' => encoded.push_str("\\t"),
+            c if (c as u32) < 0x20 => encoded.push_str(&format!("\\u{:04x}", c as u32)),
+            c => encoded.push(c),
+        }
+    }
+    encoded.push('"');
+
+    encoded
+}
+
+/// Encode an optional number as JSON
+fn json_option(value: &Option<usize>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "null".to_string(),
+    }
+}
+
+/// Records buffered by a ScheduleLogger
+///
+/// # Members
+/// * `records` - The records which were not written yet
+/// * `last_flush` - When the records were last written
+struct Buffer {
+    records: Vec<String>,
+    last_flush: Instant,
+}
+
+/// The log file shared by all threads of an instance, together with the
+/// buffer of every thread
+///
+/// # Members
+/// * `file` - The log file
+/// * `buffers` - The buffer of every ScheduleLogger
+pub(crate) struct ScheduleLog {
+    file: Mutex<BufWriter<File>>,
+    buffers: Mutex<Vec<Arc<Mutex<Buffer>>>>,
+}
+
+impl ScheduleLog {
+    /// Create the log file, it is overwritten if it exists
+    pub(crate) fn create(path: &Path) -> io::Result<Arc<ScheduleLog>> {
+        Ok(Arc::new(ScheduleLog {
+            file: Mutex::new(BufWriter::new(File::create(path)?)),
+            buffers: Mutex::new(Vec::new()),
+        }))
+    }
+
+    /// Create a logger with its own buffer, for use on a single thread
+    pub(crate) fn logger(log: &Arc<ScheduleLog>) -> ScheduleLogger {
+        let buffer = Arc::new(Mutex::new(Buffer {
+            records: Vec::new(),
+            last_flush: Instant::now(),
+        }));
+        log.buffers.lock().unwrap().push(buffer.clone());
+
+        ScheduleLogger {
+            log: log.clone(),
+            buffer,
+        }
+    }
+
+    /// Write the records buffered by all threads to the file
+    pub(crate) fn flush(&self) {
+        let buffers = self.buffers.lock().unwrap();
+        for buffer in buffers.iter() {
+            self.write(&mut buffer.lock().unwrap().records);
+        }
+        drop(buffers);
+
+        if let Err(e) = self.file.lock().unwrap().flush() {
+            warn!("Could not write the schedule log: {}", e);
+        }
+    }
+
+    /// Write and clear the given records
+    fn write(&self, records: &mut Vec<String>) {
+        if records.is_empty() {
+            return;
+        }
+
+        let mut file = self.file.lock().unwrap();
+        for record in records.drain(..) {
+            if let Err(e) = writeln!(file, "{}", record) {
+                warn!("Could not write the schedule log: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Records scheduling decisions made on one thread. Cloning a logger creates
+/// a new buffer, so the clone can be used on another thread.
+///
+/// # Members
+/// * `log` - The log shared by all threads
+/// * `buffer` - The records of this logger which were not written yet
+pub(crate) struct ScheduleLogger {
+    log: Arc<ScheduleLog>,
+    buffer: Arc<Mutex<Buffer>>,
+}
+
+impl ScheduleLogger {
+    /// Record a decision made now, the buffer is written once it is full or
+    /// older than FLUSH_INTERVAL
+    pub(crate) fn record(&self, record: ScheduleRecord) {
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.records.push(ScheduleEntry::now(record).to_json());
+
+        if buffer.records.len() >= BUFFERED_RECORDS || buffer.last_flush.elapsed() >= FLUSH_INTERVAL
+        {
+            self.log.write(&mut buffer.records);
+            buffer.last_flush = Instant::now();
+        }
+    }
+
+    /// The log shared by all threads
+    pub(crate) fn log(&self) -> &Arc<ScheduleLog> {
+        &self.log
+    }
+}
+
+impl Clone for ScheduleLogger {
+    fn clone(&self) -> ScheduleLogger {
+        ScheduleLog::logger(&self.log)
+    }
+}
+
+/// Write the remaining records and unregister the buffer
+impl Drop for ScheduleLogger {
+    fn drop(&mut self) {
+        self.log.write(&mut self.buffer.lock().unwrap().records);
+        self.log
+            .buffers
+            .lock()
+            .unwrap()
+            .retain(|buffer| !Arc::ptr_eq(buffer, &self.buffer));
+    }
+}
@@ -0,0 +1,554 @@
+///! Record and replay of the scheduling decisions of a multithreaded
+///! constellation instance, see `record_schedule` and `replay_schedule` in the
+///! ConstellationConfiguration. Use it to reproduce a failure which depends on
+///! the order in which activities run and events arrive: record runs until
+///! one of them fails, then replay that run as often as needed.
+///!
+///! A recording holds, in the order they were made:
+///! - the activities which were submitted, identified by the activity which
+///! submitted them and the number of activities it submitted before
+///! - the executor thread every activity was placed on
+///! - the order in which the executor threads started running activities
+///! - the events every activity received in each invocation, identified by
+///! the activity which sent them and the number of events it sent before
+///!
+///! Activity identifiers and event ids differ between runs, so activities get
+///! a trace id instead, 0 stands for the application. A recording is a text
+///! file with one decision per line, for example:
+///!
+///! ```text
+///! constellation-schedule-trace 1
+///! A 1 0 0
+///! P 1 2
+///! S 2 1
+///! D 1 0.0 3.1
+///! ```
+///!
+///! Activity 1 is the first activity submitted by the application, it is
+///! placed on thread 2, thread 2 starts running it and it receives the first
+///! event sent by the application and the second event sent by activity 3.
+///!
+///! When replaying, the load balancer places every activity on the thread it
+///! was placed on before, the executor threads take turns starting the
+///! activities they started before, and every activity waits until it can
+///! receive exactly the events it received before. This reproduces the run as
+///! long as the activities and the application submit and send the same for
+///! the same events. When they do not, the replay stops with a warning once a
+///! decision can not be replayed for REPLAY_STALL_TIMEOUT, and the instance
+///! schedules normally again, as it does after all decisions were replayed.
+///!
+///! Activities may run at the same time when replaying, as they did when
+///! recording, so races between activities sharing state outside of
+///! constellation are not reproduced. Executor threads do not shed work while
+///! recording or replaying, and threads must not be added or retired.
+use crate::implementation::panic_hook;
+use crate::{ActivityIdentifier, Event};
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use hashbrown::HashMap;
+
+/// First line of every recording
+const HEADER: &str = "constellation-schedule-trace 1";
+
+/// Trace id of the application
+const APPLICATION: u64 = 0;
+
+/// Key of events which were not sent through a traced path, they can not be
+/// replayed
+const UNTRACED: EventKey = (u64::max_value(), u64::max_value());
+
+/// Time a replay waits for a recorded decision to become possible, before it
+/// concludes that the run diverged from the recording
+pub(crate) const REPLAY_STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An event in a recording, the trace id of the activity which sent it and
+/// the number of events that activity sent before
+type EventKey = (u64, u64);
+
+/// The next step of an executor thread while replaying
+///
+/// * `Run` - It is the turn of this thread to start the activity
+/// * `Wait` - Another thread goes first, or the activity was not submitted
+/// yet
+/// * `Finished` - All steps were replayed, or the replay stopped
+pub(crate) enum ReplayStep {
+    Run(ActivityIdentifier),
+    Wait,
+    Finished,
+}
+
+/// The events an activity receives in its next invocation
+///
+/// * `Recorded` - Exactly these events, by event id, in this order
+/// * `NotSent` - Not all events it received before were sent yet
+/// * `Any` - Nothing to replay, it receives all events queued for it
+pub(crate) enum ReplayDrain {
+    Recorded(Vec<u64>),
+    NotSent,
+    Any,
+}
+
+/// A recording read back for replaying
+///
+/// # Members
+/// * `activities` - Trace id of every activity, by the trace id of the
+/// activity which submitted it and the number of activities submitted before
+/// * `placements` - Thread every activity was placed on, by trace id
+/// * `steps` - Executor thread and trace id of every step, in the order they
+/// were started
+/// * `next_step` - Index of the next step to start
+/// * `step_since` - When the next step became the next one
+/// * `drains` - Events received in every invocation, by trace id
+/// * `sent` - Event id of every event sent in this run which was not
+/// received yet, by its key
+struct Replay {
+    activities: HashMap<(u64, u64), u64>,
+    placements: HashMap<u64, usize>,
+    steps: Vec<(i32, u64)>,
+    next_step: usize,
+    step_since: Instant,
+    drains: HashMap<u64, VecDeque<Vec<EventKey>>>,
+    sent: HashMap<EventKey, u64>,
+}
+
+/// Whether decisions are recorded or replayed
+///
+/// * `Record` - Decisions are written to the file, `next_id` is the trace id
+/// of the next activity
+/// * `Replay` - Decisions are taken from the recording
+/// * `Stopped` - The replay stopped because the run diverged from the
+/// recording
+enum Mode {
+    Record { file: BufWriter<File>, next_id: u64 },
+    Replay(Replay),
+    Stopped,
+}
+
+/// State of a ScheduleTrace
+///
+/// # Members
+/// * `mode` - Recording or replaying
+/// * `ids` - Trace id of every activity submitted in this run
+/// * `activities` - Identifier of every trace id in this run
+/// * `submitted` - Number of activities submitted by every trace id
+/// * `sent` - Number of events sent by every trace id
+/// * `events` - Key of every event sent in this run which was not received
+/// yet, by event id. Only kept when recording.
+struct TraceState {
+    mode: Mode,
+    ids: HashMap<ActivityIdentifier, u64>,
+    activities: HashMap<u64, ActivityIdentifier>,
+    submitted: HashMap<u64, u64>,
+    sent: HashMap<u64, u64>,
+    events: HashMap<u64, EventKey>,
+}
+
+impl TraceState {
+    /// Trace id of the activity running on the calling thread, the
+    /// application if no activity is running
+    fn caller(&self) -> u64 {
+        panic_hook::current_activity()
+            .and_then(|aid| self.ids.get(&aid).cloned())
+            .unwrap_or(APPLICATION)
+    }
+
+    /// Write a line to the recording, does nothing when replaying
+    fn write(&mut self, line: fmt::Arguments) {
+        if let Mode::Record { file, .. } = &mut self.mode {
+            if let Err(e) = file.write_fmt(line).and_then(|_| file.write_all(b"\n")) {
+                warn!("Could not write the schedule recording: {}", e);
+            }
+        }
+    }
+
+    /// Stop replaying, the instance schedules normally from now on
+    fn stop(&mut self, reason: fmt::Arguments) {
+        if let Mode::Replay(_) = self.mode {
+            warn!(
+                "Replay diverged from the recording, stopping it: {}",
+                reason
+            );
+            self.mode = Mode::Stopped;
+        }
+    }
+}
+
+/// Recording or replay of the scheduling decisions of one constellation
+/// instance, shared by the load balancer, the executor threads and the
+/// handles of the activities
+///
+/// # Members
+/// * `state` - The recording or replay, decisions are recorded while holding
+/// the lock so they are written in the order they were made
+/// * `changed` - Notified when a replayed decision was made, so the threads
+/// waiting for their turn check again
+pub(crate) struct ScheduleTrace {
+    state: Mutex<TraceState>,
+    changed: Condvar,
+}
+
+impl ScheduleTrace {
+    /// Start a recording, the file is overwritten if it exists
+    pub(crate) fn record(path: &Path) -> io::Result<Arc<ScheduleTrace>> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "{}", HEADER)?;
+
+        Ok(ScheduleTrace::new(Mode::Record { file, next_id: 1 }))
+    }
+
+    /// Read a recording to replay it
+    pub(crate) fn replay(path: &Path) -> io::Result<Arc<ScheduleTrace>> {
+        let mut replay = Replay {
+            activities: HashMap::new(),
+            placements: HashMap::new(),
+            steps: Vec::new(),
+            next_step: 0,
+            step_since: Instant::now(),
+            drains: HashMap::new(),
+            sent: HashMap::new(),
+        };
+
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        if lines.next().transpose()?.as_ref().map(|l| l.as_str()) != Some(HEADER) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a schedule recording",
+            ));
+        }
+
+        for (number, line) in lines.enumerate() {
+            let line = line?;
+            if parse_line(&mut replay, &line).is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "invalid line {} in schedule recording: {}",
+                        number + 2,
+                        line
+                    ),
+                ));
+            }
+        }
+
+        Ok(ScheduleTrace::new(Mode::Replay(replay)))
+    }
+
+    fn new(mode: Mode) -> Arc<ScheduleTrace> {
+        Arc::new(ScheduleTrace {
+            state: Mutex::new(TraceState {
+                mode,
+                ids: HashMap::new(),
+                activities: HashMap::new(),
+                submitted: HashMap::new(),
+                sent: HashMap::new(),
+                events: HashMap::new(),
+            }),
+            changed: Condvar::new(),
+        })
+    }
+
+    /// Whether decisions are taken from a recording, until all of them were
+    /// replayed or the replay stopped
+    pub(crate) fn is_replaying(&self) -> bool {
+        match &self.state.lock().unwrap().mode {
+            Mode::Replay(replay) => replay.next_step < replay.steps.len(),
+            _ => false,
+        }
+    }
+
+    /// Give a submitted activity its trace id, called on the thread which
+    /// submitted it
+    pub(crate) fn submitted(&self, aid: &ActivityIdentifier) {
+        let mut state = self.state.lock().unwrap();
+        let parent = state.caller();
+        let counter = state.submitted.entry(parent).or_insert(0);
+        let index = *counter;
+        *counter += 1;
+
+        let id = match &mut state.mode {
+            Mode::Record { next_id, .. } => {
+                *next_id += 1;
+                *next_id - 1
+            }
+            Mode::Replay(replay) => match replay.activities.get(&(parent, index)) {
+                Some(&id) => id,
+                None => {
+                    state.stop(format_args!(
+                        "activity {} submitted by trace id {} was not recorded",
+                        index, parent
+                    ));
+                    return;
+                }
+            },
+            Mode::Stopped => return,
+        };
+
+        state.ids.insert(aid.clone(), id);
+        state.activities.insert(id, aid.clone());
+        state.write(format_args!("A {} {} {}", id, parent, index));
+        drop(state);
+
+        self.changed.notify_all();
+    }
+
+    /// Give a sent event its key, called on the thread which sent it
+    pub(crate) fn sent(&self, event: &Event) {
+        let mut state = self.state.lock().unwrap();
+        let sender = state.caller();
+        let counter = state.sent.entry(sender).or_insert(0);
+        let key = (sender, *counter);
+        *counter += 1;
+
+        match &mut state.mode {
+            Mode::Record { .. } => {
+                state.events.insert(event.get_id(), key);
+            }
+            Mode::Replay(replay) => {
+                replay.sent.insert(key, event.get_id());
+            }
+            Mode::Stopped => {}
+        }
+    }
+
+    /// Record that an activity was placed on a thread
+    pub(crate) fn placed(&self, aid: &ActivityIdentifier, thread: usize) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(&id) = state.ids.get(aid) {
+            state.write(format_args!("P {} {}", id, thread));
+        }
+    }
+
+    /// The thread an activity was placed on in the recording
+    ///
+    /// # Returns
+    /// * `Option<usize>` - The index of the thread, None if not replaying or
+    /// the activity was not placed in the recording
+    pub(crate) fn replayed_placement(&self, aid: &ActivityIdentifier) -> Option<usize> {
+        let state = self.state.lock().unwrap();
+        match &state.mode {
+            Mode::Replay(replay) => state
+                .ids
+                .get(aid)
+                .and_then(|id| replay.placements.get(id).cloned()),
+            _ => None,
+        }
+    }
+
+    /// Record that an executor thread started running an activity
+    pub(crate) fn step(&self, thread: i32, aid: &ActivityIdentifier) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(&id) = state.ids.get(aid) {
+            state.write(format_args!("S {} {}", thread, id));
+        }
+    }
+
+    /// The next step of an executor thread while replaying. The replay stops
+    /// when the next step could not be started for REPLAY_STALL_TIMEOUT.
+    ///
+    /// # Arguments
+    /// * `thread` - Id of the executor thread
+    ///
+    /// # Returns
+    /// * `ReplayStep` - The activity to start when it is the turn of this
+    /// thread, call `step_started()` once it is taken from the queues
+    pub(crate) fn next_step(&self, thread: i32) -> ReplayStep {
+        let mut state = self.state.lock().unwrap();
+        let (step_thread, id, stalled) = match &state.mode {
+            Mode::Replay(replay) if replay.next_step < replay.steps.len() => {
+                let (step_thread, id) = replay.steps[replay.next_step];
+                (
+                    step_thread,
+                    id,
+                    replay.step_since.elapsed() >= REPLAY_STALL_TIMEOUT,
+                )
+            }
+            _ => return ReplayStep::Finished,
+        };
+
+        if stalled {
+            state.stop(format_args!(
+                "thread {} did not start trace id {} within {:?}",
+                step_thread, id, REPLAY_STALL_TIMEOUT
+            ));
+            drop(state);
+            self.changed.notify_all();
+            return ReplayStep::Finished;
+        }
+
+        if step_thread != thread {
+            return ReplayStep::Wait;
+        }
+        match state.activities.get(&id) {
+            Some(aid) => ReplayStep::Run(aid.clone()),
+            None => ReplayStep::Wait,
+        }
+    }
+
+    /// Pass the turn to the next step, after the executor thread took the
+    /// activity of the current step from its queues
+    pub(crate) fn step_started(&self) {
+        if let Mode::Replay(replay) = &mut self.state.lock().unwrap().mode {
+            replay.next_step += 1;
+            replay.step_since = Instant::now();
+        }
+
+        self.changed.notify_all();
+    }
+
+    /// Record the events an activity received in one invocation
+    pub(crate) fn drained(&self, aid: &ActivityIdentifier, events: &[Box<Event>]) {
+        let mut state = self.state.lock().unwrap();
+        let id = match state.ids.get(aid) {
+            Some(&id) => id,
+            None => return,
+        };
+
+        let mut line = format!("D {}", id);
+        for event in events {
+            match state.events.remove(&event.get_id()) {
+                Some((sender, seq)) => line.push_str(&format!(" {}.{}", sender, seq)),
+                None => line.push_str(" ?"),
+            }
+        }
+        state.write(format_args!("{}", line));
+    }
+
+    /// The events an activity received in its next invocation in the
+    /// recording
+    pub(crate) fn expected_events(&self, aid: &ActivityIdentifier) -> ReplayDrain {
+        let mut state = self.state.lock().unwrap();
+        let keys = match (&state.mode, state.ids.get(aid)) {
+            (Mode::Replay(replay), Some(id)) => match replay.drains.get(id) {
+                Some(drains) if !drains.is_empty() => drains[0].clone(),
+                _ => return ReplayDrain::Any,
+            },
+            _ => return ReplayDrain::Any,
+        };
+
+        if keys.contains(&UNTRACED) {
+            state.stop(format_args!(
+                "{} received an event which was not traced",
+                aid
+            ));
+            return ReplayDrain::Any;
+        }
+
+        let replay = match &state.mode {
+            Mode::Replay(replay) => replay,
+            _ => return ReplayDrain::Any,
+        };
+        let ids: Option<Vec<u64>> = keys
+            .iter()
+            .map(|key| replay.sent.get(key).cloned())
+            .collect();
+
+        match ids {
+            Some(ids) => ReplayDrain::Recorded(ids),
+            None => ReplayDrain::NotSent,
+        }
+    }
+
+    /// Record that an activity received the events returned by
+    /// `expected_events(..)`
+    pub(crate) fn events_received(&self, aid: &ActivityIdentifier) {
+        let mut state = self.state.lock().unwrap();
+        let id = match state.ids.get(aid) {
+            Some(&id) => id,
+            None => return,
+        };
+
+        if let Mode::Replay(replay) = &mut state.mode {
+            if let Some(keys) = replay.drains.get_mut(&id).and_then(|d| d.pop_front()) {
+                for key in keys {
+                    replay.sent.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Stop replaying because the run diverged from the recording
+    pub(crate) fn stop(&self, reason: &str) {
+        self.state.lock().unwrap().stop(format_args!("{}", reason));
+        self.changed.notify_all();
+    }
+
+    /// Wait until a replayed decision was made, or the timeout expired
+    pub(crate) fn wait(&self, timeout: Duration) {
+        let state: MutexGuard<TraceState> = self.state.lock().unwrap();
+        let _ = self.changed.wait_timeout(state, timeout);
+    }
+
+    /// Write the buffered part of the recording to the file
+    pub(crate) fn flush(&self) {
+        if let Mode::Record { file, .. } = &mut self.state.lock().unwrap().mode {
+            if let Err(e) = file.flush() {
+                warn!("Could not write the schedule recording: {}", e);
+            }
+        }
+    }
+}
+
+/// Add one line of a recording to the replay
+///
+/// # Returns
+/// * `Option<()>` - None if the line is not valid
+fn parse_line(replay: &mut Replay, line: &str) -> Option<()> {
+    let mut fields = line.split_whitespace();
+
+    match fields.next()? {
+        "A" => {
+            let id = fields.next()?.parse().ok()?;
+            let parent = fields.next()?.parse().ok()?;
+            let index = fields.next()?.parse().ok()?;
+            replay.activities.insert((parent, index), id);
+        }
+        "P" => {
+            let id = fields.next()?.parse().ok()?;
+            let thread = fields.next()?.parse().ok()?;
+            replay.placements.insert(id, thread);
+        }
+        "S" => {
+            let thread = fields.next()?.parse().ok()?;
+            let id = fields.next()?.parse().ok()?;
+            replay.steps.push((thread, id));
+        }
+        "D" => {
+            let id = fields.next()?.parse().ok()?;
+            let keys = fields
+                .by_ref()
+                .map(parse_key)
+                .collect::<Option<Vec<EventKey>>>()?;
+            replay
+                .drains
+                .entry(id)
+                .or_insert_with(VecDeque::new)
+                .push_back(keys);
+        }
+        _ => return None,
+    }
+
+    match fields.next() {
+        Some(_) => None,
+        None => Some(()),
+    }
+}
+
+/// Parse the key of an event, "<sender>.<number>" or "?" for an event which
+/// was not traced
+fn parse_key(field: &str) -> Option<EventKey> {
+    if field == "?" {
+        return Some(UNTRACED);
+    }
+
+    let mut parts = field.splitn(2, '.');
+    let sender = parts.next()?.parse().ok()?;
+    let seq = parts.next()?.parse().ok()?;
+    Some((sender, seq))
+}
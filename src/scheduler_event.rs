@@ -0,0 +1,79 @@
+///! Observation channel for the scheduling decisions of a constellation
+///! instance, for tests, see `ConstellationConfiguration::with_observer(..)`.
+///!
+///! The events are sent at the same points the decisions are written to the
+///! schedule log, see `debug_json`, but carry the identifiers themselves, so
+///! a test can assert on what happened instead of sleeping and guessing:
+///!
+///! ```ignore
+///! let (sender, observer) = crossbeam::unbounded();
+///! config.with_observer(sender);
+///! ...
+///! constellation.done()?;
+///! let orphaned = observer
+///!     .try_iter()
+///!     .filter(|e| matches!(e, SchedulerEvent::Orphaned { .. }))
+///!     .count();
+///! assert_eq!(orphaned, 0);
+///! ```
+///!
+///! Without an observer nothing is created or sent. The events are sent
+///! while the sender holds locks on the queues, so the channel should be
+///! unbounded, a full bounded channel stalls the instance. Events sent after
+///! the receiver was dropped are discarded.
+use crate::ActivityIdentifier;
+
+use crossbeam::Sender;
+
+/// A scheduling decision observed in a constellation instance
+///
+/// * `Submitted` - An activity was placed in the work queue of the executor
+/// thread with index `thread`, None if it was held back because no thread
+/// serves its context or all threads are at `max_activities_per_thread`
+/// * `Migrated` - An activity which was moved away from the executor thread
+/// with id `from`, by shedding, retiring the thread or fault injection, was
+/// placed on the thread with index `to`. None if it was held back.
+/// * `EventRouted` - The load balancer handed event `id` to the thread with
+/// index `thread` holding its destination `dst`, None if the destination was
+/// not found and the event is kept until it is
+/// * `Orphaned` - Event `id` was dropped because its destination already
+/// finished
+/// * `Finished` - An activity finished, in any mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchedulerEvent {
+    Submitted {
+        aid: ActivityIdentifier,
+        thread: Option<usize>,
+    },
+    Migrated {
+        aid: ActivityIdentifier,
+        from: i32,
+        to: Option<usize>,
+    },
+    EventRouted {
+        id: u64,
+        dst: ActivityIdentifier,
+        thread: Option<usize>,
+    },
+    Orphaned {
+        id: u64,
+    },
+    Finished {
+        aid: ActivityIdentifier,
+    },
+}
+
+/// Send an event to the observer, the event is only created if there is one
+///
+/// # Arguments
+/// * `observer` - The observer of the configuration
+/// * `event` - Function creating the event
+#[inline]
+pub(crate) fn observe<F: FnOnce() -> SchedulerEvent>(
+    observer: &Option<Sender<SchedulerEvent>>,
+    event: F,
+) {
+    if let Some(observer) = observer {
+        let _ = observer.send(event());
+    }
+}
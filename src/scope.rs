@@ -0,0 +1,20 @@
+///! Scopes group activities so they can be cancelled together. Create a scope
+///! with `ConstellationTrait::create_scope()` and submit activities in it with
+///! `submit_in_scope(..)` (or by setting `SubmitOptions::scope`).
+///!
+///! Activities submitted from within an activity running in a scope are placed
+///! in that same scope automatically, and scopes created from within such an
+///! activity are child scopes. Cancelling a scope with `cancel_scope(..)`
+///! removes all pending and suspended activities in the scope and its child
+///! scopes, together with the events queued for them.
+use std::fmt;
+
+/// Identifier of a scope, unique within a constellation instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScopeId(pub(crate) u64);
+
+impl fmt::Display for ScopeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SID:{}", self.0)
+    }
+}
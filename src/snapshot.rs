@@ -0,0 +1,351 @@
+///! Snapshot of all internal queues of a constellation instance, returned by
+///! `ConstellationTrait::dump_state()` to find out why a run hangs. It lists,
+///! for every executor thread, the activities which are pending with their
+///! context and age, the suspended activities with how long they have been
+///! suspended and the events queued per destination. Besides the threads it
+///! lists the activities held back by the load balancer, the events it keeps
+///! because their destination was not found (orphaned events) and the number
+///! of events dropped because their destination had finished (dead-lettered).
+///!
+///! The queues are locked one at a time with a short try_lock, so a snapshot
+///! can be taken while the executor threads are busy, or hang. A queue which
+///! stays locked is skipped and listed in `skipped`. Since the queues are not
+///! locked together, an activity or event moving between queues may be
+///! missing or listed twice.
+///!
+///! With the config-file feature the snapshot can be serialized with serde.
+use crate::implementation::activity_queue::ActivityQueue;
+use crate::implementation::activity_wrapper::ActivityWrapperTrait;
+use crate::implementation::event_queue::EventQueue;
+use crate::implementation::sharded_event_queue::ShardedEventQueue;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "config-file")]
+use serde::{Deserialize, Serialize};
+
+/// Time spent trying to lock a queue before it is skipped
+pub const LOCK_WAIT: Duration = Duration::from_millis(10);
+
+/// An activity in one of the queues
+///
+/// # Members
+/// * `activity` - Identifier of the activity
+/// * `context` - Label of the context of the activity
+/// * `age_us` - Time since the activity was submitted, in microseconds
+/// * `suspended_us` - Time since the activity was suspended, in
+/// microseconds, only set for suspended activities
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "config-file", derive(Serialize, Deserialize))]
+pub struct ActivitySnapshot {
+    pub activity: String,
+    pub context: String,
+    pub age_us: u64,
+    pub suspended_us: Option<u64>,
+}
+
+impl ActivitySnapshot {
+    fn new(activity: &dyn ActivityWrapperTrait, now: Instant, suspended: bool) -> ActivitySnapshot {
+        let suspended_us = match activity.suspended_at() {
+            Some(at) if suspended => Some(now.saturating_duration_since(at).as_micros() as u64),
+            _ => None,
+        };
+
+        ActivitySnapshot {
+            activity: activity.activity_identifier().to_string(),
+            context: activity.context().label().to_string(),
+            age_us: now
+                .saturating_duration_since(activity.submitted_at())
+                .as_micros() as u64,
+            suspended_us,
+        }
+    }
+}
+
+impl fmt::Display for ActivitySnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}), age {:?}",
+            self.activity,
+            self.context,
+            Duration::from_micros(self.age_us)
+        )?;
+        if let Some(suspended_us) = self.suspended_us {
+            write!(
+                f,
+                ", suspended for {:?}",
+                Duration::from_micros(suspended_us)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of events queued for a destination
+///
+/// # Members
+/// * `destination` - Identifier of the destination activity
+/// * `events` - Number of events queued for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "config-file", derive(Serialize, Deserialize))]
+pub struct QueuedEvents {
+    pub destination: String,
+    pub events: usize,
+}
+
+/// The queues of a single executor thread
+///
+/// # Members
+/// * `thread_id` - Identifier of the thread
+/// * `pending` - Activities in the work queue of the thread, in the order
+/// they are picked
+/// * `suspended` - Activities suspended on the thread, waiting for events
+/// * `events` - Events queued on the thread, per destination
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "config-file", derive(Serialize, Deserialize))]
+pub struct ThreadSnapshot {
+    pub thread_id: i32,
+    pub pending: Vec<ActivitySnapshot>,
+    pub suspended: Vec<ActivitySnapshot>,
+    pub events: Vec<QueuedEvents>,
+}
+
+/// Snapshot of all queues of a constellation instance, see the module
+/// documentation
+///
+/// # Members
+/// * `time_us` - Time of the snapshot, in microseconds since the UNIX epoch
+/// * `node` - Identifier of the node
+/// * `threads` - The queues of every executor thread
+/// * `unplaced` - Activities held back by the load balancer, because all
+/// threads were at their cap or no thread serves their context
+/// * `orphaned_events` - Events kept by the load balancer because their
+/// destination was not found, per destination
+/// * `dead_lettered` - Number of events dropped because their destination
+/// had finished
+/// * `skipped` - Queues which were skipped because they stayed locked
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "config-file", derive(Serialize, Deserialize))]
+pub struct ConstellationSnapshot {
+    pub time_us: u64,
+    pub node: usize,
+    pub threads: Vec<ThreadSnapshot>,
+    pub unplaced: Vec<ActivitySnapshot>,
+    pub orphaned_events: Vec<QueuedEvents>,
+    pub dead_lettered: usize,
+    pub skipped: Vec<String>,
+}
+
+impl ConstellationSnapshot {
+    /// Create an empty snapshot of the given node, taken now
+    pub fn now(node: usize) -> ConstellationSnapshot {
+        let time_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.as_micros() as u64);
+
+        ConstellationSnapshot {
+            time_us,
+            node,
+            threads: Vec::new(),
+            unplaced: Vec::new(),
+            orphaned_events: Vec::new(),
+            dead_lettered: 0,
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Add the queues of an executor thread, a queue which stays locked is
+    /// skipped
+    ///
+    /// # Arguments
+    /// * `thread_id` - Identifier of the thread
+    /// * `work_queue` - Work queue of the thread
+    /// * `work_suspended` - Suspended activities of the thread
+    /// * `event_queue` - Event queue of the thread
+    pub(crate) fn add_thread(
+        &mut self,
+        thread_id: i32,
+        work_queue: &Mutex<ActivityQueue>,
+        work_suspended: &Mutex<ActivityQueue>,
+        event_queue: &Mutex<EventQueue>,
+    ) {
+        let now = Instant::now();
+        let mut thread = ThreadSnapshot {
+            thread_id,
+            pending: Vec::new(),
+            suspended: Vec::new(),
+            events: Vec::new(),
+        };
+
+        match try_lock(work_queue) {
+            Some(queue) => {
+                for (_, activity) in queue.iter() {
+                    thread
+                        .pending
+                        .push(ActivitySnapshot::new(&**activity, now, false));
+                }
+            }
+            None => self
+                .skipped
+                .push(format!("work queue of thread {}", thread_id)),
+        }
+
+        match try_lock(work_suspended) {
+            Some(queue) => {
+                for (_, activity) in queue.iter() {
+                    thread
+                        .suspended
+                        .push(ActivitySnapshot::new(&**activity, now, true));
+                }
+            }
+            None => self
+                .skipped
+                .push(format!("suspended activities of thread {}", thread_id)),
+        }
+
+        match try_lock(event_queue) {
+            Some(queue) => thread.events = queued_events(&queue),
+            None => self
+                .skipped
+                .push(format!("event queue of thread {}", thread_id)),
+        }
+
+        self.threads.push(thread);
+    }
+
+    /// Add the activities held back by the load balancer
+    pub(crate) fn add_unplaced(
+        &mut self,
+        unplaced: &Mutex<VecDeque<Box<dyn ActivityWrapperTrait>>>,
+    ) {
+        let now = Instant::now();
+        match try_lock(unplaced) {
+            Some(unplaced) => {
+                for activity in unplaced.iter() {
+                    self.unplaced
+                        .push(ActivitySnapshot::new(&**activity, now, false));
+                }
+            }
+            None => self.skipped.push("unplaced activities".to_string()),
+        }
+    }
+
+    /// Add the events kept by the load balancer, shard by shard
+    pub(crate) fn add_orphaned_events(&mut self, kept_events: &ShardedEventQueue) {
+        for (index, shard) in kept_events.shards().enumerate() {
+            match try_lock(shard) {
+                Some(shard) => self.orphaned_events.extend(queued_events(&shard)),
+                None => self.skipped.push(format!("kept events shard {}", index)),
+            }
+        }
+    }
+
+    /// Find an activity in the queues of the threads and the unplaced
+    /// activities, by the display of its identifier
+    pub fn activity(&self, activity: &str) -> Option<&ActivitySnapshot> {
+        self.threads
+            .iter()
+            .flat_map(|t| t.pending.iter().chain(t.suspended.iter()))
+            .chain(self.unplaced.iter())
+            .find(|a| a.activity == activity)
+    }
+
+    /// Check whether all queues were empty
+    pub fn is_empty(&self) -> bool {
+        self.unplaced.is_empty()
+            && self.orphaned_events.is_empty()
+            && self
+                .threads
+                .iter()
+                .all(|t| t.pending.is_empty() && t.suspended.is_empty() && t.events.is_empty())
+    }
+}
+
+impl fmt::Display for ConstellationSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "State of node {}:", self.node)?;
+        for thread in self.threads.iter() {
+            write!(
+                f,
+                "\n  thread {}: {} pending, {} suspended, {} queued events",
+                thread.thread_id,
+                thread.pending.len(),
+                thread.suspended.len(),
+                thread.events.iter().map(|e| e.events).sum::<usize>()
+            )?;
+            for activity in thread.pending.iter() {
+                write!(f, "\n    pending {}", activity)?;
+            }
+            for activity in thread.suspended.iter() {
+                write!(f, "\n    suspended {}", activity)?;
+            }
+            for events in thread.events.iter() {
+                write!(
+                    f,
+                    "\n    {} events for {}",
+                    events.events, events.destination
+                )?;
+            }
+        }
+        write!(f, "\n  {} unplaced activities", self.unplaced.len())?;
+        for activity in self.unplaced.iter() {
+            write!(f, "\n    {}", activity)?;
+        }
+        write!(
+            f,
+            "\n  {} orphaned events",
+            self.orphaned_events.iter().map(|e| e.events).sum::<usize>()
+        )?;
+        for events in self.orphaned_events.iter() {
+            write!(
+                f,
+                "\n    {} events for {}",
+                events.events, events.destination
+            )?;
+        }
+        write!(f, "\n  {} dead-lettered events", self.dead_lettered)?;
+        if !self.skipped.is_empty() {
+            write!(f, "\n  skipped, locked: {}", self.skipped.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The number of events per destination in an event queue, sorted by
+/// destination
+fn queued_events(queue: &EventQueue) -> Vec<QueuedEvents> {
+    let mut destinations: Vec<_> = queue.keys().collect();
+    destinations.sort();
+    destinations
+        .into_iter()
+        .map(|dst| QueuedEvents {
+            destination: dst.to_string(),
+            events: queue.count_for(dst),
+        })
+        .filter(|events| events.events > 0)
+        .collect()
+}
+
+/// Lock a mutex, trying for at most LOCK_WAIT. A poisoned mutex is locked
+/// anyway, the snapshot only reads.
+///
+/// # Returns
+/// * `Option<MutexGuard<T>>` - The guard, None if the mutex stayed locked
+pub(crate) fn try_lock<T: ?Sized>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    let deadline = Instant::now() + LOCK_WAIT;
+    loop {
+        match mutex.try_lock() {
+            Ok(guard) => return Some(guard),
+            Err(TryLockError::Poisoned(poisoned)) => return Some(poisoned.into_inner()),
+            Err(TryLockError::WouldBlock) if Instant::now() < deadline => thread::yield_now(),
+            Err(TryLockError::WouldBlock) => return None,
+        }
+    }
+}
@@ -0,0 +1,134 @@
+///! Statistics on stealing activities in batches, see `steal_batch_size` in
+///! the ConstellationConfiguration. Every time the load balancer takes
+///! activities submitted by threads, or an executor thread takes activities
+///! from its work queue, this counts as one steal. Comparing the number of
+///! items per steal with the configured batch size helps tuning it.
+///!
+///! Activities returning State::YIELD are put back in the work queue of their
+///! thread, these yields are counted as well.
+///!
+///! Stealing between nodes is counted separately: the steal requests this
+///! node sent to other nodes, the activities it received and the activities
+///! it handed to other nodes.
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Snapshot of the steal statistics
+///
+/// # Members
+/// * `steals` - Number of steals which moved at least one activity
+/// * `items` - Total number of activities moved by these steals
+/// * `yields` - Number of times an activity yielded and was put back in the
+/// work queue
+/// * `remote_requests` - Number of steal requests sent to other nodes
+/// * `remote_stolen` - Number of activities stolen from other nodes
+/// * `remote_given` - Number of activities handed to other nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StealStats {
+    pub steals: u64,
+    pub items: u64,
+    pub yields: u64,
+    pub remote_requests: u64,
+    pub remote_stolen: u64,
+    pub remote_given: u64,
+}
+
+impl StealStats {
+    /// Average number of activities moved per steal, 0 if nothing was stolen
+    pub fn items_per_steal(&self) -> f64 {
+        if self.steals == 0 {
+            return 0.0;
+        }
+
+        self.items as f64 / self.steals as f64
+    }
+}
+
+impl fmt::Display for StealStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} steals, {} items, {:.2} items per steal, {} yields",
+            self.steals,
+            self.items,
+            self.items_per_steal(),
+            self.yields
+        )?;
+        if self.remote_requests > 0 || self.remote_given > 0 {
+            write!(
+                f,
+                ", {} remote steal requests, {} stolen from and {} given to other nodes",
+                self.remote_requests, self.remote_stolen, self.remote_given
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Counters behind StealStats, shared by the load balancer and the executor
+/// threads
+pub(crate) struct StealCounters {
+    steals: AtomicU64,
+    items: AtomicU64,
+    yields: AtomicU64,
+    remote_requests: AtomicU64,
+    remote_stolen: AtomicU64,
+    remote_given: AtomicU64,
+}
+
+impl StealCounters {
+    pub(crate) fn new() -> StealCounters {
+        StealCounters {
+            steals: AtomicU64::new(0),
+            items: AtomicU64::new(0),
+            yields: AtomicU64::new(0),
+            remote_requests: AtomicU64::new(0),
+            remote_stolen: AtomicU64::new(0),
+            remote_given: AtomicU64::new(0),
+        }
+    }
+
+    /// Record a steal which moved the given number of activities, steals
+    /// which moved nothing are not counted
+    pub(crate) fn record(&self, items: usize) {
+        if items == 0 {
+            return;
+        }
+
+        self.steals.fetch_add(1, Ordering::Relaxed);
+        self.items.fetch_add(items as u64, Ordering::Relaxed);
+    }
+
+    /// Record that an activity yielded
+    pub(crate) fn record_yield(&self) {
+        self.yields.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a steal request sent to another node
+    pub(crate) fn record_remote_request(&self) {
+        self.remote_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record activities stolen from another node
+    pub(crate) fn record_remote_stolen(&self, items: usize) {
+        self.remote_stolen
+            .fetch_add(items as u64, Ordering::Relaxed);
+    }
+
+    /// Record activities handed to another node
+    pub(crate) fn record_remote_given(&self, items: usize) {
+        self.remote_given.fetch_add(items as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> StealStats {
+        StealStats {
+            steals: self.steals.load(Ordering::Relaxed),
+            items: self.items.load(Ordering::Relaxed),
+            yields: self.yields.load(Ordering::Relaxed),
+            remote_requests: self.remote_requests.load(Ordering::Relaxed),
+            remote_stolen: self.remote_stolen.load(Ordering::Relaxed),
+            remote_given: self.remote_given.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -1,11 +1,125 @@
 ///! Use the steal strategy to specify if a constellation instance should
 ///! prioritize large jobs or small jobs primarily when stealing and
-///! distributing them.
+///! distributing them. The strategy can be overridden per context, see
+///! `steal_strategy_overrides` in the ConstellationConfiguration.
+///!
+///! A strategy is parsed from its name in any case, as in environment
+///! variables and configuration files, and displayed as its name in capitals.
 ///!
 ///! TODO This is not yet fully implemented in the thread_helper
 
-#[derive(Clone)]
+#[cfg(feature = "config-file")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::context::{Context, ContextId};
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum StealStrategy {
     SMALLEST,
     BIGGEST,
 }
+
+impl fmt::Display for StealStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StealStrategy::SMALLEST => write!(f, "SMALLEST"),
+            StealStrategy::BIGGEST => write!(f, "BIGGEST"),
+        }
+    }
+}
+
+impl FromStr for StealStrategy {
+    type Err = ParseStealStrategyError;
+
+    /// Parse a strategy by name, in any case and ignoring surrounding
+    /// whitespace
+    fn from_str(name: &str) -> Result<StealStrategy, ParseStealStrategyError> {
+        match name.trim().to_uppercase().as_str() {
+            "SMALLEST" => Ok(StealStrategy::SMALLEST),
+            "BIGGEST" => Ok(StealStrategy::BIGGEST),
+            _ => Err(ParseStealStrategyError(name.to_string())),
+        }
+    }
+}
+
+#[cfg(feature = "config-file")]
+impl Serialize for StealStrategy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "config-file")]
+impl<'de> Deserialize<'de> for StealStrategy {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<StealStrategy, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Error parsing a StealStrategy, the value is the name which is not a
+/// strategy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseStealStrategyError(pub String);
+
+impl fmt::Display for ParseStealStrategyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Unknown steal strategy {:?}, expected BIGGEST or SMALLEST",
+            self.0
+        )
+    }
+}
+
+impl Error for ParseStealStrategyError {}
+
+/// The steal strategy of every context
+///
+/// # Members
+/// * `default` - Strategy of the contexts without an override
+/// * `overrides` - Strategy by context id, interned from the labels of the
+/// configuration
+#[derive(Debug, Clone)]
+pub(crate) struct ContextStealStrategies {
+    default: StealStrategy,
+    overrides: HashMap<ContextId, StealStrategy>,
+}
+
+impl ContextStealStrategies {
+    pub(crate) fn new(
+        default: StealStrategy,
+        overrides: HashMap<String, StealStrategy>,
+    ) -> ContextStealStrategies {
+        ContextStealStrategies {
+            default,
+            overrides: overrides
+                .into_iter()
+                .map(|(label, strategy)| (Context::intern(&label), strategy))
+                .collect(),
+        }
+    }
+
+    /// The steal strategy of a context
+    pub(crate) fn get(&self, context: &Context) -> &StealStrategy {
+        self.overrides.get(&context.id()).unwrap_or(&self.default)
+    }
+
+    /// Rank of an activity by its size hint under the strategy of its
+    /// context, activities with a lower rank are picked first
+    ///
+    /// # Arguments
+    /// * `context` - Context of the activity
+    /// * `size` - Size hint of the activity
+    pub(crate) fn size_rank(&self, context: &Context, size: usize) -> i64 {
+        match self.get(context) {
+            StealStrategy::BIGGEST => -(size as i64),
+            StealStrategy::SMALLEST => size as i64,
+        }
+    }
+}
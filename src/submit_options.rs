@@ -0,0 +1,193 @@
+///! Options used when submitting an activity to Constellation, passed to
+///! `ConstellationTrait::submit_with(..)`. Use the Default implementation and
+///! only set the options that differ, for example:
+///!
+///! ```
+///! use constellation_rust::SubmitOptions;
+///!
+///! let options = SubmitOptions {
+///!     expects_events: true,
+///!     ..Default::default()
+///! };
+///! ```
+use crate::{ConstellationError, Context, ScopeId};
+
+use std::fmt;
+use std::time::Duration;
+
+/// Nodes an activity may be executed on, see `SubmitOptions::placement`.
+/// Nodes are identified by their rank.
+///
+/// * `Anywhere` - Any node
+/// * `MasterOnly` - Only the master node, e.g. for activities writing the
+/// output of the application
+/// * `Node` - Only the given node, e.g. the node with the input files of the
+/// activity on local disk
+/// * `NotNode` - Any node except the given one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    Anywhere,
+    MasterOnly,
+    Node(usize),
+    NotNode(usize),
+}
+
+impl Placement {
+    /// Whether an activity with this placement may be executed on a node
+    ///
+    /// # Arguments
+    /// * `node_id` - Rank of the node
+    /// * `master` - Whether the node is the master node
+    pub fn allows(&self, node_id: usize, master: bool) -> bool {
+        match *self {
+            Placement::Anywhere => true,
+            Placement::MasterOnly => master,
+            Placement::Node(node) => node == node_id,
+            Placement::NotNode(node) => node != node_id,
+        }
+    }
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Placement::Anywhere => write!(f, "anywhere"),
+            Placement::MasterOnly => write!(f, "master node only"),
+            Placement::Node(node) => write!(f, "node {} only", node),
+            Placement::NotNode(node) => write!(f, "any node but {}", node),
+        }
+    }
+}
+
+/// Submit options struct
+///
+/// # Members
+/// * `may_be_stolen` - Whether this activity may be stolen by other
+/// threads/nodes
+/// * `expects_events` - Whether this activity expects events to complete
+/// * `priority` - Activities with a higher priority are executed before
+/// activities with a lower priority on the same thread, the steal strategy
+/// only decides between activities with equal priority
+/// * `thread_affinity` - Index of the executor thread this activity should be
+/// placed on, ignored when that thread does not serve the context of the
+/// activity. Submitting fails when there is no such thread.
+/// * `scope` - Scope to place the activity in, see `ConstellationTrait::
+/// create_scope()`. When None, activities submitted from within a scoped
+/// activity inherit the scope of that activity.
+/// * `max_execution_time` - Optional wall-clock limit for a single invocation
+/// of the activity (initialize, process or cleanup). Activities exceeding it
+/// are not interrupted, but are reported, see `ConstellationConfiguration::
+/// execution_timeout_callback`.
+/// * `prefer_parent_thread` - When submitted from within an activity, place
+/// the activity on the executor thread running the submitting activity, so it
+/// finds the data its parent just touched in the cache. The load balancer
+/// places it instead when that thread does not serve its context, has
+/// reached `max_activities_per_thread`, or is loaded more than
+/// `parent_thread_load_factor` times the other threads, see the
+/// ConstellationConfiguration. Ignored when a thread affinity is given and
+/// when running single threaded.
+/// * `placement` - Nodes the activity may be executed on. Activities start
+/// on the node they are submitted to and are only stolen by the nodes their
+/// placement allows. Submitting fails when the placement does not allow the
+/// submitting node, so a constraint that can not be satisfied is reported
+/// immediately.
+/// * `retries` - Number of times the activity is run again after it
+/// panicked in `initialize(..)` or `process(..)`, before it is retired. It is
+/// told about every panic through `on_error(..)`, and runs again from
+/// `initialize(..)` if it panicked there, otherwise like an activity which
+/// yielded. The events it was processing are not delivered again.
+#[derive(Debug, Clone)]
+pub struct SubmitOptions {
+    pub may_be_stolen: bool,
+    pub expects_events: bool,
+    pub priority: i32,
+    pub thread_affinity: Option<usize>,
+    pub scope: Option<ScopeId>,
+    pub max_execution_time: Option<Duration>,
+    pub prefer_parent_thread: bool,
+    pub placement: Placement,
+    pub retries: u32,
+}
+
+impl Default for SubmitOptions {
+    fn default() -> SubmitOptions {
+        SubmitOptions {
+            may_be_stolen: true,
+            expects_events: false,
+            priority: 0,
+            thread_affinity: None,
+            scope: None,
+            max_execution_time: None,
+            prefer_parent_thread: false,
+            placement: Placement::Anywhere,
+            retries: 0,
+        }
+    }
+}
+
+impl SubmitOptions {
+    /// Check the context and options of an activity before it is submitted,
+    /// so that a rejected activity has no side effects
+    ///
+    /// # Arguments
+    /// * `context` - The context of the activity
+    /// * `served` - Whether the context is known, and an executor thread
+    /// serves it or activities may be submitted with contexts no thread
+    /// serves, see `allow_unserved_contexts` in the ConstellationConfiguration
+    /// * `threads` - Number of executor threads on this node
+    /// * `node_id` - Rank of this node, the node executing the activity
+    /// * `master` - Whether this node is the master node
+    /// * `nodes` - Number of nodes
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - UnknownContext if the context is
+    /// not known or not served, InvalidThreadAffinity if there is no thread with the index
+    /// given as thread affinity, UnsatisfiablePlacement if the placement does
+    /// not allow this node
+    pub(crate) fn validate(
+        &self,
+        context: &Context,
+        served: bool,
+        threads: usize,
+        node_id: usize,
+        master: bool,
+        nodes: usize,
+    ) -> Result<(), ConstellationError> {
+        if !served {
+            warn!(
+                "Can not submit activity with {}, it is unknown or no executor thread serves it",
+                context
+            );
+            return Err(ConstellationError::UnknownContext(
+                context.label().to_string(),
+            ));
+        }
+
+        if let Some(index) = self.thread_affinity {
+            if index >= threads {
+                warn!(
+                    "Can not submit activity with affinity for thread {}, there are {} threads",
+                    index, threads
+                );
+                return Err(ConstellationError::InvalidThreadAffinity(index));
+            }
+        }
+
+        if !self.placement.allows(node_id, master) {
+            match self.placement {
+                Placement::Node(node) if node >= nodes => warn!(
+                    "Can not submit activity for node {}, there are {} nodes",
+                    node, nodes
+                ),
+                _ => warn!(
+                    "Can not submit activity placed on {} to node {}, activities \
+                     start on the node they are submitted to",
+                    self.placement, node_id
+                ),
+            }
+            return Err(ConstellationError::UnsatisfiablePlacement(self.placement));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+///! Subscriptions deliver the events for an activity to the application, see
+///! `ConstellationTrait::subscribe(..)`. The consumer of a result does not
+///! have to be an activity: an ordinary application thread subscribes to an
+///! identifier and calls `recv()` on the returned channel.
+///!
+///! Subscriptions are checked after all event interceptors, so they see the
+///! final destination of every event sent on this node, including delayed
+///! events once they are due. A subscription is removed once its receiver is
+///! dropped, the next time an event for the identifier is routed.
+///!
+///! Identifiers created with `ConstellationTrait::allocate_external_id()` do
+///! not belong to an activity. Events for them are always diverted to the
+///! subscriber, and dropped when there is none.
+use crate::intercept::{EventInterceptor, InterceptDecision};
+use crate::{ActivityIdentifier, Event};
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crossbeam::{unbounded, Receiver, Sender};
+
+/// What happens to an event for a subscribed identifier
+///
+/// * `Duplicate` - A copy of the event is sent to the subscriber, the event
+/// itself is delivered to the activity as usual. The copy does not carry the
+/// acknowledgement of `send_with_ack(..)`.
+/// * `Divert` - The event is sent to the subscriber instead of the activity,
+/// it counts as consumed for `send_with_ack(..)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionMode {
+    Duplicate,
+    Divert,
+}
+
+/// Subscriptions of a constellation instance, shared with all its threads
+///
+/// # Members
+/// * `subscribers` - Channel and mode of every subscribed identifier
+/// * `external` - Identifiers created with `allocate_external_id()`
+/// * `active` - Whether there are subscribers or external identifiers, so
+/// that sending an event does not take the lock when there are none
+pub(crate) struct Subscriptions {
+    subscribers: Mutex<HashMap<ActivityIdentifier, (Sender<Box<Event>>, SubscriptionMode)>>,
+    external: Mutex<HashSet<ActivityIdentifier>>,
+    active: AtomicBool,
+}
+
+impl Subscriptions {
+    pub fn new() -> Subscriptions {
+        Subscriptions {
+            subscribers: Mutex::new(HashMap::new()),
+            external: Mutex::new(HashSet::new()),
+            active: AtomicBool::new(false),
+        }
+    }
+
+    /// Subscribe to the events for an identifier, replacing an earlier
+    /// subscription to the same identifier
+    ///
+    /// # Arguments
+    /// * `aid` - The identifier
+    /// * `mode` - Whether events are duplicated or diverted, ignored for
+    /// external identifiers
+    ///
+    /// # Returns
+    /// * `Receiver<Box<Event>>` - Channel receiving the events
+    pub fn subscribe(
+        &self,
+        aid: &ActivityIdentifier,
+        mode: SubscriptionMode,
+    ) -> Receiver<Box<Event>> {
+        let (sender, receiver) = unbounded();
+
+        self.subscribers
+            .lock()
+            .unwrap()
+            .insert(aid.clone(), (sender, mode));
+        self.active.store(true, Ordering::SeqCst);
+
+        receiver
+    }
+
+    /// Register an identifier which does not belong to an activity
+    pub fn add_external(&self, aid: ActivityIdentifier) {
+        self.external.lock().unwrap().insert(aid);
+        self.active.store(true, Ordering::SeqCst);
+    }
+
+    /// Hand the event to the subscriber of its destination, if there is one
+    ///
+    /// # Arguments
+    /// * `e` - The event, its payload is taken when it is diverted
+    ///
+    /// # Returns
+    /// * `bool` - false if the event was diverted or its destination is an
+    /// external identifier, it must not be routed then
+    pub fn deliver(&self, e: &mut Event) -> bool {
+        if !self.active.load(Ordering::SeqCst) {
+            return true;
+        }
+
+        let aid = e.get_dst();
+        let external = self.external.lock().unwrap().contains(&aid);
+        let mut subscribers = self.subscribers.lock().unwrap();
+
+        let (sender, mode) = match subscribers.get(&aid) {
+            Some(subscriber) => subscriber,
+            None => return !external,
+        };
+
+        let sent = if external || *mode == SubscriptionMode::Divert {
+            match sender.send(Box::new(e.take())) {
+                Ok(()) => {
+                    if let Some(ack) = e.take_ack() {
+                        ack.consumed();
+                    }
+                    return false;
+                }
+                Err(returned) => {
+                    e.restore(*returned.into_inner());
+                    false
+                }
+            }
+        } else {
+            sender.send(Box::new(e.clone())).is_ok()
+        };
+
+        if !sent {
+            // The receiver was dropped
+            subscribers.remove(&aid);
+        }
+
+        !external
+    }
+}
+
+/// Create the interceptor handing events to the subscribers, it is run after
+/// all interceptors of the configuration
+pub(crate) fn interceptor(subscriptions: Arc<Subscriptions>) -> EventInterceptor {
+    Arc::new(move |e: &mut Event| {
+        if subscriptions.deliver(e) {
+            InterceptDecision::Continue
+        } else {
+            InterceptDecision::Drop
+        }
+    })
+}
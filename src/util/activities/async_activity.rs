@@ -0,0 +1,126 @@
+use crate::activity;
+use crate::activity::ActivityTrait;
+use crate::activity_identifier::ActivityIdentifier;
+use crate::event::Event;
+use crate::implementation::constellation_handle::ConstellationHandle;
+use crate::payload::PayloadTrait;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+/// Future producing the result of an AsyncActivity
+type ResultFuture = Pin<Box<dyn Future<Output = Box<dyn PayloadTrait>> + Send>>;
+
+/// Runs futures on an async runtime provided by the application, e.g. by
+/// calling `spawn` on the handle of a tokio runtime. Constellation never
+/// creates a runtime itself. Closures taking the future implement this trait,
+/// so a spawner can be created with
+/// `Arc::new(move |f| { handle.spawn(f); })`.
+pub trait Spawner: Send + Sync {
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>);
+}
+
+impl<F> Spawner for F
+where
+    F: Fn(Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync,
+{
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        self(future)
+    }
+}
+
+/// Async activity runs an async function, e.g. an HTTP call or a database
+/// fetch, without blocking an executor thread. When initialized, it spawns
+/// the future created by the factory on the spawner and suspends. Once the
+/// future completes, its result is sent to the activity itself, after which
+/// `process` forwards the result to the target and the activity finishes.
+///
+/// # Members
+/// * `spawner` - Runtime the future is spawned on
+/// * `factory` - Creates the future, taken when the activity is initialized
+/// * `target` - Activity the result is forwarded to
+pub struct AsyncActivity {
+    spawner: Arc<dyn Spawner>,
+    factory: Option<Box<dyn FnOnce() -> ResultFuture + Send>>,
+    target: ActivityIdentifier,
+}
+
+impl ActivityTrait for AsyncActivity {
+    fn cleanup(&mut self, _: &ConstellationHandle) {
+        // no cleanup necessary
+    }
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> activity::State {
+        let factory = match self.factory.take() {
+            Some(factory) => factory,
+            None => return activity::State::SUSPEND,
+        };
+
+        let future = factory();
+        let constellation = constellation.clone();
+        let id = id.clone();
+
+        self.spawner.spawn(Box::pin(async move {
+            let result = future.await;
+            if let Err(e) = constellation.send(Event::new(result, id.clone(), id)) {
+                warn!("Could not deliver the result of an async activity: {}", e);
+            }
+        }));
+
+        // Wait for the result
+        activity::State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        constellation: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        id: &ActivityIdentifier,
+    ) -> activity::State {
+        let event = match event {
+            Some(event) => event,
+            None => return activity::State::SUSPEND,
+        };
+
+        let result = Event::new(event.into_payload(), id.clone(), self.target.clone());
+        if let Err(e) = constellation.send(result) {
+            warn!("Could not forward the result of an async activity: {}", e);
+        }
+
+        activity::State::FINISH
+    }
+}
+
+impl AsyncActivity {
+    /// Create a new AsyncActivity, submit it like any other activity
+    ///
+    /// # Arguments
+    /// * `spawner` - Runtime the future is spawned on, see `Spawner`
+    /// * `factory` - Closure creating the future, called once when the
+    /// activity is initialized
+    /// * `target` - Activity the result of the future is forwarded to, in an
+    /// event sent by this activity
+    ///
+    /// # Returns
+    /// * `Arc<Mutex<AsyncActivity>>` - The activity, ready to be submitted
+    pub fn new<F, Fut>(
+        spawner: Arc<dyn Spawner>,
+        factory: F,
+        target: ActivityIdentifier,
+    ) -> Arc<Mutex<AsyncActivity>>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Box<dyn PayloadTrait>> + Send + 'static,
+    {
+        Arc::from(Mutex::from(AsyncActivity {
+            spawner,
+            factory: Some(Box::new(move || Box::pin(factory()) as ResultFuture)),
+            target,
+        }))
+    }
+}
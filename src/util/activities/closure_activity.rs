@@ -0,0 +1,92 @@
+use crate::activity;
+use crate::activity::ActivityTrait;
+use crate::activity_identifier::ActivityIdentifier;
+use crate::event::Event;
+use crate::implementation::constellation_handle::ConstellationHandle;
+use crate::payload::PayloadTrait;
+
+use std::sync::{Arc, Mutex};
+
+/// Task run by a ClosureActivity
+type Task = Box<dyn FnOnce(&ConstellationHandle, &ActivityIdentifier) + Send>;
+
+/// Closure activity runs a closure once and finishes, for one-off tasks which
+/// do not need their own activity struct. Submit it like any other activity,
+/// or use `ConstellationSpawn::spawn(..)` or `ConstellationHandle::spawn(..)`.
+///
+/// # Members
+/// * `task` - The closure, taken when the activity is initialized
+pub struct ClosureActivity {
+    task: Option<Task>,
+}
+
+impl ActivityTrait for ClosureActivity {
+    fn cleanup(&mut self, _: &ConstellationHandle) {
+        // no cleanup necessary
+    }
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> activity::State {
+        if let Some(task) = self.task.take() {
+            task(constellation, id);
+        }
+
+        activity::State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> activity::State {
+        activity::State::FINISH
+    }
+}
+
+impl ClosureActivity {
+    /// Create a new ClosureActivity
+    ///
+    /// # Arguments
+    /// * `task` - Closure called with the handle and identifier of the
+    /// activity when it is initialized
+    ///
+    /// # Returns
+    /// * `Arc<Mutex<ClosureActivity>>` - The activity, ready to be submitted
+    pub fn new<F>(task: F) -> Arc<Mutex<ClosureActivity>>
+    where
+        F: FnOnce(&ConstellationHandle, &ActivityIdentifier) + Send + 'static,
+    {
+        Arc::from(Mutex::from(ClosureActivity {
+            task: Some(Box::new(task)),
+        }))
+    }
+
+    /// Create a new ClosureActivity which sends the result of the closure to
+    /// another activity
+    ///
+    /// # Arguments
+    /// * `task` - Closure called with the handle and identifier of the
+    /// activity when it is initialized
+    /// * `target` - Activity the returned payload is sent to, in an event
+    /// sent by this activity
+    ///
+    /// # Returns
+    /// * `Arc<Mutex<ClosureActivity>>` - The activity, ready to be submitted
+    pub fn with_result<F>(task: F, target: ActivityIdentifier) -> Arc<Mutex<ClosureActivity>>
+    where
+        F: FnOnce(&ConstellationHandle, &ActivityIdentifier) -> Box<dyn PayloadTrait>
+            + Send
+            + 'static,
+    {
+        ClosureActivity::new(move |constellation, id| {
+            let result = Event::new(task(constellation, id), id.clone(), target);
+            if let Err(e) = constellation.send(result) {
+                warn!("Could not send the result of a closure activity: {}", e);
+            }
+        })
+    }
+}
@@ -0,0 +1,99 @@
+use crate::activity;
+use crate::activity::ActivityTrait;
+use crate::activity_identifier::ActivityIdentifier;
+use crate::event::Event;
+use crate::implementation::constellation_handle::ConstellationHandle;
+use crate::payload::PayloadTrait;
+
+use std::sync::{Arc, Mutex};
+
+/// What a HandlerActivity does after its handler returned
+///
+/// * `Continue` - Wait for the next event
+/// * `Finish` - Finish the activity
+/// * `FinishWith` - Send the payload to the given activity in an event sent by
+/// this activity, then finish
+pub enum HandlerControl {
+    Continue,
+    Finish,
+    FinishWith(Box<dyn PayloadTrait>, ActivityIdentifier),
+}
+
+/// Handler of a HandlerActivity, called with the state of the activity for
+/// every event it receives
+type Handler<S> = Box<dyn FnMut(&mut S, Box<Event>, &ConstellationHandle) -> HandlerControl + Send>;
+
+/// Handler activity keeps some state and calls a handler for every event it
+/// receives, until the handler tells it to finish. It suspends in between
+/// events, so the handler never deals with activity::State. Submit it with
+/// `expects_events` set.
+///
+/// # Members
+/// * `state` - State of the activity, passed to every call of the handler
+/// * `handler` - Called for every event
+pub struct HandlerActivity<S: Send + 'static> {
+    state: S,
+    handler: Handler<S>,
+}
+
+impl<S: Send + 'static> ActivityTrait for HandlerActivity<S> {
+    fn cleanup(&mut self, _: &ConstellationHandle) {
+        // no cleanup necessary
+    }
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> activity::State {
+        // Wait for the first event
+        activity::State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        constellation: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        id: &ActivityIdentifier,
+    ) -> activity::State {
+        let event = match event {
+            Some(event) => event,
+            None => return activity::State::SUSPEND,
+        };
+
+        match (self.handler)(&mut self.state, event, constellation) {
+            HandlerControl::Continue => activity::State::SUSPEND,
+            HandlerControl::Finish => activity::State::FINISH,
+            HandlerControl::FinishWith(payload, target) => {
+                if let Err(e) = constellation.send(Event::new(payload, id.clone(), target)) {
+                    warn!("Could not send the result of a handler activity: {}", e);
+                }
+                activity::State::FINISH
+            }
+        }
+    }
+}
+
+impl<S: Send + 'static> HandlerActivity<S> {
+    /// Create a new HandlerActivity
+    ///
+    /// # Arguments
+    /// * `state` - Initial state of the activity
+    /// * `handler` - Called with the state, the event and the handle for
+    /// every event the activity receives, returns whether to continue
+    ///
+    /// # Returns
+    /// * `Arc<Mutex<HandlerActivity<S>>>` - The activity, ready to be
+    /// submitted
+    pub fn new<F>(state: S, handler: F) -> Arc<Mutex<HandlerActivity<S>>>
+    where
+        F: FnMut(&mut S, Box<Event>, &ConstellationHandle) -> HandlerControl + Send + 'static,
+    {
+        Arc::from(Mutex::from(HandlerActivity {
+            state,
+            handler: Box::new(handler),
+        }))
+    }
+
+    /// The current state of the activity, e.g. to inspect it after it
+    /// finished
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+}
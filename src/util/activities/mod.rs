@@ -1 +1,6 @@
+#[cfg(feature = "futures")]
+pub mod async_activity;
+pub mod closure_activity;
+pub mod handler_activity;
 pub mod single_event_collector;
+pub mod stream_consumer;
@@ -1,39 +1,47 @@
 use crate::activity;
 use crate::activity::ActivityTrait;
 use crate::activity_identifier::ActivityIdentifier;
-use crate::constellation::ConstellationTrait;
 use crate::event::Event;
+use crate::implementation::constellation_handle::ConstellationHandle;
 
+#[cfg(feature = "futures")]
+use std::future::Future;
+#[cfg(feature = "futures")]
+use std::pin::Pin;
 use std::sync::{Arc, Mutex};
+#[cfg(feature = "futures")]
+use std::task::{self, Poll, Waker};
 use std::thread;
 use std::time::Duration;
 
 /// Single event collector is an activity which only waits for one event. The
 /// struct field event will be sent with this event upon receive.
 ///
+/// With the `futures` feature enabled, the event can also be awaited, see
+/// `event_future(..)`.
+///
 /// # Members
 /// * `event` - Event will be set when this activity retrieves the event.
+/// * `waker` - Waker of the task awaiting the event, woken when it arrives
 pub struct SingleEventCollector {
     pub event: Option<Box<Event>>,
+    #[cfg(feature = "futures")]
+    waker: Option<Waker>,
 }
 
 impl ActivityTrait for SingleEventCollector {
-    fn cleanup(&mut self, _: Arc<Mutex<Box<dyn ConstellationTrait>>>) {
+    fn cleanup(&mut self, _: &ConstellationHandle) {
         // no cleanup necessary
     }
 
-    fn initialize(
-        &mut self,
-        _: Arc<Mutex<Box<dyn ConstellationTrait>>>,
-        _id: &ActivityIdentifier,
-    ) -> activity::State {
+    fn initialize(&mut self, _: &ConstellationHandle, _id: &ActivityIdentifier) -> activity::State {
         // Don't process anything, just suspend for later processing
         return activity::State::SUSPEND;
     }
 
     fn process(
         &mut self,
-        _: Arc<Mutex<Box<dyn ConstellationTrait>>>,
+        _: &ConstellationHandle,
         event: Option<Box<Event>>,
         _id: &ActivityIdentifier,
     ) -> activity::State {
@@ -41,6 +49,12 @@ impl ActivityTrait for SingleEventCollector {
 
         match &self.event {
             Some(_e) => {
+                #[cfg(feature = "futures")]
+                {
+                    if let Some(waker) = self.waker.take() {
+                        waker.wake();
+                    }
+                }
                 return activity::State::FINISH;
             }
             None => {
@@ -52,7 +66,11 @@ impl ActivityTrait for SingleEventCollector {
 
 impl SingleEventCollector {
     pub fn new() -> Arc<Mutex<SingleEventCollector>> {
-        Arc::from(Mutex::from(SingleEventCollector { event: None }))
+        Arc::from(Mutex::from(SingleEventCollector {
+            event: None,
+            #[cfg(feature = "futures")]
+            waker: None,
+        }))
     }
 
     /// Loop on the global field event and return it when it has a value
@@ -73,4 +91,88 @@ impl SingleEventCollector {
             thread::sleep(interval);
         }
     }
+
+    /// Return the event if it has arrived, without blocking.
+    ///
+    /// This consumes the event: once it has been returned, subsequent calls
+    /// return None and `peek(..)` returns false. Note that `get_event(..)`
+    /// does NOT consume the event, it returns a copy.
+    ///
+    /// # Arguments
+    /// * `sec` - The SingleEventCollector to check event on.
+    ///
+    /// # Returns
+    /// * `Option<Box<Event>>` - The event, or None if it has not arrived (or
+    /// has already been taken)
+    pub fn try_get_event(sec: Arc<Mutex<SingleEventCollector>>) -> Option<Box<Event>> {
+        sec.lock().unwrap().event.take()
+    }
+
+    /// Check whether the event has arrived, without consuming it.
+    ///
+    /// # Arguments
+    /// * `sec` - The SingleEventCollector to check event on.
+    ///
+    /// # Returns
+    /// * `bool` - true if the event is available
+    pub fn peek(sec: Arc<Mutex<SingleEventCollector>>) -> bool {
+        sec.lock().unwrap().event.is_some()
+    }
+
+    /// Give up the collector and return its event, without copying it when
+    /// this is the last reference to the collector.
+    ///
+    /// # Arguments
+    /// * `sec` - The SingleEventCollector to take the event from.
+    ///
+    /// # Returns
+    /// * `Option<Box<Event>>` - The event, or None if it has not arrived (or
+    /// has already been taken)
+    pub fn into_event(sec: Arc<Mutex<SingleEventCollector>>) -> Option<Box<Event>> {
+        match Arc::try_unwrap(sec) {
+            Ok(sec) => sec.into_inner().unwrap().event,
+            Err(sec) => sec.lock().unwrap().event.take(),
+        }
+    }
+
+    /// Create a future resolving to the event. Like `try_get_event(..)`, the
+    /// future consumes the event.
+    ///
+    /// # Arguments
+    /// * `sec` - The SingleEventCollector to wait for.
+    ///
+    /// # Returns
+    /// * `EventFuture` - Future which is woken when the event arrives
+    #[cfg(feature = "futures")]
+    pub fn event_future(sec: Arc<Mutex<SingleEventCollector>>) -> EventFuture {
+        EventFuture { sec }
+    }
+}
+
+/// Future resolving to the event of a SingleEventCollector, see
+/// `SingleEventCollector::event_future(..)`. It does not poll: the collector
+/// stores the waker of the last poll and wakes it when the event arrives.
+///
+/// # Members
+/// * `sec` - The SingleEventCollector waited for
+#[cfg(feature = "futures")]
+pub struct EventFuture {
+    sec: Arc<Mutex<SingleEventCollector>>,
+}
+
+#[cfg(feature = "futures")]
+impl Future for EventFuture {
+    type Output = Box<Event>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<Box<Event>> {
+        let mut guard = self.sec.lock().unwrap();
+
+        match guard.event.take() {
+            Some(event) => Poll::Ready(event),
+            None => {
+                guard.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
 }
@@ -0,0 +1,119 @@
+use crate::activity;
+use crate::activity::ActivityTrait;
+use crate::activity_identifier::ActivityIdentifier;
+use crate::event::Event;
+use crate::implementation::constellation_handle::ConstellationHandle;
+use crate::payload::{PayloadTrait, PayloadTraitClone};
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Payload which stops a StreamConsumer, see `StreamConsumer::stop_event(..)`
+#[derive(Debug, Clone)]
+pub struct StopStream;
+
+impl PayloadTrait for StopStream {}
+
+impl PayloadTraitClone for StopStream {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for StopStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StopStream")
+    }
+}
+
+/// Stream consumer is a long-lived activity which calls a closure for every
+/// event it receives, until it receives an event with the StopStream payload.
+/// Events which are queued for the consumer when the stop event is processed
+/// are still handed to the closure, after which the consumer finishes
+/// (see `State::FINISH_AFTER_DRAIN`).
+///
+/// # Members
+/// * `consumer` - Closure called for every event, except the stop event
+/// * `consumed` - Number of events handed to the closure
+/// * `stopped` - Whether the stop event has been received
+pub struct StreamConsumer {
+    consumer: Box<dyn FnMut(&ConstellationHandle, Box<Event>) + Send>,
+    consumed: usize,
+    stopped: bool,
+}
+
+impl ActivityTrait for StreamConsumer {
+    fn cleanup(&mut self, _: &ConstellationHandle) {
+        // no cleanup necessary
+    }
+
+    fn initialize(&mut self, _: &ConstellationHandle, _id: &ActivityIdentifier) -> activity::State {
+        // Wait for the first event
+        activity::State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        constellation: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        _id: &ActivityIdentifier,
+    ) -> activity::State {
+        if let Some(event) = event {
+            if event.payload_as::<StopStream>().is_some() {
+                self.stopped = true;
+            } else {
+                (self.consumer)(constellation, event);
+                self.consumed += 1;
+            }
+        }
+
+        if self.stopped {
+            activity::State::FINISH_AFTER_DRAIN
+        } else {
+            activity::State::SUSPEND
+        }
+    }
+}
+
+impl StreamConsumer {
+    /// Create a new StreamConsumer, submit it like any other activity
+    ///
+    /// # Arguments
+    /// * `consumer` - Closure called for every event received, with the handle
+    /// of the thread the consumer runs on
+    ///
+    /// # Returns
+    /// * `Arc<Mutex<StreamConsumer>>` - The consumer, ready to be submitted
+    pub fn new<F>(consumer: F) -> Arc<Mutex<StreamConsumer>>
+    where
+        F: FnMut(&ConstellationHandle, Box<Event>) + Send + 'static,
+    {
+        Arc::from(Mutex::from(StreamConsumer {
+            consumer: Box::new(consumer),
+            consumed: 0,
+            stopped: false,
+        }))
+    }
+
+    /// Create the event which stops a StreamConsumer
+    ///
+    /// # Arguments
+    /// * `src` - Identifier of the sending activity
+    /// * `dst` - Identifier of the StreamConsumer
+    ///
+    /// # Returns
+    /// * `Event` - Event with the StopStream payload
+    pub fn stop_event(src: ActivityIdentifier, dst: ActivityIdentifier) -> Event {
+        Event::new(Box::new(StopStream), src, dst)
+    }
+
+    /// Number of events handed to the closure so far
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// Whether the stop event has been received
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+}
@@ -1 +1,3 @@
 pub mod activities;
+pub mod patterns;
+pub mod simulated_cluster;
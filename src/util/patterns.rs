@@ -0,0 +1,229 @@
+///! Ready made compositions of activities for common parallel patterns, which
+///! block the calling thread until the result is available. They work the
+///! same on every kind of constellation instance.
+///!
+///! `map_reduce(..)` scatters the inputs over worker activities, each of
+///! which maps a chunk of the inputs and reduces it locally, and combines the
+///! results of the workers with a tree of reducer activities, so that no
+///! single reducer receives the results of all workers:
+///!
+///! ```ignore
+///! let map: MapFn = Arc::new(|input| count_words(input));
+///! let reduce: ReduceFn = Arc::new(|a, b| merge_counts(a, b));
+///! let counts = patterns::map_reduce(&mut *constellation, inputs, map, reduce, &context)?;
+///! ```
+use crate::util::activities::closure_activity::ClosureActivity;
+use crate::util::activities::handler_activity::{HandlerActivity, HandlerControl};
+use crate::{
+    ActivityIdentifier, ActivityTrait, ConstellationError, ConstellationTrait, Context,
+    PayloadTrait, ScopeId, SingleEventCollector, SubmitOptions,
+};
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Maps one input to one result, called on the worker activities
+pub type MapFn = Arc<dyn Fn(Box<dyn PayloadTrait>) -> Box<dyn PayloadTrait> + Send + Sync>;
+
+/// Combines two results into one, called on the worker and reducer
+/// activities. It must be associative and commutative: results are combined
+/// in the order they arrive.
+pub type ReduceFn = Arc<
+    dyn Fn(Box<dyn PayloadTrait>, Box<dyn PayloadTrait>) -> Box<dyn PayloadTrait> + Send + Sync,
+>;
+
+/// How often the calling thread checks whether the result has arrived
+const RESULT_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Options for `map_reduce_with(..)`
+///
+/// # Members
+/// * `chunk_size` - Number of inputs mapped by every worker activity, at
+/// least 1. Larger chunks mean fewer, longer running activities.
+/// * `fan_in` - Number of results combined by every reducer activity, at
+/// least 2. The depth of the reducer tree is log(workers) / log(fan_in).
+#[derive(Debug, Clone)]
+pub struct MapReduceOptions {
+    pub chunk_size: usize,
+    pub fan_in: usize,
+}
+
+impl Default for MapReduceOptions {
+    fn default() -> MapReduceOptions {
+        MapReduceOptions {
+            chunk_size: 1,
+            fan_in: 2,
+        }
+    }
+}
+
+/// Map every input and reduce the results, using the default
+/// MapReduceOptions, see `map_reduce_with(..)`
+pub fn map_reduce(
+    constellation: &mut dyn ConstellationTrait,
+    inputs: Vec<Box<dyn PayloadTrait>>,
+    map: MapFn,
+    reduce: ReduceFn,
+    context: &Context,
+) -> Result<Box<dyn PayloadTrait>, ConstellationError> {
+    map_reduce_with(
+        constellation,
+        inputs,
+        map,
+        reduce,
+        context,
+        MapReduceOptions::default(),
+    )
+}
+
+/// Map every input and reduce the results, blocking until the final result
+/// is available. All activities are submitted in a new scope, which is
+/// cancelled when not all of them could be submitted.
+///
+/// # Arguments
+/// * `constellation` - An activated constellation instance
+/// * `inputs` - The inputs, at least one
+/// * `map` - Maps one input to one result
+/// * `reduce` - Combines two results, must be associative and commutative
+/// * `context` - Context of all activities
+/// * `options` - Chunk size and fan in, see MapReduceOptions
+///
+/// # Returns
+/// * `Result<Box<dyn PayloadTrait>, ConstellationError>` - The reduced
+/// result, ConstellationError::Failed if there are no inputs, or the error of
+/// submitting the activities
+pub fn map_reduce_with(
+    constellation: &mut dyn ConstellationTrait,
+    inputs: Vec<Box<dyn PayloadTrait>>,
+    map: MapFn,
+    reduce: ReduceFn,
+    context: &Context,
+    options: MapReduceOptions,
+) -> Result<Box<dyn PayloadTrait>, ConstellationError> {
+    if inputs.is_empty() {
+        warn!("map_reduce called without inputs");
+        return Err(ConstellationError::Failed);
+    }
+
+    let scope = constellation.create_scope();
+    match submit_map_reduce(constellation, inputs, map, reduce, context, &options, scope) {
+        Ok(collector) => {
+            let event = SingleEventCollector::get_event(collector, RESULT_POLL_INTERVAL);
+            Ok(event.into_payload())
+        }
+        Err(e) => {
+            constellation.cancel_scope(scope);
+            Err(e)
+        }
+    }
+}
+
+/// Submit the collector, the reducer tree and the workers, in that order so
+/// that every activity knows where to send its result
+fn submit_map_reduce(
+    constellation: &mut dyn ConstellationTrait,
+    mut inputs: Vec<Box<dyn PayloadTrait>>,
+    map: MapFn,
+    reduce: ReduceFn,
+    context: &Context,
+    options: &MapReduceOptions,
+    scope: ScopeId,
+) -> Result<Arc<Mutex<SingleEventCollector>>, ConstellationError> {
+    let chunk_size = options.chunk_size.max(1);
+    let fan_in = options.fan_in.max(2);
+    let waiting = SubmitOptions {
+        expects_events: true,
+        scope: Some(scope),
+        ..Default::default()
+    };
+
+    let collector = SingleEventCollector::new();
+    let collector_id = constellation.submit_with(
+        collector.clone() as Arc<Mutex<dyn ActivityTrait>>,
+        context,
+        waiting.clone(),
+    )?;
+
+    // Number of activities on every level of the tree, from the workers up
+    // to the root reducer. Activity i of a level sends its result to
+    // activity i / fan_in of the level above it.
+    let workers = (inputs.len() + chunk_size - 1) / chunk_size;
+    let mut levels = vec![workers];
+    while *levels.last().unwrap() > 1 {
+        let below = *levels.last().unwrap();
+        levels.push((below + fan_in - 1) / fan_in);
+    }
+
+    // Submit the reducers from the root down, the targets of every level are
+    // the activities of the level above it
+    let mut targets = vec![collector_id];
+    for level in (1..levels.len()).rev() {
+        let below = levels[level - 1];
+        let mut submitted = Vec::with_capacity(levels[level]);
+
+        for i in 0..levels[level] {
+            let expected = fan_in.min(below - i * fan_in);
+            let reducer = reducer(expected, reduce.clone(), targets[i / fan_in].clone());
+            submitted.push(constellation.submit_with(reducer, context, waiting.clone())?);
+        }
+
+        targets = submitted;
+    }
+
+    for i in (0..workers).rev() {
+        let chunk = inputs.split_off(i * chunk_size);
+        let map = map.clone();
+        let reduce = reduce.clone();
+
+        let worker = ClosureActivity::with_result(
+            move |_, _| {
+                chunk
+                    .into_iter()
+                    .map(|input| map(input))
+                    .fold(None, |acc, result| match acc {
+                        Some(acc) => Some(reduce(acc, result)),
+                        None => Some(result),
+                    })
+                    .unwrap()
+            },
+            targets[i / fan_in].clone(),
+        );
+        constellation.submit_with(
+            worker,
+            context,
+            SubmitOptions {
+                scope: Some(scope),
+                ..Default::default()
+            },
+        )?;
+    }
+
+    Ok(collector)
+}
+
+/// Reducer activity, combines the expected number of results and sends the
+/// combined result to its parent
+fn reducer(
+    expected: usize,
+    reduce: ReduceFn,
+    parent: ActivityIdentifier,
+) -> Arc<Mutex<dyn ActivityTrait>> {
+    HandlerActivity::new(
+        (None, expected),
+        move |state: &mut (Option<Box<dyn PayloadTrait>>, usize), event, _| {
+            let result = event.into_payload();
+            let combined = match state.0.take() {
+                Some(acc) => reduce(acc, result),
+                None => result,
+            };
+
+            state.1 -= 1;
+            if state.1 == 0 {
+                HandlerControl::FinishWith(combined, parent.clone())
+            } else {
+                state.0 = Some(combined);
+                HandlerControl::Continue
+            }
+        },
+    )
+}
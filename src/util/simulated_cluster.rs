@@ -0,0 +1,137 @@
+///! A cluster of constellation instances running inside a single process,
+///! used to test distributed code without mpirun. Every instance plays one
+///! node and communicates with the others through a LocalComm, so the
+///! heartbeats, activation and shutdown of all nodes run as they would with
+///! MPI.
+///!
+///! Activities are submitted to the master node, see `as_master()`. The
+///! other nodes steal from it once their executor threads are idle, just like
+///! the nodes of a distributed instance.
+use crate::constellation_factory::run_worker;
+use crate::{
+    Communication, ConstellationConfiguration, ConstellationError, ConstellationTrait, LocalComm,
+    MultiThreadedConstellation,
+};
+
+use std::sync::Arc;
+
+/// A group of MultiThreadedConstellation instances, one per simulated node
+///
+/// # Members
+/// * `nodes` - The instance of every node, by rank
+/// * `master_rank` - Rank of the master node, see `master_rank` in the
+/// ConstellationConfiguration
+pub struct SimulatedCluster {
+    nodes: Vec<MultiThreadedConstellation>,
+    master_rank: usize,
+}
+
+impl SimulatedCluster {
+    /// Create the instances of a cluster, they still have to be activated
+    ///
+    /// # Arguments
+    /// * `nodes` - Number of nodes, at least 1
+    /// * `threads_per_node` - Number of executor threads of every node,
+    /// overrides `number_of_threads` in the configuration
+    /// * `config` - Configuration used by every node, `number_of_nodes` is
+    /// set to `nodes`
+    ///
+    /// # Returns
+    /// * `SimulatedCluster` - The cluster, nodes are named node-0, node-1, ..
+    pub fn new(
+        nodes: usize,
+        threads_per_node: i32,
+        mut config: Box<ConstellationConfiguration>,
+    ) -> SimulatedCluster {
+        let nodes = nodes.max(1);
+        config.number_of_nodes = nodes as i32;
+        config.number_of_threads = threads_per_node;
+
+        let node_names = (0..nodes).map(|rank| format!("node-{}", rank)).collect();
+        let instances = LocalComm::group_on_nodes(node_names)
+            .into_iter()
+            .map(|comm| {
+                let comm: Arc<dyn Communication> = Arc::new(comm);
+                MultiThreadedConstellation::with_communication(config.clone(), comm)
+            })
+            .collect();
+
+        SimulatedCluster {
+            nodes: instances,
+            master_rank: config.master_rank.max(0) as usize,
+        }
+    }
+
+    /// Activate every node, the master first because the other nodes wait
+    /// for it to hand them its constellation_id, then the other nodes in
+    /// order of rank
+    ///
+    /// # Returns
+    /// * `Result<(), ConstellationError>` - The error of the first node which
+    /// could not be activated, nodes after it are not activated
+    pub fn activate(&mut self) -> Result<(), ConstellationError> {
+        let master_rank = self.master_rank;
+        if let Some(master) = self.nodes.get_mut(master_rank) {
+            master.activate()?;
+        }
+
+        for (rank, node) in self.nodes.iter_mut().enumerate() {
+            if rank != master_rank {
+                node.activate()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The master node, rank 0 unless `master_rank` is set in the
+    /// configuration. Activities and events are submitted here.
+    ///
+    /// Panics if `master_rank` is not the rank of one of the nodes
+    pub fn as_master(&mut self) -> &mut MultiThreadedConstellation {
+        &mut self.nodes[self.master_rank]
+    }
+
+    /// The node with the given rank, None if there is no such node
+    pub fn node(&mut self, rank: usize) -> Option<&mut MultiThreadedConstellation> {
+        self.nodes.get_mut(rank)
+    }
+
+    /// Number of nodes in the cluster
+    pub fn size(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Shut down the cluster: first the master, which waits for the
+    /// remaining work as configured, then the other nodes, which finish the
+    /// activities they stole first, see `run_worker(..)`
+    ///
+    /// # Returns
+    /// * `Result<bool, ConstellationError>` - The result of `done()` on the
+    /// master, or the first error of another node. While the master is not
+    /// shut down, the other nodes are left running, so they keep executing
+    /// stolen activities and `done()` can be called again later.
+    pub fn done(&mut self) -> Result<bool, ConstellationError> {
+        let master_rank = self.master_rank;
+        let mut result = self.nodes[master_rank].done();
+        match result {
+            Ok(true) | Err(ConstellationError::ForcedShutdown(_)) => (),
+            _ => return result,
+        }
+
+        for (rank, node) in self.nodes.iter_mut().enumerate() {
+            if rank == master_rank {
+                continue;
+            }
+
+            if let Err(e) = run_worker(node) {
+                warn!("Could not shut down simulated node {}: {}", rank, e);
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+
+        result
+    }
+}
@@ -0,0 +1,233 @@
+///! Diagnostic report on the work left in a constellation instance, returned
+///! in `ConstellationError::WorkLeft` when `done()` can not shut down. It
+///! lists, for every executor thread, the activities which are queued or
+///! suspended, and all orphaned events: events queued on a thread which does
+///! not hold their destination activity, or kept by the load balancer because
+///! no thread holds it.
+///!
+///! Lists are capped at REPORT_CAP entries, their counts always hold the full
+///! number. Activities and events which are being moved between queues at the
+///! moment the report is gathered are not listed.
+///!
+///! The report also embeds a ConstellationSnapshot of all queues, with the
+///! contexts and ages of the activities, which is not part of its display.
+use crate::implementation::activity_queue::ActivityQueue;
+use crate::implementation::event_queue::EventQueue;
+use crate::snapshot::ConstellationSnapshot;
+use crate::{ActivityIdentifier, Event};
+
+use std::fmt;
+
+/// Maximum number of entries listed per list in a report
+pub const REPORT_CAP: usize = 32;
+
+/// List holding at most REPORT_CAP entries, and the number of entries which
+/// were added in total
+///
+/// # Members
+/// * `items` - The first REPORT_CAP entries added
+/// * `count` - Number of entries added, also those which were not kept
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CappedList<T> {
+    pub items: Vec<T>,
+    pub count: usize,
+}
+
+impl<T> CappedList<T> {
+    pub fn new() -> CappedList<T> {
+        CappedList {
+            items: Vec::new(),
+            count: 0,
+        }
+    }
+
+    /// Add an entry, it is only kept when the list is not full yet
+    pub fn push(&mut self, item: T) {
+        if self.items.len() < REPORT_CAP {
+            self.items.push(item);
+        }
+        self.count += 1;
+    }
+
+    /// Number of entries which were added but not kept
+    pub fn omitted(&self) -> usize {
+        self.count - self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for CappedList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} [", self.count)?;
+        for (i, item) in self.items.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", item)?;
+        }
+        if self.omitted() > 0 {
+            write!(f, ", ... {} more", self.omitted())?;
+        }
+        write!(f, "]")
+    }
+}
+
+/// Description of an orphaned event
+///
+/// # Members
+/// * `src` - Source activity identifier
+/// * `dst` - Destination activity identifier
+/// * `payload_type` - Name of the type of the payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventInfo {
+    pub src: ActivityIdentifier,
+    pub dst: ActivityIdentifier,
+    pub payload_type: &'static str,
+}
+
+impl EventInfo {
+    pub fn new(event: &Event) -> EventInfo {
+        EventInfo {
+            src: event.get_src(),
+            dst: event.get_dst(),
+            payload_type: event.get_payload().type_name(),
+        }
+    }
+}
+
+impl fmt::Display for EventInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} -> {} ({})", self.src, self.dst, self.payload_type)
+    }
+}
+
+/// Work left on a single executor thread
+///
+/// # Members
+/// * `thread_id` - Identifier of the thread
+/// * `pending` - Activities in the work queue of the thread
+/// * `suspended` - Activities suspended on the thread, waiting for events
+/// * `queued_events` - Number of events queued for the pending and suspended
+/// activities, these are not orphaned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadWorkLeft {
+    pub thread_id: i32,
+    pub pending: CappedList<ActivityIdentifier>,
+    pub suspended: CappedList<ActivityIdentifier>,
+    pub queued_events: usize,
+}
+
+/// Work left in a constellation instance, see the module documentation
+///
+/// # Members
+/// * `threads` - Work left on each thread which has any
+/// * `unplaced` - Activities held back by the load balancer, because all
+/// threads were at their cap or no thread serves their context
+/// * `orphaned_events` - Events whose destination activity was not found
+/// * `snapshot` - Snapshot of all queues taken right after gathering the
+/// report, see `ConstellationTrait::dump_state()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkLeftReport {
+    pub threads: Vec<ThreadWorkLeft>,
+    pub unplaced: CappedList<ActivityIdentifier>,
+    pub orphaned_events: CappedList<EventInfo>,
+    pub snapshot: Option<Box<ConstellationSnapshot>>,
+}
+
+impl WorkLeftReport {
+    pub fn new() -> WorkLeftReport {
+        WorkLeftReport {
+            threads: Vec::new(),
+            unplaced: CappedList::new(),
+            orphaned_events: CappedList::new(),
+            snapshot: None,
+        }
+    }
+
+    /// Add the work left in the queues of a thread. Events queued for an
+    /// activity which is not in the queues of the thread are orphaned.
+    ///
+    /// # Arguments
+    /// * `thread_id` - Identifier of the thread
+    /// * `work_queue` - Work queue of the thread
+    /// * `work_suspended` - Suspended activities of the thread
+    /// * `event_queue` - Event queue of the thread
+    pub(crate) fn add_thread(
+        &mut self,
+        thread_id: i32,
+        work_queue: &ActivityQueue,
+        work_suspended: &ActivityQueue,
+        event_queue: &EventQueue,
+    ) {
+        let mut thread = ThreadWorkLeft {
+            thread_id,
+            pending: CappedList::new(),
+            suspended: CappedList::new(),
+            queued_events: 0,
+        };
+
+        let mut pending: Vec<&ActivityIdentifier> = work_queue.keys().collect();
+        pending.sort();
+        for aid in pending {
+            thread.pending.push(aid.clone());
+        }
+
+        let mut suspended: Vec<&ActivityIdentifier> = work_suspended.keys().collect();
+        suspended.sort();
+        for aid in suspended {
+            thread.suspended.push(aid.clone());
+        }
+
+        for event in event_queue.events() {
+            let dst = event.get_dst();
+            if work_queue.contains_key(&dst) || work_suspended.contains_key(&dst) {
+                thread.queued_events += 1;
+            } else {
+                self.orphaned_events.push(EventInfo::new(event));
+            }
+        }
+
+        if !thread.pending.is_empty() || !thread.suspended.is_empty() || thread.queued_events > 0 {
+            self.threads.push(thread);
+        }
+    }
+
+    /// Check whether the report lists the activity as pending, suspended or
+    /// unplaced
+    pub fn contains_activity(&self, aid: &ActivityIdentifier) -> bool {
+        self.unplaced.items.contains(aid)
+            || self
+                .threads
+                .iter()
+                .any(|t| t.pending.items.contains(aid) || t.suspended.items.contains(aid))
+    }
+
+    /// Check whether no work was found
+    pub fn is_empty(&self) -> bool {
+        self.threads.is_empty() && self.unplaced.is_empty() && self.orphaned_events.is_empty()
+    }
+}
+
+impl fmt::Display for WorkLeftReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Work left:")?;
+        for thread in self.threads.iter() {
+            write!(
+                f,
+                "\n  thread {}: pending {}, suspended {}, queued events {}",
+                thread.thread_id, thread.pending, thread.suspended, thread.queued_events
+            )?;
+        }
+        if !self.unplaced.is_empty() {
+            write!(f, "\n  unplaced: {}", self.unplaced)?;
+        }
+        if !self.orphaned_events.is_empty() {
+            write!(f, "\n  orphaned events: {}", self.orphaned_events)?;
+        }
+
+        Ok(())
+    }
+}
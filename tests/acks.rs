@@ -0,0 +1,78 @@
+//! Delivery acknowledgements: consumed, dead-lettered and failed events
+#[macro_use]
+mod common;
+
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, AckStatus, ActivityIdentifier, ActivityTrait, ConstellationHandle, Event,
+    SubmitOptions,
+};
+
+/// Activity which panics when it gets an event
+struct Crasher;
+
+impl ActivityTrait for Crasher {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        if event.is_some() {
+            panic!("Crasher got an event");
+        }
+        State::SUSPEND
+    }
+}
+
+fn acknowledgements(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    // Consumed by a waiting activity
+    let waiter = constellation
+        .submit(activity(Waiter), &context(), true, true)
+        .unwrap();
+    let ack = constellation.send_with_ack(ping(&waiter, &waiter)).unwrap();
+    assert_eq!(ack.wait(TIMEOUT), AckStatus::Consumed);
+    assert_eq!(ack.wait(Duration::from_millis(1)), AckStatus::Consumed);
+
+    // Dead-lettered, the destination was cancelled before it got the event
+    let scope = constellation.create_scope();
+    let cancelled = constellation
+        .submit_in_scope(
+            scope,
+            activity(Waiter),
+            &context(),
+            SubmitOptions::default(),
+        )
+        .unwrap();
+    constellation.cancel_scope(scope);
+    let ack = constellation
+        .send_with_ack(ping(&cancelled, &cancelled))
+        .unwrap();
+    assert_eq!(ack.wait(TIMEOUT), AckStatus::DeadLettered);
+
+    // Failed, the destination panicked while processing the event
+    let crasher = constellation
+        .submit(activity(Crasher), &context(), true, true)
+        .unwrap();
+    let ack = constellation
+        .send_with_ack(ping(&crasher, &crasher))
+        .unwrap();
+    assert_eq!(ack.wait(TIMEOUT), AckStatus::Failed);
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(acknowledgements, 3);
@@ -0,0 +1,101 @@
+//! ActivityIdentifier hashes over the same fields as it compares, the node
+//! name is not part of its identity
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use constellation_rust::implementation::communication::node_handler::NodeHandler;
+use constellation_rust::ActivityIdentifier;
+
+fn identifier(node_name: &str, node_id: usize, activity_id: u64) -> ActivityIdentifier {
+    ActivityIdentifier {
+        constellation_id: 7,
+        node_info: NodeHandler {
+            node_name: node_name.to_string(),
+            node_id,
+        },
+        activity_id,
+    }
+}
+
+fn hash_of(aid: &ActivityIdentifier) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    aid.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[test]
+fn node_name_is_not_hashed() {
+    let a = identifier("node001", 1, 42);
+    let b = identifier(" NODE001 ", 1, 42);
+
+    assert_eq!(a, b);
+    assert_eq!(hash_of(&a), hash_of(&b));
+}
+
+#[test]
+fn lookups_ignore_node_name() {
+    let mut queued: HashMap<ActivityIdentifier, usize> = HashMap::new();
+    for activity_id in 0..100 {
+        queued.insert(identifier("node001", 1, activity_id), activity_id as usize);
+    }
+
+    for activity_id in 0..100 {
+        let renamed = identifier("node001.cluster", 1, activity_id);
+        assert_eq!(queued.get(&renamed), Some(&(activity_id as usize)));
+    }
+
+    // Inserting an equal identifier with another node name replaces the entry
+    queued.insert(identifier("other", 1, 5), 500);
+    assert_eq!(queued.len(), 100);
+    assert_eq!(queued[&identifier("node001", 1, 5)], 500);
+}
+
+#[test]
+fn identity_fields_are_hashed() {
+    let base = identifier("node001", 1, 42);
+    let mut other_constellation = base.clone();
+    other_constellation.constellation_id += 1;
+
+    let identifiers: HashSet<ActivityIdentifier> = vec![
+        base.clone(),
+        identifier("node001", 2, 42),
+        identifier("node001", 1, 43),
+        other_constellation,
+    ]
+    .into_iter()
+    .collect();
+
+    assert_eq!(identifiers.len(), 4);
+    assert!(identifiers.contains(&identifier("renamed", 1, 42)));
+}
+
+#[test]
+fn ordered_by_constellation_node_and_activity() {
+    let mut other_constellation = identifier("node001", 0, 0);
+    other_constellation.constellation_id += 1;
+
+    let mut identifiers = vec![
+        other_constellation.clone(),
+        identifier("node001", 2, 1),
+        identifier("node002", 1, 7),
+        identifier("node001", 1, 3),
+    ];
+    identifiers.sort();
+
+    assert_eq!(
+        identifiers,
+        vec![
+            identifier("node001", 1, 3),
+            identifier("node002", 1, 7),
+            identifier("node001", 2, 1),
+            other_constellation,
+        ]
+    );
+
+    // The node name does not break ties either
+    assert_eq!(
+        identifier("a", 1, 3).cmp(&identifier("b", 1, 3)),
+        std::cmp::Ordering::Equal
+    );
+}
@@ -0,0 +1,132 @@
+//! Async functions run as activities on a runtime given by the application,
+//! the result is forwarded to a downstream collector
+#![cfg(feature = "futures")]
+#[macro_use]
+mod common;
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::executor::block_on;
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityTrait, AsyncActivity, PayloadTrait, PayloadTraitClone,
+    SingleEventCollector, Spawner,
+};
+
+const ACTIVITIES: u64 = 4;
+const SLEEP: Duration = Duration::from_millis(20);
+
+/// Result of an async function
+#[derive(Debug, Clone)]
+struct Value(u64);
+
+impl PayloadTrait for Value {}
+
+impl PayloadTraitClone for Value {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Future which is ready once the deadline passed, a timer thread wakes it
+struct Sleep {
+    deadline: Instant,
+    timer: bool,
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        deadline: Instant::now() + duration,
+        timer: false,
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+
+        if !self.timer {
+            self.timer = true;
+            let waker = cx.waker().clone();
+            let deadline = self.deadline;
+            thread::spawn(move || {
+                thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                waker.wake();
+            });
+        }
+        Poll::Pending
+    }
+}
+
+/// Runtime running every future on a thread of its own
+fn thread_spawner() -> Arc<dyn Spawner> {
+    Arc::new(|future: Pin<Box<dyn Future<Output = ()> + Send>>| {
+        thread::spawn(move || block_on(future));
+    })
+}
+
+fn result_forwarded(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let spawner = thread_spawner();
+    let start = Instant::now();
+    let collectors: Vec<Arc<Mutex<SingleEventCollector>>> = (0..ACTIVITIES)
+        .map(|value| {
+            let collector = SingleEventCollector::new();
+            let target = constellation
+                .submit(
+                    collector.clone() as Arc<Mutex<dyn ActivityTrait>>,
+                    &context(),
+                    false,
+                    true,
+                )
+                .unwrap();
+            let async_activity = AsyncActivity::new(
+                spawner.clone(),
+                move || async move {
+                    sleep(SLEEP).await;
+                    Box::new(Value(value * value)) as Box<dyn PayloadTrait>
+                },
+                target,
+            );
+            constellation
+                .submit(async_activity, &context(), true, true)
+                .unwrap();
+            collector
+        })
+        .collect();
+
+    for (value, collector) in collectors.into_iter().enumerate() {
+        let event = SingleEventCollector::get_event(collector, Duration::from_millis(1));
+        let value = value as u64;
+        assert_eq!(event.payload_as::<Value>().unwrap().0, value * value);
+    }
+
+    // The sleeps overlap, they do not block the executor threads
+    assert!(
+        start.elapsed() < SLEEP * ACTIVITIES as u32,
+        "{:?}",
+        start.elapsed()
+    );
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(result_forwarded, 4);
@@ -0,0 +1,106 @@
+//! Closures submitted as activities with `spawn(..)`, and with
+//! `spawn_with_result(..)` sending what they return to a target
+#[macro_use]
+mod common;
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationSpawn, ConstellationTrait,
+    PayloadTrait, PayloadTraitClone, SingleEventCollector,
+};
+
+/// Result of a closure
+#[derive(Debug, Clone)]
+struct Value(u64);
+
+impl PayloadTrait for Value {}
+
+impl PayloadTraitClone for Value {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Submit a SingleEventCollector
+fn collector(
+    constellation: &mut dyn ConstellationTrait,
+) -> (ActivityIdentifier, Arc<Mutex<SingleEventCollector>>) {
+    let sec = SingleEventCollector::new();
+    let aid = constellation
+        .submit(
+            sec.clone() as Arc<Mutex<dyn ActivityTrait>>,
+            &context(),
+            false,
+            true,
+        )
+        .unwrap();
+    (aid, sec)
+}
+
+fn spawn_closure(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let ran = Arc::new(Mutex::new(None));
+    let ran_in = ran.clone();
+    let aid = constellation
+        .spawn(&context(), move |_, id| {
+            *ran_in.lock().unwrap() = Some(id.clone());
+        })
+        .unwrap();
+    shut_down(constellation.as_mut());
+
+    assert_eq!(*ran.lock().unwrap(), Some(aid));
+}
+
+test_both_modes!(spawn_closure, 2);
+
+fn spawn_with_result(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let (target, sec) = collector(constellation.as_mut());
+    let aid = constellation
+        .spawn_with_result(&context(), |_, _| Box::new(Value(6 * 7)), target.clone())
+        .unwrap();
+
+    let event = SingleEventCollector::get_event(sec, Duration::from_millis(1));
+    assert_eq!(event.payload_as::<Value>().unwrap().0, 42);
+    assert_eq!(event.get_src(), aid);
+    assert_eq!(event.get_dst(), target);
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(spawn_with_result, 2);
+
+fn spawn_from_closure(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    // A closure spawning the closure which sends the result
+    let (target, sec) = collector(constellation.as_mut());
+    constellation
+        .spawn(&context(), move |handle, _| {
+            handle
+                .spawn_with_result(&context(), |_, _| Box::new(Value(1)), target)
+                .unwrap();
+        })
+        .unwrap();
+
+    let event = SingleEventCollector::get_event(sec, Duration::from_millis(1));
+    assert_eq!(event.payload_as::<Value>().unwrap().0, 1);
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(spawn_from_closure, 2);
@@ -0,0 +1,75 @@
+//! Minimal executor for the tests of the `futures` feature, it parks the
+//! thread until the future is woken instead of polling in a loop
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use std::thread::{self, Thread};
+
+/// Thread blocked on a future, and how often it was woken
+struct Signal {
+    thread: Thread,
+    wakes: AtomicUsize,
+}
+
+/// How often a future was polled, and woken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Counts {
+    pub polls: usize,
+    pub wakes: usize,
+}
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop_waker);
+
+unsafe fn clone(data: *const ()) -> RawWaker {
+    let signal = Arc::from_raw(data as *const Signal);
+    mem::forget(signal.clone());
+    RawWaker::new(Arc::into_raw(signal) as *const (), &VTABLE)
+}
+
+unsafe fn wake(data: *const ()) {
+    wake_by_ref(data);
+    drop_waker(data);
+}
+
+unsafe fn wake_by_ref(data: *const ()) {
+    let signal = &*(data as *const Signal);
+    signal.wakes.fetch_add(1, Ordering::SeqCst);
+    signal.thread.unpark();
+}
+
+unsafe fn drop_waker(data: *const ()) {
+    drop(Arc::from_raw(data as *const Signal));
+}
+
+/// Run the future to completion on the current thread
+///
+/// # Returns
+/// * `(F::Output, Counts)` - The output of the future, and how often it was
+/// polled and woken
+pub fn block_on<F: Future>(future: F) -> (F::Output, Counts) {
+    let signal = Arc::new(Signal {
+        thread: thread::current(),
+        wakes: AtomicUsize::new(0),
+    });
+    let raw = RawWaker::new(Arc::into_raw(signal.clone()) as *const (), &VTABLE);
+    let waker = unsafe { Waker::from_raw(raw) };
+    let mut cx = Context::from_waker(&waker);
+
+    let mut future = Box::pin(future);
+    let mut polls = 0;
+    loop {
+        polls += 1;
+        if let Poll::Ready(output) = Pin::as_mut(&mut future).poll(&mut cx) {
+            let wakes = signal.wakes.load(Ordering::SeqCst);
+            return (output, Counts { polls, wakes });
+        }
+
+        // Spurious unparks are possible, only poll again once woken
+        while signal.wakes.load(Ordering::SeqCst) < polls {
+            thread::park();
+        }
+    }
+}
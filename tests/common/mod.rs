@@ -0,0 +1,184 @@
+//! Activities and helpers shared by the integration tests
+#![allow(dead_code)]
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use constellation_rust::activity::State;
+use constellation_rust::payload::{PayloadTrait, PayloadTraitClone};
+use constellation_rust::{
+    ActivityIdentifier, ActivityTrait, ConstellationConfiguration, ConstellationError,
+    ConstellationHandle, ConstellationTrait, Context, ContextVec, Event, StealStrategy,
+};
+
+#[cfg(feature = "futures")]
+pub mod executor;
+pub mod remote;
+
+/// Label of the context all test activities run in
+pub const CONTEXT: &str = "test";
+
+/// Time after which a test gives up waiting for a constellation to finish
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Configuration with the given number of threads, all serving CONTEXT
+pub fn config(threads: i32) -> Box<ConstellationConfiguration> {
+    let mut context_vec = ContextVec::new();
+    context_vec.append(&Context::new(CONTEXT));
+
+    ConstellationConfiguration::new(
+        StealStrategy::BIGGEST,
+        StealStrategy::BIGGEST,
+        1,
+        threads,
+        false,
+        context_vec,
+        Duration::from_micros(100),
+    )
+}
+
+pub fn context() -> Context {
+    Context::new(CONTEXT)
+}
+
+/// Generate a module named after the test function with two tests, which
+/// run `$test(mode, threads)` on a single threaded instance and on a
+/// multithreaded instance with the given number of threads. Test files
+/// using it declare the module with `#[macro_use] mod common;`.
+#[allow(unused_macros)]
+macro_rules! test_both_modes {
+    ($test:ident, $threads:expr) => {
+        mod $test {
+            use constellation_rust::constellation_factory::Mode;
+
+            #[test]
+            fn single_threaded() {
+                super::$test(Mode::SingleThreaded, 1);
+            }
+
+            #[test]
+            fn multithreaded() {
+                super::$test(Mode::MultiThreaded, $threads);
+            }
+        }
+    };
+}
+
+/// Call done() until it succeeds, panics if work is still left after
+/// TIMEOUT
+pub fn shut_down(constellation: &mut dyn ConstellationTrait) {
+    let start = Instant::now();
+    loop {
+        match constellation.done() {
+            Ok(true) => return,
+            Ok(false) => panic!("done() called on a node which is not the master"),
+            Err(ConstellationError::WorkLeft(report)) if start.elapsed() < TIMEOUT => {
+                drop(report);
+                thread::sleep(Duration::from_millis(10));
+            }
+            Err(e) => panic!("Could not shut down: {}", e),
+        }
+    }
+}
+
+/// Wait until the condition holds, panics after TIMEOUT
+pub fn wait_for<F: FnMut() -> bool>(mut condition: F) {
+    let start = Instant::now();
+    while !condition() {
+        assert!(start.elapsed() < TIMEOUT, "Timed out waiting");
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Empty payload
+#[derive(Debug, Clone)]
+pub struct Ping;
+
+impl PayloadTrait for Ping {}
+
+impl PayloadTraitClone for Ping {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for Ping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ping")
+    }
+}
+
+pub fn ping(src: &ActivityIdentifier, dst: &ActivityIdentifier) -> Event {
+    Event::new(Box::new(Ping), src.clone(), dst.clone())
+}
+
+/// Activity which finishes right away
+pub struct Quick;
+
+impl ActivityTrait for Quick {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Activity which sleeps for the given time and finishes
+pub struct Sleeper(pub Duration);
+
+impl ActivityTrait for Sleeper {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        thread::sleep(self.0);
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Activity which waits for one event and finishes
+pub struct Waiter;
+
+impl ActivityTrait for Waiter {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        if event.is_some() {
+            State::FINISH
+        } else {
+            State::SUSPEND
+        }
+    }
+}
+
+pub fn activity<A: ActivityTrait + 'static>(activity: A) -> Arc<Mutex<dyn ActivityTrait>> {
+    Arc::new(Mutex::new(activity))
+}
@@ -0,0 +1,203 @@
+//! Activities and payloads shared by the tests of activities stolen by
+//! other nodes, on a simulated cluster or between MPI processes
+use std::convert::TryInto;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use super::config;
+use constellation_rust::activity::State;
+use constellation_rust::{
+    ActivityIdentifier, ActivityTrait, ConstellationConfiguration, ConstellationHandle, Event,
+    PayloadTrait, PayloadTraitClone,
+};
+
+/// Payload with a value and the rank of the node which sent it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub value: u64,
+    pub node: i32,
+}
+
+impl Report {
+    pub fn decode(bytes: &[u8]) -> Option<Report> {
+        if bytes.len() != 12 {
+            return None;
+        }
+        Some(Report {
+            value: u64::from_le_bytes(bytes[..8].try_into().ok()?),
+            node: i32::from_le_bytes(bytes[8..].try_into().ok()?),
+        })
+    }
+}
+
+impl PayloadTrait for Report {
+    fn encode(&self) -> Option<Vec<u8>> {
+        let mut bytes = self.value.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&self.node.to_le_bytes());
+        Some(bytes)
+    }
+}
+
+impl PayloadTraitClone for Report {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} from node {}", self.value, self.node)
+    }
+}
+
+/// Activity which waits for one event and answers its source with the value
+/// it was created with and the node it runs on
+pub struct Echo(pub u64);
+
+impl Echo {
+    pub fn decode(bytes: &[u8]) -> Option<Echo> {
+        Some(Echo(u64::from_le_bytes(bytes.try_into().ok()?)))
+    }
+}
+
+impl ActivityTrait for Echo {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        constellation: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        id: &ActivityIdentifier,
+    ) -> State {
+        let event = match event {
+            Some(event) => event,
+            None => return State::SUSPEND,
+        };
+        let report = Report {
+            value: self.0,
+            node: constellation.identifier().node_info.node_id as i32,
+        };
+        constellation
+            .send(Event::new(Box::new(report), id.clone(), event.get_src()))
+            .unwrap();
+        State::FINISH
+    }
+
+    fn encode(&self) -> Option<Vec<u8>> {
+        Some(self.0.to_le_bytes().to_vec())
+    }
+}
+
+/// Activity which can not be encoded, it reports the node it runs on to the
+/// collector when initialized
+pub struct Reporter {
+    pub collector: ActivityIdentifier,
+}
+
+impl ActivityTrait for Reporter {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        let report = Report {
+            value: 0,
+            node: constellation.identifier().node_info.node_id as i32,
+        };
+        constellation
+            .send(Event::new(
+                Box::new(report),
+                id.clone(),
+                self.collector.clone(),
+            ))
+            .unwrap();
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Activity which occupies its executor thread until it is released
+pub struct Gate {
+    pub started: Arc<AtomicBool>,
+    pub released: Arc<AtomicBool>,
+}
+
+impl ActivityTrait for Gate {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.started.store(true, Ordering::SeqCst);
+        while !self.released.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Activity which collects the reports it receives until it has the given
+/// number of them
+pub struct Collector {
+    pub expected: usize,
+    pub reports: Arc<Mutex<Vec<Report>>>,
+}
+
+impl ActivityTrait for Collector {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        let mut reports = self.reports.lock().unwrap();
+        if let Some(report) = event.and_then(|event| event.payload_as::<Report>().cloned()) {
+            reports.push(report);
+        }
+        if reports.len() < self.expected {
+            State::SUSPEND
+        } else {
+            State::FINISH
+        }
+    }
+}
+
+/// Configuration of a node with a single executor thread, which can decode
+/// Echo activities and Report payloads
+pub fn node_config() -> Box<ConstellationConfiguration> {
+    let mut config = config(1);
+    config.steal_batch_size = 4;
+    config.register_activity(Echo::decode);
+    config.register_payload(Report::decode);
+    config
+}
@@ -0,0 +1,136 @@
+//! `ActivityTrait::on_complete(..)` is called exactly once for every
+//! activity, with the reason it ended: finished, failed, timed out,
+//! cancelled with its scope, or released by a forced shutdown
+#[macro_use]
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, CompletionReason, ConstellationError,
+    ConstellationHandle, ConstellationTrait, Event, ScopeId, SubmitOptions,
+};
+
+const LIMIT: Duration = Duration::from_millis(1);
+
+/// How an Ending activity behaves when it is initialized
+#[derive(Clone, Copy)]
+enum Behaviour {
+    Finish,
+    Panic,
+    Overrun,
+    Wait,
+}
+
+/// Activity which records every reason it is told it completed with
+struct Ending {
+    behaviour: Behaviour,
+    reasons: Arc<Mutex<Vec<CompletionReason>>>,
+}
+
+impl ActivityTrait for Ending {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        match self.behaviour {
+            Behaviour::Finish => State::FINISH,
+            Behaviour::Panic => panic!("ending failed"),
+            Behaviour::Overrun => {
+                thread::sleep(LIMIT * 20);
+                State::FINISH
+            }
+            Behaviour::Wait => State::SUSPEND,
+        }
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+
+    fn on_complete(&mut self, reason: CompletionReason) {
+        self.reasons.lock().unwrap().push(reason);
+    }
+}
+
+/// Submit an Ending activity, in the given scope if any
+///
+/// # Returns
+/// * `Arc<Mutex<Vec<CompletionReason>>>` - The reasons it is told
+fn submit(
+    constellation: &mut dyn ConstellationTrait,
+    behaviour: Behaviour,
+    scope: Option<ScopeId>,
+) -> Arc<Mutex<Vec<CompletionReason>>> {
+    let reasons = Arc::new(Mutex::new(Vec::new()));
+    let ending = Ending {
+        behaviour,
+        reasons: reasons.clone(),
+    };
+    let options = SubmitOptions {
+        expects_events: matches!(behaviour, Behaviour::Wait),
+        max_execution_time: Some(LIMIT),
+        ..Default::default()
+    };
+    match scope {
+        Some(scope) => constellation.submit_in_scope(scope, activity(ending), &context(), options),
+        None => constellation.submit_with(activity(ending), &context(), options),
+    }
+    .unwrap();
+    reasons
+}
+
+fn reasons(reasons: &Arc<Mutex<Vec<CompletionReason>>>) -> Vec<CompletionReason> {
+    reasons.lock().unwrap().clone()
+}
+
+/// Number of activities suspended on the executor threads
+fn suspended(constellation: &mut dyn ConstellationTrait) -> usize {
+    let snapshot = constellation.dump_state();
+    snapshot.threads.iter().map(|t| t.suspended.len()).sum()
+}
+
+fn every_reason(mode: Mode, threads: i32) {
+    let mut config = config(threads);
+    config.shutdown_timeout = Some(Duration::from_millis(100));
+    let mut constellation = new_constellation(mode, config);
+    constellation.activate().unwrap();
+
+    let finished = submit(constellation.as_mut(), Behaviour::Finish, None);
+    let failed = submit(constellation.as_mut(), Behaviour::Panic, None);
+    let timed_out = submit(constellation.as_mut(), Behaviour::Overrun, None);
+    let scope = constellation.create_scope();
+    let cancelled = submit(constellation.as_mut(), Behaviour::Wait, Some(scope));
+
+    wait_for(|| suspended(constellation.as_mut()) == 1);
+    constellation.cancel_scope(scope);
+    constellation.wait_until_idle(TIMEOUT).unwrap();
+
+    // Still waiting when the instance is shut down
+    let shutdown = submit(constellation.as_mut(), Behaviour::Wait, None);
+    wait_for(|| suspended(constellation.as_mut()) == 1);
+    assert_eq!(
+        constellation.done(),
+        Err(ConstellationError::ForcedShutdown(1))
+    );
+
+    assert_eq!(reasons(&finished), vec![CompletionReason::Finished]);
+    match &reasons(&failed)[..] {
+        [CompletionReason::Failed(message)] => assert!(message.contains("ending failed")),
+        reasons => panic!("Unexpected reasons: {:?}", reasons),
+    }
+    assert_eq!(reasons(&timed_out), vec![CompletionReason::TimedOut]);
+    assert_eq!(reasons(&cancelled), vec![CompletionReason::Cancelled]);
+    assert_eq!(reasons(&shutdown), vec![CompletionReason::Shutdown]);
+}
+
+test_both_modes!(every_reason, 2);
@@ -0,0 +1,118 @@
+//! Parallel sections on the shared compute pool inside activities, while
+//! other activities keep running on the executor threads
+#![cfg(feature = "compute-pool")]
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::{
+    ActivityIdentifier, ActivityTrait, ComputePool, ConstellationHandle, ConstellationTrait, Event,
+    MultiThreadedConstellation,
+};
+use rayon::prelude::*;
+
+const N: u64 = 2_000_000;
+const SUMS: usize = 2;
+const OTHERS: usize = 50;
+const DEADLINE: Duration = Duration::from_secs(5);
+
+/// Activity which sums 0..N on the compute pool, and records the sum and the
+/// most activities it saw using the pool
+struct ParallelSum {
+    sums: Arc<Mutex<Vec<u64>>>,
+    most_active: Arc<AtomicUsize>,
+}
+
+impl ActivityTrait for ParallelSum {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, constellation: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        let pool = constellation.compute_pool().expect("No compute pool");
+        let most_active = self.most_active.clone();
+        let sum = pool.install(|| {
+            most_active.fetch_max(pool.active(), Ordering::SeqCst);
+            (0..N).into_par_iter().sum()
+        });
+        self.sums.lock().unwrap().push(sum);
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Activity which counts itself
+struct Other(Arc<AtomicUsize>);
+
+impl ActivityTrait for Other {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+#[test]
+fn parallel_sum_next_to_other_activities() {
+    let pool = ComputePool::new(2, 1).unwrap();
+    assert_eq!((pool.threads(), pool.max_concurrent()), (2, 1));
+
+    let mut config = config(3);
+    config.compute_pool = Some(Arc::new(pool));
+    let mut constellation = MultiThreadedConstellation::new(config);
+    constellation.activate().unwrap();
+
+    let start = Instant::now();
+    let sums = Arc::new(Mutex::new(Vec::new()));
+    let most_active = Arc::new(AtomicUsize::new(0));
+    for _ in 0..SUMS {
+        let sum = ParallelSum {
+            sums: sums.clone(),
+            most_active: most_active.clone(),
+        };
+        constellation
+            .submit(activity(sum), &context(), true, false)
+            .unwrap();
+    }
+    let others = Arc::new(AtomicUsize::new(0));
+    for _ in 0..OTHERS {
+        constellation
+            .submit(activity(Other(others.clone())), &context(), true, false)
+            .unwrap();
+    }
+
+    wait_for(|| others.load(Ordering::SeqCst) == OTHERS);
+    wait_for(|| sums.lock().unwrap().len() == SUMS);
+    assert!(start.elapsed() < DEADLINE, "Took {:?}", start.elapsed());
+    shut_down(&mut constellation);
+
+    assert_eq!(*sums.lock().unwrap(), vec![N * (N - 1) / 2; SUMS]);
+    // The second sum waited for the permit of the first
+    assert_eq!(most_active.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn invalid_pools() {
+    assert!(ComputePool::new(0, 1).is_err());
+    assert!(ComputePool::new(1, 0).is_err());
+}
@@ -0,0 +1,171 @@
+//! Loading and storing ConstellationConfiguration as TOML and JSON, with the
+//! fixture files in tests/fixtures
+#![cfg(feature = "config-file")]
+
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use constellation_rust::constellation_config::{
+    DEFAULT_NUMBER_OF_NODES, DEFAULT_TIME_BETWEEN_STEALS,
+};
+use constellation_rust::{ConfigError, ConstellationConfiguration, StealStrategy};
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+fn temp_file(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("constellation-{}-{}", std::process::id(), name))
+}
+
+/// Check every field set in full.toml and full.json
+fn assert_full(config: &ConstellationConfiguration) {
+    assert_eq!(config.local_steal_strategy, StealStrategy::SMALLEST);
+    assert_eq!(config.remote_steal_strategy, StealStrategy::BIGGEST);
+    assert_eq!(
+        config.steal_strategy_overrides["io"],
+        StealStrategy::BIGGEST
+    );
+    assert_eq!(
+        config.steal_strategy_overrides["gpu"],
+        StealStrategy::SMALLEST
+    );
+    assert_eq!(config.number_of_nodes, 2);
+    assert_eq!(config.number_of_threads, 4);
+    assert!(config.debug);
+    assert_eq!(
+        config.context_vec.to_string(),
+        r#"context:["gpu", "cpu", "io"]"#
+    );
+    assert_eq!(config.time_between_steals, Duration::from_micros(250));
+    assert_eq!(config.max_activities_per_thread, Some(1000));
+    assert_eq!(config.shed_high_watermark, Some(64));
+    assert_eq!(config.steal_batch_size, 32);
+    let thread_contexts: Vec<String> = config
+        .thread_contexts
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    assert_eq!(
+        thread_contexts,
+        vec![
+            r#"context:["gpu"]"#,
+            r#"context:["cpu"]"#,
+            r#"context:["cpu"]"#,
+            r#"context:["cpu", "io"]"#
+        ]
+    );
+    assert!(config.deterministic_scheduling);
+    assert_eq!(config.shutdown_timeout, Some(Duration::from_millis(5000)));
+    assert_eq!(config.load_report_interval, Duration::from_millis(500));
+    assert_eq!(config.load_report_stale_intervals, 4);
+    assert_eq!(config.master_rank, 1);
+    assert!(config.strict_node_count);
+    assert_eq!(
+        config.tcp_peers,
+        Some(vec![
+            "10.0.0.1:7000".to_string(),
+            "10.0.0.2:7000".to_string()
+        ])
+    );
+    assert_eq!(config.tcp_rank, 1);
+    assert_eq!(
+        config.same_node_socket_dir,
+        Some(PathBuf::from("/tmp/constellation-run-42"))
+    );
+    assert_eq!(config.heartbeat_interval, Duration::from_millis(200));
+    assert_eq!(config.heartbeat_miss_threshold, 5);
+    assert_eq!(config.debug_json, Some(PathBuf::from("schedule.jsonl")));
+    assert_eq!(
+        config.record_schedule,
+        Some(PathBuf::from("schedule.trace"))
+    );
+    assert_eq!(
+        config.replay_schedule,
+        Some(PathBuf::from("previous.trace"))
+    );
+    assert_eq!(
+        config.queue_sample_interval,
+        Some(Duration::from_millis(10))
+    );
+    assert_eq!(config.parent_thread_load_factor, 1.5);
+    assert_eq!(config.rebalance_threshold, Some(4.0));
+    assert!(config.pool_allocations);
+    assert!(config.allow_unserved_contexts);
+    assert_eq!(config.no_progress_timeout, Some(Duration::from_secs(10)));
+}
+
+#[test]
+fn minimal_file_gets_defaults() {
+    let config = ConstellationConfiguration::from_file(fixture("minimal.toml")).unwrap();
+    let default = ConstellationConfiguration::default();
+
+    assert_eq!(config.number_of_threads, 8);
+    assert_eq!(config.context_vec.to_string(), r#"context:["vector_add"]"#);
+    assert_eq!(config.number_of_nodes, DEFAULT_NUMBER_OF_NODES);
+    assert_eq!(config.time_between_steals, DEFAULT_TIME_BETWEEN_STEALS);
+    assert_eq!(config.local_steal_strategy, default.local_steal_strategy);
+    assert_eq!(config.steal_batch_size, default.steal_batch_size);
+    assert_eq!(config.max_activities_per_thread, None);
+    assert!(config.steal_strategy_overrides.is_empty());
+    assert!(config.thread_contexts.is_none());
+    assert!(!config.debug);
+}
+
+#[test]
+fn full_toml_file() {
+    assert_full(&ConstellationConfiguration::from_file(fixture("full.toml")).unwrap());
+}
+
+#[test]
+fn full_json_file() {
+    assert_full(&ConstellationConfiguration::from_file(fixture("full.json")).unwrap());
+}
+
+#[test]
+fn round_trip() {
+    let config = ConstellationConfiguration::from_file(fixture("full.toml")).unwrap();
+
+    for name in ["round_trip.toml", "round_trip.json"].iter() {
+        let path = temp_file(name);
+        config.to_file(&path).unwrap();
+        let loaded = ConstellationConfiguration::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_full(&loaded.unwrap());
+    }
+}
+
+#[test]
+fn unknown_field_reported_with_line() {
+    match ConstellationConfiguration::from_file(fixture("unknown_field.toml")) {
+        Err(ConfigError::Parse(message)) => {
+            assert!(message.contains("number_of_thread"), "{}", message);
+            assert!(message.contains("line 2"), "{}", message);
+        }
+        r => panic!("Unexpected result {:?}", r.map(|_| ())),
+    }
+}
+
+#[test]
+fn wrong_type_reported_with_line() {
+    match ConstellationConfiguration::from_file(fixture("wrong_type.toml")) {
+        Err(ConfigError::Parse(message)) => {
+            assert!(message.contains("line 2"), "{}", message)
+        }
+        r => panic!("Unexpected result {:?}", r.map(|_| ())),
+    }
+}
+
+#[test]
+fn unsupported_format() {
+    match ConstellationConfiguration::from_file("constellation.yaml") {
+        Err(ConfigError::UnsupportedFormat(_)) => {}
+        r => panic!("Unexpected result {:?}", r.map(|_| ())),
+    }
+}
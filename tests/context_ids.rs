@@ -0,0 +1,93 @@
+//! Context labels are interned into ContextIds: equal labels get equal ids
+//! also when interned from several threads at once, an id gives back its
+//! label, and contexts display their label as before
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+use constellation_rust::{Context, ContextId, ContextVec};
+
+const THREADS: usize = 8;
+const LABELS: usize = 200;
+
+fn hash(context: &Context) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    context.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[test]
+fn equal_labels_equal_ids() {
+    let a = Context::new("ids/equal");
+    let b = Context::from("ids/equal");
+    let other = Context::new("ids/other");
+
+    assert_eq!(a, b);
+    assert_eq!(a.id(), b.id());
+    assert_eq!(hash(&a), hash(&b));
+    assert_eq!(Context::intern("ids/equal"), a.id());
+
+    assert_ne!(a, other);
+    assert_ne!(a.id(), other.id());
+
+    // Labels are compared exactly
+    assert_ne!(Context::intern("ids/Equal"), a.id());
+    assert_ne!(Context::intern("ids/equal "), a.id());
+}
+
+#[test]
+fn round_trip() {
+    for label in ["ids/round", "", "ids/ünïcode", "ids/with space"].iter() {
+        let id = Context::intern(label);
+        assert_eq!(id.label(), *label);
+        assert_eq!(Context::intern(id.label()), id);
+
+        let context = Context::new(label);
+        assert_eq!(context.label(), *label);
+        assert_eq!(context.to_string(), format!("context:{}", label));
+    }
+
+    let contexts = ContextVec::from(vec!["ids/a", "ids/b"]);
+    assert_eq!(contexts.to_string(), r#"context:["ids/a", "ids/b"]"#);
+    assert!(contexts.contains(&Context::new("ids/b")));
+    assert!(!contexts.contains(&Context::new("ids/c")));
+}
+
+#[test]
+fn interned_concurrently() {
+    let barrier = Arc::new(Barrier::new(THREADS));
+    let handles: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                // Every thread interns the labels in a different order
+                (0..LABELS)
+                    .map(|i| {
+                        let label = format!("ids/concurrent/{}", (i * (t + 1)) % LABELS);
+                        (label.clone(), Context::intern(&label))
+                    })
+                    .collect::<HashMap<String, ContextId>>()
+            })
+        })
+        .collect();
+
+    let results: Vec<HashMap<String, ContextId>> =
+        handles.into_iter().map(|h| h.join().unwrap()).collect();
+    for (label, id) in &results[0] {
+        assert_eq!(id.label(), label);
+        for result in &results[1..] {
+            if let Some(other) = result.get(label) {
+                assert_eq!(other, id, "{}", label);
+            }
+        }
+    }
+
+    // Every label got an id of its own
+    let mut ids: Vec<ContextId> = results[0].values().cloned().collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), results[0].len());
+}
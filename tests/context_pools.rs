@@ -0,0 +1,112 @@
+//! Executor threads assigned their own contexts with `thread_contexts`: a
+//! "gpu" pool of one thread and a "cpu" pool of three
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::{
+    ActivityIdentifier, ActivityTrait, ConstellationHandle, ConstellationTrait, Context,
+    ContextVec, Event, MultiThreadedConstellation,
+};
+
+const GPU: &str = "gpu";
+const CPU: &str = "cpu";
+
+/// The context label and thread index of every executed activity
+type Executions = Arc<Mutex<Vec<(String, i32)>>>;
+
+/// Activity which records where it ran, and submits `children` activities
+/// of the other context
+struct Task {
+    label: &'static str,
+    children: usize,
+    executions: Executions,
+}
+
+impl ActivityTrait for Task {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, constellation: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.executions
+            .lock()
+            .unwrap()
+            .push((self.label.to_string(), constellation.thread_id()));
+
+        let label = if self.label == GPU { CPU } else { GPU };
+        for _ in 0..self.children {
+            let child = Task {
+                label,
+                children: 0,
+                executions: self.executions.clone(),
+            };
+            constellation
+                .submit(activity(child), &Context::new(label), true, false)
+                .unwrap();
+        }
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+fn contexts(label: &str) -> ContextVec {
+    let mut contexts = ContextVec::new();
+    contexts.append(&Context::new(label));
+    contexts
+}
+
+#[test]
+fn activities_stay_in_their_pool() {
+    let mut config = config(4);
+    config.thread_contexts = Some(vec![
+        contexts(GPU),
+        contexts(CPU),
+        contexts(CPU),
+        contexts(CPU),
+    ]);
+    let mut constellation = MultiThreadedConstellation::new(config);
+    constellation.activate().unwrap();
+
+    assert_eq!(constellation.threads_serving(&Context::new(GPU)), vec![0]);
+    assert_eq!(
+        constellation.threads_serving(&Context::new(CPU)),
+        vec![1, 2, 3]
+    );
+    let topology = constellation.topology();
+    assert_eq!(topology.len(), 4);
+    assert!(topology[0].as_ref().unwrap().contains(&Context::new(GPU)));
+    assert!(!topology[0].as_ref().unwrap().contains(&Context::new(CPU)));
+
+    // Submitted from outside, and from activities of the other pool
+    let executions = Arc::new(Mutex::new(Vec::new()));
+    for i in 0..60 {
+        let label = if i % 3 == 0 { GPU } else { CPU };
+        let task = Task {
+            label,
+            children: 2,
+            executions: executions.clone(),
+        };
+        constellation
+            .submit(activity(task), &Context::new(label), true, false)
+            .unwrap();
+    }
+    shut_down(&mut constellation);
+
+    let executions = executions.lock().unwrap();
+    assert_eq!(executions.len(), 180);
+    for (label, thread) in executions.iter() {
+        match label.as_str() {
+            GPU => assert_eq!(*thread, 0, "gpu activity ran on thread {}", thread),
+            _ => assert!(*thread > 0, "cpu activity ran on the gpu thread"),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! ContextVecs built with the `contexts!` macro and the From conversions,
+//! and their serde round trip through JSON and TOML as plain lists of labels,
+//! including hierarchical labels
+#![cfg(feature = "config-file")]
+#[macro_use]
+extern crate constellation_rust;
+
+use serde::{Deserialize, Serialize};
+
+use constellation_rust::{Context, ContextVec};
+
+const LABELS: [&str; 4] = ["io", "compute/gpu", "compute/gpu/cuda", "a/b/c/d/e"];
+
+/// Wrapper, TOML documents are tables
+#[derive(Serialize, Deserialize)]
+struct Document {
+    contexts: ContextVec,
+    context: Context,
+}
+
+fn labels(contexts: &ContextVec) -> Vec<&'static str> {
+    contexts.context_vec.iter().map(|x| x.label()).collect()
+}
+
+#[test]
+fn built_the_same_way() {
+    let from_macro = contexts!["io", "compute/gpu", "compute/gpu/cuda", "a/b/c/d/e",];
+    let from_vec = ContextVec::from(LABELS.to_vec());
+
+    let mut mutated = ContextVec::new();
+    for label in LABELS.iter() {
+        mutated.append(&Context::from(*label));
+    }
+
+    assert_eq!(labels(&from_macro), LABELS);
+    assert_eq!(from_macro.context_vec, from_vec.context_vec);
+    assert_eq!(from_macro.context_vec, mutated.context_vec);
+    assert_eq!(
+        ContextVec::from("io").context_vec,
+        contexts!["io"].context_vec
+    );
+    assert!(contexts![].context_vec.is_empty());
+}
+
+#[test]
+fn json_round_trip() {
+    let contexts = ContextVec::from(LABELS.to_vec());
+    let json = serde_json::to_string(&contexts).unwrap();
+    assert_eq!(
+        json,
+        r#"["io","compute/gpu","compute/gpu/cuda","a/b/c/d/e"]"#
+    );
+    let back: ContextVec = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.context_vec, contexts.context_vec);
+
+    let context = Context::new("compute/gpu/cuda");
+    let json = serde_json::to_string(&context).unwrap();
+    assert_eq!(json, r#""compute/gpu/cuda""#);
+    let back: Context = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, context);
+    assert_eq!(back.id(), context.id());
+
+    let empty: ContextVec = serde_json::from_str("[]").unwrap();
+    assert!(empty.context_vec.is_empty());
+    assert!(serde_json::from_str::<ContextVec>(r#""io""#).is_err());
+    assert!(serde_json::from_str::<Context>("[1]").is_err());
+}
+
+#[test]
+fn toml_round_trip() {
+    let document = Document {
+        contexts: ContextVec::from(LABELS.to_vec()),
+        context: Context::new("compute/gpu"),
+    };
+    let text = toml::to_string(&document).unwrap();
+    let back: Document = toml::from_str(&text).unwrap();
+    assert_eq!(back.contexts.context_vec, document.contexts.context_vec);
+    assert_eq!(back.context, document.context);
+
+    let written: Document = toml::from_str(
+        r#"
+        contexts = ["io", "compute/gpu/cuda"]
+        context = "a/b/c/d/e"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(labels(&written.contexts), ["io", "compute/gpu/cuda"]);
+    assert_eq!(written.context.label(), "a/b/c/d/e");
+}
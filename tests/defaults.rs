@@ -0,0 +1,64 @@
+//! The default configuration and `single_node(..)` are valid, and accepted by
+//! the factory
+#[macro_use]
+mod common;
+
+use common::*;
+use constellation_rust::constellation_config::{
+    DEFAULT_NUMBER_OF_NODES, DEFAULT_NUMBER_OF_THREADS, DEFAULT_PARENT_THREAD_LOAD_FACTOR,
+    DEFAULT_STEAL_STRATEGY, DEFAULT_TIME_BETWEEN_STEALS,
+};
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{new_constellation, ConstellationConfiguration, Context};
+
+#[test]
+fn defaults_validate() {
+    let config = ConstellationConfiguration::default();
+    assert_eq!(config.validate().map_err(|e| e.to_string()), Ok(()));
+
+    assert_eq!(config.local_steal_strategy, DEFAULT_STEAL_STRATEGY);
+    assert_eq!(config.remote_steal_strategy, DEFAULT_STEAL_STRATEGY);
+    assert_eq!(config.number_of_nodes, DEFAULT_NUMBER_OF_NODES);
+    assert_eq!(config.number_of_threads, DEFAULT_NUMBER_OF_THREADS);
+    assert_eq!(config.time_between_steals, DEFAULT_TIME_BETWEEN_STEALS);
+    assert_eq!(
+        config.parent_thread_load_factor,
+        DEFAULT_PARENT_THREAD_LOAD_FACTOR
+    );
+    assert!(!config.debug);
+    assert!(config.context_vec.context_vec.is_empty());
+    assert!(config.resolved_number_of_threads() > 0);
+}
+
+#[test]
+fn single_node_validates() {
+    for threads in 0..4 {
+        let config = ConstellationConfiguration::single_node(threads);
+        assert_eq!(config.validate().map_err(|e| e.to_string()), Ok(()));
+        assert_eq!(config.number_of_threads, threads);
+        assert_eq!(config.number_of_nodes, 1);
+    }
+}
+
+fn factory_accepts_defaults(mode: Mode, threads: i32) {
+    let config = ConstellationConfiguration::single_node(threads);
+    let mut constellation = new_constellation(mode, config);
+    constellation.activate().unwrap();
+
+    // Without contexts, activities of any context are accepted
+    for label in ["a", "b", CONTEXT].iter() {
+        constellation
+            .submit(activity(Quick), &Context::new(label), true, false)
+            .unwrap();
+    }
+    shut_down(constellation.as_mut());
+
+    let mut constellation = new_constellation(mode, Box::new(Default::default()));
+    constellation.activate().unwrap();
+    constellation
+        .submit(activity(Quick), &context(), true, false)
+        .unwrap();
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(factory_accepts_defaults, 2);
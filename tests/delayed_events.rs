@@ -0,0 +1,95 @@
+//! Events sent with `send_after(..)` are delivered once the delay has
+//! passed, unless they are cancelled with `cancel_delayed(..)` first
+#[macro_use]
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, Event,
+};
+
+const DELAY: Duration = Duration::from_millis(100);
+
+/// Activity which sends itself a timeout event, and finishes when it
+/// arrives
+struct Watchdog;
+
+impl ActivityTrait for Watchdog {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        constellation.send_after(ping(id, id), DELAY).unwrap();
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        if event.is_some() {
+            State::FINISH
+        } else {
+            State::SUSPEND
+        }
+    }
+}
+
+fn delivered_after_delay(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let src = constellation.allocate_external_id();
+    let dst = constellation.allocate_external_id();
+    let events = constellation.subscribe(&dst);
+
+    let start = Instant::now();
+    constellation.send_after(ping(&src, &dst), DELAY).unwrap();
+    assert!(events.try_recv().is_err());
+
+    let event = events.recv_timeout(TIMEOUT).unwrap();
+    assert!(start.elapsed() >= DELAY);
+    assert_eq!(event.get_dst(), dst);
+
+    // An activity can use it as a watchdog
+    let start = Instant::now();
+    constellation
+        .submit(activity(Watchdog), &context(), false, true)
+        .unwrap();
+    shut_down(constellation.as_mut());
+    assert!(start.elapsed() >= DELAY);
+}
+
+test_both_modes!(delivered_after_delay, 2);
+
+fn cancelled_before_delivery(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let src = constellation.allocate_external_id();
+    let dst = constellation.allocate_external_id();
+    let events = constellation.subscribe(&dst);
+
+    let token = constellation.send_after(ping(&src, &dst), DELAY).unwrap();
+    let kept = constellation.send_after(ping(&src, &dst), DELAY).unwrap();
+    assert!(constellation.cancel_delayed(token));
+    assert!(!constellation.cancel_delayed(token));
+
+    // Only the event which was not cancelled arrives
+    events.recv_timeout(TIMEOUT).unwrap();
+    assert!(events.recv_timeout(DELAY * 3).is_err());
+    assert!(!constellation.cancel_delayed(kept));
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(cancelled_before_delivery, 2);
@@ -0,0 +1,99 @@
+//! With `deterministic_scheduling` the executor picks the smallest pending
+//! ActivityIdentifier, so the same graph runs in the same order every time
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, ConstellationTrait,
+    Event, MultiThreadedConstellation,
+};
+
+/// Node of a tree, which submits `width` children while it has depth left
+struct Node {
+    depth: u32,
+    width: u32,
+}
+
+impl ActivityTrait for Node {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, constellation: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        if self.depth > 0 {
+            for width in 1..=self.width {
+                let child = Node {
+                    depth: self.depth - 1,
+                    width,
+                };
+                constellation
+                    .submit(activity(child), &context(), false, false)
+                    .unwrap();
+            }
+        }
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Run the tree, the order is recorded through the start hook
+///
+/// # Returns
+/// * `Vec<u64>` - The activity ids, in the order they were invoked
+fn run(multithreaded: bool) -> Vec<u64> {
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let recorded = order.clone();
+
+    let mut config = config(1);
+    config.deterministic_scheduling = true;
+    config.hooks.on_activity_start = Some(Arc::new(move |aid: &ActivityIdentifier, _| {
+        recorded.lock().unwrap().push(aid.activity_id);
+    }));
+
+    let mut constellation: Box<dyn ConstellationTrait> = if multithreaded {
+        Box::new(MultiThreadedConstellation::new(config))
+    } else {
+        new_constellation(Mode::SingleThreaded, config)
+    };
+    constellation.activate().unwrap();
+    let root = Node { depth: 3, width: 4 };
+    constellation
+        .submit(activity(root), &context(), false, false)
+        .unwrap();
+    shut_down(constellation.as_mut());
+
+    let order = order.lock().unwrap().clone();
+    order
+}
+
+fn same_order_twice(multithreaded: bool) {
+    let first = run(multithreaded);
+    let second = run(multithreaded);
+    assert!(first.len() > 50);
+    assert_eq!(first, second);
+
+    // The root submits its children at once, they are picked in order
+    let mut children: Vec<u64> = first.iter().cloned().filter(|id| *id <= 4).collect();
+    children.dedup();
+    assert_eq!(children, vec![0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn single_threaded() {
+    same_order_twice(false);
+}
+
+#[test]
+fn multithreaded() {
+    same_order_twice(true);
+}
@@ -0,0 +1,135 @@
+//! Activities finishing with FINISH_AFTER_DRAIN, and the StreamConsumer built
+//! on it
+#[macro_use]
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::util::activities::stream_consumer::StreamConsumer;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, Event,
+};
+
+/// Activity which expects events, but asks to finish after draining from
+/// initialize
+struct Drainer(Arc<AtomicUsize>);
+
+impl ActivityTrait for Drainer {
+    fn cleanup(&mut self, _: &ConstellationHandle) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::FINISH_AFTER_DRAIN
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH_AFTER_DRAIN
+    }
+}
+
+fn drain_from_initialize(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    // Nothing is ever sent to the drainers, they must not stay suspended
+    let cleaned = Arc::new(AtomicUsize::new(0));
+    for _ in 0..10 {
+        constellation
+            .submit(activity(Drainer(cleaned.clone())), &context(), true, true)
+            .unwrap();
+    }
+    shut_down(constellation.as_mut());
+    assert_eq!(cleaned.load(Ordering::SeqCst), 10);
+}
+
+test_both_modes!(drain_from_initialize, 3);
+
+const PRODUCERS: usize = 4;
+const EVENTS_PER_PRODUCER: usize = 2500;
+
+/// Activity which sends its events to a StreamConsumer, the last producer to
+/// finish also sends the stop event
+struct Producer {
+    consumer: ActivityIdentifier,
+    producers_left: Arc<AtomicUsize>,
+}
+
+impl ActivityTrait for Producer {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        for _ in 0..EVENTS_PER_PRODUCER {
+            constellation.send(ping(id, &self.consumer)).unwrap();
+        }
+        if self.producers_left.fetch_sub(1, Ordering::SeqCst) == 1 {
+            constellation
+                .send(StreamConsumer::stop_event(
+                    id.clone(),
+                    self.consumer.clone(),
+                ))
+                .unwrap();
+        }
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+fn stream(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let seen = Arc::new(AtomicUsize::new(0));
+    let counter = seen.clone();
+    let consumer = StreamConsumer::new(move |_, _| {
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+    let consumer_id = constellation
+        .submit(
+            consumer.clone() as Arc<Mutex<dyn ActivityTrait>>,
+            &context(),
+            false,
+            true,
+        )
+        .unwrap();
+
+    let producers_left = Arc::new(AtomicUsize::new(PRODUCERS));
+    for _ in 0..PRODUCERS {
+        let producer = Producer {
+            consumer: consumer_id.clone(),
+            producers_left: producers_left.clone(),
+        };
+        constellation
+            .submit(activity(producer), &context(), true, false)
+            .unwrap();
+    }
+    shut_down(constellation.as_mut());
+
+    let consumer = consumer.lock().unwrap();
+    assert!(consumer.stopped());
+    assert_eq!(consumer.consumed(), PRODUCERS * EVENTS_PER_PRODUCER);
+    assert_eq!(seen.load(Ordering::SeqCst), PRODUCERS * EVENTS_PER_PRODUCER);
+}
+
+test_both_modes!(stream, 4);
@@ -0,0 +1,112 @@
+//! Instances dropped without calling done() shut down their threads
+#[macro_use]
+mod common;
+
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, ConstellationTrait,
+    Event,
+};
+
+/// Longest time a drop may take, the threads are waited for briefly
+const DROP_TIME: Duration = Duration::from_secs(3);
+
+/// Activity which never finishes, counts how often it was released
+struct Stuck(Arc<AtomicUsize>);
+
+impl ActivityTrait for Stuck {
+    fn cleanup(&mut self, _: &ConstellationHandle) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::SUSPEND
+    }
+}
+
+fn mode(threads: i32) -> Mode {
+    if threads == 1 {
+        Mode::SingleThreaded
+    } else {
+        Mode::MultiThreaded
+    }
+}
+
+fn suspended(constellation: &mut dyn ConstellationTrait) -> usize {
+    constellation
+        .dump_state()
+        .threads
+        .iter()
+        .map(|t| t.suspended.len())
+        .sum()
+}
+
+fn drop_without_done(mode: Mode, threads: i32) {
+    let released = Arc::new(AtomicUsize::new(0));
+    let start;
+    {
+        let mut constellation = new_constellation(mode, config(threads));
+        constellation.activate().unwrap();
+        for _ in 0..5 {
+            constellation
+                .submit(activity(Stuck(released.clone())), &context(), true, true)
+                .unwrap();
+        }
+        wait_for(|| suspended(constellation.as_mut()) == 5);
+        start = Instant::now();
+    }
+
+    assert!(
+        start.elapsed() < DROP_TIME,
+        "Drop took {:?}",
+        start.elapsed()
+    );
+    assert_eq!(released.load(Ordering::SeqCst), 5);
+}
+
+test_both_modes!(drop_without_done, 3);
+
+fn drop_while_unwinding(mode: Mode, threads: i32) {
+    let released = Arc::new(AtomicUsize::new(0));
+    let in_unwind = released.clone();
+    let start = Instant::now();
+
+    let result = panic::catch_unwind(move || {
+        let mut constellation = new_constellation(mode, config(threads));
+        constellation.activate().unwrap();
+        constellation
+            .submit(activity(Stuck(in_unwind)), &context(), true, true)
+            .unwrap();
+        wait_for(|| suspended(constellation.as_mut()) == 1);
+        panic!("Application panicked");
+    });
+
+    assert!(result.is_err());
+    assert!(start.elapsed() < TIMEOUT + DROP_TIME);
+    assert_eq!(released.load(Ordering::SeqCst), 1);
+}
+
+test_both_modes!(drop_while_unwinding, 3);
+
+#[test]
+fn drop_before_activate() {
+    for threads in [1, 3].iter() {
+        drop(new_constellation(mode(*threads), config(*threads)));
+    }
+}
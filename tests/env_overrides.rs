@@ -0,0 +1,111 @@
+//! CONSTELLATION_* environment variables overriding the configuration. The
+//! variables are shared by the whole process, so every case runs in one
+//! test, one after the other.
+mod common;
+
+use std::env;
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::constellation_config::{
+    ENV_DEBUG, ENV_LOCAL_STEAL_STRATEGY, ENV_TCP_RANK, ENV_THREADS, ENV_TIME_BETWEEN_STEALS,
+};
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{new_constellation, ConfigError, StealStrategy};
+
+const ALL: [&str; 5] = [
+    ENV_THREADS,
+    ENV_DEBUG,
+    ENV_TIME_BETWEEN_STEALS,
+    ENV_LOCAL_STEAL_STRATEGY,
+    ENV_TCP_RANK,
+];
+
+fn clear() {
+    for key in ALL.iter() {
+        env::remove_var(key);
+    }
+}
+
+fn overrides_fields() {
+    clear();
+    env::set_var(ENV_THREADS, "3");
+    env::set_var(ENV_DEBUG, "0");
+    env::set_var(ENV_TIME_BETWEEN_STEALS, "250");
+    env::set_var(ENV_LOCAL_STEAL_STRATEGY, "smallest");
+    env::set_var(ENV_TCP_RANK, "2");
+
+    let mut config = config(1);
+    config.debug = true;
+    config.apply_env().unwrap();
+
+    assert_eq!(config.number_of_threads, 3);
+    assert!(!config.debug);
+    assert_eq!(config.time_between_steals, Duration::from_micros(250));
+    assert_eq!(config.local_steal_strategy, StealStrategy::SMALLEST);
+    assert_eq!(config.tcp_rank, 2);
+}
+
+fn unset_variables_change_nothing() {
+    clear();
+
+    let mut config = config(2);
+    config.apply_env().unwrap();
+
+    assert_eq!(config.number_of_threads, 2);
+    assert_eq!(config.time_between_steals, Duration::from_micros(100));
+    assert_eq!(config.local_steal_strategy, StealStrategy::BIGGEST);
+}
+
+fn invalid_value_is_rejected() {
+    for (key, value) in vec![
+        (ENV_THREADS, "many"),
+        (ENV_DEBUG, "maybe"),
+        (ENV_TIME_BETWEEN_STEALS, "-5"),
+        (ENV_LOCAL_STEAL_STRATEGY, "LARGEST"),
+        (ENV_TCP_RANK, "one"),
+    ] {
+        clear();
+        // A valid override is not applied when another one is malformed
+        if key != ENV_THREADS {
+            env::set_var(ENV_THREADS, "3");
+        }
+        env::set_var(key, value);
+
+        let mut config = config(2);
+        match config.apply_env() {
+            Err(ConfigError::InvalidValue {
+                key: reported,
+                value: reported_value,
+                ..
+            }) => {
+                assert_eq!(reported, key);
+                assert_eq!(reported_value, value);
+            }
+            r => panic!("Unexpected result for {}={}: {:?}", key, value, r),
+        }
+        assert_eq!(config.number_of_threads, 2);
+    }
+}
+
+fn factory_applies_overrides() {
+    clear();
+    env::set_var(ENV_THREADS, "3");
+
+    let mut constellation = new_constellation(Mode::MultiThreaded, config(2));
+    assert_eq!(constellation.threads(), 3);
+
+    let mut config = config(2);
+    config.use_env_overrides = false;
+    let mut constellation = new_constellation(Mode::MultiThreaded, config);
+    assert_eq!(constellation.threads(), 2);
+    clear();
+}
+
+#[test]
+fn environment_overrides() {
+    overrides_fields();
+    unset_variables_change_nothing();
+    invalid_value_is_rejected();
+    factory_applies_overrides();
+}
@@ -0,0 +1,181 @@
+//! Delivery of the events queued for an activity while it was suspended: one
+//! per call to `process(..)` by default, all at once to `process_batch(..)`
+//! for activities which opt in with `batch_events()`
+#[macro_use]
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, AckHandle, AckStatus, ActivityIdentifier, ActivityTrait,
+    ConstellationHandle, ConstellationTrait, Event,
+};
+
+const EVENTS: usize = 5;
+
+/// Activity which records the number of events of every call, and finishes
+/// once it processed `finish_after` events
+struct Counter {
+    batch: bool,
+    finish_after: usize,
+    processed: usize,
+    calls: Arc<Mutex<Vec<usize>>>,
+}
+
+impl ActivityTrait for Counter {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        if event.is_none() {
+            return State::SUSPEND;
+        }
+        self.calls.lock().unwrap().push(1);
+        self.processed += 1;
+        if self.processed < self.finish_after {
+            State::SUSPEND
+        } else {
+            State::FINISH
+        }
+    }
+
+    fn batch_events(&self) -> bool {
+        self.batch
+    }
+}
+
+/// Activity which opts in and handles a batch of events in one call
+struct Batcher {
+    calls: Arc<Mutex<Vec<usize>>>,
+}
+
+impl ActivityTrait for Batcher {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        self.calls.lock().unwrap().push(event.iter().count());
+        State::FINISH
+    }
+
+    fn batch_events(&self) -> bool {
+        true
+    }
+
+    fn process_batch(
+        &mut self,
+        _: &ConstellationHandle,
+        events: Vec<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        self.calls.lock().unwrap().push(events.len());
+        State::FINISH
+    }
+}
+
+/// Queue EVENTS events for the activity while the instance is paused, so
+/// they are all queued when it is reactivated
+///
+/// # Returns
+/// * `Vec<AckStatus>` - How every event was acknowledged
+fn deliver_queued(
+    constellation: &mut dyn ConstellationTrait,
+    target: &ActivityIdentifier,
+) -> Vec<AckStatus> {
+    constellation.pause().unwrap();
+    assert!(constellation.wait_until_paused(TIMEOUT).unwrap());
+
+    let src = constellation.allocate_external_id();
+    let acks: Vec<AckHandle> = (0..EVENTS)
+        .map(|_| constellation.send_with_ack(ping(&src, target)).unwrap())
+        .collect();
+    constellation.resume().unwrap();
+
+    acks.iter().map(|ack| ack.wait(TIMEOUT)).collect()
+}
+
+fn counter(
+    constellation: &mut dyn ConstellationTrait,
+    batch: bool,
+    finish_after: usize,
+) -> (ActivityIdentifier, Arc<Mutex<Vec<usize>>>) {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let counter = Counter {
+        batch,
+        finish_after,
+        processed: 0,
+        calls: calls.clone(),
+    };
+    let id = constellation
+        .submit(activity(counter), &context(), false, true)
+        .unwrap();
+    (id, calls)
+}
+
+fn one_event_per_call_by_default(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let (id, calls) = counter(constellation.as_mut(), false, EVENTS);
+    let acks = deliver_queued(constellation.as_mut(), &id);
+    assert_eq!(acks, vec![AckStatus::Consumed; EVENTS]);
+    assert_eq!(*calls.lock().unwrap(), vec![1; EVENTS]);
+
+    // The events left when it finishes are not handed to it, and reported
+    // as dead-lettered
+    let (id, calls) = counter(constellation.as_mut(), false, 2);
+    let acks = deliver_queued(constellation.as_mut(), &id);
+    assert_eq!(acks[..2], [AckStatus::Consumed, AckStatus::Consumed]);
+    assert!(acks[2..].iter().all(|ack| *ack == AckStatus::DeadLettered));
+    assert_eq!(*calls.lock().unwrap(), vec![1, 1]);
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(one_event_per_call_by_default, 2);
+
+fn batch_when_opted_in(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let batcher = Batcher {
+        calls: calls.clone(),
+    };
+    let id = constellation
+        .submit(activity(batcher), &context(), false, true)
+        .unwrap();
+    let acks = deliver_queued(constellation.as_mut(), &id);
+    assert_eq!(acks, vec![AckStatus::Consumed; EVENTS]);
+    assert_eq!(*calls.lock().unwrap(), vec![EVENTS]);
+
+    // The default process_batch processes every event, also after one of
+    // them finished the activity
+    let (id, calls) = counter(constellation.as_mut(), true, 2);
+    let acks = deliver_queued(constellation.as_mut(), &id);
+    assert_eq!(acks, vec![AckStatus::Consumed; EVENTS]);
+    assert_eq!(*calls.lock().unwrap(), vec![1; EVENTS]);
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(batch_when_opted_in, 2);
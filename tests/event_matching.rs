@@ -0,0 +1,60 @@
+//! Events are matched to their suspended destination when they arrive, so
+//! delivery does not wait for the periodic scan of all suspended activities
+#[macro_use]
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{new_constellation, AckStatus, ActivityIdentifier, ConstellationTrait};
+
+const SUSPENDED: usize = 2000;
+const TRICKLE: usize = 20;
+
+/// Time between two scans of all suspended activities by an executor thread
+const SCAN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Number of suspended activities on all threads
+fn suspended(constellation: &mut dyn ConstellationTrait) -> usize {
+    let snapshot = constellation.dump_state();
+    snapshot.threads.iter().map(|t| t.suspended.len()).sum()
+}
+
+fn trickle_of_events(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let waiters: Vec<ActivityIdentifier> = (0..SUSPENDED)
+        .map(|_| {
+            constellation
+                .submit(activity(Waiter), &context(), true, true)
+                .unwrap()
+        })
+        .collect();
+    wait_for(|| suspended(constellation.as_mut()) == SUSPENDED);
+
+    // One event at a time, each one waiting for the periodic scan would take
+    // half a SCAN_INTERVAL on average
+    let src = constellation.allocate_external_id();
+    let start = Instant::now();
+    for waiter in waiters.iter().step_by(SUSPENDED / TRICKLE) {
+        let ack = constellation.send_with_ack(ping(&src, waiter)).unwrap();
+        assert_eq!(ack.wait(TIMEOUT), AckStatus::Consumed);
+    }
+    let elapsed = start.elapsed();
+    assert!(
+        elapsed < SCAN_INTERVAL * TRICKLE as u32 / 4,
+        "{} events took {:?}",
+        TRICKLE,
+        elapsed
+    );
+    wait_for(|| suspended(constellation.as_mut()) == SUSPENDED - TRICKLE);
+
+    for waiter in &waiters {
+        constellation.send(ping(&src, waiter)).ok();
+    }
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(trickle_of_events, 2);
@@ -0,0 +1,136 @@
+//! Events queued for a suspended activity are delivered in the order they
+//! were sent, and nothing is left queued for it once they are delivered
+#[macro_use]
+mod common;
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, ConstellationTrait,
+    Event, PayloadTrait, PayloadTraitClone,
+};
+
+const EVENTS: u64 = 20;
+
+/// Payload numbered in the order it is sent
+#[derive(Debug, Clone)]
+struct Seq(u64);
+
+impl PayloadTrait for Seq {}
+
+impl PayloadTraitClone for Seq {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for Seq {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "seq {}", self.0)
+    }
+}
+
+/// Activity which records the numbers it receives, in one call per event or
+/// in batches, and finishes on any other payload
+struct Recorder {
+    batch: bool,
+    received: Arc<Mutex<Vec<u64>>>,
+}
+
+impl Recorder {
+    fn record(&mut self, event: Box<Event>) -> State {
+        match event.payload_as::<Seq>() {
+            Some(seq) => {
+                self.received.lock().unwrap().push(seq.0);
+                State::SUSPEND
+            }
+            None => State::FINISH,
+        }
+    }
+}
+
+impl ActivityTrait for Recorder {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        event.map_or(State::SUSPEND, |event| self.record(event))
+    }
+
+    fn batch_events(&self) -> bool {
+        self.batch
+    }
+
+    fn process_batch(
+        &mut self,
+        _: &ConstellationHandle,
+        events: Vec<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        let mut state = State::SUSPEND;
+        for event in events {
+            state = self.record(event);
+        }
+        state
+    }
+}
+
+/// Whether any events are queued for the activity
+fn has_queued_events(constellation: &mut dyn ConstellationTrait, aid: &ActivityIdentifier) -> bool {
+    let destination = aid.to_string();
+    let snapshot = constellation.dump_state();
+    snapshot
+        .threads
+        .iter()
+        .flat_map(|t| t.events.iter())
+        .chain(snapshot.orphaned_events.iter())
+        .any(|queued| queued.destination == destination)
+}
+
+fn delivered_in_order(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    for batch in [false, true].iter() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder {
+            batch: *batch,
+            received: received.clone(),
+        };
+        let aid = constellation
+            .submit(activity(recorder), &context(), false, true)
+            .unwrap();
+
+        // Queue all events before the activity gets to run again
+        constellation.pause().unwrap();
+        assert!(constellation.wait_until_paused(TIMEOUT).unwrap());
+        let src = constellation.allocate_external_id();
+        for seq in 0..EVENTS {
+            let event = Event::new(Box::new(Seq(seq)), src.clone(), aid.clone());
+            constellation.send(event).unwrap();
+        }
+        constellation.resume().unwrap();
+
+        wait_for(|| received.lock().unwrap().len() == EVENTS as usize);
+        assert_eq!(*received.lock().unwrap(), (0..EVENTS).collect::<Vec<_>>());
+        assert!(!has_queued_events(constellation.as_mut(), &aid));
+
+        constellation.send(ping(&src, &aid)).unwrap();
+    }
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(delivered_in_order, 2);
@@ -0,0 +1,219 @@
+//! Constellation instances running under injected faults, see `FaultInjector`
+#![cfg(feature = "fault-injection")]
+#[macro_use]
+mod common;
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::util::patterns::{map_reduce, MapFn, ReduceFn};
+use constellation_rust::{
+    new_constellation, ActivityError, ActivityIdentifier, ActivityTrait, CompletionReason,
+    ConstellationHandle, Event, FaultInjector, FaultRule, FaultTarget, PayloadTrait,
+    PayloadTraitClone,
+};
+
+const INPUTS: u64 = 100;
+const ROUNDS: usize = 5;
+
+/// Number mapped and summed by the map reduce
+#[derive(Debug, Clone)]
+struct Sum(u64);
+
+impl PayloadTrait for Sum {}
+
+impl PayloadTraitClone for Sum {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for Sum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn value(payload: &dyn PayloadTrait) -> u64 {
+    payload.as_any().downcast_ref::<Sum>().unwrap().0
+}
+
+fn reducer_completes_under_delayed_events(mode: Mode, threads: i32) {
+    let mut injector = FaultInjector::new(42);
+    injector
+        .add_rule(FaultRule::delay_events(
+            FaultTarget::PayloadType("Sum".to_string()),
+            0.1,
+            Duration::from_millis(5),
+        ))
+        .unwrap();
+    let injector = Arc::new(injector);
+
+    let mut config = config(threads);
+    config.fault_injector = Some(injector.clone());
+    let mut constellation = new_constellation(mode, config);
+    constellation.activate().unwrap();
+
+    let map: MapFn = Arc::new(|input| Box::new(Sum(2 * value(&*input))));
+    let reduce: ReduceFn = Arc::new(|a, b| Box::new(Sum(value(&*a) + value(&*b))));
+    for _ in 0..ROUNDS {
+        let inputs: Vec<Box<dyn PayloadTrait>> = (0..INPUTS)
+            .map(|i| Box::new(Sum(i)) as Box<dyn PayloadTrait>)
+            .collect();
+        let result = map_reduce(
+            &mut *constellation,
+            inputs,
+            map.clone(),
+            reduce.clone(),
+            &context(),
+        )
+        .unwrap();
+        assert_eq!(value(&*result), INPUTS * (INPUTS - 1));
+    }
+
+    assert!(injector.counts().delayed_events > 0);
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(reducer_completes_under_delayed_events, 4);
+
+/// Activity which records the errors it is told about and why it completed
+struct Recorder {
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+impl ActivityTrait for Recorder {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+
+    fn on_error(&mut self, _: &ConstellationHandle, error: &ActivityError, _: &ActivityIdentifier) {
+        self.log.lock().unwrap().push(format!("error: {}", error));
+    }
+
+    fn on_complete(&mut self, reason: CompletionReason) {
+        self.log
+            .lock()
+            .unwrap()
+            .push(format!("complete: {:?}", reason));
+    }
+}
+
+fn injected_panic_retires_the_activity(mode: Mode, threads: i32) {
+    let mut injector = FaultInjector::new(7);
+    injector
+        .add_rule(FaultRule::panic_once(FaultTarget::Any))
+        .unwrap();
+    let injector = Arc::new(injector);
+
+    let mut config = config(threads);
+    config.fault_injector = Some(injector.clone());
+    let mut constellation = new_constellation(mode, config);
+    constellation.activate().unwrap();
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    for _ in 0..2 {
+        let recorder = Recorder { log: log.clone() };
+        constellation
+            .submit(activity(recorder), &context(), true, false)
+            .unwrap();
+    }
+
+    // The executor thread survives the panic and runs the other activity
+    wait_for(|| log.lock().unwrap().len() == 3);
+    shut_down(constellation.as_mut());
+
+    let log = log.lock().unwrap();
+    assert_eq!(injector.counts().panics, 1);
+    let panicked = |l: &&String| l.starts_with("error: Activity panicked: ");
+    assert!(log
+        .iter()
+        .filter(panicked)
+        .all(|l| l.contains("fault injector")));
+    assert_eq!(log.iter().filter(panicked).count(), 1, "{:?}", log);
+    assert!(
+        log.iter().any(|l| l.starts_with("complete: Failed(")),
+        "{:?}",
+        log
+    );
+    assert!(log.contains(&"complete: Finished".to_string()), "{:?}", log);
+}
+
+test_both_modes!(injected_panic_retires_the_activity, 2);
+
+const MIGRATIONS: u32 = 3;
+
+fn migrations_counted_once_per_move(mode: Mode, threads: i32) {
+    let mut injector = FaultInjector::new(3);
+    injector
+        .add_rule(FaultRule::migrate(FaultTarget::Any, MIGRATIONS))
+        .unwrap();
+    let injector = Arc::new(injector);
+
+    let mut config = config(threads);
+    config.fault_injector = Some(injector.clone());
+    let mut constellation = new_constellation(mode, config);
+    constellation.activate().unwrap();
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    for _ in 0..INPUTS {
+        let recorder = Recorder { log: log.clone() };
+        constellation
+            .submit(activity(recorder), &context(), true, false)
+            .unwrap();
+    }
+    shut_down(constellation.as_mut());
+
+    // Every activity stops migrating after exactly MIGRATIONS moves, and
+    // runs once. Single threaded instances have nowhere to migrate to.
+    let expected = match mode {
+        Mode::SingleThreaded => 0,
+        _ => INPUTS as usize * MIGRATIONS as usize,
+    };
+    assert_eq!(injector.counts().migrations, expected);
+    let log = log.lock().unwrap();
+    assert_eq!(log.len(), INPUTS as usize, "{:?}", log);
+    assert!(log.iter().all(|l| l == "complete: Finished"), "{:?}", log);
+}
+
+test_both_modes!(migrations_counted_once_per_move, 3);
+
+fn events_follow_migrations(mode: Mode, threads: i32) {
+    let mut injector = FaultInjector::new(5);
+    injector
+        .add_rule(FaultRule::migrate(FaultTarget::Any, MIGRATIONS))
+        .unwrap();
+
+    let mut config = config(threads);
+    config.fault_injector = Some(Arc::new(injector));
+    let mut constellation = new_constellation(mode, config);
+    constellation.activate().unwrap();
+
+    // The events are queued on the thread of the activity before it is
+    // picked up and migrates
+    let src = constellation.allocate_external_id();
+    for _ in 0..INPUTS {
+        let waiter = constellation
+            .submit(activity(Waiter), &context(), true, true)
+            .unwrap();
+        constellation.send(ping(&src, &waiter)).unwrap();
+    }
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(events_follow_migrations, 3);
@@ -0,0 +1,84 @@
+//! Events sent to activities while they finish are dropped, none of them are
+//! left behind in the queues
+#[macro_use]
+mod common;
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, Event, SendError,
+};
+
+const ROUNDS: usize = 10;
+const TARGETS: usize = 8;
+const SENDERS: usize = 3;
+const EVENTS: usize = 200;
+
+fn sent_or_finished(result: Result<(), SendError>) {
+    match result {
+        Ok(()) | Err(SendError::DestinationFinished(_)) => {}
+        Err(e) => panic!("Could not send: {}", e),
+    }
+}
+
+/// Activity which sends events to a target, which finishes on the first
+struct Sender(ActivityIdentifier);
+
+impl ActivityTrait for Sender {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        for _ in 0..EVENTS {
+            sent_or_finished(constellation.send(ping(id, &self.0)));
+        }
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+fn events_for_finished(mode: Mode, threads: i32) {
+    for _ in 0..ROUNDS {
+        events_for_finished_round(mode, threads);
+    }
+}
+
+fn events_for_finished_round(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let targets: Vec<ActivityIdentifier> = (0..TARGETS)
+        .map(|_| {
+            constellation
+                .submit(activity(Waiter), &context(), false, true)
+                .unwrap()
+        })
+        .collect();
+    for target in &targets {
+        for _ in 0..SENDERS {
+            constellation
+                .submit(activity(Sender(target.clone())), &context(), true, false)
+                .unwrap();
+        }
+        for _ in 0..EVENTS {
+            sent_or_finished(constellation.send(ping(target, target)));
+        }
+    }
+
+    shut_down(constellation.as_mut());
+    assert!(constellation.dump_state().is_empty());
+}
+
+test_both_modes!(events_for_finished, 4);
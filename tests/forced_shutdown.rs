@@ -0,0 +1,95 @@
+//! With a `shutdown_timeout`, `done()` cancels the activities which are
+//! still suspended when it expires, and releases what they hold
+#[macro_use]
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationError, ConstellationHandle,
+    Event,
+};
+
+/// Resource which counts how often it is released
+struct Resource(Arc<AtomicUsize>);
+
+impl Drop for Resource {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Activity holding a resource, which waits for an event that never comes
+struct Holder {
+    _resource: Resource,
+    cancelled: Arc<AtomicUsize>,
+}
+
+impl ActivityTrait for Holder {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn cancelled(&mut self, _: &ConstellationHandle) {
+        self.cancelled.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::SUSPEND
+    }
+}
+
+fn suspended_activities_released(mode: Mode, threads: i32) {
+    let mut config = config(threads);
+    config.shutdown_timeout = Some(Duration::from_millis(100));
+    let mut constellation = new_constellation(mode, config);
+    constellation.activate().unwrap();
+
+    let released = Arc::new(AtomicUsize::new(0));
+    let cancelled = Arc::new(AtomicUsize::new(0));
+    for _ in 0..3 {
+        let holder = Holder {
+            _resource: Resource(released.clone()),
+            cancelled: cancelled.clone(),
+        };
+        constellation
+            .submit(activity(holder), &context(), false, true)
+            .unwrap();
+    }
+    constellation
+        .submit(activity(Quick), &context(), false, false)
+        .unwrap();
+    wait_for(|| {
+        let snapshot = constellation.dump_state();
+        snapshot
+            .threads
+            .iter()
+            .map(|t| t.suspended.len())
+            .sum::<usize>()
+            == 3
+    });
+
+    match constellation.done() {
+        Err(ConstellationError::ForcedShutdown(count)) => assert_eq!(count, 3),
+        result => panic!("Unexpected result of done(): {:?}", result),
+    }
+    assert_eq!(cancelled.load(Ordering::SeqCst), 3);
+    assert_eq!(released.load(Ordering::SeqCst), 3);
+
+    // It is shut down now
+    assert_eq!(constellation.done(), Ok(true));
+}
+
+test_both_modes!(suspended_activities_released, 2);
@@ -0,0 +1,55 @@
+//! Groups of activities submitted with `submit_group(..)`, waited for and
+//! cancelled through their GroupHandle
+#[macro_use]
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{new_constellation, ActivityTrait, SubmitOptions};
+
+fn quick(count: usize) -> Vec<Arc<Mutex<dyn ActivityTrait>>> {
+    (0..count).map(|_| activity(Quick)).collect()
+}
+
+fn wait_for_all(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let group = constellation
+        .submit_group(quick(20), &context(), SubmitOptions::default())
+        .unwrap();
+    assert_eq!(group.wait(TIMEOUT), Ok(()));
+    assert_eq!(group.remaining(), 0);
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(wait_for_all, 3);
+
+fn partial_completion_and_cancel(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    // The waiters suspend until they get an event, which never comes
+    let mut activities = quick(4);
+    activities.push(activity(Waiter));
+    activities.push(activity(Waiter));
+    let group = constellation
+        .submit_group(activities, &context(), SubmitOptions::default())
+        .unwrap();
+
+    wait_for(|| group.remaining() == 2);
+    assert!(group.wait(Duration::from_millis(50)).is_err());
+    assert_eq!(group.remaining(), 2);
+
+    group.cancel();
+    assert!(group.wait(TIMEOUT).is_err());
+
+    // Nothing of the group is left behind
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(partial_completion_and_cancel, 3);
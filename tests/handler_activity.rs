@@ -0,0 +1,118 @@
+//! A counter actor built on HandlerActivity, which finishes after a number of
+//! events and forwards their total
+#[macro_use]
+mod common;
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityTrait, Event, HandlerActivity, HandlerControl, PayloadTrait,
+    PayloadTraitClone, SingleEventCollector, SubmitOptions,
+};
+
+const EVENTS: u64 = 10;
+
+/// Number added up by the counter
+#[derive(Debug, Clone)]
+struct Value(u64);
+
+impl PayloadTrait for Value {}
+
+impl PayloadTraitClone for Value {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// State of the counter actor
+#[derive(Debug, Default, PartialEq)]
+struct Count {
+    events: u64,
+    total: u64,
+}
+
+fn counter_forwards_total(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let sec = SingleEventCollector::new();
+    let sink = constellation
+        .submit(
+            sec.clone() as Arc<Mutex<dyn ActivityTrait>>,
+            &context(),
+            false,
+            true,
+        )
+        .unwrap();
+
+    let forward_to = sink.clone();
+    let counter = HandlerActivity::new(Count::default(), move |count, event: Box<Event>, _| {
+        count.events += 1;
+        count.total += event.payload_as::<Value>().unwrap().0;
+        if count.events < EVENTS {
+            HandlerControl::Continue
+        } else {
+            HandlerControl::FinishWith(Box::new(Value(count.total)), forward_to.clone())
+        }
+    });
+    let options = SubmitOptions {
+        expects_events: true,
+        ..Default::default()
+    };
+    let aid = constellation
+        .submit_with(counter.clone(), &context(), options)
+        .unwrap();
+
+    let src = constellation.allocate_external_id();
+    for value in 1..=EVENTS {
+        let event = Event::new(Box::new(Value(value)), src.clone(), aid.clone());
+        constellation.send(event).unwrap();
+    }
+
+    let event = SingleEventCollector::get_event(sec, Duration::from_millis(1));
+    assert_eq!(
+        event.payload_as::<Value>().unwrap().0,
+        EVENTS * (EVENTS + 1) / 2
+    );
+    assert_eq!(event.get_src(), aid);
+    shut_down(constellation.as_mut());
+
+    // The state is kept after it finished
+    let expected = Count {
+        events: EVENTS,
+        total: EVENTS * (EVENTS + 1) / 2,
+    };
+    assert_eq!(*counter.lock().unwrap().state(), expected);
+}
+
+test_both_modes!(counter_forwards_total, 2);
+
+fn finish_without_result(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let handler = HandlerActivity::new(0, |calls: &mut u32, _, _| {
+        *calls += 1;
+        HandlerControl::Finish
+    });
+    let aid = constellation
+        .submit(handler.clone(), &context(), false, true)
+        .unwrap();
+    let src = constellation.allocate_external_id();
+    constellation.send(ping(&src, &aid)).unwrap();
+    shut_down(constellation.as_mut());
+
+    assert_eq!(*handler.lock().unwrap().state(), 1);
+}
+
+test_both_modes!(finish_without_result, 2);
@@ -0,0 +1,84 @@
+//! Liveness of the other nodes, tracked through heartbeats. A node which is
+//! created but never activated stays silent.
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use common::*;
+use constellation_rust::{
+    Communication, ConstellationTrait, HealthTable, LocalComm, MultiThreadedConstellation,
+};
+
+const INTERVAL: Duration = Duration::from_millis(100);
+
+#[test]
+fn missed_heartbeats_at_given_moments() {
+    let start = Instant::now();
+    let mut table = HealthTable::new(vec![1, 2], INTERVAL, 3, start);
+
+    // Node 1 keeps sending, node 2 is silent
+    for i in 1..3 {
+        table.record_at(1, start + INTERVAL * i);
+        assert_eq!(table.check_at(start + INTERVAL * i), vec![]);
+    }
+    let now = start + INTERVAL * 3;
+    table.record_at(1, now);
+    assert_eq!(table.check_at(now), vec![(2, 3)]);
+
+    let health = table.snapshot_at(now);
+    assert!(health[0].1.responsive);
+    assert_eq!(health[0].1.last_seen, Some(Duration::from_millis(0)));
+    assert!(!health[1].1.responsive);
+    assert_eq!((health[1].1.last_seen, health[1].1.missed), (None, 3));
+
+    // Reported once, until it responds again
+    assert_eq!(table.check_at(now), vec![]);
+    assert!(table.record_at(2, now + INTERVAL));
+    assert!(table.snapshot_at(now + INTERVAL)[1].1.responsive);
+
+    // Then both go silent
+    let later = now + INTERVAL * 5;
+    assert_eq!(table.check_at(later), vec![(1, 5), (2, 4)]);
+}
+
+#[test]
+fn silent_peer_is_reported() {
+    let mut config = config(1);
+    config.number_of_nodes = 3;
+    config.heartbeat_interval = Duration::from_millis(10);
+    config.heartbeat_miss_threshold = 3;
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    let callback = reported.clone();
+    config.on_node_unresponsive = Some(Arc::new(move |rank, missed| {
+        callback.lock().unwrap().push((rank, missed));
+    }));
+
+    let mut comms: Vec<Arc<dyn Communication>> = LocalComm::group(3)
+        .into_iter()
+        .map(|comm| Arc::new(comm) as Arc<dyn Communication>)
+        .collect();
+    let _silent = comms.pop().unwrap();
+    let mut worker =
+        MultiThreadedConstellation::with_communication(config.clone(), comms.pop().unwrap());
+    let mut master = MultiThreadedConstellation::with_communication(config, comms.pop().unwrap());
+    assert!(master.activate().unwrap());
+    assert!(!worker.activate().unwrap());
+
+    wait_for(|| !reported.lock().unwrap().is_empty());
+    let reported = reported.lock().unwrap().clone();
+    assert_eq!(reported[0], (2, 3));
+    assert!(
+        reported.iter().all(|(rank, _)| *rank == 2),
+        "{:?}",
+        reported
+    );
+
+    let health = master.node_health();
+    assert_eq!(health.len(), 2);
+    assert_eq!(health[0].0, 1);
+    assert!(health[0].1.responsive);
+    assert_eq!(health[1].0, 2);
+    assert!(!health[1].1.responsive);
+    assert_eq!(health[1].1.last_seen, None);
+}
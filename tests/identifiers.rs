@@ -0,0 +1,97 @@
+//! Identifiers of executor threads are derived from the identifier of their
+//! instance and hand out activity identifiers from its counter
+#[macro_use]
+mod common;
+
+use std::collections::HashSet;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::implementation::constellation_identifier::ConstellationIdentifier;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ConstellationSpawn, ConstellationTrait, LocalComm,
+    SingleThreadConstellation,
+};
+
+#[test]
+fn child_identifier_shares_parent_state() {
+    let comms = LocalComm::group_on_nodes(vec!["a".to_string(), "b".to_string()]);
+    let mut parent = ConstellationIdentifier::new(&comms[1], 42, Arc::new(Mutex::new(7)), -1);
+    parent.first_activity_id = 7;
+
+    let child = parent.generate_child_identifier(3);
+    assert_eq!(child.thread_id, 3);
+    assert_eq!(child.constellation_id, 42);
+    assert_eq!(child.node_info.node_name, "b");
+    assert_eq!(child.node_info.node_id, parent.node_info.node_id);
+    assert_eq!(child.group.len(), 2);
+    assert_eq!(child.first_activity_id, 7);
+    assert!(Arc::ptr_eq(
+        &child.activity_counter,
+        &parent.activity_counter
+    ));
+
+    // Identifiers generated by the parent and the child never collide
+    let a = ActivityIdentifier::new(Arc::new(Mutex::new(child.clone())));
+    let b = ActivityIdentifier::new(Arc::new(Mutex::new(parent.clone())));
+    assert_ne!(a.activity_id, b.activity_id);
+    assert!(parent.generated(&a));
+}
+
+fn threads_share_counter(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+    let root = constellation.identifier();
+
+    let (sender, receiver) = mpsc::channel();
+    let sender = Arc::new(Mutex::new(sender));
+    for _ in 0..30 {
+        let sender = sender.clone();
+        constellation
+            .spawn(&context(), move |handle, id| {
+                let message = (handle.identifier(), id.clone());
+                sender.lock().unwrap().send(message).unwrap();
+            })
+            .unwrap();
+    }
+
+    let mut ids = HashSet::new();
+    for _ in 0..30 {
+        let (identifier, aid) = receiver.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(identifier.constellation_id, root.constellation_id);
+        assert_eq!(identifier.node_info.node_id, root.node_info.node_id);
+        assert!(Arc::ptr_eq(
+            &identifier.activity_counter,
+            &root.activity_counter
+        ));
+        assert!(ids.insert(aid.activity_id));
+    }
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(threads_share_counter, 3);
+
+#[test]
+fn restart_keeps_counter() {
+    let mut constellation = SingleThreadConstellation::new(config(1));
+    constellation.activate().unwrap();
+    let before = constellation
+        .submit(activity(Quick), &context(), true, false)
+        .unwrap();
+    shut_down(&mut constellation);
+
+    constellation.activate().unwrap();
+    let identifier = constellation.identifier();
+    let after = constellation
+        .submit(activity(Quick), &context(), true, false)
+        .unwrap();
+    shut_down(&mut constellation);
+
+    assert_eq!(identifier.constellation_id, before.constellation_id);
+    assert!(after.activity_id > before.activity_id);
+    assert!(identifier.from_earlier_run(&before));
+    assert!(!identifier.from_earlier_run(&after));
+}
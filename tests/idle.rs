@@ -0,0 +1,117 @@
+//! `wait_until_idle(..)` blocks until all work is done, including work that
+//! is being executed, and the instance can be used again afterwards
+#[macro_use]
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationError, ConstellationHandle,
+    Event,
+};
+
+const DEPTH: u32 = 3;
+const FAN_OUT: usize = 3;
+
+/// Number of nodes in a tree of the given depth
+fn tree_size(depth: u32) -> usize {
+    (0..=depth).map(|level| FAN_OUT.pow(level)).sum()
+}
+
+/// Activity which submits FAN_OUT children until `depth` reaches 0, every
+/// node of the tree sleeps for a while and counts itself once it is done
+struct Node {
+    depth: u32,
+    done: Arc<AtomicUsize>,
+}
+
+impl ActivityTrait for Node {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, constellation: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        if self.depth > 0 {
+            for _ in 0..FAN_OUT {
+                let child = Node {
+                    depth: self.depth - 1,
+                    done: self.done.clone(),
+                };
+                constellation
+                    .submit(activity(child), &context(), true, false)
+                    .unwrap();
+            }
+        }
+        thread::sleep(Duration::from_millis(2));
+        self.done.fetch_add(1, Ordering::SeqCst);
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+fn idle_between_trees(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let done = Arc::new(AtomicUsize::new(0));
+    for tree in 1..=2 {
+        let root = Node {
+            depth: DEPTH,
+            done: done.clone(),
+        };
+        constellation
+            .submit(activity(root), &context(), true, false)
+            .unwrap();
+        constellation.wait_until_idle(TIMEOUT).unwrap();
+        assert_eq!(done.load(Ordering::SeqCst), tree * tree_size(DEPTH));
+    }
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(idle_between_trees, 3);
+
+fn running_activity_is_not_idle(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    // The queues are empty while the sleeper runs
+    let done = Arc::new(AtomicUsize::new(0));
+    let sleeper = Node {
+        depth: 0,
+        done: done.clone(),
+    };
+    constellation
+        .submit(activity(sleeper), &context(), true, false)
+        .unwrap();
+    constellation.wait_until_idle(TIMEOUT).unwrap();
+    assert_eq!(done.load(Ordering::SeqCst), 1);
+
+    // A suspended activity is work left
+    let waiter = constellation
+        .submit(activity(Waiter), &context(), true, true)
+        .unwrap();
+    match constellation.wait_until_idle(Duration::from_millis(50)) {
+        Err(ConstellationError::WorkLeft(_)) => {}
+        result => panic!("Unexpected result: {:?}", result),
+    }
+    let src = constellation.allocate_external_id();
+    constellation.send(ping(&src, &waiter)).unwrap();
+    constellation.wait_until_idle(TIMEOUT).unwrap();
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(running_activity_is_not_idle, 2);
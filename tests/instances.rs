@@ -0,0 +1,114 @@
+//! Several constellation instances in one process
+mod common;
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::implementation::constellation_identifier::ConstellationIdentifier;
+use constellation_rust::{
+    new_constellation, ConstellationError, ConstellationTrait, LocalComm, SimulatedCluster,
+    SingleThreadConstellation,
+};
+
+#[test]
+fn recreate_single_threaded() {
+    let mut used = Vec::new();
+
+    for _ in 0..2 {
+        let mut constellation = SingleThreadConstellation::new(config(1));
+        assert!(constellation.activate().unwrap());
+        let aid = constellation
+            .submit(activity(Quick), &context(), true, false)
+            .unwrap();
+        used.push((constellation.identifier().constellation_id, aid));
+        shut_down(&mut constellation);
+    }
+
+    assert_ne!(used[0].0, used[1].0);
+    assert_ne!(used[0].1, used[1].1);
+}
+
+#[test]
+fn instances_side_by_side() {
+    let mut first = SingleThreadConstellation::new(config(1));
+    let mut second = SingleThreadConstellation::new(config(1));
+    first.activate().unwrap();
+    second.activate().unwrap();
+
+    let a = first
+        .submit(activity(Waiter), &context(), true, true)
+        .unwrap();
+    let b = second
+        .submit(activity(Waiter), &context(), true, true)
+        .unwrap();
+    assert_ne!(a, b);
+
+    first.send(ping(&a, &a)).unwrap();
+    second.send(ping(&b, &b)).unwrap();
+    shut_down(&mut first);
+    shut_down(&mut second);
+}
+
+#[test]
+fn instances_get_their_own_constellation_id() {
+    let mut ids = HashSet::new();
+    for (mode, threads) in vec![
+        (Mode::SingleThreaded, 1),
+        (Mode::MultiThreaded, 2),
+        (Mode::MultiThreaded, 2),
+    ] {
+        let mut constellation = new_constellation(mode, config(threads));
+        constellation.activate().unwrap();
+        let constellation_id = constellation.identifier().constellation_id;
+        assert!(constellation_id > 0);
+
+        let aid = constellation
+            .submit(activity(Quick), &context(), true, false)
+            .unwrap();
+        assert_eq!(aid.constellation_id, constellation_id);
+        assert!(ids.insert(constellation_id));
+        shut_down(constellation.as_mut());
+    }
+}
+
+#[test]
+fn nodes_use_constellation_id_of_master() {
+    for master_rank in vec![0, 2] {
+        let mut config = config(1);
+        config.master_rank = master_rank;
+        let mut cluster = SimulatedCluster::new(3, 1, config);
+        cluster.activate().unwrap();
+
+        let constellation_id = cluster.as_master().identifier().constellation_id;
+        for rank in 0..3 {
+            let node = cluster.node(rank).unwrap();
+            assert_eq!(node.identifier().constellation_id, constellation_id);
+        }
+        assert_eq!(cluster.done(), Ok(true));
+    }
+}
+
+#[test]
+fn agreeing_times_out_without_master() {
+    let comms = LocalComm::group(2);
+    let timeout = Duration::from_millis(50);
+
+    let start = Instant::now();
+    assert_eq!(
+        ConstellationIdentifier::agree_constellation_id(&comms[1], 0, 7, timeout),
+        Err(ConstellationError::Failed)
+    );
+    assert!(start.elapsed() >= timeout);
+
+    // The identifier of the master is taken over once it arrives
+    assert_eq!(
+        ConstellationIdentifier::agree_constellation_id(&comms[0], 0, 3, timeout),
+        Ok(3)
+    );
+    assert_eq!(
+        ConstellationIdentifier::agree_constellation_id(&comms[1], 0, 7, timeout),
+        Ok(3)
+    );
+}
@@ -0,0 +1,161 @@
+//! Event interceptors registered with `add_event_interceptor(..)`, which see
+//! every event before it is routed and continue, drop or redirect it
+#[macro_use]
+mod common;
+
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, AckStatus, ActivityIdentifier, ConstellationConfiguration,
+    ConstellationTrait, Event, InterceptDecision, MultiThreadedConstellation, PayloadTrait,
+    PayloadTraitClone,
+};
+
+/// Payload telling the interceptor what to decide
+#[derive(Debug, Clone, PartialEq)]
+enum Decide {
+    Continue,
+    Drop,
+    Redirect,
+}
+
+impl PayloadTrait for Decide {}
+
+impl PayloadTraitClone for Decide {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for Decide {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Configuration with an auditing interceptor counting all events, followed
+/// by an interceptor deciding as the payload tells it to. Events are
+/// redirected to the canary, once it is set.
+fn intercepted_config(
+    threads: i32,
+    seen: Arc<AtomicUsize>,
+    canary: Arc<Mutex<Option<ActivityIdentifier>>>,
+) -> Box<ConstellationConfiguration> {
+    let mut config = config(threads);
+    config.add_event_interceptor(Box::new(move |_| {
+        seen.fetch_add(1, Ordering::SeqCst);
+        InterceptDecision::Continue
+    }));
+    config.add_event_interceptor(Box::new(move |e| match e.payload_as::<Decide>() {
+        Some(Decide::Drop) => InterceptDecision::Drop,
+        Some(Decide::Redirect) => match canary.lock().unwrap().clone() {
+            Some(canary) => InterceptDecision::Redirect(canary),
+            None => InterceptDecision::Continue,
+        },
+        _ => InterceptDecision::Continue,
+    }));
+    config
+}
+
+fn event(decide: Decide, src: &ActivityIdentifier, dst: &ActivityIdentifier) -> Event {
+    Event::new(Box::new(decide), src.clone(), dst.clone())
+}
+
+fn all_decisions(mode: Mode, threads: i32) {
+    let seen = Arc::new(AtomicUsize::new(0));
+    let canary = Arc::new(Mutex::new(None));
+    let config = intercepted_config(threads, seen.clone(), canary.clone());
+    let mut constellation = new_constellation(mode, config);
+    constellation.activate().unwrap();
+
+    let src = constellation.allocate_external_id();
+    let dst = constellation.allocate_external_id();
+    let events = constellation.subscribe(&dst);
+    let redirected = constellation.allocate_external_id();
+    let canary_events = constellation.subscribe(&redirected);
+    *canary.lock().unwrap() = Some(redirected.clone());
+
+    constellation.send(event(Decide::Drop, &src, &dst)).unwrap();
+    constellation
+        .send(event(Decide::Redirect, &src, &dst))
+        .unwrap();
+    constellation
+        .send(event(Decide::Continue, &src, &dst))
+        .unwrap();
+
+    // Events keep their order, so the dropped event would have come first
+    let e = events.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(e.payload_as::<Decide>(), Some(&Decide::Continue));
+    let e = canary_events.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(e.payload_as::<Decide>(), Some(&Decide::Redirect));
+    assert_eq!(e.get_dst(), redirected);
+    assert!(events.try_recv().is_err());
+    assert!(canary_events.try_recv().is_err());
+
+    // Later interceptors do not run on the event, earlier ones see all
+    assert_eq!(seen.load(Ordering::SeqCst), 3);
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(all_decisions, 2);
+
+fn dropped_event_is_dead_lettered(mode: Mode, threads: i32) {
+    let config = intercepted_config(threads, Default::default(), Default::default());
+    let mut constellation = new_constellation(mode, config);
+    constellation.activate().unwrap();
+
+    let waiter = constellation
+        .submit(activity(Waiter), &context(), false, true)
+        .unwrap();
+    let src = constellation.allocate_external_id();
+
+    let ack = constellation
+        .send_with_ack(event(Decide::Drop, &src, &waiter))
+        .unwrap();
+    assert_eq!(ack.wait(TIMEOUT), AckStatus::DeadLettered);
+
+    // The waiter was not woken up by it, only by the next event
+    let ack = constellation
+        .send_with_ack(event(Decide::Continue, &src, &waiter))
+        .unwrap();
+    assert_eq!(ack.wait(TIMEOUT), AckStatus::Consumed);
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(dropped_event_is_dead_lettered, 2);
+
+#[test]
+fn drop_leaves_no_residue() {
+    let config = intercepted_config(2, Default::default(), Default::default());
+    let mut constellation = MultiThreadedConstellation::new(config);
+    constellation.activate().unwrap();
+
+    let waiter = constellation
+        .submit(activity(Waiter), &context(), false, true)
+        .unwrap();
+    let src = constellation.allocate_external_id();
+    for _ in 0..10 {
+        constellation
+            .send(event(Decide::Drop, &src, &waiter))
+            .unwrap();
+    }
+    let ack = constellation
+        .send_with_ack(event(Decide::Continue, &src, &waiter))
+        .unwrap();
+    assert_eq!(ack.wait(TIMEOUT), AckStatus::Consumed);
+    constellation.wait_until_idle(TIMEOUT).unwrap();
+
+    // Not queued anywhere, nor dropped later because the waiter finished
+    let stats = constellation.stats();
+    assert_eq!((stats.pending, stats.suspended), (0, 0));
+    assert_eq!((stats.events, stats.dropped_events), (0, 0));
+    assert_eq!(constellation.dropped_events(), 0);
+
+    shut_down(&mut constellation);
+}
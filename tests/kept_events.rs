@@ -0,0 +1,120 @@
+//! Events kept by the load balancer for activities which are not placed yet,
+//! while those activities finish right after they are placed
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, Event, SendError,
+};
+
+const ROUNDS: usize = 20;
+const SPAWNERS: usize = 4;
+const CHILDREN: usize = 200;
+const EVENTS: usize = 5;
+
+/// Activity which submits short-lived children and sends them events right
+/// away, before the load balancer placed them
+struct Spawner;
+
+impl ActivityTrait for Spawner {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        for _ in 0..CHILDREN {
+            let child = constellation
+                .submit(activity(Quick), &context(), true, false)
+                .unwrap();
+            for _ in 0..EVENTS {
+                match constellation.send(ping(id, &child)) {
+                    Ok(()) | Err(SendError::DestinationFinished(_)) => {}
+                    Err(e) => panic!("Could not send: {}", e),
+                }
+            }
+        }
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+#[test]
+fn kept_events_for_finishing_activities() {
+    for _ in 0..ROUNDS {
+        let mut constellation = new_constellation(Mode::MultiThreaded, config(4));
+        constellation.activate().unwrap();
+
+        for _ in 0..SPAWNERS {
+            constellation
+                .submit(activity(Spawner), &context(), true, false)
+                .unwrap();
+        }
+
+        // No event may be left behind in the queues of the threads
+        shut_down(constellation.as_mut());
+        assert!(constellation.dump_state().is_empty());
+    }
+}
+
+const WAITERS: usize = 2000;
+
+/// Activity which submits many children at once, sending each an event
+/// before the load balancer placed it
+struct WaiterSpawner;
+
+impl ActivityTrait for WaiterSpawner {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        for _ in 0..WAITERS {
+            let child = constellation
+                .submit(activity(Waiter), &context(), true, true)
+                .unwrap();
+            constellation.send(ping(id, &child)).unwrap();
+        }
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+#[test]
+fn kept_events_for_many_children() {
+    let mut constellation = new_constellation(Mode::MultiThreaded, config(4));
+    constellation.activate().unwrap();
+
+    // All children are placed at once instead of one batch per pass of the
+    // load balancer, which took seconds
+    let start = Instant::now();
+    constellation
+        .submit(activity(WaiterSpawner), &context(), true, false)
+        .unwrap();
+    shut_down(constellation.as_mut());
+    assert!(start.elapsed() < Duration::from_secs(3));
+    assert!(constellation.dump_state().is_empty());
+}
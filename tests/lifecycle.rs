@@ -0,0 +1,104 @@
+//! Calls on instances which are not activated yet, were activated before,
+//! or were shut down
+#[macro_use]
+mod common;
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::implementation::communication::node_handler::NodeHandler;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ConstellationError, SendError, SubmitOptions,
+};
+
+fn some_identifier() -> ActivityIdentifier {
+    ActivityIdentifier {
+        constellation_id: 1,
+        node_info: NodeHandler {
+            node_name: "node".to_string(),
+            node_id: 0,
+        },
+        activity_id: 0,
+    }
+}
+
+fn not_activated(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    let aid = some_identifier();
+
+    assert_eq!(
+        constellation
+            .submit(activity(Quick), &context(), true, false)
+            .err(),
+        Some(ConstellationError::NotActivated)
+    );
+    assert_eq!(
+        constellation
+            .submit_with(activity(Quick), &context(), SubmitOptions::default())
+            .err(),
+        Some(ConstellationError::NotActivated)
+    );
+    assert_eq!(
+        constellation.send(ping(&aid, &aid)).err(),
+        Some(SendError::Constellation(ConstellationError::NotActivated))
+    );
+    assert_eq!(
+        constellation.done().err(),
+        Some(ConstellationError::NotActivated)
+    );
+
+    // The failed calls do not get in the way of activating
+    constellation.activate().unwrap();
+    constellation
+        .submit(activity(Quick), &context(), true, false)
+        .unwrap();
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(not_activated, 2);
+
+fn after_done(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+    let aid = constellation
+        .submit(activity(Quick), &context(), true, false)
+        .unwrap();
+    shut_down(constellation.as_mut());
+
+    // done() is idempotent
+    assert_eq!(constellation.done(), Ok(true));
+
+    assert_eq!(
+        constellation
+            .submit(activity(Quick), &context(), true, false)
+            .err(),
+        Some(ConstellationError::AlreadyShutDown)
+    );
+    assert_eq!(
+        constellation.send(ping(&aid, &aid)).err(),
+        Some(SendError::Constellation(
+            ConstellationError::AlreadyShutDown
+        ))
+    );
+}
+
+test_both_modes!(after_done, 2);
+
+fn activated_twice(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+    assert_eq!(
+        constellation.activate(),
+        Err(ConstellationError::AlreadyActivated)
+    );
+    assert_eq!(constellation.threads(), threads);
+
+    // The first activation keeps running, and shuts down once
+    for _ in 0..10 {
+        constellation
+            .submit(activity(Quick), &context(), true, false)
+            .unwrap();
+    }
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(activated_twice, 2);
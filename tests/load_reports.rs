@@ -0,0 +1,81 @@
+//! Load reports sent between the nodes of a constellation instance, and
+//! recorded in the load table of the receiving node
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::implementation::communication::comm::LOAD_TAG;
+use constellation_rust::{
+    Communication, ConstellationTrait, LocalComm, MultiThreadedConstellation, NodeLoad,
+};
+
+#[test]
+fn load_reports_between_nodes() {
+    let mut comms = LocalComm::group(2);
+    let peer = comms.pop().unwrap();
+
+    let mut config = config(2);
+    config.load_report_interval = Duration::from_millis(10);
+    let mut master =
+        MultiThreadedConstellation::with_communication(config, Arc::new(comms.pop().unwrap()));
+    master.activate().unwrap();
+
+    // The master sends its own load to the other node
+    wait_for(|| match peer.try_receive(LOAD_TAG) {
+        Some((source, bytes)) => {
+            assert_eq!(source, 0);
+            let load = NodeLoad::decode(&bytes).unwrap();
+            assert_eq!(load.node_id, 0);
+            true
+        }
+        None => false,
+    });
+
+    // and records the load reported by the other node
+    let mut load = NodeLoad::new(1);
+    load.add(CONTEXT);
+    load.add(CONTEXT);
+    load.add("other");
+    peer.send(0, LOAD_TAG, &load.encode());
+    wait_for(|| master.cluster_load().iter().any(|entry| entry.load == load));
+    assert!(master
+        .cluster_load()
+        .iter()
+        .any(|entry| entry.load.node_id == 0));
+
+    // Malformed reports and reports of another node are dropped
+    peer.send(0, LOAD_TAG, &[1, 2, 3]);
+    let mut forged = NodeLoad::new(0);
+    forged.add(CONTEXT);
+    peer.send(0, LOAD_TAG, &forged.encode());
+    let mut update = NodeLoad::new(1);
+    update.add(CONTEXT);
+    peer.send(0, LOAD_TAG, &update.encode());
+    wait_for(|| {
+        master
+            .cluster_load()
+            .iter()
+            .any(|entry| entry.load == update)
+    });
+    assert!(master
+        .cluster_load()
+        .iter()
+        .all(|entry| entry.load != forged));
+
+    shut_down(&mut master);
+}
+
+#[test]
+fn load_report_encoding() {
+    let mut load = NodeLoad::new(3);
+    load.add(CONTEXT);
+    load.add("other");
+    load.add("other");
+
+    let bytes = load.encode();
+    assert_eq!(NodeLoad::decode(&bytes), Some(load));
+    assert_eq!(NodeLoad::decode(&bytes[..bytes.len() - 1]), None);
+    assert_eq!(NodeLoad::decode(&[bytes.clone(), vec![0]].concat()), None);
+}
@@ -0,0 +1,72 @@
+//! LocalComm, the Communication of processes which all run inside the test
+//! process, behaves like the MPI implementation
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use constellation_rust::{Communication, LocalComm};
+
+#[test]
+fn ranks_and_nodes() {
+    let names = vec![
+        "node0".to_string(),
+        "node0".to_string(),
+        "node1".to_string(),
+    ];
+    let comms = LocalComm::group_on_nodes(names.clone());
+
+    for (rank, comm) in comms.iter().enumerate() {
+        assert_eq!(comm.rank(), rank as i32);
+        assert_eq!(comm.size(), 3);
+        assert_eq!(comm.processor_name(), names[rank]);
+        assert_eq!(comm.is_master(0), rank == 0);
+        assert_eq!(comm.is_master(2), rank == 2);
+
+        let groups = comm.groups();
+        assert_eq!(groups.len(), 3);
+        for (rank, node) in groups.iter() {
+            assert_eq!(node.node_name, names[*rank as usize]);
+        }
+    }
+
+    let single = LocalComm::new();
+    assert_eq!((single.rank(), single.size()), (0, 1));
+}
+
+#[test]
+fn messages_by_tag_in_order() {
+    let comms = LocalComm::group(3);
+
+    comms[1].send(0, 7, b"first");
+    comms[2].send(0, 8, b"other tag");
+    comms[2].send(0, 7, b"second");
+
+    assert_eq!(comms[0].try_receive(7), Some((1, b"first".to_vec())));
+    assert_eq!(comms[0].try_receive(7), Some((2, b"second".to_vec())));
+    assert_eq!(comms[0].try_receive(7), None);
+    assert_eq!(comms[0].try_receive(8), Some((2, b"other tag".to_vec())));
+
+    // Nothing was delivered to the other processes
+    assert_eq!(comms[1].try_receive(7), None);
+    assert_eq!(comms[2].try_receive(8), None);
+}
+
+#[test]
+fn barrier_waits_for_all() {
+    let arrived = Arc::new(AtomicUsize::new(0));
+    let handles: Vec<_> = LocalComm::group(4)
+        .into_iter()
+        .map(|comm| {
+            let arrived = arrived.clone();
+            thread::spawn(move || {
+                arrived.fetch_add(1, Ordering::SeqCst);
+                comm.barrier();
+                arrived.load(Ordering::SeqCst)
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), 4);
+    }
+}
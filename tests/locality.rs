@@ -0,0 +1,115 @@
+//! Children submitted with `SubmitOptions::prefer_parent_thread` run on the
+//! executor thread of their parent
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::{
+    ActivityIdentifier, ActivityTrait, ConstellationHandle, ConstellationTrait, Event,
+    MultiThreadedConstellation, SubmitOptions,
+};
+
+const THREADS: i32 = 4;
+const CHILDREN: usize = 8;
+
+/// Activity which records the thread it runs on
+struct Child(Arc<Mutex<Vec<i32>>>);
+
+impl ActivityTrait for Child {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, constellation: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.0.lock().unwrap().push(constellation.thread_id());
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Activity which records its thread, submits CHILDREN children and keeps
+/// its thread busy for a while
+struct Parent {
+    prefer_parent_thread: bool,
+    threads: Arc<Mutex<Vec<i32>>>,
+}
+
+impl ActivityTrait for Parent {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, constellation: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.threads.lock().unwrap().push(constellation.thread_id());
+        for _ in 0..CHILDREN {
+            let options = SubmitOptions {
+                prefer_parent_thread: self.prefer_parent_thread,
+                ..Default::default()
+            };
+            constellation
+                .submit_with(activity(Child(self.threads.clone())), &context(), options)
+                .unwrap();
+        }
+        thread::sleep(Duration::from_millis(20));
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Run a parent and its children
+///
+/// # Returns
+/// * `Vec<i32>` - The thread of the parent, followed by the threads of the
+/// children
+fn run(prefer_parent_thread: bool) -> Vec<i32> {
+    // The other threads are empty, allow all children on the thread of the
+    // parent
+    let mut config = config(THREADS);
+    config.parent_thread_load_factor = CHILDREN as f64;
+    let mut constellation = MultiThreadedConstellation::new(config);
+    constellation.activate().unwrap();
+
+    let threads = Arc::new(Mutex::new(Vec::new()));
+    let parent = Parent {
+        prefer_parent_thread,
+        threads: threads.clone(),
+    };
+    constellation
+        .submit(activity(parent), &context(), true, false)
+        .unwrap();
+    shut_down(&mut constellation);
+
+    let threads = threads.lock().unwrap().clone();
+    assert_eq!(threads.len(), CHILDREN + 1);
+    threads
+}
+
+#[test]
+fn children_stay_on_parent_thread() {
+    let threads = run(true);
+    assert!(threads.iter().all(|t| *t == threads[0]), "{:?}", threads);
+}
+
+#[test]
+fn children_spread_without_preference() {
+    // The thread of the parent is busy, the load balancer places the
+    // children on the other threads
+    let threads = run(false);
+    assert!(threads.iter().any(|t| *t != threads[0]), "{:?}", threads);
+}
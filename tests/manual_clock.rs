@@ -0,0 +1,199 @@
+//! Timers under a ManualClock: a delayed send, an activity suspended until
+//! its timeout event arrives, and the maximum execution time all fire when
+//! the clock is advanced, without waiting for the delay in real time
+#[macro_use]
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, Clock, CompletionReason,
+    ConstellationConfiguration, ConstellationHandle, ConstellationTrait, Event, ManualClock,
+    SubmitOptions,
+};
+
+const DELAY: Duration = Duration::from_secs(3600);
+
+/// Real time after which an event which is not due yet is assumed not to
+/// arrive
+const QUIET: Duration = Duration::from_millis(50);
+
+/// Configuration with a manual clock, and the clock
+fn manual_config(threads: i32) -> (Box<ConstellationConfiguration>, Arc<ManualClock>) {
+    let clock = Arc::new(ManualClock::new());
+    let mut config = config(threads);
+    config.clock = Clock::manual(&clock);
+    (config, clock)
+}
+
+/// Activity which suspends with a timeout: it sends itself an event after
+/// DELAY, and records whether it finished
+struct Timeout {
+    finished: Arc<AtomicBool>,
+}
+
+impl ActivityTrait for Timeout {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        constellation.send_after(ping(id, id), DELAY).unwrap();
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        if event.is_none() {
+            return State::SUSPEND;
+        }
+        self.finished.store(true, Ordering::SeqCst);
+        State::FINISH
+    }
+}
+
+fn delayed_send(mode: Mode, threads: i32) {
+    let (config, clock) = manual_config(threads);
+    let mut constellation = new_constellation(mode, config);
+    constellation.activate().unwrap();
+
+    let src = constellation.allocate_external_id();
+    let dst = constellation.allocate_external_id();
+    let events = constellation.subscribe(&dst);
+    constellation.send_after(ping(&src, &dst), DELAY).unwrap();
+
+    assert!(events.recv_timeout(QUIET).is_err());
+    clock.advance(DELAY - Duration::from_secs(1));
+    assert!(events.recv_timeout(QUIET).is_err());
+
+    let start = Instant::now();
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(events.recv_timeout(TIMEOUT).unwrap().get_dst(), dst);
+    assert!(start.elapsed() < TIMEOUT / 2);
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(delayed_send, 2);
+
+fn suspend_timeout(mode: Mode, threads: i32) {
+    let (config, clock) = manual_config(threads);
+    let mut constellation = new_constellation(mode, config);
+    constellation.activate().unwrap();
+
+    let finished = Arc::new(AtomicBool::new(false));
+    let timeout = Timeout {
+        finished: finished.clone(),
+    };
+    constellation
+        .submit(activity(timeout), &context(), false, true)
+        .unwrap();
+
+    thread::sleep(QUIET);
+    assert!(!finished.load(Ordering::SeqCst));
+
+    clock.advance(DELAY);
+    wait_for(|| finished.load(Ordering::SeqCst));
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(suspend_timeout, 2);
+
+/// Activity which runs until it is released, and records how it completed
+struct Busy {
+    started: Arc<AtomicBool>,
+    released: Arc<AtomicBool>,
+    completed: Arc<Mutex<Option<CompletionReason>>>,
+}
+
+impl ActivityTrait for Busy {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.started.store(true, Ordering::SeqCst);
+        while !self.released.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+
+    fn on_complete(&mut self, reason: CompletionReason) {
+        *self.completed.lock().unwrap() = Some(reason);
+    }
+}
+
+/// Run a Busy activity with a maximum execution time of a second, while the
+/// clock is advanced by the given time
+///
+/// # Returns
+/// * `Option<CompletionReason>` - How the activity completed
+fn run_busy(
+    constellation: &mut dyn ConstellationTrait,
+    clock: &ManualClock,
+    advance: Duration,
+) -> Option<CompletionReason> {
+    let started = Arc::new(AtomicBool::new(false));
+    let released = Arc::new(AtomicBool::new(false));
+    let completed = Arc::new(Mutex::new(None));
+    let busy = Busy {
+        started: started.clone(),
+        released: released.clone(),
+        completed: completed.clone(),
+    };
+    let options = SubmitOptions {
+        max_execution_time: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
+    constellation
+        .submit_with(activity(busy), &context(), options)
+        .unwrap();
+
+    wait_for(|| started.load(Ordering::SeqCst));
+    clock.advance(advance);
+    released.store(true, Ordering::SeqCst);
+    wait_for(|| completed.lock().unwrap().is_some());
+
+    let reason = completed.lock().unwrap().take();
+    reason
+}
+
+fn execution_time_limit(mode: Mode, threads: i32) {
+    let (config, clock) = manual_config(threads);
+    let mut constellation = new_constellation(mode, config);
+    constellation.activate().unwrap();
+
+    // Real time does not count, only the time the clock was advanced
+    assert_eq!(
+        run_busy(constellation.as_mut(), &clock, Duration::from_secs(0)),
+        Some(CompletionReason::Finished)
+    );
+    assert_eq!(
+        run_busy(constellation.as_mut(), &clock, Duration::from_secs(2)),
+        Some(CompletionReason::TimedOut)
+    );
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(execution_time_limit, 2);
@@ -0,0 +1,175 @@
+//! Word count with `map_reduce(..)` for several chunk sizes and fan ins, and
+//! a large number of inputs
+#[macro_use]
+mod common;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::util::patterns::{
+    map_reduce, map_reduce_with, MapFn, MapReduceOptions, ReduceFn,
+};
+use constellation_rust::{new_constellation, ConstellationError, PayloadTrait, PayloadTraitClone};
+
+const TEXT: &str = "the quick brown fox
+jumps over the lazy dog
+the dog sleeps
+a fox is quick and a dog is lazy
+over and over
+the end";
+
+/// Line of text, or the word counts of one or more lines
+#[derive(Debug, Clone)]
+enum Words {
+    Line(String),
+    Counts(HashMap<String, usize>),
+}
+
+impl PayloadTrait for Words {}
+
+impl PayloadTraitClone for Words {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for Words {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+fn counts(payload: Box<dyn PayloadTrait>) -> HashMap<String, usize> {
+    match payload.as_any().downcast_ref::<Words>() {
+        Some(Words::Counts(counts)) => counts.clone(),
+        other => panic!("Unexpected payload {:?}", other),
+    }
+}
+
+fn count_words() -> (MapFn, ReduceFn) {
+    let map: MapFn = Arc::new(|line| {
+        let mut counts = HashMap::new();
+        if let Some(Words::Line(line)) = line.as_any().downcast_ref::<Words>() {
+            for word in line.split_whitespace() {
+                *counts.entry(word.to_string()).or_insert(0) += 1;
+            }
+        }
+        Box::new(Words::Counts(counts))
+    });
+    let reduce: ReduceFn = Arc::new(|a, b| {
+        let mut total = counts(a);
+        for (word, count) in counts(b) {
+            *total.entry(word).or_insert(0) += count;
+        }
+        Box::new(Words::Counts(total))
+    });
+    (map, reduce)
+}
+
+fn lines() -> Vec<Box<dyn PayloadTrait>> {
+    TEXT.lines()
+        .map(|line| Box::new(Words::Line(line.to_string())) as Box<dyn PayloadTrait>)
+        .collect()
+}
+
+fn word_count(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let mut expected = HashMap::new();
+    for word in TEXT.split_whitespace() {
+        *expected.entry(word.to_string()).or_insert(0) += 1;
+    }
+
+    let (map, reduce) = count_words();
+    let result = map_reduce(&mut *constellation, lines(), map, reduce, &context()).unwrap();
+    assert_eq!(counts(result), expected);
+
+    for (chunk_size, fan_in) in [(1, 3), (2, 2), (4, 5), (100, 2)].iter() {
+        let options = MapReduceOptions {
+            chunk_size: *chunk_size,
+            fan_in: *fan_in,
+        };
+        let (map, reduce) = count_words();
+        let result = map_reduce_with(
+            &mut *constellation,
+            lines(),
+            map,
+            reduce,
+            &context(),
+            options,
+        )
+        .unwrap();
+        assert_eq!(counts(result), expected, "{}, {}", chunk_size, fan_in);
+    }
+
+    let (map, reduce) = count_words();
+    assert_eq!(
+        map_reduce(&mut *constellation, Vec::new(), map, reduce, &context()).err(),
+        Some(ConstellationError::Failed)
+    );
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(word_count, 4);
+
+/// Number mapped and summed by the stress test
+#[derive(Debug, Clone)]
+struct Sum(u64);
+
+impl PayloadTrait for Sum {}
+
+impl PayloadTraitClone for Sum {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for Sum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn value(payload: &dyn PayloadTrait) -> u64 {
+    payload.as_any().downcast_ref::<Sum>().unwrap().0
+}
+
+const STRESS_INPUTS: u64 = 2_000;
+
+fn stress(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let map: MapFn = Arc::new(|input| Box::new(Sum(value(&*input) + 1)));
+    let reduce: ReduceFn = Arc::new(|a, b| Box::new(Sum(value(&*a) + value(&*b))));
+    for options in vec![
+        MapReduceOptions::default(),
+        MapReduceOptions {
+            chunk_size: 16,
+            fan_in: 8,
+        },
+    ] {
+        let inputs: Vec<Box<dyn PayloadTrait>> = (0..STRESS_INPUTS)
+            .map(|i| Box::new(Sum(i)) as Box<dyn PayloadTrait>)
+            .collect();
+        let result = map_reduce_with(
+            &mut *constellation,
+            inputs,
+            map.clone(),
+            reduce.clone(),
+            &context(),
+            options,
+        )
+        .unwrap();
+        assert_eq!(value(&*result), STRESS_INPUTS * (STRESS_INPUTS + 1) / 2);
+    }
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(stress, 4);
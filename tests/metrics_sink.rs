@@ -0,0 +1,92 @@
+//! Statistics written to a metrics sink while activities run, and once more
+//! when the instance shuts down
+mod common;
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::metrics::MetricsSink;
+use constellation_rust::{ConstellationTrait, MultiThreadedConstellation};
+
+const ROUNDS: usize = 5;
+const ACTIVITIES_PER_ROUND: usize = 10;
+
+/// Writer keeping everything in memory, clones share the buffer
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Buffer {
+    /// The value of `activities_finished` on every line written so far
+    fn finished_counts(&self) -> Vec<u64> {
+        const KEY: &str = "\"activities_finished\":";
+
+        let buffer = self.0.lock().unwrap();
+        String::from_utf8(buffer.clone())
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let start = line.find(KEY).expect(line) + KEY.len();
+                let digits: String = line[start..]
+                    .chars()
+                    .take_while(|c| c.is_ascii_digit())
+                    .collect();
+                digits.parse().unwrap()
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn snapshots_while_running() {
+    let buffer = Buffer::default();
+    let mut config = config(2);
+    config.metrics_sink = Some(MetricsSink::new(
+        Duration::from_millis(5),
+        Box::new(buffer.clone()),
+    ));
+    let mut constellation = MultiThreadedConstellation::new(config);
+    constellation.activate().unwrap();
+
+    for round in 1..=ROUNDS {
+        for _ in 0..ACTIVITIES_PER_ROUND {
+            constellation
+                .submit(activity(Quick), &context(), true, false)
+                .unwrap();
+        }
+        let finished = (round * ACTIVITIES_PER_ROUND) as u64;
+        wait_for(|| buffer.finished_counts().last() == Some(&finished));
+        thread::sleep(Duration::from_millis(10));
+    }
+    let written = buffer.finished_counts().len();
+    shut_down(&mut constellation);
+
+    let counts = buffer.finished_counts();
+    assert!(counts.len() > written, "No final snapshot");
+    assert!(
+        counts.windows(2).all(|pair| pair[0] <= pair[1]),
+        "{:?}",
+        counts
+    );
+    for round in 1..=ROUNDS {
+        let finished = (round * ACTIVITIES_PER_ROUND) as u64;
+        assert!(counts.contains(&finished), "{} in {:?}", finished, counts);
+    }
+    assert_eq!(
+        counts.last(),
+        Some(&((ROUNDS * ACTIVITIES_PER_ROUND) as u64))
+    );
+}
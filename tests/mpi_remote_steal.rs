@@ -0,0 +1,87 @@
+//! Activities stolen between two MPI processes. This test needs two ranks,
+//! so it is ignored by `cargo test` and can not run in CI. Run it with:
+//!
+//! ```text
+//! cargo test --test mpi_remote_steal --no-run
+//! mpirun -np 2 target/debug/deps/mpi_remote_steal-<hash> --ignored
+//! ```
+//!
+//! Both ranks run the test; rank 0 is the master, rank 1 steals from it.
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use common::remote::*;
+use common::*;
+use constellation_rust::{
+    run_worker, ActivityIdentifier, ConstellationTrait, Event, MultiThreadedConstellation,
+};
+
+const NODES: i32 = 2;
+const ACTIVITIES: usize = 8;
+
+#[test]
+#[ignore]
+fn activities_stolen_by_other_rank() {
+    let mut config = node_config();
+    config.number_of_nodes = NODES;
+    config.strict_node_count = true;
+
+    let mut constellation = MultiThreadedConstellation::new(config);
+    constellation.activate().unwrap();
+    if !constellation.is_master().unwrap() {
+        assert_eq!(run_worker(&mut constellation), Ok(true));
+        return;
+    }
+
+    // Occupy the executor thread of the master, so the other rank steals the
+    // activities submitted next
+    let started = Arc::new(AtomicBool::new(false));
+    let released = Arc::new(AtomicBool::new(false));
+    let gate = Gate {
+        started: started.clone(),
+        released: released.clone(),
+    };
+    constellation
+        .submit(activity(gate), &context(), false, false)
+        .unwrap();
+    wait_for(|| started.load(Ordering::SeqCst));
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let collector = Collector {
+        expected: ACTIVITIES,
+        reports: reports.clone(),
+    };
+    let collector = constellation
+        .submit(activity(collector), &context(), false, true)
+        .unwrap();
+    let echos: Vec<ActivityIdentifier> = (0..ACTIVITIES)
+        .map(|value| {
+            constellation
+                .submit(activity(Echo(value as u64)), &context(), true, true)
+                .unwrap()
+        })
+        .collect();
+
+    wait_for(|| constellation.steal_stats().remote_given == ACTIVITIES as u64);
+    released.store(true, Ordering::SeqCst);
+
+    // The events follow the activities to the other rank
+    for echo in &echos {
+        let start = Report { value: 0, node: 0 };
+        constellation
+            .send(Event::new(Box::new(start), collector.clone(), echo.clone()))
+            .unwrap();
+    }
+    wait_for(|| reports.lock().unwrap().len() == ACTIVITIES);
+
+    let mut reports = reports.lock().unwrap().clone();
+    reports.sort_by_key(|report| report.value);
+    for (value, report) in reports.iter().enumerate() {
+        assert_eq!(report.value, value as u64);
+        assert_eq!(report.node, 1, "Echo {} did not run on rank 1", value);
+    }
+
+    shut_down(&mut constellation);
+}
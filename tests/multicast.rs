@@ -0,0 +1,99 @@
+//! Events multicast to all live activities of a context with
+//! `send_to_context(..)`
+#[macro_use]
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, Context, ContextVec,
+    Event,
+};
+
+const ROUND: &str = "round";
+const OTHER: &str = "other";
+
+/// Activity which records its name when it receives an event, and finishes
+struct Receiver {
+    name: &'static str,
+    received: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl ActivityTrait for Receiver {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        if event.is_none() {
+            return State::SUSPEND;
+        }
+        self.received.lock().unwrap().push(self.name);
+        State::FINISH
+    }
+}
+
+fn exactly_three(mode: Mode, threads: i32) {
+    let mut config = config(threads);
+    let mut context_vec = ContextVec::new();
+    context_vec.append(&Context::new(ROUND));
+    context_vec.append(&Context::new(OTHER));
+    config.context_vec = context_vec;
+    let mut constellation = new_constellation(mode, config);
+    constellation.activate().unwrap();
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let mut submit = |name, label| {
+        let receiver = Receiver {
+            name,
+            received: received.clone(),
+        };
+        constellation
+            .submit(activity(receiver), &Context::new(label), true, true)
+            .unwrap()
+    };
+    for name in ["a", "b", "c"].iter() {
+        submit(name, ROUND);
+    }
+    let other = submit("other", OTHER);
+    drop(submit);
+
+    let src = constellation.allocate_external_id();
+    let sent = constellation
+        .send_to_context(Box::new(Ping), src.clone(), &Context::new(ROUND))
+        .unwrap();
+    assert_eq!(sent, 3);
+    wait_for(|| received.lock().unwrap().len() == 3);
+
+    // Submitted after the multicast
+    let receiver = Receiver {
+        name: "late",
+        received: received.clone(),
+    };
+    let late = constellation
+        .submit(activity(receiver), &Context::new(ROUND), true, true)
+        .unwrap();
+
+    // Only the activities left receive these
+    constellation.send(ping(&src, &other)).unwrap();
+    constellation.send(ping(&src, &late)).unwrap();
+    shut_down(constellation.as_mut());
+
+    let mut received = received.lock().unwrap().clone();
+    received[..3].sort();
+    assert_eq!(received[..3], ["a", "b", "c"]);
+    received[3..].sort();
+    assert_eq!(received[3..], ["late", "other"]);
+}
+
+test_both_modes!(exactly_three, 3);
@@ -0,0 +1,90 @@
+//! Activities submitted with a name, looked up by the application and from
+//! inside activities, see `submit_named(..)`
+#[macro_use]
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationError, ConstellationHandle,
+    Event, SubmitOptions,
+};
+
+const SINK: &str = "sink";
+
+/// Activity which looks up the sink by name and sends it an event, it
+/// records what it found
+struct Client(Arc<Mutex<Option<ActivityIdentifier>>>);
+
+impl ActivityTrait for Client {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        let sink = constellation.lookup(SINK);
+        if let Some(sink) = &sink {
+            constellation.send(ping(id, sink)).unwrap();
+        }
+        *self.0.lock().unwrap() = sink;
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+fn waiter_options() -> SubmitOptions {
+    SubmitOptions {
+        expects_events: true,
+        ..Default::default()
+    }
+}
+
+fn named_sink(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    // Found before the activity is placed on a thread
+    constellation.pause().unwrap();
+    let sink = constellation
+        .submit_named(SINK, activity(Waiter), &context(), waiter_options())
+        .unwrap();
+    assert_eq!(constellation.lookup(SINK), Some(sink.clone()));
+    assert_eq!(constellation.lookup("unknown"), None);
+
+    // The name is taken, and nothing is submitted
+    assert_eq!(
+        constellation.submit_named(SINK, activity(Quick), &context(), Default::default()),
+        Err(ConstellationError::DuplicateName(SINK.to_string()))
+    );
+    constellation.resume().unwrap();
+
+    let found = Arc::new(Mutex::new(None));
+    constellation
+        .submit(activity(Client(found.clone())), &context(), true, false)
+        .unwrap();
+
+    // Released once the sink finished, after which the name can be used again
+    wait_for(|| constellation.lookup(SINK).is_none());
+    assert_eq!(*found.lock().unwrap(), Some(sink.clone()));
+    let again = constellation
+        .submit_named(SINK, activity(Quick), &context(), Default::default())
+        .unwrap();
+    assert_ne!(again, sink);
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(named_sink, 2);
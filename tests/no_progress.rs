@@ -0,0 +1,164 @@
+//! Detection of multithreaded instances which make no progress, see
+//! `no_progress_timeout` in the configuration
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationConfiguration,
+    ConstellationHandle, ConstellationSnapshot, Event,
+};
+
+const NO_PROGRESS_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Configuration which records the snapshot of every detected stall
+fn monitored(threads: i32) -> (Box<ConstellationConfiguration>, Arc<Mutex<Vec<String>>>) {
+    let stalls = Arc::new(Mutex::new(Vec::new()));
+    let recorded = stalls.clone();
+
+    let mut config = config(threads);
+    config.no_progress_timeout = Some(NO_PROGRESS_TIMEOUT);
+    config.on_no_progress = Some(Arc::new(move |snapshot: &ConstellationSnapshot| {
+        let suspended = snapshot
+            .threads
+            .iter()
+            .flat_map(|thread| thread.suspended.iter())
+            .map(|activity| activity.activity.clone());
+        recorded.lock().unwrap().extend(suspended);
+    }));
+
+    (config, stalls)
+}
+
+/// Activity which only answers its partner after it got an event from it
+struct Partner {
+    partner: Arc<Mutex<Option<ActivityIdentifier>>>,
+}
+
+impl ActivityTrait for Partner {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        constellation: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        id: &ActivityIdentifier,
+    ) -> State {
+        if event.is_none() {
+            return State::SUSPEND;
+        }
+        if let Some(partner) = self.partner.lock().unwrap().take() {
+            // The partner may have finished already
+            constellation.send(ping(id, &partner)).ok();
+        }
+        State::FINISH
+    }
+}
+
+/// Activity which executes for a while and then wakes up the target
+struct Waker {
+    target: ActivityIdentifier,
+    work: Duration,
+}
+
+impl ActivityTrait for Waker {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        thread::sleep(self.work);
+        constellation.send(ping(id, &self.target)).unwrap();
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+#[test]
+fn wait_cycle_is_detected() {
+    let (config, stalls) = monitored(2);
+    let mut constellation = new_constellation(Mode::MultiThreaded, config);
+    constellation.activate().unwrap();
+
+    // Two activities each waiting for an event from the other
+    let first_partner = Arc::new(Mutex::new(None));
+    let second_partner = Arc::new(Mutex::new(None));
+    let first = constellation
+        .submit(
+            activity(Partner {
+                partner: first_partner.clone(),
+            }),
+            &context(),
+            true,
+            true,
+        )
+        .unwrap();
+    let second = constellation
+        .submit(
+            activity(Partner {
+                partner: second_partner.clone(),
+            }),
+            &context(),
+            true,
+            true,
+        )
+        .unwrap();
+    *first_partner.lock().unwrap() = Some(second.clone());
+    *second_partner.lock().unwrap() = Some(first.clone());
+
+    wait_for(|| !stalls.lock().unwrap().is_empty());
+    let reported = stalls.lock().unwrap().clone();
+    assert!(reported.contains(&first.to_string()));
+    assert!(reported.contains(&second.to_string()));
+
+    // Reported once per stall
+    thread::sleep(3 * NO_PROGRESS_TIMEOUT);
+    assert_eq!(stalls.lock().unwrap().len(), reported.len());
+
+    // Break the cycle from outside
+    let src = constellation.allocate_external_id();
+    constellation.send(ping(&src, &first)).unwrap();
+    shut_down(constellation.as_mut());
+}
+
+#[test]
+fn busy_instance_is_not_reported() {
+    let (config, stalls) = monitored(2);
+    let mut constellation = new_constellation(Mode::MultiThreaded, config);
+    constellation.activate().unwrap();
+
+    // The waiter stays suspended much longer than the timeout, while another
+    // activity is executing
+    let waiter = constellation
+        .submit(activity(Waiter), &context(), true, true)
+        .unwrap();
+    let waker = Waker {
+        target: waiter,
+        work: 5 * NO_PROGRESS_TIMEOUT,
+    };
+    constellation
+        .submit(activity(waker), &context(), true, false)
+        .unwrap();
+
+    shut_down(constellation.as_mut());
+    assert!(stalls.lock().unwrap().is_empty());
+}
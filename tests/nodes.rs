@@ -0,0 +1,132 @@
+//! The number of nodes reported by the instance, the inner instance of a
+//! single threaded instance and the handles of its activities
+mod common;
+
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::{
+    ActivityIdentifier, ActivityTrait, Communication, ConfigError, ConstellationError,
+    ConstellationHandle, ConstellationTrait, Event, LocalComm, MultiThreadedConstellation,
+    SingleThreadConstellation,
+};
+
+/// Activity which stores the number of nodes its handle reports
+struct Nodes(Arc<AtomicI32>);
+
+impl ActivityTrait for Nodes {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, constellation: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.0.store(constellation.nodes(), Ordering::SeqCst);
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Number of nodes the handle of an activity submitted to `constellation`
+/// reports
+fn handle_nodes(constellation: &mut dyn ConstellationTrait) -> i32 {
+    let nodes = Arc::new(AtomicI32::new(0));
+    constellation
+        .submit(activity(Nodes(nodes.clone())), &context(), true, false)
+        .unwrap();
+    wait_for(|| nodes.load(Ordering::SeqCst) != 0);
+    nodes.load(Ordering::SeqCst)
+}
+
+fn group(size: usize) -> Vec<Arc<dyn Communication>> {
+    LocalComm::group(size)
+        .into_iter()
+        .map(|comm| Arc::new(comm) as Arc<dyn Communication>)
+        .collect()
+}
+
+#[test]
+fn surfaces_agree_single_threaded() {
+    let mut constellation = SingleThreadConstellation::new(config(1));
+    constellation.activate().unwrap();
+
+    assert_eq!(constellation.nodes(), 1);
+    assert_eq!(handle_nodes(&mut constellation), 1);
+    shut_down(&mut constellation);
+}
+
+#[test]
+fn surfaces_agree_multithreaded() {
+    let mut constellation = MultiThreadedConstellation::new(config(2));
+    constellation.activate().unwrap();
+
+    assert_eq!(constellation.nodes(), 1);
+    assert_eq!(handle_nodes(&mut constellation), 1);
+    shut_down(&mut constellation);
+}
+
+/// The communication layer decides, `number_of_nodes` only warns when it
+/// differs
+#[test]
+fn communication_decides_number_of_nodes() {
+    let mut config = config(1);
+    config.number_of_nodes = 5;
+
+    let mut comms = group(2);
+    let mut worker =
+        SingleThreadConstellation::with_communication(config.clone(), comms.pop().unwrap());
+    let mut master =
+        MultiThreadedConstellation::with_communication(config.clone(), comms.pop().unwrap());
+    assert!(master.activate().unwrap());
+    assert!(!worker.activate().unwrap());
+
+    assert_eq!(master.nodes(), 2);
+    assert_eq!(worker.nodes(), 2);
+    assert_eq!(handle_nodes(&mut master), 2);
+
+    shut_down(&mut master);
+}
+
+#[test]
+fn strict_node_count() {
+    let mut config = config(1);
+    config.number_of_nodes = 5;
+    config.strict_node_count = true;
+
+    let mut comms = group(2);
+    let mut master =
+        MultiThreadedConstellation::with_communication(config.clone(), comms.remove(0));
+    assert_eq!(
+        master.activate(),
+        Err(ConstellationError::InvalidConfiguration)
+    );
+    assert_eq!(master.nodes(), 2);
+}
+
+/// A faked number of MPI processes which differs from `number_of_nodes`
+#[test]
+fn mismatch_is_reported() {
+    let mut config = config(1);
+    config.number_of_nodes = 4;
+    assert!(config.check_node_count(4).is_ok());
+
+    // Only a warning by default
+    assert!(config.check_node_count(3).is_ok());
+
+    config.strict_node_count = true;
+    match config.check_node_count(3) {
+        Err(ConfigError::InvalidValue { key, value, reason }) => {
+            assert_eq!(key, "number_of_nodes");
+            assert_eq!(value, "4");
+            assert!(reason.contains("3 MPI processes"), "{}", reason);
+        }
+        result => panic!("Unexpected result: {:?}", result),
+    }
+}
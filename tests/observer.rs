@@ -0,0 +1,165 @@
+//! The scheduling decisions sent to the observer of the configuration: every
+//! activity is placed and finishes exactly once, every event is routed to
+//! its destination, migrations are reported per move, and nothing is
+//! orphaned in a run in which no event outlives its destination
+#[macro_use]
+mod common;
+
+use std::collections::HashMap;
+#[cfg(feature = "fault-injection")]
+use std::sync::Arc;
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ConstellationConfiguration, SchedulerEvent,
+};
+
+const ACTIVITIES: usize = 30;
+
+/// Run ACTIVITIES Waiters and send each of them an event, while observing
+/// the instance
+///
+/// # Returns
+/// * `Vec<ActivityIdentifier>` - The Waiters
+/// * `Vec<(u64, ActivityIdentifier)>` - The id and destination of every
+/// event sent
+/// * `Vec<SchedulerEvent>` - What the observer received
+fn observe_waiters(
+    mode: Mode,
+    mut config: Box<ConstellationConfiguration>,
+) -> (
+    Vec<ActivityIdentifier>,
+    Vec<(u64, ActivityIdentifier)>,
+    Vec<SchedulerEvent>,
+) {
+    let (sender, observer) = crossbeam::unbounded();
+    config.with_observer(sender);
+    let mut constellation = new_constellation(mode, config);
+    constellation.activate().unwrap();
+
+    let src = constellation.allocate_external_id();
+    let mut waiters = Vec::new();
+    let mut sent = Vec::new();
+    for _ in 0..ACTIVITIES {
+        let waiter = constellation
+            .submit(activity(Waiter), &context(), true, true)
+            .unwrap();
+        let event = ping(&src, &waiter);
+        sent.push((event.get_id(), waiter.clone()));
+        constellation.send(event).unwrap();
+        waiters.push(waiter);
+    }
+    shut_down(constellation.as_mut());
+    drop(constellation);
+
+    (waiters, sent, observer.try_iter().collect())
+}
+
+/// Number of times every activity occurs in the observed events selected by
+/// `select`
+fn per_activity<F>(events: &[SchedulerEvent], select: F) -> HashMap<ActivityIdentifier, usize>
+where
+    F: Fn(&SchedulerEvent) -> Option<&ActivityIdentifier>,
+{
+    let mut counts = HashMap::new();
+    for aid in events.iter().filter_map(select) {
+        *counts.entry(aid.clone()).or_insert(0) += 1;
+    }
+    counts
+}
+
+fn finished_once(events: &[SchedulerEvent], waiters: &[ActivityIdentifier]) {
+    let finished = per_activity(events, |e| match e {
+        SchedulerEvent::Finished { aid } => Some(aid),
+        _ => None,
+    });
+    assert_eq!(finished.len(), waiters.len());
+    assert!(waiters.iter().all(|w| finished.get(w) == Some(&1)));
+}
+
+fn finished_in_any_mode(mode: Mode, threads: i32) {
+    let (waiters, _, events) = observe_waiters(mode, config(threads));
+    finished_once(&events, &waiters);
+    assert!(!events
+        .iter()
+        .any(|e| matches!(e, SchedulerEvent::Orphaned { .. })));
+}
+
+test_both_modes!(finished_in_any_mode, 3);
+
+#[test]
+fn placed_and_routed() {
+    const THREADS: usize = 3;
+    let (waiters, sent, events) = observe_waiters(Mode::MultiThreaded, config(THREADS as i32));
+    finished_once(&events, &waiters);
+
+    let submitted = per_activity(&events, |e| match e {
+        SchedulerEvent::Submitted { aid, thread } => {
+            assert!(thread.map_or(false, |t| t < THREADS), "{:?}", e);
+            Some(aid)
+        }
+        _ => None,
+    });
+    assert!(waiters.iter().all(|w| submitted.get(w) == Some(&1)));
+
+    // Every event reached the thread holding its destination
+    let routed: HashMap<u64, ActivityIdentifier> = events
+        .iter()
+        .filter_map(|e| match e {
+            SchedulerEvent::EventRouted { id, dst, thread } => {
+                assert!(thread.is_some(), "{:?}", e);
+                Some((*id, dst.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+    for (id, dst) in &sent {
+        assert_eq!(routed.get(id), Some(dst), "Event {}", id);
+    }
+
+    // An activity is placed before it finishes
+    for waiter in &waiters {
+        let position = |finished: bool| {
+            events.iter().position(|e| match e {
+                SchedulerEvent::Submitted { aid, .. } => !finished && aid == waiter,
+                SchedulerEvent::Finished { aid } => finished && aid == waiter,
+                _ => false,
+            })
+        };
+        assert!(position(false) < position(true));
+    }
+}
+
+#[cfg(feature = "fault-injection")]
+#[test]
+fn migrated_per_move() {
+    use constellation_rust::{FaultInjector, FaultRule, FaultTarget};
+
+    const MIGRATIONS: u32 = 2;
+    let mut injector = FaultInjector::new(3);
+    injector
+        .add_rule(FaultRule::migrate(FaultTarget::Any, MIGRATIONS))
+        .unwrap();
+    let injector = Arc::new(injector);
+
+    let mut config = config(3);
+    config.fault_injector = Some(injector.clone());
+    let (waiters, _, events) = observe_waiters(Mode::MultiThreaded, config);
+    finished_once(&events, &waiters);
+
+    let migrated = per_activity(&events, |e| match e {
+        SchedulerEvent::Migrated { aid, to, .. } => {
+            assert!(to.is_some(), "{:?}", e);
+            Some(aid)
+        }
+        _ => None,
+    });
+    assert!(waiters
+        .iter()
+        .all(|w| migrated.get(w) == Some(&(MIGRATIONS as usize))));
+    assert_eq!(
+        injector.counts().migrations,
+        ACTIVITIES * MIGRATIONS as usize
+    );
+}
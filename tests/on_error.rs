@@ -0,0 +1,146 @@
+//! Errors reported to activities through `ActivityTrait::on_error(..)`, the
+//! hook may send events, and a panic of the hook does not take down the
+//! executor thread
+#[macro_use]
+mod common;
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityError, ActivityIdentifier, ActivityTrait, ConstellationHandle,
+    Event, PayloadTrait, PayloadTraitClone,
+};
+
+/// Payload telling the collector what went wrong
+#[derive(Debug, Clone)]
+struct Failure(String);
+
+impl PayloadTrait for Failure {}
+
+impl PayloadTraitClone for Failure {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failure: {}", self.0)
+    }
+}
+
+/// Activity which collects the failures it receives
+struct Collector {
+    failures: Arc<Mutex<Vec<String>>>,
+}
+
+impl ActivityTrait for Collector {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        if let Some(failure) = event.and_then(|e| e.payload_as::<Failure>().cloned()) {
+            self.failures.lock().unwrap().push(failure.0);
+        }
+        State::FINISH
+    }
+}
+
+/// Activity which panics when it is initialized, and tells the collector
+/// about it from its error hook, or panics again when it has no collector
+struct Failing {
+    collector: Option<ActivityIdentifier>,
+}
+
+impl ActivityTrait for Failing {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        panic!("initialize failed");
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+
+    fn on_error(
+        &mut self,
+        constellation: &ConstellationHandle,
+        error: &ActivityError,
+        id: &ActivityIdentifier,
+    ) {
+        let collector = self.collector.clone().expect("on_error failed");
+        let failure = Failure(error.to_string());
+        constellation
+            .send(Event::new(Box::new(failure), id.clone(), collector))
+            .unwrap();
+    }
+}
+
+fn error_hook_sends_failure_to_collector(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let failures = Arc::new(Mutex::new(Vec::new()));
+    let collector = Collector {
+        failures: failures.clone(),
+    };
+    let collector = constellation
+        .submit(activity(collector), &context(), false, true)
+        .unwrap();
+    let failing = Failing {
+        collector: Some(collector),
+    };
+    constellation
+        .submit(activity(failing), &context(), true, false)
+        .unwrap();
+
+    shut_down(constellation.as_mut());
+    let failures = failures.lock().unwrap();
+    assert_eq!(failures.len(), 1);
+    assert!(failures[0].contains("initialize failed"), "{:?}", failures);
+}
+
+test_both_modes!(error_hook_sends_failure_to_collector, 2);
+
+fn panic_of_error_hook_is_contained(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    constellation
+        .submit(
+            activity(Failing { collector: None }),
+            &context(),
+            true,
+            false,
+        )
+        .unwrap();
+
+    // The executor thread is still there to deliver the event
+    let waiter = constellation
+        .submit(activity(Waiter), &context(), true, true)
+        .unwrap();
+    let src = constellation.allocate_external_id();
+    constellation.send(ping(&src, &waiter)).unwrap();
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(panic_of_error_hook_is_contained, 2);
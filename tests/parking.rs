@@ -0,0 +1,46 @@
+//! Idle executor threads park instead of spinning their loop
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::{ConstellationTrait, MultiThreadedConstellation};
+
+const THREADS: i32 = 4;
+const IDLE: Duration = Duration::from_millis(500);
+
+fn total(iterations: &[u64]) -> u64 {
+    iterations.iter().sum()
+}
+
+#[test]
+fn idle_threads_park() {
+    let mut constellation = MultiThreadedConstellation::new(config(THREADS));
+    constellation.activate().unwrap();
+    assert_eq!(constellation.loop_iterations().len(), THREADS as usize);
+
+    // Let the threads settle, then count the iterations while idle
+    thread::sleep(Duration::from_millis(100));
+    let before = total(&constellation.loop_iterations());
+    thread::sleep(IDLE);
+    let idle = total(&constellation.loop_iterations()) - before;
+
+    // Spinning with a 100µs timeout would take about 5000 iterations per
+    // thread, parked threads wake up at most every 10ms
+    let spinning = THREADS as u64 * (IDLE.as_micros() / 100) as u64;
+    assert!(
+        idle * 20 < spinning,
+        "{} iterations while idle, spinning takes about {}",
+        idle,
+        spinning
+    );
+
+    // Parked threads still pick up work right away
+    for _ in 0..100 {
+        constellation
+            .submit(activity(Quick), &context(), true, false)
+            .unwrap();
+    }
+    shut_down(&mut constellation);
+}
@@ -0,0 +1,76 @@
+//! Instances paused with `pause()` queue new work without running it, until
+//! `resume()`
+#[macro_use]
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, ConstellationTrait,
+    Event,
+};
+
+const ACTIVITIES: usize = 20;
+
+/// Activity which counts how often it ran
+struct Counted(Arc<AtomicUsize>);
+
+impl ActivityTrait for Counted {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Number of activities waiting to run, on a thread or in the load balancer
+fn pending(constellation: &mut dyn ConstellationTrait) -> usize {
+    let snapshot = constellation.dump_state();
+    let queued: usize = snapshot.threads.iter().map(|t| t.pending.len()).sum();
+    queued + snapshot.unplaced.len()
+}
+
+fn paused_until_resumed(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    constellation.pause().unwrap();
+    assert!(constellation.wait_until_paused(TIMEOUT).unwrap());
+
+    let ran = Arc::new(AtomicUsize::new(0));
+    for _ in 0..ACTIVITIES {
+        constellation
+            .submit(activity(Counted(ran.clone())), &context(), true, false)
+            .unwrap();
+    }
+
+    // Submissions queue up, but nothing runs
+    wait_for(|| pending(constellation.as_mut()) == ACTIVITIES);
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(pending(constellation.as_mut()), ACTIVITIES);
+    assert_eq!(ran.load(Ordering::SeqCst), 0);
+
+    constellation.resume().unwrap();
+    wait_for(|| ran.load(Ordering::SeqCst) == ACTIVITIES);
+    assert_eq!(pending(constellation.as_mut()), 0);
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(paused_until_resumed, 3);
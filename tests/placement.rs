@@ -0,0 +1,172 @@
+//! Placement constraints of `SubmitOptions::placement` on a simulated
+//! cluster: activities only run on the nodes their placement allows, also
+//! when the other nodes steal, and a placement the cluster can not satisfy
+//! is rejected when the activity is submitted
+#[macro_use]
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use common::remote::*;
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ConstellationError, ConstellationTrait, Event, Placement, SimulatedCluster,
+    SubmitOptions,
+};
+
+const NODES: usize = 3;
+const PER_PLACEMENT: usize = 4;
+
+fn options(placement: Placement) -> SubmitOptions {
+    SubmitOptions {
+        expects_events: true,
+        placement,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn run_where_allowed() {
+    let placements = [
+        Placement::Anywhere,
+        Placement::MasterOnly,
+        Placement::Node(0),
+        Placement::NotNode(1),
+    ];
+    let activities = placements.len() * PER_PLACEMENT;
+
+    let mut cluster = SimulatedCluster::new(NODES, 1, node_config());
+    cluster.activate().unwrap();
+
+    // Occupy the executor thread of the master, so the other nodes steal
+    // what they may
+    let started = Arc::new(AtomicBool::new(false));
+    let released = Arc::new(AtomicBool::new(false));
+    let gate = Gate {
+        started: started.clone(),
+        released: released.clone(),
+    };
+    cluster
+        .as_master()
+        .submit(activity(gate), &context(), true, false)
+        .unwrap();
+    wait_for(|| started.load(Ordering::SeqCst));
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let collector = Collector {
+        expected: activities,
+        reports: reports.clone(),
+    };
+    let collector = cluster
+        .as_master()
+        .submit(activity(collector), &context(), false, true)
+        .unwrap();
+
+    // Echo `value` has placement `placements[value % placements.len()]`
+    let echos: Vec<_> = (0..activities)
+        .map(|value| {
+            let placement = placements[value % placements.len()];
+            cluster
+                .as_master()
+                .submit_with(activity(Echo(value as u64)), &context(), options(placement))
+                .unwrap()
+        })
+        .collect();
+
+    // Only the activities placed anywhere and on any node but 1 are stolen
+    let stealable = 2 * PER_PLACEMENT as u64;
+    wait_for(|| cluster.as_master().steal_stats().remote_given == stealable);
+    let stolen_by = |cluster: &mut SimulatedCluster, rank| {
+        cluster.node(rank).unwrap().steal_stats().remote_stolen
+    };
+    assert!(stolen_by(&mut cluster, 2) >= PER_PLACEMENT as u64);
+    released.store(true, Ordering::SeqCst);
+
+    for echo in &echos {
+        let start = Report { value: 0, node: 0 };
+        cluster
+            .as_master()
+            .send(Event::new(Box::new(start), collector.clone(), echo.clone()))
+            .unwrap();
+    }
+    wait_for(|| reports.lock().unwrap().len() == activities);
+
+    for report in reports.lock().unwrap().iter() {
+        let placement = placements[report.value as usize % placements.len()];
+        assert!(
+            placement.allows(report.node as usize, report.node == 0),
+            "Echo {} placed on {} ran on node {}",
+            report.value,
+            placement,
+            report.node
+        );
+    }
+
+    assert_eq!(cluster.done(), Ok(true));
+}
+
+#[test]
+fn unsatisfiable_on_cluster() {
+    let mut cluster = SimulatedCluster::new(NODES, 1, node_config());
+    cluster.activate().unwrap();
+
+    // Activities start on the node they are submitted to, the master
+    for placement in [
+        Placement::Node(1),
+        Placement::Node(NODES),
+        Placement::NotNode(0),
+    ]
+    .iter()
+    {
+        let result =
+            cluster
+                .as_master()
+                .submit_with(activity(Echo(0)), &context(), options(*placement));
+        assert_eq!(
+            result.err(),
+            Some(ConstellationError::UnsatisfiablePlacement(*placement))
+        );
+    }
+
+    assert_eq!(cluster.done(), Ok(true));
+}
+
+fn single_node(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    for placement in [
+        Placement::Anywhere,
+        Placement::MasterOnly,
+        Placement::Node(0),
+        Placement::NotNode(1),
+    ]
+    .iter()
+    {
+        let options = SubmitOptions {
+            placement: *placement,
+            ..Default::default()
+        };
+        constellation
+            .submit_with(activity(Quick), &context(), options)
+            .unwrap();
+    }
+    for placement in [Placement::Node(1), Placement::NotNode(0)].iter() {
+        let options = SubmitOptions {
+            placement: *placement,
+            ..Default::default()
+        };
+        assert_eq!(
+            constellation
+                .submit_with(activity(Quick), &context(), options)
+                .err(),
+            Some(ConstellationError::UnsatisfiablePlacement(*placement))
+        );
+    }
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(single_node, 2);
@@ -0,0 +1,120 @@
+//! Allocations of a burst of no-op activities, with and without the object
+//! pool of the executor threads (`pool_allocations` in the configuration),
+//! counted by a counting global allocator
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, Event,
+};
+
+const ACTIVITIES: usize = 100_000;
+const BATCH: usize = 100;
+
+/// Global allocator counting the allocations
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Activity submitting `left` Quick activities, BATCH per invocation,
+/// yielding in between so a batch finishes before the next is submitted
+struct Spawner {
+    left: usize,
+}
+
+impl Spawner {
+    fn spawn_batch(&mut self, constellation: &ConstellationHandle) -> State {
+        let batch = BATCH.min(self.left);
+        for _ in 0..batch {
+            constellation
+                .submit(activity(Quick), &context(), true, false)
+                .unwrap();
+        }
+        self.left -= batch;
+
+        if self.left > 0 {
+            State::YIELD
+        } else {
+            State::FINISH
+        }
+    }
+}
+
+impl ActivityTrait for Spawner {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, constellation: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.spawn_batch(constellation)
+    }
+
+    fn process(
+        &mut self,
+        constellation: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        self.spawn_batch(constellation)
+    }
+}
+
+/// Run the burst on a single threaded instance, the pool of a thread is only
+/// refilled by the activities finishing on the thread which submitted them
+///
+/// # Returns
+/// * `usize` - Number of allocations made while the burst ran
+fn burst(pool_allocations: bool) -> usize {
+    let mut config = config(1);
+    config.pool_allocations = pool_allocations;
+    let mut constellation = new_constellation(Mode::SingleThreaded, config);
+    constellation.activate().unwrap();
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    constellation
+        .submit(
+            activity(Spawner { left: ACTIVITIES }),
+            &context(),
+            false,
+            false,
+        )
+        .unwrap();
+    constellation.wait_until_idle(10 * TIMEOUT).unwrap();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+    shut_down(constellation.as_mut());
+    allocations
+}
+
+#[test]
+fn pool_reduces_allocations() {
+    let unpooled = burst(false);
+    let pooled = burst(true);
+
+    // Every activity needs at least its own box and the box of its wrapper,
+    // the pool reuses the wrappers
+    assert!(unpooled >= 2 * ACTIVITIES, "{} allocations", unpooled);
+    assert!(
+        pooled + ACTIVITIES / 2 < unpooled,
+        "{} allocations with the pool, {} without",
+        pooled,
+        unpooled
+    );
+}
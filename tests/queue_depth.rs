@@ -0,0 +1,91 @@
+//! Histograms of the queue depths sampled by the load balancer capture the
+//! peak of a known load
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use common::remote::Gate;
+use common::*;
+use constellation_rust::queue_depth::HISTOGRAM_BUCKETS;
+use constellation_rust::{
+    ActivityIdentifier, ConstellationTrait, DepthHistogram, MultiThreadedConstellation,
+};
+
+const SUSPENDED: usize = 3;
+const PENDING: usize = 40;
+
+/// Give the load balancer time to take a few samples
+fn sample() {
+    thread::sleep(Duration::from_millis(20));
+}
+
+#[test]
+fn histograms_capture_peak() {
+    let mut config = config(1);
+    config.queue_sample_interval = Some(Duration::from_millis(1));
+    let mut constellation = MultiThreadedConstellation::new(config);
+    constellation.activate().unwrap();
+
+    let waiters: Vec<ActivityIdentifier> = (0..SUSPENDED)
+        .map(|_| {
+            constellation
+                .submit(activity(Waiter), &context(), false, true)
+                .unwrap()
+        })
+        .collect();
+    wait_for(|| constellation.dump_state().threads[0].suspended.len() == SUSPENDED);
+
+    // Block the only executor thread, everything submitted or sent next is
+    // queued on it
+    let started = Arc::new(AtomicBool::new(false));
+    let released = Arc::new(AtomicBool::new(false));
+    let gate = Gate {
+        started: started.clone(),
+        released: released.clone(),
+    };
+    constellation
+        .submit(activity(gate), &context(), false, false)
+        .unwrap();
+    wait_for(|| started.load(Ordering::SeqCst));
+
+    for _ in 0..PENDING {
+        constellation
+            .submit(activity(Quick), &context(), false, false)
+            .unwrap();
+    }
+    let src = constellation.allocate_external_id();
+    for waiter in &waiters {
+        constellation.send(ping(&src, waiter)).unwrap();
+    }
+    wait_for(|| {
+        let snapshot = constellation.dump_state();
+        snapshot.threads[0].pending.len() == PENDING
+            && snapshot.threads[0].events.len() == SUSPENDED
+    });
+    sample();
+
+    released.store(true, Ordering::SeqCst);
+    constellation.wait_until_idle(TIMEOUT).unwrap();
+    sample();
+
+    let stats = constellation.queue_depth_stats();
+    assert_eq!(stats.len(), 1);
+    let stats = &stats[0];
+    assert_eq!(stats.pending.max, PENDING);
+    assert_eq!(stats.suspended.max, SUSPENDED);
+    assert_eq!(stats.events.max, SUSPENDED);
+
+    // The peak and the empty queues afterwards are both counted
+    for histogram in &[stats.pending, stats.suspended, stats.events] {
+        assert!(histogram.buckets[0] > 0, "{}", histogram);
+        assert!(histogram.buckets[DepthHistogram::bucket(histogram.max)] > 0);
+        assert_eq!(histogram.buckets.iter().sum::<u64>(), histogram.samples);
+        assert_eq!(histogram.buckets[HISTOGRAM_BUCKETS - 1], 0);
+    }
+    assert_eq!(DepthHistogram::bucket(PENDING), 6);
+
+    shut_down(&mut constellation);
+}
@@ -0,0 +1,122 @@
+//! With a `rebalance_threshold`, a batch of long running activities queued on
+//! one executor thread is spread over the idle threads, until every thread
+//! runs about the same number of them. Activities which may not be stolen
+//! stay where they were placed.
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::{
+    ActivityIdentifier, ActivityTrait, ConstellationHandle, ConstellationTrait, Event,
+    MultiThreadedConstellation, SubmitOptions,
+};
+
+const THREADS: i32 = 4;
+const CHILDREN: usize = 40;
+const WORK: Duration = Duration::from_millis(5);
+
+/// Activity which records the thread it runs on, and keeps it busy
+struct Child(Arc<Mutex<Vec<i32>>>);
+
+impl ActivityTrait for Child {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, constellation: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.0.lock().unwrap().push(constellation.thread_id());
+        thread::sleep(WORK);
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Activity which queues all its children on its own thread
+struct Parent {
+    may_be_stolen: bool,
+    threads: Arc<Mutex<Vec<i32>>>,
+}
+
+impl ActivityTrait for Parent {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, constellation: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        for _ in 0..CHILDREN {
+            let options = SubmitOptions {
+                prefer_parent_thread: true,
+                may_be_stolen: self.may_be_stolen,
+                ..Default::default()
+            };
+            constellation
+                .submit_with(activity(Child(self.threads.clone())), &context(), options)
+                .unwrap();
+        }
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Run the children of one parent with rebalancing enabled
+///
+/// # Returns
+/// * `Vec<usize>` - The number of children which ran on every thread
+fn run(may_be_stolen: bool) -> Vec<usize> {
+    let mut config = config(THREADS);
+    config.parent_thread_load_factor = CHILDREN as f64;
+    config.rebalance_threshold = Some(2.0);
+    let mut constellation = MultiThreadedConstellation::new(config);
+    constellation.activate().unwrap();
+
+    let threads = Arc::new(Mutex::new(Vec::new()));
+    let parent = Parent {
+        may_be_stolen,
+        threads: threads.clone(),
+    };
+    constellation
+        .submit(activity(parent), &context(), true, false)
+        .unwrap();
+    shut_down(&mut constellation);
+
+    let threads = threads.lock().unwrap();
+    assert_eq!(threads.len(), CHILDREN);
+    let mut counts = vec![0; THREADS as usize];
+    for thread in threads.iter() {
+        counts[*thread as usize] += 1;
+    }
+    counts
+}
+
+#[test]
+fn converges_to_equal_counts() {
+    let counts = run(true);
+    let fair = CHILDREN / THREADS as usize;
+    assert!(
+        counts.iter().all(|c| *c >= fair / 2 && *c <= fair * 2),
+        "{:?}",
+        counts
+    );
+}
+
+#[test]
+fn unstealable_work_stays() {
+    let counts = run(false);
+    assert_eq!(counts.iter().filter(|c| **c > 0).count(), 1, "{:?}", counts);
+}
@@ -0,0 +1,73 @@
+//! Names registered in the activity registry of the master, looked up and
+//! registered from the other nodes of a simulated cluster
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::{
+    ActivityTrait, ConstellationError, ConstellationTrait, SimulatedCluster, SingleEventCollector,
+    SubmitOptions,
+};
+
+#[test]
+fn names_across_nodes() {
+    let mut cluster = SimulatedCluster::new(3, 2, config(2));
+    cluster.activate().unwrap();
+
+    let sec = SingleEventCollector::new();
+    let sink = cluster
+        .as_master()
+        .submit_named(
+            "sink",
+            sec.clone() as Arc<Mutex<dyn ActivityTrait>>,
+            &context(),
+            SubmitOptions {
+                expects_events: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    // Looked up at the master, and from the cache afterwards
+    let found = cluster.node(2).unwrap().lookup("sink").unwrap();
+    assert_eq!(found, sink);
+    assert_eq!(found.node_info.node_id, 0);
+    assert_eq!(cluster.node(2).unwrap().lookup("sink"), Some(sink.clone()));
+    assert!(cluster.node(1).unwrap().lookup("nothing").is_none());
+
+    // A name registered on another node is known everywhere, and names are
+    // unique across the cluster
+    let client = cluster.node(1).unwrap().allocate_external_id();
+    cluster
+        .node(1)
+        .unwrap()
+        .register_name("client", &client)
+        .unwrap();
+    assert_eq!(cluster.as_master().lookup("client"), Some(client.clone()));
+    assert_eq!(
+        cluster.node(2).unwrap().lookup("client"),
+        Some(client.clone())
+    );
+    assert_eq!(
+        cluster.node(2).unwrap().register_name("client", &client),
+        Err(ConstellationError::DuplicateName("client".to_string()))
+    );
+    assert_eq!(
+        cluster.node(2).unwrap().register_name("sink", &client),
+        Err(ConstellationError::DuplicateName("sink".to_string()))
+    );
+
+    // Finishing or releasing removes the name, also from the caches
+    let src = cluster.as_master().allocate_external_id();
+    cluster.as_master().send(ping(&src, &sink)).unwrap();
+    SingleEventCollector::get_event(sec, Duration::from_millis(5));
+    wait_for(|| cluster.as_master().lookup("sink").is_none());
+    wait_for(|| cluster.node(2).unwrap().lookup("sink").is_none());
+
+    cluster.node(1).unwrap().release_name(&client);
+    wait_for(|| cluster.node(2).unwrap().lookup("client").is_none());
+
+    assert_eq!(cluster.done(), Ok(true));
+}
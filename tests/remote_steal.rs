@@ -0,0 +1,157 @@
+//! Activities stolen by the idle nodes of a simulated cluster, and the events
+//! sent to and from the activities they stole
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use common::remote::*;
+use common::*;
+use constellation_rust::{ActivityIdentifier, ConstellationTrait, Event, SimulatedCluster};
+
+const NODES: usize = 3;
+const ACTIVITIES: usize = 8;
+
+/// Activate a cluster and occupy the executor thread of the master with a
+/// gate, so the activities submitted next stay in its work queue
+///
+/// # Returns
+/// * `Arc<AtomicBool>` - Releases the gate when set
+fn blocked_cluster(cluster: &mut SimulatedCluster) -> Arc<AtomicBool> {
+    cluster.activate().unwrap();
+
+    let started = Arc::new(AtomicBool::new(false));
+    let released = Arc::new(AtomicBool::new(false));
+    cluster
+        .as_master()
+        .submit(
+            activity(Gate {
+                started: started.clone(),
+                released: released.clone(),
+            }),
+            &context(),
+            true,
+            false,
+        )
+        .unwrap();
+    wait_for(|| started.load(Ordering::SeqCst));
+
+    released
+}
+
+fn collector(
+    cluster: &mut SimulatedCluster,
+    expected: usize,
+) -> (ActivityIdentifier, Arc<Mutex<Vec<Report>>>) {
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let id = cluster
+        .as_master()
+        .submit(
+            activity(Collector {
+                expected,
+                reports: reports.clone(),
+            }),
+            &context(),
+            false,
+            true,
+        )
+        .unwrap();
+    (id, reports)
+}
+
+fn stolen_by_workers(cluster: &mut SimulatedCluster) -> u64 {
+    (1..cluster.size())
+        .map(|rank| cluster.node(rank).unwrap().steal_stats().remote_stolen)
+        .sum()
+}
+
+#[test]
+fn activities_stolen_by_idle_nodes() {
+    let mut cluster = SimulatedCluster::new(NODES, 1, node_config());
+    let released = blocked_cluster(&mut cluster);
+    let (collector, reports) = collector(&mut cluster, ACTIVITIES);
+
+    let echos: Vec<ActivityIdentifier> = (0..ACTIVITIES)
+        .map(|value| {
+            cluster
+                .as_master()
+                .submit(activity(Echo(value as u64)), &context(), true, true)
+                .unwrap()
+        })
+        .collect();
+
+    // The master is busy, so the idle nodes steal all of them
+    wait_for(|| cluster.as_master().steal_stats().remote_given == ACTIVITIES as u64);
+    assert_eq!(stolen_by_workers(&mut cluster), ACTIVITIES as u64);
+    released.store(true, Ordering::SeqCst);
+
+    // Events follow the stolen activities, and their answers come back
+    for echo in &echos {
+        let start = Report { value: 0, node: 0 };
+        cluster
+            .as_master()
+            .send(Event::new(Box::new(start), collector.clone(), echo.clone()))
+            .unwrap();
+    }
+    wait_for(|| reports.lock().unwrap().len() == ACTIVITIES);
+
+    let mut reports = reports.lock().unwrap().clone();
+    reports.sort_by_key(|report| report.value);
+    for (value, report) in reports.iter().enumerate() {
+        assert_eq!(report.value, value as u64);
+        assert_ne!(report.node, 0, "Echo {} ran on the master", value);
+    }
+
+    assert_eq!(cluster.done(), Ok(true));
+}
+
+#[test]
+fn activities_which_can_not_be_stolen_stay_on_master() {
+    let mut cluster = SimulatedCluster::new(NODES, 1, node_config());
+    let released = blocked_cluster(&mut cluster);
+    let (collector, reports) = collector(&mut cluster, 2 * ACTIVITIES);
+
+    // Activities which can not be encoded, and activities which may not be
+    // stolen
+    for _ in 0..ACTIVITIES {
+        let reporter = Reporter {
+            collector: collector.clone(),
+        };
+        cluster
+            .as_master()
+            .submit(activity(reporter), &context(), true, false)
+            .unwrap();
+    }
+    let echos: Vec<ActivityIdentifier> = (0..ACTIVITIES)
+        .map(|value| {
+            cluster
+                .as_master()
+                .submit(activity(Echo(value as u64)), &context(), false, true)
+                .unwrap()
+        })
+        .collect();
+
+    // Give the idle nodes time to ask for work
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(cluster.as_master().steal_stats().remote_given, 0);
+    assert_eq!(stolen_by_workers(&mut cluster), 0);
+
+    released.store(true, Ordering::SeqCst);
+    for echo in &echos {
+        let start = Report { value: 0, node: 0 };
+        cluster
+            .as_master()
+            .send(Event::new(Box::new(start), collector.clone(), echo.clone()))
+            .unwrap();
+    }
+    wait_for(|| reports.lock().unwrap().len() == 2 * ACTIVITIES);
+    assert!(reports
+        .lock()
+        .unwrap()
+        .iter()
+        .all(|report| report.node == 0));
+
+    assert_eq!(cluster.done(), Ok(true));
+}
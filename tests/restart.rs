@@ -0,0 +1,93 @@
+//! Instances activated again after `done()`, every run starts with empty
+//! queues and never hands out the identifiers of an earlier run
+#[macro_use]
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, ConstellationTrait,
+    Event, SendError,
+};
+
+const ACTIVITIES: usize = 20;
+
+/// Activity which records its identifier when it runs
+struct Recorder(Arc<Mutex<Vec<u64>>>);
+
+impl ActivityTrait for Recorder {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, id: &ActivityIdentifier) -> State {
+        self.0.lock().unwrap().push(id.activity_id);
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Submit ACTIVITIES recorders and a waiter, and shut down once they ran
+///
+/// # Returns
+/// * `(Vec<u64>, ActivityIdentifier)` - The sorted identifiers of the
+/// recorders, and the identifier of the waiter
+fn run_phase(constellation: &mut dyn ConstellationTrait) -> (Vec<u64>, ActivityIdentifier) {
+    let ran = Arc::new(Mutex::new(Vec::new()));
+    for _ in 0..ACTIVITIES {
+        constellation
+            .submit(activity(Recorder(ran.clone())), &context(), true, false)
+            .unwrap();
+    }
+    let waiter = constellation
+        .submit(activity(Waiter), &context(), true, true)
+        .unwrap();
+    let src = constellation.allocate_external_id();
+    constellation.send(ping(&src, &waiter)).unwrap();
+
+    shut_down(constellation);
+    let mut ran = ran.lock().unwrap().clone();
+    ran.sort_unstable();
+    (ran, waiter)
+}
+
+fn two_cycles(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+
+    constellation.activate().unwrap();
+    let (first, first_waiter) = run_phase(constellation.as_mut());
+    assert_eq!(first.len(), ACTIVITIES);
+
+    constellation.activate().unwrap();
+    assert_eq!(constellation.threads(), threads);
+    let snapshot = constellation.dump_state();
+    assert!(snapshot.unplaced.is_empty() && snapshot.orphaned_events.is_empty());
+    for thread in &snapshot.threads {
+        assert!(thread.pending.is_empty() && thread.suspended.is_empty());
+        assert!(thread.events.is_empty());
+    }
+
+    // Activities of the first run can not receive events anymore
+    let src = constellation.allocate_external_id();
+    assert_eq!(
+        constellation.send(ping(&src, &first_waiter)),
+        Err(SendError::DestinationFinished(first_waiter.clone()))
+    );
+
+    let (second, second_waiter) = run_phase(constellation.as_mut());
+    assert_eq!(second.len(), ACTIVITIES);
+    let first_last = first.last().unwrap().max(&first_waiter.activity_id);
+    assert!(second[0] > *first_last, "{:?} and {:?}", first, second);
+    assert!(second_waiter.activity_id > *first_last);
+}
+
+test_both_modes!(two_cycles, 3);
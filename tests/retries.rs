@@ -0,0 +1,148 @@
+//! Activities run again after they panicked, see `SubmitOptions::retries`
+#[macro_use]
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityError, ActivityIdentifier, ActivityTrait, CompletionReason,
+    ConstellationHandle, ConstellationTrait, Event, SubmitOptions,
+};
+
+/// What happened to a Flaky activity
+#[derive(Debug, Default)]
+struct Outcome {
+    initialized: usize,
+    processed: usize,
+    errors: usize,
+    completed: Option<CompletionReason>,
+}
+
+/// Activity which panics the first `panics` times it is initialized, or
+/// processes an event when it expects events
+struct Flaky {
+    panics: usize,
+    expects_events: bool,
+    outcome: Arc<Mutex<Outcome>>,
+}
+
+impl ActivityTrait for Flaky {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        let mut outcome = self.outcome.lock().unwrap();
+        outcome.initialized += 1;
+        if self.expects_events {
+            return State::SUSPEND;
+        }
+        if outcome.initialized <= self.panics {
+            drop(outcome);
+            panic!("initialize failed");
+        }
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        if event.is_none() {
+            return if self.expects_events {
+                State::SUSPEND
+            } else {
+                State::FINISH
+            };
+        }
+        let mut outcome = self.outcome.lock().unwrap();
+        outcome.processed += 1;
+        if outcome.processed <= self.panics {
+            drop(outcome);
+            panic!("process failed");
+        }
+        State::FINISH
+    }
+
+    fn on_error(&mut self, _: &ConstellationHandle, error: &ActivityError, _: &ActivityIdentifier) {
+        if let ActivityError::Panicked(_) = error {
+            self.outcome.lock().unwrap().errors += 1;
+        }
+    }
+
+    fn on_complete(&mut self, reason: CompletionReason) {
+        self.outcome.lock().unwrap().completed = Some(reason);
+    }
+}
+
+fn submit_flaky(
+    constellation: &mut dyn ConstellationTrait,
+    panics: usize,
+    retries: u32,
+    expects_events: bool,
+) -> (ActivityIdentifier, Arc<Mutex<Outcome>>) {
+    let outcome = Arc::new(Mutex::new(Outcome::default()));
+    let flaky = Flaky {
+        panics,
+        expects_events,
+        outcome: outcome.clone(),
+    };
+    let options = SubmitOptions {
+        expects_events,
+        retries,
+        ..Default::default()
+    };
+    let id = constellation
+        .submit_with(activity(flaky), &context(), options)
+        .unwrap();
+    (id, outcome)
+}
+
+fn initialize_is_retried(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let (_, recovers) = submit_flaky(constellation.as_mut(), 2, 2, false);
+    let (_, retired) = submit_flaky(constellation.as_mut(), 2, 1, false);
+    let (_, no_retries) = submit_flaky(constellation.as_mut(), 1, 0, false);
+    shut_down(constellation.as_mut());
+
+    let recovers = recovers.lock().unwrap();
+    assert_eq!((recovers.initialized, recovers.errors), (3, 2));
+    assert_eq!(recovers.completed, Some(CompletionReason::Finished));
+
+    let retired = retired.lock().unwrap();
+    assert_eq!((retired.initialized, retired.errors), (2, 2));
+    match &retired.completed {
+        Some(CompletionReason::Failed(message)) => assert!(message.contains("initialize failed")),
+        reason => panic!("Unexpected completion: {:?}", reason),
+    }
+
+    let no_retries = no_retries.lock().unwrap();
+    assert_eq!((no_retries.initialized, no_retries.errors), (1, 1));
+}
+
+test_both_modes!(initialize_is_retried, 3);
+
+fn process_is_retried(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let (id, outcome) = submit_flaky(constellation.as_mut(), 1, 1, true);
+    let src = constellation.allocate_external_id();
+    constellation.send(ping(&src, &id)).unwrap();
+    wait_for(|| outcome.lock().unwrap().errors == 1);
+
+    // It is not initialized again, and gets the next event
+    constellation.send(ping(&src, &id)).unwrap();
+    shut_down(constellation.as_mut());
+
+    let outcome = outcome.lock().unwrap();
+    assert_eq!((outcome.initialized, outcome.processed), (1, 2));
+    assert_eq!(outcome.completed, Some(CompletionReason::Finished));
+}
+
+test_both_modes!(process_is_retried, 2);
@@ -0,0 +1,105 @@
+//! `run(..)` activates the instance, runs the application on the master and
+//! shuts the instance down, also when the application panics, after which
+//! the panic is resumed
+#[macro_use]
+mod common;
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    run, ActivityIdentifier, ActivityTrait, CompletionReason, ConstellationHandle, Event,
+    SubmitOptions,
+};
+
+/// Activity which suspends until it gets an event, and records how it
+/// completed
+struct Recorded {
+    reasons: Arc<Mutex<Vec<CompletionReason>>>,
+}
+
+impl ActivityTrait for Recorded {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+
+    fn on_complete(&mut self, reason: CompletionReason) {
+        self.reasons.lock().unwrap().push(reason);
+    }
+}
+
+fn recorded(reasons: &Arc<Mutex<Vec<CompletionReason>>>) -> Arc<Mutex<dyn ActivityTrait>> {
+    activity(Recorded {
+        reasons: reasons.clone(),
+    })
+}
+
+fn result_of_master(mode: Mode, threads: i32) {
+    let reasons = Arc::new(Mutex::new(Vec::new()));
+
+    let result = run(mode, config(threads), |constellation| {
+        let options = SubmitOptions {
+            expects_events: true,
+            ..Default::default()
+        };
+        let id = constellation
+            .submit_with(recorded(&reasons), &context(), options)
+            .unwrap();
+        let src = constellation.allocate_external_id();
+        constellation.send(ping(&src, &id)).unwrap();
+
+        // done() does not wait for work left, it shuts the instance down
+        wait_for(|| !reasons.lock().unwrap().is_empty());
+        constellation.is_master().unwrap()
+    });
+
+    assert_eq!(result, Some(true));
+    assert_eq!(*reasons.lock().unwrap(), vec![CompletionReason::Finished]);
+}
+
+test_both_modes!(result_of_master, 2);
+
+fn shut_down_when_master_panics(mode: Mode, threads: i32) {
+    let reasons = Arc::new(Mutex::new(Vec::new()));
+
+    let caught = panic::catch_unwind(AssertUnwindSafe(|| {
+        run(mode, config(threads), |constellation| {
+            let options = SubmitOptions {
+                expects_events: true,
+                ..Default::default()
+            };
+            constellation
+                .submit_with(recorded(&reasons), &context(), options)
+                .unwrap();
+            wait_for(|| {
+                let snapshot = constellation.dump_state();
+                snapshot.threads.iter().any(|t| !t.suspended.is_empty())
+            });
+            panic!("master failed");
+        })
+    }));
+
+    // The panic of the application is resumed unchanged
+    let payload = caught.expect_err("run(..) returned after a panic");
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"master failed"));
+
+    // The instance was shut down before resuming, the waiting activity was
+    // told so
+    assert_eq!(*reasons.lock().unwrap(), vec![CompletionReason::Shutdown]);
+}
+
+test_both_modes!(shut_down_when_master_panics, 2);
@@ -0,0 +1,103 @@
+//! Adding and retiring executor threads at runtime without losing work
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::{
+    ActivityIdentifier, ActivityTrait, ConstellationHandle, ConstellationTrait, Event,
+    MultiThreadedConstellation, SubmitOptions,
+};
+
+static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+/// Activity which sleeps briefly and counts that it finished
+struct Counted;
+
+impl ActivityTrait for Counted {
+    fn cleanup(&mut self, _: &ConstellationHandle) {
+        FINISHED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        thread::sleep(Duration::from_micros(200));
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+#[test]
+fn scale_up_and_down() {
+    let mut constellation = MultiThreadedConstellation::new(config(1));
+    constellation.activate().unwrap();
+
+    for _ in 0..100 {
+        constellation
+            .submit(activity(Counted), &context(), true, false)
+            .unwrap();
+    }
+
+    constellation.add_executor_threads(3).unwrap();
+    assert_eq!(constellation.threads(), 4);
+
+    for _ in 0..300 {
+        constellation
+            .submit(activity(Counted), &context(), true, false)
+            .unwrap();
+    }
+    let waiters: Vec<ActivityIdentifier> = (0..8)
+        .map(|_| {
+            constellation
+                .submit(activity(Waiter), &context(), true, true)
+                .unwrap()
+        })
+        .collect();
+
+    // An activity which may not be stolen keeps its thread from retiring
+    let pinned = constellation
+        .submit_with(
+            activity(Waiter),
+            &context(),
+            SubmitOptions {
+                may_be_stolen: false,
+                expects_events: true,
+                thread_affinity: Some(3),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let pinned_name = pinned.to_string();
+    wait_for(|| {
+        constellation.dump_state().threads[3]
+            .suspended
+            .iter()
+            .any(|a| a.activity == pinned_name)
+    });
+    assert!(constellation.remove_executor_threads(3).is_err());
+    assert_eq!(constellation.threads(), 4);
+
+    constellation.send(ping(&pinned, &pinned)).unwrap();
+    wait_for(|| constellation.remove_executor_threads(3).is_ok());
+    assert_eq!(constellation.threads(), 1);
+
+    // The last thread is never retired
+    assert!(constellation.remove_executor_threads(1).is_err());
+
+    // Suspended activities were migrated and still get their events
+    for waiter in &waiters {
+        constellation.send(ping(waiter, waiter)).unwrap();
+    }
+    shut_down(&mut constellation);
+    assert_eq!(FINISHED.load(Ordering::SeqCst), 400);
+}
@@ -0,0 +1,267 @@
+//! The structured log of scheduling decisions, see `debug_json` in the
+//! configuration. Every line written must be one of the documented records,
+//! checked with a small parser for the flat JSON objects of the log.
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{new_constellation, ScheduleEntry, ScheduleRecord};
+
+/// A value of a field of a log line
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Str(String),
+    Num(u64),
+    Null,
+}
+
+/// Parse a JSON object whose values are strings, unsigned numbers or null,
+/// None if the line is anything else
+fn parse_object(line: &str) -> Option<Vec<(String, Value)>> {
+    let mut chars = line.chars().peekable();
+    let mut fields = Vec::new();
+
+    if chars.next()? != '{' {
+        return None;
+    }
+    loop {
+        let key = match parse_value(&mut chars)? {
+            Value::Str(key) => key,
+            _ => return None,
+        };
+        if chars.next()? != ':' {
+            return None;
+        }
+        fields.push((key, parse_value(&mut chars)?));
+
+        match chars.next()? {
+            ',' => continue,
+            '}' if chars.next().is_none() => return Some(fields),
+            _ => return None,
+        }
+    }
+}
+
+fn parse_value<I: Iterator<Item = char>>(chars: &mut std::iter::Peekable<I>) -> Option<Value> {
+    match *chars.peek()? {
+        '"' => {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next()? {
+                    '"' => return Some(Value::Str(s)),
+                    '\\' => match chars.next()? {
+                        '"' => s.push('"'),
+                        '\\' => s.push('\\'),
+                        'n' => s.push('\n'),
+                        'r' => s.push('\r'),
+                        't' => s.push('\t'),
+                        'u' => {
+                            let hex: String = chars.by_ref().take(4).collect();
+                            s.push(std::char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                        }
+                        _ => return None,
+                    },
+                    c if (c as u32) < 0x20 => return None,
+                    c => s.push(c),
+                }
+            }
+        }
+        'n' => {
+            let null: String = chars.by_ref().take(4).collect();
+            if null == "null" {
+                Some(Value::Null)
+            } else {
+                None
+            }
+        }
+        _ => {
+            let mut digits = String::new();
+            while let Some(c) = chars.peek() {
+                if !c.is_ascii_digit() {
+                    break;
+                }
+                digits.push(*c);
+                chars.next();
+            }
+            digits.parse().ok().map(Value::Num)
+        }
+    }
+}
+
+/// Turn the fields of a line into the documented record, None if the kind
+/// is unknown or a field is missing, unexpected or of the wrong type
+fn parse_entry(line: &str) -> Option<ScheduleEntry> {
+    let mut fields = parse_object(line)?;
+    let mut take = |name: &str| {
+        let index = fields.iter().position(|(key, _)| key == name)?;
+        Some(fields.remove(index).1)
+    };
+    let string = |value: Value| match value {
+        Value::Str(s) => Some(s),
+        _ => None,
+    };
+    let number = |value: Value| match value {
+        Value::Num(n) => Some(n),
+        _ => None,
+    };
+    let thread = |value: Value| match value {
+        Value::Num(n) => Some(Some(n as usize)),
+        Value::Null => Some(None),
+        _ => None,
+    };
+
+    let time_us = number(take("time_us")?)?;
+    let record = match string(take("kind")?)?.as_str() {
+        "submit" => ScheduleRecord::Submit {
+            activity: string(take("activity")?)?,
+            context: string(take("context")?)?,
+            thread: thread(take("thread")?)?,
+        },
+        "steal" => ScheduleRecord::Steal {
+            thread: number(take("thread")?)? as i32,
+            activities: number(take("activities")?)? as usize,
+        },
+        "event_route" => ScheduleRecord::EventRoute {
+            event: number(take("event")?)?,
+            destination: string(take("destination")?)?,
+            thread: thread(take("thread")?)?,
+        },
+        "orphan" => ScheduleRecord::Orphan {
+            destination: string(take("destination")?)?,
+            events: number(take("events")?)? as usize,
+        },
+        "rebalance" => ScheduleRecord::Rebalance {
+            thread: number(take("thread")?)? as i32,
+            activities: number(take("activities")?)? as usize,
+        },
+        _ => return None,
+    };
+
+    if fields.is_empty() {
+        Some(ScheduleEntry { time_us, record })
+    } else {
+        None
+    }
+}
+
+fn log_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("constellation-{}-{}.jsonl", name, process::id()))
+}
+
+#[test]
+fn every_line_is_a_documented_record() {
+    let path = log_path("schedule");
+    let mut config = config(3);
+    config.debug_json = Some(path.clone());
+
+    let mut constellation = new_constellation(Mode::MultiThreaded, config);
+    constellation.activate().unwrap();
+    for _ in 0..50 {
+        constellation
+            .submit(activity(Quick), &context(), true, false)
+            .unwrap();
+        let waiter = constellation
+            .submit(activity(Waiter), &context(), true, true)
+            .unwrap();
+        constellation.send(ping(&waiter, &waiter)).unwrap();
+    }
+    shut_down(constellation.as_mut());
+    drop(constellation);
+
+    let log = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).ok();
+
+    let entries: Vec<ScheduleEntry> = log
+        .lines()
+        .map(|line| match parse_entry(line) {
+            Some(entry) => {
+                assert_eq!(entry.to_json(), line);
+                entry
+            }
+            None => panic!("Not a documented record: {}", line),
+        })
+        .collect();
+
+    let submits = entries
+        .iter()
+        .filter(|entry| match &entry.record {
+            ScheduleRecord::Submit { thread, .. } => thread.is_some(),
+            _ => false,
+        })
+        .count();
+    assert_eq!(submits, 100);
+    assert!(entries.iter().any(|entry| match &entry.record {
+        ScheduleRecord::Steal { .. } => true,
+        _ => false,
+    }));
+    assert!(entries.iter().any(|entry| match &entry.record {
+        ScheduleRecord::EventRoute { .. } => true,
+        _ => false,
+    }));
+}
+
+#[test]
+fn records_round_trip() {
+    let awkward = "a \"quoted\" \\ label\nwith\ttabs and \u{1} control";
+    let records = vec![
+        ScheduleRecord::Submit {
+            activity: "CID:1:NID:0:AID:3".to_string(),
+            context: awkward.to_string(),
+            thread: None,
+        },
+        ScheduleRecord::Steal {
+            thread: 2,
+            activities: 4,
+        },
+        ScheduleRecord::EventRoute {
+            event: 7,
+            destination: awkward.to_string(),
+            thread: Some(1),
+        },
+        ScheduleRecord::Orphan {
+            destination: "CID:1:NID:0:AID:9".to_string(),
+            events: 3,
+        },
+        ScheduleRecord::Rebalance {
+            thread: 0,
+            activities: 12,
+        },
+    ];
+
+    for record in records {
+        let entry = ScheduleEntry::now(record);
+        let line = entry.to_json();
+        assert!(!line.contains('\n'));
+        assert_eq!(parse_entry(&line), Some(entry));
+    }
+
+    assert_eq!(parse_entry("{\"time_us\":1,\"kind\":\"unknown\"}"), None);
+    assert_eq!(
+        parse_entry("{\"time_us\":1,\"kind\":\"steal\",\"thread\":1,\"activities\":1,\"x\":1}"),
+        None
+    );
+    assert_eq!(
+        parse_entry("{\"time_us\":1,\"kind\":\"steal\",\"thread\":1}"),
+        None
+    );
+}
+
+#[cfg(feature = "config-file")]
+#[test]
+fn serde_reads_the_same_records() {
+    let entry = ScheduleEntry::now(ScheduleRecord::EventRoute {
+        event: 7,
+        destination: "CID:1:NID:0:AID:3".to_string(),
+        thread: None,
+    });
+    let line = entry.to_json();
+
+    let read: ScheduleEntry = serde_json::from_str(&line).unwrap();
+    assert_eq!(read, entry);
+    assert_eq!(parse_entry(&line), Some(read));
+}
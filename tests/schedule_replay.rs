@@ -0,0 +1,124 @@
+//! A recorded schedule is replayed: workers send their number to a collector,
+//! and every replay of a recording delivers the numbers in the recorded
+//! order, also when that order differs from the order of submission
+mod common;
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ConstellationSpawn, HandlerActivity, HandlerControl, PayloadTrait,
+    PayloadTraitClone, SubmitOptions,
+};
+
+const THREADS: i32 = 4;
+const WORKERS: u64 = 8;
+const RECORDED_RUNS: usize = 20;
+const REPLAYS: usize = 100;
+
+/// Numbers sent by the workers, and the order sent by the collector
+#[derive(Debug, Clone)]
+struct Numbers(Vec<u64>);
+
+impl PayloadTrait for Numbers {}
+
+impl PayloadTraitClone for Numbers {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for Numbers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+/// Some work which takes longer for the workers submitted first
+fn work(worker: u64) -> u64 {
+    (0..(WORKERS - worker) * 5_000).fold(worker, |acc, i| acc.wrapping_mul(31).wrapping_add(i))
+}
+
+/// Run the workers and the collector once, recording to or replaying from
+/// the trace
+///
+/// # Returns
+/// * `Vec<u64>` - The numbers of the workers, in the order the collector
+/// received them
+fn run(trace: &Path, replay: bool) -> Vec<u64> {
+    let mut config = config(THREADS);
+    if replay {
+        config.replay_schedule = Some(trace.to_path_buf());
+    } else {
+        config.record_schedule = Some(trace.to_path_buf());
+    }
+    let mut constellation = new_constellation(Mode::MultiThreaded, config);
+    constellation.activate().unwrap();
+
+    let target = constellation.allocate_external_id();
+    let results = constellation.subscribe(&target);
+    let collector = HandlerActivity::new(Vec::new(), move |order: &mut Vec<u64>, event, _| {
+        order.push(event.payload_as::<Numbers>().unwrap().0[0]);
+        if order.len() as u64 == WORKERS {
+            HandlerControl::FinishWith(Box::new(Numbers(order.clone())), target.clone())
+        } else {
+            HandlerControl::Continue
+        }
+    });
+    let options = SubmitOptions {
+        expects_events: true,
+        ..Default::default()
+    };
+    let collector = constellation
+        .submit_with(collector, &context(), options)
+        .unwrap();
+    for worker in 0..WORKERS {
+        constellation
+            .spawn_with_result(
+                &context(),
+                move |_, _| Box::new(Numbers(vec![worker, work(worker)])),
+                collector.clone(),
+            )
+            .unwrap();
+    }
+
+    let order = results
+        .recv_timeout(TIMEOUT)
+        .unwrap()
+        .into_payload_as::<Numbers>()
+        .unwrap()
+        .0;
+    shut_down(constellation.as_mut());
+    order
+}
+
+fn trace_path() -> PathBuf {
+    std::env::temp_dir().join(format!("constellation-replay-{}.trace", process::id()))
+}
+
+#[test]
+fn replays_are_identical() {
+    let trace = trace_path();
+
+    // Prefer a run in which the numbers arrived out of order, the ordering
+    // bug a replay has to reproduce
+    let mut recorded = run(&trace, false);
+    for _ in 1..RECORDED_RUNS {
+        if recorded.windows(2).any(|w| w[0] > w[1]) {
+            break;
+        }
+        recorded = run(&trace, false);
+    }
+    assert!(fs::read_to_string(&trace)
+        .unwrap()
+        .starts_with("constellation-schedule-trace 1"));
+
+    for replay in 0..REPLAYS {
+        assert_eq!(run(&trace, true), recorded, "Replay {} diverged", replay);
+    }
+    fs::remove_file(&trace).ok();
+}
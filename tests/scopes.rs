@@ -0,0 +1,122 @@
+//! Structured cancellation with scopes: a speculative search runs two
+//! branches in their own scope, cancels the losing one when the other finds
+//! the answer, and no work is left behind
+#[macro_use]
+mod common;
+
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, Event, SubmitOptions,
+};
+
+const DEPTH: u32 = 4;
+
+/// Node of a search tree. Inner nodes spawn two children in the scope they
+/// run in, the leaves of the winning branch report to `result`, the leaves
+/// of the losing branch wait for an answer which never comes.
+struct Branch {
+    depth: u32,
+    wins: bool,
+    result: ActivityIdentifier,
+}
+
+impl ActivityTrait for Branch {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        if self.depth > 0 {
+            for _ in 0..2 {
+                let child = Branch {
+                    depth: self.depth - 1,
+                    wins: self.wins,
+                    result: self.result.clone(),
+                };
+                constellation
+                    .submit(activity(child), &context(), true, false)
+                    .unwrap();
+            }
+            return State::FINISH;
+        }
+
+        if self.wins {
+            constellation.send(ping(id, &self.result)).unwrap();
+            return State::FINISH;
+        }
+
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        if self.depth == 0 && !self.wins {
+            return State::SUSPEND;
+        }
+
+        State::FINISH
+    }
+}
+
+fn speculative_search(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let result = constellation.allocate_external_id();
+    let answers = constellation.subscribe(&result);
+
+    let winning = constellation.create_scope();
+    let losing = constellation.create_scope();
+    for (scope, wins) in vec![(losing, false), (winning, true)] {
+        let branch = Branch {
+            depth: DEPTH,
+            wins,
+            result: result.clone(),
+        };
+        constellation
+            .submit_in_scope(
+                scope,
+                activity(branch),
+                &context(),
+                SubmitOptions::default(),
+            )
+            .unwrap();
+    }
+
+    // Every leaf of the winning branch answers
+    for _ in 0..(1 << DEPTH) {
+        answers.recv_timeout(TIMEOUT).unwrap();
+    }
+
+    // The losing leaves are still waiting
+    let leaves = 1 << DEPTH;
+    wait_for(|| {
+        let snapshot = constellation.dump_state();
+        snapshot
+            .threads
+            .iter()
+            .map(|t| t.suspended.len())
+            .sum::<usize>()
+            == leaves
+    });
+    assert!(constellation.done().is_err());
+
+    constellation.cancel_scope(losing);
+    constellation
+        .wait_until_idle(Duration::from_secs(5))
+        .unwrap();
+    assert!(constellation.dump_state().is_empty());
+    assert_eq!(constellation.done(), Ok(true));
+}
+
+test_both_modes!(speculative_search, 4);
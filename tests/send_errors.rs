@@ -0,0 +1,98 @@
+//! `send(..)` reports events which can not be delivered, instead of keeping
+//! them as orphans forever. SendError::QueueFull is not tested, event queues
+//! are not bounded yet.
+#[macro_use]
+mod common;
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{new_constellation, ConstellationError, SendError};
+
+fn unknown_destination(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+    let mut other = new_constellation(mode, config(threads));
+    other.activate().unwrap();
+
+    // Generated by another instance
+    let src = constellation.allocate_external_id();
+    let elsewhere = other
+        .submit(activity(Waiter), &context(), false, true)
+        .unwrap();
+    assert_eq!(
+        constellation.send(ping(&src, &elsewhere)),
+        Err(SendError::UnknownDestination(elsewhere.clone()))
+    );
+
+    let own = other.allocate_external_id();
+    other.send(ping(&own, &elsewhere)).unwrap();
+    shut_down(other.as_mut());
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(unknown_destination, 2);
+
+fn destination_finished(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let finished = constellation
+        .submit(activity(Quick), &context(), false, false)
+        .unwrap();
+    constellation.wait_until_idle(TIMEOUT).unwrap();
+
+    let src = constellation.allocate_external_id();
+    assert_eq!(
+        constellation.send(ping(&src, &finished)),
+        Err(SendError::DestinationFinished(finished.clone()))
+    );
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(destination_finished, 2);
+
+fn not_activated_or_shut_down(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    let mut other = new_constellation(mode, config(threads));
+    other.activate().unwrap();
+    let src = other.allocate_external_id();
+    let dst = other.allocate_external_id();
+
+    assert_eq!(
+        constellation.send(ping(&src, &dst)),
+        Err(SendError::Constellation(ConstellationError::NotActivated))
+    );
+
+    constellation.activate().unwrap();
+    let src = constellation.allocate_external_id();
+    shut_down(constellation.as_mut());
+    assert_eq!(
+        constellation.send(ping(&src, &src)),
+        Err(SendError::Constellation(
+            ConstellationError::AlreadyShutDown
+        ))
+    );
+
+    shut_down(other.as_mut());
+}
+
+test_both_modes!(not_activated_or_shut_down, 2);
+
+fn unplaced_destination_is_not_an_error(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    // The event is kept until the waiter is placed and suspends
+    constellation.pause().unwrap();
+    let waiter = constellation
+        .submit(activity(Waiter), &context(), false, true)
+        .unwrap();
+    let src = constellation.allocate_external_id();
+    assert_eq!(constellation.send(ping(&src, &waiter)), Ok(()));
+    constellation.resume().unwrap();
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(unplaced_destination_is_not_an_error, 2);
@@ -0,0 +1,118 @@
+//! Payloads shared through an ArcPayload are not copied when they are sent
+//! to many activities, every activity gets the same data
+#[macro_use]
+mod common;
+
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ArcPayload, ConstellationHandle, Event,
+    PayloadTrait, PayloadTraitClone,
+};
+
+const WORKERS: usize = 16;
+
+/// Number of times a Table was cloned
+static CLONES: AtomicUsize = AtomicUsize::new(0);
+
+/// Large lookup table, counting its clones
+#[derive(Debug)]
+struct Table(Vec<u8>);
+
+impl Clone for Table {
+    fn clone(&self) -> Table {
+        CLONES.fetch_add(1, Ordering::SeqCst);
+        Table(self.0.clone())
+    }
+}
+
+impl PayloadTrait for Table {}
+
+impl PayloadTraitClone for Table {
+    fn clone_box(&self) -> Box<dyn PayloadTrait> {
+        Box::new(self.clone())
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "table of {} bytes", self.0.len())
+    }
+}
+
+/// Activity which keeps the table it receives
+struct Worker {
+    tables: Arc<Mutex<Vec<Arc<Table>>>>,
+}
+
+impl ActivityTrait for Worker {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        State::SUSPEND
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        event: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        let event = match event {
+            Some(event) => event,
+            None => return State::SUSPEND,
+        };
+        let table = event.payload_as::<ArcPayload<Table>>().unwrap();
+        self.tables.lock().unwrap().push(table.arc().clone());
+        State::FINISH
+    }
+}
+
+fn one_copy_for_all_workers(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let tables = Arc::new(Mutex::new(Vec::new()));
+    let workers: Vec<ActivityIdentifier> = (0..WORKERS)
+        .map(|_| {
+            let worker = Worker {
+                tables: tables.clone(),
+            };
+            constellation
+                .submit(activity(worker), &context(), false, true)
+                .unwrap()
+        })
+        .collect();
+
+    let table = Arc::new(Table(vec![7; 1 << 20]));
+    let clones = CLONES.load(Ordering::SeqCst);
+    let src = constellation.allocate_external_id();
+
+    // Half through the multicast, half one by one
+    let (multicast, single) = workers.split_at(WORKERS / 2);
+    for worker in single {
+        let event = Event::shared(table.clone(), src.clone(), worker.clone());
+        constellation.send(event).unwrap();
+    }
+    wait_for(|| tables.lock().unwrap().len() == single.len());
+    let payload = Box::new(ArcPayload::new(table.clone()));
+    assert_eq!(
+        constellation.send_to_context(payload, src, &context()),
+        Ok(multicast.len())
+    );
+    shut_down(constellation.as_mut());
+
+    // Every worker holds the one table, the events holding it are gone
+    let tables = tables.lock().unwrap();
+    assert_eq!(tables.len(), WORKERS);
+    assert!(tables.iter().all(|t| Arc::ptr_eq(t, &table)));
+    assert_eq!(Arc::strong_count(&table), WORKERS + 1);
+    assert_eq!(CLONES.load(Ordering::SeqCst), clones);
+}
+
+test_both_modes!(one_copy_for_all_workers, 3);
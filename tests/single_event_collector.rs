@@ -0,0 +1,109 @@
+//! Checks for the event of a SingleEventCollector, taking it and awaiting it
+#[macro_use]
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{new_constellation, ActivityTrait, SingleEventCollector, SubmitOptions};
+
+fn collect(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let sec = SingleEventCollector::new();
+    let aid = constellation
+        .submit_with(
+            sec.clone() as Arc<Mutex<dyn ActivityTrait>>,
+            &context(),
+            SubmitOptions {
+                may_be_stolen: false,
+                expects_events: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    // Not ready
+    assert!(!SingleEventCollector::peek(sec.clone()));
+    assert!(SingleEventCollector::try_get_event(sec.clone()).is_none());
+
+    constellation.send(ping(&aid, &aid)).unwrap();
+
+    // Ready, peeking does not consume the event
+    wait_for(|| SingleEventCollector::peek(sec.clone()));
+    assert!(SingleEventCollector::peek(sec.clone()));
+    let event = SingleEventCollector::try_get_event(sec.clone()).unwrap();
+    assert_eq!(event.get_dst(), aid);
+
+    // Taken
+    assert!(!SingleEventCollector::peek(sec.clone()));
+    assert!(SingleEventCollector::try_get_event(sec).is_none());
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(collect, 2);
+
+fn into_event(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let sec = SingleEventCollector::new();
+    let aid = constellation
+        .submit(
+            sec.clone() as Arc<Mutex<dyn ActivityTrait>>,
+            &context(),
+            false,
+            true,
+        )
+        .unwrap();
+    constellation.send(ping(&aid, &aid)).unwrap();
+    wait_for(|| SingleEventCollector::peek(sec.clone()));
+
+    // Taken from the collector while another reference is left
+    let other = sec.clone();
+    let event = SingleEventCollector::into_event(sec).unwrap();
+    assert_eq!(event.get_dst(), aid);
+    assert!(!SingleEventCollector::peek(other.clone()));
+    assert!(SingleEventCollector::into_event(other).is_none());
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(into_event, 2);
+
+#[cfg(feature = "futures")]
+fn awaited(mode: Mode, threads: i32) {
+    use common::executor::{block_on, Counts};
+    use std::thread;
+    use std::time::Duration;
+
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let sec = SingleEventCollector::new();
+    let aid = constellation
+        .submit(
+            sec.clone() as Arc<Mutex<dyn ActivityTrait>>,
+            &context(),
+            false,
+            true,
+        )
+        .unwrap();
+    let future = SingleEventCollector::event_future(sec);
+    let awaiting = thread::spawn(move || block_on(future));
+
+    // Polled once, then parked until the event wakes it
+    thread::sleep(Duration::from_millis(50));
+    constellation.send(ping(&aid, &aid)).unwrap();
+    let (event, counts) = awaiting.join().unwrap();
+    assert_eq!(event.get_dst(), aid);
+    assert_eq!(counts, Counts { polls: 2, wakes: 1 });
+
+    shut_down(constellation.as_mut());
+}
+
+#[cfg(feature = "futures")]
+test_both_modes!(awaited, 2);
@@ -0,0 +1,80 @@
+//! Activities picked by the steal strategy comparing their size hints, see
+//! `ActivityTrait::size_hint()`
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, Event, StealStrategy,
+};
+
+const SIZES: [usize; 6] = [3, 1, 6, 2, 5, 4];
+
+/// Activity with the given size hint, which records it when it runs
+struct Sized {
+    size: usize,
+    order: Arc<Mutex<Vec<usize>>>,
+}
+
+impl ActivityTrait for Sized {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.order.lock().unwrap().push(self.size);
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+
+    fn size_hint(&self) -> usize {
+        self.size
+    }
+}
+
+/// Queue the activities while the instance is paused and run them
+///
+/// # Returns
+/// * `Vec<usize>` - The size hints in the order the activities ran
+fn run_order(strategy: StealStrategy) -> Vec<usize> {
+    let mut config = config(1);
+    config.local_steal_strategy = strategy;
+    let mut constellation = new_constellation(Mode::SingleThreaded, config);
+    constellation.activate().unwrap();
+    constellation.pause().unwrap();
+
+    let order = Arc::new(Mutex::new(Vec::new()));
+    for size in SIZES.iter() {
+        let sized = Sized {
+            size: *size,
+            order: order.clone(),
+        };
+        constellation
+            .submit(activity(sized), &context(), true, false)
+            .unwrap();
+    }
+    constellation.resume().unwrap();
+    shut_down(constellation.as_mut());
+
+    let order = order.lock().unwrap().clone();
+    order
+}
+
+#[test]
+fn biggest_first() {
+    assert_eq!(run_order(StealStrategy::BIGGEST), vec![6, 5, 4, 3, 2, 1]);
+}
+
+#[test]
+fn smallest_first() {
+    assert_eq!(run_order(StealStrategy::SMALLEST), vec![1, 2, 3, 4, 5, 6]);
+}
@@ -0,0 +1,115 @@
+//! `dump_state()` lists the pending, suspended and unplaced activities, the
+//! queued events and the dead-lettered events of a known state, and `done()`
+//! embeds the snapshot in its report of the work left
+#[macro_use]
+mod common;
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ConstellationError, ConstellationSnapshot,
+    ConstellationTrait,
+};
+
+/// The activities listed as pending in the snapshot, on a thread or
+/// unplaced, sorted
+fn pending(snapshot: &ConstellationSnapshot) -> Vec<String> {
+    let mut pending: Vec<String> = snapshot
+        .threads
+        .iter()
+        .flat_map(|t| t.pending.iter())
+        .chain(snapshot.unplaced.iter())
+        .map(|a| a.activity.clone())
+        .collect();
+    pending.sort();
+    pending
+}
+
+/// The number of events queued on the threads for the destination
+fn queued_for(snapshot: &ConstellationSnapshot, dst: &ActivityIdentifier) -> usize {
+    snapshot
+        .threads
+        .iter()
+        .flat_map(|t| t.events.iter())
+        .filter(|e| e.destination == dst.to_string())
+        .map(|e| e.events)
+        .sum()
+}
+
+fn known_state(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+    assert!(constellation.dump_state().is_empty());
+
+    let waiter = constellation
+        .submit(activity(Waiter), &context(), false, true)
+        .unwrap();
+    wait_for(|| {
+        let snapshot = constellation.dump_state();
+        snapshot.threads.iter().any(|t| !t.suspended.is_empty())
+    });
+
+    // Nothing runs while paused, so the activities and events stay queued
+    constellation.pause().unwrap();
+    assert!(constellation.wait_until_paused(TIMEOUT).unwrap());
+    let mut quick: Vec<String> = (0..3)
+        .map(|_| {
+            constellation
+                .submit(activity(Quick), &context(), false, false)
+                .unwrap()
+                .to_string()
+        })
+        .collect();
+    quick.sort();
+    let src = constellation.allocate_external_id();
+    for _ in 0..2 {
+        constellation.send(ping(&src, &waiter)).unwrap();
+    }
+
+    let snapshot = constellation.dump_state();
+    assert_eq!(snapshot.threads.len(), threads as usize);
+    assert!(snapshot.skipped.is_empty(), "{:?}", snapshot.skipped);
+    assert_eq!(pending(&snapshot), quick);
+
+    let suspended: Vec<_> = snapshot
+        .threads
+        .iter()
+        .flat_map(|t| t.suspended.iter())
+        .collect();
+    assert_eq!(suspended.len(), 1);
+    assert_eq!(suspended[0].activity, waiter.to_string());
+    assert_eq!(suspended[0].context, CONTEXT);
+    assert!(suspended[0].suspended_us.is_some());
+    assert!(suspended[0].suspended_us.unwrap() <= suspended[0].age_us);
+    assert_eq!(snapshot.activity(&waiter.to_string()), Some(suspended[0]));
+
+    assert_eq!(queued_for(&snapshot, &waiter), 2);
+    assert!(snapshot.orphaned_events.is_empty());
+    assert_eq!(snapshot.dead_lettered, 0);
+
+    let report = snapshot.to_string();
+    assert!(report.contains(&format!("suspended {} ({})", waiter, CONTEXT)));
+    assert!(report.contains(&format!("2 events for {}", waiter)));
+    assert!(report.contains("0 dead-lettered events"));
+
+    // done() does not shut down with work left, and embeds the snapshot
+    match constellation.done() {
+        Err(ConstellationError::WorkLeft(left)) => {
+            let embedded = left.snapshot.expect("No snapshot in the report");
+            assert_eq!(pending(&embedded), quick);
+            assert_eq!(queued_for(&embedded, &waiter), 2);
+        }
+        result => panic!("Unexpected result of done(): {:?}", result),
+    }
+
+    // The first event finishes the waiter, the second is dead-lettered
+    constellation.resume().unwrap();
+    wait_for(|| {
+        let snapshot = constellation.dump_state();
+        snapshot.is_empty() && snapshot.dead_lettered == 1
+    });
+
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(known_state, 2);
@@ -0,0 +1,110 @@
+//! An activity is not starved by a stream of newer submissions, pending
+//! activities run in the order they were submitted
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, Event,
+};
+
+/// Number of activities the stream submits in total
+const STREAM: usize = 2000;
+
+/// Activities submitted after the watched one which may still run before it,
+/// because they were being submitted at the same time
+const SLACK: usize = 2;
+
+/// Activity which records its identifier, and submits two more streamers
+/// while the budget lasts, so new activities keep arriving faster than they
+/// run
+struct Streamer {
+    budget: Arc<AtomicUsize>,
+    order: Arc<Mutex<Vec<u64>>>,
+}
+
+impl ActivityTrait for Streamer {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(
+        &mut self,
+        constellation: &ConstellationHandle,
+        id: &ActivityIdentifier,
+    ) -> State {
+        self.order.lock().unwrap().push(id.activity_id);
+        for _ in 0..2 {
+            // Only the single executor thread spends the budget
+            let left = self.budget.load(Ordering::SeqCst);
+            if left == 0 {
+                break;
+            }
+            self.budget.store(left - 1, Ordering::SeqCst);
+            let child = Streamer {
+                budget: self.budget.clone(),
+                order: self.order.clone(),
+            };
+            constellation
+                .submit(activity(child), &context(), true, false)
+                .unwrap();
+        }
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+#[test]
+fn not_starved_by_newer_submissions() {
+    let mut constellation = new_constellation(Mode::SingleThreaded, config(1));
+    constellation.activate().unwrap();
+
+    let budget = Arc::new(AtomicUsize::new(STREAM));
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let streamer = Streamer {
+        budget: budget.clone(),
+        order: order.clone(),
+    };
+    constellation
+        .submit(activity(streamer), &context(), true, false)
+        .unwrap();
+    wait_for(|| order.lock().unwrap().len() >= 10);
+
+    // Any activity works as the watched one, a streamer records itself
+    let watched = Streamer {
+        budget: Arc::new(AtomicUsize::new(0)),
+        order: order.clone(),
+    };
+    let watched = constellation
+        .submit(activity(watched), &context(), true, false)
+        .unwrap();
+    shut_down(constellation.as_mut());
+
+    let order = order.lock().unwrap();
+    let position = order
+        .iter()
+        .position(|id| *id == watched.activity_id)
+        .unwrap();
+    let newer_first = order[..position]
+        .iter()
+        .filter(|id| **id > watched.activity_id)
+        .count();
+    assert!(
+        newer_first <= SLACK,
+        "{} newer activities ran first, at position {} of {}",
+        newer_first,
+        position,
+        order.len()
+    );
+    assert!(position < order.len() - 1, "Ran last of {}", order.len());
+}
@@ -0,0 +1,48 @@
+//! Stealing and redistributing activities in batches of up to
+//! `steal_batch_size`, with thousands of tiny activities
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::*;
+use constellation_rust::{ConstellationTrait, MultiThreadedConstellation, StealStats};
+
+const ACTIVITIES: usize = 5_000;
+
+/// Run ACTIVITIES tiny activities with the given batch size
+///
+/// # Returns
+/// * `(StealStats, Duration)` - The steal statistics, and how long it took
+fn run(batch_size: usize) -> (StealStats, Duration) {
+    let mut config = config(4);
+    config.steal_batch_size = batch_size;
+    let mut constellation = MultiThreadedConstellation::new(config);
+    constellation.activate().unwrap();
+
+    let start = Instant::now();
+    for _ in 0..ACTIVITIES {
+        constellation
+            .submit(activity(Quick), &context(), true, false)
+            .unwrap();
+    }
+    shut_down(&mut constellation);
+
+    (constellation.steal_stats(), start.elapsed())
+}
+
+#[test]
+fn batch_size_1_vs_32() {
+    let (single, single_time) = run(1);
+    let (batched, batched_time) = run(32);
+    println!("Batch size 1: {}, took {:?}", single, single_time);
+    println!("Batch size 32: {}, took {:?}", batched, batched_time);
+
+    // Every activity is taken from a work queue once
+    assert_eq!(single.items, ACTIVITIES as u64);
+    assert_eq!(batched.items, ACTIVITIES as u64);
+
+    assert_eq!(single.items_per_steal(), 1.0);
+    assert!(batched.items_per_steal() > 1.0, "{}", batched);
+    assert!(batched.items_per_steal() <= 32.0, "{}", batched);
+    assert!(batched.steals < single.steals);
+}
@@ -0,0 +1,43 @@
+//! Parsing and displaying steal strategies, as done for environment
+//! variables and configuration files
+use constellation_rust::{ParseStealStrategyError, StealStrategy};
+
+#[test]
+fn parse_names() {
+    let table = [
+        ("BIGGEST", StealStrategy::BIGGEST),
+        ("SMALLEST", StealStrategy::SMALLEST),
+        ("biggest", StealStrategy::BIGGEST),
+        ("smallest", StealStrategy::SMALLEST),
+        ("Biggest", StealStrategy::BIGGEST),
+        ("sMaLLesT", StealStrategy::SMALLEST),
+        ("  biggest\n", StealStrategy::BIGGEST),
+    ];
+
+    for (name, strategy) in table.iter() {
+        assert_eq!(
+            name.parse::<StealStrategy>().as_ref(),
+            Ok(strategy),
+            "{:?}",
+            name
+        );
+    }
+}
+
+#[test]
+fn parse_unknown_names() {
+    for name in ["", "LARGEST", "big", "BIGGEST SMALLEST", "SMALLEST,"].iter() {
+        let error = name.parse::<StealStrategy>().unwrap_err();
+        assert_eq!(error, ParseStealStrategyError(name.to_string()));
+        assert!(error.to_string().contains("expected BIGGEST or SMALLEST"));
+    }
+}
+
+#[test]
+fn display_round_trip() {
+    for strategy in [StealStrategy::BIGGEST, StealStrategy::SMALLEST].iter() {
+        let name = strategy.to_string();
+        assert_eq!(name, name.to_uppercase());
+        assert_eq!(name.parse::<StealStrategy>().as_ref(), Ok(strategy));
+    }
+}
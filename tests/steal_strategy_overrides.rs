@@ -0,0 +1,107 @@
+//! Contexts with a steal strategy override pick their activities by their
+//! own strategy, see `steal_strategy_overrides` in the configuration
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, Context, ContextVec,
+    Event, StealStrategy,
+};
+
+const SIZES: [usize; 6] = [3, 1, 6, 2, 5, 4];
+
+/// Activity with the given size hint, which records its context and size
+/// when it runs
+struct Sized {
+    label: &'static str,
+    size: usize,
+    order: Arc<Mutex<Vec<(&'static str, usize)>>>,
+}
+
+impl ActivityTrait for Sized {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.order.lock().unwrap().push((self.label, self.size));
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+
+    fn size_hint(&self) -> usize {
+        self.size
+    }
+}
+
+/// Queue activities of the "io" and "compute" contexts while the instance is
+/// paused, with "io" overridden to pick the smallest activity first
+///
+/// # Returns
+/// * `(Vec<usize>, Vec<usize>)` - The size hints of the "io" and the
+/// "compute" activities in the order they ran
+fn run_order(default: StealStrategy) -> (Vec<usize>, Vec<usize>) {
+    let mut config = config(1);
+    let mut context_vec = ContextVec::new();
+    context_vec.append(&Context::new("io"));
+    context_vec.append(&Context::new("compute"));
+    config.context_vec = context_vec;
+    config.local_steal_strategy = default;
+    config
+        .steal_strategy_overrides
+        .insert("io".to_string(), StealStrategy::SMALLEST);
+
+    let mut constellation = new_constellation(Mode::SingleThreaded, config);
+    constellation.activate().unwrap();
+    constellation.pause().unwrap();
+
+    let order = Arc::new(Mutex::new(Vec::new()));
+    for size in SIZES.iter() {
+        for label in ["io", "compute"].iter() {
+            let sized = Sized {
+                label,
+                size: *size,
+                order: order.clone(),
+            };
+            constellation
+                .submit(activity(sized), &Context::new(label), true, false)
+                .unwrap();
+        }
+    }
+    constellation.resume().unwrap();
+    shut_down(constellation.as_mut());
+
+    let order = order.lock().unwrap();
+    let of = |label| {
+        order
+            .iter()
+            .filter(|(l, _)| *l == label)
+            .map(|(_, size)| *size)
+            .collect()
+    };
+    (of("io"), of("compute"))
+}
+
+#[test]
+fn opposite_strategies() {
+    let (io, compute) = run_order(StealStrategy::BIGGEST);
+    assert_eq!(io, vec![1, 2, 3, 4, 5, 6]);
+    assert_eq!(compute, vec![6, 5, 4, 3, 2, 1]);
+}
+
+#[test]
+fn override_same_as_default() {
+    let (io, compute) = run_order(StealStrategy::SMALLEST);
+    assert_eq!(io, vec![1, 2, 3, 4, 5, 6]);
+    assert_eq!(compute, vec![1, 2, 3, 4, 5, 6]);
+}
@@ -0,0 +1,75 @@
+//! `submit(..)` validates its inputs before anything is submitted, and
+//! rejects activities with an error instead. ConstellationError::QueueFull is
+//! not tested, work queues are not bounded yet.
+#[macro_use]
+mod common;
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ConstellationError, Context, Placement, SubmitOptions,
+};
+
+fn rejected(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    assert_eq!(
+        constellation
+            .submit(activity(Quick), &context(), false, false)
+            .err(),
+        Some(ConstellationError::NotActivated)
+    );
+
+    constellation.activate().unwrap();
+    let before = constellation
+        .submit(activity(Quick), &context(), false, false)
+        .unwrap();
+
+    let unknown = Context::new("unknown");
+    assert_eq!(
+        constellation
+            .submit(activity(Quick), &unknown, false, false)
+            .err(),
+        Some(ConstellationError::UnknownContext("unknown".to_string()))
+    );
+
+    let affinity = SubmitOptions {
+        thread_affinity: Some(threads as usize),
+        ..Default::default()
+    };
+    assert_eq!(
+        constellation
+            .submit_with(activity(Quick), &context(), affinity)
+            .err(),
+        Some(ConstellationError::InvalidThreadAffinity(threads as usize))
+    );
+
+    // There is a single node
+    let placement = SubmitOptions {
+        placement: Placement::Node(1),
+        ..Default::default()
+    };
+    assert_eq!(
+        constellation
+            .submit_with(activity(Quick), &context(), placement)
+            .err(),
+        Some(ConstellationError::UnsatisfiablePlacement(Placement::Node(
+            1
+        )))
+    );
+
+    // The rejected activities did not even take an identifier
+    let after = constellation
+        .submit(activity(Quick), &context(), false, false)
+        .unwrap();
+    assert_eq!(after.activity_id, before.activity_id + 1);
+
+    shut_down(constellation.as_mut());
+    assert_eq!(
+        constellation
+            .submit(activity(Quick), &context(), false, false)
+            .err(),
+        Some(ConstellationError::AlreadyShutDown)
+    );
+}
+
+test_both_modes!(rejected, 2);
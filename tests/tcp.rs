@@ -0,0 +1,133 @@
+//! Two nodes in this process, communicating over TCP sockets on localhost
+mod common;
+
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use common::remote::*;
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, run_worker, ActivityIdentifier, Communication, ConstellationConfiguration,
+    ConstellationTrait, Event, MultiThreadedConstellation, TcpComm,
+};
+
+const ACTIVITIES: usize = 4;
+
+/// Addresses of two nodes on localhost, on ports which were free
+fn peers() -> Vec<String> {
+    (0..2)
+        .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+        .collect::<Vec<TcpListener>>()
+        .iter()
+        .map(|listener| listener.local_addr().unwrap().to_string())
+        .collect()
+}
+
+fn receive(comm: &TcpComm, tag: i32) -> (i32, Vec<u8>) {
+    let mut message = None;
+    wait_for(|| {
+        message = comm.try_receive(tag);
+        message.is_some()
+    });
+    message.unwrap()
+}
+
+#[test]
+fn messages_and_barrier() {
+    let peers = peers();
+    let first = TcpComm::new(&peers, 0).unwrap();
+    let second = TcpComm::new(&peers, 1).unwrap();
+    assert_eq!((first.rank(), second.rank()), (0, 1));
+    assert_eq!(first.size(), 2);
+
+    first.send(1, 3, b"ping");
+    assert_eq!(receive(&second, 3), (0, b"ping".to_vec()));
+    second.send(0, 3, b"pong");
+    second.send(0, 4, &[]);
+    assert_eq!(receive(&first, 4), (1, Vec::new()));
+    assert_eq!(receive(&first, 3), (1, b"pong".to_vec()));
+
+    let other = thread::spawn(move || second.barrier());
+    first.barrier();
+    other.join().unwrap();
+}
+
+/// Configuration of the node with the given rank, see `node_config()`
+fn node_config_for(peers: &[String], rank: usize) -> Box<ConstellationConfiguration> {
+    let mut config = node_config();
+    config.number_of_nodes = 2;
+    config.tcp_peers = Some(peers.to_vec());
+    config.tcp_rank = rank;
+    config
+}
+
+#[test]
+fn two_nodes_exchange_events() {
+    let peers = peers();
+
+    // The worker selects TCP through its configuration
+    let mut worker = new_constellation(Mode::Distributed, node_config_for(&peers, 1));
+    let worker = thread::spawn(move || {
+        assert_eq!(worker.activate(), Ok(false));
+        run_worker(worker.as_mut())
+    });
+
+    // The master is created directly, to see what it gave to the worker
+    let comm = Arc::new(TcpComm::new(&peers, 0).unwrap());
+    let mut master =
+        MultiThreadedConstellation::with_communication(node_config_for(&peers, 0), comm);
+    assert_eq!(master.activate(), Ok(true));
+
+    // Keep the master busy, so the worker steals the echos
+    let started = Arc::new(AtomicBool::new(false));
+    let released = Arc::new(AtomicBool::new(false));
+    let gate = Gate {
+        started: started.clone(),
+        released: released.clone(),
+    };
+    master
+        .submit(activity(gate), &context(), false, false)
+        .unwrap();
+    wait_for(|| started.load(Ordering::SeqCst));
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let collector = Collector {
+        expected: ACTIVITIES,
+        reports: reports.clone(),
+    };
+    let collector = master
+        .submit(activity(collector), &context(), false, true)
+        .unwrap();
+    let echos: Vec<ActivityIdentifier> = (0..ACTIVITIES)
+        .map(|value| {
+            master
+                .submit(activity(Echo(value as u64)), &context(), true, true)
+                .unwrap()
+        })
+        .collect();
+
+    wait_for(|| master.steal_stats().remote_given == ACTIVITIES as u64);
+    released.store(true, Ordering::SeqCst);
+
+    // Events go to the worker over TCP, and the answers come back
+    for echo in &echos {
+        let start = Report { value: 0, node: 0 };
+        master
+            .send(Event::new(Box::new(start), collector.clone(), echo.clone()))
+            .unwrap();
+    }
+    wait_for(|| reports.lock().unwrap().len() == ACTIVITIES);
+
+    let mut reports = reports.lock().unwrap().clone();
+    reports.sort_by_key(|report| report.value);
+    for (value, report) in reports.iter().enumerate() {
+        assert_eq!(report.value, value as u64);
+        assert_eq!(report.node, 1, "Echo {} ran on the master", value);
+    }
+
+    shut_down(&mut master);
+    assert_eq!(worker.join().unwrap(), Ok(true));
+}
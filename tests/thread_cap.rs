@@ -0,0 +1,52 @@
+//! max_activities_per_thread: activities overflow to siblings or are held
+//! back, no thread queues much more than the cap
+mod common;
+
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::{ConstellationTrait, MultiThreadedConstellation};
+
+const CAP: usize = 10;
+const ACTIVITIES: usize = 1000;
+const THREADS: i32 = 4;
+
+#[test]
+fn pending_activities_stay_below_cap() {
+    let mut config = config(THREADS);
+    config.max_activities_per_thread = Some(CAP);
+    // Activities taken by an executor thread in one steal are in flight and
+    // not counted, so the cap holds up to one steal batch
+    let slack = config.steal_batch_size;
+    let mut constellation = MultiThreadedConstellation::new(config);
+    constellation.activate().unwrap();
+
+    for _ in 0..ACTIVITIES {
+        constellation
+            .submit(
+                activity(Sleeper(Duration::from_micros(200))),
+                &context(),
+                true,
+                false,
+            )
+            .unwrap();
+    }
+
+    let mut max_pending = 0;
+    wait_for(|| {
+        let snapshot = constellation.dump_state();
+        for thread in snapshot.threads.iter() {
+            max_pending = max_pending.max(thread.pending.len());
+        }
+        constellation.stats().activities_finished == ACTIVITIES as u64
+    });
+
+    assert!(
+        max_pending <= CAP + slack,
+        "A thread queued {} activities, cap {}",
+        max_pending,
+        CAP
+    );
+    assert!(constellation.stats().overflows > 0);
+    shut_down(&mut constellation);
+}
@@ -0,0 +1,49 @@
+//! `number_of_threads = 0` in the configuration, resolved to the number of
+//! available cores when the instance is activated
+mod common;
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::new_constellation;
+
+fn six_cores() -> usize {
+    6
+}
+
+fn no_cores() -> usize {
+    0
+}
+
+#[test]
+fn zero_resolves_to_detected_cores() {
+    let config = config(0);
+    assert_eq!(config.resolve_number_of_threads(six_cores), 6);
+    // At least one thread, also when detection fails
+    assert_eq!(config.resolve_number_of_threads(no_cores), 1);
+}
+
+#[test]
+fn given_count_is_not_detected() {
+    let config = config(3);
+    assert_eq!(config.resolve_number_of_threads(six_cores), 3);
+    assert_eq!(config.resolve_number_of_threads(no_cores), 3);
+    assert_eq!(config.resolved_number_of_threads(), 3);
+}
+
+#[test]
+fn activated_instance_reports_resolved_count() {
+    let config = config(0);
+    let resolved = config.resolved_number_of_threads();
+    assert!(resolved >= 1);
+
+    let mut constellation = new_constellation(Mode::MultiThreaded, config);
+    constellation.activate().unwrap();
+    assert_eq!(constellation.threads(), resolved);
+
+    for _ in 0..10 {
+        constellation
+            .submit(activity(Quick), &context(), true, false)
+            .unwrap();
+    }
+    shut_down(constellation.as_mut());
+}
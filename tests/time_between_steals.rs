@@ -0,0 +1,64 @@
+//! The deprecated microsecond setter of `time_between_steals` configures the
+//! same as setting the Duration
+#![allow(deprecated)]
+mod common;
+
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{new_constellation, ConstellationConfiguration};
+
+fn both_paths(
+    micros: u64,
+) -> (
+    Box<ConstellationConfiguration>,
+    Box<ConstellationConfiguration>,
+) {
+    let mut deprecated = config(2);
+    deprecated.set_time_between_steals(micros);
+
+    let mut duration = config(2);
+    duration.time_between_steals = Duration::from_micros(micros);
+
+    (deprecated, duration)
+}
+
+#[test]
+fn same_interval() {
+    for micros in [0, 1, 100, 250, 1_000, 1_000_000].iter() {
+        let (deprecated, duration) = both_paths(*micros);
+        assert_eq!(deprecated.time_between_steals, duration.time_between_steals);
+        assert_eq!(
+            deprecated.validate().map_err(|e| e.to_string()),
+            duration.validate().map_err(|e| e.to_string()),
+            "{} microseconds",
+            micros
+        );
+    }
+
+    // Not milliseconds, the unit callers used to get wrong
+    let (deprecated, _) = both_paths(250);
+    assert_eq!(deprecated.time_between_steals, Duration::from_micros(250));
+}
+
+#[test]
+fn same_behaviour() {
+    let (deprecated, duration) = both_paths(1_000);
+
+    for config in vec![deprecated, duration] {
+        let mut constellation = new_constellation(Mode::MultiThreaded, config);
+        constellation.activate().unwrap();
+        for _ in 0..20 {
+            constellation
+                .submit(
+                    activity(Sleeper(Duration::from_millis(2))),
+                    &context(),
+                    true,
+                    false,
+                )
+                .unwrap();
+        }
+        shut_down(constellation.as_mut());
+    }
+}
@@ -0,0 +1,137 @@
+//! Submitting an activity with a context no executor thread serves fails
+//! right away, unless `allow_unserved_contexts` is set for threads added
+//! later. An instance without contexts accepts activities of any context.
+#[macro_use]
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    contexts, new_constellation, ActivityIdentifier, ActivityTrait, ConstellationConfiguration,
+    ConstellationError, ConstellationHandle, ConstellationTrait, Context, ContextVec, Event,
+    MultiThreadedConstellation,
+};
+
+const GPU: &str = "gpu";
+
+/// Activity which records that it ran
+struct Flag(Arc<AtomicBool>);
+
+impl ActivityTrait for Flag {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.0.store(true, Ordering::SeqCst);
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Configuration knowing CONTEXT and GPU, with two threads serving only
+/// CONTEXT
+fn no_gpu_threads() -> Box<ConstellationConfiguration> {
+    let mut config = config(2);
+    config.context_vec = contexts![CONTEXT, GPU];
+    config.thread_contexts = Some(vec![contexts![CONTEXT], contexts![CONTEXT]]);
+    config
+}
+
+fn submit_flag(
+    constellation: &mut dyn ConstellationTrait,
+    label: &str,
+) -> (
+    Result<ActivityIdentifier, ConstellationError>,
+    Arc<AtomicBool>,
+) {
+    let ran = Arc::new(AtomicBool::new(false));
+    let result = constellation.submit(
+        activity(Flag(ran.clone())),
+        &Context::new(label),
+        false,
+        false,
+    );
+    (result, ran)
+}
+
+#[test]
+fn rejected_when_unserved() {
+    let mut constellation = MultiThreadedConstellation::new(no_gpu_threads());
+    constellation.activate().unwrap();
+
+    let (result, _) = submit_flag(&mut constellation, GPU);
+    assert_eq!(
+        result.err(),
+        Some(ConstellationError::UnknownContext(GPU.to_string()))
+    );
+    let (result, _) = submit_flag(&mut constellation, "unknown");
+    assert_eq!(
+        result.err(),
+        Some(ConstellationError::UnknownContext("unknown".to_string()))
+    );
+
+    let (result, ran) = submit_flag(&mut constellation, CONTEXT);
+    result.unwrap();
+    shut_down(&mut constellation);
+    assert!(ran.load(Ordering::SeqCst));
+}
+
+fn any_context_without_contexts(mode: Mode, threads: i32) {
+    let mut config = config(threads);
+    config.context_vec = ContextVec::new();
+    assert!(config.known_contexts().context_vec.is_empty());
+
+    let mut constellation = new_constellation(mode, config);
+    constellation.activate().unwrap();
+
+    let flags: Vec<Arc<AtomicBool>> = [CONTEXT, GPU, "compute/anything"]
+        .iter()
+        .map(|label| {
+            let (result, ran) = submit_flag(constellation.as_mut(), label);
+            result.unwrap();
+            ran
+        })
+        .collect();
+    shut_down(constellation.as_mut());
+    assert!(flags.iter().all(|ran| ran.load(Ordering::SeqCst)));
+}
+
+test_both_modes!(any_context_without_contexts, 2);
+
+#[test]
+fn escape_hatch_for_threads_added_later() {
+    let mut config = no_gpu_threads();
+    config.allow_unserved_contexts = true;
+    let mut constellation = MultiThreadedConstellation::new(config);
+    constellation.activate().unwrap();
+
+    // Accepted and held back until a thread serves it
+    let (result, ran) = submit_flag(&mut constellation, GPU);
+    result.unwrap();
+    wait_for(|| constellation.dump_state().unplaced.len() == 1);
+    assert!(!ran.load(Ordering::SeqCst));
+
+    // Contexts missing from the configuration are still rejected
+    let (result, _) = submit_flag(&mut constellation, "unknown");
+    assert_eq!(
+        result.err(),
+        Some(ConstellationError::UnknownContext("unknown".to_string()))
+    );
+
+    // The added thread has no entry in thread_contexts, it serves all
+    // contexts of the configuration
+    constellation.add_executor_threads(1).unwrap();
+    wait_for(|| ran.load(Ordering::SeqCst));
+    shut_down(&mut constellation);
+}
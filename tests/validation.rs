@@ -0,0 +1,136 @@
+//! Invalid configurations are rejected by `validate()`, naming the offending
+//! field, by the factory and on activation
+mod common;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ConfigError, ConstellationConfiguration, ConstellationError,
+    ConstellationTrait, Context, ContextVec, MultiThreadedConstellation, StealStrategy,
+};
+
+/// Change made to a valid configuration, and the key it should be rejected
+/// for
+struct Invalid {
+    key: &'static str,
+    value: &'static str,
+    change: fn(&mut ConstellationConfiguration),
+}
+
+fn invalid_configurations() -> Vec<Invalid> {
+    vec![
+        Invalid {
+            key: "number_of_threads",
+            value: "-1",
+            change: |c| c.number_of_threads = -1,
+        },
+        Invalid {
+            key: "number_of_nodes",
+            value: "0",
+            change: |c| c.number_of_nodes = 0,
+        },
+        Invalid {
+            key: "number_of_nodes",
+            value: "-2",
+            change: |c| c.number_of_nodes = -2,
+        },
+        Invalid {
+            key: "time_between_steals",
+            value: "0ns",
+            change: |c| c.time_between_steals = Duration::from_secs(0),
+        },
+        Invalid {
+            key: "parent_thread_load_factor",
+            value: "-0.5",
+            change: |c| c.parent_thread_load_factor = -0.5,
+        },
+        Invalid {
+            key: "rebalance_threshold",
+            value: "0.5",
+            change: |c| c.rebalance_threshold = Some(0.5),
+        },
+        Invalid {
+            key: "no_progress_timeout",
+            value: "0",
+            change: |c| c.no_progress_timeout = Some(Duration::from_secs(0)),
+        },
+        Invalid {
+            key: "replay_schedule",
+            value: "replay.log",
+            change: |c| {
+                c.record_schedule = Some(PathBuf::from("record.log"));
+                c.replay_schedule = Some(PathBuf::from("replay.log"));
+            },
+        },
+        Invalid {
+            key: "steal_strategy_overrides",
+            value: "unknown",
+            change: |c| {
+                c.steal_strategy_overrides
+                    .insert("unknown".to_string(), StealStrategy::SMALLEST);
+            },
+        },
+        Invalid {
+            key: "thread_contexts",
+            value: "3 entries",
+            change: |c| {
+                let mut contexts = ContextVec::new();
+                contexts.append(&Context::new(CONTEXT));
+                c.thread_contexts = Some(vec![contexts; 3]);
+            },
+        },
+        Invalid {
+            key: "thread_contexts[1]",
+            value: "[]",
+            change: |c| {
+                let mut contexts = ContextVec::new();
+                contexts.append(&Context::new(CONTEXT));
+                c.thread_contexts = Some(vec![contexts, ContextVec::new()]);
+            },
+        },
+    ]
+}
+
+#[test]
+fn each_failure_names_field() {
+    assert!(config(2).validate().is_ok());
+
+    for invalid in invalid_configurations() {
+        let mut config = config(2);
+        (invalid.change)(&mut config);
+        match config.validate() {
+            Err(ConfigError::InvalidValue { key, value, reason }) => {
+                assert_eq!(key, invalid.key);
+                assert_eq!(value, invalid.value, "{}", key);
+                assert!(!reason.is_empty());
+            }
+            result => panic!("{}: unexpected result {:?}", invalid.key, result),
+        }
+    }
+}
+
+#[test]
+fn rejected_on_activation() {
+    for invalid in invalid_configurations() {
+        let mut config = config(2);
+        (invalid.change)(&mut config);
+        let mut constellation = MultiThreadedConstellation::new(config);
+        assert_eq!(
+            constellation.activate().err(),
+            Some(ConstellationError::InvalidConfiguration),
+            "{}",
+            invalid.key
+        );
+    }
+}
+
+#[test]
+#[should_panic(expected = "Invalid configuration: Invalid value '0' for number_of_nodes")]
+fn rejected_by_factory() {
+    let mut config = config(2);
+    config.number_of_nodes = 0;
+    new_constellation(Mode::MultiThreaded, config);
+}
@@ -0,0 +1,81 @@
+//! `done()` fails with a report of the work left, which names the stuck
+//! activities
+#[macro_use]
+mod common;
+
+use common::*;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::work_left::REPORT_CAP;
+use constellation_rust::{
+    new_constellation, ConstellationError, ConstellationTrait, WorkLeftReport,
+};
+
+fn work_left(constellation: &mut dyn ConstellationTrait) -> Box<WorkLeftReport> {
+    match constellation.done() {
+        Err(ConstellationError::WorkLeft(report)) => report,
+        result => panic!("Unexpected result of done(): {:?}", result),
+    }
+}
+
+fn stuck_activity_is_named(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let stuck = constellation
+        .submit(activity(Waiter), &context(), false, true)
+        .unwrap();
+    wait_for(|| {
+        !constellation
+            .dump_state()
+            .threads
+            .iter()
+            .all(|t| t.suspended.is_empty())
+    });
+
+    let report = work_left(constellation.as_mut());
+    assert!(report.contains_activity(&stuck));
+    let suspended: Vec<_> = report
+        .threads
+        .iter()
+        .flat_map(|t| t.suspended.items.iter())
+        .collect();
+    assert_eq!(suspended, vec![&stuck]);
+
+    let error = ConstellationError::WorkLeft(report).to_string();
+    assert!(error.contains(&stuck.to_string()), "{}", error);
+
+    // Done succeeds once the activity got its event
+    let src = constellation.allocate_external_id();
+    constellation.send(ping(&src, &stuck)).unwrap();
+    shut_down(constellation.as_mut());
+}
+
+test_both_modes!(stuck_activity_is_named, 2);
+
+#[test]
+fn long_lists_are_capped() {
+    let mut constellation = new_constellation(Mode::SingleThreaded, config(1));
+    constellation.activate().unwrap();
+
+    let stuck = REPORT_CAP + 8;
+    let ids: Vec<_> = (0..stuck)
+        .map(|_| {
+            constellation
+                .submit(activity(Waiter), &context(), false, true)
+                .unwrap()
+        })
+        .collect();
+    wait_for(|| constellation.dump_state().threads[0].suspended.len() == stuck);
+
+    let report = work_left(constellation.as_mut());
+    let suspended = &report.threads[0].suspended;
+    assert_eq!(suspended.count, stuck);
+    assert_eq!(suspended.items.len(), REPORT_CAP);
+    assert!(report.to_string().contains("... 8 more"), "{}", report);
+
+    let src = constellation.allocate_external_id();
+    for id in ids.iter() {
+        constellation.send(ping(&src, id)).unwrap();
+    }
+    shut_down(constellation.as_mut());
+}
@@ -0,0 +1,110 @@
+//! Executor threads with more queued activities than `shed_high_watermark`
+//! hand the excess back to the load balancer while other threads are idle
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, Event,
+};
+
+const THREADS: i32 = 4;
+const ACTIVITIES: usize = 200;
+const LONG: Duration = Duration::from_millis(10);
+const TOTAL: Duration = Duration::from_millis(500);
+
+/// Activity which sleeps, and adds the time it slept to the busy time of
+/// the thread it ran on
+struct Task {
+    sleep: Duration,
+    busy: Arc<Mutex<HashMap<ThreadId, Duration>>>,
+}
+
+impl ActivityTrait for Task {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        thread::sleep(self.sleep);
+        let mut busy = self.busy.lock().unwrap();
+        *busy.entry(thread::current().id()).or_default() += self.sleep;
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Activity which submits all tasks at once. The load balancer spreads
+/// them evenly over the threads, but all long tasks end up on one thread.
+struct Burst {
+    busy: Arc<Mutex<HashMap<ThreadId, Duration>>>,
+}
+
+impl ActivityTrait for Burst {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, constellation: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        for i in 0..ACTIVITIES {
+            let sleep = if i % THREADS as usize == 0 {
+                LONG
+            } else {
+                Duration::from_millis(0)
+            };
+            let task = Task {
+                sleep,
+                busy: self.busy.clone(),
+            };
+            constellation
+                .submit(activity(task), &context(), true, false)
+                .unwrap();
+        }
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+/// Run the burst, and return the longest time a single thread was busy
+fn busiest_thread(watermark: usize) -> Duration {
+    let mut config = config(THREADS);
+    config.shed_high_watermark = Some(watermark);
+    let mut constellation = new_constellation(Mode::MultiThreaded, config);
+    constellation.activate().unwrap();
+
+    let busy = Arc::new(Mutex::new(HashMap::new()));
+    let burst = Burst { busy: busy.clone() };
+    constellation
+        .submit(activity(burst), &context(), false, false)
+        .unwrap();
+    shut_down(constellation.as_mut());
+
+    let busy = busy.lock().unwrap();
+    assert_eq!(busy.values().sum::<Duration>(), TOTAL);
+    busy.values().max().cloned().unwrap()
+}
+
+#[test]
+fn skewed_submission_is_balanced() {
+    // Roughly a quarter of the work per thread, instead of all of it on one
+    let busiest = busiest_thread(4);
+    assert!(busiest <= TOTAL / 2, "A thread was busy for {:?}", busiest);
+}
@@ -0,0 +1,129 @@
+//! Activities returning State::YIELD are put back in the work queue of their
+//! thread, so short activities are not held up by a long one
+#[macro_use]
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use common::*;
+use constellation_rust::activity::State;
+use constellation_rust::constellation_factory::Mode;
+use constellation_rust::{
+    new_constellation, ActivityIdentifier, ActivityTrait, ConstellationHandle, ConstellationTrait,
+    Event, MultiThreadedConstellation,
+};
+
+const SLICES: usize = 50;
+const SHORT: usize = 20;
+
+/// Names of the activities, in the order they finished
+type Finished = Arc<Mutex<Vec<&'static str>>>;
+
+/// Activity which works in slices of 2ms, and yields after every slice
+struct Solver {
+    slices: usize,
+    finished: Finished,
+}
+
+impl Solver {
+    fn slice(&mut self) -> State {
+        thread::sleep(Duration::from_millis(2));
+        self.slices -= 1;
+        if self.slices > 0 {
+            return State::YIELD;
+        }
+        self.finished.lock().unwrap().push("solver");
+        State::FINISH
+    }
+}
+
+impl ActivityTrait for Solver {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.slice()
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        if self.slices == 0 {
+            return State::FINISH;
+        }
+        self.slice()
+    }
+}
+
+/// Activity which finishes right away
+struct Short {
+    finished: Finished,
+}
+
+impl ActivityTrait for Short {
+    fn cleanup(&mut self, _: &ConstellationHandle) {}
+
+    fn initialize(&mut self, _: &ConstellationHandle, _: &ActivityIdentifier) -> State {
+        self.finished.lock().unwrap().push("short");
+        State::FINISH
+    }
+
+    fn process(
+        &mut self,
+        _: &ConstellationHandle,
+        _: Option<Box<Event>>,
+        _: &ActivityIdentifier,
+    ) -> State {
+        State::FINISH
+    }
+}
+
+fn submit_work(constellation: &mut dyn ConstellationTrait) -> Finished {
+    let finished = Arc::new(Mutex::new(Vec::new()));
+    let solver = Solver {
+        slices: SLICES,
+        finished: finished.clone(),
+    };
+    constellation
+        .submit(activity(solver), &context(), false, false)
+        .unwrap();
+    for _ in 0..SHORT {
+        let short = Short {
+            finished: finished.clone(),
+        };
+        constellation
+            .submit(activity(short), &context(), false, false)
+            .unwrap();
+    }
+    finished
+}
+
+fn short_activities_finish_first(mode: Mode, threads: i32) {
+    let mut constellation = new_constellation(mode, config(threads));
+    constellation.activate().unwrap();
+
+    let finished = submit_work(constellation.as_mut());
+    shut_down(constellation.as_mut());
+
+    // All on one thread, the solver only finishes after the short activities
+    let finished = finished.lock().unwrap();
+    assert_eq!(finished.len(), SHORT + 1);
+    assert_eq!(finished.last(), Some(&"solver"), "{:?}", finished);
+}
+
+test_both_modes!(short_activities_finish_first, 1);
+
+#[test]
+fn yields_are_counted() {
+    let mut constellation = MultiThreadedConstellation::new(config(1));
+    constellation.activate().unwrap();
+
+    submit_work(&mut constellation);
+    shut_down(&mut constellation);
+
+    assert_eq!(constellation.steal_stats().yields, SLICES as u64 - 1);
+}